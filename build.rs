@@ -1,10 +1,34 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let proto_file = "./proto/mls_service.proto";
+    // Current versioned API, plus the frozen unversioned API served by the compatibility shim
+    let proto_files = [
+        "./proto/mls/v1/mls_service.proto",
+        "./proto/mls_service.proto",
+    ];
 
-    // Tell Cargo to recompile if the proto file changes
-    println!("cargo:rerun-if-changed={}", proto_file);
+    // Tell Cargo to recompile if the proto files change
+    for proto_file in &proto_files {
+        println!("cargo:rerun-if-changed={}", proto_file);
+    }
     println!("cargo:rerun-if-changed=proto");
 
+    // Commit reported by GetServerInfo. Builds outside a checkout (e.g. from a source archive)
+    // can pass it in GIT_COMMIT.
+    let git_commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        std::process::Command::new("git")
+            .args(["rev-parse", "--short=12", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_string())
+    });
+    println!(
+        "cargo:rustc-env=GIT_COMMIT={}",
+        git_commit.as_deref().unwrap_or("unknown")
+    );
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+
     // Get the output directory from Cargo
     let out_dir = std::env::var("OUT_DIR").unwrap();
 
@@ -14,7 +38,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build_server(true)
         .build_client(true)
         .out_dir(&out_dir) // Generate all files in the Cargo OUT_DIR
-        .compile_protos(&[proto_file], &["proto"])?;
+        .compile_protos(&proto_files, &["proto"])?;
 
     Ok(())
 }
@@ -3,6 +3,23 @@
 -- Enable UUID extension if not already enabled
 CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
 
+-- Enum types: closed sets of values for message, proposal, role and credential scheme columns
+DO $$ BEGIN
+  CREATE TYPE message_type AS ENUM ('proposal', 'commit', 'welcome', 'application', 'system');
+EXCEPTION WHEN duplicate_object THEN NULL; END $$;
+DO $$ BEGIN
+  CREATE TYPE proposal_type AS ENUM ('add', 'update', 'remove', 'psk', 'reinit', 'external_init', 'group_context_extensions');
+EXCEPTION WHEN duplicate_object THEN NULL; END $$;
+DO $$ BEGIN
+  CREATE TYPE membership_role AS ENUM ('admin', 'member', 'moderator');
+EXCEPTION WHEN duplicate_object THEN NULL; END $$;
+DO $$ BEGIN
+  CREATE TYPE credential_scheme AS ENUM ('basic', 'x509');
+EXCEPTION WHEN duplicate_object THEN NULL; END $$;
+DO $$ BEGIN
+  CREATE TYPE attestation_verdict AS ENUM ('unattested', 'passed', 'failed');
+EXCEPTION WHEN duplicate_object THEN NULL; END $$;
+
 -- Users table: This table is a flexible table that can be used to store any user data as long as the id is tied to a client
 CREATE TABLE IF NOT EXISTS users (
   id UUID PRIMARY KEY,
@@ -20,9 +37,17 @@ CREATE TABLE IF NOT EXISTS groups (
   creator_id UUID NOT NULL,
   epoch BIGINT NOT NULL DEFAULT 0,
   state BYTEA,
+  mls_group_id BYTEA UNIQUE,
+  tenant_id UUID,
+  handle TEXT,
   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
   updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
-  is_active BOOLEAN NOT NULL DEFAULT true
+  epoch_started_at TIMESTAMPTZ,
+  max_epoch_age_secs BIGINT,
+  rotation_reminded_epoch BIGINT,
+  is_active BOOLEAN NOT NULL DEFAULT true,
+  deleted_at TIMESTAMPTZ,
+  blob_version SMALLINT NOT NULL DEFAULT 0
 );
 
 -- Clients table: This table is used to store the clients that are created by the users
@@ -30,45 +55,192 @@ CREATE TABLE IF NOT EXISTS clients (
   id UUID PRIMARY KEY,
   user_id UUID NOT NULL,
   credential BYTEA NOT NULL,
-  scheme TEXT NOT NULL,
+  scheme credential_scheme NOT NULL,
   device_name TEXT NOT NULL,
   last_seen TIMESTAMPTZ NOT NULL DEFAULT now(),
-  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  init_key BYTEA,
+  signature_key BYTEA,
+  key_packages_low_at TIMESTAMPTZ,
+  is_service BOOLEAN NOT NULL DEFAULT false,
+  attestation_verdict attestation_verdict NOT NULL DEFAULT 'unattested',
+  deleted_at TIMESTAMPTZ,
+  blob_version SMALLINT NOT NULL DEFAULT 0
 );
 
 -- Key packages table: This table is used to store the key packages that are created by the clients
 CREATE TABLE IF NOT EXISTS key_packages (
   id UUID PRIMARY KEY,
-  client_id UUID NOT NULL REFERENCES clients(id),
+  client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
   data BYTEA NOT NULL,
   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
-  used BOOLEAN NOT NULL DEFAULT false
+  used BOOLEAN NOT NULL DEFAULT false,
+  used_at TIMESTAMPTZ,             -- When it was claimed; used key packages are purged by it
+  deleted_at TIMESTAMPTZ,
+  blob_version SMALLINT NOT NULL DEFAULT 0
 );
 
 -- Memberships table: This table is used to store the memberships that are created by the clients
 CREATE TABLE IF NOT EXISTS memberships (
   id UUID PRIMARY KEY,
-  client_id UUID NOT NULL REFERENCES clients(id),
-  group_id UUID NOT NULL REFERENCES groups(id),
-  role TEXT NOT NULL,
+  client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+  group_id UUID NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  role membership_role NOT NULL,
   added_at TIMESTAMPTZ NOT NULL DEFAULT now(),
-  removed_at TIMESTAMPTZ
+  removed_at TIMESTAMPTZ,
+  last_acked_epoch BIGINT,
+  last_acked_at TIMESTAMPTZ,
+  escalated_at TIMESTAMPTZ,
+  welcome_requested_at TIMESTAMPTZ,
+  welcome_reminders BIGINT NOT NULL DEFAULT 0,
+  welcome_reminded_at TIMESTAMPTZ,
+  welcome_escalated_at TIMESTAMPTZ,
+  evicted_messages BIGINT NOT NULL DEFAULT 0
 );
 
 -- Messages table: This table is used to store the messages that are sent by the clients
+-- System messages are enqueued by the service itself with a nil sender_id, and have no
+-- group_id when addressed to clients directly
 CREATE TABLE IF NOT EXISTS messages (
   id UUID PRIMARY KEY,
-  group_id UUID NOT NULL REFERENCES groups(id),
-  sender_id UUID NOT NULL REFERENCES clients(id),
+  group_id UUID REFERENCES groups(id) ON DELETE CASCADE,
+  sender_id UUID NOT NULL,
   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
   read BOOLEAN NOT NULL DEFAULT false,
-  message_type TEXT NOT NULL,
+  message_type message_type NOT NULL,
   proposal BYTEA,
   commit BYTEA,
   welcome BYTEA,
-  proposal_type TEXT,
+  system BYTEA,
+  application BYTEA,
+  proposal_type proposal_type,
   epoch BIGINT,
-  recipients UUID[]
+  recipients UUID[],
+  extra JSONB,
+  payload_hash BYTEA,
+  expires_at TIMESTAMPTZ,
+  deleted_at TIMESTAMPTZ,
+  sequence BIGINT NOT NULL,
+  blob_version SMALLINT NOT NULL DEFAULT 0
+);
+
+-- Message sequences table: The last sequence of each group's messages, and of the notices outside
+-- any group under the nil group id
+CREATE TABLE IF NOT EXISTS message_sequences (
+  group_id UUID PRIMARY KEY,
+  last_sequence BIGINT NOT NULL
+);
+
+-- Client backups table: This table stores one encrypted state backup per client, replaced version by version
+CREATE TABLE IF NOT EXISTS client_backups (
+  client_id UUID PRIMARY KEY REFERENCES clients(id) ON DELETE CASCADE,
+  version BIGINT NOT NULL,
+  data BYTEA NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Request nonces table: Recently used nonces of signed requests, kept until their replay window ends
+CREATE TABLE IF NOT EXISTS request_nonces (
+  client_id UUID NOT NULL,
+  nonce TEXT NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (client_id, nonce)
+);
+
+-- Feature flags table: Gradual rollout of risky behaviors, managed through the admin service
+CREATE TABLE IF NOT EXISTS feature_flags (
+  name TEXT PRIMARY KEY,
+  enabled BOOLEAN NOT NULL DEFAULT false,
+  percentage INTEGER NOT NULL DEFAULT 0 CHECK (percentage BETWEEN 0 AND 100),
+  group_ids UUID[] NOT NULL DEFAULT '{}',
+  user_ids UUID[] NOT NULL DEFAULT '{}',
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Key package claims table: Audit trail of claimed key packages, kept after the packages are purged
+CREATE TABLE IF NOT EXISTS key_package_claims (
+  key_package_id UUID PRIMARY KEY,
+  client_id UUID NOT NULL,
+  hash BYTEA NOT NULL,
+  published_at TIMESTAMPTZ NOT NULL,
+  claimed_at TIMESTAMPTZ NOT NULL,
+  group_id UUID NOT NULL,
+  claimed_by UUID NOT NULL
+);
+
+-- Verbose traces table: Temporary verbose tracing of a group or a client, until it expires
+CREATE TABLE IF NOT EXISTS verbose_traces (
+  id UUID PRIMARY KEY,
+  group_id UUID,
+  client_id UUID,
+  reason TEXT NOT NULL DEFAULT '',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  expires_at TIMESTAMPTZ NOT NULL,
+  CHECK ((group_id IS NULL) <> (client_id IS NULL))
+);
+
+-- Proposal refs table: Queue of the proposals commits include by reference
+CREATE TABLE IF NOT EXISTS proposal_refs (
+  group_id UUID NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  proposal_ref BYTEA NOT NULL,
+  epoch BIGINT NOT NULL,
+  message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+  created_at TIMESTAMPTZ NOT NULL,
+  committed_by UUID,
+  PRIMARY KEY (group_id, proposal_ref)
+);
+
+-- Group info table: GroupInfos published for external joins, one per epoch
+CREATE TABLE IF NOT EXISTS group_info (
+  group_id UUID NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  epoch BIGINT NOT NULL,
+  data BYTEA NOT NULL,
+  publisher_id UUID NOT NULL,
+  published_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (group_id, epoch)
+);
+
+-- Ratchet trees table: Ratchet trees uploaded for new members, one per epoch
+CREATE TABLE IF NOT EXISTS ratchet_trees (
+  group_id UUID NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  epoch BIGINT NOT NULL,
+  data BYTEA NOT NULL,
+  uploader_id UUID NOT NULL,
+  uploaded_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (group_id, epoch)
+);
+
+-- Abuse reports table: Messages reported by group members, kept for moderators to resolve
+CREATE TABLE IF NOT EXISTS abuse_reports (
+  id UUID PRIMARY KEY,
+  group_id UUID NOT NULL,
+  reporter_id UUID NOT NULL,
+  message_ids UUID[] NOT NULL,
+  reason TEXT NOT NULL,
+  excerpt BYTEA,
+  created_at TIMESTAMPTZ NOT NULL,
+  resolved_at TIMESTAMPTZ,
+  resolution TEXT
+);
+
+-- Delivery cursors table: The last sequence delivered to each client, per group, with notices
+-- outside any group under the nil group id
+CREATE TABLE IF NOT EXISTS delivery_cursors (
+  client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+  group_id UUID NOT NULL,
+  sequence BIGINT NOT NULL,
+  PRIMARY KEY (client_id, group_id)
+);
+
+-- Tenant keys table: Salts of the tenants' storage keys. A destroyed key keeps its row, with the
+-- salt dropped, so nothing can be stored for its tenant again
+CREATE TABLE IF NOT EXISTS tenant_keys (
+  tenant_id UUID PRIMARY KEY,
+  salt BYTEA,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  destroyed_at TIMESTAMPTZ,
+  CHECK ((salt IS NULL) = (destroyed_at IS NOT NULL))
 );
 
 -- Indexes for better performance
@@ -77,4 +249,12 @@ CREATE INDEX IF NOT EXISTS idx_key_packages_client_id ON key_packages(client_id)
 CREATE INDEX IF NOT EXISTS idx_memberships_group_id ON memberships(group_id);
 CREATE INDEX IF NOT EXISTS idx_memberships_client_id ON memberships(client_id);
 CREATE INDEX IF NOT EXISTS idx_messages_group_id ON messages(group_id);
-CREATE INDEX IF NOT EXISTS idx_messages_sender_id ON messages(sender_id); 
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_messages_sender_id ON messages(sender_id); 
+CREATE UNIQUE INDEX IF NOT EXISTS idx_groups_tenant_handle ON groups(tenant_id, handle);
+-- Composite indexes for the membership, message and key package queries
+CREATE INDEX IF NOT EXISTS idx_memberships_client_id_removed_at ON memberships(client_id, removed_at);
+CREATE INDEX IF NOT EXISTS idx_messages_group_id_created_at ON messages(group_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_messages_expires_at ON messages(expires_at);
+CREATE INDEX IF NOT EXISTS idx_messages_group_id_sequence ON messages(group_id, sequence);
+CREATE INDEX IF NOT EXISTS idx_key_packages_client_id_used ON key_packages(client_id, used);
+CREATE INDEX IF NOT EXISTS idx_key_package_claims_client_id ON key_package_claims(client_id);
@@ -27,9 +27,17 @@ if ! command -v psql &> /dev/null; then
   exit 1
 fi
 
-# Run the SQL schema
+# Create the tables in DATABASE_SCHEMA, if set
+if [ -n "$DATABASE_SCHEMA" ]; then
+  echo "Using schema: $DATABASE_SCHEMA"
+  psql $DATABASE_URL -c "CREATE SCHEMA IF NOT EXISTS $DATABASE_SCHEMA"
+  export PGOPTIONS="-c search_path=$DATABASE_SCHEMA"
+fi
+
+# Run the SQL schema, with TABLE_PREFIX prepended to table and index names
 echo "Applying database schema..."
-psql $DATABASE_URL -f schema.sql
+sed -E "s/\b(users|groups|clients|key_packages|memberships|messages|message_sequences|client_backups|request_nonces|feature_flags|key_package_claims|verbose_traces|proposal_refs|group_info|ratchet_trees|abuse_reports|delivery_cursors|tenant_keys|idx_[a-z_]+)\b/${TABLE_PREFIX}\1/g" schema.sql \
+  | psql $DATABASE_URL -f -
 
 echo "Database setup complete!"
 echo "You can now run the MLS Delivery Service with: cargo run" 
\ No newline at end of file
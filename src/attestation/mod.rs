@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::db::AttestationVerdict;
+use crate::redact::redacted_debug;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    #[error("Attestation request failed: {0}")]
+    Request(String),
+
+    #[error("Unexpected attestation verifier response: {0}")]
+    Response(String),
+}
+
+// Kind of evidence a device attests its integrity with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttestationFormat {
+    // Google Play Integrity token
+    PlayIntegrity,
+    // Apple App Attest attestation object
+    AppAttest,
+    // Evidence only a deployment's own verifier understands
+    Custom,
+}
+
+impl AttestationFormat {
+    pub const ALL: [AttestationFormat; 3] = [
+        AttestationFormat::PlayIntegrity,
+        AttestationFormat::AppAttest,
+        AttestationFormat::Custom,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PlayIntegrity => "play_integrity",
+            Self::AppAttest => "app_attest",
+            Self::Custom => "custom",
+        }
+    }
+}
+
+// What a device presented at registration, along with the registration it vouches for.
+// Verifiers should check that the token is bound to these details (e.g. through its nonce), so
+// it can't be replayed for another device.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AttestationEvidence {
+    pub format: AttestationFormat,
+    pub token: Vec<u8>,
+    pub user_id: Uuid,
+    pub identity: String,
+    pub device_name: String,
+    pub signature_key: Option<Vec<u8>>,
+}
+
+redacted_debug!(AttestationEvidence {
+    format, user_id, identity, device_name;
+    redacted: token, signature_key
+});
+
+// Checks attestation evidence of one format
+#[async_trait]
+pub trait AttestationVerifier: Send + Sync {
+    // Passed or Failed for evidence the verifier could judge, an error if it couldn't
+    async fn verify(
+        &self,
+        evidence: &AttestationEvidence,
+    ) -> Result<AttestationVerdict, AttestationError>;
+}
+
+// Verifier that POSTs the evidence as JSON to a URL, such as a service wrapping Google's or
+// Apple's verification APIs, and reads back `{"verdict": "passed"}` or `{"verdict": "failed"}`
+pub struct WebhookVerifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+// JSON body posted by `WebhookVerifier`. Byte fields are standard base64 strings.
+#[derive(Debug, Serialize)]
+struct WebhookEvidence<'a> {
+    format: AttestationFormat,
+    token: String,
+    user_id: Uuid,
+    identity: &'a str,
+    device_name: &'a str,
+    signature_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookVerdict {
+    verdict: String,
+}
+
+impl WebhookVerifier {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("HTTP client configuration is valid"),
+            url: url.into(),
+        }
+    }
+
+    // Parse the JSON body the webhook returns
+    pub fn parse_verdict(body: &str) -> Result<AttestationVerdict, AttestationError> {
+        let response: WebhookVerdict =
+            serde_json::from_str(body).map_err(|e| AttestationError::Response(e.to_string()))?;
+        match response.verdict.parse() {
+            Ok(verdict @ (AttestationVerdict::Passed | AttestationVerdict::Failed)) => Ok(verdict),
+            _ => Err(AttestationError::Response(format!(
+                "unknown verdict {:?}",
+                response.verdict
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl AttestationVerifier for WebhookVerifier {
+    async fn verify(
+        &self,
+        evidence: &AttestationEvidence,
+    ) -> Result<AttestationVerdict, AttestationError> {
+        let body = WebhookEvidence {
+            format: evidence.format,
+            token: BASE64.encode(&evidence.token),
+            user_id: evidence.user_id,
+            identity: &evidence.identity,
+            device_name: &evidence.device_name,
+            signature_key: evidence
+                .signature_key
+                .as_ref()
+                .map(|key| BASE64.encode(key)),
+        };
+
+        let body = self
+            .client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AttestationError::Request(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| AttestationError::Request(e.to_string()))?;
+        Self::parse_verdict(&body)
+    }
+}
+
+// The verifier of each supported format. Registrations attested with a format that has none
+// are rejected.
+#[derive(Clone, Default)]
+pub struct AttestationVerifiers {
+    verifiers: HashMap<AttestationFormat, Arc<dyn AttestationVerifier>>,
+}
+
+impl AttestationVerifiers {
+    // Verify the format's evidence with the verifier, replacing any it had
+    pub fn with_verifier(
+        mut self,
+        format: AttestationFormat,
+        verifier: Arc<dyn AttestationVerifier>,
+    ) -> Self {
+        self.verifiers.insert(format, verifier);
+        self
+    }
+
+    pub fn get(&self, format: AttestationFormat) -> Option<&Arc<dyn AttestationVerifier>> {
+        self.verifiers.get(&format)
+    }
+
+    // Formats that have a verifier
+    pub fn formats(&self) -> Vec<AttestationFormat> {
+        AttestationFormat::ALL
+            .into_iter()
+            .filter(|format| self.verifiers.contains_key(format))
+            .collect()
+    }
+}
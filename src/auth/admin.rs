@@ -0,0 +1,122 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tonic::{Extensions, Request, Status};
+use tower::{Layer, Service};
+
+use super::token::AUTHORIZATION_HEADER;
+use crate::mls_codec;
+
+// An operator the admin listener authenticated, attached to the request extensions. The
+// destructive admin RPCs check for it themselves, so they stay closed even if the service is
+// served without the `AdminAuthLayer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdminPrincipal;
+
+// Fail unless the request was made by an authenticated operator
+pub fn require_admin<T>(request: &Request<T>) -> Result<(), Status> {
+    require_admin_extensions(request.extensions())
+}
+
+// For handlers that take the request apart
+pub fn require_admin_extensions(extensions: &Extensions) -> Result<(), Status> {
+    if extensions.get::<AdminPrincipal>().is_none() {
+        return Err(Status::permission_denied(
+            "Only operators of the admin service may do this",
+        ));
+    }
+    Ok(())
+}
+
+// Tower layer authenticating every request on the admin listener with the admin bearer token.
+// Without a configured token it fails closed: every request is rejected.
+#[derive(Clone)]
+pub struct AdminAuthLayer {
+    // SHA-256 of the token, so comparing a presented token takes the same time however much of
+    // it matches
+    token_hash: Option<Arc<[u8]>>,
+}
+
+impl AdminAuthLayer {
+    pub fn new(token: Option<&str>) -> Self {
+        Self {
+            token_hash: token
+                .filter(|token| !token.is_empty())
+                .map(|token| mls_codec::sha256(token.as_bytes()).into()),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.token_hash.is_some()
+    }
+}
+
+impl<S> Layer<S> for AdminAuthLayer {
+    type Service = AdminAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AdminAuth {
+            inner,
+            token_hash: self.token_hash.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AdminAuth<S> {
+    inner: S,
+    token_hash: Option<Arc<[u8]>>,
+}
+
+impl<S> AdminAuth<S> {
+    fn check(&self, headers: &http::HeaderMap) -> Result<(), Status> {
+        let Some(expected) = &self.token_hash else {
+            return Err(Status::unauthenticated(
+                "The admin service has no credentials configured",
+            ));
+        };
+        let token = headers
+            .get(AUTHORIZATION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| Status::unauthenticated("Request must carry the admin bearer token"))?;
+
+        let presented = mls_codec::sha256(token.as_bytes());
+        let difference = presented
+            .iter()
+            .zip(expected.iter())
+            .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+        if difference != 0 || presented.len() != expected.len() {
+            return Err(Status::unauthenticated("Invalid admin bearer token"));
+        }
+        Ok(())
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for AdminAuth<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+        if let Err(status) = self.check(request.headers()) {
+            return Box::pin(async move { Ok(status.into_http()) });
+        }
+        request.extensions_mut().insert(AdminPrincipal);
+        Box::pin(self.inner.call(request))
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Status};
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use crate::db::{DatabaseInterface, DbError, DbResult, EntityKind};
+use crate::error::ServiceError;
+use crate::events::{DomainEvent, EventBus};
+use crate::metrics::jobs::CLIENT_KEY_REFRESH_JOB;
+use crate::metrics::layer::rpc_method;
+use crate::metrics::JobTracker;
+use crate::mls_codec::{self, MlsCrypto};
+use crate::timestamps;
+
+pub mod admin;
+pub mod token;
+
+// Metadata authenticating the caller of a delivery RPC
+pub const CLIENT_HEADER: &str = "x-mls-auth-client";
+pub const TIMESTAMP_HEADER: &str = "x-mls-auth-timestamp";
+pub const NONCE_HEADER: &str = "x-mls-auth-nonce";
+pub const SIGNATURE_HEADER: &str = "x-mls-auth-signature-bin";
+
+pub const MAX_NONCE_LEN: usize = 64;
+
+// Domain separation label, so authentication signatures can't be confused with request or MLS
+// signatures
+const DIGEST_LABEL: &[u8] = b"hermetic-mls auth v1";
+
+// Delivery RPCs a caller can make before it has a registered key
+pub const UNAUTHENTICATED_METHODS: &[&str] = &["RegisterClient", "GetServerInfo"];
+
+// Prefix of authentication nonces in the nonce store, which request signatures share, so a
+// nonce used for one doesn't use it up for the other
+const NONCE_SCOPE: &str = "auth:";
+
+// SHA-256 digest a client signs to authenticate: the label, the path of the RPC it calls, its
+// client id, the unix timestamp in seconds and a nonce, each followed by a zero byte. Signing
+// the path keeps a captured signature from being spent on another RPC.
+pub fn auth_digest(path: &str, client_id: Uuid, timestamp: i64, nonce: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(DIGEST_LABEL.len() + path.len() + nonce.len() + 64);
+    for part in [
+        DIGEST_LABEL,
+        path.as_bytes(),
+        client_id.to_string().as_bytes(),
+        timestamp.to_string().as_bytes(),
+        nonce.as_bytes(),
+    ] {
+        data.extend_from_slice(part);
+        data.push(0);
+    }
+
+    mls_codec::sha256(&data)
+}
+
+// Whether delivery RPCs must be authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    // Authentication headers are ignored
+    #[default]
+    Off,
+    // Authenticated requests are verified; others proceed without an identity
+    Optional,
+    // Every delivery RPC except RegisterClient and GetServerInfo must be authenticated
+    Required,
+}
+
+impl FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "off" => Ok(Self::Off),
+            "optional" => Ok(Self::Optional),
+            "required" => Ok(Self::Required),
+            _ => Err(format!(
+                "unknown auth mode {:?}, expected off, optional or required",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthConfig {
+    pub mode: AuthMode,
+    // How far an authentication timestamp may be from the server's clock
+    pub window: Duration,
+    // How often the signature keys are reloaded, to pick up other instances' registrations
+    pub key_refresh_interval: Duration,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            mode: AuthMode::Off,
+            window: Duration::from_secs(300),
+            key_refresh_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+// The client a request was authenticated as, attached to the request extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthenticatedClient(pub Uuid);
+
+// The client a request was authenticated as, if any
+pub fn authenticated_client<T>(request: &Request<T>) -> Option<Uuid> {
+    request
+        .extensions()
+        .get::<AuthenticatedClient>()
+        .map(|client| client.0)
+}
+
+// In-memory snapshot of the clients' signature keys, so authenticating a request never touches
+// the database. Local registrations and deletions are applied as they happen; the snapshot is
+// reloaded periodically to pick up other instances' changes.
+#[derive(Clone, Default)]
+pub struct ClientKeys {
+    keys: Arc<RwLock<HashMap<Uuid, Vec<u8>>>>,
+}
+
+impl ClientKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Load every signature key from the database
+    pub async fn load<DB: DatabaseInterface + ?Sized>(db: &DB) -> DbResult<Self> {
+        let keys = Self::new();
+        keys.refresh(db).await?;
+        Ok(keys)
+    }
+
+    // Replace the snapshot with the keys currently in the database
+    pub async fn refresh<DB: DatabaseInterface + ?Sized>(&self, db: &DB) -> DbResult<()> {
+        let keys = db.list_signature_keys().await?;
+        *self.keys.write().unwrap() = keys.into_iter().collect();
+        Ok(())
+    }
+
+    pub fn get(&self, client_id: Uuid) -> Option<Vec<u8>> {
+        self.keys.read().unwrap().get(&client_id).cloned()
+    }
+
+    pub fn insert(&self, client_id: Uuid, key: Vec<u8>) {
+        self.keys.write().unwrap().insert(client_id, key);
+    }
+
+    pub fn remove(&self, client_id: Uuid) {
+        self.keys.write().unwrap().remove(&client_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Load the key of a client that was registered or restored
+    async fn reload_client<DB: DatabaseInterface + ?Sized>(
+        &self,
+        db: &DB,
+        client_id: Uuid,
+    ) -> DbResult<()> {
+        match db.get_client(client_id).await {
+            Ok(client) => match client.signature_key.filter(|_| client.deleted_at.is_none()) {
+                Some(key) => self.insert(client_id, key),
+                None => self.remove(client_id),
+            },
+            Err(DbError::NotFound) => self.remove(client_id),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    // Apply a registration, restoration or deletion of a client
+    async fn apply<DB: DatabaseInterface + ?Sized>(&self, db: &DB, event: &DomainEvent) {
+        match *event {
+            DomainEvent::ClientRegistered { client_id, .. }
+            | DomainEvent::EntityRestored {
+                kind: EntityKind::Client,
+                id: client_id,
+            } => {
+                if let Err(e) = self.reload_client(db, client_id).await {
+                    error!(
+                        "Failed to load the signature key of client {}: {}",
+                        client_id, e
+                    );
+                }
+            }
+            DomainEvent::EntityDeleted {
+                kind: EntityKind::Client,
+                id,
+            }
+            | DomainEvent::EntityPurged {
+                kind: EntityKind::Client,
+                id,
+            } => self.remove(id),
+            _ => {}
+        }
+    }
+
+    // Apply this instance's registrations and deletions as they are published, and reload the
+    // whole snapshot on a fixed interval, reporting each reload to the job tracker
+    pub fn spawn_sync<DB: DatabaseInterface + ?Sized + 'static>(
+        &self,
+        db: Arc<DB>,
+        events: &EventBus,
+        every: Duration,
+        jobs: JobTracker,
+    ) -> JoinHandle<()> {
+        let keys = self.clone();
+        let mut events = events.subscribe();
+        jobs.register(CLIENT_KEY_REFRESH_JOB, every);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let refresh = async {
+                            keys.refresh(db.as_ref()).await?;
+                            Ok::<_, DbError>(keys.len() as u64)
+                        };
+                        if let Err(e) = jobs.track(CLIENT_KEY_REFRESH_JOB, refresh).await {
+                            error!("Failed to refresh client signature keys: {}", e);
+                        }
+                    }
+                    event = events.recv() => match event {
+                        Ok(event) => keys.apply(db.as_ref(), &event).await,
+                        Err(RecvError::Lagged(missed)) => {
+                            warn!("Client key sync missed {} events", missed)
+                        }
+                        Err(RecvError::Closed) => return,
+                    },
+                }
+            }
+        })
+    }
+}
+
+// Authentication headers of a request
+struct Credentials {
+    client_id: Uuid,
+    timestamp: i64,
+    nonce: String,
+    signature: Vec<u8>,
+}
+
+impl Credentials {
+    // Parse the authentication metadata. Returns None if the request carries none at all.
+    fn from_metadata(metadata: &MetadataMap) -> Result<Option<Self>, ServiceError> {
+        let client = metadata.get(CLIENT_HEADER);
+        let timestamp = metadata.get(TIMESTAMP_HEADER);
+        let nonce = metadata.get(NONCE_HEADER);
+        let signature = metadata.get_bin(SIGNATURE_HEADER);
+
+        let (client, timestamp, nonce, signature) = match (client, timestamp, nonce, signature) {
+            (None, None, None, None) => return Ok(None),
+            (Some(client), Some(timestamp), Some(nonce), Some(signature)) => {
+                (client, timestamp, nonce, signature)
+            }
+            _ => {
+                return Err(ServiceError::Unauthenticated(format!(
+                    "Authenticated requests need all of {}, {}, {} and {}",
+                    CLIENT_HEADER, TIMESTAMP_HEADER, NONCE_HEADER, SIGNATURE_HEADER
+                )))
+            }
+        };
+
+        let client_id = client
+            .to_str()
+            .ok()
+            .and_then(|s| Uuid::parse_str(s).ok())
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(format!("{} must be a UUID", CLIENT_HEADER))
+            })?;
+        let timestamp = timestamp
+            .to_str()
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(format!(
+                    "{} must be a unix timestamp",
+                    TIMESTAMP_HEADER
+                ))
+            })?;
+        let nonce = nonce
+            .to_str()
+            .ok()
+            .filter(|s| !s.is_empty() && s.len() <= MAX_NONCE_LEN)
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(format!(
+                    "{} must be 1 to {} characters",
+                    NONCE_HEADER, MAX_NONCE_LEN
+                ))
+            })?
+            .to_string();
+        let signature = signature
+            .to_bytes()
+            .map_err(|_| ServiceError::Unauthenticated("Malformed signature".to_string()))?
+            .to_vec();
+
+        Ok(Some(Self {
+            client_id,
+            timestamp,
+            nonce,
+            signature,
+        }))
+    }
+}
+
+// Where used authentication nonces are recorded until they expire. The database is shared by
+// every instance, so a captured request can't be replayed against another one.
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    // Returns false if the client already used the nonce
+    async fn record_auth_nonce(
+        &self,
+        client_id: Uuid,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<bool>;
+}
+
+#[async_trait]
+impl<DB: DatabaseInterface + ?Sized> NonceStore for DB {
+    async fn record_auth_nonce(
+        &self,
+        client_id: Uuid,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<bool> {
+        self.record_request_nonce(client_id, &format!("{}{}", NONCE_SCOPE, nonce), expires_at)
+            .await
+    }
+}
+
+// Checks a request's authentication headers
+#[derive(Clone)]
+struct Authenticator {
+    window: chrono::Duration,
+    keys: ClientKeys,
+    nonces: Arc<dyn NonceStore>,
+    crypto: Arc<MlsCrypto>,
+}
+
+impl Authenticator {
+    // The client the request is authenticated as, or None if it carries no credentials
+    async fn authenticate(
+        &self,
+        path: &str,
+        credentials: Option<Credentials>,
+    ) -> Result<Option<Uuid>, ServiceError> {
+        let Some(credentials) = credentials else {
+            return Ok(None);
+        };
+
+        let signed_at = DateTime::<Utc>::from_timestamp(credentials.timestamp, 0)
+            .filter(|signed_at| (timestamps::now() - *signed_at).abs() <= self.window)
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(
+                    "Authentication timestamp is outside the replay window".to_string(),
+                )
+            })?;
+
+        let key = self.keys.get(credentials.client_id).ok_or_else(|| {
+            ServiceError::Unauthenticated("Client has no registered signature key".to_string())
+        })?;
+        let digest = auth_digest(
+            path,
+            credentials.client_id,
+            credentials.timestamp,
+            &credentials.nonce,
+        );
+        self.crypto
+            .verify_ed25519(&digest, &key, &credentials.signature)
+            .map_err(|_| {
+                ServiceError::Unauthenticated("Invalid authentication signature".to_string())
+            })?;
+
+        // Only a correctly signed request uses up its nonce
+        let fresh = self
+            .nonces
+            .record_auth_nonce(
+                credentials.client_id,
+                &credentials.nonce,
+                signed_at + self.window,
+            )
+            .await
+            .map_err(ServiceError::from)?;
+        if !fresh {
+            return Err(ServiceError::Unauthenticated(
+                "Authentication nonce was already used".to_string(),
+            ));
+        }
+
+        Ok(Some(credentials.client_id))
+    }
+}
+
+// Tower layer authenticating callers of the delivery service. A caller signs the RPC's path, a
+// fresh nonce and the current time with the signature key it registered; the verified client
+// id is attached to the request as an `AuthenticatedClient`. The admin service, health checks
+// and reflection are left alone.
+#[derive(Clone)]
+pub struct ClientAuthLayer {
+    mode: AuthMode,
+    authenticator: Authenticator,
+}
+
+impl ClientAuthLayer {
+    pub fn new(config: &AuthConfig, keys: ClientKeys, nonces: Arc<dyn NonceStore>) -> Self {
+        Self {
+            mode: config.mode,
+            authenticator: Authenticator {
+                window: chrono::Duration::from_std(config.window).unwrap_or(chrono::Duration::MAX),
+                keys,
+                nonces,
+                crypto: Arc::default(),
+            },
+        }
+    }
+}
+
+impl<S> Layer<S> for ClientAuthLayer {
+    type Service = ClientAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientAuth {
+            inner,
+            mode: self.mode,
+            authenticator: self.authenticator.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClientAuth<S> {
+    inner: S,
+    mode: AuthMode,
+    authenticator: Authenticator,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for ClientAuth<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+        let path = request.uri().path().to_string();
+        let Some(method) = rpc_method(&path)
+            .filter(|_| self.mode != AuthMode::Off && is_delivery_rpc(&path))
+            .map(str::to_string)
+        else {
+            return Box::pin(self.inner.call(request));
+        };
+
+        let credentials =
+            Credentials::from_metadata(&MetadataMap::from_headers(request.headers().clone()));
+        let mode = self.mode;
+        let authenticator = self.authenticator.clone();
+        // The ready inner service is used for this request, a clone is left for the next
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let authenticated = match credentials {
+                Ok(credentials) => authenticator.authenticate(&path, credentials).await,
+                Err(e) => Err(e),
+            };
+            match authenticated {
+                Ok(Some(client_id)) => {
+                    request
+                        .extensions_mut()
+                        .insert(AuthenticatedClient(client_id));
+                }
+                Ok(None)
+                    if mode == AuthMode::Required
+                        && !UNAUTHENTICATED_METHODS.contains(&method.as_str()) =>
+                {
+                    return Ok(Status::unauthenticated("Request must be authenticated").into_http());
+                }
+                Ok(None) => {}
+                Err(e) => return Ok(Status::from(e).into_http()),
+            }
+            inner.call(request).await
+        })
+    }
+}
+
+pub(crate) fn is_delivery_rpc(path: &str) -> bool {
+    path.trim_start_matches('/')
+        .split_once('/')
+        .is_some_and(|(service, _)| service.ends_with(".MlsDeliveryService"))
+}
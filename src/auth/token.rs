@@ -0,0 +1,361 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use jsonwebtoken::jwk::{AlgorithmParameters, Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::warn;
+use serde_json::{Map, Value};
+use thiserror::Error;
+use tonic::{Request, Status};
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use super::{is_delivery_rpc, AuthMode};
+
+// Metadata carrying the bearer token, as `Bearer <token>`
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TokenError {
+    #[error("Invalid token auth configuration: {0}")]
+    Config(String),
+
+    #[error("Invalid bearer token: {0}")]
+    Invalid(String),
+
+    #[error("Bearer token was signed with an unknown key")]
+    UnknownKey,
+
+    #[error("Fetching the JWKS failed: {0}")]
+    Jwks(String),
+}
+
+// Checks bearer tokens issued by an identity provider
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    // The user the token was issued to
+    async fn authenticate(&self, token: &str) -> Result<Uuid, TokenError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenAuthConfig {
+    pub mode: AuthMode,
+    // Expected `iss` and `aud` claims
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    // Where the identity provider publishes its signing keys
+    pub jwks_url: Option<String>,
+    // Claim holding the user id
+    pub user_id_claim: String,
+    // Minimum time between JWKS fetches, so tokens with made-up key ids can't hammer the
+    // identity provider
+    pub jwks_refresh_interval: Duration,
+}
+
+impl Default for TokenAuthConfig {
+    fn default() -> Self {
+        Self {
+            mode: AuthMode::Off,
+            issuer: None,
+            audience: None,
+            jwks_url: None,
+            user_id_claim: "sub".to_string(),
+            jwks_refresh_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+// The signing keys of the identity provider. Keys are fetched when a token names a key id that
+// isn't cached yet, which also picks up key rotations.
+struct KeySet {
+    client: reqwest::Client,
+    url: Option<String>,
+    refresh_interval: Duration,
+    keys: RwLock<JwkSet>,
+    // Held while fetching, so concurrent misses make a single request
+    fetched_at: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl KeySet {
+    fn find(&self, kid: Option<&str>) -> Option<Jwk> {
+        let keys = self.keys.read().unwrap();
+        match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            // Without a key id the token can only be checked against a single key
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        }
+    }
+
+    async fn get(&self, kid: Option<&str>) -> Result<Jwk, TokenError> {
+        if let Some(key) = self.find(kid) {
+            return Ok(key);
+        }
+        let Some(url) = &self.url else {
+            return Err(TokenError::UnknownKey);
+        };
+
+        let mut fetched_at = self.fetched_at.lock().await;
+        // Another request may have fetched the key while this one waited
+        if let Some(key) = self.find(kid) {
+            return Ok(key);
+        }
+        if fetched_at.is_some_and(|at| at.elapsed() < self.refresh_interval) {
+            return Err(TokenError::UnknownKey);
+        }
+
+        let keys = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| TokenError::Jwks(e.to_string()))?
+            .json::<JwkSet>()
+            .await
+            .map_err(|e| TokenError::Jwks(e.to_string()))?;
+        *fetched_at = Some(Instant::now());
+        *self.keys.write().unwrap() = keys;
+
+        self.find(kid).ok_or(TokenError::UnknownKey)
+    }
+}
+
+// Validates JWTs signed by an OIDC identity provider: the signature against the provider's
+// JWKS, the expiry, issuer and audience. The user id is read from a claim, `sub` by default.
+pub struct JwtValidator {
+    issuer: String,
+    audience: String,
+    user_id_claim: String,
+    keys: KeySet,
+}
+
+impl JwtValidator {
+    pub fn new(
+        issuer: impl Into<String>,
+        audience: impl Into<String>,
+        jwks_url: impl Into<String>,
+    ) -> Self {
+        let mut validator = Self::with_jwks(issuer, audience, JwkSet { keys: Vec::new() });
+        validator.keys.url = Some(jwks_url.into());
+        validator
+    }
+
+    // Validator that only trusts the given keys, never fetching others
+    pub fn with_jwks(issuer: impl Into<String>, audience: impl Into<String>, jwks: JwkSet) -> Self {
+        Self {
+            issuer: issuer.into(),
+            audience: audience.into(),
+            user_id_claim: TokenAuthConfig::default().user_id_claim,
+            keys: KeySet {
+                client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                    .expect("HTTP client configuration is valid"),
+                url: None,
+                refresh_interval: TokenAuthConfig::default().jwks_refresh_interval,
+                keys: RwLock::new(jwks),
+                fetched_at: tokio::sync::Mutex::new(None),
+            },
+        }
+    }
+
+    // Validator for the configured identity provider, which needs an issuer, audience and
+    // JWKS URL
+    pub fn from_config(config: &TokenAuthConfig) -> Result<Self, TokenError> {
+        let required = |value: &Option<String>, name: &str| {
+            value
+                .clone()
+                .ok_or_else(|| TokenError::Config(format!("{} must be set", name)))
+        };
+        let validator = Self::new(
+            required(&config.issuer, "JWT_ISSUER")?,
+            required(&config.audience, "JWT_AUDIENCE")?,
+            required(&config.jwks_url, "JWT_JWKS_URL")?,
+        )
+        .with_user_id_claim(config.user_id_claim.clone())
+        .with_jwks_refresh_interval(config.jwks_refresh_interval);
+        Ok(validator)
+    }
+
+    pub fn with_user_id_claim(mut self, claim: impl Into<String>) -> Self {
+        self.user_id_claim = claim.into();
+        self
+    }
+
+    pub fn with_jwks_refresh_interval(mut self, interval: Duration) -> Self {
+        self.keys.refresh_interval = interval;
+        self
+    }
+}
+
+#[async_trait]
+impl AuthProvider for JwtValidator {
+    async fn authenticate(&self, token: &str) -> Result<Uuid, TokenError> {
+        let invalid = |e: jsonwebtoken::errors::Error| TokenError::Invalid(e.to_string());
+        let header = decode_header(token).map_err(invalid)?;
+        // Identity providers sign with their private keys; a shared secret would let anyone
+        // holding the JWKS mint tokens
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(TokenError::Invalid(format!(
+                "{:?} tokens are not accepted",
+                header.alg
+            )));
+        }
+
+        let jwk = self.keys.get(header.kid.as_deref()).await?;
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            return Err(TokenError::UnknownKey);
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(invalid)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        let claims = decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(invalid)?
+            .claims;
+
+        claims
+            .get(&self.user_id_claim)
+            .and_then(Value::as_str)
+            .and_then(|user_id| Uuid::parse_str(user_id).ok())
+            .ok_or_else(|| {
+                TokenError::Invalid(format!("{} claim is not a user id", self.user_id_claim))
+            })
+    }
+}
+
+// The user a request's bearer token was issued to, attached to the request extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthenticatedUser(pub Uuid);
+
+// The user a request's bearer token was issued to, if any
+pub fn authenticated_user<T>(request: &Request<T>) -> Option<Uuid> {
+    request
+        .extensions()
+        .get::<AuthenticatedUser>()
+        .map(|user| user.0)
+}
+
+// Tower layer authenticating the bearer tokens of delivery RPCs. The user a valid token was
+// issued to is attached to the request as an `AuthenticatedUser`, for the handlers to check
+// `user_id` fields against. The admin service, health checks and reflection are left alone.
+#[derive(Clone)]
+pub struct TokenAuthLayer {
+    mode: AuthMode,
+    provider: Option<Arc<dyn AuthProvider>>,
+}
+
+impl TokenAuthLayer {
+    pub fn new(mode: AuthMode, provider: Arc<dyn AuthProvider>) -> Self {
+        Self {
+            mode,
+            provider: Some(provider),
+        }
+    }
+
+    // Layer that ignores bearer tokens
+    pub fn disabled() -> Self {
+        Self {
+            mode: AuthMode::Off,
+            provider: None,
+        }
+    }
+}
+
+impl<S> Layer<S> for TokenAuthLayer {
+    type Service = TokenAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TokenAuth {
+            inner,
+            mode: self.mode,
+            provider: self.provider.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TokenAuth<S> {
+    inner: S,
+    mode: AuthMode,
+    provider: Option<Arc<dyn AuthProvider>>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for TokenAuth<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+        let Some(provider) = self
+            .provider
+            .clone()
+            .filter(|_| self.mode != AuthMode::Off && is_delivery_rpc(request.uri().path()))
+        else {
+            return Box::pin(self.inner.call(request));
+        };
+
+        let token = bearer_token(request.headers());
+        let mode = self.mode;
+        // The ready inner service is used for this request, a clone is left for the next
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            match token {
+                Some(Ok(token)) => match provider.authenticate(&token).await {
+                    Ok(user_id) => {
+                        request.extensions_mut().insert(AuthenticatedUser(user_id));
+                    }
+                    Err(TokenError::Jwks(e)) => {
+                        warn!("Could not fetch the JWKS: {}", e);
+                        return Ok(
+                            Status::unavailable("Token validation is unavailable").into_http()
+                        );
+                    }
+                    Err(e) => return Ok(Status::unauthenticated(e.to_string()).into_http()),
+                },
+                Some(Err(status)) => return Ok(status.into_http()),
+                None if mode == AuthMode::Required => {
+                    return Ok(
+                        Status::unauthenticated("Request must carry a bearer token").into_http()
+                    );
+                }
+                None => {}
+            }
+            inner.call(request).await
+        })
+    }
+}
+
+// The token of the request's `authorization` header, or None if it has none
+fn bearer_token(headers: &http::HeaderMap) -> Option<Result<String, Status>> {
+    let value = headers.get(AUTHORIZATION_HEADER)?;
+    let token = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| Status::unauthenticated("authorization must be a Bearer token"));
+    Some(token)
+}
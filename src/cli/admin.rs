@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use tonic::{Code, Request, Status};
+
+use crate::auth::admin::AdminPrincipal;
+use crate::cli::AdminCommand;
+use crate::db::DatabaseInterface;
+use crate::service::mls::mls_admin_service_server::MlsAdminService;
+use crate::service::mls::{self, ForcePurgeRequest, GetStorageStatsRequest};
+use crate::service::MLSServiceImpl;
+
+// Run an admin command through the admin service, so it is validated, logged and announced to
+// the other instances as if it had come in over gRPC, and return the report to print. Whoever
+// can run the binary against the database is an operator.
+pub async fn run<DB: DatabaseInterface + Send + Sync + 'static>(
+    service: &MLSServiceImpl<DB>,
+    command: AdminCommand,
+) -> Result<String, Status> {
+    match command {
+        AdminCommand::PurgeGroup { group_id, dry_run } => {
+            let mut request = Request::new(ForcePurgeRequest {
+                entity_type: mls::EntityType::Group as i32,
+                id: group_id.to_string(),
+                dry_run,
+            });
+            request.extensions_mut().insert(AdminPrincipal);
+            let response = service
+                .force_purge(request)
+                .await
+                .map_err(|status| match status.code() {
+                    // Only groups already deleted are purged, so a live group is not found either
+                    Code::NotFound => Status::not_found(format!(
+                        "No soft-deleted group {}; delete the group before purging it",
+                        group_id
+                    )),
+                    _ => status,
+                })?
+                .into_inner();
+            Ok(describe_purge(group_id, dry_run, &response))
+        }
+        AdminCommand::Stats { group, limit } => {
+            let response = service
+                .get_storage_stats(Request::new(GetStorageStatsRequest {
+                    group_id: group.map(|id| id.to_string()).unwrap_or_default(),
+                    limit,
+                }))
+                .await?
+                .into_inner();
+            Ok(describe_stats(&response))
+        }
+    }
+}
+
+// Helper function to describe the outcome of a group purge
+fn describe_purge(
+    group_id: uuid::Uuid,
+    dry_run: bool,
+    response: &mls::ForcePurgeResponse,
+) -> String {
+    if !dry_run {
+        return format!("Purged group {}", group_id);
+    }
+    let mut report = format!("Purging group {} would delete:", group_id);
+    for affected in response.affected.iter().filter(|a| a.rows > 0) {
+        let _ = write!(report, "\n  {:>8} {}", affected.rows, affected.table);
+    }
+    report
+}
+
+// Helper function to lay out storage stats as a table, largest group first, followed by the
+// totals
+fn describe_stats(response: &mls::GetStorageStatsResponse) -> String {
+    let mut report = format!(
+        "{:<36} {:>9} {:>8} {:>14} {:>14} {:>7} {:>12}",
+        "GROUP", "MESSAGES", "MEMBERS", "PAYLOAD BYTES", "DELIVERED", "FANOUT", "BYTES/DAY"
+    );
+    for group in &response.groups {
+        let _ = write!(
+            report,
+            "\n{:<36} {:>9} {:>8} {:>14} {:>14} {:>7.1} {:>12.0}",
+            group.group_id,
+            group.messages,
+            group.active_members,
+            group.payload_bytes,
+            group.delivered_bytes,
+            group.fanout,
+            group.bytes_per_day
+        );
+    }
+    let _ = write!(
+        report,
+        "\n{:<36} {:>9} {:>8} {:>14} {:>14} {:>7} {:>12.0}",
+        format!("TOTAL ({} groups)", response.groups.len()),
+        "",
+        "",
+        response.payload_bytes,
+        response.delivered_bytes,
+        "",
+        response.bytes_per_day
+    );
+    report
+}
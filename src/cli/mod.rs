@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use uuid::Uuid;
+
+pub mod admin;
+
+// Command line of the server binary. Without a subcommand it serves, as it did before it had
+// subcommands, so existing deployments keep working.
+#[derive(Debug, Parser)]
+#[command(name = "hermetic-mls", version, about = "MLS delivery service")]
+pub struct Cli {
+    /// Keep everything in memory instead of Postgres (needs the memory-db feature)
+    #[arg(long, global = true)]
+    pub dev: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    // The command to run, serving when none was given
+    pub fn command(&self) -> Command {
+        self.command.clone().unwrap_or(Command::Serve)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Serve the delivery and admin services (the default)
+    Serve,
+    /// Run the database migrations and exit, even with RUN_MIGRATIONS=false
+    Migrate,
+    /// Import clients, key packages and groups exported from another delivery service
+    Import {
+        /// Export file, in JSON
+        path: PathBuf,
+    },
+    /// Routine admin tasks, run against the database without going through the gRPC API
+    #[command(subcommand)]
+    Admin(AdminCommand),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum AdminCommand {
+    /// Purge a soft-deleted group and everything in it now, instead of after the grace period
+    PurgeGroup {
+        /// UUID of the group
+        group_id: Uuid,
+        /// Print the rows the purge would delete without deleting them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the stored payload size and growth of the largest groups
+    Stats {
+        /// UUID of one group to report on instead
+        #[arg(long)]
+        group: Option<Uuid>,
+        /// Maximum groups to report on (at most 1000)
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+}
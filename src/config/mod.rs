@@ -0,0 +1,1143 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::attestation::{AttestationFormat, AttestationVerifiers, WebhookVerifier};
+use crate::auth::token::TokenAuthConfig;
+use crate::auth::{AuthConfig, AuthMode};
+use crate::db::{DbNamespace, InvalidIdentifier, MessageType, PoolConfig, PrimingConfig};
+use crate::events::publisher::PublisherConfig;
+use crate::ids;
+use crate::metrics::slo::parse_slos;
+use crate::metrics::SloConfig;
+use crate::secrets::{SecretError, SecretResolver};
+use crate::service::admission::AdmissionConfig;
+use crate::service::membership_cache::DEFAULT_MEMBERSHIP_CACHE_SIZE;
+use crate::service::probe::ReadinessProbe;
+use crate::service::rate_limit::RateLimitConfig;
+use crate::service::validation::ValidationPolicy;
+use crate::settings::cors::{AllowedOrigins, CorsError, DEFAULT_CORS_MAX_AGE};
+use crate::settings::{CorsConfig, RuntimeSettings};
+use crate::tls::TlsConfig;
+use crate::trace::DEFAULT_TRACE_LINES_PER_MINUTE;
+
+// Environment variable naming the TOML config file
+pub const CONFIG_FILE_VAR: &str = "CONFIG_FILE";
+
+// Errors loading the startup configuration
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse config file {path}: {source}")]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    // Every invalid value found, each naming its environment variable and config file key
+    #[error("Invalid configuration:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+// A config value holding a secret or a reference to one (see `secrets`), resolved at startup.
+// Its Debug output never shows it.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct SecretValue(String);
+
+impl SecretValue {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    // Resolve the value; errors name it after its environment variable
+    pub fn resolve(&self, name: &str, secrets: &SecretResolver) -> Result<String, SecretError> {
+        secrets.resolve(name, &self.0)
+    }
+}
+
+impl fmt::Debug for SecretValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretValue(..)")
+    }
+}
+
+impl FromStr for SecretValue {
+    type Err = std::convert::Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(value))
+    }
+}
+
+// Configuration read once at startup; changing it needs a restart. It comes from an optional
+// TOML file named by CONFIG_FILE, overridden by the environment variables documented in the
+// README, and is checked as a whole so every invalid value is reported at once. The runtime
+// settings start from its [retention] and [limits] values, and can still change them on reload.
+// Vault reads its own variables.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // How uploaded MLS payloads are checked: strict, or unchecked in test-utils builds
+    pub validation: ValidationPolicy,
+    // JSON file of runtime setting overrides, read again on reload
+    pub settings_file: Option<PathBuf>,
+    pub server: ServerSection,
+    pub database: DatabaseSection,
+    pub tls: TlsSection,
+    pub cors: CorsSection,
+    pub retention: RetentionSection,
+    pub limits: LimitsSection,
+    pub jobs: JobsSection,
+    pub slo: SloSection,
+    pub auth: AuthSection,
+    pub attestation: AttestationSection,
+    pub cache_priming: CachePrimingSection,
+    pub events: EventsSection,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub addr: SocketAddr,
+    // Where the admin service is served, apart from the delivery service
+    pub admin_addr: SocketAddr,
+    // Bearer token operators present to the admin service; without one it rejects every call
+    pub admin_token: Option<SecretValue>,
+    // Id strategy for new rows: uuid_v4 or uuid_v7
+    pub id_generator: String,
+    pub readiness_probe: ReadinessProbe,
+    // Key signing admin listing page tokens; random per process if unset
+    pub page_token_secret: Option<SecretValue>,
+    pub legacy_api: bool,
+    pub json_debug_endpoint: bool,
+    pub metrics_endpoint: bool,
+}
+
+impl Default for ServerSection {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from(([0, 0, 0, 0], 50051)),
+            admin_addr: SocketAddr::from(([127, 0, 0, 1], 50052)),
+            admin_token: None,
+            id_generator: "uuid_v4".to_string(),
+            readiness_probe: ReadinessProbe::default(),
+            page_token_secret: None,
+            legacy_api: true,
+            json_debug_endpoint: false,
+            metrics_endpoint: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSection {
+    // Required unless running with --dev
+    pub url: Option<SecretValue>,
+    // Overrides the password in the URL
+    pub password: Option<SecretValue>,
+    // Base64 master key tenants' storage keys are derived from; unset stores blobs in the clear
+    pub encryption_key: Option<SecretValue>,
+    pub schema: Option<String>,
+    pub table_prefix: String,
+    pub run_migrations: bool,
+    pub message_fanout: bool,
+    pub pool_size: u32,
+    pub background_pool_size: u32,
+    pub acquire_timeout_secs: u64,
+}
+
+impl Default for DatabaseSection {
+    fn default() -> Self {
+        let pools = PoolConfig::default();
+        Self {
+            url: None,
+            password: None,
+            encryption_key: None,
+            schema: None,
+            table_prefix: String::new(),
+            run_migrations: true,
+            message_fanout: false,
+            pool_size: pools.interactive_connections,
+            background_pool_size: pools.background_connections,
+            acquire_timeout_secs: pools.acquire_timeout.as_secs(),
+        }
+    }
+}
+
+impl DatabaseSection {
+    pub fn namespace(&self) -> Result<DbNamespace, InvalidIdentifier> {
+        DbNamespace::new(self.schema.clone(), self.table_prefix.clone())
+    }
+
+    pub fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            interactive_connections: self.pool_size,
+            background_connections: self.background_pool_size,
+            acquire_timeout: Duration::from_secs(self.acquire_timeout_secs),
+            ..PoolConfig::default()
+        }
+    }
+}
+
+// PEM certificate chain and private key; TLS is served when both are set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSection {
+    pub cert_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+}
+
+impl TlsSection {
+    pub fn tls_config(&self) -> Option<TlsConfig> {
+        Some(TlsConfig {
+            cert_path: self.cert_path.clone()?,
+            key_path: self.key_path.clone()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsSection {
+    // Origins browser clients may call from; none, or "*", allows any
+    pub allowed_origins: Vec<String>,
+    pub max_age_secs: u64,
+}
+
+impl Default for CorsSection {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            max_age_secs: DEFAULT_CORS_MAX_AGE.as_secs(),
+        }
+    }
+}
+
+impl CorsSection {
+    pub fn cors_config(&self) -> Result<CorsConfig, CorsError> {
+        Ok(CorsConfig {
+            origins: AllowedOrigins::parse(&self.allowed_origins.join(","))?,
+            max_age: Duration::from_secs(self.max_age_secs),
+        })
+    }
+}
+
+// Starting values of the runtime settings of the same names
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionSection {
+    pub purge_grace_days: i64,
+    pub message_retention_days: BTreeMap<MessageType, i64>,
+    pub used_key_package_retention_days: i64,
+}
+
+impl Default for RetentionSection {
+    fn default() -> Self {
+        let settings = RuntimeSettings::default();
+        Self {
+            purge_grace_days: settings.purge_grace_days,
+            message_retention_days: settings.message_retention_days,
+            used_key_package_retention_days: settings.used_key_package_retention_days,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsSection {
+    // Starting values of the runtime settings of the same names
+    pub max_message_ttl_secs: i64,
+    pub max_queued_application_messages: i64,
+    // Groups whose active members are cached; 0 turns the cache off
+    pub membership_cache_size: usize,
+    pub verbose_trace_lines_per_minute: u32,
+    // Delivery RPCs handled at once (0 disables admission control), the requests of each
+    // priority class that may queue for a slot, and how long they wait
+    pub admission_max_in_flight: usize,
+    pub admission_critical_queue: usize,
+    pub admission_standard_queue: usize,
+    pub admission_bulk_queue: usize,
+    pub admission_queue_timeout_ms: u64,
+    // Sustained requests a second and bursts of FetchMessages and ClaimKeyPackage allowed per
+    // authenticated caller and per IP address; a rate of 0 turns that limit off
+    pub rate_limit_client_per_sec: u32,
+    pub rate_limit_client_burst: u32,
+    pub rate_limit_ip_per_sec: u32,
+    pub rate_limit_ip_burst: u32,
+}
+
+impl Default for LimitsSection {
+    fn default() -> Self {
+        let settings = RuntimeSettings::default();
+        let admission = AdmissionConfig::default();
+        let [critical, standard, bulk] = admission.max_queued;
+        let rate_limits = RateLimitConfig::default();
+        Self {
+            max_message_ttl_secs: settings.max_message_ttl_secs,
+            max_queued_application_messages: settings.max_queued_application_messages,
+            membership_cache_size: DEFAULT_MEMBERSHIP_CACHE_SIZE,
+            verbose_trace_lines_per_minute: DEFAULT_TRACE_LINES_PER_MINUTE,
+            admission_max_in_flight: admission.max_in_flight,
+            admission_critical_queue: critical,
+            admission_standard_queue: standard,
+            admission_bulk_queue: bulk,
+            admission_queue_timeout_ms: admission.queue_timeout.as_millis() as u64,
+            rate_limit_client_per_sec: rate_limits.client_per_sec,
+            rate_limit_client_burst: rate_limits.client_burst,
+            rate_limit_ip_per_sec: rate_limits.ip_per_sec,
+            rate_limit_ip_burst: rate_limits.ip_burst,
+        }
+    }
+}
+
+impl LimitsSection {
+    pub fn admission_config(&self) -> AdmissionConfig {
+        AdmissionConfig {
+            max_in_flight: self.admission_max_in_flight,
+            max_queued: [
+                self.admission_critical_queue,
+                self.admission_standard_queue,
+                self.admission_bulk_queue,
+            ],
+            queue_timeout: Duration::from_millis(self.admission_queue_timeout_ms),
+        }
+    }
+
+    pub fn rate_limit_config(&self) -> RateLimitConfig {
+        RateLimitConfig {
+            client_per_sec: self.rate_limit_client_per_sec,
+            client_burst: self.rate_limit_client_burst,
+            ip_per_sec: self.rate_limit_ip_per_sec,
+            ip_burst: self.rate_limit_ip_burst,
+        }
+    }
+}
+
+// Intervals of the background jobs, in seconds
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsSection {
+    pub janitor_interval_secs: u64,
+    pub feature_flag_refresh_secs: u64,
+    pub verbose_trace_refresh_secs: u64,
+    pub self_test_interval_secs: u64,
+    // URL welcomes reported stranded are POSTed to, in addition to the notice to group admins
+    pub welcome_alert_webhook_url: Option<SecretValue>,
+}
+
+impl Default for JobsSection {
+    fn default() -> Self {
+        Self {
+            janitor_interval_secs: 300,
+            feature_flag_refresh_secs: 30,
+            verbose_trace_refresh_secs: 30,
+            self_test_interval_secs: 60,
+            welcome_alert_webhook_url: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SloSection {
+    // Per-RPC latency objectives, such as "FetchMessages p99<100ms"
+    pub latency_slos: Vec<String>,
+    pub window_secs: u64,
+    pub burn_rate_alert: f64,
+    // URL alerts are POSTed to, in addition to the log
+    pub alert_webhook_url: Option<SecretValue>,
+}
+
+impl Default for SloSection {
+    fn default() -> Self {
+        let defaults = SloConfig::default();
+        Self {
+            latency_slos: defaults.slos.iter().map(ToString::to_string).collect(),
+            window_secs: defaults.window.as_secs(),
+            burn_rate_alert: defaults.burn_rate_alert,
+            alert_webhook_url: None,
+        }
+    }
+}
+
+impl SloSection {
+    pub fn slo_config(&self) -> Result<SloConfig, String> {
+        Ok(SloConfig {
+            slos: parse_slos(&self.latency_slos.join(","))?,
+            window: Duration::from_secs(self.window_secs),
+            burn_rate_alert: self.burn_rate_alert,
+            ..SloConfig::default()
+        })
+    }
+}
+
+// How delivery callers authenticate: with the signature key they registered, a bearer token
+// from an identity provider, or both
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSection {
+    pub client_auth: AuthMode,
+    pub client_auth_window_secs: u64,
+    pub client_key_refresh_secs: u64,
+    pub token_auth: AuthMode,
+    // Required while token_auth is on
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    pub jwt_jwks_url: Option<String>,
+    pub jwt_user_id_claim: String,
+    pub jwt_jwks_refresh_secs: u64,
+}
+
+impl Default for AuthSection {
+    fn default() -> Self {
+        let client = AuthConfig::default();
+        let token = TokenAuthConfig::default();
+        Self {
+            client_auth: client.mode,
+            client_auth_window_secs: client.window.as_secs(),
+            client_key_refresh_secs: client.key_refresh_interval.as_secs(),
+            token_auth: token.mode,
+            jwt_issuer: token.issuer,
+            jwt_audience: token.audience,
+            jwt_jwks_url: token.jwks_url,
+            jwt_user_id_claim: token.user_id_claim,
+            jwt_jwks_refresh_secs: token.jwks_refresh_interval.as_secs(),
+        }
+    }
+}
+
+impl AuthSection {
+    pub fn auth_config(&self) -> AuthConfig {
+        AuthConfig {
+            mode: self.client_auth,
+            window: Duration::from_secs(self.client_auth_window_secs),
+            key_refresh_interval: Duration::from_secs(self.client_key_refresh_secs),
+        }
+    }
+
+    pub fn token_auth_config(&self) -> TokenAuthConfig {
+        TokenAuthConfig {
+            mode: self.token_auth,
+            issuer: self.jwt_issuer.clone(),
+            audience: self.jwt_audience.clone(),
+            jwks_url: self.jwt_jwks_url.clone(),
+            user_id_claim: self.jwt_user_id_claim.clone(),
+            jwks_refresh_interval: Duration::from_secs(self.jwt_jwks_refresh_secs),
+        }
+    }
+}
+
+// URLs of the webhook verifiers device attestations are POSTed to, by format. Registrations
+// attested with a format that has none are rejected.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttestationSection {
+    pub play_integrity_url: Option<String>,
+    pub app_attest_url: Option<String>,
+    pub custom_url: Option<String>,
+}
+
+impl AttestationSection {
+    fn urls(&self) -> [(AttestationFormat, &Option<String>); 3] {
+        [
+            (AttestationFormat::PlayIntegrity, &self.play_integrity_url),
+            (AttestationFormat::AppAttest, &self.app_attest_url),
+            (AttestationFormat::Custom, &self.custom_url),
+        ]
+    }
+
+    pub fn verifiers(&self) -> AttestationVerifiers {
+        let mut verifiers = AttestationVerifiers::default();
+        for (format, url) in self.urls() {
+            if let Some(url) = url {
+                verifiers = verifiers.with_verifier(format, Arc::new(WebhookVerifier::new(url)));
+            }
+        }
+        verifiers
+    }
+}
+
+// Loading recently active groups before the instance reports ready
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CachePrimingSection {
+    pub enabled: bool,
+    pub window_secs: u64,
+    pub max_groups: i64,
+    pub concurrency: usize,
+    pub timeout_secs: u64,
+}
+
+impl Default for CachePrimingSection {
+    fn default() -> Self {
+        let defaults = PrimingConfig::default();
+        Self {
+            enabled: false,
+            window_secs: defaults.window.as_secs(),
+            max_groups: defaults.max_groups,
+            concurrency: defaults.concurrency,
+            timeout_secs: defaults.timeout.as_secs(),
+        }
+    }
+}
+
+impl CachePrimingSection {
+    // None unless priming is on
+    pub fn priming_config(&self) -> Option<PrimingConfig> {
+        self.enabled.then(|| PrimingConfig {
+            window: Duration::from_secs(self.window_secs),
+            max_groups: self.max_groups,
+            concurrency: self.concurrency,
+            timeout: Duration::from_secs(self.timeout_secs),
+        })
+    }
+}
+
+// The external bus events are published to: nats or kafka, each needing the build feature of
+// the same name. Nothing is published while it's unset.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsSection {
+    pub publisher: Option<String>,
+    pub nats_url: String,
+    pub nats_subject_prefix: String,
+    pub kafka_brokers: String,
+    pub kafka_topic: String,
+}
+
+impl Default for EventsSection {
+    fn default() -> Self {
+        Self {
+            publisher: None,
+            nats_url: "nats://localhost:4222".to_string(),
+            nats_subject_prefix: "mls.events".to_string(),
+            kafka_brokers: "localhost:9092".to_string(),
+            kafka_topic: "mls.events".to_string(),
+        }
+    }
+}
+
+impl EventsSection {
+    pub fn publisher_config(&self) -> Result<Option<PublisherConfig>, String> {
+        match self.publisher.as_deref() {
+            None => Ok(None),
+            Some("nats") if cfg!(feature = "nats") => Ok(Some(PublisherConfig::Nats {
+                url: self.nats_url.clone(),
+                subject_prefix: self.nats_subject_prefix.clone(),
+            })),
+            Some("kafka") if cfg!(feature = "kafka") => Ok(Some(PublisherConfig::Kafka {
+                brokers: self.kafka_brokers.clone(),
+                topic: self.kafka_topic.clone(),
+            })),
+            Some(publisher @ ("nats" | "kafka")) => {
+                Err(format!("this build doesn't have the {} feature", publisher))
+            }
+            Some(publisher) => Err(format!(
+                "unknown event publisher {:?}, expected nats or kafka",
+                publisher
+            )),
+        }
+    }
+}
+
+impl Config {
+    // Read the file named by CONFIG_FILE, if set, and the environment
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = env::var(CONFIG_FILE_VAR).ok().filter(|v| !v.is_empty());
+        Self::from_sources(file.as_deref().map(Path::new), |name| env::var(name).ok())
+    }
+
+    // Read a TOML config file, if any, override it with the variables `env` returns, and check
+    // the result
+    pub fn from_sources(
+        file: Option<&Path>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut config = match file {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+                    path: path.display().to_string(),
+                    source,
+                })?;
+                Self::from_toml(&contents).map_err(|source| ConfigError::Parse {
+                    path: path.display().to_string(),
+                    source,
+                })?
+            }
+            None => Self::default(),
+        };
+
+        let mut overrides = Overrides {
+            env: &env,
+            problems: Vec::new(),
+        };
+        config.apply_env(&mut overrides);
+        let mut problems = overrides.problems;
+        problems.extend(config.check());
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // Parse a TOML config file, without checking the values
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    // The runtime settings before the environment and settings file override them
+    pub fn settings_defaults(&self) -> RuntimeSettings {
+        RuntimeSettings {
+            purge_grace_days: self.retention.purge_grace_days,
+            message_retention_days: self.retention.message_retention_days.clone(),
+            used_key_package_retention_days: self.retention.used_key_package_retention_days,
+            max_message_ttl_secs: self.limits.max_message_ttl_secs,
+            max_queued_application_messages: self.limits.max_queued_application_messages,
+            ..RuntimeSettings::default()
+        }
+    }
+
+    fn apply_env(&mut self, env: &mut Overrides) {
+        env.set(&mut self.validation, "VALIDATION_POLICY", "validation");
+        env.set_optional(&mut self.settings_file, "SETTINGS_FILE", "settings_file");
+
+        let server = &mut self.server;
+        env.set(&mut server.addr, "ADDR", "server.addr");
+        env.set(&mut server.admin_addr, "ADMIN_ADDR", "server.admin_addr");
+        env.set_optional(&mut server.admin_token, "ADMIN_TOKEN", "server.admin_token");
+        env.set(
+            &mut server.id_generator,
+            "ID_GENERATOR",
+            "server.id_generator",
+        );
+        env.set(
+            &mut server.readiness_probe,
+            "READINESS_PROBE",
+            "server.readiness_probe",
+        );
+        env.set_optional(
+            &mut server.page_token_secret,
+            "PAGE_TOKEN_SECRET",
+            "server.page_token_secret",
+        );
+        env.set(
+            &mut server.legacy_api,
+            "LEGACY_API_ENABLED",
+            "server.legacy_api",
+        );
+        env.set(
+            &mut server.json_debug_endpoint,
+            "JSON_DEBUG_ENDPOINT",
+            "server.json_debug_endpoint",
+        );
+        env.set(
+            &mut server.metrics_endpoint,
+            "METRICS_ENDPOINT",
+            "server.metrics_endpoint",
+        );
+
+        let database = &mut self.database;
+        env.set_optional(&mut database.url, "DATABASE_URL", "database.url");
+        env.set_optional(
+            &mut database.password,
+            "DATABASE_PASSWORD",
+            "database.password",
+        );
+        env.set_optional(
+            &mut database.encryption_key,
+            "DATABASE_ENCRYPTION_KEY",
+            "database.encryption_key",
+        );
+        env.set_optional(&mut database.schema, "DATABASE_SCHEMA", "database.schema");
+        env.set(
+            &mut database.table_prefix,
+            "TABLE_PREFIX",
+            "database.table_prefix",
+        );
+        env.set(
+            &mut database.run_migrations,
+            "RUN_MIGRATIONS",
+            "database.run_migrations",
+        );
+        env.set(
+            &mut database.message_fanout,
+            "MESSAGE_FANOUT",
+            "database.message_fanout",
+        );
+        env.set(
+            &mut database.pool_size,
+            "DATABASE_POOL_SIZE",
+            "database.pool_size",
+        );
+        env.set(
+            &mut database.background_pool_size,
+            "DATABASE_BACKGROUND_POOL_SIZE",
+            "database.background_pool_size",
+        );
+        env.set(
+            &mut database.acquire_timeout_secs,
+            "DATABASE_ACQUIRE_TIMEOUT_SECS",
+            "database.acquire_timeout_secs",
+        );
+
+        env.set_optional(&mut self.tls.cert_path, "TLS_CERT_PATH", "tls.cert_path");
+        env.set_optional(&mut self.tls.key_path, "TLS_KEY_PATH", "tls.key_path");
+
+        env.set_list(&mut self.cors.allowed_origins, "CORS_ALLOWED_ORIGINS");
+        env.set(
+            &mut self.cors.max_age_secs,
+            "CORS_MAX_AGE_SECS",
+            "cors.max_age_secs",
+        );
+
+        let limits = &mut self.limits;
+        env.set(
+            &mut limits.membership_cache_size,
+            "MEMBERSHIP_CACHE_SIZE",
+            "limits.membership_cache_size",
+        );
+        env.set(
+            &mut limits.verbose_trace_lines_per_minute,
+            "VERBOSE_TRACE_LINES_PER_MINUTE",
+            "limits.verbose_trace_lines_per_minute",
+        );
+        env.set(
+            &mut limits.admission_max_in_flight,
+            "ADMISSION_MAX_IN_FLIGHT",
+            "limits.admission_max_in_flight",
+        );
+        env.set(
+            &mut limits.admission_critical_queue,
+            "ADMISSION_CRITICAL_QUEUE",
+            "limits.admission_critical_queue",
+        );
+        env.set(
+            &mut limits.admission_standard_queue,
+            "ADMISSION_STANDARD_QUEUE",
+            "limits.admission_standard_queue",
+        );
+        env.set(
+            &mut limits.admission_bulk_queue,
+            "ADMISSION_BULK_QUEUE",
+            "limits.admission_bulk_queue",
+        );
+        env.set(
+            &mut limits.admission_queue_timeout_ms,
+            "ADMISSION_QUEUE_TIMEOUT_MS",
+            "limits.admission_queue_timeout_ms",
+        );
+        env.set(
+            &mut limits.rate_limit_client_per_sec,
+            "RATE_LIMIT_CLIENT_PER_SEC",
+            "limits.rate_limit_client_per_sec",
+        );
+        env.set(
+            &mut limits.rate_limit_client_burst,
+            "RATE_LIMIT_CLIENT_BURST",
+            "limits.rate_limit_client_burst",
+        );
+        env.set(
+            &mut limits.rate_limit_ip_per_sec,
+            "RATE_LIMIT_IP_PER_SEC",
+            "limits.rate_limit_ip_per_sec",
+        );
+        env.set(
+            &mut limits.rate_limit_ip_burst,
+            "RATE_LIMIT_IP_BURST",
+            "limits.rate_limit_ip_burst",
+        );
+
+        let jobs = &mut self.jobs;
+        env.set(
+            &mut jobs.janitor_interval_secs,
+            "JANITOR_INTERVAL_SECS",
+            "jobs.janitor_interval_secs",
+        );
+        env.set(
+            &mut jobs.feature_flag_refresh_secs,
+            "FEATURE_FLAG_REFRESH_SECS",
+            "jobs.feature_flag_refresh_secs",
+        );
+        env.set(
+            &mut jobs.verbose_trace_refresh_secs,
+            "VERBOSE_TRACE_REFRESH_SECS",
+            "jobs.verbose_trace_refresh_secs",
+        );
+        env.set(
+            &mut jobs.self_test_interval_secs,
+            "SELF_TEST_INTERVAL_SECS",
+            "jobs.self_test_interval_secs",
+        );
+        env.set_optional(
+            &mut jobs.welcome_alert_webhook_url,
+            "WELCOME_ALERT_WEBHOOK_URL",
+            "jobs.welcome_alert_webhook_url",
+        );
+
+        env.set_list(&mut self.slo.latency_slos, "LATENCY_SLOS");
+        env.set(
+            &mut self.slo.window_secs,
+            "SLO_WINDOW_SECS",
+            "slo.window_secs",
+        );
+        env.set(
+            &mut self.slo.burn_rate_alert,
+            "SLO_BURN_RATE_ALERT",
+            "slo.burn_rate_alert",
+        );
+        env.set_optional(
+            &mut self.slo.alert_webhook_url,
+            "SLO_ALERT_WEBHOOK_URL",
+            "slo.alert_webhook_url",
+        );
+
+        let auth = &mut self.auth;
+        env.set(&mut auth.client_auth, "CLIENT_AUTH", "auth.client_auth");
+        env.set(
+            &mut auth.client_auth_window_secs,
+            "CLIENT_AUTH_WINDOW_SECS",
+            "auth.client_auth_window_secs",
+        );
+        env.set(
+            &mut auth.client_key_refresh_secs,
+            "CLIENT_KEY_REFRESH_SECS",
+            "auth.client_key_refresh_secs",
+        );
+        env.set(&mut auth.token_auth, "TOKEN_AUTH", "auth.token_auth");
+        env.set_optional(&mut auth.jwt_issuer, "JWT_ISSUER", "auth.jwt_issuer");
+        env.set_optional(&mut auth.jwt_audience, "JWT_AUDIENCE", "auth.jwt_audience");
+        env.set_optional(&mut auth.jwt_jwks_url, "JWT_JWKS_URL", "auth.jwt_jwks_url");
+        env.set(
+            &mut auth.jwt_user_id_claim,
+            "JWT_USER_ID_CLAIM",
+            "auth.jwt_user_id_claim",
+        );
+        env.set(
+            &mut auth.jwt_jwks_refresh_secs,
+            "JWT_JWKS_REFRESH_SECS",
+            "auth.jwt_jwks_refresh_secs",
+        );
+
+        let attestation = &mut self.attestation;
+        env.set_optional(
+            &mut attestation.play_integrity_url,
+            "ATTESTATION_PLAY_INTEGRITY_URL",
+            "attestation.play_integrity_url",
+        );
+        env.set_optional(
+            &mut attestation.app_attest_url,
+            "ATTESTATION_APP_ATTEST_URL",
+            "attestation.app_attest_url",
+        );
+        env.set_optional(
+            &mut attestation.custom_url,
+            "ATTESTATION_CUSTOM_URL",
+            "attestation.custom_url",
+        );
+
+        let priming = &mut self.cache_priming;
+        env.set(
+            &mut priming.enabled,
+            "CACHE_PRIMING",
+            "cache_priming.enabled",
+        );
+        env.set(
+            &mut priming.window_secs,
+            "CACHE_PRIMING_WINDOW_SECS",
+            "cache_priming.window_secs",
+        );
+        env.set(
+            &mut priming.max_groups,
+            "CACHE_PRIMING_MAX_GROUPS",
+            "cache_priming.max_groups",
+        );
+        env.set(
+            &mut priming.concurrency,
+            "CACHE_PRIMING_CONCURRENCY",
+            "cache_priming.concurrency",
+        );
+        env.set(
+            &mut priming.timeout_secs,
+            "CACHE_PRIMING_TIMEOUT_SECS",
+            "cache_priming.timeout_secs",
+        );
+
+        let events = &mut self.events;
+        env.set_optional(&mut events.publisher, "EVENT_PUBLISHER", "events.publisher");
+        env.set(&mut events.nats_url, "NATS_URL", "events.nats_url");
+        env.set(
+            &mut events.nats_subject_prefix,
+            "NATS_SUBJECT_PREFIX",
+            "events.nats_subject_prefix",
+        );
+        env.set(
+            &mut events.kafka_brokers,
+            "KAFKA_BROKERS",
+            "events.kafka_brokers",
+        );
+        env.set(&mut events.kafka_topic, "KAFKA_TOPIC", "events.kafka_topic");
+    }
+
+    // Problems with values that parsed but can't be used
+    fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut problem =
+            |name: &str, message: String| problems.push(format!("{}: {}", name, message));
+
+        if ids::from_name(&self.server.id_generator).is_none() {
+            problem(
+                "ID_GENERATOR (server.id_generator)",
+                format!(
+                    "unknown id generator {:?}, expected uuid_v4 or uuid_v7",
+                    self.server.id_generator
+                ),
+            );
+        }
+        if self.server.admin_addr == self.server.addr {
+            problem(
+                "ADMIN_ADDR (server.admin_addr)",
+                "must differ from ADDR, so the admin service isn't served to clients".to_string(),
+            );
+        }
+        if let Err(e) = self.database.namespace() {
+            problem("DATABASE_SCHEMA or TABLE_PREFIX (database)", e.to_string());
+        }
+        for (name, value) in [
+            (
+                "DATABASE_POOL_SIZE (database.pool_size)",
+                self.database.pool_size,
+            ),
+            (
+                "DATABASE_BACKGROUND_POOL_SIZE (database.background_pool_size)",
+                self.database.background_pool_size,
+            ),
+        ] {
+            if value == 0 {
+                problem(name, "must be at least 1".to_string());
+            }
+        }
+        if self.tls.cert_path.is_some() != self.tls.key_path.is_some() {
+            problem(
+                "TLS_CERT_PATH and TLS_KEY_PATH (tls.cert_path, tls.key_path)",
+                "must be set together".to_string(),
+            );
+        }
+        if let Err(e) = self.cors.cors_config() {
+            problem("CORS_ALLOWED_ORIGINS (cors.allowed_origins)", e.to_string());
+        }
+        if let Err(e) = self.slo.slo_config() {
+            problem("LATENCY_SLOS (slo.latency_slos)", e);
+        }
+        if self.slo.burn_rate_alert.is_nan() || self.slo.burn_rate_alert <= 0.0 {
+            problem(
+                "SLO_BURN_RATE_ALERT (slo.burn_rate_alert)",
+                "must be positive".to_string(),
+            );
+        }
+
+        // Token checks can't be on without an identity provider to check tokens against
+        if self.auth.token_auth != AuthMode::Off {
+            for (name, value) in [
+                ("JWT_ISSUER (auth.jwt_issuer)", &self.auth.jwt_issuer),
+                ("JWT_AUDIENCE (auth.jwt_audience)", &self.auth.jwt_audience),
+                ("JWT_JWKS_URL (auth.jwt_jwks_url)", &self.auth.jwt_jwks_url),
+            ] {
+                if value.is_none() {
+                    problem(name, "must be set while TOKEN_AUTH is on".to_string());
+                }
+            }
+        }
+        if self.auth.jwt_user_id_claim.is_empty() {
+            problem(
+                "JWT_USER_ID_CLAIM (auth.jwt_user_id_claim)",
+                "must not be empty".to_string(),
+            );
+        }
+        let mut urls = vec![(
+            "JWT_JWKS_URL (auth.jwt_jwks_url)".to_string(),
+            &self.auth.jwt_jwks_url,
+        )];
+        for (format, url) in self.attestation.urls() {
+            let name = format!(
+                "ATTESTATION_{}_URL (attestation.{}_url)",
+                format.as_str().to_uppercase(),
+                format.as_str()
+            );
+            urls.push((name, url));
+        }
+        for (name, url) in urls {
+            if let Some(Err(e)) = url.as_deref().map(reqwest::Url::parse) {
+                problem(&name, format!("invalid URL: {}", e));
+            }
+        }
+
+        if let Err(e) = self.events.publisher_config() {
+            problem("EVENT_PUBLISHER (events.publisher)", e);
+        }
+        for (name, value) in [
+            (
+                "CACHE_PRIMING_MAX_GROUPS (cache_priming.max_groups)",
+                self.cache_priming.max_groups,
+            ),
+            (
+                "CACHE_PRIMING_CONCURRENCY (cache_priming.concurrency)",
+                self.cache_priming.concurrency as i64,
+            ),
+        ] {
+            if value < 1 {
+                problem(name, "must be at least 1".to_string());
+            }
+        }
+
+        // Intervals of zero would spin, or panic when the job starts
+        for (name, secs) in [
+            (
+                "DATABASE_ACQUIRE_TIMEOUT_SECS (database.acquire_timeout_secs)",
+                self.database.acquire_timeout_secs,
+            ),
+            (
+                "JANITOR_INTERVAL_SECS (jobs.janitor_interval_secs)",
+                self.jobs.janitor_interval_secs,
+            ),
+            (
+                "FEATURE_FLAG_REFRESH_SECS (jobs.feature_flag_refresh_secs)",
+                self.jobs.feature_flag_refresh_secs,
+            ),
+            (
+                "VERBOSE_TRACE_REFRESH_SECS (jobs.verbose_trace_refresh_secs)",
+                self.jobs.verbose_trace_refresh_secs,
+            ),
+            (
+                "SELF_TEST_INTERVAL_SECS (jobs.self_test_interval_secs)",
+                self.jobs.self_test_interval_secs,
+            ),
+            ("SLO_WINDOW_SECS (slo.window_secs)", self.slo.window_secs),
+            (
+                "CLIENT_AUTH_WINDOW_SECS (auth.client_auth_window_secs)",
+                self.auth.client_auth_window_secs,
+            ),
+            (
+                "CLIENT_KEY_REFRESH_SECS (auth.client_key_refresh_secs)",
+                self.auth.client_key_refresh_secs,
+            ),
+            (
+                "CACHE_PRIMING_WINDOW_SECS (cache_priming.window_secs)",
+                self.cache_priming.window_secs,
+            ),
+            (
+                "CACHE_PRIMING_TIMEOUT_SECS (cache_priming.timeout_secs)",
+                self.cache_priming.timeout_secs,
+            ),
+        ] {
+            if secs == 0 {
+                problem(name, "must be at least 1 second".to_string());
+            }
+        }
+
+        // The runtime settings' own variables are read later, so only the file sets these
+        for (name, value) in [
+            (
+                "retention.purge_grace_days",
+                self.retention.purge_grace_days,
+            ),
+            (
+                "retention.used_key_package_retention_days",
+                self.retention.used_key_package_retention_days,
+            ),
+            (
+                "limits.max_message_ttl_secs",
+                self.limits.max_message_ttl_secs,
+            ),
+            (
+                "limits.max_queued_application_messages",
+                self.limits.max_queued_application_messages,
+            ),
+        ] {
+            if value < 0 {
+                problem(name, "must not be negative".to_string());
+            }
+        }
+        for (message_type, days) in &self.retention.message_retention_days {
+            if *days <= 0 {
+                problem(
+                    &format!("retention.message_retention_days.{}", message_type.as_str()),
+                    "must be at least 1 day; leave the type out to keep its messages".to_string(),
+                );
+            }
+        }
+        problems
+    }
+}
+
+// Environment variables being applied over the file, and the values that didn't parse
+struct Overrides<'a> {
+    env: &'a dyn Fn(&str) -> Option<String>,
+    problems: Vec<String>,
+}
+
+impl Overrides<'_> {
+    // The variable's value, if it's set and not blank
+    fn get(&self, name: &str) -> Option<String> {
+        (self.env)(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn set<T: FromStr>(&mut self, field: &mut T, name: &str, key: &str)
+    where
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = self.get(name) {
+            match value.parse() {
+                Ok(parsed) => *field = parsed,
+                Err(e) => self.problems.push(format!(
+                    "{} ({}): invalid value {:?}: {}",
+                    name, key, value, e
+                )),
+            }
+        }
+    }
+
+    fn set_optional<T: FromStr>(&mut self, field: &mut Option<T>, name: &str, key: &str)
+    where
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = self.get(name) {
+            match value.parse() {
+                Ok(parsed) => *field = Some(parsed),
+                Err(e) => self.problems.push(format!(
+                    "{} ({}): invalid value {:?}: {}",
+                    name, key, value, e
+                )),
+            }
+        }
+    }
+
+    // A comma-separated list
+    fn set_list(&mut self, field: &mut Vec<String>, name: &str) {
+        if let Some(value) = self.get(name) {
+            *field = value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, Row};
+use uuid::Uuid;
+
+use super::encryption::{sealed_tenant, TenantKey, SEALED_OVERHEAD};
+use super::{Client, DbError, DbResult, Group, KeyPackage, Message};
+
+// Version of the format blobs are written in. Each stored blob starts with the version byte of
+// its format, and its row records the version in a `blob_version` column, so rows in an older
+// format can be found and rewritten without reading every blob.
+pub const CURRENT_BLOB_VERSION: u8 = 1;
+
+// Version recorded for rows stored before blobs were versioned. Their blobs are the bare payload,
+// without a version byte.
+pub const LEGACY_BLOB_VERSION: u8 = 0;
+
+// Version of blobs encrypted at rest with their tenant's key: the version byte, then the sealed
+// blob. Rows of tenants' groups are written in it when storage encryption is configured. Rows
+// are never upgraded to it, and it's never upgraded from.
+pub const ENCRYPTED_BLOB_VERSION: u8 = 2;
+
+// Tables holding versioned blobs, with their blob columns
+pub const VERSIONED_BLOBS: &[(&str, &[&str])] = &[
+    ("clients", &["credential"]),
+    ("key_packages", &["data"]),
+    ("groups", &["state"]),
+    (
+        "messages",
+        &["proposal", "commit", "welcome", "system", "application"],
+    ),
+];
+
+// Bytes a stored blob of the given version holds on top of its payload: none for legacy blobs,
+// the version byte for the others, and the sealing overhead as well for encrypted ones
+pub fn blob_overhead(version: u8) -> usize {
+    match version {
+        LEGACY_BLOB_VERSION => 0,
+        ENCRYPTED_BLOB_VERSION => 1 + SEALED_OVERHEAD,
+        _ => 1,
+    }
+}
+
+// Encode a payload in the current format
+pub fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(payload.len() + 1);
+    stored.push(CURRENT_BLOB_VERSION);
+    stored.extend_from_slice(payload);
+    stored
+}
+
+// The payload of a blob stored in the given version's format. Empty blobs are projections that
+// left the payload out, and stay empty. Versions this release doesn't know were written by a
+// newer one, which has to stay deployed until its formats are readable by every instance.
+pub fn decode(version: i16, mut stored: Vec<u8>) -> DbResult<Vec<u8>> {
+    if version == i16::from(LEGACY_BLOB_VERSION) || stored.is_empty() {
+        return Ok(stored);
+    }
+    if stored[0] as i16 != version {
+        return Err(DbError::SerializationError(format!(
+            "Stored blob starts with version {} but its row is at version {}",
+            stored[0], version
+        )));
+    }
+
+    match version {
+        1 => {
+            stored.drain(..1);
+            Ok(stored)
+        }
+        2 => Err(DbError::SerializationError(
+            "Stored blob is encrypted, and can only be read with its tenant's key".to_string(),
+        )),
+        _ => Err(DbError::SerializationError(format!(
+            "Stored blob is in format version {}, newer than this release's {}",
+            version, CURRENT_BLOB_VERSION
+        ))),
+    }
+}
+
+// Tenant keys encrypted blobs are read with. A tenant mapped to None had its key destroyed.
+pub(crate) type TenantKeys = HashMap<Uuid, Option<TenantKey>>;
+
+// The payload of a blob in any version's format, decrypting encrypted ones with their tenant's
+// key
+pub(crate) fn open(version: i16, stored: Vec<u8>, keys: &TenantKeys) -> DbResult<Vec<u8>> {
+    if version != i16::from(ENCRYPTED_BLOB_VERSION) || stored.is_empty() {
+        return decode(version, stored);
+    }
+    if stored[0] != ENCRYPTED_BLOB_VERSION {
+        return Err(DbError::SerializationError(format!(
+            "Stored blob starts with version {} but its row is at version {}",
+            stored[0], version
+        )));
+    }
+
+    let sealed = &stored[1..];
+    let tenant_id = sealed_tenant(sealed)
+        .ok_or_else(|| DbError::SerializationError("Encrypted blob is truncated".to_string()))?;
+    match keys.get(&tenant_id) {
+        Some(Some(key)) => key.open(sealed),
+        Some(None) => Err(DbError::TenantKeyDestroyed(tenant_id)),
+        None => Err(DbError::SerializationError(format!(
+            "No storage key was loaded for tenant {}",
+            tenant_id
+        ))),
+    }
+}
+
+// Format a row's blobs are written in: the current one, or encrypted with its tenant's key
+pub(crate) enum BlobEncoder {
+    Plain,
+    Encrypted(TenantKey),
+}
+
+impl BlobEncoder {
+    // Version to record in the row
+    pub(crate) fn version(&self) -> i16 {
+        match self {
+            Self::Plain => i16::from(CURRENT_BLOB_VERSION),
+            Self::Encrypted(_) => i16::from(ENCRYPTED_BLOB_VERSION),
+        }
+    }
+
+    pub(crate) fn encode(&self, payload: &[u8]) -> DbResult<Vec<u8>> {
+        match self {
+            Self::Plain => Ok(encode(payload)),
+            Self::Encrypted(key) => {
+                let sealed = key.seal(payload)?;
+                let mut stored = Vec::with_capacity(sealed.len() + 1);
+                stored.push(ENCRYPTED_BLOB_VERSION);
+                stored.extend_from_slice(&sealed);
+                Ok(stored)
+            }
+        }
+    }
+}
+
+// Rewrite a blob stored in the given version's format in the current one
+pub fn upgrade(version: i16, stored: Vec<u8>) -> DbResult<Vec<u8>> {
+    Ok(encode(&decode(version, stored)?))
+}
+
+// Rows with versioned blobs
+pub(crate) trait VersionedBlobs {
+    fn blobs_mut(&mut self) -> Vec<&mut Vec<u8>>;
+}
+
+impl VersionedBlobs for Client {
+    fn blobs_mut(&mut self) -> Vec<&mut Vec<u8>> {
+        vec![&mut self.credential]
+    }
+}
+
+impl VersionedBlobs for KeyPackage {
+    fn blobs_mut(&mut self) -> Vec<&mut Vec<u8>> {
+        vec![&mut self.data]
+    }
+}
+
+impl VersionedBlobs for Group {
+    fn blobs_mut(&mut self) -> Vec<&mut Vec<u8>> {
+        self.state.iter_mut().collect()
+    }
+}
+
+impl VersionedBlobs for Message {
+    fn blobs_mut(&mut self) -> Vec<&mut Vec<u8>> {
+        [
+            &mut self.proposal,
+            &mut self.commit,
+            &mut self.welcome,
+            &mut self.system,
+            &mut self.application,
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+// A row read along with its `blob_version`, whose blobs are still encoded
+pub(crate) struct Versioned<T> {
+    row: T,
+    version: i16,
+}
+
+impl<'r, T: FromRow<'r, PgRow>> FromRow<'r, PgRow> for Versioned<T> {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            row: T::from_row(row)?,
+            version: row.try_get("blob_version")?,
+        })
+    }
+}
+
+impl<T: VersionedBlobs> Versioned<T> {
+    // Tenants whose keys the row's encrypted blobs are read with
+    pub(crate) fn tenants(&mut self) -> Vec<Uuid> {
+        if self.version != i16::from(ENCRYPTED_BLOB_VERSION) {
+            return Vec::new();
+        }
+        self.row
+            .blobs_mut()
+            .into_iter()
+            .filter_map(|blob| blob.get(1..).and_then(sealed_tenant))
+            .collect()
+    }
+
+    // The row with its blobs decoded
+    pub(crate) fn decode(mut self, keys: &TenantKeys) -> DbResult<T> {
+        for blob in self.row.blobs_mut() {
+            *blob = open(self.version, std::mem::take(blob), keys)?;
+        }
+        Ok(self.row)
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::blob::{BlobEncoder, TenantKeys, Versioned, VersionedBlobs};
+use super::{DbError, DbResult, PostgresDatabase};
+use crate::timestamps;
+
+// Length of the master key and of the tenant keys derived from it
+pub const STORAGE_KEY_LEN: usize = 32;
+
+// Length of the random salt each tenant's key is derived with
+pub const TENANT_SALT_LEN: usize = 32;
+
+const NONCE_LEN: usize = 12;
+const TENANT_ID_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+
+// Bytes sealing adds to a payload: the tenant id, the nonce and the authentication tag
+pub const SEALED_OVERHEAD: usize = TENANT_ID_LEN + NONCE_LEN + TAG_LEN;
+
+// Bound into every derived key along with the tenant id, so keys derived from the same master
+// key for anything else never match a tenant's
+const TENANT_KEY_INFO: &[u8] = b"hermetic-mls tenant storage key v1";
+
+// How long an instance keeps a tenant's key after reading its salt. A key destroyed through
+// another instance stays usable on this one for at most this long.
+pub const TENANT_KEY_TTL: Duration = Duration::from_secs(60);
+
+// A master key that isn't 32 base64-encoded bytes
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid storage encryption key: {0}")]
+pub struct InvalidStorageKey(String);
+
+// Key the tenant keys are derived from. It never encrypts anything itself.
+#[derive(Clone)]
+pub struct MasterKey([u8; STORAGE_KEY_LEN]);
+
+impl MasterKey {
+    // Parse a base64-encoded 32-byte key
+    pub fn parse(encoded: &str) -> Result<Self, InvalidStorageKey> {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| InvalidStorageKey(e.to_string()))?;
+        let key = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            InvalidStorageKey(format!(
+                "must be {} bytes, not {}",
+                STORAGE_KEY_LEN,
+                bytes.len()
+            ))
+        })?;
+        Ok(Self(key))
+    }
+}
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MasterKey(<redacted>)")
+    }
+}
+
+// Key one tenant's blobs are encrypted under: HKDF-SHA256 of the master key, with the tenant's
+// salt and its id as context. Destroying the salt leaves the key underivable, and with it every
+// blob sealed under it, but only in this database: backups made before still hold the salt.
+#[derive(Clone)]
+pub struct TenantKey {
+    tenant_id: Uuid,
+    cipher: Aes256Gcm,
+}
+
+impl TenantKey {
+    pub fn derive(master: &MasterKey, tenant_id: Uuid, salt: &[u8]) -> Self {
+        let mut key = [0u8; STORAGE_KEY_LEN];
+        Hkdf::<Sha256>::new(Some(salt), &master.0)
+            .expand_multi_info(&[TENANT_KEY_INFO, tenant_id.as_bytes()], &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Self {
+            tenant_id,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    // Encrypt a payload with AES-256-GCM under a fresh nonce. The sealed blob is the tenant id,
+    // the nonce and the ciphertext; the tenant id is also authenticated, so a blob can't be
+    // passed off as another tenant's.
+    pub fn seal(&self, payload: &[u8]) -> DbResult<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| {
+            DbError::SerializationError(format!("Could not generate a blob nonce: {}", e))
+        })?;
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: payload,
+                    aad: self.tenant_id.as_bytes(),
+                },
+            )
+            .map_err(|_| DbError::SerializationError("Could not encrypt blob".to_string()))?;
+
+        let mut sealed = Vec::with_capacity(TENANT_ID_LEN + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(self.tenant_id.as_bytes());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    // Decrypt a blob sealed with this key
+    pub fn open(&self, sealed: &[u8]) -> DbResult<Vec<u8>> {
+        if sealed_tenant(sealed) != Some(self.tenant_id) {
+            return Err(DbError::SerializationError(
+                "Encrypted blob wasn't sealed for this tenant".to_string(),
+            ));
+        }
+        let (nonce, ciphertext) = sealed[TENANT_ID_LEN..]
+            .split_at_checked(NONCE_LEN)
+            .ok_or_else(|| {
+                DbError::SerializationError("Encrypted blob is truncated".to_string())
+            })?;
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: self.tenant_id.as_bytes(),
+                },
+            )
+            .map_err(|_| {
+                DbError::SerializationError(
+                    "Encrypted blob failed authentication with its tenant's key".to_string(),
+                )
+            })
+    }
+}
+
+impl fmt::Debug for TenantKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantKey")
+            .field("tenant_id", &self.tenant_id)
+            .finish_non_exhaustive()
+    }
+}
+
+// Tenant a sealed blob was encrypted for
+pub fn sealed_tenant(sealed: &[u8]) -> Option<Uuid> {
+    Uuid::from_slice(sealed.get(..TENANT_ID_LEN)?).ok()
+}
+
+// A fresh salt for a tenant's key
+pub fn new_tenant_salt() -> DbResult<Vec<u8>> {
+    let mut salt = vec![0u8; TENANT_SALT_LEN];
+    getrandom::getrandom(&mut salt).map_err(|e| {
+        DbError::SerializationError(format!("Could not generate a tenant salt: {}", e))
+    })?;
+    Ok(salt)
+}
+
+// At-rest encryption of the blobs of tenants' groups: their states and message payloads. Each
+// tenant's salt lives in `tenant_keys`; its derived key is cached here for TENANT_KEY_TTL, and
+// a tenant whose key was destroyed is cached as None.
+pub struct StorageEncryption {
+    master: MasterKey,
+    keys: Mutex<HashMap<Uuid, (Option<TenantKey>, Instant)>>,
+}
+
+impl StorageEncryption {
+    pub fn new(master: MasterKey) -> Self {
+        Self {
+            master,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn master(&self) -> &MasterKey {
+        &self.master
+    }
+
+    // A tenant's key if it was read within the TTL: Some(None) once destroyed
+    pub fn cached(&self, tenant_id: Uuid) -> Option<Option<TenantKey>> {
+        let mut keys = self.keys.lock().unwrap();
+        match keys.get(&tenant_id) {
+            Some((key, read_at)) if read_at.elapsed() < TENANT_KEY_TTL => Some(key.clone()),
+            Some(_) => {
+                keys.remove(&tenant_id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn cache(&self, tenant_id: Uuid, key: Option<TenantKey>) {
+        self.keys
+            .lock()
+            .unwrap()
+            .insert(tenant_id, (key, Instant::now()));
+    }
+}
+
+impl fmt::Debug for StorageEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StorageEncryption").finish_non_exhaustive()
+    }
+}
+
+impl PostgresDatabase {
+    // Encrypt the blobs of tenants' groups at rest. Handles on the same database should share
+    // one `StorageEncryption`, so a key destroyed through one is dropped by the others at once.
+    pub fn with_storage_encryption(mut self, encryption: Arc<StorageEncryption>) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    // A tenant's key, unless it was destroyed. With `create`, a tenant that has no salt yet is
+    // given one.
+    async fn tenant_key(
+        &self,
+        encryption: &StorageEncryption,
+        tenant_id: Uuid,
+        create: bool,
+    ) -> DbResult<Option<TenantKey>> {
+        if let Some(key) = encryption.cached(tenant_id) {
+            return Ok(key);
+        }
+
+        if create {
+            sqlx::query(&self.sql(
+                r#"
+                INSERT INTO {tenant_keys} (tenant_id, salt, created_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (tenant_id) DO NOTHING
+                "#,
+            ))
+            .bind(tenant_id)
+            .bind(new_tenant_salt()?)
+            .bind(timestamps::now())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        let salt = sqlx::query_scalar::<_, Option<Vec<u8>>>(
+            &self.sql("SELECT salt FROM {tenant_keys} WHERE tenant_id = $1"),
+        )
+        .bind(tenant_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+        // Tenants never seen aren't cached, so their first write still creates their salt
+        let Some(salt) = salt else {
+            return Ok(None);
+        };
+
+        let key = salt.map(|salt| TenantKey::derive(encryption.master(), tenant_id, &salt));
+        encryption.cache(tenant_id, key.clone());
+        Ok(key)
+    }
+
+    // Format to write the blobs of a tenant's row in: encrypted when storage encryption is on
+    pub(super) async fn blob_encoder(&self, tenant_id: Option<Uuid>) -> DbResult<BlobEncoder> {
+        let (Some(encryption), Some(tenant_id)) = (&self.encryption, tenant_id) else {
+            return Ok(BlobEncoder::Plain);
+        };
+        match self.tenant_key(encryption, tenant_id, true).await? {
+            Some(key) => Ok(BlobEncoder::Encrypted(key)),
+            None => Err(DbError::TenantKeyDestroyed(tenant_id)),
+        }
+    }
+
+    // Tenant of a group, when storage encryption is on and its blobs are the tenant's
+    pub(super) async fn group_tenant(&self, group_id: Option<Uuid>) -> DbResult<Option<Uuid>> {
+        let (Some(_), Some(group_id)) = (&self.encryption, group_id) else {
+            return Ok(None);
+        };
+        let tenant_id = sqlx::query_scalar::<_, Option<Uuid>>(
+            &self.sql("SELECT tenant_id FROM {groups} WHERE id = $1"),
+        )
+        .bind(group_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+        Ok(tenant_id.flatten())
+    }
+
+    // Keys of the tenants encrypted blobs were read for
+    async fn read_keys(&self, tenants: HashSet<Uuid>) -> DbResult<TenantKeys> {
+        let mut keys = TenantKeys::new();
+        if tenants.is_empty() {
+            return Ok(keys);
+        }
+        let Some(encryption) = &self.encryption else {
+            return Err(DbError::SerializationError(
+                "Stored blobs are encrypted, but no storage encryption key is configured"
+                    .to_string(),
+            ));
+        };
+        for tenant_id in tenants {
+            let key = self.tenant_key(encryption, tenant_id, false).await?;
+            keys.insert(tenant_id, key);
+        }
+        Ok(keys)
+    }
+
+    // The row with its blobs decoded, decrypting them if they're encrypted
+    pub(super) async fn decode_row<T: VersionedBlobs>(&self, mut row: Versioned<T>) -> DbResult<T> {
+        let keys = self.read_keys(row.tenants().into_iter().collect()).await?;
+        row.decode(&keys)
+    }
+
+    // Decode each of the rows' blobs. Rows of tenants whose key was destroyed can't be read
+    // anymore and are left out, so an offboarded tenant's rows don't fail a listing or fetch
+    // that also holds other tenants'.
+    pub(super) async fn decode_rows<T: VersionedBlobs>(
+        &self,
+        mut rows: Vec<Versioned<T>>,
+    ) -> DbResult<Vec<T>> {
+        let tenants = rows.iter_mut().flat_map(Versioned::tenants).collect();
+        let keys = self.read_keys(tenants).await?;
+        let mut decoded = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row.decode(&keys) {
+                Ok(row) => decoded.push(row),
+                Err(DbError::TenantKeyDestroyed(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(decoded)
+    }
+
+    // Destroy a tenant's key by dropping its salt. A tenant that never stored anything gets a
+    // destroyed key too, so nothing can be written for it afterwards. The salt lives next to the
+    // blobs, so copies of the database made before, such as backups and the WAL archive, can
+    // still derive the key until they expire.
+    pub(super) async fn destroy_key(
+        &self,
+        encryption: &StorageEncryption,
+        tenant_id: Uuid,
+    ) -> DbResult<()> {
+        let now = timestamps::now();
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {tenant_keys} AS k (tenant_id, salt, created_at, destroyed_at)
+            VALUES ($1, NULL, $2, $2)
+            ON CONFLICT (tenant_id) DO UPDATE
+            SET salt = NULL, destroyed_at = COALESCE(k.destroyed_at, EXCLUDED.destroyed_at)
+            "#,
+        ))
+        .bind(tenant_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        encryption.cache(tenant_id, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgArguments;
+use sqlx::query::QueryAs;
+use sqlx::Postgres;
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{AbuseReport, Client, Group, KeyPackageClaim};
+use crate::timestamps;
+
+// Filter expressions for admin listings: `field op value` terms joined by AND (or just
+// whitespace), e.g. `is_service=true AND created_at>="2024-01-01T00:00:00Z"`. Operators are
+// = != < <= > >=, values are bare words or double-quoted strings, and `null` matches a missing
+// value. Fields come from a per-listing allowlist and values are parsed to the column's type, so
+// a filter only ever turns into allowlisted column names and bound parameters.
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{0}")]
+pub struct FilterError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Uuid,
+    Text,
+    Bool,
+    Int,
+    Timestamp,
+}
+
+// A column a filter can test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterField {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    pub nullable: bool,
+}
+
+const fn field(name: &'static str, kind: FieldKind) -> FilterField {
+    FilterField {
+        name,
+        kind,
+        nullable: false,
+    }
+}
+
+const fn nullable(name: &'static str, kind: FieldKind) -> FilterField {
+    FilterField {
+        name,
+        kind,
+        nullable: true,
+    }
+}
+
+pub const CLIENT_FILTER_FIELDS: &[FilterField] = &[
+    field("id", FieldKind::Uuid),
+    field("user_id", FieldKind::Uuid),
+    field("device_name", FieldKind::Text),
+    field("is_service", FieldKind::Bool),
+    field("created_at", FieldKind::Timestamp),
+    field("last_seen", FieldKind::Timestamp),
+    nullable("deleted_at", FieldKind::Timestamp),
+];
+
+pub const GROUP_FILTER_FIELDS: &[FilterField] = &[
+    field("id", FieldKind::Uuid),
+    field("creator_id", FieldKind::Uuid),
+    field("epoch", FieldKind::Int),
+    field("is_active", FieldKind::Bool),
+    field("created_at", FieldKind::Timestamp),
+    field("updated_at", FieldKind::Timestamp),
+    nullable("deleted_at", FieldKind::Timestamp),
+];
+
+pub const ABUSE_REPORT_FILTER_FIELDS: &[FilterField] = &[
+    field("id", FieldKind::Uuid),
+    field("group_id", FieldKind::Uuid),
+    field("reporter_id", FieldKind::Uuid),
+    field("created_at", FieldKind::Timestamp),
+    nullable("resolved_at", FieldKind::Timestamp),
+];
+
+pub const KEY_PACKAGE_CLAIM_FILTER_FIELDS: &[FilterField] = &[
+    field("key_package_id", FieldKind::Uuid),
+    field("client_id", FieldKind::Uuid),
+    field("group_id", FieldKind::Uuid),
+    field("claimed_by", FieldKind::Uuid),
+    field("published_at", FieldKind::Timestamp),
+    field("claimed_at", FieldKind::Timestamp),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FilterOp {
+    // Longest operators first, so `<=` isn't read as `<`
+    const ALL: &'static [(&'static str, FilterOp)] = &[
+        ("!=", FilterOp::Ne),
+        ("<=", FilterOp::Le),
+        (">=", FilterOp::Ge),
+        ("=", FilterOp::Eq),
+        ("<", FilterOp::Lt),
+        (">", FilterOp::Gt),
+    ];
+
+    fn sql(&self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "<>",
+            FilterOp::Lt => "<",
+            FilterOp::Le => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::Ge => ">=",
+        }
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            FilterOp::Eq => ordering == Ordering::Equal,
+            FilterOp::Ne => ordering != Ordering::Equal,
+            FilterOp::Lt => ordering == Ordering::Less,
+            FilterOp::Le => ordering != Ordering::Greater,
+            FilterOp::Gt => ordering == Ordering::Greater,
+            FilterOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilterValue {
+    Null,
+    Uuid(Uuid),
+    Text(String),
+    Bool(bool),
+    Int(i64),
+    Timestamp(DateTime<Utc>),
+}
+
+impl From<Option<DateTime<Utc>>> for FilterValue {
+    fn from(value: Option<DateTime<Utc>>) -> Self {
+        value.map_or(FilterValue::Null, FilterValue::Timestamp)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterTerm {
+    pub field: &'static str,
+    pub op: FilterOp,
+    pub value: FilterValue,
+}
+
+// Where a page of a listing ends. Listings are ordered newest first by a timestamp, with ties
+// broken by id, so the next page holds the rows strictly before this position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagePosition {
+    pub at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+// Keyset condition restricting a filter to the rows after a previous page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageBoundary {
+    pub at_field: &'static str,
+    pub id_field: &'static str,
+    pub position: PagePosition,
+}
+
+// A parsed filter: the conjunction of its terms, and of the page boundary if any. The empty
+// filter matches every row.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub terms: Vec<FilterTerm>,
+    pub boundary: Option<PageBoundary>,
+}
+
+// Rows admin listings can filter in memory, by the same field names as the SQL columns
+pub trait Filterable {
+    fn filter_value(&self, field: &str) -> FilterValue;
+}
+
+impl Filter {
+    pub fn parse(input: &str, fields: &[FilterField]) -> Result<Filter, FilterError> {
+        let mut terms = Vec::new();
+        let mut rest = input.trim_start();
+        while !rest.is_empty() {
+            let (term, remaining) = parse_term(rest, fields)?;
+            terms.push(term);
+            rest = remaining.trim_start();
+
+            // AND between terms is optional
+            if let Some(after) = strip_keyword(rest, "AND") {
+                rest = after.trim_start();
+                if rest.is_empty() {
+                    return Err(FilterError("expected a term after AND".to_string()));
+                }
+            }
+        }
+        Ok(Filter {
+            terms,
+            boundary: None,
+        })
+    }
+
+    // Add a term the caller checked itself
+    pub fn and(mut self, field: &'static str, op: FilterOp, value: FilterValue) -> Self {
+        self.terms.push(FilterTerm { field, op, value });
+        self
+    }
+
+    // Only match rows ordered after the position, by the listing's timestamp and id columns
+    pub fn after(
+        mut self,
+        at_field: &'static str,
+        id_field: &'static str,
+        position: PagePosition,
+    ) -> Self {
+        self.boundary = Some(PageBoundary {
+            at_field,
+            id_field,
+            position,
+        });
+        self
+    }
+
+    // SQL predicate of the filter, with its values as parameters numbered from `first_param`
+    pub fn to_sql(&self, first_param: usize) -> String {
+        let mut param = first_param;
+        let mut predicates: Vec<String> = self
+            .terms
+            .iter()
+            .map(|term| match (&term.value, term.op) {
+                (FilterValue::Null, FilterOp::Eq) => format!("{} IS NULL", term.field),
+                (FilterValue::Null, _) => format!("{} IS NOT NULL", term.field),
+                _ => {
+                    param += 1;
+                    format!("{} {} ${}", term.field, term.op.sql(), param - 1)
+                }
+            })
+            .collect();
+        if let Some(boundary) = &self.boundary {
+            predicates.push(format!(
+                "({}, {}) < (${}, ${})",
+                boundary.at_field,
+                boundary.id_field,
+                param,
+                param + 1
+            ));
+        }
+        match predicates.is_empty() {
+            true => "TRUE".to_string(),
+            false => predicates.join(" AND "),
+        }
+    }
+
+    // Number of parameters `to_sql` uses
+    pub fn param_count(&self) -> usize {
+        let boundary_params = if self.boundary.is_some() { 2 } else { 0 };
+        self.terms
+            .iter()
+            .filter(|term| term.value != FilterValue::Null)
+            .count()
+            + boundary_params
+    }
+
+    // Bind the filter's values, in the order `to_sql` numbered them
+    pub fn bind<'q, O>(
+        &self,
+        mut query: QueryAs<'q, Postgres, O, PgArguments>,
+    ) -> QueryAs<'q, Postgres, O, PgArguments> {
+        for term in &self.terms {
+            query = match &term.value {
+                FilterValue::Null => query,
+                FilterValue::Uuid(value) => query.bind(*value),
+                FilterValue::Text(value) => query.bind(value.clone()),
+                FilterValue::Bool(value) => query.bind(*value),
+                FilterValue::Int(value) => query.bind(*value),
+                FilterValue::Timestamp(value) => query.bind(*value),
+            };
+        }
+        if let Some(boundary) = &self.boundary {
+            query = query.bind(boundary.position.at).bind(boundary.position.id);
+        }
+        query
+    }
+
+    // Evaluate the filter against a row in memory
+    pub fn matches(&self, row: &impl Filterable) -> bool {
+        self.terms.iter().all(|term| {
+            let actual = row.filter_value(term.field);
+            match (&term.value, &actual) {
+                (FilterValue::Null, _) => {
+                    (actual == FilterValue::Null) == (term.op == FilterOp::Eq)
+                }
+                // Like SQL, comparing a missing value matches nothing
+                (_, FilterValue::Null) => false,
+                (expected, actual) => term.op.holds(actual.cmp(expected)),
+            }
+        }) && self.boundary.is_none_or(|boundary| {
+            let position = (
+                FilterValue::Timestamp(boundary.position.at),
+                FilterValue::Uuid(boundary.position.id),
+            );
+            (
+                row.filter_value(boundary.at_field),
+                row.filter_value(boundary.id_field),
+            ) < position
+        })
+    }
+}
+
+// Strip a case-insensitive keyword followed by whitespace or the end of the input
+fn strip_keyword<'a>(input: &'a str, keyword: &str) -> Option<&'a str> {
+    let head = input.get(..keyword.len())?;
+    let rest = &input[keyword.len()..];
+    (head.eq_ignore_ascii_case(keyword)
+        && (rest.is_empty() || rest.starts_with(char::is_whitespace)))
+    .then_some(rest)
+}
+
+fn parse_term<'a>(
+    input: &'a str,
+    fields: &[FilterField],
+) -> Result<(FilterTerm, &'a str), FilterError> {
+    let name_len = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(input.len());
+    let (name, rest) = input.split_at(name_len);
+    if name.is_empty() {
+        return Err(FilterError(format!("expected a field name at {:?}", input)));
+    }
+    if name.eq_ignore_ascii_case("OR") {
+        return Err(FilterError("only AND is supported".to_string()));
+    }
+    let field = fields.iter().find(|f| f.name == name).ok_or_else(|| {
+        let names: Vec<_> = fields.iter().map(|f| f.name).collect();
+        FilterError(format!(
+            "unknown field {:?}, expected one of: {}",
+            name,
+            names.join(", ")
+        ))
+    })?;
+
+    let rest = rest.trim_start();
+    let (symbol, op) = FilterOp::ALL
+        .iter()
+        .find(|(symbol, _)| rest.starts_with(symbol))
+        .ok_or_else(|| FilterError(format!("expected an operator after {}", name)))?;
+    let rest = rest[symbol.len()..].trim_start();
+
+    let (raw, quoted, rest) = parse_value(rest)?;
+    let value = if !quoted && raw == "null" {
+        if !field.nullable {
+            return Err(FilterError(format!("{} is never null", name)));
+        }
+        if !matches!(op, FilterOp::Eq | FilterOp::Ne) {
+            return Err(FilterError(
+                "null can only be compared with = or !=".to_string(),
+            ));
+        }
+        FilterValue::Null
+    } else {
+        parse_typed(field, *op, &raw)?
+    };
+
+    Ok((
+        FilterTerm {
+            field: field.name,
+            op: *op,
+            value,
+        },
+        rest,
+    ))
+}
+
+// Read a bare word or a double-quoted string (with \" and \\ escapes)
+fn parse_value(input: &str) -> Result<(String, bool, &str), FilterError> {
+    let Some(quoted) = input.strip_prefix('"') else {
+        let len = input.find(char::is_whitespace).unwrap_or(input.len());
+        if len == 0 {
+            return Err(FilterError("expected a value".to_string()));
+        }
+        return Ok((input[..len].to_string(), false, &input[len..]));
+    };
+
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, true, &quoted[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                _ => return Err(FilterError("invalid escape in quoted value".to_string())),
+            },
+            c => value.push(c),
+        }
+    }
+    Err(FilterError("unterminated quoted value".to_string()))
+}
+
+fn parse_typed(field: &FilterField, op: FilterOp, raw: &str) -> Result<FilterValue, FilterError> {
+    let invalid = |expected: &str| FilterError(format!("{} must be {}", field.name, expected));
+    let ordered = !matches!(op, FilterOp::Eq | FilterOp::Ne);
+    match field.kind {
+        FieldKind::Uuid | FieldKind::Bool if ordered => Err(FilterError(format!(
+            "{} can only be compared with = or !=",
+            field.name
+        ))),
+        FieldKind::Uuid => Uuid::parse_str(raw)
+            .map(FilterValue::Uuid)
+            .map_err(|_| invalid("a UUID")),
+        FieldKind::Bool => match raw {
+            "true" => Ok(FilterValue::Bool(true)),
+            "false" => Ok(FilterValue::Bool(false)),
+            _ => Err(invalid("true or false")),
+        },
+        FieldKind::Int => raw
+            .parse()
+            .map(FilterValue::Int)
+            .map_err(|_| invalid("an integer")),
+        FieldKind::Timestamp => timestamps::parse_rfc3339(raw)
+            .map(FilterValue::Timestamp)
+            .map_err(|_| invalid("an RFC 3339 timestamp")),
+        FieldKind::Text => Ok(FilterValue::Text(raw.to_string())),
+    }
+}
+
+impl Filterable for Client {
+    fn filter_value(&self, field: &str) -> FilterValue {
+        match field {
+            "id" => FilterValue::Uuid(self.id),
+            "user_id" => FilterValue::Uuid(self.user_id),
+            "device_name" => FilterValue::Text(self.device_name.clone()),
+            "is_service" => FilterValue::Bool(self.is_service),
+            "created_at" => FilterValue::Timestamp(self.created_at),
+            "last_seen" => FilterValue::Timestamp(self.last_seen),
+            "deleted_at" => self.deleted_at.into(),
+            _ => FilterValue::Null,
+        }
+    }
+}
+
+impl Filterable for Group {
+    fn filter_value(&self, field: &str) -> FilterValue {
+        match field {
+            "id" => FilterValue::Uuid(self.id),
+            "creator_id" => FilterValue::Uuid(self.creator_id),
+            "epoch" => FilterValue::Int(self.epoch),
+            "is_active" => FilterValue::Bool(self.is_active),
+            "created_at" => FilterValue::Timestamp(self.created_at),
+            "updated_at" => FilterValue::Timestamp(self.updated_at),
+            "deleted_at" => self.deleted_at.into(),
+            _ => FilterValue::Null,
+        }
+    }
+}
+
+impl Filterable for KeyPackageClaim {
+    fn filter_value(&self, field: &str) -> FilterValue {
+        match field {
+            "key_package_id" => FilterValue::Uuid(self.key_package_id),
+            "client_id" => FilterValue::Uuid(self.client_id),
+            "group_id" => FilterValue::Uuid(self.group_id),
+            "claimed_by" => FilterValue::Uuid(self.claimed_by),
+            "published_at" => FilterValue::Timestamp(self.published_at),
+            "claimed_at" => FilterValue::Timestamp(self.claimed_at),
+            _ => FilterValue::Null,
+        }
+    }
+}
+
+impl Filterable for AbuseReport {
+    fn filter_value(&self, field: &str) -> FilterValue {
+        match field {
+            "id" => FilterValue::Uuid(self.id),
+            "group_id" => FilterValue::Uuid(self.group_id),
+            "reporter_id" => FilterValue::Uuid(self.reporter_id),
+            "created_at" => FilterValue::Timestamp(self.created_at),
+            "resolved_at" => self.resolved_at.into(),
+            _ => FilterValue::Null,
+        }
+    }
+}
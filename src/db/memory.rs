@@ -0,0 +1,1924 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use super::{
+    check_proposal_pending, in_delivery_order, payload_hash, AbuseReport, AffectedRows, Client,
+    ClientBackup, DatabaseInterface, DbError, DbResult, DeliveryCursor, EntityKind, FeatureFlag,
+    Filter, Group, GroupInfo, GroupStorageStats, IntegrityIssue, IntegrityIssueKind, KeyPackage,
+    KeyPackageClaim, KeyPackageCounts, KeyPackageInventory, Membership, Message, MessageType,
+    OverdueWelcome, QueueTruncation, QueuedProposal, RatchetTree, StaleEpochGroup, StuckMembership,
+    VerboseTrace,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::timestamps;
+
+// Database kept entirely in memory, for `--dev` runs without Postgres and for tests. Nothing
+// survives a restart, and every table sits behind its own lock, so it's not meant for load.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    clients: Mutex<HashMap<Uuid, Client>>,
+    key_packages: Mutex<HashMap<Uuid, KeyPackage>>,
+    groups: Mutex<HashMap<Uuid, Group>>,
+    memberships: Mutex<HashMap<Uuid, Membership>>,
+    messages: Mutex<HashMap<Uuid, Message>>,
+    client_backups: Mutex<HashMap<Uuid, ClientBackup>>,
+    escalated_memberships: Mutex<HashSet<Uuid>>,
+    // When each group that has committed entered its current epoch
+    epoch_started_at: Mutex<HashMap<Uuid, DateTime<Utc>>>,
+    max_epoch_ages: Mutex<HashMap<Uuid, i64>>,
+    // Epoch each group's members were last reminded to rotate out of
+    rotation_reminders: Mutex<HashMap<Uuid, i64>>,
+    evicted_messages: Mutex<HashMap<Uuid, i64>>,
+    welcome_requests: Mutex<HashSet<Uuid>>,
+    // Push reminders sent for each membership's welcome, and when the last one was
+    welcome_reminders: Mutex<HashMap<Uuid, (i64, DateTime<Utc>)>>,
+    escalated_welcomes: Mutex<HashSet<Uuid>>,
+    key_packages_low_notified: Mutex<HashSet<Uuid>>,
+    request_nonces: Mutex<HashMap<(Uuid, String), DateTime<Utc>>>,
+    feature_flags: Mutex<HashMap<String, FeatureFlag>>,
+    verbose_traces: Mutex<HashMap<Uuid, VerboseTrace>>,
+    key_package_claims: Mutex<Vec<KeyPackageClaim>>,
+    proposal_refs: Mutex<HashMap<(Uuid, Vec<u8>), QueuedProposal>>,
+    group_info: Mutex<HashMap<(Uuid, i64), GroupInfo>>,
+    ratchet_trees: Mutex<HashMap<(Uuid, i64), RatchetTree>>,
+    abuse_reports: Mutex<HashMap<Uuid, AbuseReport>>,
+    delivery_cursors: Mutex<HashMap<(Uuid, Option<Uuid>), i64>>,
+    // Last sequence of each group's messages, and of the notices outside any group
+    message_sequences: Mutex<HashMap<Option<Uuid>, i64>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryDatabase {
+    // The message as the next of its group's sequence
+    fn sequenced(&self, message: Message) -> Message {
+        let mut sequences = self.message_sequences.lock().unwrap();
+        let sequence = sequences.entry(message.group_id).or_default();
+        *sequence += 1;
+        Message {
+            sequence: *sequence,
+            ..message
+        }
+    }
+
+    // Ids of soft-deleted entities, optionally only those deleted before a cutoff
+    fn deleted_ids(&self, kind: EntityKind, cutoff: Option<DateTime<Utc>>) -> Vec<Uuid> {
+        let is_due = |deleted_at: Option<DateTime<Utc>>| match (deleted_at, cutoff) {
+            (Some(at), Some(cutoff)) => at < cutoff,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        match kind {
+            EntityKind::Client => self
+                .clients
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|c| is_due(c.deleted_at))
+                .map(|c| c.id)
+                .collect(),
+            EntityKind::Group => self
+                .groups
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|g| is_due(g.deleted_at))
+                .map(|g| g.id)
+                .collect(),
+            EntityKind::Membership => self
+                .memberships
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|m| is_due(m.removed_at))
+                .map(|m| m.id)
+                .collect(),
+            EntityKind::KeyPackage => self
+                .key_packages
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|kp| is_due(kp.deleted_at))
+                .map(|kp| kp.id)
+                .collect(),
+            EntityKind::Message => self
+                .messages
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|m| is_due(m.deleted_at))
+                .map(|m| m.id)
+                .collect(),
+        }
+    }
+
+    // Hard-delete entities along with the rows that depend on them
+    fn purge_ids(&self, kind: EntityKind, ids: &[Uuid]) {
+        match kind {
+            EntityKind::Client => {
+                self.key_packages
+                    .lock()
+                    .unwrap()
+                    .retain(|_, kp| !ids.contains(&kp.client_id));
+                self.memberships
+                    .lock()
+                    .unwrap()
+                    .retain(|_, m| !ids.contains(&m.client_id));
+                self.client_backups
+                    .lock()
+                    .unwrap()
+                    .retain(|client_id, _| !ids.contains(client_id));
+                self.delivery_cursors
+                    .lock()
+                    .unwrap()
+                    .retain(|(client_id, _), _| !ids.contains(client_id));
+                self.clients
+                    .lock()
+                    .unwrap()
+                    .retain(|id, _| !ids.contains(id));
+            }
+            EntityKind::Group => {
+                self.group_info
+                    .lock()
+                    .unwrap()
+                    .retain(|(group_id, _), _| !ids.contains(group_id));
+                self.ratchet_trees
+                    .lock()
+                    .unwrap()
+                    .retain(|(group_id, _), _| !ids.contains(group_id));
+                self.proposal_refs
+                    .lock()
+                    .unwrap()
+                    .retain(|(group_id, _), _| !ids.contains(group_id));
+                self.delivery_cursors
+                    .lock()
+                    .unwrap()
+                    .retain(|(_, group_id), _| !group_id.is_some_and(|g| ids.contains(&g)));
+                self.message_sequences
+                    .lock()
+                    .unwrap()
+                    .retain(|group_id, _| !group_id.is_some_and(|g| ids.contains(&g)));
+                self.messages
+                    .lock()
+                    .unwrap()
+                    .retain(|_, m| !m.group_id.is_some_and(|g| ids.contains(&g)));
+                self.memberships
+                    .lock()
+                    .unwrap()
+                    .retain(|_, m| !ids.contains(&m.group_id));
+                self.groups
+                    .lock()
+                    .unwrap()
+                    .retain(|id, _| !ids.contains(id));
+            }
+            EntityKind::Membership => {
+                self.memberships
+                    .lock()
+                    .unwrap()
+                    .retain(|id, _| !ids.contains(id));
+            }
+            EntityKind::KeyPackage => {
+                self.key_packages
+                    .lock()
+                    .unwrap()
+                    .retain(|id, _| !ids.contains(id));
+            }
+            EntityKind::Message => {
+                self.proposal_refs
+                    .lock()
+                    .unwrap()
+                    .retain(|_, p| !ids.contains(&p.message_id));
+                self.messages
+                    .lock()
+                    .unwrap()
+                    .retain(|id, _| !ids.contains(id));
+            }
+        }
+    }
+
+    // Rows purge_ids would delete for one entity, in the order Postgres deletes them
+    fn purge_counts(&self, kind: EntityKind, id: Uuid) -> Vec<AffectedRows> {
+        let group = Some(id);
+        let counts: Vec<(&'static str, usize)> = match kind {
+            EntityKind::Client => vec![
+                (
+                    "key_packages",
+                    self.key_packages
+                        .lock()
+                        .unwrap()
+                        .values()
+                        .filter(|kp| kp.client_id == id)
+                        .count(),
+                ),
+                (
+                    "memberships",
+                    self.memberships
+                        .lock()
+                        .unwrap()
+                        .values()
+                        .filter(|m| m.client_id == id)
+                        .count(),
+                ),
+                (
+                    "client_backups",
+                    self.client_backups.lock().unwrap().contains_key(&id) as usize,
+                ),
+                (
+                    "delivery_cursors",
+                    self.delivery_cursors
+                        .lock()
+                        .unwrap()
+                        .keys()
+                        .filter(|(client_id, _)| *client_id == id)
+                        .count(),
+                ),
+            ],
+            EntityKind::Group => vec![
+                (
+                    "group_info",
+                    self.group_info
+                        .lock()
+                        .unwrap()
+                        .keys()
+                        .filter(|(group_id, _)| *group_id == id)
+                        .count(),
+                ),
+                (
+                    "ratchet_trees",
+                    self.ratchet_trees
+                        .lock()
+                        .unwrap()
+                        .keys()
+                        .filter(|(group_id, _)| *group_id == id)
+                        .count(),
+                ),
+                (
+                    "proposal_refs",
+                    self.proposal_refs
+                        .lock()
+                        .unwrap()
+                        .keys()
+                        .filter(|(group_id, _)| *group_id == id)
+                        .count(),
+                ),
+                (
+                    "delivery_cursors",
+                    self.delivery_cursors
+                        .lock()
+                        .unwrap()
+                        .keys()
+                        .filter(|(_, group_id)| *group_id == group)
+                        .count(),
+                ),
+                (
+                    "message_sequences",
+                    self.message_sequences.lock().unwrap().contains_key(&group) as usize,
+                ),
+                (
+                    "messages",
+                    self.messages
+                        .lock()
+                        .unwrap()
+                        .values()
+                        .filter(|m| m.group_id == group)
+                        .count(),
+                ),
+                (
+                    "memberships",
+                    self.memberships
+                        .lock()
+                        .unwrap()
+                        .values()
+                        .filter(|m| m.group_id == id)
+                        .count(),
+                ),
+            ],
+            EntityKind::Message => vec![(
+                "proposal_refs",
+                self.proposal_refs
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .filter(|p| p.message_id == id)
+                    .count(),
+            )],
+            EntityKind::Membership | EntityKind::KeyPackage => Vec::new(),
+        };
+        counts
+            .into_iter()
+            .chain([(kind.table(), 1)])
+            .map(|(table, rows)| AffectedRows {
+                table,
+                rows: rows as i64,
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl DatabaseInterface for MemoryDatabase {
+    // Client operations
+    async fn register_client(&self, client: Client) -> DbResult<()> {
+        let mut clients = self.clients.lock().unwrap();
+        clients.insert(client.id, client);
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: Uuid) -> DbResult<Client> {
+        let clients = self.clients.lock().unwrap();
+        clients
+            .get(&client_id)
+            .filter(|c| c.deleted_at.is_none())
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn get_clients(&self, client_ids: &[Uuid]) -> DbResult<Vec<Client>> {
+        let clients = self.clients.lock().unwrap();
+        Ok(client_ids
+            .iter()
+            .filter_map(|id| clients.get(id))
+            .filter(|c| c.deleted_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn list_clients_by_user(
+        &self,
+        user_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<Client>> {
+        let clients = self.clients.lock().unwrap();
+        let filtered_clients: Vec<Client> = clients
+            .values()
+            .filter(|client| client.user_id == user_id && client.deleted_at.is_none())
+            .cloned()
+            .map(|client| match include_payload {
+                true => client,
+                false => Client {
+                    credential: Vec::new(),
+                    init_key: None,
+                    signature_key: None,
+                    ..client
+                },
+            })
+            .collect();
+        Ok(filtered_clients)
+    }
+
+    async fn update_client_last_seen(&self, client_id: Uuid) -> DbResult<()> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get_mut(&client_id) {
+            client.last_seen = timestamps::now();
+            Ok(())
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    async fn list_clients(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Client>> {
+        let clients = self.clients.lock().unwrap();
+        let mut clients: Vec<Client> = clients
+            .values()
+            .filter(|c| filter.matches(*c))
+            .cloned()
+            .collect();
+        clients.sort_by_key(|c| std::cmp::Reverse((c.created_at, c.id)));
+        clients.truncate(limit as usize);
+        Ok(clients)
+    }
+
+    // Request replay protection
+    async fn record_request_nonce(
+        &self,
+        client_id: Uuid,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<bool> {
+        let mut nonces = self.request_nonces.lock().unwrap();
+        match nonces.entry((client_id, nonce.to_string())) {
+            std::collections::hash_map::Entry::Occupied(_) => Ok(false),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(expires_at);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn purge_expired_request_nonces(&self, now: DateTime<Utc>) -> DbResult<u64> {
+        let mut nonces = self.request_nonces.lock().unwrap();
+        let before = nonces.len();
+        nonces.retain(|_, expires_at| *expires_at >= now);
+        Ok((before - nonces.len()) as u64)
+    }
+
+    async fn list_signature_keys(&self) -> DbResult<Vec<(Uuid, Vec<u8>)>> {
+        let clients = self.clients.lock().unwrap();
+        Ok(clients
+            .values()
+            .filter(|client| client.deleted_at.is_none())
+            .filter_map(|client| Some((client.id, client.signature_key.clone()?)))
+            .collect())
+    }
+
+    // Feature flag operations
+    async fn upsert_feature_flag(&self, flag: FeatureFlag) -> DbResult<()> {
+        let mut flags = self.feature_flags.lock().unwrap();
+        flags.insert(flag.name.clone(), flag);
+        Ok(())
+    }
+
+    async fn list_feature_flags(&self) -> DbResult<Vec<FeatureFlag>> {
+        let flags = self.feature_flags.lock().unwrap();
+        let mut flags: Vec<_> = flags.values().cloned().collect();
+        flags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(flags)
+    }
+
+    async fn delete_feature_flag(&self, name: &str) -> DbResult<()> {
+        let mut flags = self.feature_flags.lock().unwrap();
+        flags.remove(name).map(|_| ()).ok_or(DbError::NotFound)
+    }
+
+    // Verbose tracing operations
+    async fn create_verbose_trace(&self, trace: VerboseTrace) -> DbResult<()> {
+        let mut traces = self.verbose_traces.lock().unwrap();
+        traces.insert(trace.id, trace);
+        Ok(())
+    }
+
+    async fn list_verbose_traces(&self, now: DateTime<Utc>) -> DbResult<Vec<VerboseTrace>> {
+        let traces = self.verbose_traces.lock().unwrap();
+        let mut traces: Vec<_> = traces
+            .values()
+            .filter(|trace| trace.expires_at > now)
+            .cloned()
+            .collect();
+        traces.sort_by_key(|trace| (trace.created_at, trace.id));
+        Ok(traces)
+    }
+
+    async fn delete_verbose_trace(&self, trace_id: Uuid) -> DbResult<()> {
+        let mut traces = self.verbose_traces.lock().unwrap();
+        traces
+            .remove(&trace_id)
+            .map(|_| ())
+            .ok_or(DbError::NotFound)
+    }
+
+    // Client backup operations
+    async fn store_client_backup(
+        &self,
+        client_id: Uuid,
+        data: Vec<u8>,
+        expected_version: i64,
+    ) -> DbResult<ClientBackup> {
+        let mut backups = self.client_backups.lock().unwrap();
+        let current_version = backups.get(&client_id).map_or(0, |b| b.version);
+        if current_version != expected_version {
+            return Err(DbError::Conflict(format!(
+                "Backup version {} is not the current version",
+                expected_version
+            )));
+        }
+
+        let now = timestamps::now();
+        let backup = ClientBackup {
+            client_id,
+            version: current_version + 1,
+            data,
+            created_at: backups.get(&client_id).map_or(now, |b| b.created_at),
+            updated_at: now,
+        };
+        backups.insert(client_id, backup.clone());
+        Ok(backup)
+    }
+
+    async fn get_client_backup(&self, client_id: Uuid) -> DbResult<ClientBackup> {
+        let backups = self.client_backups.lock().unwrap();
+        backups.get(&client_id).cloned().ok_or(DbError::NotFound)
+    }
+
+    // KeyPackage operations
+    async fn store_key_package(&self, key_package: KeyPackage) -> DbResult<()> {
+        self.key_packages_low_notified
+            .lock()
+            .unwrap()
+            .remove(&key_package.client_id);
+        let mut key_packages = self.key_packages.lock().unwrap();
+        key_packages.insert(key_package.id, key_package);
+        Ok(())
+    }
+
+    async fn get_key_package(&self, key_package_id: Uuid) -> DbResult<KeyPackage> {
+        let key_packages = self.key_packages.lock().unwrap();
+        key_packages
+            .get(&key_package_id)
+            .filter(|kp| kp.deleted_at.is_none())
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn list_key_packages_by_client(
+        &self,
+        client_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<KeyPackage>> {
+        let key_packages = self.key_packages.lock().unwrap();
+        let filtered_packages: Vec<KeyPackage> = key_packages
+            .values()
+            .filter(|kp| kp.client_id == client_id && kp.deleted_at.is_none())
+            .cloned()
+            .map(|kp| match include_payload {
+                true => kp,
+                false => KeyPackage {
+                    data: Vec::new(),
+                    ..kp
+                },
+            })
+            .collect();
+        Ok(filtered_packages)
+    }
+
+    async fn mark_key_package_used(&self, key_package_id: Uuid) -> DbResult<()> {
+        let mut key_packages = self.key_packages.lock().unwrap();
+        if let Some(key_package) = key_packages.get_mut(&key_package_id) {
+            key_package.used = true;
+            key_package.used_at.get_or_insert_with(timestamps::now);
+            Ok(())
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    async fn claim_key_package(&self, client_id: Uuid, skip: &[Uuid]) -> DbResult<KeyPackage> {
+        let mut key_packages = self.key_packages.lock().unwrap();
+        let key_package = key_packages
+            .values_mut()
+            .filter(|kp| kp.client_id == client_id && !kp.used && kp.deleted_at.is_none())
+            .filter(|kp| !skip.contains(&kp.id))
+            .min_by_key(|kp| kp.created_at)
+            .ok_or(DbError::NotFound)?;
+        key_package.used = true;
+        key_package.used_at = Some(timestamps::now());
+        Ok(key_package.clone())
+    }
+
+    async fn release_key_package(&self, key_package_id: Uuid) -> DbResult<()> {
+        if let Some(key_package) = self.key_packages.lock().unwrap().get_mut(&key_package_id) {
+            key_package.used = false;
+            key_package.used_at = None;
+        }
+        Ok(())
+    }
+
+    async fn record_key_package_claim(&self, claim: KeyPackageClaim) -> DbResult<()> {
+        self.key_package_claims.lock().unwrap().push(claim);
+        Ok(())
+    }
+
+    async fn list_key_package_claims(
+        &self,
+        filter: &Filter,
+        limit: i64,
+    ) -> DbResult<Vec<KeyPackageClaim>> {
+        let claims = self.key_package_claims.lock().unwrap();
+        let mut claims: Vec<_> = claims
+            .iter()
+            .filter(|c| filter.matches(*c))
+            .cloned()
+            .collect();
+        claims.sort_by_key(|c| std::cmp::Reverse((c.claimed_at, c.key_package_id)));
+        claims.truncate(limit as usize);
+        Ok(claims)
+    }
+
+    async fn list_clients_low_on_key_packages(
+        &self,
+        min_unused: i64,
+    ) -> DbResult<Vec<KeyPackageInventory>> {
+        let clients = self.clients.lock().unwrap();
+        let key_packages = self.key_packages.lock().unwrap();
+        let notified = self.key_packages_low_notified.lock().unwrap();
+
+        Ok(clients
+            .values()
+            .filter(|c| c.deleted_at.is_none() && !notified.contains(&c.id))
+            .map(|c| KeyPackageInventory {
+                client_id: c.id,
+                unused: key_packages
+                    .values()
+                    .filter(|kp| kp.client_id == c.id && !kp.used && kp.deleted_at.is_none())
+                    .count() as i64,
+            })
+            .filter(|inventory| inventory.unused < min_unused)
+            .collect())
+    }
+
+    async fn mark_key_packages_low_notified(&self, client_id: Uuid) -> DbResult<()> {
+        self.key_packages_low_notified
+            .lock()
+            .unwrap()
+            .insert(client_id);
+        Ok(())
+    }
+
+    async fn count_unused_key_packages(
+        &self,
+        client_id: Uuid,
+        expired_before: DateTime<Utc>,
+    ) -> DbResult<KeyPackageCounts> {
+        let key_packages = self.key_packages.lock().unwrap();
+        let mut counts = KeyPackageCounts {
+            unused: 0,
+            used: 0,
+            expired: 0,
+        };
+        for kp in key_packages
+            .values()
+            .filter(|kp| kp.client_id == client_id && kp.deleted_at.is_none())
+        {
+            if kp.used {
+                counts.used += 1;
+            } else if kp.created_at < expired_before {
+                counts.expired += 1;
+            } else {
+                counts.unused += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn purge_used_key_packages(&self, before: DateTime<Utc>) -> DbResult<u64> {
+        let mut key_packages = self.key_packages.lock().unwrap();
+        let count = key_packages.len();
+        key_packages.retain(|_, kp| !(kp.used && kp.used_at.is_some_and(|at| at < before)));
+        Ok((count - key_packages.len()) as u64)
+    }
+
+    // Group operations
+    async fn create_group(&self, group: Group) -> DbResult<()> {
+        let mut groups = self.groups.lock().unwrap();
+        if group.mls_group_id.is_some()
+            && groups
+                .values()
+                .any(|g| g.mls_group_id == group.mls_group_id)
+        {
+            return Err(DbError::Conflict(
+                "MLS group id is already mapped to another group".to_string(),
+            ));
+        }
+        if group.handle.is_some()
+            && groups
+                .values()
+                .any(|g| g.tenant_id == group.tenant_id && g.handle == group.handle)
+        {
+            return Err(DbError::Conflict(
+                "Group handle is already taken".to_string(),
+            ));
+        }
+        groups.insert(group.id, group);
+        Ok(())
+    }
+
+    async fn create_group_with_members(
+        &self,
+        group: Group,
+        members: Vec<Membership>,
+    ) -> DbResult<()> {
+        self.create_group(group).await?;
+        let mut memberships = self.memberships.lock().unwrap();
+        for membership in members {
+            memberships.insert(membership.id, membership);
+        }
+        Ok(())
+    }
+
+    async fn get_group(&self, group_id: Uuid) -> DbResult<Group> {
+        let groups = self.groups.lock().unwrap();
+        groups
+            .get(&group_id)
+            .filter(|g| g.deleted_at.is_none())
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn get_group_by_handle(&self, tenant_id: Uuid, handle: &str) -> DbResult<Group> {
+        let groups = self.groups.lock().unwrap();
+        groups
+            .values()
+            .find(|g| {
+                g.tenant_id == Some(tenant_id)
+                    && g.handle.as_deref() == Some(handle)
+                    && g.deleted_at.is_none()
+            })
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn get_group_by_mls_group_id(&self, mls_group_id: &[u8]) -> DbResult<Group> {
+        let groups = self.groups.lock().unwrap();
+        groups
+            .values()
+            .find(|g| g.mls_group_id.as_deref() == Some(mls_group_id) && g.deleted_at.is_none())
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn list_groups_by_client(
+        &self,
+        client_id: Uuid,
+        include_state: bool,
+    ) -> DbResult<Vec<Group>> {
+        let groups = self.groups.lock().unwrap();
+        let memberships = self.memberships.lock().unwrap();
+
+        // Find group IDs where this client is a member
+        let client_group_ids: Vec<Uuid> = memberships
+            .values()
+            .filter(|m| m.client_id == client_id && m.removed_at.is_none())
+            .map(|m| m.group_id)
+            .collect();
+
+        // Get the groups
+        let client_groups: Vec<Group> = groups
+            .values()
+            .filter(|g| client_group_ids.contains(&g.id) && g.is_active && g.deleted_at.is_none())
+            .cloned()
+            .map(|g| Group {
+                state: g.state.filter(|_| include_state),
+                ..g
+            })
+            .collect();
+
+        Ok(client_groups)
+    }
+
+    async fn list_groups(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Group>> {
+        let groups = self.groups.lock().unwrap();
+        let mut groups: Vec<Group> = groups
+            .values()
+            .filter(|g| filter.matches(*g))
+            .cloned()
+            .map(|g| Group { state: None, ..g })
+            .collect();
+        groups.sort_by_key(|g| std::cmp::Reverse((g.created_at, g.id)));
+        groups.truncate(limit as usize);
+        Ok(groups)
+    }
+
+    async fn list_active_groups(&self, since: DateTime<Utc>, limit: i64) -> DbResult<Vec<Group>> {
+        let mut last_message_at: HashMap<Uuid, DateTime<Utc>> = HashMap::new();
+        for message in self.messages.lock().unwrap().values() {
+            if let Some(group_id) = message.group_id.filter(|_| message.created_at >= since) {
+                let last = last_message_at
+                    .entry(group_id)
+                    .or_insert(message.created_at);
+                *last = (*last).max(message.created_at);
+            }
+        }
+
+        let groups = self.groups.lock().unwrap();
+        let mut active: Vec<(DateTime<Utc>, Group)> = last_message_at
+            .into_iter()
+            .filter_map(|(group_id, at)| Some((at, groups.get(&group_id)?.clone())))
+            .filter(|(_, g)| g.deleted_at.is_none())
+            .map(|(at, g)| (at, Group { state: None, ..g }))
+            .collect();
+        active.sort_by_key(|(at, _)| std::cmp::Reverse(*at));
+        active.truncate(limit as usize);
+        Ok(active.into_iter().map(|(_, g)| g).collect())
+    }
+
+    async fn update_group_epoch(&self, group_id: Uuid, epoch: i64) -> DbResult<()> {
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(group) = groups.get_mut(&group_id) {
+            group.epoch = epoch;
+            group.updated_at = timestamps::now();
+            self.epoch_started_at
+                .lock()
+                .unwrap()
+                .insert(group_id, group.updated_at);
+            Ok(())
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    async fn update_group_state(&self, group_id: Uuid, state: Vec<u8>) -> DbResult<()> {
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(group) = groups.get_mut(&group_id) {
+            group.state = Some(state);
+            group.updated_at = timestamps::now();
+            Ok(())
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    async fn set_group_active(&self, group_id: Uuid, active: bool) -> DbResult<bool> {
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups.get_mut(&group_id).ok_or(DbError::NotFound)?;
+        if group.is_active == active {
+            return Ok(false);
+        }
+        group.is_active = active;
+        group.updated_at = timestamps::now();
+        Ok(true)
+    }
+
+    async fn set_group_max_epoch_age(
+        &self,
+        group_id: Uuid,
+        max_epoch_age_secs: Option<i64>,
+    ) -> DbResult<()> {
+        self.get_group(group_id).await?;
+        let mut max_ages = self.max_epoch_ages.lock().unwrap();
+        match max_epoch_age_secs {
+            Some(secs) => max_ages.insert(group_id, secs),
+            None => max_ages.remove(&group_id),
+        };
+        Ok(())
+    }
+
+    async fn get_group_max_epoch_age(&self, group_id: Uuid) -> DbResult<Option<i64>> {
+        self.get_group(group_id).await?;
+        Ok(self.max_epoch_ages.lock().unwrap().get(&group_id).copied())
+    }
+
+    async fn list_stale_epoch_groups(
+        &self,
+        default_max_age_secs: i64,
+        now: DateTime<Utc>,
+    ) -> DbResult<Vec<StaleEpochGroup>> {
+        let groups = self.groups.lock().unwrap();
+        let started = self.epoch_started_at.lock().unwrap();
+        let max_ages = self.max_epoch_ages.lock().unwrap();
+        let reminders = self.rotation_reminders.lock().unwrap();
+
+        let mut stale: Vec<_> = groups
+            .values()
+            .filter(|g| g.deleted_at.is_none() && g.is_active)
+            .filter(|g| reminders.get(&g.id) != Some(&g.epoch))
+            .map(|g| StaleEpochGroup {
+                group_id: g.id,
+                epoch: g.epoch,
+                epoch_started_at: started.get(&g.id).copied().unwrap_or(g.created_at),
+                max_epoch_age_secs: max_ages.get(&g.id).copied().unwrap_or(default_max_age_secs),
+            })
+            .filter(|g| {
+                g.max_epoch_age_secs > 0
+                    && g.epoch_started_at < now - chrono::Duration::seconds(g.max_epoch_age_secs)
+            })
+            .collect();
+        stale.sort_by_key(|g| g.epoch_started_at);
+        Ok(stale)
+    }
+
+    async fn mark_rotation_reminded(&self, group_id: Uuid, epoch: i64) -> DbResult<()> {
+        self.rotation_reminders
+            .lock()
+            .unwrap()
+            .insert(group_id, epoch);
+        Ok(())
+    }
+
+    async fn store_group_info(&self, group_info: GroupInfo) -> DbResult<()> {
+        if !self
+            .groups
+            .lock()
+            .unwrap()
+            .contains_key(&group_info.group_id)
+        {
+            return Err(DbError::MissingReference("Group"));
+        }
+        self.group_info
+            .lock()
+            .unwrap()
+            .insert((group_info.group_id, group_info.epoch), group_info);
+        Ok(())
+    }
+
+    async fn get_group_info(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<GroupInfo> {
+        self.group_info
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|g| g.group_id == group_id && epoch.is_none_or(|epoch| g.epoch == epoch))
+            .max_by_key(|g| g.epoch)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn store_ratchet_tree(&self, tree: RatchetTree) -> DbResult<()> {
+        if !self.groups.lock().unwrap().contains_key(&tree.group_id) {
+            return Err(DbError::MissingReference("Group"));
+        }
+        self.ratchet_trees
+            .lock()
+            .unwrap()
+            .insert((tree.group_id, tree.epoch), tree);
+        Ok(())
+    }
+
+    async fn get_ratchet_tree(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<RatchetTree> {
+        self.ratchet_trees
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|t| t.group_id == group_id && epoch.is_none_or(|epoch| t.epoch == epoch))
+            .max_by_key(|t| t.epoch)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    // Membership operations
+    async fn add_membership(&self, membership: Membership) -> DbResult<()> {
+        let mut memberships = self.memberships.lock().unwrap();
+        memberships.insert(membership.id, membership);
+        Ok(())
+    }
+
+    async fn remove_membership(&self, membership_id: Uuid) -> DbResult<()> {
+        let mut memberships = self.memberships.lock().unwrap();
+        if let Some(membership) = memberships.get_mut(&membership_id) {
+            membership.removed_at = Some(timestamps::now());
+            Ok(())
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    async fn list_memberships_by_group(&self, group_id: Uuid) -> DbResult<Vec<Membership>> {
+        let memberships = self.memberships.lock().unwrap();
+        let filtered_memberships: Vec<Membership> = memberships
+            .values()
+            .filter(|m| m.group_id == group_id && m.removed_at.is_none())
+            .cloned()
+            .collect();
+        Ok(filtered_memberships)
+    }
+
+    async fn list_memberships_by_client(&self, client_id: Uuid) -> DbResult<Vec<Membership>> {
+        let memberships = self.memberships.lock().unwrap();
+        let filtered_memberships: Vec<Membership> = memberships
+            .values()
+            .filter(|m| m.client_id == client_id && m.removed_at.is_none())
+            .cloned()
+            .collect();
+        Ok(filtered_memberships)
+    }
+
+    async fn list_memberships_by_groups(&self, group_ids: &[Uuid]) -> DbResult<Vec<Membership>> {
+        let memberships = self.memberships.lock().unwrap();
+        Ok(memberships
+            .values()
+            .filter(|m| group_ids.contains(&m.group_id) && m.removed_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn get_membership(&self, membership_id: Uuid) -> DbResult<Membership> {
+        let memberships = self.memberships.lock().unwrap();
+        memberships
+            .get(&membership_id)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn list_membership_history(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<Membership>> {
+        let memberships = self.memberships.lock().unwrap();
+        let mut history: Vec<Membership> = memberships
+            .values()
+            .filter(|m| m.client_id == client_id && group_id.is_none_or(|g| m.group_id == g))
+            .cloned()
+            .collect();
+        history.sort_by_key(|m| m.added_at);
+        Ok(history)
+    }
+
+    async fn acknowledge_epoch(&self, group_id: Uuid, client_id: Uuid, epoch: i64) -> DbResult<()> {
+        let mut memberships = self.memberships.lock().unwrap();
+        let mut found = false;
+        for membership in memberships.values_mut().filter(|m| {
+            m.group_id == group_id && m.client_id == client_id && m.removed_at.is_none()
+        }) {
+            membership.last_acked_epoch =
+                Some(membership.last_acked_epoch.unwrap_or_default().max(epoch));
+            membership.last_acked_at = Some(timestamps::now());
+            self.escalated_memberships
+                .lock()
+                .unwrap()
+                .remove(&membership.id);
+            found = true;
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    async fn list_stuck_memberships(
+        &self,
+        max_epochs_behind: i64,
+        idle_since: DateTime<Utc>,
+    ) -> DbResult<Vec<StuckMembership>> {
+        let groups = self.groups.lock().unwrap();
+        let memberships = self.memberships.lock().unwrap();
+        let escalated = self.escalated_memberships.lock().unwrap();
+
+        let mut stuck = Vec::new();
+        for membership in memberships.values() {
+            if membership.removed_at.is_some() || escalated.contains(&membership.id) {
+                continue;
+            }
+            let Some(group) = groups.get(&membership.group_id).filter(|g| g.is_active) else {
+                continue;
+            };
+            let acked = membership.last_acked_epoch.unwrap_or_default();
+            let last_activity = membership.last_acked_at.unwrap_or(membership.added_at);
+            if group.epoch > acked
+                && (group.epoch - acked > max_epochs_behind || last_activity < idle_since)
+            {
+                stuck.push(StuckMembership {
+                    membership: membership.clone(),
+                    group_epoch: group.epoch,
+                });
+            }
+        }
+        Ok(stuck)
+    }
+
+    async fn mark_membership_escalated(&self, membership_id: Uuid) -> DbResult<()> {
+        self.escalated_memberships
+            .lock()
+            .unwrap()
+            .insert(membership_id);
+        Ok(())
+    }
+
+    async fn mark_welcome_requested(&self, membership_id: Uuid) -> DbResult<bool> {
+        Ok(self.welcome_requests.lock().unwrap().insert(membership_id))
+    }
+
+    async fn clear_welcome_requests(&self, group_id: Uuid, client_ids: &[Uuid]) -> DbResult<()> {
+        let memberships = self.memberships.lock().unwrap();
+        let cleared = |id: &Uuid| {
+            memberships
+                .get(id)
+                .is_some_and(|m| m.group_id == group_id && client_ids.contains(&m.client_id))
+        };
+        self.welcome_requests
+            .lock()
+            .unwrap()
+            .retain(|id| !cleared(id));
+        self.welcome_reminders
+            .lock()
+            .unwrap()
+            .retain(|id, _| !cleared(id));
+        self.escalated_welcomes
+            .lock()
+            .unwrap()
+            .retain(|id| !cleared(id));
+        Ok(())
+    }
+
+    async fn list_overdue_welcomes(
+        &self,
+        stored_before: DateTime<Utc>,
+    ) -> DbResult<Vec<OverdueWelcome>> {
+        let groups = self.groups.lock().unwrap();
+        let memberships = self.memberships.lock().unwrap();
+        let messages = self.messages.lock().unwrap();
+        let cursors = self.delivery_cursors.lock().unwrap();
+        let reminders = self.welcome_reminders.lock().unwrap();
+        let escalated = self.escalated_welcomes.lock().unwrap();
+
+        let mut overdue = Vec::new();
+        for membership in memberships.values() {
+            if membership.removed_at.is_some() || escalated.contains(&membership.id) {
+                continue;
+            }
+            if !groups
+                .get(&membership.group_id)
+                .is_some_and(|g| g.deleted_at.is_none())
+            {
+                continue;
+            }
+
+            // A newer welcome replaces an older one, so only the newest counts
+            let newest = messages
+                .values()
+                .filter(|m| {
+                    m.message_type == MessageType::Welcome
+                        && m.deleted_at.is_none()
+                        && m.group_id == Some(membership.group_id)
+                        && m.recipients
+                            .as_ref()
+                            .is_some_and(|r| r.contains(&membership.client_id))
+                })
+                .max_by_key(|m| m.sequence);
+            let Some(welcome) = newest.filter(|m| m.created_at < stored_before) else {
+                continue;
+            };
+            let acked = cursors
+                .get(&(membership.client_id, Some(membership.group_id)))
+                .is_some_and(|&sequence| welcome.sequence <= sequence);
+            if acked {
+                continue;
+            }
+
+            let (count, last) = reminders.get(&membership.id).copied().unzip();
+            overdue.push(OverdueWelcome {
+                membership_id: membership.id,
+                group_id: membership.group_id,
+                client_id: membership.client_id,
+                message_id: welcome.id,
+                stored_at: welcome.created_at,
+                reminders: count.unwrap_or_default(),
+                last_reminded_at: last,
+            });
+        }
+        overdue.sort_by_key(|w| w.stored_at);
+        Ok(overdue)
+    }
+
+    async fn record_welcome_reminder(
+        &self,
+        membership_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> DbResult<()> {
+        let mut reminders = self.welcome_reminders.lock().unwrap();
+        let reminder = reminders.entry(membership_id).or_insert((0, at));
+        *reminder = (reminder.0 + 1, at);
+        Ok(())
+    }
+
+    async fn mark_welcome_escalated(&self, membership_id: Uuid) -> DbResult<()> {
+        self.escalated_welcomes
+            .lock()
+            .unwrap()
+            .insert(membership_id);
+        Ok(())
+    }
+
+    // Deletion lifecycle operations
+    async fn soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        let now = Some(timestamps::now());
+        let found = match kind {
+            EntityKind::Client => self.clients.lock().unwrap().get_mut(&id).map(|c| {
+                c.deleted_at = c.deleted_at.or(now);
+            }),
+            EntityKind::Group => self.groups.lock().unwrap().get_mut(&id).map(|g| {
+                g.deleted_at = g.deleted_at.or(now);
+                g.is_active = false;
+            }),
+            EntityKind::Membership => self.memberships.lock().unwrap().get_mut(&id).map(|m| {
+                m.removed_at = m.removed_at.or(now);
+            }),
+            EntityKind::KeyPackage => self.key_packages.lock().unwrap().get_mut(&id).map(|kp| {
+                kp.deleted_at = kp.deleted_at.or(now);
+            }),
+            EntityKind::Message => self.messages.lock().unwrap().get_mut(&id).map(|m| {
+                m.deleted_at = m.deleted_at.or(now);
+            }),
+        };
+        found.ok_or(DbError::NotFound)
+    }
+
+    async fn restore_deleted(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        let restored = match kind {
+            EntityKind::Client => self
+                .clients
+                .lock()
+                .unwrap()
+                .get_mut(&id)
+                .and_then(|c| c.deleted_at.take().map(|_| ())),
+            EntityKind::Group => self.groups.lock().unwrap().get_mut(&id).and_then(|g| {
+                g.is_active = true;
+                g.deleted_at.take().map(|_| ())
+            }),
+            EntityKind::Membership => self
+                .memberships
+                .lock()
+                .unwrap()
+                .get_mut(&id)
+                .and_then(|m| m.removed_at.take().map(|_| ())),
+            EntityKind::KeyPackage => self
+                .key_packages
+                .lock()
+                .unwrap()
+                .get_mut(&id)
+                .and_then(|kp| kp.deleted_at.take().map(|_| ())),
+            EntityKind::Message => self
+                .messages
+                .lock()
+                .unwrap()
+                .get_mut(&id)
+                .and_then(|m| m.deleted_at.take().map(|_| ())),
+        };
+        restored.ok_or(DbError::NotFound)
+    }
+
+    async fn purge_entity(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        if self.deleted_ids(kind, None).contains(&id) {
+            self.purge_ids(kind, &[id]);
+            Ok(())
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    async fn purge_deleted_before(&self, kind: EntityKind, cutoff: DateTime<Utc>) -> DbResult<u64> {
+        let ids = self.deleted_ids(kind, Some(cutoff));
+        self.purge_ids(kind, &ids);
+        Ok(ids.len() as u64)
+    }
+
+    async fn preview_soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>> {
+        let found = match kind {
+            EntityKind::Client => self.clients.lock().unwrap().contains_key(&id),
+            EntityKind::Group => self.groups.lock().unwrap().contains_key(&id),
+            EntityKind::Membership => self.memberships.lock().unwrap().contains_key(&id),
+            EntityKind::KeyPackage => self.key_packages.lock().unwrap().contains_key(&id),
+            EntityKind::Message => self.messages.lock().unwrap().contains_key(&id),
+        };
+        if !found {
+            return Err(DbError::NotFound);
+        }
+        Ok(vec![AffectedRows {
+            table: kind.table(),
+            rows: 1,
+        }])
+    }
+
+    async fn preview_purge(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>> {
+        if self.deleted_ids(kind, None).contains(&id) {
+            Ok(self.purge_counts(kind, id))
+        } else {
+            Err(DbError::NotFound)
+        }
+    }
+
+    // Message operations
+    async fn store_message(&self, message: Message) -> DbResult<Message> {
+        let message = self.sequenced(message);
+        let mut messages = self.messages.lock().unwrap();
+        messages.insert(message.id, message.clone());
+        Ok(message)
+    }
+
+    async fn store_proposal(&self, message: Message) -> DbResult<Message> {
+        let group_id = message.group_id.ok_or(DbError::MissingReference("Group"))?;
+        let groups = self.groups.lock().unwrap();
+        let group = groups
+            .get(&group_id)
+            .ok_or(DbError::MissingReference("Group"))?;
+        let message = Message {
+            epoch: Some(group.epoch),
+            ..message.with_payload_hash()
+        };
+        let proposal_ref = message.payload_hash.clone().unwrap_or_default();
+
+        let mut proposal_refs = self.proposal_refs.lock().unwrap();
+        if proposal_refs.contains_key(&(group_id, proposal_ref.clone())) {
+            return Err(DbError::Conflict("Proposal is already queued".to_string()));
+        }
+        proposal_refs.insert(
+            (group_id, proposal_ref.clone()),
+            QueuedProposal {
+                proposal_ref,
+                group_id,
+                epoch: group.epoch,
+                message_id: message.id,
+                sender_id: message.sender_id,
+                proposal_type: message.proposal_type,
+                created_at: message.created_at,
+                committed_by: None,
+            },
+        );
+        let message = self.sequenced(message);
+        self.messages
+            .lock()
+            .unwrap()
+            .insert(message.id, message.clone());
+        Ok(message)
+    }
+
+    async fn list_pending_proposals(&self, group_id: Uuid) -> DbResult<Vec<QueuedProposal>> {
+        let Some(epoch) = self.groups.lock().unwrap().get(&group_id).map(|g| g.epoch) else {
+            return Ok(Vec::new());
+        };
+        let messages = self.messages.lock().unwrap();
+        let mut pending: Vec<QueuedProposal> = self
+            .proposal_refs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|p| {
+                p.group_id == group_id
+                    && p.epoch == epoch
+                    && p.committed_by.is_none()
+                    && messages
+                        .get(&p.message_id)
+                        .is_some_and(|m| m.deleted_at.is_none())
+            })
+            .cloned()
+            .collect();
+        pending.sort_by_key(|p| (p.created_at, p.message_id));
+        Ok(pending)
+    }
+
+    async fn store_commit(
+        &self,
+        message: Message,
+        epoch: i64,
+        proposal_refs: &[Vec<u8>],
+    ) -> DbResult<Message> {
+        let group_id = message.group_id.ok_or(DbError::MissingReference("Group"))?;
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups
+            .get_mut(&group_id)
+            .ok_or(DbError::MissingReference("Group"))?;
+        if epoch != group.epoch + 1 {
+            return Err(DbError::StaleEpoch {
+                epoch,
+                current_epoch: group.epoch,
+            });
+        }
+
+        let mut messages = self.messages.lock().unwrap();
+        let mut queued = self.proposal_refs.lock().unwrap();
+        for proposal_ref in proposal_refs {
+            let proposal = queued
+                .get(&(group_id, proposal_ref.clone()))
+                .filter(|p| {
+                    messages
+                        .get(&p.message_id)
+                        .is_some_and(|m| m.deleted_at.is_none())
+                })
+                .map(|p| (p.epoch, p.committed_by));
+            check_proposal_pending(proposal_ref, proposal, group.epoch)?;
+        }
+
+        for proposal_ref in proposal_refs {
+            if let Some(proposal) = queued.get_mut(&(group_id, proposal_ref.clone())) {
+                proposal.committed_by = Some(message.id);
+            }
+        }
+        group.epoch = epoch;
+        group.updated_at = timestamps::now();
+        self.epoch_started_at
+            .lock()
+            .unwrap()
+            .insert(group_id, group.updated_at);
+        let message = self.sequenced(message);
+        messages.insert(message.id, message.clone());
+        Ok(message)
+    }
+
+    async fn fetch_messages_for_client(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
+    ) -> DbResult<Vec<Message>> {
+        // First get all groups this client was ever a member of; like the membership join,
+        // removed members still get the group's messages, such as the commit removing them
+        let memberships = self.memberships.lock().unwrap();
+        let client_group_ids: Vec<Uuid> = memberships
+            .values()
+            .filter(|m| m.client_id == client_id)
+            .map(|m| m.group_id)
+            .collect();
+
+        // Filter messages
+        let cursors = self.delivery_cursors.lock().unwrap();
+        let messages = self.messages.lock().unwrap();
+        let mut filtered_messages: Vec<Message> = Vec::new();
+
+        let now = timestamps::now();
+        for message in messages
+            .values()
+            .filter(|m| m.deleted_at.is_none() && m.expires_at.is_none_or(|at| at > now))
+        {
+            // Apply group filter if provided
+            match (group_id, message.group_id) {
+                (Some(filter_group_id), message_group_id) => {
+                    if message_group_id != Some(filter_group_id)
+                        || !client_group_ids.contains(&filter_group_id)
+                    {
+                        continue;
+                    }
+                }
+                // Skip messages for groups the client is not a member of
+                (None, Some(message_group_id)) => {
+                    if !client_group_ids.contains(&message_group_id) {
+                        continue;
+                    }
+                }
+                // Notices outside any group are covered by the recipient check below
+                (None, None) => {}
+            }
+
+            // Addressed messages (welcomes, system messages) only go to their recipients
+            let addressed = match &message.recipients {
+                Some(recipients) => recipients.contains(&client_id),
+                None => message.message_type != MessageType::System,
+            };
+            if !addressed {
+                continue;
+            }
+
+            // Leave out messages the client acknowledged
+            let acked = cursors
+                .get(&(client_id, message.group_id))
+                .is_some_and(|&sequence| message.sequence <= sequence);
+            if !include_acked && acked {
+                continue;
+            }
+
+            // Apply message type filter if provided
+            if !message_types.is_empty() && !message_types.contains(&message.message_type) {
+                continue;
+            }
+
+            let message = message.clone();
+            filtered_messages.push(match include_payload {
+                true => message,
+                false => Message {
+                    proposal: None,
+                    commit: None,
+                    welcome: None,
+                    system: None,
+                    application: None,
+                    ..message
+                },
+            });
+        }
+
+        Ok(in_delivery_order(filtered_messages))
+    }
+
+    async fn fetch_addressed_messages(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        message_ids: &[Uuid],
+    ) -> DbResult<Vec<Message>> {
+        let memberships = self.memberships.lock().unwrap();
+        let messages = self.messages.lock().unwrap();
+        let now = timestamps::now();
+        Ok(message_ids
+            .iter()
+            .filter_map(|id| messages.get(id))
+            .filter(|m| m.deleted_at.is_none() && m.expires_at.is_none_or(|at| at > now))
+            .filter(|m| group_id.is_none() || m.group_id == group_id)
+            .filter(|m| match (m.group_id, &m.recipients) {
+                (Some(group_id), _) => memberships
+                    .values()
+                    .any(|mem| mem.client_id == client_id && mem.group_id == group_id),
+                (None, Some(recipients)) => recipients.contains(&client_id),
+                (None, None) => false,
+            })
+            .filter(|m| match &m.recipients {
+                Some(recipients) => recipients.contains(&client_id),
+                None => m.message_type != MessageType::System,
+            })
+            .map(|m| Message {
+                proposal: None,
+                commit: None,
+                welcome: None,
+                system: None,
+                application: None,
+                ..m.clone()
+            })
+            .collect())
+    }
+
+    async fn fetch_messages_after(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        after: &[DeliveryCursor],
+        limit: i64,
+    ) -> DbResult<Vec<Message>> {
+        let client_group_ids: HashSet<Uuid> = self
+            .memberships
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|m| m.client_id == client_id)
+            .map(|m| m.group_id)
+            .collect();
+        let cursors = self.delivery_cursors.lock().unwrap();
+        let now = timestamps::now();
+        let mut messages: Vec<Message> = self
+            .messages
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|m| m.deleted_at.is_none() && m.expires_at.is_none_or(|at| at > now))
+            .filter(|m| group_id.is_none_or(|g| m.group_id == Some(g)))
+            .filter(|m| match (&m.recipients, m.group_id) {
+                (_, Some(g)) if !client_group_ids.contains(&g) => false,
+                (Some(recipients), _) => recipients.contains(&client_id),
+                (None, Some(_)) => m.message_type != MessageType::System,
+                (None, None) => false,
+            })
+            .filter(|m| {
+                let position = after
+                    .iter()
+                    .find(|c| c.group_id == m.group_id)
+                    .map(|c| c.sequence)
+                    .or_else(|| cursors.get(&(client_id, m.group_id)).copied())
+                    .unwrap_or(0);
+                m.sequence > position
+            })
+            .cloned()
+            .collect();
+        messages.sort_by_key(|m| (m.created_at, m.id));
+        messages.truncate(limit.max(0) as usize);
+        Ok(in_delivery_order(messages))
+    }
+
+    async fn last_message_sequences(
+        &self,
+        group_ids: &[Option<Uuid>],
+    ) -> DbResult<Vec<DeliveryCursor>> {
+        let sequences = self.message_sequences.lock().unwrap();
+        Ok(group_ids
+            .iter()
+            .map(|&group_id| DeliveryCursor {
+                group_id,
+                sequence: sequences.get(&group_id).copied().unwrap_or(0),
+            })
+            .collect())
+    }
+
+    async fn fetch_messages_for_member(
+        &self,
+        client_id: Uuid,
+        group_id: Uuid,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
+    ) -> DbResult<Vec<Message>> {
+        self.fetch_messages_for_client(
+            client_id,
+            Some(group_id),
+            include_acked,
+            message_types,
+            include_payload,
+        )
+        .await
+    }
+
+    async fn purge_expired_messages(&self, now: DateTime<Utc>) -> DbResult<u64> {
+        let ids: Vec<Uuid> = self
+            .messages
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|m| m.expires_at.is_some_and(|at| at <= now))
+            .map(|m| m.id)
+            .collect();
+        self.purge_ids(EntityKind::Message, &ids);
+        Ok(ids.len() as u64)
+    }
+
+    async fn purge_retained_messages(
+        &self,
+        message_type: MessageType,
+        before: DateTime<Utc>,
+        delivered_only: bool,
+    ) -> DbResult<u64> {
+        let ids: Vec<Uuid> = {
+            let clients = self.clients.lock().unwrap();
+            let memberships = self.memberships.lock().unwrap();
+            let cursors = self.delivery_cursors.lock().unwrap();
+            let messages = self.messages.lock().unwrap();
+            let acked = |message: &Message, client_id: Uuid| {
+                cursors
+                    .get(&(client_id, message.group_id))
+                    .is_some_and(|&sequence| message.sequence <= sequence)
+            };
+            messages
+                .values()
+                .filter(|m| m.message_type == message_type && m.created_at < before)
+                .filter(|m| {
+                    if !delivered_only {
+                        return true;
+                    }
+                    let recipients = m.recipients.clone().unwrap_or_else(|| {
+                        memberships
+                            .values()
+                            .filter(|mem| Some(mem.group_id) == m.group_id)
+                            .filter(|mem| mem.removed_at.is_none())
+                            .map(|mem| mem.client_id)
+                            .collect()
+                    });
+                    recipients
+                        .into_iter()
+                        .filter(|id| clients.get(id).is_some_and(|c| c.deleted_at.is_none()))
+                        .all(|id| acked(m, id))
+                })
+                .map(|m| m.id)
+                .collect()
+        };
+        self.purge_ids(EntityKind::Message, &ids);
+        Ok(ids.len() as u64)
+    }
+
+    async fn evict_application_messages(&self, group_id: Uuid, keep: i64) -> DbResult<u64> {
+        let keep = keep.max(0) as usize;
+        // Each active member's queue: the application messages to it past its delivery cursor,
+        // newest first
+        let queues: Vec<(Uuid, Vec<(i64, Uuid)>)> = {
+            let memberships = self.memberships.lock().unwrap();
+            let messages = self.messages.lock().unwrap();
+            let cursors = self.delivery_cursors.lock().unwrap();
+            memberships
+                .values()
+                .filter(|m| m.group_id == group_id && m.removed_at.is_none())
+                .map(|membership| {
+                    let cursor = cursors
+                        .get(&(membership.client_id, Some(group_id)))
+                        .copied()
+                        .unwrap_or(0);
+                    let mut queued: Vec<(i64, Uuid)> = messages
+                        .values()
+                        .filter(|m| {
+                            m.group_id == Some(group_id)
+                                && m.message_type == MessageType::Application
+                                && m.deleted_at.is_none()
+                                && m.sequence > cursor
+                                && m.recipients
+                                    .as_ref()
+                                    .is_none_or(|r| r.contains(&membership.client_id))
+                        })
+                        .map(|m| (m.sequence, m.id))
+                        .collect();
+                    queued.sort_by(|a, b| b.cmp(a));
+                    (membership.id, queued)
+                })
+                .collect()
+        };
+
+        // A message goes once it's past the cap of every member still waiting for it
+        let mut within_cap = HashSet::new();
+        let mut past_cap = HashSet::new();
+        for (_, queued) in &queues {
+            within_cap.extend(queued.iter().take(keep).map(|(_, id)| *id));
+            past_cap.extend(queued.iter().skip(keep).map(|(_, id)| *id));
+        }
+        let ids: Vec<Uuid> = past_cap.difference(&within_cap).copied().collect();
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        self.purge_ids(EntityKind::Message, &ids);
+
+        let mut evicted = self.evicted_messages.lock().unwrap();
+        for (membership_id, queued) in queues {
+            let lost = queued.iter().filter(|(_, id)| ids.contains(id)).count() as i64;
+            if lost > 0 {
+                *evicted.entry(membership_id).or_default() += lost;
+            }
+        }
+        Ok(ids.len() as u64)
+    }
+
+    async fn take_queue_truncations(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<QueueTruncation>> {
+        let memberships = self.memberships.lock().unwrap();
+        let mut evicted = self.evicted_messages.lock().unwrap();
+        Ok(memberships
+            .values()
+            .filter(|m| m.client_id == client_id && group_id.is_none_or(|g| g == m.group_id))
+            .filter_map(|m| {
+                evicted.remove(&m.id).map(|count| QueueTruncation {
+                    group_id: m.group_id,
+                    evicted_messages: count,
+                })
+            })
+            .collect())
+    }
+
+    async fn mark_messages_read(&self, message_ids: Vec<Uuid>) -> DbResult<()> {
+        let mut messages = self.messages.lock().unwrap();
+        for id in message_ids {
+            if let Some(message) = messages.get_mut(&id) {
+                message.read = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn advance_delivery_cursors(
+        &self,
+        client_id: Uuid,
+        cursors: &[DeliveryCursor],
+    ) -> DbResult<()> {
+        let last_sequences = self.message_sequences.lock().unwrap();
+        let mut stored = self.delivery_cursors.lock().unwrap();
+        for cursor in cursors {
+            let last = last_sequences.get(&cursor.group_id).copied().unwrap_or(0);
+            let sequence = stored.entry((client_id, cursor.group_id)).or_insert(0);
+            *sequence = (*sequence).max(cursor.sequence.min(last));
+        }
+        Ok(())
+    }
+
+    async fn group_storage_stats(
+        &self,
+        group_id: Option<Uuid>,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<GroupStorageStats>> {
+        let groups = self.groups.lock().unwrap();
+        let memberships = self.memberships.lock().unwrap();
+        let messages = self.messages.lock().unwrap();
+
+        let mut stats: Vec<GroupStorageStats> = groups
+            .values()
+            .filter(|g| g.deleted_at.is_none() && group_id.is_none_or(|id| id == g.id))
+            .map(|g| {
+                let active_members = memberships
+                    .values()
+                    .filter(|m| m.group_id == g.id && m.removed_at.is_none())
+                    .count() as i64;
+                let mut stats = GroupStorageStats {
+                    group_id: g.id,
+                    messages: 0,
+                    active_members,
+                    payload_bytes: 0,
+                    delivered_bytes: 0,
+                    payload_bytes_last_day: 0,
+                    payload_bytes_last_week: 0,
+                };
+                for m in messages
+                    .values()
+                    .filter(|m| m.group_id == Some(g.id) && m.deleted_at.is_none())
+                {
+                    let bytes = [
+                        &m.proposal,
+                        &m.commit,
+                        &m.welcome,
+                        &m.system,
+                        &m.application,
+                    ]
+                    .into_iter()
+                    .find_map(|payload| payload.as_ref())
+                    .map_or(0, |payload| payload.len() as i64);
+                    let recipients = m
+                        .recipients
+                        .as_ref()
+                        .map_or(active_members, |r| r.len() as i64);
+                    stats.messages += 1;
+                    stats.payload_bytes += bytes;
+                    stats.delivered_bytes += bytes * recipients;
+                    if m.created_at >= now - chrono::Duration::days(1) {
+                        stats.payload_bytes_last_day += bytes;
+                    }
+                    if m.created_at >= now - chrono::Duration::days(7) {
+                        stats.payload_bytes_last_week += bytes;
+                    }
+                }
+                stats
+            })
+            .collect();
+
+        stats.sort_by(|a, b| {
+            b.payload_bytes
+                .cmp(&a.payload_bytes)
+                .then(a.group_id.cmp(&b.group_id))
+        });
+        stats.truncate(limit.max(0) as usize);
+        Ok(stats)
+    }
+
+    async fn find_integrity_issues(&self, limit: i64) -> DbResult<Vec<IntegrityIssue>> {
+        let clients = self.clients.lock().unwrap();
+        let groups = self.groups.lock().unwrap();
+        let memberships = self.memberships.lock().unwrap();
+        let messages = self.messages.lock().unwrap();
+        let limit = limit.max(0) as usize;
+
+        let mut live_messages: Vec<&Message> = messages
+            .values()
+            .filter(|m| m.deleted_at.is_none())
+            .collect();
+        live_messages.sort_by_key(|m| m.id);
+        let mut active_memberships: Vec<&Membership> = memberships
+            .values()
+            .filter(|m| m.removed_at.is_none())
+            .collect();
+        active_memberships.sort_by_key(|m| m.id);
+
+        let mut issues = Vec::new();
+        issues.extend(
+            live_messages
+                .iter()
+                .filter(|m| {
+                    m.payload_hash
+                        .as_ref()
+                        .is_some_and(|hash| m.payload().map(payload_hash).as_ref() != Some(hash))
+                })
+                .take(limit)
+                .map(|m| IntegrityIssue {
+                    kind: IntegrityIssueKind::PayloadHashMismatch,
+                    id: m.id,
+                    related_id: m.group_id,
+                }),
+        );
+        issues.extend(
+            live_messages
+                .iter()
+                .filter(|m| m.group_id.is_some_and(|id| !groups.contains_key(&id)))
+                .take(limit)
+                .map(|m| IntegrityIssue {
+                    kind: IntegrityIssueKind::OrphanedMessage,
+                    id: m.id,
+                    related_id: m.group_id,
+                }),
+        );
+        issues.extend(
+            active_memberships
+                .iter()
+                .filter(|m| {
+                    clients
+                        .get(&m.client_id)
+                        .is_none_or(|c| c.deleted_at.is_some())
+                })
+                .take(limit)
+                .map(|m| IntegrityIssue {
+                    kind: IntegrityIssueKind::OrphanedMembership,
+                    id: m.id,
+                    related_id: Some(m.client_id),
+                }),
+        );
+        Ok(issues)
+    }
+
+    async fn upgrade_blob_formats(&self, _limit: i64) -> DbResult<u64> {
+        // Payloads are kept decoded, so there's no stored format to upgrade
+        Ok(0)
+    }
+
+    async fn destroy_tenant_key(&self, _tenant_id: Uuid) -> DbResult<bool> {
+        // Nothing is stored at rest, so there's no key to destroy
+        Ok(false)
+    }
+
+    async fn store_abuse_report(&self, report: AbuseReport) -> DbResult<()> {
+        self.abuse_reports.lock().unwrap().insert(report.id, report);
+        Ok(())
+    }
+
+    async fn list_abuse_reports(&self, filter: &Filter, limit: i64) -> DbResult<Vec<AbuseReport>> {
+        let reports = self.abuse_reports.lock().unwrap();
+        let mut reports: Vec<_> = reports
+            .values()
+            .filter(|r| filter.matches(*r))
+            .cloned()
+            .collect();
+        reports.sort_by_key(|r| std::cmp::Reverse((r.created_at, r.id)));
+        reports.truncate(limit as usize);
+        Ok(reports)
+    }
+
+    async fn resolve_abuse_report(
+        &self,
+        report_id: Uuid,
+        resolution: &str,
+        resolved_at: DateTime<Utc>,
+    ) -> DbResult<()> {
+        let mut reports = self.abuse_reports.lock().unwrap();
+        let report = reports.get_mut(&report_id).ok_or(DbError::NotFound)?;
+        if report.resolved_at.is_some() {
+            return Err(DbError::Conflict(
+                "Abuse report is already resolved".to_string(),
+            ));
+        }
+        report.resolved_at = Some(resolved_at);
+        report.resolution = Some(resolution.to_string());
+        Ok(())
+    }
+}
@@ -1,10 +1,36 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool, Row};
 use thiserror::Error;
 use uuid::Uuid;
 
+use crate::redact::redacted_debug;
+use crate::timestamps;
+use blob::{Versioned, CURRENT_BLOB_VERSION, VERSIONED_BLOBS};
+
+pub mod blob;
+mod encryption;
+mod filter;
+#[cfg(feature = "memory-db")]
+pub mod memory;
+mod namespace;
+mod notify;
+mod pools;
+mod priming;
+mod types;
+pub use encryption::*;
+pub use filter::*;
+pub use namespace::*;
+pub use notify::*;
+pub use pools::*;
+pub use priming::*;
+pub use types::*;
+
 // Define error types
 #[derive(Error, Debug)]
 pub enum DbError {
@@ -19,72 +45,579 @@ pub enum DbError {
 
     #[error("Serialization error: {0}")]
     SerializationError(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    // A write referenced a row that doesn't exist, named by its entity
+    #[error("{0} not found")]
+    MissingReference(&'static str),
+
+    // A commit for an epoch that doesn't follow the group's current one
+    #[error("Commit for epoch {epoch} doesn't follow the group's epoch {current_epoch}")]
+    StaleEpoch { epoch: i64, current_epoch: i64 },
+
+    // A commit named a proposal that isn't pending in its group
+    #[error("{0}")]
+    ProposalNotPending(String),
+
+    // A read or write of a tenant's blobs after its storage key was destroyed
+    #[error("Storage key of tenant {0} was destroyed")]
+    TenantKeyDestroyed(Uuid),
 }
 
 // Define a common result type for database operations
 pub type DbResult<T> = Result<T, DbError>;
 
+// Sender id used for messages enqueued by the delivery service itself
+pub const SYSTEM_SENDER_ID: Uuid = Uuid::nil();
+
+// Indexes the queries rely on, as (name, table, leading columns)
+pub const EXPECTED_INDEXES: &[(&str, &str, &[&str])] = &[
+    (
+        "idx_memberships_client_id_removed_at",
+        "memberships",
+        &["client_id", "removed_at"],
+    ),
+    (
+        "idx_messages_group_id_created_at",
+        "messages",
+        &["group_id", "created_at"],
+    ),
+    ("idx_messages_expires_at", "messages", &["expires_at"]),
+    (
+        "idx_messages_group_id_sequence",
+        "messages",
+        &["group_id", "sequence"],
+    ),
+    (
+        "idx_key_packages_client_id_used",
+        "key_packages",
+        &["client_id", "used"],
+    ),
+    (
+        "idx_key_package_claims_client_id",
+        "key_package_claims",
+        &["client_id"],
+    ),
+];
+
+// Foreign keys the schema enforces, as (table, column, referenced table). Each is ON DELETE
+// CASCADE: purges delete dependent rows themselves, and the cascade catches any they miss.
+pub const FOREIGN_KEYS: &[(&str, &str, &str)] = &[
+    ("key_packages", "client_id", "clients"),
+    ("client_backups", "client_id", "clients"),
+    ("memberships", "client_id", "clients"),
+    ("memberships", "group_id", "groups"),
+    ("messages", "group_id", "groups"),
+    ("proposal_refs", "group_id", "groups"),
+    ("group_info", "group_id", "groups"),
+    ("ratchet_trees", "group_id", "groups"),
+    ("proposal_refs", "message_id", "messages"),
+    ("delivery_cursors", "client_id", "clients"),
+];
+
+// Columns of a message row with the content left NULL, for reads that only want metadata
+const MESSAGE_METADATA_COLUMNS: &str = "m.id, m.group_id, m.sender_id, m.created_at, m.read, \
+    m.message_type, NULL::bytea AS proposal, NULL::bytea AS commit, NULL::bytea AS welcome, \
+    NULL::bytea AS system, NULL::bytea AS application, m.proposal_type, m.epoch, m.recipients, \
+    m.extra, m.payload_hash, m.expires_at, m.deleted_at, m.sequence, m.blob_version";
+
+// Map a failed write, turning a foreign key violation into the entity that's missing
+fn write_error(e: sqlx::Error) -> DbError {
+    match e.as_database_error() {
+        Some(db_err) if db_err.is_foreign_key_violation() => {
+            let constraint = db_err.constraint().unwrap_or_default();
+            if constraint.ends_with("_group_id_fkey") {
+                DbError::MissingReference("Group")
+            } else if constraint.ends_with("_client_id_fkey") {
+                DbError::MissingReference("Client")
+            } else {
+                DbError::MissingReference("Referenced row")
+            }
+        }
+        _ => DbError::QueryError(e.to_string()),
+    }
+}
+
+// Map a failed group insert, turning a taken MLS group id or handle into a conflict
+fn group_insert_error(e: sqlx::Error) -> DbError {
+    match e.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            if db_err.constraint().unwrap_or_default().contains("handle") {
+                DbError::Conflict("Group handle is already taken".to_string())
+            } else {
+                DbError::Conflict("MLS group id is already mapped to another group".to_string())
+            }
+        }
+        _ => DbError::QueryError(e.to_string()),
+    }
+}
+
+// Check that a proposal a commit includes is pending: queued under the group's current epoch
+// (given its epoch and the commit that included it, if any) and not committed yet
+pub fn check_proposal_pending(
+    proposal_ref: &[u8],
+    queued: Option<(i64, Option<Uuid>)>,
+    current_epoch: i64,
+) -> DbResult<()> {
+    let name = proposal_ref
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    match queued {
+        None => Err(DbError::ProposalNotPending(format!(
+            "Proposal {} is not queued in the group",
+            name
+        ))),
+        Some((_, Some(_))) => Err(DbError::ProposalNotPending(format!(
+            "Proposal {} was already committed",
+            name
+        ))),
+        Some((epoch, None)) if epoch != current_epoch => Err(DbError::ProposalNotPending(format!(
+            "Proposal {} was sent in epoch {}, the group is at epoch {}",
+            name, epoch, current_epoch
+        ))),
+        Some(_) => Ok(()),
+    }
+}
+
 // Client data structure
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Clone, Serialize, Deserialize, FromRow)]
 pub struct Client {
     pub id: Uuid,
     pub user_id: Uuid,
     pub credential: Vec<u8>,
-    pub scheme: String,
+    pub scheme: CredentialScheme,
     pub device_name: String,
     pub last_seen: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub init_key: Option<Vec<u8>>,
+    pub signature_key: Option<Vec<u8>>, // Ed25519 public key the client signs requests with
+    pub is_service: bool,               // Bot accounts, which can only post application messages
+    pub attestation_verdict: AttestationVerdict, // Device attestation checked at registration
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
+redacted_debug!(Client {
+    id, user_id, scheme, device_name, last_seen, created_at, is_service, attestation_verdict,
+    deleted_at;
+    redacted: credential, init_key, signature_key
+});
+
 // KeyPackage data structure
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Clone, Serialize, Deserialize, FromRow)]
 pub struct KeyPackage {
     pub id: Uuid,
     pub client_id: Uuid,
     pub data: Vec<u8>,
     pub created_at: DateTime<Utc>,
     pub used: bool,
+    // When it was claimed, which the used key package retention counts from
+    pub used_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+redacted_debug!(KeyPackage { id, client_id, created_at, used, used_at, deleted_at; redacted: data });
+
+// Audit record of a claimed key package. It outlives the key package row, so it keeps a
+// hash of the payload rather than the payload itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct KeyPackageClaim {
+    pub key_package_id: Uuid,
+    pub client_id: Uuid,
+    pub hash: Vec<u8>, // SHA-256 of the key package bytes
+    pub published_at: DateTime<Utc>,
+    pub claimed_at: DateTime<Utc>,
+    pub group_id: Uuid,   // Group the key package was claimed for
+    pub claimed_by: Uuid, // Client that claimed it
 }
 
 // Group data structure
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Clone, Serialize, Deserialize, FromRow)]
 pub struct Group {
     pub id: Uuid,
     pub creator_id: Uuid,
     pub epoch: i64, // Changed to i64 for PostgreSQL compatibility
     pub state: Option<Vec<u8>>,
+    // MLS group id the clients use for the group, if they registered one
+    pub mls_group_id: Option<Vec<u8>>,
+    // User the creator belongs to. Handles are unique per tenant.
+    pub tenant_id: Option<Uuid>,
+    // Client-chosen handle that makes creating the group idempotent
+    pub handle: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub is_active: bool,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
+redacted_debug!(Group {
+    id, creator_id, epoch, mls_group_id, tenant_id, handle, created_at, updated_at, is_active,
+    deleted_at;
+    redacted: state
+});
+
 // Membership data structure
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Membership {
     pub id: Uuid,
     pub client_id: Uuid,
     pub group_id: Uuid,
-    pub role: String,
+    pub role: MembershipRole,
     pub added_at: DateTime<Utc>,
     pub removed_at: Option<DateTime<Utc>>,
+    pub last_acked_epoch: Option<i64>,
+    pub last_acked_at: Option<DateTime<Utc>>,
+}
+
+// Client backup data structure. The blob is encrypted client-side and opaque to the service
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct ClientBackup {
+    pub client_id: Uuid,
+    pub version: i64,
+    pub data: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+redacted_debug!(ClientBackup { client_id, version, created_at, updated_at; redacted: data });
+
+// A GroupInfo a member published for one epoch of a group, which clients outside the group need
+// to join it with an external commit
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct GroupInfo {
+    pub group_id: Uuid,
+    pub epoch: i64,
+    // MLS message carrying the GroupInfo
+    pub data: Vec<u8>,
+    pub publisher_id: Uuid,
+    pub published_at: DateTime<Utc>,
+}
+
+redacted_debug!(GroupInfo { group_id, epoch, publisher_id, published_at; redacted: data });
+
+// The ratchet tree of one epoch of a group, for members that joined from a welcome without the
+// ratchet_tree extension
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct RatchetTree {
+    pub group_id: Uuid,
+    pub epoch: i64,
+    // TLS-serialized ratchet tree
+    pub data: Vec<u8>,
+    pub uploader_id: Uuid,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+redacted_debug!(RatchetTree { group_id, epoch, uploader_id, uploaded_at; redacted: data });
+
+// A member's report of abusive messages in a group, kept for moderator review. Reports are kept
+// after the group and messages they reference are purged.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct AbuseReport {
+    pub id: Uuid,
+    pub group_id: Uuid,
+    pub reporter_id: Uuid,
+    pub message_ids: Vec<Uuid>,
+    pub reason: String,
+    // Decrypted excerpt of the messages, encrypted by the reporter to the moderators' key
+    pub excerpt: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolution: Option<String>,
+}
+
+redacted_debug!(AbuseReport {
+    id, group_id, reporter_id, message_ids, reason, created_at, resolved_at, resolution;
+    redacted: excerpt
+});
+
+// Feature flag gating a risky behavior; see `flags::FeatureFlags` for how it is evaluated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,        // Kill switch; a disabled flag is off everywhere
+    pub percentage: i32,      // Share of groups the flag is on for, 0 to 100
+    pub group_ids: Vec<Uuid>, // Groups the flag is always on for
+    pub user_ids: Vec<Uuid>,  // Users (tenants) whose groups the flag is always on for
+    pub updated_at: DateTime<Utc>,
+}
+
+// Verbose tracing turned on for one group or one client until it expires; see
+// `trace::VerboseTracing` for how it is applied
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct VerboseTrace {
+    pub id: Uuid,
+    pub group_id: Option<Uuid>, // Exactly one of group_id and client_id is set
+    pub client_id: Option<Uuid>,
+    pub reason: String, // Why it was turned on, e.g. a support ticket
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
 }
 
 // Message data structure
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Clone, Serialize, Deserialize, FromRow)]
 pub struct Message {
     pub id: Uuid,
-    pub group_id: Uuid,
+    pub group_id: Option<Uuid>, // None for system notices addressed to clients directly
     pub sender_id: Uuid,
     pub created_at: DateTime<Utc>,
     pub read: bool,
-    pub message_type: String,
+    pub message_type: MessageType,
     pub proposal: Option<Vec<u8>>,
     pub commit: Option<Vec<u8>>,
     pub welcome: Option<Vec<u8>>,
-    pub proposal_type: Option<String>,
+    pub system: Option<Vec<u8>>,
+    pub application: Option<Vec<u8>>,
+    pub proposal_type: Option<ProposalType>,
     pub epoch: Option<i64>, // Changed to i64 for PostgreSQL compatibility
     pub recipients: Option<Vec<Uuid>>,
+    pub extra: Option<serde_json::Value>, // Deployment-defined metadata, always a JSON object
+    // SHA-256 of the payload, taken when the message is stored; None for messages stored before
+    // hashes were kept
+    pub payload_hash: Option<Vec<u8>>,
+    // When the message stops being delivered and is purged; None keeps it until deleted
+    pub expires_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    // Position in its group's delivery order, assigned when the message is stored: each message
+    // of a group gets the next sequence, in the order the stores commit. Notices outside any
+    // group share one sequence.
+    pub sequence: i64,
+}
+
+redacted_debug!(Message {
+    id, group_id, sender_id, created_at, read, message_type, proposal_type, epoch, recipients,
+    extra, payload_hash, expires_at, deleted_at, sequence;
+    redacted: proposal, commit, welcome, system, application
+});
+
+impl Message {
+    // The payload of whichever content type the message has
+    pub fn payload(&self) -> Option<&[u8]> {
+        self.proposal
+            .as_deref()
+            .or(self.commit.as_deref())
+            .or(self.welcome.as_deref())
+            .or(self.system.as_deref())
+            .or(self.application.as_deref())
+    }
+
+    // Set the payload hash from the payload, before the message is stored
+    pub fn with_payload_hash(mut self) -> Self {
+        self.payload_hash = self.payload().map(payload_hash);
+        self
+    }
+}
+
+// SHA-256 of a message payload
+pub fn payload_hash(payload: &[u8]) -> Vec<u8> {
+    Sha256::digest(payload).to_vec()
+}
+
+// Messages oldest first, with each group's messages in sequence order. Timestamps come from the
+// storing server's clock, so they can disagree with the order stores committed in; each group's
+// messages keep the places they take in the timeline, sorted by sequence within them.
+pub fn in_delivery_order(mut messages: Vec<Message>) -> Vec<Message> {
+    messages.sort_by_key(|m| (m.created_at, m.id));
+    let mut places: HashMap<Option<Uuid>, Vec<usize>> = HashMap::new();
+    for (i, message) in messages.iter().enumerate() {
+        places.entry(message.group_id).or_default().push(i);
+    }
+
+    let mut ordered: Vec<Option<Message>> = messages.into_iter().map(Some).collect();
+    for places in places.values() {
+        let mut group: Vec<Message> = places.iter().filter_map(|&i| ordered[i].take()).collect();
+        group.sort_by_key(|m| m.sequence);
+        for (&i, message) in places.iter().zip(group) {
+            ordered[i] = Some(message);
+        }
+    }
+    ordered.into_iter().flatten().collect()
+}
+
+// Entities that follow the soft-delete and purge lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntityKind {
+    Client,
+    Group,
+    Membership,
+    KeyPackage,
+    Message,
+}
+
+impl EntityKind {
+    // Purge order that removes dependent rows before the rows they reference
+    pub const PURGE_ORDER: [EntityKind; 5] = [
+        EntityKind::Message,
+        EntityKind::KeyPackage,
+        EntityKind::Membership,
+        EntityKind::Group,
+        EntityKind::Client,
+    ];
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            EntityKind::Client => "clients",
+            EntityKind::Group => "groups",
+            EntityKind::Membership => "memberships",
+            EntityKind::KeyPackage => "key_packages",
+            EntityKind::Message => "messages",
+        }
+    }
+
+    // Column holding the soft-delete timestamp (memberships reuse removed_at)
+    pub fn deleted_column(&self) -> &'static str {
+        match self {
+            EntityKind::Membership => "removed_at",
+            _ => "deleted_at",
+        }
+    }
+
+    // Tables and columns holding rows that must be purged along with this entity
+    fn dependents(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            EntityKind::Client => &[
+                ("key_packages", "client_id"),
+                ("memberships", "client_id"),
+                ("client_backups", "client_id"),
+                ("delivery_cursors", "client_id"),
+            ],
+            EntityKind::Group => &[
+                ("group_info", "group_id"),
+                ("ratchet_trees", "group_id"),
+                ("proposal_refs", "group_id"),
+                ("delivery_cursors", "group_id"),
+                ("message_sequences", "group_id"),
+                ("messages", "group_id"),
+                ("memberships", "group_id"),
+            ],
+            EntityKind::Message => &[("proposal_refs", "message_id")],
+            _ => &[],
+        }
+    }
+}
+
+// Rows of one table an operation would change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AffectedRows {
+    pub table: &'static str,
+    pub rows: i64,
+}
+
+// A client's count of unused key packages
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct KeyPackageInventory {
+    pub client_id: Uuid,
+    pub unused: i64,
+}
+
+// Application messages of a group evicted from a member's queue since it was last told
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct QueueTruncation {
+    pub group_id: Uuid,
+    pub evicted_messages: i64,
+}
+
+// How far a client has acknowledged the messages of a group, or its notices outside any group
+// when group_id is None. Fetches leave out the messages up to and including the sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryCursor {
+    pub group_id: Option<Uuid>,
+    pub sequence: i64,
+}
+
+// A client's key packages by state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct KeyPackageCounts {
+    // Unused and created at or after the expiry cutoff
+    pub unused: i64,
+    pub used: i64,
+    // Unused but created before the expiry cutoff
+    pub expired: i64,
+}
+
+// A proposal queued for the group epoch it was stored in. Commits include it by its ref, the
+// SHA-256 of the proposal message (also its payload hash).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct QueuedProposal {
+    pub proposal_ref: Vec<u8>,
+    pub group_id: Uuid,
+    pub epoch: i64,
+    pub message_id: Uuid,
+    pub sender_id: Uuid,
+    pub proposal_type: Option<ProposalType>,
+    pub created_at: DateTime<Utc>,
+    // Commit message that included the proposal, once one has
+    pub committed_by: Option<Uuid>,
+}
+
+// An active membership that has fallen behind its group's epoch
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StuckMembership {
+    #[sqlx(flatten)]
+    pub membership: Membership,
+    pub group_epoch: i64,
+}
+
+// A group whose current epoch has lasted longer than its key rotation policy allows
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StaleEpochGroup {
+    pub group_id: Uuid,
+    pub epoch: i64,
+    // When the group entered its current epoch, or was created if it never committed
+    pub epoch_started_at: DateTime<Utc>,
+    // The group's own limit, or else the default one
+    pub max_epoch_age_secs: i64,
+}
+
+// The newest welcome addressed to an active member that the member hasn't acknowledged
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OverdueWelcome {
+    pub membership_id: Uuid,
+    pub group_id: Uuid,
+    pub client_id: Uuid,
+    pub message_id: Uuid,
+    pub stored_at: DateTime<Utc>,
+    // Push reminders sent for it so far, and when the last one was
+    pub reminders: i64,
+    pub last_reminded_at: Option<DateTime<Utc>>,
+}
+
+// Storage taken up by a group's messages, for capacity planning
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct GroupStorageStats {
+    pub group_id: Uuid,
+    pub messages: i64,
+    pub active_members: i64,
+    // Bytes of MLS payloads stored, without what the blob format adds to them
+    pub payload_bytes: i64,
+    // Bytes served if every recipient fetches every message once: messages with recipients
+    // (welcomes, admin notices) go to those, everything else to each active member
+    pub delivered_bytes: i64,
+    pub payload_bytes_last_day: i64,
+    pub payload_bytes_last_week: i64,
+}
+
+// Kinds of inconsistency the integrity scan looks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntegrityIssueKind {
+    // A live message whose payload no longer matches the hash taken when it was stored
+    PayloadHashMismatch,
+    // A message whose group row is gone
+    OrphanedMessage,
+    // An active membership whose client is gone or soft-deleted
+    OrphanedMembership,
+}
+
+// An inconsistency found by the integrity scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityIssue {
+    pub kind: IntegrityIssueKind,
+    // The message or membership affected
+    pub id: Uuid,
+    // The group of the message (None for notices addressed to clients directly), or the client
+    // of the membership
+    pub related_id: Option<Uuid>,
 }
 
 // Define the database interface trait
@@ -93,101 +626,448 @@ pub trait DatabaseInterface: Send + Sync {
     // Client operations
     async fn register_client(&self, client: Client) -> DbResult<()>;
     async fn get_client(&self, client_id: Uuid) -> DbResult<Client>;
-    async fn list_clients_by_user(&self, user_id: Uuid) -> DbResult<Vec<Client>>;
+    // The clients among `client_ids` that exist and aren't deleted, in no particular order
+    async fn get_clients(&self, client_ids: &[Uuid]) -> DbResult<Vec<Client>>;
+    // Without `include_payload` the credential and key columns are left empty
+    async fn list_clients_by_user(
+        &self,
+        user_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<Client>>;
     async fn update_client_last_seen(&self, client_id: Uuid) -> DbResult<()>;
+    // Clients matching an admin filter, soft-deleted ones included, newest first
+    async fn list_clients(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Client>>;
+
+    // Client backup operations
+    // Stores a new backup version; fails with Conflict unless `expected_version` is the current one
+    async fn store_client_backup(
+        &self,
+        client_id: Uuid,
+        data: Vec<u8>,
+        expected_version: i64,
+    ) -> DbResult<ClientBackup>;
+    async fn get_client_backup(&self, client_id: Uuid) -> DbResult<ClientBackup>;
+
+    // Request replay protection
+    // Returns false if the client already used the nonce
+    async fn record_request_nonce(
+        &self,
+        client_id: Uuid,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<bool>;
+    async fn purge_expired_request_nonces(&self, now: DateTime<Utc>) -> DbResult<u64>;
+    // Signature keys of the active clients that registered one
+    async fn list_signature_keys(&self) -> DbResult<Vec<(Uuid, Vec<u8>)>>;
+
+    // Feature flag operations
+    async fn upsert_feature_flag(&self, flag: FeatureFlag) -> DbResult<()>;
+    async fn list_feature_flags(&self) -> DbResult<Vec<FeatureFlag>>;
+    async fn delete_feature_flag(&self, name: &str) -> DbResult<()>;
+
+    // Verbose tracing operations
+    async fn create_verbose_trace(&self, trace: VerboseTrace) -> DbResult<()>;
+    // Traces that haven't expired by `now`, oldest first
+    async fn list_verbose_traces(&self, now: DateTime<Utc>) -> DbResult<Vec<VerboseTrace>>;
+    async fn delete_verbose_trace(&self, trace_id: Uuid) -> DbResult<()>;
 
     // KeyPackage operations
     async fn store_key_package(&self, key_package: KeyPackage) -> DbResult<()>;
     async fn get_key_package(&self, key_package_id: Uuid) -> DbResult<KeyPackage>;
-    async fn list_key_packages_by_client(&self, client_id: Uuid) -> DbResult<Vec<KeyPackage>>;
+    // Without `include_payload` the key package data is left empty
+    async fn list_key_packages_by_client(
+        &self,
+        client_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<KeyPackage>>;
     async fn mark_key_package_used(&self, key_package_id: Uuid) -> DbResult<()>;
+    // Marks the client's oldest unused key package, other than those in `skip`, used and returns
+    // it; NotFound if it has none
+    async fn claim_key_package(&self, client_id: Uuid, skip: &[Uuid]) -> DbResult<KeyPackage>;
+    // Marks a claimed key package unused again, undoing a claim that was refused
+    async fn release_key_package(&self, key_package_id: Uuid) -> DbResult<()>;
+    async fn record_key_package_claim(&self, claim: KeyPackageClaim) -> DbResult<()>;
+    // Claims matching an admin filter, most recent first
+    async fn list_key_package_claims(
+        &self,
+        filter: &Filter,
+        limit: i64,
+    ) -> DbResult<Vec<KeyPackageClaim>>;
+    async fn list_clients_low_on_key_packages(
+        &self,
+        min_unused: i64,
+    ) -> DbResult<Vec<KeyPackageInventory>>;
+    async fn mark_key_packages_low_notified(&self, client_id: Uuid) -> DbResult<()>;
+    // Count a client's key packages. Unused ones created before `expired_before` count as expired.
+    async fn count_unused_key_packages(
+        &self,
+        client_id: Uuid,
+        expired_before: DateTime<Utc>,
+    ) -> DbResult<KeyPackageCounts>;
+    // Hard-delete key packages used before `before`. Returns the count.
+    async fn purge_used_key_packages(&self, before: DateTime<Utc>) -> DbResult<u64>;
 
     // Group operations
     async fn create_group(&self, group: Group) -> DbResult<()>;
+    // Creates the group and its initial memberships atomically: all of them or none
+    async fn create_group_with_members(
+        &self,
+        group: Group,
+        members: Vec<Membership>,
+    ) -> DbResult<()>;
     async fn get_group(&self, group_id: Uuid) -> DbResult<Group>;
-    async fn list_groups_by_client(&self, client_id: Uuid) -> DbResult<Vec<Group>>;
+    // Look up a group by the handle its creator chose
+    async fn get_group_by_handle(&self, tenant_id: Uuid, handle: &str) -> DbResult<Group>;
+    // Look up a group by the MLS group id its clients registered
+    async fn get_group_by_mls_group_id(&self, mls_group_id: &[u8]) -> DbResult<Group>;
+    // Groups the client is an active member of; `state` is only loaded if `include_state`
+    async fn list_groups_by_client(
+        &self,
+        client_id: Uuid,
+        include_state: bool,
+    ) -> DbResult<Vec<Group>>;
+    // Groups matching an admin filter, soft-deleted ones included, newest first and without
+    // their state
+    async fn list_groups(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Group>>;
+    // Live groups with messages stored since a time, the most recently active first and without
+    // their state
+    async fn list_active_groups(&self, since: DateTime<Utc>, limit: i64) -> DbResult<Vec<Group>>;
     async fn update_group_epoch(&self, group_id: Uuid, epoch: i64) -> DbResult<()>;
     async fn update_group_state(&self, group_id: Uuid, state: Vec<u8>) -> DbResult<()>;
+    // Freeze or unfreeze a group. Returns false if it already was in that state.
+    async fn set_group_active(&self, group_id: Uuid, active: bool) -> DbResult<bool>;
+    // Set how long the group's epochs may last before its members are reminded to rotate their
+    // keys: None follows the default, and 0 turns the reminders off for the group
+    async fn set_group_max_epoch_age(
+        &self,
+        group_id: Uuid,
+        max_epoch_age_secs: Option<i64>,
+    ) -> DbResult<()>;
+    // The group's own epoch age limit, None if it follows the default
+    async fn get_group_max_epoch_age(&self, group_id: Uuid) -> DbResult<Option<i64>>;
+    // Live groups whose current epoch is older than their limit, or than `default_max_age_secs`
+    // for those without one (0 for no default), and whose members haven't been reminded of it
+    async fn list_stale_epoch_groups(
+        &self,
+        default_max_age_secs: i64,
+        now: DateTime<Utc>,
+    ) -> DbResult<Vec<StaleEpochGroup>>;
+    // Record that the group's members were reminded to rotate out of `epoch`
+    async fn mark_rotation_reminded(&self, group_id: Uuid, epoch: i64) -> DbResult<()>;
+    // Store the GroupInfo of an epoch, replacing one already published for it
+    async fn store_group_info(&self, group_info: GroupInfo) -> DbResult<()>;
+    // The GroupInfo published for an epoch, or for the newest epoch one was published for
+    async fn get_group_info(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<GroupInfo>;
+    // Store the ratchet tree of an epoch, replacing one already uploaded for it
+    async fn store_ratchet_tree(&self, tree: RatchetTree) -> DbResult<()>;
+    // The ratchet tree uploaded for an epoch, or for the newest epoch one was uploaded for
+    async fn get_ratchet_tree(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<RatchetTree>;
 
     // Membership operations
     async fn add_membership(&self, membership: Membership) -> DbResult<()>;
     async fn remove_membership(&self, membership_id: Uuid) -> DbResult<()>;
+    // Active memberships of the group
     async fn list_memberships_by_group(&self, group_id: Uuid) -> DbResult<Vec<Membership>>;
+    // Active memberships of the client
     async fn list_memberships_by_client(&self, client_id: Uuid) -> DbResult<Vec<Membership>>;
+    // Active memberships of any of the groups
+    async fn list_memberships_by_groups(&self, group_ids: &[Uuid]) -> DbResult<Vec<Membership>>;
+    async fn get_membership(&self, membership_id: Uuid) -> DbResult<Membership>;
+    async fn list_membership_history(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<Membership>>;
+    async fn acknowledge_epoch(&self, group_id: Uuid, client_id: Uuid, epoch: i64) -> DbResult<()>;
+    async fn list_stuck_memberships(
+        &self,
+        max_epochs_behind: i64,
+        idle_since: DateTime<Utc>,
+    ) -> DbResult<Vec<StuckMembership>>;
+    async fn mark_membership_escalated(&self, membership_id: Uuid) -> DbResult<()>;
+    // Returns false if a welcome resend was already pending for the membership
+    async fn mark_welcome_requested(&self, membership_id: Uuid) -> DbResult<bool>;
+    // Clear the welcome resends pending for the clients' memberships of the group, and restart
+    // their welcome delivery tracking
+    async fn clear_welcome_requests(&self, group_id: Uuid, client_ids: &[Uuid]) -> DbResult<()>;
+    // Unacknowledged welcomes stored before `stored_before`, the newest per membership, except
+    // those already escalated
+    async fn list_overdue_welcomes(
+        &self,
+        stored_before: DateTime<Utc>,
+    ) -> DbResult<Vec<OverdueWelcome>>;
+    // Count a push reminder sent for the membership's welcome
+    async fn record_welcome_reminder(&self, membership_id: Uuid, at: DateTime<Utc>)
+        -> DbResult<()>;
+    // Record that the membership's welcome was reported stranded, so it isn't again until a new
+    // welcome is stored for it
+    async fn mark_welcome_escalated(&self, membership_id: Uuid) -> DbResult<()>;
+
+    // Deletion lifecycle operations
+    async fn soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<()>;
+    async fn restore_deleted(&self, kind: EntityKind, id: Uuid) -> DbResult<()>;
+    async fn purge_entity(&self, kind: EntityKind, id: Uuid) -> DbResult<()>;
+    async fn purge_deleted_before(&self, kind: EntityKind, cutoff: DateTime<Utc>) -> DbResult<u64>;
+    // Rows soft_delete and purge_entity would change, without changing them. NotFound where
+    // those would fail with it.
+    async fn preview_soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>>;
+    async fn preview_purge(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>>;
 
     // Message operations
-    async fn store_message(&self, message: Message) -> DbResult<()>;
+    // Store a message as the next of its group's sequence. Returns the stored message, with its
+    // sequence.
+    async fn store_message(&self, message: Message) -> DbResult<Message>;
+    // Store a proposal and queue it under its group's current epoch, which becomes the
+    // message's epoch. Returns the stored message, with its payload hash (the proposal ref) and
+    // sequence.
+    async fn store_proposal(&self, message: Message) -> DbResult<Message>;
+    // Proposals queued under the group's current epoch that no commit has included yet,
+    // oldest first
+    async fn list_pending_proposals(&self, group_id: Uuid) -> DbResult<Vec<QueuedProposal>>;
+    // Store a commit and advance its group to `epoch` in one transaction. Fails with
+    // StaleEpoch unless the group is at the epoch before, so concurrent commits for the same
+    // epoch can't both land, and with ProposalNotPending unless every proposal ref names a
+    // pending proposal of the group. Those proposals are marked committed by the message.
+    // Returns the stored message, with its sequence.
+    async fn store_commit(
+        &self,
+        message: Message,
+        epoch: i64,
+        proposal_refs: &[Vec<u8>],
+    ) -> DbResult<Message>;
+    // A group's messages go to the clients with a membership in it, removed ones included so
+    // they get the commit removing them. Messages with recipients (welcomes, system messages)
+    // only go to those clients, oldest first with each group's messages in sequence order. Without `include_acked` only the messages after the client's delivery cursors are
+    // returned, and without `include_payload` the content columns are left empty.
     async fn fetch_messages_for_client(
         &self,
         client_id: Uuid,
         group_id: Option<Uuid>,
-        include_read: bool,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
+    ) -> DbResult<Vec<Message>>;
+    // The messages among `message_ids` that `fetch_messages_for_client` returns the client, with
+    // the group if given, when including acknowledged ones, without their content; the others
+    // are left out
+    async fn fetch_addressed_messages(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        message_ids: &[Uuid],
+    ) -> DbResult<Vec<Message>>;
+    // The client's messages, of one group or all, stored after the positions: past a group's
+    // sequence in `after` (the notices' under None), or past the client's delivery cursor for
+    // groups it leaves out. At most `limit`, in delivery order.
+    async fn fetch_messages_after(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        after: &[DeliveryCursor],
+        limit: i64,
+    ) -> DbResult<Vec<Message>>;
+    // The last sequence stored in each of the groups, or of the notices for None; 0 for those
+    // without messages
+    async fn last_message_sequences(
+        &self,
+        group_ids: &[Option<Uuid>],
+    ) -> DbResult<Vec<DeliveryCursor>>;
+    // A group's messages for a client the caller knew to be an active member of it, like
+    // `fetch_messages_for_client` with the group but without checking the membership again
+    async fn fetch_messages_for_member(
+        &self,
+        client_id: Uuid,
+        group_id: Uuid,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
     ) -> DbResult<Vec<Message>>;
+    // Hard-delete messages that expired at or before `now`, with the rows depending on them
+    async fn purge_expired_messages(&self, now: DateTime<Utc>) -> DbResult<u64>;
+    // Hard-delete messages of the type stored before `before`, with the rows depending on them.
+    // With `delivered_only`, only those every recipient has acknowledged: each listed recipient,
+    // or each active member of the group, whose client still exists. Returns the count.
+    async fn purge_retained_messages(
+        &self,
+        message_type: MessageType,
+        before: DateTime<Utc>,
+        delivered_only: bool,
+    ) -> DbResult<u64>;
+    // Cap each active member's queue of the group at its newest `keep` application messages past
+    // its delivery cursor, by sequence. Messages past the cap of every member still waiting for
+    // them are hard-deleted and added to those members' truncations. Returns the count.
+    async fn evict_application_messages(&self, group_id: Uuid, keep: i64) -> DbResult<u64>;
+    // The client's truncations, of one group or all, resetting them
+    async fn take_queue_truncations(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<QueueTruncation>>;
     async fn mark_messages_read(&self, message_ids: Vec<Uuid>) -> DbResult<()>;
+    // Move the client's delivery cursors forward; a cursor already past the new sequence stays,
+    // and none moves past its group's last stored message
+    async fn advance_delivery_cursors(
+        &self,
+        client_id: Uuid,
+        cursors: &[DeliveryCursor],
+    ) -> DbResult<()>;
+    // Storage stats of one group, or of every group, largest first
+    async fn group_storage_stats(
+        &self,
+        group_id: Option<Uuid>,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<GroupStorageStats>>;
+
+    // Integrity checks
+    // Up to `limit` issues of each kind. The foreign keys are added NOT VALID, so rows written
+    // before they existed (or while they were missing) aren't guaranteed to be consistent.
+    async fn find_integrity_issues(&self, limit: i64) -> DbResult<Vec<IntegrityIssue>>;
+
+    // Stored blob formats
+    // Rewrite up to `limit` rows of each table with blobs in an older format in the current one.
+    // Returns how many rows were rewritten.
+    async fn upgrade_blob_formats(&self, limit: i64) -> DbResult<u64>;
+
+    // Tenant storage keys
+    // Destroy the key a tenant's blobs are encrypted with at rest, leaving them unreadable and
+    // refusing any further writes for it. Returns false if the backend doesn't encrypt blobs.
+    async fn destroy_tenant_key(&self, tenant_id: Uuid) -> DbResult<bool>;
+
+    // Abuse reports
+    async fn store_abuse_report(&self, report: AbuseReport) -> DbResult<()>;
+    // Reports matching an admin filter, most recent first
+    async fn list_abuse_reports(&self, filter: &Filter, limit: i64) -> DbResult<Vec<AbuseReport>>;
+    // Conflict if the report is already resolved
+    async fn resolve_abuse_report(
+        &self,
+        report_id: Uuid,
+        resolution: &str,
+        resolved_at: DateTime<Utc>,
+    ) -> DbResult<()>;
 }
 
 // Implementation of the DatabaseInterface trait using SQLx and PostgreSQL
 pub struct PostgresDatabase {
     pool: PgPool,
+    namespace: DbNamespace,
+    // Id stored messages are announced under on the message channel; None announces nothing
+    notify_as: Option<Uuid>,
+    // Keys the blobs of tenants' groups are encrypted with; None stores every blob in the clear
+    encryption: Option<Arc<StorageEncryption>>,
 }
 
 impl PostgresDatabase {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            namespace: DbNamespace::default(),
+            notify_as: None,
+            encryption: None,
+        }
     }
 
-    // Migration method to add init_key column to clients table
-    pub async fn migrate_clients_table(&self) -> DbResult<()> {
-        // Check if init_key column exists
-        let column_exists = sqlx::query_scalar::<_, bool>(
-            r#"
-            SELECT EXISTS (
-                SELECT 1
-                FROM information_schema.columns
-                WHERE table_name = 'clients'
-                AND column_name = 'init_key'
-            )
-            "#,
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| DbError::QueryError(e.to_string()))?;
+    // Use a schema and table prefix. The pool's connections should be configured with
+    // `DbNamespace::connect_options` so the schema is on their search_path.
+    pub fn with_namespace(mut self, namespace: DbNamespace) -> Self {
+        self.namespace = namespace;
+        self
+    }
 
-        // Add column if it doesn't exist
-        if !column_exists {
-            sqlx::query(
-                r#"
-                ALTER TABLE clients
-                ADD COLUMN init_key BYTEA
-                "#,
-            )
-            .execute(&self.pool)
-            .await
-            .map_err(|e| DbError::QueryError(e.to_string()))?;
+    // Prefixed name of a table or index
+    fn table(&self, name: &str) -> String {
+        self.namespace.table(name)
+    }
+
+    // Query with `{table}` placeholders replaced by the prefixed table names
+    fn sql(&self, query: &str) -> String {
+        self.namespace.sql(query)
+    }
+
+    // Run all schema migrations in order
+    pub async fn run_migrations(&self) -> DbResult<()> {
+        self.migrate_schema().await?;
+        self.migrate_clients_table().await?;
+        self.migrate_groups_table().await?;
+        self.migrate_memberships_table().await?;
+        self.migrate_messages_table().await?;
+        self.migrate_soft_delete_columns().await?;
+        self.migrate_blob_versions().await?;
+        self.migrate_client_backups_table().await?;
+        self.migrate_request_nonces_table().await?;
+        self.migrate_feature_flags_table().await?;
+        self.migrate_verbose_traces_table().await?;
+        self.migrate_key_package_claims_table().await?;
+        self.migrate_key_package_used_at().await?;
+        self.migrate_proposal_refs_table().await?;
+        self.migrate_group_info_table().await?;
+        self.migrate_ratchet_trees_table().await?;
+        self.migrate_abuse_reports_table().await?;
+        self.migrate_delivery_cursors_table().await?;
+        self.migrate_tenant_keys_table().await?;
+        self.migrate_enum_columns().await?;
+        self.migrate_foreign_keys().await?;
+        self.migrate_indexes().await?;
+
+        Ok(())
+    }
+
+    // Migration method to create the configured schema, if any
+    pub async fn migrate_schema(&self) -> DbResult<()> {
+        if let Some(schema) = self.namespace.schema() {
+            sqlx::query(&format!("CREATE SCHEMA IF NOT EXISTS {}", schema))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| DbError::QueryError(e.to_string()))?;
         }
 
         Ok(())
     }
-}
 
-#[async_trait]
-impl DatabaseInterface for PostgresDatabase {
-    // Client operations
-    async fn register_client(&self, client: Client) -> DbResult<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO clients (id, user_id, credential, scheme, device_name, last_seen, created_at, init_key)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-            "#,
-        )
-        .bind(client.id)
-        .bind(client.user_id)
-        .bind(client.credential)
-        .bind(&client.scheme)
-        .bind(&client.device_name)
-        .bind(client.last_seen)
-        .bind(client.created_at)
-        .bind(client.init_key)
+    // Migration method to add init_key, signature key, key package notice and service account columns to clients table
+    pub async fn migrate_clients_table(&self) -> DbResult<()> {
+        self.add_column_if_missing("clients", "init_key", "BYTEA")
+            .await?;
+        self.add_column_if_missing("clients", "signature_key", "BYTEA")
+            .await?;
+        self.add_column_if_missing("clients", "key_packages_low_at", "TIMESTAMPTZ")
+            .await?;
+        self.add_column_if_missing("clients", "is_service", "BOOLEAN NOT NULL DEFAULT false")
+            .await
+    }
+
+    // Migration method to map groups to the MLS group id their clients use and to their
+    // creators' handles, and to track how old their epochs are
+    pub async fn migrate_groups_table(&self) -> DbResult<()> {
+        self.add_column_if_missing("groups", "mls_group_id", "BYTEA")
+            .await?;
+        self.add_column_if_missing("groups", "tenant_id", "UUID")
+            .await?;
+        self.add_column_if_missing("groups", "handle", "TEXT")
+            .await?;
+        self.add_column_if_missing("groups", "epoch_started_at", "TIMESTAMPTZ")
+            .await?;
+        self.add_column_if_missing("groups", "max_epoch_age_secs", "BIGINT")
+            .await?;
+        self.add_column_if_missing("groups", "rotation_reminded_epoch", "BIGINT")
+            .await?;
+
+        // Each MLS group id maps to at most one group
+        sqlx::query(&format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {} ON {} (mls_group_id)",
+            self.table("idx_groups_mls_group_id"),
+            self.table("groups")
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        // Each handle maps to at most one group of its tenant
+        sqlx::query(&format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {} ON {} (tenant_id, handle)",
+            self.table("idx_groups_tenant_handle"),
+            self.table("groups")
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
@@ -195,70 +1075,168 @@ impl DatabaseInterface for PostgresDatabase {
         Ok(())
     }
 
-    async fn get_client(&self, client_id: Uuid) -> DbResult<Client> {
-        let client = sqlx::query_as::<_, Client>(
-            r#"
-            SELECT * FROM clients
-            WHERE id = $1
-            "#,
+    // Migration method to add epoch acknowledgement tracking to memberships table
+    pub async fn migrate_memberships_table(&self) -> DbResult<()> {
+        self.add_column_if_missing("memberships", "last_acked_epoch", "BIGINT")
+            .await?;
+        self.add_column_if_missing("memberships", "last_acked_at", "TIMESTAMPTZ")
+            .await?;
+        self.add_column_if_missing("memberships", "escalated_at", "TIMESTAMPTZ")
+            .await?;
+        self.add_column_if_missing("memberships", "welcome_requested_at", "TIMESTAMPTZ")
+            .await?;
+        self.add_column_if_missing(
+            "memberships",
+            "welcome_reminders",
+            "BIGINT NOT NULL DEFAULT 0",
+        )
+        .await?;
+        self.add_column_if_missing("memberships", "welcome_reminded_at", "TIMESTAMPTZ")
+            .await?;
+        self.add_column_if_missing("memberships", "welcome_escalated_at", "TIMESTAMPTZ")
+            .await?;
+        self.add_column_if_missing(
+            "memberships",
+            "evicted_messages",
+            "BIGINT NOT NULL DEFAULT 0",
         )
-        .bind(client_id)
-        .fetch_optional(&self.pool)
         .await
-        .map_err(|e| DbError::QueryError(e.to_string()))?
-        .ok_or(DbError::NotFound)?;
-
-        Ok(client)
     }
 
-    async fn list_clients_by_user(&self, user_id: Uuid) -> DbResult<Vec<Client>> {
-        let clients = sqlx::query_as::<_, Client>(
-            r#"
-            SELECT * FROM clients
-            WHERE user_id = $1
-            ORDER BY created_at DESC
-            "#,
+    // Migration method to support system and application messages, and message metadata
+    pub async fn migrate_messages_table(&self) -> DbResult<()> {
+        self.add_column_if_missing("messages", "system", "BYTEA")
+            .await?;
+        self.add_column_if_missing("messages", "application", "BYTEA")
+            .await?;
+        self.add_column_if_missing("messages", "extra", "JSONB")
+            .await?;
+        self.add_column_if_missing("messages", "payload_hash", "BYTEA")
+            .await?;
+        self.add_column_if_missing("messages", "expires_at", "TIMESTAMPTZ")
+            .await?;
+
+        // System messages have no sending client
+        sqlx::query(
+            &self.sql("ALTER TABLE {messages} DROP CONSTRAINT IF EXISTS {messages}_sender_id_fkey"),
         )
-        .bind(user_id)
-        .fetch_all(&self.pool)
+        .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(clients)
-    }
+        // System notices can be addressed to clients outside any group
+        sqlx::query(&self.sql("ALTER TABLE {messages} ALTER COLUMN group_id DROP NOT NULL"))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-    async fn update_client_last_seen(&self, client_id: Uuid) -> DbResult<()> {
-        let now = Utc::now();
+        self.migrate_message_sequences().await
+    }
 
-        sqlx::query(
+    // Migration method to number each group's messages in delivery order. The last sequence of
+    // each group, and of the notices outside any group under the nil group id, is kept in
+    // message_sequences; storing a message locks its group's row until the store commits, so
+    // sequences become visible in order.
+    async fn migrate_message_sequences(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            UPDATE clients
-            SET last_seen = $1
-            WHERE id = $2
+            CREATE TABLE IF NOT EXISTS {message_sequences} (
+                group_id UUID PRIMARY KEY,
+                last_sequence BIGINT NOT NULL
+            )
             "#,
-        )
-        .bind(now)
-        .bind(client_id)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
+        if self.column_exists("messages", "sequence").await? {
+            return Ok(());
+        }
+
+        // Messages stored before sequences are numbered in the order they were stored
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        for query in [
+            "ALTER TABLE {messages} ADD COLUMN sequence BIGINT",
+            r#"
+            UPDATE {messages} m SET sequence = numbered.sequence
+            FROM (
+                SELECT id, ROW_NUMBER() OVER (PARTITION BY group_id ORDER BY created_at, id) AS sequence
+                FROM {messages}
+            ) numbered
+            WHERE m.id = numbered.id
+            "#,
+            "ALTER TABLE {messages} ALTER COLUMN sequence SET NOT NULL",
+            r#"
+            INSERT INTO {message_sequences} (group_id, last_sequence)
+            SELECT COALESCE(group_id, '00000000-0000-0000-0000-000000000000'), MAX(sequence)
+            FROM {messages}
+            GROUP BY 1
+            ON CONFLICT (group_id) DO UPDATE SET last_sequence = EXCLUDED.last_sequence
+            "#,
+        ] {
+            sqlx::query(&self.sql(query))
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
         Ok(())
     }
 
-    // KeyPackage operations
-    async fn store_key_package(&self, key_package: KeyPackage) -> DbResult<()> {
-        sqlx::query(
+    // Migration method to add soft-delete timestamps to every entity table
+    pub async fn migrate_soft_delete_columns(&self) -> DbResult<()> {
+        for table in ["clients", "groups", "key_packages", "messages"] {
+            self.add_column_if_missing(table, "deleted_at", "TIMESTAMPTZ")
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Migration method to add blob_version columns. Existing rows keep the legacy version until
+    // the upgrade job rewrites them, found through a partial index of rows behind the current one.
+    pub async fn migrate_blob_versions(&self) -> DbResult<()> {
+        for (table, _) in VERSIONED_BLOBS {
+            self.add_column_if_missing(table, "blob_version", "SMALLINT NOT NULL DEFAULT 0")
+                .await?;
+            sqlx::query(&format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {} (id) WHERE blob_version < {}",
+                self.table(&format!(
+                    "{}_blobs_before_v{}_idx",
+                    table, CURRENT_BLOB_VERSION
+                )),
+                self.table(table),
+                CURRENT_BLOB_VERSION
+            ))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    // Migration method to create the client backup store
+    pub async fn migrate_client_backups_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            INSERT INTO key_packages (id, client_id, data, created_at, used)
-            VALUES ($1, $2, $3, $4, $5)
+            CREATE TABLE IF NOT EXISTS {client_backups} (
+                client_id UUID PRIMARY KEY REFERENCES {clients}(id) ON DELETE CASCADE,
+                version BIGINT NOT NULL,
+                data BYTEA NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )
             "#,
-        )
-        .bind(key_package.id)
-        .bind(key_package.client_id)
-        .bind(key_package.data)
-        .bind(key_package.created_at)
-        .bind(key_package.used)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
@@ -266,47 +1244,63 @@ impl DatabaseInterface for PostgresDatabase {
         Ok(())
     }
 
-    async fn get_key_package(&self, key_package_id: Uuid) -> DbResult<KeyPackage> {
-        let key_package = sqlx::query_as::<_, KeyPackage>(
+    // Migration method to create the queue of proposals commits include by reference
+    pub async fn migrate_proposal_refs_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            SELECT * FROM key_packages
-            WHERE id = $1
+            CREATE TABLE IF NOT EXISTS {proposal_refs} (
+                group_id UUID NOT NULL REFERENCES {groups}(id) ON DELETE CASCADE,
+                proposal_ref BYTEA NOT NULL,
+                epoch BIGINT NOT NULL,
+                message_id UUID NOT NULL REFERENCES {messages}(id) ON DELETE CASCADE,
+                created_at TIMESTAMPTZ NOT NULL,
+                committed_by UUID,
+                PRIMARY KEY (group_id, proposal_ref)
+            )
             "#,
-        )
-        .bind(key_package_id)
-        .fetch_optional(&self.pool)
+        ))
+        .execute(&self.pool)
         .await
-        .map_err(|e| DbError::QueryError(e.to_string()))?
-        .ok_or(DbError::NotFound)?;
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(key_package)
+        Ok(())
     }
 
-    async fn list_key_packages_by_client(&self, client_id: Uuid) -> DbResult<Vec<KeyPackage>> {
-        let key_packages = sqlx::query_as::<_, KeyPackage>(
+    // Migration method to create the store of published GroupInfos
+    pub async fn migrate_group_info_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            SELECT * FROM key_packages
-            WHERE client_id = $1 AND used = false
-            ORDER BY created_at DESC
+            CREATE TABLE IF NOT EXISTS {group_info} (
+                group_id UUID NOT NULL REFERENCES {groups}(id) ON DELETE CASCADE,
+                epoch BIGINT NOT NULL,
+                data BYTEA NOT NULL,
+                publisher_id UUID NOT NULL,
+                published_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (group_id, epoch)
+            )
             "#,
-        )
-        .bind(client_id)
-        .fetch_all(&self.pool)
+        ))
+        .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(key_packages)
+        Ok(())
     }
 
-    async fn mark_key_package_used(&self, key_package_id: Uuid) -> DbResult<()> {
-        sqlx::query(
+    // Migration method to create the store of uploaded ratchet trees
+    pub async fn migrate_ratchet_trees_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            UPDATE key_packages
-            SET used = true
-            WHERE id = $1
+            CREATE TABLE IF NOT EXISTS {ratchet_trees} (
+                group_id UUID NOT NULL REFERENCES {groups}(id) ON DELETE CASCADE,
+                epoch BIGINT NOT NULL,
+                data BYTEA NOT NULL,
+                uploader_id UUID NOT NULL,
+                uploaded_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (group_id, epoch)
+            )
             "#,
-        )
-        .bind(key_package_id)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
@@ -314,76 +1308,94 @@ impl DatabaseInterface for PostgresDatabase {
         Ok(())
     }
 
-    // Group operations
-    async fn create_group(&self, group: Group) -> DbResult<()> {
-        sqlx::query(
+    // Migration method to create the clients' delivery cursors. Notices outside any group are
+    // tracked under the nil group id.
+    pub async fn migrate_delivery_cursors_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            INSERT INTO groups (id, creator_id, epoch, state, created_at, updated_at, is_active)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            CREATE TABLE IF NOT EXISTS {delivery_cursors} (
+                client_id UUID NOT NULL REFERENCES {clients}(id) ON DELETE CASCADE,
+                group_id UUID NOT NULL,
+                sequence BIGINT NOT NULL,
+                PRIMARY KEY (client_id, group_id)
+            )
             "#,
-        )
-        .bind(group.id)
-        .bind(group.creator_id)
-        .bind(group.epoch)
-        .bind(group.state)
-        .bind(group.created_at)
-        .bind(group.updated_at)
-        .bind(group.is_active)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(())
-    }
+        if !self.column_exists("delivery_cursors", "message_id").await? {
+            return Ok(());
+        }
 
-    async fn get_group(&self, group_id: Uuid) -> DbResult<Group> {
-        let group = sqlx::query_as::<_, Group>(
+        // Cursors kept as message positions before sequences move to the last message at or
+        // before their position
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        for query in [
+            "ALTER TABLE {delivery_cursors} ADD COLUMN IF NOT EXISTS sequence BIGINT",
             r#"
-            SELECT * FROM groups
-            WHERE id = $1
+            UPDATE {delivery_cursors} c SET sequence = COALESCE((
+                SELECT MAX(m.sequence) FROM {messages} m
+                WHERE COALESCE(m.group_id, '00000000-0000-0000-0000-000000000000') = c.group_id
+                  AND (m.created_at, m.id) <= (c.message_created_at, c.message_id)
+            ), 0)
             "#,
-        )
-        .bind(group_id)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| DbError::QueryError(e.to_string()))?
-        .ok_or(DbError::NotFound)?;
+            "ALTER TABLE {delivery_cursors} ALTER COLUMN sequence SET NOT NULL",
+            "ALTER TABLE {delivery_cursors} DROP COLUMN message_created_at, DROP COLUMN message_id",
+        ] {
+            sqlx::query(&self.sql(query))
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(group)
+        Ok(())
     }
 
-    async fn list_groups_by_client(&self, client_id: Uuid) -> DbResult<Vec<Group>> {
-        let groups = sqlx::query_as::<_, Group>(
+    // Migration method to create the store of abuse reports
+    pub async fn migrate_abuse_reports_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            SELECT g.* FROM groups g
-            JOIN memberships m ON g.id = m.group_id
-            WHERE m.client_id = $1
-              AND m.removed_at IS NULL
-              AND g.is_active = true
-            ORDER BY g.updated_at DESC
+            CREATE TABLE IF NOT EXISTS {abuse_reports} (
+                id UUID PRIMARY KEY,
+                group_id UUID NOT NULL,
+                reporter_id UUID NOT NULL,
+                message_ids UUID[] NOT NULL,
+                reason TEXT NOT NULL,
+                excerpt BYTEA,
+                created_at TIMESTAMPTZ NOT NULL,
+                resolved_at TIMESTAMPTZ,
+                resolution TEXT
+            )
             "#,
-        )
-        .bind(client_id)
-        .fetch_all(&self.pool)
+        ))
+        .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(groups)
+        Ok(())
     }
 
-    async fn update_group_epoch(&self, group_id: Uuid, epoch: i64) -> DbResult<()> {
-        let now = Utc::now();
-
-        sqlx::query(
+    // Migration method to create the store of recently used request nonces
+    pub async fn migrate_request_nonces_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            UPDATE groups
-            SET epoch = $1, updated_at = $2
-            WHERE id = $3
+            CREATE TABLE IF NOT EXISTS {request_nonces} (
+                client_id UUID NOT NULL,
+                nonce TEXT NOT NULL,
+                expires_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (client_id, nonce)
+            )
             "#,
-        )
-        .bind(epoch)
-        .bind(now)
-        .bind(group_id)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
@@ -391,19 +1403,20 @@ impl DatabaseInterface for PostgresDatabase {
         Ok(())
     }
 
-    async fn update_group_state(&self, group_id: Uuid, state: Vec<u8>) -> DbResult<()> {
-        let now = Utc::now();
-
-        sqlx::query(
+    // Migration method to create the table of tenants' storage key salts. A destroyed key keeps
+    // its row, with the salt dropped, so nothing can be stored for its tenant again.
+    pub async fn migrate_tenant_keys_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            UPDATE groups
-            SET state = $1, updated_at = $2
-            WHERE id = $3
+            CREATE TABLE IF NOT EXISTS {tenant_keys} (
+                tenant_id UUID PRIMARY KEY,
+                salt BYTEA,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                destroyed_at TIMESTAMPTZ,
+                CHECK ((salt IS NULL) = (destroyed_at IS NOT NULL))
+            )
             "#,
-        )
-        .bind(state)
-        .bind(now)
-        .bind(group_id)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
@@ -411,20 +1424,20 @@ impl DatabaseInterface for PostgresDatabase {
         Ok(())
     }
 
-    // Membership operations
-    async fn add_membership(&self, membership: Membership) -> DbResult<()> {
-        sqlx::query(
+    // Migration method to create the feature flag table
+    pub async fn migrate_feature_flags_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            INSERT INTO memberships (id, client_id, group_id, role, added_at, removed_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            CREATE TABLE IF NOT EXISTS {feature_flags} (
+                name TEXT PRIMARY KEY,
+                enabled BOOLEAN NOT NULL DEFAULT false,
+                percentage INTEGER NOT NULL DEFAULT 0 CHECK (percentage BETWEEN 0 AND 100),
+                group_ids UUID[] NOT NULL DEFAULT '{}',
+                user_ids UUID[] NOT NULL DEFAULT '{}',
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )
             "#,
-        )
-        .bind(membership.id)
-        .bind(membership.client_id)
-        .bind(membership.group_id)
-        .bind(&membership.role)
-        .bind(membership.added_at)
-        .bind(membership.removed_at)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
@@ -432,18 +1445,21 @@ impl DatabaseInterface for PostgresDatabase {
         Ok(())
     }
 
-    async fn remove_membership(&self, membership_id: Uuid) -> DbResult<()> {
-        let now = Utc::now();
-
-        sqlx::query(
+    // Migration method to create the verbose tracing table
+    pub async fn migrate_verbose_traces_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            UPDATE memberships
-            SET removed_at = $1
-            WHERE id = $2
+            CREATE TABLE IF NOT EXISTS {verbose_traces} (
+                id UUID PRIMARY KEY,
+                group_id UUID,
+                client_id UUID,
+                reason TEXT NOT NULL DEFAULT '',
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                expires_at TIMESTAMPTZ NOT NULL,
+                CHECK ((group_id IS NULL) <> (client_id IS NULL))
+            )
             "#,
-        )
-        .bind(now)
-        .bind(membership_id)
+        ))
         .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
@@ -451,152 +1467,2978 @@ impl DatabaseInterface for PostgresDatabase {
         Ok(())
     }
 
-    async fn list_memberships_by_group(&self, group_id: Uuid) -> DbResult<Vec<Membership>> {
-        let memberships = sqlx::query_as::<_, Membership>(
+    // Migration method to create the key package audit trail
+    pub async fn migrate_key_package_claims_table(&self) -> DbResult<()> {
+        sqlx::query(&self.sql(
             r#"
-            SELECT * FROM memberships
-            WHERE group_id = $1
-              AND removed_at IS NULL
+            CREATE TABLE IF NOT EXISTS {key_package_claims} (
+                key_package_id UUID PRIMARY KEY,
+                client_id UUID NOT NULL,
+                hash BYTEA NOT NULL,
+                published_at TIMESTAMPTZ NOT NULL,
+                claimed_at TIMESTAMPTZ NOT NULL,
+                group_id UUID NOT NULL,
+                claimed_by UUID NOT NULL
+            )
             "#,
-        )
-        .bind(group_id)
-        .fetch_all(&self.pool)
+        ))
+        .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(memberships)
+        Ok(())
     }
 
-    async fn list_memberships_by_client(&self, client_id: Uuid) -> DbResult<Vec<Membership>> {
-        let memberships = sqlx::query_as::<_, Membership>(
+    // Migration method to record when key packages were used. Packages used before take the
+    // time of their recorded claim, or else now, so none is purged sooner than its retention.
+    pub async fn migrate_key_package_used_at(&self) -> DbResult<()> {
+        self.add_column_if_missing("key_packages", "used_at", "TIMESTAMPTZ")
+            .await?;
+        sqlx::query(&self.sql(
             r#"
-            SELECT * FROM memberships
-            WHERE client_id = $1
-              AND removed_at IS NULL
+            UPDATE {key_packages} kp
+            SET used_at = COALESCE(
+                (SELECT c.claimed_at FROM {key_package_claims} c WHERE c.key_package_id = kp.id),
+                now()
+            )
+            WHERE kp.used AND kp.used_at IS NULL
             "#,
-        )
-        .bind(client_id)
-        .fetch_all(&self.pool)
+        ))
+        .execute(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        Ok(memberships)
+        Ok(())
     }
 
-    // Message operations
-    async fn store_message(&self, message: Message) -> DbResult<()> {
-        sqlx::query(
+    // Migration method to constrain stringly typed columns to Postgres enum types.
+    // Existing values are normalized ("External-Init" -> "external_init") before the cast,
+    // so a row that still doesn't match fails the migration instead of being dropped.
+    pub async fn migrate_enum_columns(&self) -> DbResult<()> {
+        for (type_name, values) in PG_ENUM_TYPES {
+            let values = values
+                .iter()
+                .map(|value| format!("'{}'", value))
+                .collect::<Vec<_>>()
+                .join(", ");
+            sqlx::query(&format!(
+                "DO $$ BEGIN CREATE TYPE {} AS ENUM ({}); EXCEPTION WHEN duplicate_object THEN NULL; END $$",
+                type_name, values
+            ))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        // Types created by an older version lack the values added since
+        for (type_name, values) in PG_ENUM_TYPES {
+            for value in *values {
+                sqlx::query(&format!(
+                    "ALTER TYPE {} ADD VALUE IF NOT EXISTS '{}'",
+                    type_name, value
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| DbError::QueryError(e.to_string()))?;
+            }
+        }
+
+        for (table, column, type_name) in [
+            ("clients", "scheme", "credential_scheme"),
+            ("memberships", "role", "membership_role"),
+            ("messages", "message_type", "message_type"),
+            ("messages", "proposal_type", "proposal_type"),
+        ] {
+            self.convert_column_to_enum(table, column, type_name)
+                .await?;
+        }
+
+        // Columns added since the types were introduced use them from the start
+        self.add_column_if_missing(
+            "clients",
+            "attestation_verdict",
+            "attestation_verdict NOT NULL DEFAULT 'unattested'",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    // Change a text column to an enum type if it isn't one already
+    async fn convert_column_to_enum(
+        &self,
+        table: &str,
+        column: &str,
+        type_name: &str,
+    ) -> DbResult<()> {
+        let current_type = sqlx::query_scalar::<_, String>(
             r#"
-            INSERT INTO messages 
-            (id, group_id, sender_id, created_at, read, message_type, 
-             proposal, commit, welcome, proposal_type, epoch, recipients)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            SELECT udt_name::text
+            FROM information_schema.columns
+            WHERE table_schema = current_schema()
+            AND table_name = $1
+            AND column_name = $2
             "#,
         )
-        .bind(message.id)
-        .bind(message.group_id)
-        .bind(message.sender_id)
-        .bind(message.created_at)
-        .bind(message.read)
-        .bind(&message.message_type)
-        .bind(message.proposal)
-        .bind(message.commit)
-        .bind(message.welcome)
-        .bind(message.proposal_type)
-        .bind(message.epoch)
-        .bind(message.recipients)
-        .execute(&self.pool)
+        .bind(self.table(table))
+        .bind(column)
+        .fetch_optional(&self.pool)
         .await
         .map_err(|e| DbError::QueryError(e.to_string()))?;
 
+        if current_type.is_some_and(|current_type| current_type != type_name) {
+            sqlx::query(&format!(
+                "ALTER TABLE {0} ALTER COLUMN {1} TYPE {2} USING replace(lower(trim({1})), '-', '_')::{2}",
+                self.table(table),
+                column,
+                type_name
+            ))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
         Ok(())
     }
 
-    async fn fetch_messages_for_client(
+    // Migration method to enforce the foreign keys, with their cascade rules, on databases
+    // created before they were declared
+    pub async fn migrate_foreign_keys(&self) -> DbResult<()> {
+        for (table, column, references) in FOREIGN_KEYS {
+            self.ensure_cascading_foreign_key(table, column, references)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Make `<table>_<column>_fkey` an ON DELETE CASCADE foreign key, replacing a missing one or
+    // one with another delete rule. It's added NOT VALID, so rows written before it existed
+    // don't block the migration.
+    async fn ensure_cascading_foreign_key(
         &self,
-        client_id: Uuid,
-        group_id: Option<Uuid>,
-        include_read: bool,
-    ) -> DbResult<Vec<Message>> {
-        let query = match (group_id, include_read) {
-            (Some(g_id), true) => sqlx::query_as::<_, Message>(
-                r#"
-                    SELECT m.* FROM messages m
-                    JOIN memberships mem ON m.group_id = mem.group_id
-                    WHERE mem.client_id = $1
-                      AND m.group_id = $2
-                    ORDER BY m.created_at ASC
-                    "#,
-            )
-            .bind(client_id)
-            .bind(g_id),
-            (Some(g_id), false) => sqlx::query_as::<_, Message>(
-                r#"
-                    SELECT m.* FROM messages m
-                    JOIN memberships mem ON m.group_id = mem.group_id
-                    WHERE mem.client_id = $1
-                      AND m.group_id = $2
-                      AND m.read = false
-                    ORDER BY m.created_at ASC
-                    "#,
-            )
-            .bind(client_id)
-            .bind(g_id),
-            (None, true) => sqlx::query_as::<_, Message>(
-                r#"
-                    SELECT m.* FROM messages m
-                    JOIN memberships mem ON m.group_id = mem.group_id
-                    WHERE mem.client_id = $1
-                    ORDER BY m.created_at ASC
-                    "#,
-            )
-            .bind(client_id),
-            (None, false) => sqlx::query_as::<_, Message>(
-                r#"
-                    SELECT m.* FROM messages m
-                    JOIN memberships mem ON m.group_id = mem.group_id
-                    WHERE mem.client_id = $1
-                      AND m.read = false
-                    ORDER BY m.created_at ASC
-                    "#,
-            )
-            .bind(client_id),
-        };
+        table: &str,
+        column: &str,
+        references: &str,
+    ) -> DbResult<()> {
+        let name = format!("{}_{}_fkey", self.table(table), column);
+        let delete_rule = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT delete_rule
+            FROM information_schema.referential_constraints
+            WHERE constraint_schema = current_schema()
+            AND constraint_name = $1
+            "#,
+        )
+        .bind(&name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
 
-        let messages = query
-            .fetch_all(&self.pool)
+        if delete_rule.as_deref() != Some("CASCADE") {
+            sqlx::query(&format!(
+                "ALTER TABLE {table} DROP CONSTRAINT IF EXISTS {name}, \
+                 ADD CONSTRAINT {name} FOREIGN KEY ({column}) REFERENCES {references}(id) \
+                 ON DELETE CASCADE NOT VALID",
+                table = self.table(table),
+                name = name,
+                column = column,
+                references = self.table(references)
+            ))
+            .execute(&self.pool)
             .await
             .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
 
-        Ok(messages)
+        Ok(())
     }
 
-    async fn mark_messages_read(&self, message_ids: Vec<Uuid>) -> DbResult<()> {
-        // Use a transaction to mark all messages as read
-        let mut tx = self
-            .pool
-            .begin()
+    // Migration method to create the composite indexes the queries rely on
+    pub async fn migrate_indexes(&self) -> DbResult<()> {
+        for (name, table, columns) in EXPECTED_INDEXES {
+            sqlx::query(&format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {} ({})",
+                self.table(name),
+                self.table(table),
+                columns.join(", ")
+            ))
+            .execute(&self.pool)
             .await
             .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
 
-        for msg_id in &message_ids {
-            sqlx::query(
+        Ok(())
+    }
+
+    // Expected indexes with no index on the same leading columns, under any name.
+    // Used to warn about externally managed schemas, where migrations don't run.
+    pub async fn missing_indexes(&self) -> DbResult<Vec<(String, &'static [&'static str])>> {
+        let mut missing = Vec::new();
+        for (_, table, columns) in EXPECTED_INDEXES {
+            let exists = sqlx::query_scalar::<_, bool>(
                 r#"
-                UPDATE messages
-                SET read = true
-                WHERE id = $1
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM pg_index i
+                    JOIN pg_class t ON t.oid = i.indrelid
+                    WHERE t.relname = $1
+                    AND pg_table_is_visible(t.oid)
+                    AND (
+                        SELECT array_agg(a.attname::text ORDER BY k.ord)
+                        FROM unnest(i.indkey::smallint[]) WITH ORDINALITY AS k(attnum, ord)
+                        JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = k.attnum
+                        WHERE k.ord <= cardinality($2::text[])
+                    ) = $2::text[]
+                )
                 "#,
             )
-            .bind(msg_id)
-            .execute(&mut *tx)
+            .bind(self.table(table))
+            .bind(columns)
+            .fetch_one(&self.pool)
             .await
             .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+            if !exists {
+                missing.push((self.table(table), *columns));
+            }
         }
 
-        tx.commit()
-            .await
-            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        Ok(missing)
+    }
 
-        Ok(())
+    // Insert a message row, on the pool or within a transaction
+    // Insert a message as the next of its group's sequence. The group's sequence row stays
+    // locked until the executor's transaction commits, so a later sequence never becomes
+    // visible before an earlier one.
+    async fn insert_message<'e>(
+        &self,
+        executor: impl sqlx::PgExecutor<'e>,
+        message: Message,
+    ) -> DbResult<Message> {
+        let encoder = self
+            .blob_encoder(self.group_tenant(message.group_id).await?)
+            .await?;
+        let encode = |payload: &Option<Vec<u8>>| {
+            payload
+                .as_deref()
+                .map(|payload| encoder.encode(payload))
+                .transpose()
+        };
+
+        // Announcing the message in the same statement makes it part of the write's
+        // transaction, so listeners only hear of it once it's committed
+        let notify = match self.notify_as {
+            Some(_) => ", pg_notify($20, $21)",
+            None => "",
+        };
+        let sql = self.sql(&format!(
+            r#"
+            WITH next AS (
+                INSERT INTO {{message_sequences}} AS s (group_id, last_sequence)
+                VALUES (COALESCE($2, '00000000-0000-0000-0000-000000000000'::uuid), 1)
+                ON CONFLICT (group_id) DO UPDATE SET last_sequence = s.last_sequence + 1
+                RETURNING last_sequence
+            ), stored AS (
+                INSERT INTO {{messages}}
+                (id, group_id, sender_id, created_at, read, message_type,
+                 proposal, commit, welcome, system, application, proposal_type, epoch, recipients,
+                 extra, payload_hash, expires_at, deleted_at, sequence, blob_version)
+                SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
+                       $18, last_sequence, $19
+                FROM next
+                RETURNING sequence
+            )
+            SELECT sequence FROM stored{}
+            "#,
+            notify
+        ));
+        let query = sqlx::query_scalar::<_, i64>(&sql)
+            .bind(message.id)
+            .bind(message.group_id)
+            .bind(message.sender_id)
+            .bind(message.created_at)
+            .bind(message.read)
+            .bind(message.message_type)
+            .bind(encode(&message.proposal)?)
+            .bind(encode(&message.commit)?)
+            .bind(encode(&message.welcome)?)
+            .bind(encode(&message.system)?)
+            .bind(encode(&message.application)?)
+            .bind(message.proposal_type)
+            .bind(message.epoch)
+            .bind(&message.recipients)
+            .bind(&message.extra)
+            .bind(&message.payload_hash)
+            .bind(message.expires_at)
+            .bind(message.deleted_at)
+            .bind(encoder.version());
+        let query = match self.notify_as {
+            Some(origin) => query.bind(self.message_channel()).bind(
+                MessageNotification {
+                    origin,
+                    message_id: message.id,
+                }
+                .to_string(),
+            ),
+            None => query,
+        };
+        let sequence = query.fetch_one(executor).await.map_err(write_error)?;
+
+        Ok(Message {
+            sequence,
+            ..message
+        })
+    }
+
+    // Add a column to a table if it doesn't exist yet
+    async fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> DbResult<()> {
+        // Add column if it doesn't exist
+        if !self.column_exists(table, column).await? {
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                self.table(table),
+                column,
+                definition
+            ))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    // Whether a table has a column
+    async fn column_exists(&self, table: &str, column: &str) -> DbResult<bool> {
+        sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM information_schema.columns
+                WHERE table_schema = current_schema()
+                AND table_name = $1
+                AND column_name = $2
+            )
+            "#,
+        )
+        .bind(self.table(table))
+        .bind(column)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl DatabaseInterface for PostgresDatabase {
+    // Client operations
+    async fn register_client(&self, client: Client) -> DbResult<()> {
+        sqlx::query(
+            &self.sql(r#"
+            INSERT INTO {clients} (id, user_id, credential, scheme, device_name, last_seen, created_at, init_key, signature_key, is_service, attestation_verdict, deleted_at, blob_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            "#),
+        )
+        .bind(client.id)
+        .bind(client.user_id)
+        .bind(blob::encode(&client.credential))
+        .bind(client.scheme)
+        .bind(&client.device_name)
+        .bind(client.last_seen)
+        .bind(client.created_at)
+        .bind(client.init_key)
+        .bind(client.signature_key)
+        .bind(client.is_service)
+        .bind(client.attestation_verdict)
+        .bind(client.deleted_at)
+        .bind(i16::from(CURRENT_BLOB_VERSION))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: Uuid) -> DbResult<Client> {
+        let client = sqlx::query_as::<_, Versioned<Client>>(&self.sql(
+            r#"
+            SELECT * FROM {clients}
+            WHERE id = $1
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+
+        self.decode_row(client).await
+    }
+
+    async fn get_clients(&self, client_ids: &[Uuid]) -> DbResult<Vec<Client>> {
+        let clients = sqlx::query_as::<_, Versioned<Client>>(&self.sql(
+            r#"
+            SELECT * FROM {clients}
+            WHERE id = ANY($1)
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(client_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        self.decode_rows(clients).await
+    }
+
+    async fn list_clients_by_user(
+        &self,
+        user_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<Client>> {
+        let clients = sqlx::query_as::<_, Versioned<Client>>(&self.sql(
+            r#"
+            SELECT id, user_id, CASE WHEN $2 THEN credential ELSE ''::bytea END AS credential,
+                   scheme, device_name, last_seen, created_at,
+                   CASE WHEN $2 THEN init_key END AS init_key,
+                   CASE WHEN $2 THEN signature_key END AS signature_key,
+                   is_service, attestation_verdict, deleted_at, blob_version
+            FROM {clients}
+            WHERE user_id = $1
+              AND deleted_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+        ))
+        .bind(user_id)
+        .bind(include_payload)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        self.decode_rows(clients).await
+    }
+
+    async fn update_client_last_seen(&self, client_id: Uuid) -> DbResult<()> {
+        let now = timestamps::now();
+
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {clients}
+            SET last_seen = $1
+            WHERE id = $2
+            "#,
+        ))
+        .bind(now)
+        .bind(client_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_clients(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Client>> {
+        let sql = self.sql(&format!(
+            r#"
+            SELECT * FROM {{clients}}
+            WHERE {}
+            ORDER BY created_at DESC, id DESC
+            LIMIT ${}
+            "#,
+            filter.to_sql(1),
+            filter.param_count() + 1
+        ));
+        let clients = filter
+            .bind(sqlx::query_as::<_, Versioned<Client>>(&sql))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        self.decode_rows(clients).await
+    }
+
+    // Request replay protection
+    async fn record_request_nonce(
+        &self,
+        client_id: Uuid,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<bool> {
+        let result = sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {request_nonces} (client_id, nonce, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (client_id, nonce) DO NOTHING
+            "#,
+        ))
+        .bind(client_id)
+        .bind(nonce)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn purge_expired_request_nonces(&self, now: DateTime<Utc>) -> DbResult<u64> {
+        let result = sqlx::query(&self.sql("DELETE FROM {request_nonces} WHERE expires_at < $1"))
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn list_signature_keys(&self) -> DbResult<Vec<(Uuid, Vec<u8>)>> {
+        sqlx::query_as::<_, (Uuid, Vec<u8>)>(&self.sql(
+            r#"
+            SELECT id, signature_key FROM {clients}
+            WHERE signature_key IS NOT NULL
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))
+    }
+
+    // Feature flag operations
+    async fn upsert_feature_flag(&self, flag: FeatureFlag) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {feature_flags} (name, enabled, percentage, group_ids, user_ids, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (name) DO UPDATE
+            SET enabled = EXCLUDED.enabled, percentage = EXCLUDED.percentage,
+                group_ids = EXCLUDED.group_ids, user_ids = EXCLUDED.user_ids,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        ))
+        .bind(&flag.name)
+        .bind(flag.enabled)
+        .bind(flag.percentage)
+        .bind(&flag.group_ids)
+        .bind(&flag.user_ids)
+        .bind(flag.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_feature_flags(&self) -> DbResult<Vec<FeatureFlag>> {
+        let flags = sqlx::query_as::<_, FeatureFlag>(
+            &self.sql("SELECT * FROM {feature_flags} ORDER BY name"),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(flags)
+    }
+
+    async fn delete_feature_flag(&self, name: &str) -> DbResult<()> {
+        let result = sqlx::query(&self.sql("DELETE FROM {feature_flags} WHERE name = $1"))
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+        Ok(())
+    }
+
+    // Verbose tracing operations
+    async fn create_verbose_trace(&self, trace: VerboseTrace) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {verbose_traces} (id, group_id, client_id, reason, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        ))
+        .bind(trace.id)
+        .bind(trace.group_id)
+        .bind(trace.client_id)
+        .bind(&trace.reason)
+        .bind(trace.created_at)
+        .bind(trace.expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_verbose_traces(&self, now: DateTime<Utc>) -> DbResult<Vec<VerboseTrace>> {
+        let traces =
+            sqlx::query_as::<_, VerboseTrace>(&self.sql(
+                "SELECT * FROM {verbose_traces} WHERE expires_at > $1 ORDER BY created_at, id",
+            ))
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(traces)
+    }
+
+    async fn delete_verbose_trace(&self, trace_id: Uuid) -> DbResult<()> {
+        let result = sqlx::query(&self.sql("DELETE FROM {verbose_traces} WHERE id = $1"))
+            .bind(trace_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+        Ok(())
+    }
+
+    // Client backup operations
+    async fn store_client_backup(
+        &self,
+        client_id: Uuid,
+        data: Vec<u8>,
+        expected_version: i64,
+    ) -> DbResult<ClientBackup> {
+        let now = timestamps::now();
+
+        // Version 0 means no backup exists yet; otherwise compare-and-swap on the version
+        let backup = if expected_version == 0 {
+            sqlx::query_as::<_, ClientBackup>(&self.sql(
+                r#"
+                INSERT INTO {client_backups} (client_id, version, data, created_at, updated_at)
+                VALUES ($1, 1, $2, $3, $3)
+                ON CONFLICT (client_id) DO NOTHING
+                RETURNING *
+                "#,
+            ))
+            .bind(client_id)
+            .bind(data)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await
+        } else {
+            sqlx::query_as::<_, ClientBackup>(&self.sql(
+                r#"
+                UPDATE {client_backups}
+                SET data = $1, version = version + 1, updated_at = $2
+                WHERE client_id = $3 AND version = $4
+                RETURNING *
+                "#,
+            ))
+            .bind(data)
+            .bind(now)
+            .bind(client_id)
+            .bind(expected_version)
+            .fetch_optional(&self.pool)
+            .await
+        }
+        .map_err(write_error)?;
+
+        backup.ok_or_else(|| {
+            DbError::Conflict(format!(
+                "Backup version {} is not the current version",
+                expected_version
+            ))
+        })
+    }
+
+    async fn get_client_backup(&self, client_id: Uuid) -> DbResult<ClientBackup> {
+        let backup = sqlx::query_as::<_, ClientBackup>(&self.sql(
+            r#"
+            SELECT * FROM {client_backups}
+            WHERE client_id = $1
+            "#,
+        ))
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+
+        Ok(backup)
+    }
+
+    // KeyPackage operations
+    async fn store_key_package(&self, key_package: KeyPackage) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {key_packages} (id, client_id, data, created_at, used, used_at, deleted_at, blob_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        ))
+        .bind(key_package.id)
+        .bind(key_package.client_id)
+        .bind(blob::encode(&key_package.data))
+        .bind(key_package.created_at)
+        .bind(key_package.used)
+        .bind(key_package.used_at)
+        .bind(key_package.deleted_at)
+        .bind(i16::from(CURRENT_BLOB_VERSION))
+        .execute(&self.pool)
+        .await
+        .map_err(write_error)?;
+
+        // A fresh key package means the client may be notified again when it runs low
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {clients}
+            SET key_packages_low_at = NULL
+            WHERE id = $1
+            "#,
+        ))
+        .bind(key_package.client_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get_key_package(&self, key_package_id: Uuid) -> DbResult<KeyPackage> {
+        let key_package = sqlx::query_as::<_, Versioned<KeyPackage>>(&self.sql(
+            r#"
+            SELECT * FROM {key_packages}
+            WHERE id = $1
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(key_package_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+
+        self.decode_row(key_package).await
+    }
+
+    async fn list_key_packages_by_client(
+        &self,
+        client_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<KeyPackage>> {
+        let key_packages = sqlx::query_as::<_, Versioned<KeyPackage>>(&self.sql(
+            r#"
+            SELECT id, client_id, CASE WHEN $2 THEN data ELSE ''::bytea END AS data,
+                   created_at, used, used_at, deleted_at, blob_version
+            FROM {key_packages}
+            WHERE client_id = $1 AND used = false
+              AND deleted_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+        ))
+        .bind(client_id)
+        .bind(include_payload)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        self.decode_rows(key_packages).await
+    }
+
+    async fn mark_key_package_used(&self, key_package_id: Uuid) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {key_packages}
+            SET used = true, used_at = COALESCE(used_at, now())
+            WHERE id = $1
+            "#,
+        ))
+        .bind(key_package_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn claim_key_package(&self, client_id: Uuid, skip: &[Uuid]) -> DbResult<KeyPackage> {
+        // SKIP LOCKED lets concurrent claims for the same client take different packages
+        let key_package = sqlx::query_as::<_, Versioned<KeyPackage>>(&self.sql(
+            r#"
+            UPDATE {key_packages}
+            SET used = true, used_at = now()
+            WHERE id = (
+                SELECT id FROM {key_packages}
+                WHERE client_id = $1 AND used = false AND deleted_at IS NULL
+                  AND id <> ALL($2)
+                ORDER BY created_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        ))
+        .bind(client_id)
+        .bind(skip)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        self.decode_row(key_package.ok_or(DbError::NotFound)?).await
+    }
+
+    async fn release_key_package(&self, key_package_id: Uuid) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {key_packages}
+            SET used = false, used_at = NULL
+            WHERE id = $1
+            "#,
+        ))
+        .bind(key_package_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn record_key_package_claim(&self, claim: KeyPackageClaim) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {key_package_claims} (key_package_id, client_id, hash, published_at, claimed_at, group_id, claimed_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        ))
+        .bind(claim.key_package_id)
+        .bind(claim.client_id)
+        .bind(claim.hash)
+        .bind(claim.published_at)
+        .bind(claim.claimed_at)
+        .bind(claim.group_id)
+        .bind(claim.claimed_by)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_key_package_claims(
+        &self,
+        filter: &Filter,
+        limit: i64,
+    ) -> DbResult<Vec<KeyPackageClaim>> {
+        let sql = self.sql(&format!(
+            r#"
+            SELECT * FROM {{key_package_claims}}
+            WHERE {}
+            ORDER BY claimed_at DESC, key_package_id DESC
+            LIMIT ${}
+            "#,
+            filter.to_sql(1),
+            filter.param_count() + 1
+        ));
+        let claims = filter
+            .bind(sqlx::query_as::<_, KeyPackageClaim>(&sql))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(claims)
+    }
+
+    async fn list_clients_low_on_key_packages(
+        &self,
+        min_unused: i64,
+    ) -> DbResult<Vec<KeyPackageInventory>> {
+        // Active clients below the threshold that haven't been notified yet
+        let clients = sqlx::query_as::<_, KeyPackageInventory>(&self.sql(
+            r#"
+            SELECT c.id AS client_id, COUNT(kp.id) AS unused FROM {clients} c
+            LEFT JOIN {key_packages} kp
+              ON kp.client_id = c.id AND kp.used = false AND kp.deleted_at IS NULL
+            WHERE c.deleted_at IS NULL
+              AND c.key_packages_low_at IS NULL
+            GROUP BY c.id
+            HAVING COUNT(kp.id) < $1
+            "#,
+        ))
+        .bind(min_unused)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(clients)
+    }
+
+    async fn mark_key_packages_low_notified(&self, client_id: Uuid) -> DbResult<()> {
+        let now = timestamps::now();
+
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {clients}
+            SET key_packages_low_at = $1
+            WHERE id = $2
+            "#,
+        ))
+        .bind(now)
+        .bind(client_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn count_unused_key_packages(
+        &self,
+        client_id: Uuid,
+        expired_before: DateTime<Utc>,
+    ) -> DbResult<KeyPackageCounts> {
+        let counts = sqlx::query_as::<_, KeyPackageCounts>(&self.sql(
+            r#"
+            SELECT
+              COUNT(*) FILTER (WHERE NOT used AND created_at >= $2) AS unused,
+              COUNT(*) FILTER (WHERE used) AS used,
+              COUNT(*) FILTER (WHERE NOT used AND created_at < $2) AS expired
+            FROM {key_packages}
+            WHERE client_id = $1
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(client_id)
+        .bind(expired_before)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(counts)
+    }
+
+    async fn purge_used_key_packages(&self, before: DateTime<Utc>) -> DbResult<u64> {
+        let result = sqlx::query(&self.sql(
+            r#"
+            DELETE FROM {key_packages}
+            WHERE used = true
+              AND used_at < $1
+            "#,
+        ))
+        .bind(before)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    // Group operations
+    async fn create_group(&self, group: Group) -> DbResult<()> {
+        let encoder = self.blob_encoder(group.tenant_id).await?;
+        sqlx::query(
+            &self.sql(r#"
+            INSERT INTO {groups} (id, creator_id, epoch, state, mls_group_id, tenant_id, handle, created_at, updated_at, is_active, deleted_at, blob_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#),
+        )
+        .bind(group.id)
+        .bind(group.creator_id)
+        .bind(group.epoch)
+        .bind(
+            group
+                .state
+                .as_deref()
+                .map(|state| encoder.encode(state))
+                .transpose()?,
+        )
+        .bind(group.mls_group_id)
+        .bind(group.tenant_id)
+        .bind(group.handle)
+        .bind(group.created_at)
+        .bind(group.updated_at)
+        .bind(group.is_active)
+        .bind(group.deleted_at)
+        .bind(encoder.version())
+        .execute(&self.pool)
+        .await
+        .map_err(group_insert_error)?;
+
+        Ok(())
+    }
+
+    async fn create_group_with_members(
+        &self,
+        group: Group,
+        members: Vec<Membership>,
+    ) -> DbResult<()> {
+        let encoder = self.blob_encoder(group.tenant_id).await?;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        sqlx::query(
+            &self.sql(r#"
+            INSERT INTO {groups} (id, creator_id, epoch, state, mls_group_id, tenant_id, handle, created_at, updated_at, is_active, deleted_at, blob_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#),
+        )
+        .bind(group.id)
+        .bind(group.creator_id)
+        .bind(group.epoch)
+        .bind(
+            group
+                .state
+                .as_deref()
+                .map(|state| encoder.encode(state))
+                .transpose()?,
+        )
+        .bind(group.mls_group_id)
+        .bind(group.tenant_id)
+        .bind(group.handle)
+        .bind(group.created_at)
+        .bind(group.updated_at)
+        .bind(group.is_active)
+        .bind(group.deleted_at)
+        .bind(encoder.version())
+        .execute(&mut *tx)
+        .await
+        .map_err(group_insert_error)?;
+
+        for membership in members {
+            sqlx::query(&self.sql(
+                r#"
+                INSERT INTO {memberships}
+                (id, client_id, group_id, role, added_at, removed_at, last_acked_epoch, last_acked_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                "#,
+            ))
+            .bind(membership.id)
+            .bind(membership.client_id)
+            .bind(membership.group_id)
+            .bind(membership.role)
+            .bind(membership.added_at)
+            .bind(membership.removed_at)
+            .bind(membership.last_acked_epoch)
+            .bind(membership.last_acked_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(write_error)?;
+        }
+        self.announce_membership_change(&mut *tx, MembershipChange::Group(group.id))
+            .await?;
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get_group(&self, group_id: Uuid) -> DbResult<Group> {
+        let group = sqlx::query_as::<_, Versioned<Group>>(&self.sql(
+            r#"
+            SELECT * FROM {groups}
+            WHERE id = $1
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(group_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+
+        self.decode_row(group).await
+    }
+
+    async fn get_group_by_handle(&self, tenant_id: Uuid, handle: &str) -> DbResult<Group> {
+        let group = sqlx::query_as::<_, Versioned<Group>>(&self.sql(
+            r#"
+            SELECT * FROM {groups}
+            WHERE tenant_id = $1
+              AND handle = $2
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(tenant_id)
+        .bind(handle)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+
+        self.decode_row(group).await
+    }
+
+    async fn get_group_by_mls_group_id(&self, mls_group_id: &[u8]) -> DbResult<Group> {
+        // Served by the unique index on mls_group_id
+        let group = sqlx::query_as::<_, Versioned<Group>>(&self.sql(
+            r#"
+            SELECT * FROM {groups}
+            WHERE mls_group_id = $1
+              AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(mls_group_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+
+        self.decode_row(group).await
+    }
+
+    async fn list_groups_by_client(
+        &self,
+        client_id: Uuid,
+        include_state: bool,
+    ) -> DbResult<Vec<Group>> {
+        // Leave the state blob out of the projection unless asked for
+        let groups = sqlx::query_as::<_, Versioned<Group>>(&self.sql(
+            r#"
+            SELECT g.id, g.creator_id, g.epoch, CASE WHEN $2 THEN g.state END AS state,
+                   g.mls_group_id, g.tenant_id, g.handle, g.created_at, g.updated_at,
+                   g.is_active, g.deleted_at, g.blob_version
+            FROM {groups} g
+            JOIN {memberships} m ON g.id = m.group_id
+            WHERE m.client_id = $1
+              AND m.removed_at IS NULL
+              AND g.is_active = true
+              AND g.deleted_at IS NULL
+            ORDER BY g.updated_at DESC
+            "#,
+        ))
+        .bind(client_id)
+        .bind(include_state)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        self.decode_rows(groups).await
+    }
+
+    async fn list_groups(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Group>> {
+        let sql = self.sql(&format!(
+            r#"
+            SELECT id, creator_id, epoch, NULL::bytea AS state, mls_group_id, tenant_id, handle,
+                   created_at, updated_at, is_active, deleted_at
+            FROM {{groups}}
+            WHERE {}
+            ORDER BY created_at DESC, id DESC
+            LIMIT ${}
+            "#,
+            filter.to_sql(1),
+            filter.param_count() + 1
+        ));
+        let groups = filter
+            .bind(sqlx::query_as::<_, Group>(&sql))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(groups)
+    }
+
+    async fn list_active_groups(&self, since: DateTime<Utc>, limit: i64) -> DbResult<Vec<Group>> {
+        let groups = sqlx::query_as::<_, Group>(&self.sql(
+            r#"
+            SELECT g.id, g.creator_id, g.epoch, NULL::bytea AS state, g.mls_group_id, g.tenant_id,
+                   g.handle, g.created_at, g.updated_at, g.is_active, g.deleted_at
+            FROM {groups} g
+            JOIN (
+                SELECT group_id, MAX(created_at) AS last_message_at
+                FROM {messages}
+                WHERE created_at >= $1
+                  AND group_id IS NOT NULL
+                GROUP BY group_id
+            ) recent ON recent.group_id = g.id
+            WHERE g.deleted_at IS NULL
+            ORDER BY recent.last_message_at DESC
+            LIMIT $2
+            "#,
+        ))
+        .bind(since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(groups)
+    }
+
+    async fn update_group_epoch(&self, group_id: Uuid, epoch: i64) -> DbResult<()> {
+        let now = timestamps::now();
+
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {groups}
+            SET epoch = $1, updated_at = $2, epoch_started_at = $2
+            WHERE id = $3
+            "#,
+        ))
+        .bind(epoch)
+        .bind(now)
+        .bind(group_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn update_group_state(&self, group_id: Uuid, state: Vec<u8>) -> DbResult<()> {
+        let now = timestamps::now();
+        let encoder = self
+            .blob_encoder(self.group_tenant(Some(group_id)).await?)
+            .await?;
+
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {groups}
+            SET state = $1, updated_at = $2, blob_version = $4
+            WHERE id = $3
+            "#,
+        ))
+        .bind(encoder.encode(&state)?)
+        .bind(now)
+        .bind(group_id)
+        .bind(encoder.version())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn set_group_active(&self, group_id: Uuid, active: bool) -> DbResult<bool> {
+        let result = sqlx::query(&self.sql(
+            r#"
+            UPDATE {groups}
+            SET is_active = $1, updated_at = $2
+            WHERE id = $3 AND is_active <> $1
+            "#,
+        ))
+        .bind(active)
+        .bind(timestamps::now())
+        .bind(group_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            // Tell an unchanged group from a missing one
+            self.get_group(group_id).await?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    async fn set_group_max_epoch_age(
+        &self,
+        group_id: Uuid,
+        max_epoch_age_secs: Option<i64>,
+    ) -> DbResult<()> {
+        let result = sqlx::query(&self.sql(
+            r#"
+            UPDATE {groups}
+            SET max_epoch_age_secs = $1
+            WHERE id = $2 AND deleted_at IS NULL
+            "#,
+        ))
+        .bind(max_epoch_age_secs)
+        .bind(group_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_group_max_epoch_age(&self, group_id: Uuid) -> DbResult<Option<i64>> {
+        sqlx::query_scalar::<_, Option<i64>>(
+            &self.sql(
+                "SELECT max_epoch_age_secs FROM {groups} WHERE id = $1 AND deleted_at IS NULL",
+            ),
+        )
+        .bind(group_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)
+    }
+
+    async fn list_stale_epoch_groups(
+        &self,
+        default_max_age_secs: i64,
+        now: DateTime<Utc>,
+    ) -> DbResult<Vec<StaleEpochGroup>> {
+        let stale = sqlx::query_as::<_, StaleEpochGroup>(&self.sql(
+            r#"
+            SELECT * FROM (
+                SELECT id AS group_id, epoch,
+                       COALESCE(epoch_started_at, created_at) AS epoch_started_at,
+                       COALESCE(max_epoch_age_secs, $1) AS max_epoch_age_secs,
+                       rotation_reminded_epoch
+                FROM {groups}
+                WHERE deleted_at IS NULL AND is_active = true
+            ) g
+            WHERE g.max_epoch_age_secs > 0
+              AND g.rotation_reminded_epoch IS DISTINCT FROM g.epoch
+              AND g.epoch_started_at < $2 - g.max_epoch_age_secs * interval '1 second'
+            ORDER BY g.epoch_started_at ASC
+            "#,
+        ))
+        .bind(default_max_age_secs)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(stale)
+    }
+
+    async fn mark_rotation_reminded(&self, group_id: Uuid, epoch: i64) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {groups}
+            SET rotation_reminded_epoch = $1
+            WHERE id = $2
+            "#,
+        ))
+        .bind(epoch)
+        .bind(group_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn store_group_info(&self, group_info: GroupInfo) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {group_info} (group_id, epoch, data, publisher_id, published_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (group_id, epoch) DO UPDATE
+            SET data = EXCLUDED.data, publisher_id = EXCLUDED.publisher_id,
+                published_at = EXCLUDED.published_at
+            "#,
+        ))
+        .bind(group_info.group_id)
+        .bind(group_info.epoch)
+        .bind(group_info.data)
+        .bind(group_info.publisher_id)
+        .bind(group_info.published_at)
+        .execute(&self.pool)
+        .await
+        .map_err(write_error)?;
+
+        Ok(())
+    }
+
+    async fn get_group_info(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<GroupInfo> {
+        sqlx::query_as::<_, GroupInfo>(&self.sql(
+            r#"
+            SELECT * FROM {group_info}
+            WHERE group_id = $1
+              AND ($2::BIGINT IS NULL OR epoch = $2)
+            ORDER BY epoch DESC
+            LIMIT 1
+            "#,
+        ))
+        .bind(group_id)
+        .bind(epoch)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)
+    }
+
+    async fn store_ratchet_tree(&self, tree: RatchetTree) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {ratchet_trees} (group_id, epoch, data, uploader_id, uploaded_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (group_id, epoch) DO UPDATE
+            SET data = EXCLUDED.data, uploader_id = EXCLUDED.uploader_id,
+                uploaded_at = EXCLUDED.uploaded_at
+            "#,
+        ))
+        .bind(tree.group_id)
+        .bind(tree.epoch)
+        .bind(tree.data)
+        .bind(tree.uploader_id)
+        .bind(tree.uploaded_at)
+        .execute(&self.pool)
+        .await
+        .map_err(write_error)?;
+
+        Ok(())
+    }
+
+    async fn get_ratchet_tree(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<RatchetTree> {
+        sqlx::query_as::<_, RatchetTree>(&self.sql(
+            r#"
+            SELECT * FROM {ratchet_trees}
+            WHERE group_id = $1
+              AND ($2::BIGINT IS NULL OR epoch = $2)
+            ORDER BY epoch DESC
+            LIMIT 1
+            "#,
+        ))
+        .bind(group_id)
+        .bind(epoch)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)
+    }
+
+    // Membership operations
+    async fn add_membership(&self, membership: Membership) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {memberships}
+            (id, client_id, group_id, role, added_at, removed_at, last_acked_epoch, last_acked_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        ))
+        .bind(membership.id)
+        .bind(membership.client_id)
+        .bind(membership.group_id)
+        .bind(membership.role)
+        .bind(membership.added_at)
+        .bind(membership.removed_at)
+        .bind(membership.last_acked_epoch)
+        .bind(membership.last_acked_at)
+        .execute(&self.pool)
+        .await
+        .map_err(write_error)?;
+        self.announce_membership_change(&self.pool, MembershipChange::Group(membership.group_id))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_membership(&self, membership_id: Uuid) -> DbResult<()> {
+        let now = timestamps::now();
+
+        let group_id: Option<Uuid> = sqlx::query_scalar(&self.sql(
+            r#"
+            UPDATE {memberships}
+            SET removed_at = $1
+            WHERE id = $2
+            RETURNING group_id
+            "#,
+        ))
+        .bind(now)
+        .bind(membership_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+        if let Some(group_id) = group_id {
+            self.announce_membership_change(&self.pool, MembershipChange::Group(group_id))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn list_memberships_by_group(&self, group_id: Uuid) -> DbResult<Vec<Membership>> {
+        let memberships = sqlx::query_as::<_, Membership>(&self.sql(
+            r#"
+            SELECT * FROM {memberships}
+            WHERE group_id = $1
+              AND removed_at IS NULL
+            "#,
+        ))
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(memberships)
+    }
+
+    async fn list_memberships_by_client(&self, client_id: Uuid) -> DbResult<Vec<Membership>> {
+        let memberships = sqlx::query_as::<_, Membership>(&self.sql(
+            r#"
+            SELECT * FROM {memberships}
+            WHERE client_id = $1
+              AND removed_at IS NULL
+            "#,
+        ))
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(memberships)
+    }
+
+    async fn list_memberships_by_groups(&self, group_ids: &[Uuid]) -> DbResult<Vec<Membership>> {
+        let memberships = sqlx::query_as::<_, Membership>(&self.sql(
+            r#"
+            SELECT * FROM {memberships}
+            WHERE group_id = ANY($1)
+              AND removed_at IS NULL
+            "#,
+        ))
+        .bind(group_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(memberships)
+    }
+
+    async fn get_membership(&self, membership_id: Uuid) -> DbResult<Membership> {
+        // Removed memberships are still returned, for audit
+        let membership = sqlx::query_as::<_, Membership>(&self.sql(
+            r#"
+            SELECT * FROM {memberships}
+            WHERE id = $1
+            "#,
+        ))
+        .bind(membership_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+
+        Ok(membership)
+    }
+
+    async fn list_membership_history(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<Membership>> {
+        // Every add/remove cycle, including removed memberships, oldest first
+        let memberships = sqlx::query_as::<_, Membership>(&self.sql(
+            r#"
+            SELECT * FROM {memberships}
+            WHERE client_id = $1
+              AND ($2::uuid IS NULL OR group_id = $2)
+            ORDER BY added_at ASC
+            "#,
+        ))
+        .bind(client_id)
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(memberships)
+    }
+
+    async fn acknowledge_epoch(&self, group_id: Uuid, client_id: Uuid, epoch: i64) -> DbResult<()> {
+        let now = timestamps::now();
+
+        // Only ever move the acknowledged epoch forward
+        let result = sqlx::query(&self.sql(
+            r#"
+            UPDATE {memberships}
+            SET last_acked_epoch = GREATEST(COALESCE(last_acked_epoch, 0), $1),
+                last_acked_at = $2,
+                escalated_at = NULL
+            WHERE group_id = $3
+              AND client_id = $4
+              AND removed_at IS NULL
+            "#,
+        ))
+        .bind(epoch)
+        .bind(now)
+        .bind(group_id)
+        .bind(client_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn list_stuck_memberships(
+        &self,
+        max_epochs_behind: i64,
+        idle_since: DateTime<Utc>,
+    ) -> DbResult<Vec<StuckMembership>> {
+        // Behind by too many epochs, or behind and silent for too long
+        let stuck = sqlx::query_as::<_, StuckMembership>(&self.sql(
+            r#"
+            SELECT m.*, g.epoch AS group_epoch FROM {memberships} m
+            JOIN {groups} g ON g.id = m.group_id
+            WHERE m.removed_at IS NULL
+              AND m.escalated_at IS NULL
+              AND g.is_active = true
+              AND g.epoch > COALESCE(m.last_acked_epoch, 0)
+              AND (
+                g.epoch - COALESCE(m.last_acked_epoch, 0) > $1
+                OR COALESCE(m.last_acked_at, m.added_at) < $2
+              )
+            "#,
+        ))
+        .bind(max_epochs_behind)
+        .bind(idle_since)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(stuck)
+    }
+
+    async fn mark_membership_escalated(&self, membership_id: Uuid) -> DbResult<()> {
+        let now = timestamps::now();
+
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {memberships}
+            SET escalated_at = $1
+            WHERE id = $2
+            "#,
+        ))
+        .bind(now)
+        .bind(membership_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn mark_welcome_requested(&self, membership_id: Uuid) -> DbResult<bool> {
+        let now = timestamps::now();
+
+        let result = sqlx::query(&self.sql(
+            r#"
+            UPDATE {memberships}
+            SET welcome_requested_at = $1
+            WHERE id = $2 AND welcome_requested_at IS NULL
+            "#,
+        ))
+        .bind(now)
+        .bind(membership_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn clear_welcome_requests(&self, group_id: Uuid, client_ids: &[Uuid]) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {memberships}
+            SET welcome_requested_at = NULL, welcome_reminders = 0,
+                welcome_reminded_at = NULL, welcome_escalated_at = NULL
+            WHERE group_id = $1
+              AND client_id = ANY($2)
+            "#,
+        ))
+        .bind(group_id)
+        .bind(client_ids)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_overdue_welcomes(
+        &self,
+        stored_before: DateTime<Utc>,
+    ) -> DbResult<Vec<OverdueWelcome>> {
+        // A newer welcome replaces an older one, so only the newest per membership counts
+        let overdue = sqlx::query_as::<_, OverdueWelcome>(&self.sql(
+            r#"
+            SELECT w.* FROM (
+                SELECT DISTINCT ON (ms.id)
+                       ms.id AS membership_id, ms.group_id, ms.client_id,
+                       m.id AS message_id, m.created_at AS stored_at, m.sequence,
+                       ms.welcome_reminders AS reminders,
+                       ms.welcome_reminded_at AS last_reminded_at
+                FROM {messages} m
+                JOIN {memberships} ms
+                  ON ms.group_id = m.group_id AND ms.client_id = ANY(m.recipients)
+                JOIN {groups} g ON g.id = m.group_id
+                WHERE m.message_type = 'welcome'
+                  AND m.deleted_at IS NULL
+                  AND ms.removed_at IS NULL
+                  AND ms.welcome_escalated_at IS NULL
+                  AND g.deleted_at IS NULL
+                ORDER BY ms.id, m.sequence DESC
+            ) w
+            LEFT JOIN {delivery_cursors} c
+              ON c.client_id = w.client_id AND c.group_id = w.group_id
+            WHERE w.stored_at < $1
+              AND w.sequence > COALESCE(c.sequence, 0)
+            ORDER BY w.stored_at ASC
+            "#,
+        ))
+        .bind(stored_before)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(overdue)
+    }
+
+    async fn record_welcome_reminder(
+        &self,
+        membership_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {memberships}
+            SET welcome_reminders = welcome_reminders + 1, welcome_reminded_at = $1
+            WHERE id = $2
+            "#,
+        ))
+        .bind(at)
+        .bind(membership_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn mark_welcome_escalated(&self, membership_id: Uuid) -> DbResult<()> {
+        let now = timestamps::now();
+
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {memberships}
+            SET welcome_escalated_at = $1
+            WHERE id = $2
+            "#,
+        ))
+        .bind(now)
+        .bind(membership_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    // Deletion lifecycle operations
+    async fn soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        let now = timestamps::now();
+        let column = kind.deleted_column();
+
+        // Keep the original timestamp if the entity is already soft-deleted
+        let mut query = format!(
+            "UPDATE {} SET {} = COALESCE({}, $1)",
+            self.table(kind.table()),
+            column,
+            column
+        );
+        if kind == EntityKind::Group {
+            query.push_str(", is_active = false");
+        }
+        query.push_str(" WHERE id = $2");
+
+        let result = sqlx::query(&query)
+            .bind(now)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn restore_deleted(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        let column = kind.deleted_column();
+
+        let mut query = format!("UPDATE {} SET {} = NULL", self.table(kind.table()), column);
+        if kind == EntityKind::Group {
+            query.push_str(", is_active = true");
+        }
+        query.push_str(&format!(" WHERE id = $1 AND {} IS NOT NULL", column));
+
+        let result = sqlx::query(&query)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn purge_entity(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        // Only soft-deleted entities can be purged
+        let selector = format!(
+            "SELECT id FROM {} WHERE id = $1 AND {} IS NOT NULL",
+            self.table(kind.table()),
+            kind.deleted_column()
+        );
+
+        for (table, column) in kind.dependents() {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} IN ({})",
+                self.table(table),
+                column,
+                selector
+            ))
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        let result = sqlx::query(&format!(
+            "DELETE FROM {} WHERE id IN ({})",
+            self.table(kind.table()),
+            selector
+        ))
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+        if let Some(change) = MembershipChange::of_purged(kind, id) {
+            self.announce_membership_change(&mut *tx, change).await?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn purge_deleted_before(&self, kind: EntityKind, cutoff: DateTime<Utc>) -> DbResult<u64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        // Entities whose grace period has elapsed
+        let selector = format!(
+            "SELECT id FROM {} WHERE {} < $1",
+            self.table(kind.table()),
+            kind.deleted_column()
+        );
+
+        for (table, column) in kind.dependents() {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} IN ({})",
+                self.table(table),
+                column,
+                selector
+            ))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        let result = sqlx::query(&format!(
+            "DELETE FROM {} WHERE id IN ({})",
+            self.table(kind.table()),
+            selector
+        ))
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+        // Which entities were purged isn't known here, so every membership may have changed
+        if result.rows_affected() > 0 && matches!(kind, EntityKind::Client | EntityKind::Group) {
+            self.announce_membership_change(&mut *tx, MembershipChange::All)
+                .await?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn preview_soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>> {
+        let rows: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM {} WHERE id = $1",
+            self.table(kind.table())
+        ))
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        if rows == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        Ok(vec![AffectedRows {
+            table: kind.table(),
+            rows,
+        }])
+    }
+
+    async fn preview_purge(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>> {
+        // Count what purge_entity's statements would delete, in the same order
+        let selector = format!(
+            "SELECT id FROM {} WHERE id = $1 AND {} IS NOT NULL",
+            self.table(kind.table()),
+            kind.deleted_column()
+        );
+
+        let mut affected = Vec::new();
+        for (table, column) in kind
+            .dependents()
+            .iter()
+            .copied()
+            .chain([(kind.table(), "id")])
+        {
+            let rows: i64 = sqlx::query_scalar(&format!(
+                "SELECT COUNT(*) FROM {} WHERE {} IN ({})",
+                self.table(table),
+                column,
+                selector
+            ))
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+            affected.push(AffectedRows { table, rows });
+        }
+
+        if affected.last().is_some_and(|entity| entity.rows == 0) {
+            return Err(DbError::NotFound);
+        }
+
+        Ok(affected)
+    }
+
+    // Message operations
+    async fn store_message(&self, message: Message) -> DbResult<Message> {
+        self.insert_message(&self.pool, message).await
+    }
+
+    async fn store_proposal(&self, message: Message) -> DbResult<Message> {
+        let group_id = message.group_id.ok_or(DbError::MissingReference("Group"))?;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        // Share-lock the group so a commit can't advance the epoch before the proposal is queued
+        let epoch = sqlx::query_scalar::<_, i64>(&self.sql(
+            r#"
+            SELECT epoch FROM {groups}
+            WHERE id = $1
+            FOR SHARE
+            "#,
+        ))
+        .bind(group_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::MissingReference("Group"))?;
+
+        let message = Message {
+            epoch: Some(epoch),
+            ..message.with_payload_hash()
+        };
+        let message = self.insert_message(&mut *tx, message).await?;
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {proposal_refs} (group_id, proposal_ref, epoch, message_id, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        ))
+        .bind(group_id)
+        .bind(&message.payload_hash)
+        .bind(epoch)
+        .bind(message.id)
+        .bind(message.created_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => {
+                DbError::Conflict("Proposal is already queued".to_string())
+            }
+            _ => DbError::QueryError(e.to_string()),
+        })?;
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(message)
+    }
+
+    async fn list_pending_proposals(&self, group_id: Uuid) -> DbResult<Vec<QueuedProposal>> {
+        sqlx::query_as::<_, QueuedProposal>(&self.sql(
+            r#"
+            SELECT p.proposal_ref, p.group_id, p.epoch, p.message_id, m.sender_id,
+                   m.proposal_type, p.created_at, p.committed_by
+            FROM {proposal_refs} p
+            JOIN {groups} g ON g.id = p.group_id
+            JOIN {messages} m ON m.id = p.message_id
+            WHERE p.group_id = $1
+              AND p.epoch = g.epoch
+              AND p.committed_by IS NULL
+              AND m.deleted_at IS NULL
+            ORDER BY p.created_at, p.message_id
+            "#,
+        ))
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))
+    }
+
+    async fn store_commit(
+        &self,
+        message: Message,
+        epoch: i64,
+        proposal_refs: &[Vec<u8>],
+    ) -> DbResult<Message> {
+        let group_id = message.group_id.ok_or(DbError::MissingReference("Group"))?;
+        let message_id = message.id;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        // Lock the group row so concurrent commits for the same epoch are serialized
+        let current_epoch = sqlx::query_scalar::<_, i64>(&self.sql(
+            r#"
+            SELECT epoch FROM {groups}
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+        ))
+        .bind(group_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::MissingReference("Group"))?;
+        if epoch != current_epoch + 1 {
+            return Err(DbError::StaleEpoch {
+                epoch,
+                current_epoch,
+            });
+        }
+
+        for proposal_ref in proposal_refs {
+            let queued = sqlx::query_as::<_, (i64, Option<Uuid>)>(&self.sql(
+                r#"
+                SELECT p.epoch, p.committed_by
+                FROM {proposal_refs} p
+                JOIN {messages} m ON m.id = p.message_id
+                WHERE p.group_id = $1
+                  AND p.proposal_ref = $2
+                  AND m.deleted_at IS NULL
+                "#,
+            ))
+            .bind(group_id)
+            .bind(proposal_ref)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+            check_proposal_pending(proposal_ref, queued, current_epoch)?;
+        }
+
+        let message = self.insert_message(&mut *tx, message).await?;
+        sqlx::query(&self.sql(
+            r#"
+            UPDATE {groups}
+            SET epoch = $1, updated_at = $2, epoch_started_at = $2
+            WHERE id = $3
+            "#,
+        ))
+        .bind(epoch)
+        .bind(timestamps::now())
+        .bind(group_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+        if !proposal_refs.is_empty() {
+            sqlx::query(&self.sql(
+                r#"
+                UPDATE {proposal_refs}
+                SET committed_by = $1
+                WHERE group_id = $2 AND proposal_ref = ANY($3)
+                "#,
+            ))
+            .bind(message_id)
+            .bind(group_id)
+            .bind(proposal_refs)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(message)
+    }
+
+    async fn fetch_messages_for_client(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
+    ) -> DbResult<Vec<Message>> {
+        let mut sql = self.sql(match (group_id, include_acked) {
+            (Some(_), true) => {
+                r#"
+                SELECT m.* FROM {messages} m
+                WHERE EXISTS (
+                    SELECT 1 FROM {memberships} mem
+                    WHERE mem.group_id = m.group_id AND mem.client_id = $1
+                  )
+                  AND m.deleted_at IS NULL
+                  AND (m.expires_at IS NULL OR m.expires_at > now())
+                  AND m.group_id = $2
+                  AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+                  AND (cardinality($3::message_type[]) = 0 OR m.message_type = ANY($3))
+                ORDER BY m.created_at ASC, m.id ASC
+                "#
+            }
+            (Some(_), false) => {
+                r#"
+                SELECT m.* FROM {messages} m
+                WHERE EXISTS (
+                    SELECT 1 FROM {memberships} mem
+                    WHERE mem.group_id = m.group_id AND mem.client_id = $1
+                  )
+                  AND m.deleted_at IS NULL
+                  AND (m.expires_at IS NULL OR m.expires_at > now())
+                  AND m.group_id = $2
+                  AND NOT EXISTS (
+                    SELECT 1 FROM {delivery_cursors} c
+                    WHERE c.client_id = $1
+                      AND c.group_id = COALESCE(m.group_id, '00000000-0000-0000-0000-000000000000')
+                      AND m.sequence <= c.sequence
+                  )
+                  AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+                  AND (cardinality($3::message_type[]) = 0 OR m.message_type = ANY($3))
+                ORDER BY m.created_at ASC, m.id ASC
+                "#
+            }
+            (None, true) => {
+                r#"
+                SELECT m.* FROM {messages} m
+                WHERE (
+                    m.group_id IN (SELECT group_id FROM {memberships} WHERE client_id = $1)
+                    OR (m.group_id IS NULL AND $1 = ANY(m.recipients))
+                  )
+                  AND m.deleted_at IS NULL
+                  AND (m.expires_at IS NULL OR m.expires_at > now())
+                  AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+                  AND (cardinality($2::message_type[]) = 0 OR m.message_type = ANY($2))
+                ORDER BY m.created_at ASC, m.id ASC
+                "#
+            }
+            (None, false) => {
+                r#"
+                SELECT m.* FROM {messages} m
+                WHERE (
+                    m.group_id IN (SELECT group_id FROM {memberships} WHERE client_id = $1)
+                    OR (m.group_id IS NULL AND $1 = ANY(m.recipients))
+                  )
+                  AND m.deleted_at IS NULL
+                  AND (m.expires_at IS NULL OR m.expires_at > now())
+                  AND NOT EXISTS (
+                    SELECT 1 FROM {delivery_cursors} c
+                    WHERE c.client_id = $1
+                      AND c.group_id = COALESCE(m.group_id, '00000000-0000-0000-0000-000000000000')
+                      AND m.sequence <= c.sequence
+                  )
+                  AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+                  AND (cardinality($2::message_type[]) = 0 OR m.message_type = ANY($2))
+                ORDER BY m.created_at ASC, m.id ASC
+                "#
+            }
+        });
+
+        // Leave the content columns out of the projection unless asked for
+        if !include_payload {
+            sql = sql.replacen("m.*", MESSAGE_METADATA_COLUMNS, 1);
+        }
+
+        // The group filter is only bound when the query has one
+        let query = sqlx::query_as::<_, Versioned<Message>>(&sql).bind(client_id);
+        let query = match group_id {
+            Some(g_id) => query.bind(g_id),
+            None => query,
+        };
+
+        let messages = query
+            .bind(message_types)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(in_delivery_order(self.decode_rows(messages).await?))
+    }
+
+    async fn fetch_addressed_messages(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        message_ids: &[Uuid],
+    ) -> DbResult<Vec<Message>> {
+        let sql = self.sql(
+            r#"
+            SELECT m.* FROM {messages} m
+            WHERE m.id = ANY($3)
+              AND ($2::uuid IS NULL OR m.group_id = $2)
+              AND (
+                m.group_id IN (SELECT group_id FROM {memberships} WHERE client_id = $1)
+                OR (m.group_id IS NULL AND $1 = ANY(m.recipients))
+              )
+              AND m.deleted_at IS NULL
+              AND (m.expires_at IS NULL OR m.expires_at > now())
+              AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+            "#,
+        );
+        let messages = sqlx::query_as::<_, Versioned<Message>>(&sql.replacen(
+            "m.*",
+            MESSAGE_METADATA_COLUMNS,
+            1,
+        ))
+        .bind(client_id)
+        .bind(group_id)
+        .bind(message_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        self.decode_rows(messages).await
+    }
+
+    async fn fetch_messages_after(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        after: &[DeliveryCursor],
+        limit: i64,
+    ) -> DbResult<Vec<Message>> {
+        let (groups, sequences): (Vec<Uuid>, Vec<i64>) = after
+            .iter()
+            .map(|c| (c.group_id.unwrap_or_default(), c.sequence))
+            .unzip();
+        let messages = sqlx::query_as::<_, Versioned<Message>>(&self.sql(
+            r#"
+            SELECT m.* FROM {messages} m
+            LEFT JOIN UNNEST($3::uuid[], $4::bigint[]) AS after (group_id, sequence)
+              ON after.group_id = COALESCE(m.group_id, '00000000-0000-0000-0000-000000000000')
+            LEFT JOIN {delivery_cursors} c
+              ON c.client_id = $1
+             AND c.group_id = COALESCE(m.group_id, '00000000-0000-0000-0000-000000000000')
+            WHERE (
+                m.group_id IN (SELECT group_id FROM {memberships} WHERE client_id = $1)
+                OR (m.group_id IS NULL AND $1 = ANY(m.recipients))
+              )
+              AND ($2::uuid IS NULL OR m.group_id = $2)
+              AND m.deleted_at IS NULL
+              AND (m.expires_at IS NULL OR m.expires_at > now())
+              AND m.sequence > COALESCE(after.sequence, c.sequence, 0)
+              AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+            ORDER BY m.created_at ASC, m.id ASC
+            LIMIT $5
+            "#,
+        ))
+        .bind(client_id)
+        .bind(group_id)
+        .bind(groups)
+        .bind(sequences)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(in_delivery_order(self.decode_rows(messages).await?))
+    }
+
+    async fn last_message_sequences(
+        &self,
+        group_ids: &[Option<Uuid>],
+    ) -> DbResult<Vec<DeliveryCursor>> {
+        let ids: Vec<Uuid> = group_ids.iter().map(|g| g.unwrap_or_default()).collect();
+        let rows: Vec<(Uuid, i64)> = sqlx::query_as(&self.sql(
+            r#"
+            SELECT ids.group_id, COALESCE(s.last_sequence, 0)
+            FROM UNNEST($1::uuid[]) AS ids (group_id)
+            LEFT JOIN {message_sequences} s ON s.group_id = ids.group_id
+            "#,
+        ))
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(group_id, sequence)| DeliveryCursor {
+                group_id: (!group_id.is_nil()).then_some(group_id),
+                sequence,
+            })
+            .collect())
+    }
+
+    async fn fetch_messages_for_member(
+        &self,
+        client_id: Uuid,
+        group_id: Uuid,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
+    ) -> DbResult<Vec<Message>> {
+        let mut sql = self.sql(match include_acked {
+            true => {
+                r#"
+                SELECT m.* FROM {messages} m
+                WHERE m.group_id = $2
+                  AND m.deleted_at IS NULL
+                  AND (m.expires_at IS NULL OR m.expires_at > now())
+                  AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+                  AND (cardinality($3::message_type[]) = 0 OR m.message_type = ANY($3))
+                ORDER BY m.created_at ASC, m.id ASC
+                "#
+            }
+            false => {
+                r#"
+                SELECT m.* FROM {messages} m
+                WHERE m.group_id = $2
+                  AND m.deleted_at IS NULL
+                  AND (m.expires_at IS NULL OR m.expires_at > now())
+                  AND NOT EXISTS (
+                    SELECT 1 FROM {delivery_cursors} c
+                    WHERE c.client_id = $1
+                      AND c.group_id = $2
+                      AND m.sequence <= c.sequence
+                  )
+                  AND ((m.recipients IS NULL AND m.message_type <> 'system') OR $1 = ANY(m.recipients))
+                  AND (cardinality($3::message_type[]) = 0 OR m.message_type = ANY($3))
+                ORDER BY m.created_at ASC, m.id ASC
+                "#
+            }
+        });
+
+        // Leave the content columns out of the projection unless asked for
+        if !include_payload {
+            sql = sql.replacen("m.*", MESSAGE_METADATA_COLUMNS, 1);
+        }
+
+        let messages = sqlx::query_as::<_, Versioned<Message>>(&sql)
+            .bind(client_id)
+            .bind(group_id)
+            .bind(message_types)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(in_delivery_order(self.decode_rows(messages).await?))
+    }
+
+    async fn purge_expired_messages(&self, now: DateTime<Utc>) -> DbResult<u64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        let selector = self.sql("SELECT id FROM {messages} WHERE expires_at <= $1");
+        for (table, column) in EntityKind::Message.dependents() {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} IN ({})",
+                self.table(table),
+                column,
+                selector
+            ))
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        let result = sqlx::query(&self.sql("DELETE FROM {messages} WHERE expires_at <= $1"))
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn purge_retained_messages(
+        &self,
+        message_type: MessageType,
+        before: DateTime<Utc>,
+        delivered_only: bool,
+    ) -> DbResult<u64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        let selector = self.sql(
+            r#"
+            SELECT m.id FROM {messages} m
+            WHERE m.message_type = $1
+              AND m.created_at < $2
+              AND (NOT $3 OR NOT EXISTS (
+                SELECT 1
+                FROM unnest(COALESCE(
+                    m.recipients,
+                    ARRAY(
+                        SELECT client_id FROM {memberships}
+                        WHERE group_id = m.group_id AND removed_at IS NULL
+                    )
+                )) AS r(client_id)
+                JOIN {clients} cl ON cl.id = r.client_id AND cl.deleted_at IS NULL
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM {delivery_cursors} c
+                    WHERE c.client_id = r.client_id
+                      AND c.group_id = COALESCE(m.group_id, '00000000-0000-0000-0000-000000000000')
+                      AND m.sequence <= c.sequence
+                )
+              ))
+            "#,
+        );
+        for (table, column) in EntityKind::Message.dependents() {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} IN ({})",
+                self.table(table),
+                column,
+                selector
+            ))
+            .bind(message_type)
+            .bind(before)
+            .bind(delivered_only)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        let result = sqlx::query(&format!(
+            "DELETE FROM {} WHERE id IN ({})",
+            self.table("messages"),
+            selector
+        ))
+        .bind(message_type)
+        .bind(before)
+        .bind(delivered_only)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn evict_application_messages(&self, group_id: Uuid, keep: i64) -> DbResult<u64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        // Lock the group so concurrent stores don't each evict the same overflow
+        sqlx::query(&self.sql("SELECT id FROM {groups} WHERE id = $1 FOR UPDATE"))
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        // Each active member's queue: the application messages to it past its delivery cursor,
+        // numbered from the newest
+        let queues = self.sql(
+            r#"
+            WITH queued AS (
+                SELECT m.id, mem.id AS membership_id,
+                       ROW_NUMBER() OVER (PARTITION BY mem.id ORDER BY m.sequence DESC) AS position
+                FROM {memberships} mem
+                JOIN {messages} m ON m.group_id = mem.group_id
+                LEFT JOIN {delivery_cursors} c
+                  ON c.client_id = mem.client_id AND c.group_id = mem.group_id
+                WHERE mem.group_id = $1
+                  AND mem.removed_at IS NULL
+                  AND m.message_type = 'application'
+                  AND m.deleted_at IS NULL
+                  AND (m.recipients IS NULL OR mem.client_id = ANY(m.recipients))
+                  AND m.sequence > COALESCE(c.sequence, 0)
+            )
+            "#,
+        );
+
+        // A message goes once it's past the cap of every member still waiting for it
+        let ids: Vec<Uuid> = sqlx::query_scalar(&format!(
+            "{} SELECT id FROM queued GROUP BY id HAVING bool_and(position > $2)",
+            queues
+        ))
+        .bind(group_id)
+        .bind(keep)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        // Only the members that were still waiting for the messages lost them
+        sqlx::query(&format!(
+            r#"
+            {}
+            UPDATE {} mem
+            SET evicted_messages = mem.evicted_messages + lost.count
+            FROM (
+                SELECT membership_id, COUNT(*) AS count FROM queued
+                WHERE id = ANY($2)
+                GROUP BY membership_id
+            ) lost
+            WHERE mem.id = lost.membership_id
+            "#,
+            queues,
+            self.table("memberships")
+        ))
+        .bind(group_id)
+        .bind(&ids)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        for (table, column) in EntityKind::Message.dependents() {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} = ANY($1)",
+                self.table(table),
+                column
+            ))
+            .bind(&ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+        let evicted = sqlx::query(&format!(
+            "DELETE FROM {} WHERE id = ANY($1)",
+            self.table("messages")
+        ))
+        .bind(&ids)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .rows_affected();
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(evicted)
+    }
+
+    async fn take_queue_truncations(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<QueueTruncation>> {
+        sqlx::query_as::<_, QueueTruncation>(&self.sql(
+            r#"
+            UPDATE {memberships} m
+            SET evicted_messages = 0
+            FROM (
+                SELECT id, evicted_messages FROM {memberships}
+                WHERE client_id = $1
+                  AND evicted_messages > 0
+                  AND ($2::uuid IS NULL OR group_id = $2)
+                FOR UPDATE
+            ) old
+            WHERE m.id = old.id
+            RETURNING m.group_id, old.evicted_messages
+            "#,
+        ))
+        .bind(client_id)
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))
+    }
+
+    async fn mark_messages_read(&self, message_ids: Vec<Uuid>) -> DbResult<()> {
+        // Use a transaction to mark all messages as read
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        for msg_id in &message_ids {
+            sqlx::query(&self.sql(
+                r#"
+                UPDATE {messages}
+                SET read = true
+                WHERE id = $1
+                "#,
+            ))
+            .bind(msg_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn advance_delivery_cursors(
+        &self,
+        client_id: Uuid,
+        cursors: &[DeliveryCursor],
+    ) -> DbResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        for cursor in cursors {
+            sqlx::query(&self.sql(
+                r#"
+                INSERT INTO {delivery_cursors} AS c (client_id, group_id, sequence)
+                VALUES ($1, $2, LEAST($3, COALESCE(
+                    (SELECT last_sequence FROM {message_sequences} WHERE group_id = $2), 0
+                )))
+                ON CONFLICT (client_id, group_id) DO UPDATE
+                SET sequence = EXCLUDED.sequence
+                WHERE c.sequence < EXCLUDED.sequence
+                "#,
+            ))
+            .bind(client_id)
+            .bind(cursor.group_id.unwrap_or_else(Uuid::nil))
+            .bind(cursor.sequence)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn group_storage_stats(
+        &self,
+        group_id: Option<Uuid>,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<GroupStorageStats>> {
+        // Payload sizes leave out what the blob format adds: the version byte, and the tenant
+        // id, nonce and tag of encrypted payloads
+        sqlx::query_as::<_, GroupStorageStats>(&self.sql(&format!(
+            r#"
+            WITH members AS (
+                SELECT group_id, COUNT(*) AS active
+                FROM {{memberships}}
+                WHERE removed_at IS NULL
+                GROUP BY group_id
+            ),
+            sizes AS (
+                SELECT group_id, created_at, recipients,
+                       COALESCE(
+                           octet_length(COALESCE(proposal, commit, welcome, system, application))
+                               - CASE blob_version
+                                     WHEN {current} THEN {current_overhead}
+                                     WHEN {encrypted} THEN {encrypted_overhead}
+                                     ELSE 0
+                                 END,
+                           0
+                       )::BIGINT AS bytes
+                FROM {{messages}}
+                WHERE deleted_at IS NULL
+                  AND group_id IS NOT NULL
+            )
+            SELECT g.id AS group_id,
+                   COUNT(s.group_id) AS messages,
+                   COALESCE(MAX(mb.active), 0) AS active_members,
+                   COALESCE(SUM(s.bytes), 0)::BIGINT AS payload_bytes,
+                   COALESCE(SUM(s.bytes * COALESCE(cardinality(s.recipients), mb.active, 0)), 0)::BIGINT AS delivered_bytes,
+                   COALESCE(SUM(s.bytes) FILTER (WHERE s.created_at >= $2), 0)::BIGINT AS payload_bytes_last_day,
+                   COALESCE(SUM(s.bytes) FILTER (WHERE s.created_at >= $3), 0)::BIGINT AS payload_bytes_last_week
+            FROM {{groups}} g
+            LEFT JOIN members mb ON mb.group_id = g.id
+            LEFT JOIN sizes s ON s.group_id = g.id
+            WHERE g.deleted_at IS NULL
+              AND ($1::UUID IS NULL OR g.id = $1)
+            GROUP BY g.id
+            ORDER BY payload_bytes DESC, g.id
+            LIMIT $4
+            "#,
+            current = CURRENT_BLOB_VERSION,
+            current_overhead = blob::blob_overhead(CURRENT_BLOB_VERSION),
+            encrypted = blob::ENCRYPTED_BLOB_VERSION,
+            encrypted_overhead = blob::blob_overhead(blob::ENCRYPTED_BLOB_VERSION),
+        )))
+        .bind(group_id)
+        .bind(now - chrono::Duration::days(1))
+        .bind(now - chrono::Duration::days(7))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))
+    }
+
+    async fn find_integrity_issues(&self, limit: i64) -> DbResult<Vec<IntegrityIssue>> {
+        let checks = [
+            // Payloads stored since blobs were versioned follow their version byte. Encrypted
+            // payloads are checked by their authentication tag whenever they're read instead.
+            (
+                IntegrityIssueKind::PayloadHashMismatch,
+                r#"
+                SELECT id, group_id AS related_id
+                FROM {messages}
+                WHERE deleted_at IS NULL
+                  AND payload_hash IS NOT NULL
+                  AND blob_version < 2
+                  AND sha256(CASE WHEN blob_version = 0
+                                  THEN COALESCE(proposal, commit, welcome, system, application)
+                                  ELSE substring(COALESCE(proposal, commit, welcome, system, application) FROM 2)
+                             END) IS DISTINCT FROM payload_hash
+                ORDER BY id
+                LIMIT $1
+                "#,
+            ),
+            (
+                IntegrityIssueKind::OrphanedMessage,
+                r#"
+                SELECT m.id, m.group_id AS related_id
+                FROM {messages} m
+                LEFT JOIN {groups} g ON g.id = m.group_id
+                WHERE m.deleted_at IS NULL
+                  AND m.group_id IS NOT NULL
+                  AND g.id IS NULL
+                ORDER BY m.id
+                LIMIT $1
+                "#,
+            ),
+            (
+                IntegrityIssueKind::OrphanedMembership,
+                r#"
+                SELECT mb.id, mb.client_id AS related_id
+                FROM {memberships} mb
+                LEFT JOIN {clients} c ON c.id = mb.client_id
+                WHERE mb.removed_at IS NULL
+                  AND (c.id IS NULL OR c.deleted_at IS NOT NULL)
+                ORDER BY mb.id
+                LIMIT $1
+                "#,
+            ),
+        ];
+
+        let mut issues = Vec::new();
+        for (kind, query) in checks {
+            let rows = sqlx::query_as::<_, (Uuid, Option<Uuid>)>(&self.sql(query))
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| DbError::QueryError(e.to_string()))?;
+            issues.extend(rows.into_iter().map(|(id, related_id)| IntegrityIssue {
+                kind,
+                id,
+                related_id,
+            }));
+        }
+        Ok(issues)
+    }
+
+    async fn upgrade_blob_formats(&self, limit: i64) -> DbResult<u64> {
+        let mut upgraded = 0;
+        for (table, columns) in VERSIONED_BLOBS {
+            let rows = sqlx::query(&format!(
+                "SELECT id, blob_version, {} FROM {} WHERE blob_version < $1 ORDER BY id LIMIT $2",
+                columns.join(", "),
+                self.table(table)
+            ))
+            .bind(i16::from(CURRENT_BLOB_VERSION))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+            // Only rows still at the version they were read at are rewritten, so blobs written
+            // in the meantime aren't overwritten
+            let assignments: Vec<String> = columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| format!("{} = ${}", column, i + 4))
+                .collect();
+            let update = format!(
+                "UPDATE {} SET {}, blob_version = $2 WHERE id = $1 AND blob_version = $3",
+                self.table(table),
+                assignments.join(", ")
+            );
+
+            for row in rows {
+                let id: Uuid = row
+                    .try_get("id")
+                    .map_err(|e| DbError::SerializationError(e.to_string()))?;
+                let version: i16 = row
+                    .try_get("blob_version")
+                    .map_err(|e| DbError::SerializationError(e.to_string()))?;
+                let mut query = sqlx::query(&update)
+                    .bind(id)
+                    .bind(i16::from(CURRENT_BLOB_VERSION))
+                    .bind(version);
+                for column in columns.iter() {
+                    let stored: Option<Vec<u8>> = row
+                        .try_get(*column)
+                        .map_err(|e| DbError::SerializationError(e.to_string()))?;
+                    query = query.bind(
+                        stored
+                            .map(|stored| blob::upgrade(version, stored))
+                            .transpose()?,
+                    );
+                }
+                let result = query
+                    .execute(&self.pool)
+                    .await
+                    .map_err(|e| DbError::QueryError(e.to_string()))?;
+                upgraded += result.rows_affected();
+            }
+        }
+        Ok(upgraded)
+    }
+
+    async fn destroy_tenant_key(&self, tenant_id: Uuid) -> DbResult<bool> {
+        let Some(encryption) = &self.encryption else {
+            return Ok(false);
+        };
+        self.destroy_key(encryption, tenant_id).await?;
+        Ok(true)
+    }
+
+    async fn store_abuse_report(&self, report: AbuseReport) -> DbResult<()> {
+        sqlx::query(&self.sql(
+            r#"
+            INSERT INTO {abuse_reports} (id, group_id, reporter_id, message_ids, reason, excerpt, created_at, resolved_at, resolution)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        ))
+        .bind(report.id)
+        .bind(report.group_id)
+        .bind(report.reporter_id)
+        .bind(report.message_ids)
+        .bind(report.reason)
+        .bind(report.excerpt)
+        .bind(report.created_at)
+        .bind(report.resolved_at)
+        .bind(report.resolution)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_abuse_reports(&self, filter: &Filter, limit: i64) -> DbResult<Vec<AbuseReport>> {
+        let sql = self.sql(&format!(
+            r#"
+            SELECT * FROM {{abuse_reports}}
+            WHERE {}
+            ORDER BY created_at DESC, id DESC
+            LIMIT ${}
+            "#,
+            filter.to_sql(1),
+            filter.param_count() + 1
+        ));
+        filter
+            .bind(sqlx::query_as::<_, AbuseReport>(&sql))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))
+    }
+
+    async fn resolve_abuse_report(
+        &self,
+        report_id: Uuid,
+        resolution: &str,
+        resolved_at: DateTime<Utc>,
+    ) -> DbResult<()> {
+        // Returns the previous resolution time, so an already resolved report can be told apart
+        // from a missing one
+        let previous = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(&self.sql(
+            r#"
+            UPDATE {abuse_reports} r
+            SET resolved_at = COALESCE(r.resolved_at, $2),
+                resolution = COALESCE(r.resolution, $3)
+            FROM (SELECT id, resolved_at FROM {abuse_reports} WHERE id = $1 FOR UPDATE) old
+            WHERE r.id = old.id
+            RETURNING old.resolved_at
+            "#,
+        ))
+        .bind(report_id)
+        .bind(resolved_at)
+        .bind(resolution)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?;
+
+        match previous {
+            None => Err(DbError::NotFound),
+            Some(Some(_)) => Err(DbError::Conflict(
+                "Abuse report is already resolved".to_string(),
+            )),
+            Some(None) => Ok(()),
+        }
     }
 }
@@ -0,0 +1,121 @@
+use sqlx::postgres::PgConnectOptions;
+use thiserror::Error;
+
+// Tables owned by the service, as written in `{name}` placeholders in queries
+const TABLES: &[&str] = &[
+    "abuse_reports",
+    "clients",
+    "client_backups",
+    "delivery_cursors",
+    "feature_flags",
+    "group_info",
+    "groups",
+    "key_package_claims",
+    "key_packages",
+    "memberships",
+    "message_sequences",
+    "messages",
+    "proposal_refs",
+    "ratchet_trees",
+    "request_nonces",
+    "tenant_keys",
+    "verbose_traces",
+];
+
+// A schema or table prefix that isn't a plain lowercase SQL identifier
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid {field} {value:?}: use lowercase letters, digits and underscores")]
+pub struct InvalidIdentifier {
+    pub field: &'static str,
+    pub value: String,
+}
+
+// Where the service's tables live, so several instances can share a database server.
+// The schema is applied through each connection's search_path, the prefix to every table
+// and index name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DbNamespace {
+    schema: Option<String>,
+    table_prefix: String,
+}
+
+impl DbNamespace {
+    pub fn new(
+        schema: Option<String>,
+        table_prefix: impl Into<String>,
+    ) -> Result<Self, InvalidIdentifier> {
+        let table_prefix = table_prefix.into();
+
+        // Both end up in SQL text, so only allow plain identifiers
+        if let Some(schema) = &schema {
+            check_identifier("schema", schema, false)?;
+        }
+        check_identifier("table prefix", &table_prefix, true)?;
+
+        Ok(Self {
+            schema,
+            table_prefix,
+        })
+    }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    pub fn table_prefix(&self) -> &str {
+        &self.table_prefix
+    }
+
+    // Prefixed name of a table or index
+    pub fn table(&self, name: &str) -> String {
+        format!("{}{}", self.table_prefix, name)
+    }
+
+    // Name of a NOTIFY channel, qualified like the tables so namespaces sharing a server don't
+    // hear each other
+    pub fn channel(&self, name: &str) -> String {
+        match &self.schema {
+            Some(schema) => format!("{}.{}", schema, self.table(name)),
+            None => self.table(name),
+        }
+    }
+
+    // Replace `{table}` placeholders in a query with the prefixed table names
+    pub fn sql(&self, query: &str) -> String {
+        TABLES.iter().fold(query.to_string(), |query, table| {
+            query.replace(&format!("{{{}}}", table), &self.table(table))
+        })
+    }
+
+    // Point new connections at the schema, so unqualified tables and types resolve there
+    pub fn connect_options(&self, options: PgConnectOptions) -> PgConnectOptions {
+        match &self.schema {
+            Some(schema) => options.options([("search_path", schema.as_str())]),
+            None => options,
+        }
+    }
+}
+
+fn check_identifier(
+    field: &'static str,
+    value: &str,
+    allow_empty: bool,
+) -> Result<(), InvalidIdentifier> {
+    let valid = if value.is_empty() {
+        allow_empty
+    } else {
+        !value.starts_with(|c: char| c.is_ascii_digit())
+            && value
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(InvalidIdentifier {
+            field,
+            value: value.to_string(),
+        })
+    }
+}
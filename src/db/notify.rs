@@ -0,0 +1,171 @@
+use std::fmt;
+use std::str::FromStr;
+
+use sqlx::postgres::PgListener;
+use uuid::Uuid;
+
+use super::blob::Versioned;
+use super::{DbError, DbResult, EntityKind, Message, PostgresDatabase};
+
+// NOTIFY channel stored messages are announced on, qualified by the namespace
+pub const MESSAGE_CHANNEL: &str = "messages";
+
+// NOTIFY channel changes to memberships are announced on, qualified by the namespace
+pub const MEMBERSHIP_CHANNEL: &str = "memberships";
+
+// Payload of a message notification: which database handle stored which message. Messages
+// can be larger than a NOTIFY payload may be, so listeners load them by id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageNotification {
+    pub origin: Uuid,
+    pub message_id: Uuid,
+}
+
+impl fmt::Display for MessageNotification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.origin, self.message_id)
+    }
+}
+
+impl FromStr for MessageNotification {
+    type Err = DbError;
+
+    fn from_str(payload: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || DbError::SerializationError(format!("Invalid message notification {:?}", payload));
+        let (origin, message_id) = payload.split_once(' ').ok_or_else(invalid)?;
+        Ok(Self {
+            origin: origin.parse().map_err(|_| invalid())?,
+            message_id: message_id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+// Memberships that changed: those of one group, those of one client, or any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembershipChange {
+    Group(Uuid),
+    Client(Uuid),
+    All,
+}
+
+impl MembershipChange {
+    // The memberships purging an entity deletes, if it has any
+    pub fn of_purged(kind: EntityKind, id: Uuid) -> Option<Self> {
+        match kind {
+            EntityKind::Group => Some(Self::Group(id)),
+            EntityKind::Client => Some(Self::Client(id)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MembershipChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Group(group_id) => write!(f, "group {}", group_id),
+            Self::Client(client_id) => write!(f, "client {}", client_id),
+            Self::All => f.write_str("all"),
+        }
+    }
+}
+
+impl FromStr for MembershipChange {
+    type Err = DbError;
+
+    fn from_str(payload: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            DbError::SerializationError(format!("Invalid membership notification {:?}", payload))
+        };
+        if payload == "all" {
+            return Ok(Self::All);
+        }
+        let (scope, id) = payload.split_once(' ').ok_or_else(invalid)?;
+        let id = id.parse().map_err(|_| invalid())?;
+        match scope {
+            "group" => Ok(Self::Group(id)),
+            "client" => Ok(Self::Client(id)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl PostgresDatabase {
+    // Announce every stored message on the message channel, so other instances sharing the
+    // database can wake their subscribers. Each handle tags its notifications with its own id.
+    pub fn with_message_notifications(mut self) -> Self {
+        self.notify_as = Some(Uuid::new_v4());
+        self
+    }
+
+    // Id this handle tags its message notifications with, if it sends any
+    pub fn notification_origin(&self) -> Option<Uuid> {
+        self.notify_as
+    }
+
+    // Namespaced name of the message channel
+    pub(super) fn message_channel(&self) -> String {
+        self.namespace.channel(MESSAGE_CHANNEL)
+    }
+
+    // A connection of its own listening on the message channel. It reconnects by itself, but
+    // misses what's announced while it's down.
+    pub async fn listen_for_messages(&self) -> DbResult<PgListener> {
+        let mut listener = PgListener::connect_with(&self.pool)
+            .await
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+        listener
+            .listen(&self.message_channel())
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        Ok(listener)
+    }
+
+    // Namespaced name of the membership channel
+    pub(super) fn membership_channel(&self) -> String {
+        self.namespace.channel(MEMBERSHIP_CHANNEL)
+    }
+
+    // Announce a change to memberships. Every handle does, so instances caching memberships
+    // hear of changes made anywhere; within a transaction, listeners hear of it once it's
+    // committed.
+    pub(super) async fn announce_membership_change<'e>(
+        &self,
+        executor: impl sqlx::PgExecutor<'e>,
+        change: MembershipChange,
+    ) -> DbResult<()> {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(self.membership_channel())
+            .bind(change.to_string())
+            .execute(executor)
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        Ok(())
+    }
+
+    // A connection of its own listening on the membership channel. Like the message listener,
+    // it misses what's announced while it's down.
+    pub async fn listen_for_membership_changes(&self) -> DbResult<PgListener> {
+        let mut listener = PgListener::connect_with(&self.pool)
+            .await
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+        listener
+            .listen(&self.membership_channel())
+            .await
+            .map_err(|e| DbError::QueryError(e.to_string()))?;
+        Ok(listener)
+    }
+
+    // A stored message by id, for listeners loading the messages they were notified of
+    pub async fn get_message(&self, message_id: Uuid) -> DbResult<Message> {
+        let message = sqlx::query_as::<_, Versioned<Message>>(
+            &self.sql("SELECT * FROM {messages} WHERE id = $1 AND deleted_at IS NULL"),
+        )
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::QueryError(e.to_string()))?
+        .ok_or(DbError::NotFound)?;
+        self.decode_row(message).await
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use sqlx::postgres::PgPoolOptions;
+
+// Kinds of database work, each served by its own connection pool so a slow or bulky one can't
+// take the connections another needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Workload {
+    // Client-facing RPCs, including the admin service and the self-test
+    Interactive,
+    // The janitor's sweeps and the feature flag refresh
+    Background,
+}
+
+// Size and limits of the pool of each workload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub interactive_connections: u32,
+    pub background_connections: u32,
+    // How long a caller waits for a connection before the query fails
+    pub acquire_timeout: Duration,
+    // How long a connection may live before it's replaced
+    pub max_lifetime: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            interactive_connections: 5,
+            background_connections: 2,
+            acquire_timeout: Duration::from_secs(30),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn connections(&self, workload: Workload) -> u32 {
+        match workload {
+            Workload::Interactive => self.interactive_connections,
+            Workload::Background => self.background_connections,
+        }
+        .max(1)
+    }
+
+    // Options for the pool of one workload
+    pub fn pool_options(&self, workload: Workload) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.connections(workload))
+            .acquire_timeout(self.acquire_timeout)
+            .max_lifetime(self.max_lifetime)
+    }
+}
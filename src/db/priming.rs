@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use uuid::Uuid;
+
+use super::{DatabaseInterface, DbResult, Group, Membership};
+use crate::timestamps;
+
+// Groups whose memberships are loaded by one query
+const GROUPS_PER_QUERY: usize = 500;
+
+// Which groups startup priming loads, and how long readiness waits for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimingConfig {
+    // Groups with messages stored this recently are loaded
+    pub window: Duration,
+    pub max_groups: i64,
+    // Membership queries run at once, each on its own pooled connection
+    pub concurrency: usize,
+    // The instance reports ready after this long even if priming hasn't finished
+    pub timeout: Duration,
+}
+
+impl Default for PrimingConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(24 * 60 * 60),
+            max_groups: 10_000,
+            concurrency: 4,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+// What a priming run loaded
+#[derive(Debug, Clone, Default)]
+pub struct PrimedGroups {
+    pub groups: Vec<Group>,
+    // Active memberships by group
+    pub memberships: HashMap<Uuid, Vec<Membership>>,
+    pub elapsed: Duration,
+}
+
+impl PrimedGroups {
+    pub fn membership_count(&self) -> usize {
+        self.memberships.values().map(Vec::len).sum()
+    }
+}
+
+// Loads the metadata and memberships of recently active groups before an instance reports
+// ready, so the first fetches after a restart find the rows, indexes and pooled connections
+// they need warm instead of all going to disk and opening connections at once
+pub struct CachePrimer<DB: DatabaseInterface + ?Sized> {
+    db: Arc<DB>,
+    config: PrimingConfig,
+}
+
+impl<DB: DatabaseInterface + ?Sized> CachePrimer<DB> {
+    pub fn new(db: Arc<DB>, config: PrimingConfig) -> Self {
+        Self { db, config }
+    }
+
+    pub async fn run(&self) -> DbResult<PrimedGroups> {
+        let started = Instant::now();
+        let since = chrono::Duration::from_std(self.config.window)
+            .ok()
+            .and_then(|window| timestamps::now().checked_sub_signed(window))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let groups = self
+            .db
+            .list_active_groups(since, self.config.max_groups)
+            .await?;
+
+        let ids: Vec<Uuid> = groups.iter().map(|g| g.id).collect();
+        let chunks: Vec<Vec<Uuid>> = ids.chunks(GROUPS_PER_QUERY).map(<[_]>::to_vec).collect();
+        let chunks: Vec<Vec<Membership>> = stream::iter(chunks)
+            .map(|chunk| async move { self.db.list_memberships_by_groups(&chunk).await })
+            .buffer_unordered(self.config.concurrency)
+            .try_collect()
+            .await?;
+
+        let mut memberships: HashMap<Uuid, Vec<Membership>> = HashMap::new();
+        for membership in chunks.into_iter().flatten() {
+            memberships
+                .entry(membership.group_id)
+                .or_default()
+                .push(membership);
+        }
+        Ok(PrimedGroups {
+            groups,
+            memberships,
+            elapsed: started.elapsed(),
+        })
+    }
+}
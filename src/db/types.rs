@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use thiserror::Error;
+
+// A string that doesn't name any variant of a stored enum
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("unknown {kind} {value:?}")]
+pub struct UnknownVariant {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+// Closed sets of values stored as Postgres enum types. Each variant keeps the
+// lowercase string it was stored as before the columns were constrained.
+macro_rules! db_enum {
+    (
+        $(#[$meta:meta])*
+        $name:ident, $pg_type:tt, $pg_array_type:tt {
+            $($variant:ident => $value:tt),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(
+            Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+            sqlx::Type,
+        )]
+        #[sqlx(type_name = $pg_type)]
+        pub enum $name {
+            $(
+                #[serde(rename = $value)]
+                #[sqlx(rename = $value)]
+                $variant,
+            )+
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant),+];
+            pub const NAMES: &'static [&'static str] = &[$($value),+];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $value,)+
+                }
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = UnknownVariant;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                match value {
+                    $($value => Ok($name::$variant),)+
+                    _ => Err(UnknownVariant {
+                        kind: $pg_type,
+                        value: value.to_string(),
+                    }),
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl PgHasArrayType for $name {
+            fn array_type_info() -> PgTypeInfo {
+                PgTypeInfo::with_name($pg_array_type)
+            }
+        }
+    };
+}
+
+db_enum! {
+    // Kind of a stored message, matching the content it carries
+    MessageType, "message_type", "_message_type" {
+        Proposal => "proposal",
+        Commit => "commit",
+        Welcome => "welcome",
+        Application => "application",
+        System => "system",
+    }
+}
+
+db_enum! {
+    // MLS proposal types accepted by StoreProposal
+    ProposalType, "proposal_type", "_proposal_type" {
+        Add => "add",
+        Update => "update",
+        Remove => "remove",
+        Psk => "psk",
+        Reinit => "reinit",
+        ExternalInit => "external_init",
+        GroupContextExtensions => "group_context_extensions",
+    }
+}
+
+db_enum! {
+    // Role of a member within a group
+    MembershipRole, "membership_role", "_membership_role" {
+        Admin => "admin",
+        Member => "member",
+        // Removes members and freezes the group, as allowed by the runtime settings
+        Moderator => "moderator",
+    }
+}
+
+db_enum! {
+    // Scheme of a client's MLS credential
+    CredentialScheme, "credential_scheme", "_credential_scheme" {
+        Basic => "basic",
+        X509 => "x509",
+    }
+}
+
+db_enum! {
+    // Outcome of the attestation a client registered with
+    AttestationVerdict, "attestation_verdict", "_attestation_verdict" {
+        // Registered without attestation evidence
+        Unattested => "unattested",
+        Passed => "passed",
+        Failed => "failed",
+    }
+}
+
+// Postgres enum types and the values they accept, in declaration order
+pub(crate) const PG_ENUM_TYPES: &[(&str, &[&str])] = &[
+    ("message_type", MessageType::NAMES),
+    ("proposal_type", ProposalType::NAMES),
+    ("membership_role", MembershipRole::NAMES),
+    ("credential_scheme", CredentialScheme::NAMES),
+    ("attestation_verdict", AttestationVerdict::NAMES),
+];
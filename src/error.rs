@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+use log::error;
+use thiserror::Error;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+use crate::db::DbError;
+use crate::mls_codec::CodecError;
+use crate::redact;
+use crate::service::rate_limit::RETRY_AFTER_HEADER;
+
+// Domain of the google.rpc.ErrorInfo detail attached to service errors
+pub const ERROR_DOMAIN: &str = "hermetic-mls";
+
+// Errors handlers report to clients. Each maps to one gRPC code, and the domain errors carry an
+// ErrorInfo detail whose reason and metadata clients can act on without parsing the message.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ServiceError {
+    #[error("{entity} not found")]
+    NotFound { entity: &'static str },
+
+    // The request lost a race with another writer; `current_epoch` is set when the conflict is
+    // about the group's epoch
+    #[error("{reason}")]
+    Conflict {
+        reason: String,
+        current_epoch: Option<i64>,
+    },
+
+    #[error("Application message epoch {epoch} is too old, the group is at epoch {current_epoch} (minimum {min_epoch})")]
+    EpochTooOld {
+        epoch: i64,
+        current_epoch: i64,
+        min_epoch: i64,
+    },
+
+    #[error("Commit for epoch {epoch} doesn't follow the group's current epoch {current_epoch}; rebase onto it and retry")]
+    StaleCommit { epoch: i64, current_epoch: i64 },
+
+    #[error("{quota} quota of {limit} exceeded")]
+    QuotaExceeded { quota: &'static str, limit: u64 },
+
+    // The caller ran out of requests under the per-client or per-IP rate limit
+    #[error("{limit} rate limit exceeded; retry in {}ms", .retry_after.as_millis())]
+    RateLimited {
+        limit: &'static str,
+        retry_after: Duration,
+    },
+
+    #[error("{reason}")]
+    ValidationFailed { reason: String },
+
+    #[error("{0}")]
+    FailedPrecondition(String),
+
+    #[error("{0}")]
+    PermissionDenied(String),
+
+    #[error("{0}")]
+    Unauthenticated(String),
+
+    #[error("{0}")]
+    Unavailable(String),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ServiceError {
+    pub fn validation(reason: impl Into<String>) -> Self {
+        Self::ValidationFailed {
+            reason: reason.into(),
+        }
+    }
+
+    // An internal failure, described as "<context>: <cause>"
+    pub fn internal(context: &str, cause: impl Display) -> Self {
+        Self::Internal(format!("{}: {}", context, cause))
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            Self::NotFound { .. } => Code::NotFound,
+            Self::Conflict { .. } => Code::Aborted,
+            Self::EpochTooOld { .. } => Code::FailedPrecondition,
+            Self::StaleCommit { .. } => Code::FailedPrecondition,
+            Self::QuotaExceeded { .. } => Code::ResourceExhausted,
+            Self::RateLimited { .. } => Code::ResourceExhausted,
+            Self::ValidationFailed { .. } => Code::InvalidArgument,
+            Self::FailedPrecondition(_) => Code::FailedPrecondition,
+            Self::PermissionDenied(_) => Code::PermissionDenied,
+            Self::Unauthenticated(_) => Code::Unauthenticated,
+            Self::Unavailable(_) => Code::Unavailable,
+            Self::Internal(_) => Code::Internal,
+        }
+    }
+
+    // ErrorInfo reason and metadata for the domain errors
+    fn error_info(&self) -> Option<(&'static str, HashMap<String, String>)> {
+        match self {
+            Self::NotFound { entity } => Some((
+                "NOT_FOUND",
+                HashMap::from([("entity".to_string(), entity.to_string())]),
+            )),
+            Self::Conflict { current_epoch, .. } => Some((
+                "CONFLICT",
+                current_epoch
+                    .map(|epoch| ("current_epoch".to_string(), epoch.to_string()))
+                    .into_iter()
+                    .collect(),
+            )),
+            Self::EpochTooOld {
+                current_epoch,
+                min_epoch,
+                ..
+            } => Some((
+                "EPOCH_TOO_OLD",
+                HashMap::from([
+                    ("current_epoch".to_string(), current_epoch.to_string()),
+                    ("min_epoch".to_string(), min_epoch.to_string()),
+                ]),
+            )),
+            Self::StaleCommit { current_epoch, .. } => Some((
+                "STALE_COMMIT",
+                HashMap::from([("current_epoch".to_string(), current_epoch.to_string())]),
+            )),
+            Self::QuotaExceeded { quota, limit } => Some((
+                "QUOTA_EXCEEDED",
+                HashMap::from([
+                    ("quota".to_string(), quota.to_string()),
+                    ("limit".to_string(), limit.to_string()),
+                ]),
+            )),
+            Self::RateLimited { limit, retry_after } => Some((
+                "RATE_LIMITED",
+                HashMap::from([
+                    ("limit".to_string(), limit.to_string()),
+                    (
+                        "retry_after_ms".to_string(),
+                        retry_after.as_millis().to_string(),
+                    ),
+                ]),
+            )),
+            Self::ValidationFailed { .. } => Some(("VALIDATION_FAILED", HashMap::new())),
+            _ => None,
+        }
+    }
+}
+
+impl From<DbError> for ServiceError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::NotFound => Self::NotFound { entity: "Resource" },
+            DbError::ConnectionError(msg) => Self::Unavailable(msg),
+            DbError::QueryError(msg) => Self::internal("Database query error", msg),
+            DbError::SerializationError(msg) => Self::internal("Serialization error", msg),
+            DbError::Conflict(msg) => Self::Conflict {
+                reason: msg,
+                current_epoch: None,
+            },
+            DbError::MissingReference(entity) => Self::NotFound { entity },
+            DbError::ProposalNotPending(msg) => Self::FailedPrecondition(msg),
+            DbError::TenantKeyDestroyed(tenant_id) => Self::FailedPrecondition(format!(
+                "Storage key of tenant {} was destroyed",
+                tenant_id
+            )),
+            DbError::StaleEpoch {
+                epoch,
+                current_epoch,
+            } => Self::StaleCommit {
+                epoch,
+                current_epoch,
+            },
+        }
+    }
+}
+
+impl From<CodecError> for ServiceError {
+    fn from(err: CodecError) -> Self {
+        match err {
+            CodecError::Malformed { .. } | CodecError::Invalid { .. } => {
+                Self::validation(err.to_string())
+            }
+            CodecError::Crypto { .. } => Self::Internal(err.to_string()),
+        }
+    }
+}
+
+// Every status a handler returns is built here, so this is where messages carrying library
+// errors are scrubbed of payload bytes
+impl From<ServiceError> for Status {
+    fn from(err: ServiceError) -> Self {
+        let message = redact::scrub(&err.to_string()).into_owned();
+        if let ServiceError::Internal(_) = &err {
+            error!("Internal error: {}", message);
+        }
+
+        let code = err.code();
+        let retry_after = match &err {
+            ServiceError::RateLimited { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        };
+        let mut status = match err.error_info() {
+            Some((reason, metadata)) => {
+                let mut details = ErrorDetails::with_error_info(reason, ERROR_DOMAIN, metadata);
+                if retry_after.is_some() {
+                    details.set_retry_info(retry_after);
+                }
+                Status::with_error_details(code, message, details)
+            }
+            None => Status::new(code, message),
+        };
+
+        // Also as whole seconds in plain metadata, rounded up, for clients that don't decode
+        // the details
+        if let Some(retry_after) = retry_after {
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            status
+                .metadata_mut()
+                .insert(RETRY_AFTER_HEADER, secs.into());
+        }
+        status
+    }
+}
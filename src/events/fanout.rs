@@ -0,0 +1,136 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tokio::task::JoinHandle;
+
+use crate::db::{DbError, MembershipChange, MessageNotification, PostgresDatabase};
+
+use super::{DomainEvent, EventBus};
+
+// How long to wait before listening again after the listener connection failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Wakes this instance's subscribers for messages other instances stored, so replicas behind a
+// load balancer stream each other's messages. Every instance's database handle announces the
+// messages it stores (see `PostgresDatabase::with_message_notifications`); this listens to
+// those announcements and publishes `MessageRelayed` for each one this instance didn't publish
+// itself.
+pub struct MessageFanout {
+    db: Arc<PostgresDatabase>,
+    events: EventBus,
+}
+
+impl MessageFanout {
+    // `db` is the handle the service stores messages with, since those are published locally
+    // already; messages any other handle stores, this instance's background jobs included,
+    // are forwarded
+    pub fn new(db: Arc<PostgresDatabase>, events: EventBus) -> Self {
+        Self { db, events }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.listen().await {
+                    error!("Message fanout listener failed: {}", e);
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        })
+    }
+
+    // Forward notifications until the listener fails
+    async fn listen(&self) -> Result<(), DbError> {
+        let mut listener = self.db.listen_for_messages().await?;
+        info!("Listening for messages stored by other instances");
+
+        loop {
+            let notification = match listener.try_recv().await {
+                Ok(Some(notification)) => notification,
+                // The listener reconnects on the next call
+                Ok(None) => {
+                    warn!("Message fanout connection lost; messages stored meanwhile won't be streamed from this instance");
+                    continue;
+                }
+                Err(e) => return Err(DbError::ConnectionError(e.to_string())),
+            };
+            let notification: MessageNotification = match notification.payload().parse() {
+                Ok(notification) => notification,
+                Err(e) => {
+                    warn!("{}", e);
+                    continue;
+                }
+            };
+            if Some(notification.origin) == self.db.notification_origin() {
+                continue;
+            }
+
+            match self.db.get_message(notification.message_id).await {
+                Ok(message) => self
+                    .events
+                    .publish(DomainEvent::MessageRelayed(Arc::new(message))),
+                // Deleted or evicted since
+                Err(DbError::NotFound) => {}
+                Err(e) => warn!(
+                    "Could not load message {} for fanout: {}",
+                    notification.message_id, e
+                ),
+            }
+        }
+    }
+}
+
+// Relays the membership changes every database handle announces (see
+// `PostgresDatabase::announce_membership_change`) as `MembershipsChanged`, so caches of
+// memberships on every instance drop what changed. Changes announced while the listener is
+// down are lost, so after reconnecting it reports that anything may have changed.
+pub struct MembershipFanout {
+    db: Arc<PostgresDatabase>,
+    events: EventBus,
+}
+
+impl MembershipFanout {
+    pub fn new(db: Arc<PostgresDatabase>, events: EventBus) -> Self {
+        Self { db, events }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.listen().await {
+                    error!("Membership change listener failed: {}", e);
+                }
+                self.events
+                    .publish(DomainEvent::MembershipsChanged(MembershipChange::All));
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        })
+    }
+
+    // Relay notifications until the listener fails
+    async fn listen(&self) -> Result<(), DbError> {
+        let mut listener = self.db.listen_for_membership_changes().await?;
+        info!("Listening for membership changes");
+
+        loop {
+            let notification = match listener.try_recv().await {
+                Ok(Some(notification)) => notification,
+                // The listener reconnects on the next call
+                Ok(None) => {
+                    warn!(
+                        "Membership change listener connection lost; reloading cached memberships"
+                    );
+                    self.events
+                        .publish(DomainEvent::MembershipsChanged(MembershipChange::All));
+                    continue;
+                }
+                Err(e) => return Err(DbError::ConnectionError(e.to_string())),
+            };
+            match notification.payload().parse() {
+                Ok(change) => self.events.publish(DomainEvent::MembershipsChanged(change)),
+                Err(e) => warn!("{}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::db::{EntityKind, MembershipChange, Message};
+
+pub mod fanout;
+pub mod publisher;
+
+// Events buffered per subscriber before it starts missing them
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+// A successful mutation, published after it has been committed to the database.
+// Delivery mechanisms (streaming, webhooks, push, metrics) subscribe to these
+// instead of being called from the RPC handlers.
+#[derive(Debug, Clone)]
+pub enum DomainEvent {
+    ClientRegistered {
+        client_id: Uuid,
+        user_id: Uuid,
+        is_service: bool,
+    },
+    ClientBackupStored {
+        client_id: Uuid,
+        version: i64,
+    },
+    KeyPackagePublished {
+        client_id: Uuid,
+        key_package_id: Uuid,
+    },
+    KeyPackageClaimed {
+        client_id: Uuid,
+        key_package_id: Uuid,
+        group_id: Uuid,
+    },
+    GroupCreated {
+        group_id: Uuid,
+        creator_id: Uuid,
+    },
+    GroupEpochAdvanced {
+        group_id: Uuid,
+        epoch: i64,
+    },
+    MemberAdded {
+        membership_id: Uuid,
+        group_id: Uuid,
+        client_id: Uuid,
+    },
+    MemberRemoved {
+        membership_id: Uuid,
+        group_id: Uuid,
+        client_id: Uuid,
+    },
+    EpochAcknowledged {
+        group_id: Uuid,
+        client_id: Uuid,
+        epoch: i64,
+    },
+    WelcomeResendRequested {
+        membership_id: Uuid,
+        group_id: Uuid,
+        client_id: Uuid,
+    },
+    // A welcome its joiner hasn't acknowledged within the deadline, for push notifications to
+    // try again; `attempt` counts from 1
+    WelcomeReminder {
+        message_id: Uuid,
+        group_id: Uuid,
+        client_id: Uuid,
+        attempt: i64,
+    },
+    AbuseReported {
+        report_id: Uuid,
+        group_id: Uuid,
+        reporter_id: Uuid,
+    },
+    // Any message this instance stored, including system notices
+    MessageStored(Arc<Message>),
+    // A message stored elsewhere (another instance, or this instance's background jobs) and
+    // relayed by the message fanout
+    MessageRelayed(Arc<Message>),
+    // Memberships changed through any database handle, as announced to every instance
+    MembershipsChanged(MembershipChange),
+    EntityDeleted {
+        kind: EntityKind,
+        id: Uuid,
+    },
+    EntityPurged {
+        kind: EntityKind,
+        id: Uuid,
+    },
+    EntityRestored {
+        kind: EntityKind,
+        id: Uuid,
+    },
+}
+
+// In-process fan-out of domain events. Publishing never blocks; subscribers that
+// fall more than `capacity` events behind miss the oldest ones.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: DomainEvent) {
+        // Having no subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
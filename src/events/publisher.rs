@@ -0,0 +1,340 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+#[cfg(any(feature = "nats", feature = "kafka"))]
+use log::info;
+use log::{error, warn};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::db::{Message, MessageType};
+
+use super::{DomainEvent, EventBus};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PublishError {
+    #[error("Invalid event publisher configuration: {0}")]
+    Config(String),
+
+    #[error("Failed to connect to the event bus: {0}")]
+    Connect(String),
+
+    #[error("Failed to publish event: {0}")]
+    Publish(String),
+}
+
+// An event published to the external bus. Only metadata is published: payloads stay in the
+// delivery service, and consumers that need one fetch the message by id and can check it
+// against `payload_hash`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExternalEvent {
+    Proposal(StoredMessage),
+    Commit(StoredMessage),
+    Welcome(StoredMessage),
+    MemberAdded {
+        membership_id: Uuid,
+        group_id: Uuid,
+        client_id: Uuid,
+    },
+    MemberRemoved {
+        membership_id: Uuid,
+        group_id: Uuid,
+        client_id: Uuid,
+    },
+    WelcomeReminder {
+        message_id: Uuid,
+        group_id: Uuid,
+        client_id: Uuid,
+        attempt: i64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoredMessage {
+    pub message_id: Uuid,
+    pub group_id: Uuid,
+    pub sender_id: Uuid,
+    pub epoch: Option<i64>,
+    pub sequence: i64,
+    pub created_at: DateTime<Utc>,
+    // Lowercase hex SHA-256 of the payload
+    pub payload_hash: Option<String>,
+    // Set for welcomes, which are addressed to the clients joining
+    pub recipients: Option<Vec<Uuid>>,
+}
+
+impl StoredMessage {
+    fn new(message: &Message, group_id: Uuid) -> Self {
+        Self {
+            message_id: message.id,
+            group_id,
+            sender_id: message.sender_id,
+            epoch: message.epoch,
+            sequence: message.sequence,
+            created_at: message.created_at,
+            payload_hash: message
+                .payload_hash
+                .as_ref()
+                .map(|hash| hash.iter().map(|b| format!("{:02x}", b)).collect()),
+            recipients: message.recipients.clone(),
+        }
+    }
+}
+
+impl ExternalEvent {
+    // The external event for a domain event, if it is one that is published. Application
+    // messages and system notices aren't.
+    pub fn from_domain(event: &DomainEvent) -> Option<Self> {
+        match event {
+            DomainEvent::MessageStored(message) => {
+                let stored = StoredMessage::new(message, message.group_id?);
+                match message.message_type {
+                    MessageType::Proposal => Some(Self::Proposal(stored)),
+                    MessageType::Commit => Some(Self::Commit(stored)),
+                    MessageType::Welcome => Some(Self::Welcome(stored)),
+                    MessageType::Application | MessageType::System => None,
+                }
+            }
+            DomainEvent::MemberAdded {
+                membership_id,
+                group_id,
+                client_id,
+            } => Some(Self::MemberAdded {
+                membership_id: *membership_id,
+                group_id: *group_id,
+                client_id: *client_id,
+            }),
+            DomainEvent::MemberRemoved {
+                membership_id,
+                group_id,
+                client_id,
+            } => Some(Self::MemberRemoved {
+                membership_id: *membership_id,
+                group_id: *group_id,
+                client_id: *client_id,
+            }),
+            DomainEvent::WelcomeReminder {
+                message_id,
+                group_id,
+                client_id,
+                attempt,
+            } => Some(Self::WelcomeReminder {
+                message_id: *message_id,
+                group_id: *group_id,
+                client_id: *client_id,
+                attempt: *attempt,
+            }),
+            _ => None,
+        }
+    }
+
+    // Matches the `type` field of the JSON
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Proposal(_) => "proposal",
+            Self::Commit(_) => "commit",
+            Self::Welcome(_) => "welcome",
+            Self::MemberAdded { .. } => "member_added",
+            Self::MemberRemoved { .. } => "member_removed",
+            Self::WelcomeReminder { .. } => "welcome_reminder",
+        }
+    }
+
+    // Group the event belongs to. Publishers that partition use it as the key, so each group's
+    // events stay in order.
+    pub fn group_id(&self) -> Uuid {
+        match self {
+            Self::Proposal(message) | Self::Commit(message) | Self::Welcome(message) => {
+                message.group_id
+            }
+            Self::MemberAdded { group_id, .. }
+            | Self::MemberRemoved { group_id, .. }
+            | Self::WelcomeReminder { group_id, .. } => *group_id,
+        }
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("external events serialize to JSON")
+    }
+}
+
+// Publishes events to an external bus for downstream systems (search, analytics, push)
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, event: &ExternalEvent) -> Result<(), PublishError>;
+}
+
+// Publishes each event as JSON to the subject `<prefix>.<type>`
+#[cfg(feature = "nats")]
+pub struct NatsPublisher {
+    client: async_nats::Client,
+    prefix: String,
+}
+
+#[cfg(feature = "nats")]
+impl NatsPublisher {
+    pub async fn connect(url: &str, prefix: impl Into<String>) -> Result<Self, PublishError> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|e| PublishError::Connect(e.to_string()))?;
+        Ok(Self {
+            client,
+            prefix: prefix.into(),
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventPublisher for NatsPublisher {
+    async fn publish(&self, event: &ExternalEvent) -> Result<(), PublishError> {
+        self.client
+            .publish(
+                format!("{}.{}", self.prefix, event.kind()),
+                event.to_json().into(),
+            )
+            .await
+            .map_err(|e| PublishError::Publish(e.to_string()))
+    }
+}
+
+// Produces each event as JSON to one topic, keyed by group ID
+#[cfg(feature = "kafka")]
+pub struct KafkaPublisher {
+    producer: rdkafka::producer::FutureProducer,
+    topic: String,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaPublisher {
+    // How long a record may wait in the producer queue before publishing it fails
+    const QUEUE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    pub fn new(brokers: &str, topic: impl Into<String>) -> Result<Self, PublishError> {
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()
+            .map_err(|e| PublishError::Connect(e.to_string()))?;
+        Ok(Self {
+            producer,
+            topic: topic.into(),
+        })
+    }
+}
+
+#[cfg(feature = "kafka")]
+#[async_trait]
+impl EventPublisher for KafkaPublisher {
+    async fn publish(&self, event: &ExternalEvent) -> Result<(), PublishError> {
+        let key = event.group_id().to_string();
+        let payload = event.to_json();
+        let record = rdkafka::producer::FutureRecord::to(&self.topic)
+            .key(&key)
+            .payload(&payload);
+        self.producer
+            .send(record, Self::QUEUE_TIMEOUT)
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| PublishError::Publish(e.to_string()))
+    }
+}
+
+// The external bus events are published to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublisherConfig {
+    // Subjects `<subject_prefix>.<type>` on the NATS server at `url`
+    Nats { url: String, subject_prefix: String },
+    // One topic on the Kafka brokers, a comma-separated list
+    Kafka { brokers: String, topic: String },
+}
+
+// Connect to the configured bus. Each kind of publisher needs the build feature of the same
+// name.
+pub async fn connect_publisher(
+    config: &PublisherConfig,
+) -> Result<Arc<dyn EventPublisher>, PublishError> {
+    match config {
+        PublisherConfig::Nats {
+            url,
+            subject_prefix,
+        } => connect_nats(url, subject_prefix).await,
+        PublisherConfig::Kafka { brokers, topic } => connect_kafka(brokers, topic),
+    }
+}
+
+#[cfg(feature = "nats")]
+async fn connect_nats(url: &str, prefix: &str) -> Result<Arc<dyn EventPublisher>, PublishError> {
+    info!("Publishing events to NATS at {} under {}", url, prefix);
+    Ok(Arc::new(NatsPublisher::connect(url, prefix).await?))
+}
+
+#[cfg(not(feature = "nats"))]
+async fn connect_nats(_: &str, _: &str) -> Result<Arc<dyn EventPublisher>, PublishError> {
+    Err(missing_feature("nats"))
+}
+
+#[cfg(feature = "kafka")]
+fn connect_kafka(brokers: &str, topic: &str) -> Result<Arc<dyn EventPublisher>, PublishError> {
+    info!("Publishing events to Kafka topic {} on {}", topic, brokers);
+    Ok(Arc::new(KafkaPublisher::new(brokers, topic)?))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn connect_kafka(_: &str, _: &str) -> Result<Arc<dyn EventPublisher>, PublishError> {
+    Err(missing_feature("kafka"))
+}
+
+#[cfg(not(all(feature = "nats", feature = "kafka")))]
+fn missing_feature(feature: &str) -> PublishError {
+    PublishError::Config(format!(
+        "EVENT_PUBLISHER is {}, but this build doesn't have the {} feature",
+        feature, feature
+    ))
+}
+
+// Forwards this instance's events from the event bus to an external publisher, one at a time
+// so each group's events are published in order. Delivery is at most once: events that fail to
+// publish, or that are published while the forwarder lags behind the bus, are logged and
+// dropped.
+pub struct EventForwarder {
+    publisher: Arc<dyn EventPublisher>,
+    events: EventBus,
+}
+
+impl EventForwarder {
+    pub fn new(publisher: Arc<dyn EventPublisher>, events: EventBus) -> Self {
+        Self { publisher, events }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        let mut events = self.events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Event publisher missed {} events", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                let Some(event) = ExternalEvent::from_domain(&event) else {
+                    continue;
+                };
+                if let Err(e) = self.publisher.publish(&event).await {
+                    error!(
+                        "Dropped {} event for group {}: {}",
+                        event.kind(),
+                        event.group_id(),
+                        e
+                    );
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,414 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::db::{
+    AttestationVerdict, Client, CredentialScheme, DatabaseInterface, DbResult, Group, KeyPackage,
+    Membership, MembershipRole, Message, MessageType, ProposalType,
+};
+use crate::mls_codec::{self, MlsCrypto};
+use crate::timestamps;
+
+// Builders for the rows tests set up, so a test names only the fields it cares about and keeps
+// compiling when a column is added. Every fixture starts from a valid, active row with fresh
+// ids; `build` returns the row and `insert` stores it through any database.
+
+// An MLS basic credential for the identity, serialized
+pub fn credential(identity: &[u8]) -> Vec<u8> {
+    mls_codec::basic_credential(identity).expect("basic credentials always serialize")
+}
+
+// A serialized key package for the identity's basic credential, as a client would publish it
+pub fn key_package_bytes(identity: &[u8]) -> Vec<u8> {
+    MlsCrypto::default()
+        .build_key_package(&credential(identity))
+        .expect("key packages for basic credentials always build")
+}
+
+// A registered client of a fresh user, with a basic credential for its id
+#[derive(Debug, Clone)]
+pub struct ClientFixture(Client);
+
+impl Default for ClientFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientFixture {
+    pub fn new() -> Self {
+        let id = Uuid::new_v4();
+        let now = timestamps::now();
+        Self(Client {
+            id,
+            user_id: Uuid::new_v4(),
+            credential: credential(id.to_string().as_bytes()),
+            scheme: CredentialScheme::Basic,
+            device_name: "test-device".to_string(),
+            last_seen: now,
+            created_at: now,
+            init_key: None,
+            signature_key: None,
+            is_service: false,
+            attestation_verdict: AttestationVerdict::Unattested,
+            deleted_at: None,
+        })
+    }
+
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.0.id = id;
+        self
+    }
+
+    pub fn with_user(mut self, user_id: Uuid) -> Self {
+        self.0.user_id = user_id;
+        self
+    }
+
+    pub fn with_credential(mut self, scheme: CredentialScheme, credential: Vec<u8>) -> Self {
+        self.0.scheme = scheme;
+        self.0.credential = credential;
+        self
+    }
+
+    pub fn with_device_name(mut self, device_name: impl Into<String>) -> Self {
+        self.0.device_name = device_name.into();
+        self
+    }
+
+    // Ed25519 public key the client signs requests with
+    pub fn with_signature_key(mut self, signature_key: Vec<u8>) -> Self {
+        self.0.signature_key = Some(signature_key);
+        self
+    }
+
+    pub fn with_attestation(mut self, verdict: AttestationVerdict) -> Self {
+        self.0.attestation_verdict = verdict;
+        self
+    }
+
+    // A bot account
+    pub fn service(mut self) -> Self {
+        self.0.is_service = true;
+        self
+    }
+
+    pub fn created_at(mut self, at: DateTime<Utc>) -> Self {
+        self.0.created_at = at;
+        self.0.last_seen = at;
+        self
+    }
+
+    pub fn deleted(mut self) -> Self {
+        self.0.deleted_at = Some(timestamps::now());
+        self
+    }
+
+    pub fn build(self) -> Client {
+        self.0
+    }
+
+    pub async fn insert<DB: DatabaseInterface + ?Sized>(self, db: &DB) -> DbResult<Client> {
+        db.register_client(self.0.clone()).await?;
+        Ok(self.0)
+    }
+}
+
+// An active group at epoch 0, created by a fresh client
+#[derive(Debug, Clone)]
+pub struct GroupFixture(Group);
+
+impl Default for GroupFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GroupFixture {
+    pub fn new() -> Self {
+        let now = timestamps::now();
+        Self(Group {
+            id: Uuid::new_v4(),
+            creator_id: Uuid::new_v4(),
+            epoch: 0,
+            state: Some(vec![1, 2, 3]),
+            mls_group_id: None,
+            tenant_id: None,
+            handle: None,
+            created_at: now,
+            updated_at: now,
+            is_active: true,
+            deleted_at: None,
+        })
+    }
+
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.0.id = id;
+        self
+    }
+
+    pub fn with_creator(mut self, creator_id: Uuid) -> Self {
+        self.0.creator_id = creator_id;
+        self
+    }
+
+    pub fn with_epoch(mut self, epoch: i64) -> Self {
+        self.0.epoch = epoch;
+        self
+    }
+
+    pub fn with_state(mut self, state: Option<Vec<u8>>) -> Self {
+        self.0.state = state;
+        self
+    }
+
+    pub fn with_mls_group_id(mut self, mls_group_id: Vec<u8>) -> Self {
+        self.0.mls_group_id = Some(mls_group_id);
+        self
+    }
+
+    // A handle, unique within the tenant
+    pub fn with_handle(mut self, tenant_id: Uuid, handle: impl Into<String>) -> Self {
+        self.0.tenant_id = Some(tenant_id);
+        self.0.handle = Some(handle.into());
+        self
+    }
+
+    pub fn created_at(mut self, at: DateTime<Utc>) -> Self {
+        self.0.created_at = at;
+        self.0.updated_at = at;
+        self
+    }
+
+    pub fn inactive(mut self) -> Self {
+        self.0.is_active = false;
+        self
+    }
+
+    pub fn deleted(mut self) -> Self {
+        self.0.deleted_at = Some(timestamps::now());
+        self
+    }
+
+    pub fn build(self) -> Group {
+        self.0
+    }
+
+    pub async fn insert<DB: DatabaseInterface + ?Sized>(self, db: &DB) -> DbResult<Group> {
+        db.create_group(self.0.clone()).await?;
+        Ok(self.0)
+    }
+}
+
+// An active plain membership of the client in the group
+#[derive(Debug, Clone)]
+pub struct MembershipFixture(Membership);
+
+impl MembershipFixture {
+    pub fn new(group_id: Uuid, client_id: Uuid) -> Self {
+        Self(Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: timestamps::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        })
+    }
+
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.0.id = id;
+        self
+    }
+
+    pub fn with_role(mut self, role: MembershipRole) -> Self {
+        self.0.role = role;
+        self
+    }
+
+    pub fn acked_epoch(mut self, epoch: i64) -> Self {
+        self.0.last_acked_epoch = Some(epoch);
+        self.0.last_acked_at = Some(timestamps::now());
+        self
+    }
+
+    pub fn removed(mut self) -> Self {
+        self.0.removed_at = Some(timestamps::now());
+        self
+    }
+
+    pub fn build(self) -> Membership {
+        self.0
+    }
+
+    pub async fn insert<DB: DatabaseInterface + ?Sized>(self, db: &DB) -> DbResult<Membership> {
+        db.add_membership(self.0.clone()).await?;
+        Ok(self.0)
+    }
+}
+
+// A message of the type, to a group or addressed to clients directly, carrying a payload in the
+// column of its type
+#[derive(Debug, Clone)]
+pub struct MessageFixture(Message);
+
+impl MessageFixture {
+    pub fn new(message_type: MessageType) -> Self {
+        let message = Message {
+            id: Uuid::new_v4(),
+            group_id: None,
+            sender_id: Uuid::new_v4(),
+            created_at: timestamps::now(),
+            read: false,
+            message_type,
+            proposal: None,
+            commit: None,
+            welcome: None,
+            system: None,
+            application: None,
+            proposal_type: (message_type == MessageType::Proposal).then_some(ProposalType::Add),
+            epoch: Some(0),
+            recipients: None,
+            extra: None,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
+        };
+        Self(message).with_payload(vec![1, 2, 3])
+    }
+
+    pub fn application(group_id: Uuid) -> Self {
+        Self::new(MessageType::Application).in_group(group_id)
+    }
+
+    pub fn commit(group_id: Uuid, epoch: i64) -> Self {
+        Self::new(MessageType::Commit)
+            .in_group(group_id)
+            .with_epoch(epoch)
+    }
+
+    pub fn welcome(group_id: Uuid, recipients: Vec<Uuid>) -> Self {
+        Self::new(MessageType::Welcome)
+            .in_group(group_id)
+            .addressed_to(recipients)
+    }
+
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.0.id = id;
+        self
+    }
+
+    pub fn in_group(mut self, group_id: Uuid) -> Self {
+        self.0.group_id = Some(group_id);
+        self
+    }
+
+    // Only these clients receive the message
+    pub fn addressed_to(mut self, recipients: Vec<Uuid>) -> Self {
+        self.0.recipients = Some(recipients);
+        self
+    }
+
+    pub fn sent_by(mut self, sender_id: Uuid) -> Self {
+        self.0.sender_id = sender_id;
+        self
+    }
+
+    // Replaces the payload, in the column of the message's type
+    pub fn with_payload(mut self, payload: Vec<u8>) -> Self {
+        let column = match self.0.message_type {
+            MessageType::Proposal => &mut self.0.proposal,
+            MessageType::Commit => &mut self.0.commit,
+            MessageType::Welcome => &mut self.0.welcome,
+            MessageType::Application => &mut self.0.application,
+            MessageType::System => &mut self.0.system,
+        };
+        *column = Some(payload);
+        self
+    }
+
+    pub fn with_proposal_type(mut self, proposal_type: ProposalType) -> Self {
+        self.0.proposal_type = Some(proposal_type);
+        self
+    }
+
+    pub fn with_epoch(mut self, epoch: i64) -> Self {
+        self.0.epoch = Some(epoch);
+        self
+    }
+
+    pub fn with_extra(mut self, extra: serde_json::Value) -> Self {
+        self.0.extra = Some(extra);
+        self
+    }
+
+    pub fn created_at(mut self, at: DateTime<Utc>) -> Self {
+        self.0.created_at = at;
+        self
+    }
+
+    pub fn expires_at(mut self, at: DateTime<Utc>) -> Self {
+        self.0.expires_at = Some(at);
+        self
+    }
+
+    pub fn build(self) -> Message {
+        self.0
+    }
+
+    // Stored as the service stores messages, with its payload hash; returns the message with the
+    // sequence it was given
+    pub async fn insert<DB: DatabaseInterface + ?Sized>(self, db: &DB) -> DbResult<Message> {
+        db.store_message(self.0.with_payload_hash()).await
+    }
+}
+
+// An unused key package of the client, holding a real serialized key package
+#[derive(Debug, Clone)]
+pub struct KeyPackageFixture(KeyPackage);
+
+impl KeyPackageFixture {
+    // For a credential with the client's id as its identity
+    pub fn new(client_id: Uuid) -> Self {
+        Self::for_identity(client_id, client_id.to_string().as_bytes())
+    }
+
+    pub fn for_identity(client_id: Uuid, identity: &[u8]) -> Self {
+        Self(KeyPackage {
+            id: Uuid::new_v4(),
+            client_id,
+            data: key_package_bytes(identity),
+            created_at: timestamps::now(),
+            used: false,
+            used_at: None,
+            deleted_at: None,
+        })
+    }
+
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.0.id = id;
+        self
+    }
+
+    pub fn created_at(mut self, at: DateTime<Utc>) -> Self {
+        self.0.created_at = at;
+        self
+    }
+
+    pub fn used(mut self) -> Self {
+        self.0.used = true;
+        self.0.used_at = Some(timestamps::now());
+        self
+    }
+
+    pub fn build(self) -> KeyPackage {
+        self.0
+    }
+
+    pub async fn insert<DB: DatabaseInterface + ?Sized>(self, db: &DB) -> DbResult<KeyPackage> {
+        db.store_key_package(self.0.clone()).await?;
+        Ok(self.0)
+    }
+}
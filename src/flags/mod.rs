@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use log::error;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::db::{DatabaseInterface, DbError, DbResult, FeatureFlag};
+use crate::metrics::jobs::FEATURE_FLAG_REFRESH_JOB;
+use crate::metrics::JobTracker;
+
+// Bucket (0 to 99) a group falls into for a flag. FNV-1a over the flag name and group id, so
+// it is stable across restarts and instances, and each flag picks a different set of groups.
+pub fn rollout_bucket(name: &str, group_id: Uuid) -> i32 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in name.as_bytes().iter().chain(group_id.as_bytes()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    (hash % 100) as i32
+}
+
+// Whether a flag is on for a group owned by a user. Either may be unknown to the caller.
+pub fn is_enabled_for(flag: &FeatureFlag, group_id: Option<Uuid>, user_id: Option<Uuid>) -> bool {
+    if !flag.enabled {
+        return false;
+    }
+    if flag.percentage >= 100 {
+        return true;
+    }
+
+    group_id.is_some_and(|id| flag.group_ids.contains(&id))
+        || user_id.is_some_and(|id| flag.user_ids.contains(&id))
+        || group_id.is_some_and(|id| rollout_bucket(&flag.name, id) < flag.percentage)
+}
+
+// In-memory snapshot of the feature flags stored in the database. Checking a flag never
+// touches the database; the snapshot is updated by the admin service and refreshed
+// periodically so changes made through other instances are picked up.
+#[derive(Clone, Default)]
+pub struct FeatureFlags {
+    flags: Arc<RwLock<HashMap<String, FeatureFlag>>>,
+}
+
+impl FeatureFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Load every flag from the database
+    pub async fn load<DB: DatabaseInterface + ?Sized>(db: &DB) -> DbResult<Self> {
+        let flags = Self::new();
+        flags.refresh(db).await?;
+        Ok(flags)
+    }
+
+    // Replace the snapshot with the flags currently in the database
+    pub async fn refresh<DB: DatabaseInterface + ?Sized>(&self, db: &DB) -> DbResult<()> {
+        let flags = db.list_feature_flags().await?;
+        *self.flags.write().unwrap() = flags
+            .into_iter()
+            .map(|flag| (flag.name.clone(), flag))
+            .collect();
+        Ok(())
+    }
+
+    // Whether the named flag is on for a group owned by a user. Unknown flags are off.
+    pub fn is_enabled(&self, name: &str, group_id: Option<Uuid>, user_id: Option<Uuid>) -> bool {
+        self.flags
+            .read()
+            .unwrap()
+            .get(name)
+            .is_some_and(|flag| is_enabled_for(flag, group_id, user_id))
+    }
+
+    pub fn get(&self, name: &str) -> Option<FeatureFlag> {
+        self.flags.read().unwrap().get(name).cloned()
+    }
+
+    // Every flag, ordered by name
+    pub fn list(&self) -> Vec<FeatureFlag> {
+        let mut flags: Vec<_> = self.flags.read().unwrap().values().cloned().collect();
+        flags.sort_by(|a, b| a.name.cmp(&b.name));
+        flags
+    }
+
+    pub fn insert(&self, flag: FeatureFlag) {
+        self.flags.write().unwrap().insert(flag.name.clone(), flag);
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.flags.write().unwrap().remove(name);
+    }
+
+    // Refresh the snapshot from the database on a fixed interval, reporting each run to the
+    // job tracker
+    pub fn spawn_refresh<DB: DatabaseInterface + ?Sized + 'static>(
+        &self,
+        db: Arc<DB>,
+        every: Duration,
+        jobs: JobTracker,
+    ) -> JoinHandle<()> {
+        let flags = self.clone();
+        jobs.register(FEATURE_FLAG_REFRESH_JOB, every);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let refresh = async {
+                    flags.refresh(db.as_ref()).await?;
+                    Ok::<_, DbError>(flags.flags.read().unwrap().len() as u64)
+                };
+                if let Err(e) = jobs.track(FEATURE_FLAG_REFRESH_JOB, refresh).await {
+                    error!("Failed to refresh feature flags: {}", e);
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+// Generates primary keys for new rows. Deployments that need ULIDs or
+// snowflake-style ids can implement this and encode them as UUIDs.
+pub trait IdGenerator: Send + Sync {
+    fn generate(&self) -> Uuid;
+}
+
+// Random UUIDv4 ids (the default)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {
+    fn generate(&self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+// Time-ordered UUIDv7 ids, which keep B-tree inserts close to the right edge of the index
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeOrderedIds;
+
+impl IdGenerator for TimeOrderedIds {
+    fn generate(&self) -> Uuid {
+        Uuid::now_v7()
+    }
+}
+
+// Predictable ids counting up from 1, for tests and fixtures
+#[derive(Debug, Default)]
+pub struct SequentialIds {
+    next: AtomicU64,
+}
+
+impl IdGenerator for SequentialIds {
+    fn generate(&self) -> Uuid {
+        Uuid::from_u128(self.next.fetch_add(1, Ordering::Relaxed) as u128 + 1)
+    }
+}
+
+// Look up a built-in generator by its configuration name
+pub fn from_name(name: &str) -> Option<Arc<dyn IdGenerator>> {
+    match name {
+        "uuid_v4" => Some(Arc::new(RandomIds)),
+        "uuid_v7" => Some(Arc::new(TimeOrderedIds)),
+        _ => None,
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::db::{
+    AttestationVerdict, Client, CredentialScheme, DatabaseInterface, DbError, Group, KeyPackage,
+    Membership, MembershipRole,
+};
+use crate::ids::IdGenerator;
+use crate::mls_codec;
+use crate::timestamps;
+
+// Errors that can occur while importing an export bundle
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Failed to read export file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to parse export file: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("Invalid export data: {0}")]
+    InvalidData(String),
+
+    #[error("Unknown client referenced by export: {0}")]
+    UnknownClient(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+}
+
+// Data exported from another delivery service, in a source-neutral JSON format.
+// Byte fields are standard base64 strings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportBundle {
+    // Name of the exporting implementation, for the report only
+    #[serde(default)]
+    pub source: String,
+    #[serde(default)]
+    pub clients: Vec<ImportedClient>,
+    #[serde(default)]
+    pub groups: Vec<ImportedGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedClient {
+    // Identifier of the client in the source system
+    pub external_id: String,
+    // Identifier of the owning user in the source system; clients sharing it share a user_id
+    pub external_user_id: String,
+    // Identity used to build a BasicCredential when no credential is exported
+    #[serde(default)]
+    pub identity: String,
+    // TLS-serialized MLS credential
+    #[serde(default)]
+    pub credential: Option<String>,
+    #[serde(default)]
+    pub scheme: Option<CredentialScheme>,
+    #[serde(default)]
+    pub device_name: String,
+    // TLS-serialized, unused MLS key packages
+    #[serde(default)]
+    pub key_packages: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedGroup {
+    // Identifier of the group in the source system
+    pub external_id: String,
+    // External id of the creating client
+    pub creator: String,
+    #[serde(default)]
+    pub epoch: u64,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub members: Vec<ImportedMember>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedMember {
+    // External id of the member client
+    pub client: String,
+    pub role: MembershipRole,
+}
+
+// Mapping from source identifiers to the ids assigned by this service
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub source: String,
+    pub users: HashMap<String, Uuid>,
+    pub clients: HashMap<String, Uuid>,
+    pub groups: HashMap<String, Uuid>,
+    pub key_packages: usize,
+    pub memberships: usize,
+}
+
+impl ImportBundle {
+    // Load an export bundle from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ImportError> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+// Helper function to decode a base64 field
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, ImportError> {
+    BASE64
+        .decode(value)
+        .map_err(|e| ImportError::InvalidData(format!("{} is not valid base64: {}", field, e)))
+}
+
+// Import every client, key package, group, and membership in the bundle.
+// Imported rows get fresh ids; the returned report maps source ids to them.
+pub async fn import_bundle<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    ids: &dyn IdGenerator,
+    bundle: ImportBundle,
+) -> Result<ImportReport, ImportError> {
+    let mut report = ImportReport {
+        source: bundle.source,
+        ..ImportReport::default()
+    };
+
+    // Clients and their key packages
+    for imported in bundle.clients {
+        if report.clients.contains_key(&imported.external_id) {
+            return Err(ImportError::InvalidData(format!(
+                "Duplicate client {}",
+                imported.external_id
+            )));
+        }
+
+        let user_id = *report
+            .users
+            .entry(imported.external_user_id.clone())
+            .or_insert_with(|| ids.generate());
+
+        // Keep the exported credential, or build a BasicCredential from the identity
+        let credential = match &imported.credential {
+            Some(credential) => decode("credential", credential)?,
+            None => mls_codec::basic_credential(imported.identity.as_bytes())
+                .map_err(|e| ImportError::InvalidData(e.to_string()))?,
+        };
+
+        let client_id = ids.generate();
+        let client = Client {
+            id: client_id,
+            user_id,
+            credential,
+            scheme: imported.scheme.unwrap_or(CredentialScheme::Basic),
+            device_name: imported.device_name,
+            last_seen: timestamps::now(),
+            created_at: timestamps::now(),
+            init_key: None,
+            signature_key: None,
+            is_service: false,
+            attestation_verdict: AttestationVerdict::Unattested,
+            deleted_at: None,
+        };
+        db.register_client(client).await?;
+
+        for key_package in &imported.key_packages {
+            let key_package = KeyPackage {
+                id: ids.generate(),
+                client_id,
+                data: decode("key_package", key_package)?,
+                created_at: timestamps::now(),
+                used: false,
+                used_at: None,
+                deleted_at: None,
+            };
+            db.store_key_package(key_package).await?;
+            report.key_packages += 1;
+        }
+
+        report.clients.insert(imported.external_id, client_id);
+    }
+
+    // Groups and their memberships
+    for imported in bundle.groups {
+        let creator_id = *report
+            .clients
+            .get(&imported.creator)
+            .ok_or_else(|| ImportError::UnknownClient(imported.creator.clone()))?;
+
+        let group_id = ids.generate();
+        let group = Group {
+            id: group_id,
+            creator_id,
+            epoch: imported.epoch as i64,
+            state: imported
+                .state
+                .as_deref()
+                .map(|state| decode("state", state))
+                .transpose()?,
+            mls_group_id: None,
+            tenant_id: None,
+            handle: None,
+            created_at: timestamps::now(),
+            updated_at: timestamps::now(),
+            is_active: true,
+            deleted_at: None,
+        };
+        let mut memberships = Vec::with_capacity(imported.members.len());
+        for member in imported.members {
+            let client_id = *report
+                .clients
+                .get(&member.client)
+                .ok_or_else(|| ImportError::UnknownClient(member.client.clone()))?;
+
+            // Imported members are assumed to be at the exported epoch
+            let membership = Membership {
+                id: ids.generate(),
+                client_id,
+                group_id,
+                role: member.role,
+                added_at: timestamps::now(),
+                removed_at: None,
+                last_acked_epoch: Some(imported.epoch as i64),
+                last_acked_at: None,
+            };
+            memberships.push(membership);
+        }
+
+        let count = memberships.len();
+        db.create_group_with_members(group, memberships).await?;
+        report.memberships += count;
+
+        report.groups.insert(imported.external_id, group_id);
+    }
+
+    Ok(report)
+}
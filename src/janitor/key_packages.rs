@@ -0,0 +1,34 @@
+use crate::db::{DatabaseInterface, DbResult};
+use crate::ids::IdGenerator;
+use crate::notices::{system_message, SystemNotice};
+
+// Find clients with fewer than `min_unused` unused key packages and send each a
+// key package low notice. Returns the number of clients notified.
+pub async fn sweep_low_key_packages<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    ids: &dyn IdGenerator,
+    min_unused: i64,
+) -> DbResult<usize> {
+    let low = db.list_clients_low_on_key_packages(min_unused).await?;
+
+    for inventory in &low {
+        let notice = SystemNotice::KeyPackageLow {
+            client_id: inventory.client_id,
+            remaining: inventory.unused,
+        };
+        db.store_message(system_message(
+            ids,
+            None,
+            None,
+            vec![inventory.client_id],
+            notice,
+        ))
+        .await?;
+
+        // Only notify once until the client publishes a new key package
+        db.mark_key_packages_low_notified(inventory.client_id)
+            .await?;
+    }
+
+    Ok(low.len())
+}
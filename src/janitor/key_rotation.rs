@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+
+use crate::db::{DatabaseInterface, DbResult, StaleEpochGroup};
+use crate::ids::IdGenerator;
+use crate::notices::{notify_group, SystemNotice};
+
+// How long a group's epoch may last before its members are asked to rotate their keys. Groups
+// can set their own limit; this is the one for groups that don't.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyRotationPolicy {
+    pub default_max_epoch_age: Option<chrono::Duration>,
+}
+
+impl KeyRotationPolicy {
+    // The default limit as the database stores it, 0 for none
+    pub fn default_max_age_secs(&self) -> i64 {
+        self.default_max_epoch_age
+            .map_or(0, |age| age.num_seconds())
+    }
+}
+
+// Find groups that haven't committed within their maximum epoch age and ask their members to
+// commit an Update. Each group is reminded once per epoch. Returns the number of groups reminded.
+pub async fn sweep_stale_epochs<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    ids: &dyn IdGenerator,
+    policy: &KeyRotationPolicy,
+    now: DateTime<Utc>,
+) -> DbResult<usize> {
+    let stale = db
+        .list_stale_epoch_groups(policy.default_max_age_secs(), now)
+        .await?;
+
+    for group in &stale {
+        notify_group(db, ids, group.group_id, None, rotation_notice(group)).await?;
+
+        // Only remind once until the group commits again
+        db.mark_rotation_reminded(group.group_id, group.epoch)
+            .await?;
+    }
+
+    Ok(stale.len())
+}
+
+// Build the notice asking a group's members to rotate their keys
+fn rotation_notice(group: &StaleEpochGroup) -> SystemNotice {
+    SystemNotice::KeyRotationDue {
+        group_id: group.group_id,
+        epoch: group.epoch,
+        epoch_started_at: group.epoch_started_at,
+        max_epoch_age_secs: group.max_epoch_age_secs,
+    }
+}
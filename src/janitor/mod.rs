@@ -0,0 +1,312 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tokio::task::JoinHandle;
+
+use crate::db::{DatabaseInterface, DbError, DbResult, EntityKind};
+use crate::events::EventBus;
+use crate::ids::{IdGenerator, RandomIds};
+use crate::metrics::jobs::{
+    BLOB_FORMAT_UPGRADE_JOB, KEY_PACKAGE_RETENTION_JOB, KEY_PACKAGE_SWEEP_JOB,
+    KEY_ROTATION_SWEEP_JOB, MESSAGE_EXPIRY_JOB, MESSAGE_RETENTION_JOB, NONCE_PURGE_JOB,
+    PURGE_SWEEP_JOB, STUCK_MEMBER_SWEEP_JOB, WELCOME_SLA_SWEEP_JOB,
+};
+use crate::metrics::JobTracker;
+use crate::settings::SettingsHandle;
+use crate::timestamps;
+
+pub mod key_packages;
+pub mod key_rotation;
+pub mod retention;
+pub mod stuck_members;
+pub mod welcome_sla;
+
+pub use key_rotation::KeyRotationPolicy;
+pub use retention::RetentionPolicy;
+pub use stuck_members::{StuckMemberAction, StuckMemberPolicy};
+pub use welcome_sla::{StrandedWelcomeHook, WelcomeSlaPolicy};
+
+// Configuration for the background maintenance tasks
+#[derive(Debug, Clone)]
+pub struct JanitorConfig {
+    pub interval: Duration,
+    pub stuck_members: StuckMemberPolicy,
+    pub purge_grace_period: chrono::Duration,
+    // Clients with fewer unused key packages are notified; 0 disables the sweep
+    pub key_package_low_threshold: i64,
+    pub retention: RetentionPolicy,
+    pub key_rotation: KeyRotationPolicy,
+    pub welcome_sla: WelcomeSlaPolicy,
+    // Rows of each table with blobs in an older format rewritten per pass; 0 disables the upgrade
+    pub blob_upgrade_batch: i64,
+}
+
+impl Default for JanitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(300),
+            stuck_members: StuckMemberPolicy::default(),
+            purge_grace_period: chrono::Duration::days(30),
+            key_package_low_threshold: 5,
+            retention: RetentionPolicy::default(),
+            key_rotation: KeyRotationPolicy::default(),
+            welcome_sla: WelcomeSlaPolicy::default(),
+            blob_upgrade_batch: 500,
+        }
+    }
+}
+
+// Periodically runs maintenance sweeps against the database
+pub struct Janitor<DB: DatabaseInterface> {
+    db: Arc<DB>,
+    ids: Arc<dyn IdGenerator>,
+    config: JanitorConfig,
+    settings: Option<SettingsHandle>,
+    jobs: JobTracker,
+    events: EventBus,
+    welcome_hooks: Vec<Arc<dyn StrandedWelcomeHook>>,
+}
+
+impl<DB: DatabaseInterface + 'static> Janitor<DB> {
+    pub fn new(db: Arc<DB>, config: JanitorConfig) -> Self {
+        Self {
+            db,
+            ids: Arc::new(RandomIds),
+            config,
+            settings: None,
+            jobs: JobTracker::default(),
+            events: EventBus::default(),
+            welcome_hooks: Vec::new(),
+        }
+    }
+
+    // Take the sweep policies from the runtime settings on every pass, so they can be
+    // reloaded without a restart. The interval stays as configured.
+    pub fn with_settings(mut self, settings: SettingsHandle) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    // Configuration for the next pass
+    fn current_config(&self) -> JanitorConfig {
+        match &self.settings {
+            Some(settings) => settings.current().janitor_config(self.config.interval),
+            None => self.config.clone(),
+        }
+    }
+
+    // Use a different strategy for generating ids of system messages
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    // Publish push reminders for overdue welcomes on the service's event bus
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    // Also report stranded welcomes to `hook`, besides the notice to the group's admins
+    pub fn with_welcome_hook(mut self, hook: Arc<dyn StrandedWelcomeHook>) -> Self {
+        self.welcome_hooks.push(hook);
+        self
+    }
+
+    // Report each sweep's runs to the tracker
+    pub fn with_jobs(mut self, jobs: JobTracker) -> Self {
+        for job in [
+            STUCK_MEMBER_SWEEP_JOB,
+            KEY_PACKAGE_SWEEP_JOB,
+            KEY_ROTATION_SWEEP_JOB,
+            PURGE_SWEEP_JOB,
+            NONCE_PURGE_JOB,
+            MESSAGE_EXPIRY_JOB,
+        ] {
+            jobs.register(job, self.config.interval);
+        }
+        self.jobs = jobs;
+        self
+    }
+
+    // Run a single pass of every sweep
+    pub async fn run_once(&self) {
+        let config = self.current_config();
+
+        let sweep = async {
+            stuck_members::sweep_stuck_members(
+                self.db.as_ref(),
+                self.ids.as_ref(),
+                &config.stuck_members,
+            )
+            .await
+            .map(|count| count as u64)
+        };
+        match self.jobs.track(STUCK_MEMBER_SWEEP_JOB, sweep).await {
+            Ok(0) => {}
+            Ok(count) => info!("Escalated {} stuck group members", count),
+            Err(e) => error!("Stuck member sweep failed: {}", e),
+        }
+
+        // The key package sweep can be disabled at runtime, and isn't reported while it is
+        if config.key_package_low_threshold > 0 {
+            self.jobs.register(KEY_PACKAGE_SWEEP_JOB, config.interval);
+            let sweep = async {
+                key_packages::sweep_low_key_packages(
+                    self.db.as_ref(),
+                    self.ids.as_ref(),
+                    config.key_package_low_threshold,
+                )
+                .await
+                .map(|count| count as u64)
+            };
+            match self.jobs.track(KEY_PACKAGE_SWEEP_JOB, sweep).await {
+                Ok(0) => {}
+                Ok(count) => info!("Notified {} clients low on key packages", count),
+                Err(e) => error!("Key package sweep failed: {}", e),
+            }
+        } else {
+            self.jobs.unregister(KEY_PACKAGE_SWEEP_JOB);
+        }
+
+        // Groups can set their own epoch age limit, so this runs even without a default one
+        let sweep = async {
+            key_rotation::sweep_stale_epochs(
+                self.db.as_ref(),
+                self.ids.as_ref(),
+                &config.key_rotation,
+                timestamps::now(),
+            )
+            .await
+            .map(|count| count as u64)
+        };
+        match self.jobs.track(KEY_ROTATION_SWEEP_JOB, sweep).await {
+            Ok(0) => {}
+            Ok(count) => info!("Reminded {} groups to rotate their keys", count),
+            Err(e) => error!("Key rotation sweep failed: {}", e),
+        }
+
+        // Welcome delivery is only tracked while a deadline is set, and isn't reported while
+        // it isn't
+        if config.welcome_sla.deadline.is_some() {
+            self.jobs.register(WELCOME_SLA_SWEEP_JOB, config.interval);
+            let sweep = async {
+                let sweep = welcome_sla::sweep_overdue_welcomes(
+                    self.db.as_ref(),
+                    self.ids.as_ref(),
+                    &self.events,
+                    &config.welcome_sla,
+                    &self.welcome_hooks,
+                    timestamps::now(),
+                )
+                .await?;
+                if sweep.reminded > 0 {
+                    info!(
+                        "Sent push reminders for {} overdue welcomes",
+                        sweep.reminded
+                    );
+                }
+                if sweep.stranded > 0 {
+                    warn!(
+                        "Reported {} joiners stranded without their welcomes",
+                        sweep.stranded
+                    );
+                }
+                Ok::<_, DbError>((sweep.reminded + sweep.stranded) as u64)
+            };
+            if let Err(e) = self.jobs.track(WELCOME_SLA_SWEEP_JOB, sweep).await {
+                error!("Welcome delivery sweep failed: {}", e);
+            }
+        } else {
+            self.jobs.unregister(WELCOME_SLA_SWEEP_JOB);
+        }
+
+        match self.jobs.track(PURGE_SWEEP_JOB, self.purge_deleted()).await {
+            Ok(0) => {}
+            Ok(count) => info!("Purged {} soft-deleted rows", count),
+            Err(e) => error!("Purge sweep failed: {}", e),
+        }
+
+        // Nonces of signed requests only matter within their replay window
+        let purge = self.db.purge_expired_request_nonces(timestamps::now());
+        match self.jobs.track(NONCE_PURGE_JOB, purge).await {
+            Ok(0) => {}
+            Ok(count) => info!("Purged {} expired request nonces", count),
+            Err(e) => error!("Request nonce purge failed: {}", e),
+        }
+
+        // Messages past the TTL their sender asked for are gone for good, not soft-deleted
+        let purge = self.db.purge_expired_messages(timestamps::now());
+        match self.jobs.track(MESSAGE_EXPIRY_JOB, purge).await {
+            Ok(0) => {}
+            Ok(count) => info!("Purged {} expired messages", count),
+            Err(e) => error!("Message expiry purge failed: {}", e),
+        }
+
+        // Retention sweeps are off until a retention is configured, and aren't reported
+        // while they are
+        let now = timestamps::now();
+        if !config.retention.messages.is_empty() {
+            self.jobs.register(MESSAGE_RETENTION_JOB, config.interval);
+            let sweep = retention::sweep_messages(self.db.as_ref(), &config.retention, now);
+            match self.jobs.track(MESSAGE_RETENTION_JOB, sweep).await {
+                Ok(0) => {}
+                Ok(count) => info!("Purged {} messages past their retention", count),
+                Err(e) => error!("Message retention sweep failed: {}", e),
+            }
+        } else {
+            self.jobs.unregister(MESSAGE_RETENTION_JOB);
+        }
+
+        if config.retention.used_key_packages.is_some() {
+            self.jobs
+                .register(KEY_PACKAGE_RETENTION_JOB, config.interval);
+            let sweep = retention::sweep_key_packages(self.db.as_ref(), &config.retention, now);
+            match self.jobs.track(KEY_PACKAGE_RETENTION_JOB, sweep).await {
+                Ok(0) => {}
+                Ok(count) => info!("Purged {} used key packages past their retention", count),
+                Err(e) => error!("Key package retention sweep failed: {}", e),
+            }
+        } else {
+            self.jobs.unregister(KEY_PACKAGE_RETENTION_JOB);
+        }
+
+        // Blobs in an older format are rewritten a batch at a time, so a format change rolls
+        // forward without a migration rewriting every row at once
+        if config.blob_upgrade_batch > 0 {
+            self.jobs.register(BLOB_FORMAT_UPGRADE_JOB, config.interval);
+            let upgrade = self.db.upgrade_blob_formats(config.blob_upgrade_batch);
+            match self.jobs.track(BLOB_FORMAT_UPGRADE_JOB, upgrade).await {
+                Ok(0) => {}
+                Ok(count) => info!("Rewrote {} rows with blobs in an older format", count),
+                Err(e) => error!("Blob format upgrade failed: {}", e),
+            }
+        } else {
+            self.jobs.unregister(BLOB_FORMAT_UPGRADE_JOB);
+        }
+    }
+
+    // Hard-delete soft-deleted entities whose grace period has elapsed
+    pub async fn purge_deleted(&self) -> DbResult<u64> {
+        let cutoff = timestamps::now() - self.current_config().purge_grace_period;
+
+        let mut purged = 0;
+        for kind in EntityKind::PURGE_ORDER {
+            purged += self.db.purge_deleted_before(kind, cutoff).await?;
+        }
+
+        Ok(purged)
+    }
+
+    // Spawn the janitor loop onto the tokio runtime
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.interval);
+            loop {
+                interval.tick().await;
+                self.run_once().await;
+            }
+        })
+    }
+}
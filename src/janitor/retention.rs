@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::db::{DatabaseInterface, DbResult, MessageType};
+
+// How long delivered messages and used key packages are kept. Types without a retention, and
+// used key packages without one, are kept until something else deletes them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub messages: BTreeMap<MessageType, chrono::Duration>,
+    pub used_key_packages: Option<chrono::Duration>,
+}
+
+// Hard-delete the messages older than their type's retention. Welcomes go once they're old
+// enough, delivered or not, since a joiner that never fetched one needs a resend anyway; other
+// messages only once every recipient has acknowledged them. Returns the number deleted.
+pub async fn sweep_messages<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> DbResult<u64> {
+    let mut purged = 0;
+    for (&message_type, &retention) in &policy.messages {
+        let delivered_only = message_type != MessageType::Welcome;
+        purged += db
+            .purge_retained_messages(message_type, now - retention, delivered_only)
+            .await?;
+    }
+    Ok(purged)
+}
+
+// Hard-delete key packages used longer ago than their retention. Returns the number deleted.
+pub async fn sweep_key_packages<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> DbResult<u64> {
+    match policy.used_key_packages {
+        Some(retention) => db.purge_used_key_packages(now - retention).await,
+        None => Ok(0),
+    }
+}
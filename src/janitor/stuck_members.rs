@@ -0,0 +1,103 @@
+use uuid::Uuid;
+
+use crate::db::{DatabaseInterface, DbResult, MembershipRole, Message, StuckMembership};
+use crate::ids::IdGenerator;
+use crate::notices::{system_message, StuckMemberDetails, SystemNotice};
+use crate::timestamps;
+
+// What to do once a member is found to be stuck
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StuckMemberAction {
+    // Tell the group admins about the stuck member
+    NotifyAdmins,
+    // Ask the group admins' clients to commit a Remove for the stuck member.
+    // The delivery service can't author MLS proposals itself, so this is
+    // delivered as a system message the admin client acts on.
+    RequestRemoval,
+}
+
+// Thresholds after which a member is considered stuck
+#[derive(Debug, Clone)]
+pub struct StuckMemberPolicy {
+    pub max_epochs_behind: i64,
+    pub max_days_behind: i64,
+    pub action: StuckMemberAction,
+}
+
+impl Default for StuckMemberPolicy {
+    fn default() -> Self {
+        Self {
+            max_epochs_behind: 50,
+            max_days_behind: 14,
+            action: StuckMemberAction::NotifyAdmins,
+        }
+    }
+}
+
+// Find stuck members and enqueue a system message to their group's admins.
+// Returns the number of members escalated.
+pub async fn sweep_stuck_members<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    ids: &dyn IdGenerator,
+    policy: &StuckMemberPolicy,
+) -> DbResult<usize> {
+    let idle_since = timestamps::now() - chrono::Duration::days(policy.max_days_behind);
+    let stuck = db
+        .list_stuck_memberships(policy.max_epochs_behind, idle_since)
+        .await?;
+
+    let mut escalated = 0;
+    for member in stuck {
+        let group_id = member.membership.group_id;
+
+        // Admins of the group receive the notice
+        let admins: Vec<Uuid> = db
+            .list_memberships_by_group(group_id)
+            .await?
+            .into_iter()
+            .filter(|m| m.removed_at.is_none() && m.role == MembershipRole::Admin)
+            .map(|m| m.client_id)
+            .filter(|client_id| *client_id != member.membership.client_id)
+            .collect();
+
+        if !admins.is_empty() {
+            db.store_message(stuck_member_notice(ids, &member, policy.action, admins))
+                .await?;
+        }
+
+        // Only escalate once until the member makes progress again
+        db.mark_membership_escalated(member.membership.id).await?;
+        escalated += 1;
+    }
+
+    Ok(escalated)
+}
+
+// Build the system message describing a stuck member
+fn stuck_member_notice(
+    ids: &dyn IdGenerator,
+    member: &StuckMembership,
+    action: StuckMemberAction,
+    admins: Vec<Uuid>,
+) -> Message {
+    let last_acked_epoch = member.membership.last_acked_epoch.unwrap_or_default();
+    let details = StuckMemberDetails {
+        membership_id: member.membership.id,
+        client_id: member.membership.client_id,
+        last_acked_epoch,
+        current_epoch: member.group_epoch,
+        epochs_behind: member.group_epoch - last_acked_epoch,
+    };
+    let notice = match action {
+        StuckMemberAction::NotifyAdmins => SystemNotice::MemberStuck(details),
+        StuckMemberAction::RequestRemoval => SystemNotice::MemberRemovalRequested(details),
+    };
+
+    system_message(
+        ids,
+        Some(member.membership.group_id),
+        Some(member.group_epoch),
+        admins,
+        notice,
+    )
+}
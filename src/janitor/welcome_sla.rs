@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::db::{DatabaseInterface, DbResult, MembershipRole, Message, OverdueWelcome};
+use crate::events::{DomainEvent, EventBus};
+use crate::ids::IdGenerator;
+use crate::notices::{system_message, StrandedJoiner, SystemNotice};
+
+// How long a joining member has to acknowledge its welcome, and how many push reminders it gets
+// before the welcome is reported stranded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WelcomeSlaPolicy {
+    // None disables the sweep
+    pub deadline: Option<chrono::Duration>,
+    pub push_retries: i64,
+}
+
+impl Default for WelcomeSlaPolicy {
+    fn default() -> Self {
+        Self {
+            deadline: None,
+            push_retries: 2,
+        }
+    }
+}
+
+// What one pass of the sweep did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WelcomeSweep {
+    // Push reminders published for overdue welcomes
+    pub reminded: usize,
+    // Joiners reported stranded after their reminders ran out
+    pub stranded: usize,
+}
+
+// Receives the joiners each sweep finds stranded
+#[async_trait]
+pub trait StrandedWelcomeHook: Send + Sync {
+    async fn notify(&self, stranded: &[OverdueWelcome]);
+}
+
+// Hook that POSTs the stranded joiners as JSON to a URL
+pub struct WebhookStrandedWelcomeHook {
+    client: reqwest::Client,
+    url: String,
+}
+
+// JSON body posted by `WebhookStrandedWelcomeHook`
+#[derive(Debug, Serialize)]
+struct StrandedWelcomeAlert<'a> {
+    stranded: &'a [OverdueWelcome],
+}
+
+impl WebhookStrandedWelcomeHook {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("HTTP client configuration is valid"),
+            url: url.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl StrandedWelcomeHook for WebhookStrandedWelcomeHook {
+    async fn notify(&self, stranded: &[OverdueWelcome]) {
+        let result = self
+            .client
+            .post(&self.url)
+            .json(&StrandedWelcomeAlert { stranded })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            error!(
+                "Failed to deliver the alert for {} stranded welcomes: {}",
+                stranded.len(),
+                e
+            );
+        }
+    }
+}
+
+// Find welcomes their joiners haven't acknowledged within the deadline. Each gets a push
+// reminder published every deadline, up to the policy's retries; after that the group's admins
+// are told, as are the hooks, and the welcome isn't looked at again until a new one is stored.
+pub async fn sweep_overdue_welcomes<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    ids: &dyn IdGenerator,
+    events: &EventBus,
+    policy: &WelcomeSlaPolicy,
+    hooks: &[Arc<dyn StrandedWelcomeHook>],
+    now: DateTime<Utc>,
+) -> DbResult<WelcomeSweep> {
+    let Some(deadline) = policy.deadline else {
+        return Ok(WelcomeSweep::default());
+    };
+    let overdue = db.list_overdue_welcomes(now - deadline).await?;
+
+    let mut sweep = WelcomeSweep::default();
+    let mut stranded: BTreeMap<Uuid, Vec<OverdueWelcome>> = BTreeMap::new();
+    for welcome in overdue {
+        // Reminders are a deadline apart
+        let waiting_since = welcome.last_reminded_at.unwrap_or(welcome.stored_at);
+        if waiting_since + deadline > now {
+            continue;
+        }
+
+        if welcome.reminders < policy.push_retries {
+            events.publish(DomainEvent::WelcomeReminder {
+                message_id: welcome.message_id,
+                group_id: welcome.group_id,
+                client_id: welcome.client_id,
+                attempt: welcome.reminders + 1,
+            });
+            db.record_welcome_reminder(welcome.membership_id, now)
+                .await?;
+            sweep.reminded += 1;
+        } else {
+            stranded.entry(welcome.group_id).or_default().push(welcome);
+        }
+    }
+
+    let mut reported = Vec::new();
+    for (group_id, joiners) in stranded {
+        if let Some(notice) = stranded_notice(db, ids, group_id, &joiners).await? {
+            db.store_message(notice).await?;
+        }
+        for joiner in &joiners {
+            db.mark_welcome_escalated(joiner.membership_id).await?;
+        }
+        reported.extend(joiners);
+    }
+
+    sweep.stranded = reported.len();
+    if !reported.is_empty() {
+        for hook in hooks {
+            hook.notify(&reported).await;
+        }
+    }
+    Ok(sweep)
+}
+
+// Build the notice listing a group's stranded joiners, for its admins or, without any, its
+// other members. None if the joiners are all the group has.
+async fn stranded_notice<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    ids: &dyn IdGenerator,
+    group_id: Uuid,
+    joiners: &[OverdueWelcome],
+) -> DbResult<Option<Message>> {
+    let members: Vec<_> = db
+        .list_memberships_by_group(group_id)
+        .await?
+        .into_iter()
+        .filter(|m| m.removed_at.is_none())
+        .filter(|m| !joiners.iter().any(|j| j.client_id == m.client_id))
+        .collect();
+    let mut recipients: Vec<Uuid> = members
+        .iter()
+        .filter(|m| m.role == MembershipRole::Admin)
+        .map(|m| m.client_id)
+        .collect();
+    if recipients.is_empty() {
+        recipients = members.iter().map(|m| m.client_id).collect();
+    }
+    if recipients.is_empty() {
+        return Ok(None);
+    }
+
+    let notice = SystemNotice::WelcomesStranded {
+        group_id,
+        joiners: joiners
+            .iter()
+            .map(|j| StrandedJoiner {
+                membership_id: j.membership_id,
+                client_id: j.client_id,
+                message_id: j.message_id,
+                stored_at: j.stored_at,
+                reminders: j.reminders,
+            })
+            .collect(),
+    };
+    Ok(Some(system_message(
+        ids,
+        Some(group_id),
+        None,
+        recipients,
+        notice,
+    )))
+}
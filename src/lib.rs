@@ -1,5 +1,27 @@
+pub mod attestation;
+pub mod auth;
+pub mod cli;
+pub mod config;
 pub mod db;
+pub mod error;
+pub mod events;
+#[cfg(feature = "test-utils")]
+pub mod fixtures;
+pub mod flags;
+pub mod ids;
+pub mod import;
+pub mod janitor;
+pub mod metrics;
+pub mod mls_codec;
+pub mod notices;
+pub mod redact;
+pub mod secrets;
+pub mod server;
 pub mod service;
+pub mod settings;
+pub mod timestamps;
+pub mod tls;
+pub mod trace;
 
 // Re-export the service module
 pub use service::*;
@@ -1,84 +1,314 @@
+mod attestation;
+mod auth;
+mod cli;
+mod config;
 mod db;
+mod error;
+mod events;
+mod flags;
+mod ids;
+mod import;
+mod janitor;
+mod metrics;
+mod mls_codec;
+mod notices;
+mod redact;
+mod secrets;
+mod server;
 mod service;
+mod settings;
+mod timestamps;
+mod tls;
+mod trace;
 
 use std::env;
 use std::error::Error;
-use std::net::SocketAddr;
 use std::sync::Arc;
 
+use clap::Parser;
 use dotenv::dotenv;
-use log::info;
-use pretty_env_logger;
-use sqlx::postgres::PgPoolOptions;
-use tonic::transport::Server;
-use tonic_reflection::server::Builder as ReflectionBuilder;
-use tower_http::cors::{Any, CorsLayer};
-
-use crate::service::mls;
-use crate::service::mls::mls_delivery_service_server::MlsDeliveryServiceServer;
+use log::{error, info, warn, LevelFilter};
+use sqlx::postgres::PgConnectOptions;
+
+use crate::cli::{Cli, Command};
+use crate::config::Config;
+use crate::db::{DatabaseInterface, Workload};
+use crate::events::fanout::{MembershipFanout, MessageFanout};
+use crate::events::EventBus;
+use crate::mls_codec::MlsCrypto;
+use crate::secrets::{SecretResolver, VaultClient, VaultConfig};
+use crate::server::ServerBuilder;
+use crate::service::server_info::ServerInfo;
 use crate::service::MLSServiceImpl;
+use crate::settings::SettingsHandle;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // Initialize logger
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info");
+    // Parse the command line first, so --help and usage errors print before anything starts
+    let cli = Cli::parse();
+    let command = cli.command();
+
+    // Initialize logger. RUST_LOG can still set per-module filters, while the overall
+    // level comes from the runtime settings so it can be changed without a restart.
+    let mut logger = pretty_env_logger::formatted_builder();
+    logger.filter_level(LevelFilter::Trace);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        logger.parse_filters(&filters);
     }
-    pretty_env_logger::init();
-    info!("Starting MLS Delivery Service");
+    logger.init();
+    log::set_max_level(LevelFilter::Info);
+    info!(
+        "Starting MLS Delivery Service: {}",
+        ServerInfo::new(&MlsCrypto::default()).banner()
+    );
 
     // Load environment variables from .env file if present
     dotenv().ok();
 
-    // Get configuration from environment variables
-    let addr: SocketAddr = env::var("ADDR")
-        .unwrap_or_else(|_| "0.0.0.0:50051".to_string())
+    // Startup configuration, from CONFIG_FILE overridden by the environment. Every invalid value
+    // is reported before exiting.
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Runtime settings, reloaded from the environment and SETTINGS_FILE on SIGHUP or
+    // ReloadSettings; the config's retention and limits are their defaults
+    let settings = SettingsHandle::load_with_defaults(
+        config.settings_defaults(),
+        config.settings_file.clone(),
+    )
+    .expect("Invalid runtime settings");
+    settings.spawn_log_level_watcher();
+    settings
+        .spawn_sighup_listener()
+        .expect("Could not listen for SIGHUP");
+
+    // Secret-bearing values may be env:, file: or kms: references, resolved once here
+    let secrets = SecretResolver::from_env();
+
+    // With --dev everything is kept in memory, for demos and local client development
+    if cli.dev {
+        #[cfg(feature = "memory-db")]
+        {
+            warn!("Running with the in-memory database; nothing is persisted");
+            let db = Arc::new(db::memory::MemoryDatabase::new());
+            return serve(
+                db.clone(),
+                db,
+                command,
+                &config,
+                settings,
+                secrets,
+                EventBus::default(),
+            )
+            .await;
+        }
+        #[cfg(not(feature = "memory-db"))]
+        return Err("--dev needs a build with the memory-db feature".into());
+    }
+
+    // Get required database connection string, and optionally the password kept out of it
+    let database_url = config
+        .database
+        .url
+        .as_ref()
+        .expect("DATABASE_URL (database.url in CONFIG_FILE) is required")
+        .resolve("DATABASE_URL", &secrets)?;
+    let database_password = config
+        .database
+        .password
+        .as_ref()
+        .map(|password| password.resolve("DATABASE_PASSWORD", &secrets))
+        .transpose()?;
+
+    // Optional master key the tenants' storage keys are derived from
+    let storage_encryption = config
+        .database
+        .encryption_key
+        .as_ref()
+        .map(|key| key.resolve("DATABASE_ENCRYPTION_KEY", &secrets))
+        .transpose()?
+        .map(|key| db::MasterKey::parse(&key))
+        .transpose()?
+        .map(|key| Arc::new(db::StorageEncryption::new(key)));
+
+    // Optional schema and table prefix, for sharing a database server between instances
+    let namespace = config
+        .database
+        .namespace()
+        .expect("Invalid DATABASE_SCHEMA or TABLE_PREFIX");
+
+    // Set up connection pools with PostgreSQL, one per workload
+    let mut connect_options: PgConnectOptions = database_url
         .parse()
-        .expect("Invalid address format in ADDR environment variable");
+        .expect("Invalid DATABASE_URL connection string");
+    if let Some(password) = &database_password {
+        connect_options = connect_options.password(password);
+    }
+    let mut pool_config = config.database.pool_config();
 
-    // Get required database connection string
-    let database_url =
-        env::var("DATABASE_URL").expect("DATABASE_URL environment variable is required");
+    // With Vault, connect with short-lived credentials instead, and recycle connections so
+    // none outlives its lease
+    let vault = match VaultConfig::from_env(&secrets)? {
+        Some(config) => {
+            let client = VaultClient::new(config);
+            let credentials = client
+                .database_credentials()
+                .await
+                .expect("Could not get database credentials from Vault");
+            connect_options = credentials.apply(connect_options);
+            pool_config.max_lifetime = Some(credentials.connection_max_lifetime());
+            Some((client, credentials))
+        }
+        None => None,
+    };
 
-    // Set up connection pool with PostgreSQL
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
+    let connect_options = namespace.connect_options(connect_options);
+    let pool = pool_config
+        .pool_options(Workload::Interactive)
+        .connect_with(connect_options.clone())
+        .await
+        .expect("Could not connect to database");
+    let background_pool = pool_config
+        .pool_options(Workload::Background)
+        .connect_with(connect_options)
         .await
         .expect("Could not connect to database");
+    if let Some((client, credentials)) = vault {
+        client.spawn_rotation(vec![pool.clone(), background_pool.clone()], credentials);
+    }
 
-    // Initialize the database interface
-    let db = Arc::new(db::PostgresDatabase::new(pool));
+    // Initialize the database interfaces. Client-facing RPCs use one, the background jobs the
+    // other, so a long sweep can't take the connections clients need.
+    let mut db = db::PostgresDatabase::new(pool).with_namespace(namespace.clone());
+    let mut background_db = db::PostgresDatabase::new(background_pool).with_namespace(namespace);
 
-    // Run migrations
-    info!("Running database migrations");
-    db.migrate_clients_table()
-        .await
-        .expect("Failed to migrate clients table");
-
-    // Create the MLS service implementation
-    let mls_service = MLSServiceImpl::new(db);
-
-    // Create a CORS layer that allows any origin
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    // Setup the gRPC server with reflection
-    info!("Starting MLS Delivery Service on {}", addr);
-
-    // Create the reflection service using your file descriptor set
-    let reflection_service = ReflectionBuilder::configure()
-        .register_encoded_file_descriptor_set(mls::FILE_DESCRIPTOR_SET)
-        .build_v1()
-        .unwrap();
-
-    Server::builder()
-        .layer(cors)
-        .add_service(reflection_service)
-        .add_service(MlsDeliveryServiceServer::new(mls_service))
-        .serve(addr)
+    // With several replicas, every instance announces the messages it stores so the others can
+    // stream them to their subscribers
+    let message_fanout = config.database.message_fanout;
+    if message_fanout {
+        db = db.with_message_notifications();
+        background_db = background_db.with_message_notifications();
+    }
+
+    // Both handles share the tenant keys, so destroying one through either drops it from both
+    if let Some(encryption) = storage_encryption {
+        info!("Encrypting tenants' group states and message payloads at rest");
+        db = db.with_storage_encryption(encryption.clone());
+        background_db = background_db.with_storage_encryption(encryption);
+    }
+    let db = Arc::new(db);
+    let background_db = Arc::new(background_db);
+
+    // Run migrations, unless the schema is managed externally; `migrate` runs them regardless
+    // and exits
+    if config.database.run_migrations || command == Command::Migrate {
+        info!("Running database migrations");
+        db.run_migrations()
+            .await
+            .expect("Failed to run database migrations");
+    } else {
+        info!("Skipping database migrations");
+    }
+    if command == Command::Migrate {
+        return Ok(());
+    }
+
+    // Warn about indexes the schema is missing; queries still work, just slowly
+    match db.missing_indexes().await {
+        Ok(missing) => {
+            for (table, columns) in missing {
+                warn!(
+                    "Missing index on {} ({}); expect slow queries on large tables",
+                    table,
+                    columns.join(", ")
+                );
+            }
+        }
+        Err(e) => warn!("Could not check database indexes: {}", e),
+    }
+
+    let events = EventBus::default();
+    if message_fanout {
+        MessageFanout::new(db.clone(), events.clone()).spawn();
+    }
+    // Every instance announces the membership changes it makes, so the others drop the member
+    // sets they cached
+    MembershipFanout::new(db.clone(), events.clone()).spawn();
+
+    serve(
+        db,
+        background_db,
+        command,
+        &config,
+        settings,
+        secrets,
+        events,
+    )
+    .await
+}
+
+// Serve the delivery and admin services from a database, or run a one-off command against it;
+// the background jobs use their own connection to it
+async fn serve<DB: DatabaseInterface + 'static>(
+    db: Arc<DB>,
+    background_db: Arc<DB>,
+    command: Command,
+    config: &Config,
+    settings: SettingsHandle,
+    secrets: SecretResolver,
+    events: EventBus,
+) -> Result<(), Box<dyn Error>> {
+    // Strategy for generating ids of new rows
+    let ids = ids::from_name(&config.server.id_generator).expect("Invalid ID_GENERATOR");
+
+    match command {
+        Command::Serve => {}
+        // Nothing to migrate in memory
+        Command::Migrate => return Ok(()),
+        // Import data exported from another delivery service and exit
+        Command::Import { path } => {
+            info!("Importing {}", path.display());
+
+            let bundle = import::ImportBundle::from_file(&path)?;
+            let report = import::import_bundle(db.as_ref(), ids.as_ref(), bundle).await?;
+            info!(
+                "Imported {} clients, {} key packages, {} groups, {} memberships",
+                report.clients.len(),
+                report.key_packages,
+                report.groups.len(),
+                report.memberships
+            );
+
+            // Print the id mapping so the source system can be re-pointed
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            return Ok(());
+        }
+        // Run an admin task through the admin service and exit
+        Command::Admin(admin) => {
+            let service = MLSServiceImpl::new(db)
+                .with_validation_policy(config.validation)
+                .with_id_generator(ids)
+                .with_event_bus(events)
+                .with_settings(settings);
+            let report = cli::admin::run(&service, admin)
+                .await
+                .map_err(|status| status.message().to_string())?;
+            println!("{}", report);
+            return Ok(());
+        }
+    }
+
+    ServerBuilder::new(config.clone(), db)
+        .with_background_db(background_db)
+        .with_settings(settings)
+        .with_secrets(secrets)
+        .with_event_bus(events)
+        .serve()
         .await?;
 
     Ok(())
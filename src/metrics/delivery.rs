@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::db::{Message, MessageType};
+
+// Upper bounds of the delivery latency buckets, in seconds: from messages picked up by a
+// subscriber right away to members coming back online after a day. Later deliveries fall in the
+// final +Inf bucket.
+pub const DELIVERY_BUCKETS_SECS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0, 21600.0, 86400.0,
+];
+
+// Name of the exported Prometheus histogram
+pub const DELIVERY_LATENCY_METRIC: &str = "mls_delivery_latency_seconds";
+
+// Deliveries of one message type: a count per bucket (the last one +Inf) and the sum of their
+// latencies
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryHistogram {
+    pub counts: Vec<u64>,
+    pub sum_secs: f64,
+}
+
+impl Default for DeliveryHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; DELIVERY_BUCKETS_SECS.len() + 1],
+            sum_secs: 0.0,
+        }
+    }
+}
+
+impl DeliveryHistogram {
+    pub fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let bucket = DELIVERY_BUCKETS_SECS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(DELIVERY_BUCKETS_SECS.len());
+        self.counts[bucket] += 1;
+        self.sum_secs += secs;
+    }
+
+    // Number of deliveries observed
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+// End-to-end delivery latency per message type: how long after a message was stored it was
+// handed to a recipient, by a fetch or a subscription. Shared by the delivery RPCs that hand
+// messages out and the endpoint exporting it.
+#[derive(Clone, Default)]
+pub struct DeliveryMetrics {
+    histograms: Arc<Mutex<HashMap<MessageType, DeliveryHistogram>>>,
+}
+
+impl DeliveryMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record a message handed to a recipient at the given time. Clock skew between instances
+    // can make it look delivered before it was stored; that counts as no latency.
+    pub fn record_delivery(&self, message: &Message, at: DateTime<Utc>) {
+        let latency = (at - message.created_at).to_std().unwrap_or_default();
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry(message.message_type)
+            .or_default()
+            .observe(latency);
+    }
+
+    // Deliveries of a message type so far
+    pub fn histogram(&self, message_type: MessageType) -> DeliveryHistogram {
+        self.histograms
+            .lock()
+            .unwrap()
+            .get(&message_type)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // The histograms in the Prometheus text exposition format, labelled by message type.
+    // Types nothing was delivered of yet are left out.
+    pub fn render_prometheus(&self, out: &mut String) {
+        let histograms = self.histograms.lock().unwrap();
+        let _ = writeln!(
+            out,
+            "# HELP {} Time from a message being stored to its delivery to a recipient",
+            DELIVERY_LATENCY_METRIC
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", DELIVERY_LATENCY_METRIC);
+
+        for message_type in MessageType::ALL {
+            let Some(histogram) = histograms.get(message_type) else {
+                continue;
+            };
+            let bounds = DELIVERY_BUCKETS_SECS
+                .iter()
+                .map(|bound| bound.to_string())
+                .chain(["+Inf".to_string()]);
+            let mut cumulative = 0;
+            for (bound, count) in bounds.zip(&histogram.counts) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{message_type=\"{}\",le=\"{}\"}} {}",
+                    DELIVERY_LATENCY_METRIC, message_type, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_sum{{message_type=\"{}\"}} {}",
+                DELIVERY_LATENCY_METRIC, message_type, histogram.sum_secs
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{message_type=\"{}\"}} {}",
+                DELIVERY_LATENCY_METRIC, message_type, cumulative
+            );
+        }
+    }
+}
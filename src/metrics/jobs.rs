@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use super::RpcMetrics;
+use crate::timestamps;
+
+// Names of the background jobs, also the method names their durations are recorded under
+pub const STUCK_MEMBER_SWEEP_JOB: &str = "StuckMemberSweep";
+pub const KEY_PACKAGE_SWEEP_JOB: &str = "KeyPackageSweep";
+pub const KEY_ROTATION_SWEEP_JOB: &str = "KeyRotationSweep";
+pub const WELCOME_SLA_SWEEP_JOB: &str = "WelcomeSlaSweep";
+pub const PURGE_SWEEP_JOB: &str = "PurgeSweep";
+pub const NONCE_PURGE_JOB: &str = "NoncePurge";
+pub const MESSAGE_EXPIRY_JOB: &str = "MessageExpiry";
+pub const MESSAGE_RETENTION_JOB: &str = "MessageRetention";
+pub const KEY_PACKAGE_RETENTION_JOB: &str = "KeyPackageRetention";
+pub const FEATURE_FLAG_REFRESH_JOB: &str = "FeatureFlagRefresh";
+pub const VERBOSE_TRACE_REFRESH_JOB: &str = "VerboseTraceRefresh";
+pub const CLIENT_KEY_REFRESH_JOB: &str = "ClientKeyRefresh";
+pub const BLOB_FORMAT_UPGRADE_JOB: &str = "BlobFormatUpgrade";
+
+// A job is stalled once it hasn't succeeded for this many of its intervals
+pub const STALL_INTERVALS: u32 = 3;
+
+// Health of one background job: its most recent run and running totals
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobStatus {
+    pub name: String,
+    // How often the job is scheduled to run
+    pub interval: Duration,
+    pub registered_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_duration: Duration,
+    // Rows the last run processed, or 0 if it failed
+    pub last_rows: u64,
+    pub last_error: Option<String>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub runs: u64,
+    pub failures: u64,
+    pub rows_processed: u64,
+}
+
+impl JobStatus {
+    // Whether the job hasn't succeeded (or, if it never has, hasn't since it was registered)
+    // for STALL_INTERVALS of its intervals
+    pub fn is_stalled(&self, now: DateTime<Utc>) -> bool {
+        let since = self.last_success_at.unwrap_or(self.registered_at);
+        let allowed = chrono::Duration::from_std(self.interval * STALL_INTERVALS)
+            .unwrap_or(chrono::Duration::MAX);
+        now.signed_duration_since(since) > allowed
+    }
+}
+
+// Shared record of the background jobs' runs, for the admin service. Durations are also
+// recorded in the RPC metrics, if given, so jobs can have latency objectives.
+#[derive(Clone, Default)]
+pub struct JobTracker {
+    jobs: Arc<Mutex<BTreeMap<String, JobStatus>>>,
+    metrics: Option<RpcMetrics>,
+}
+
+impl JobTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_metrics(mut self, metrics: RpcMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Start tracking a job scheduled every `interval`, so it's reported (and can stall) before
+    // its first run
+    pub fn register(&self, name: &str, interval: Duration) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let status = jobs.entry(name.to_string()).or_insert_with(|| JobStatus {
+            name: name.to_string(),
+            interval,
+            registered_at: timestamps::now(),
+            last_run_at: None,
+            last_duration: Duration::ZERO,
+            last_rows: 0,
+            last_error: None,
+            last_success_at: None,
+            runs: 0,
+            failures: 0,
+            rows_processed: 0,
+        });
+        status.interval = interval;
+    }
+
+    // Stop reporting a job, e.g. once it's disabled
+    pub fn unregister(&self, name: &str) {
+        self.jobs.lock().unwrap().remove(name);
+    }
+
+    // Record a run that started at `started_at`, with the rows it processed or its error
+    pub fn record(
+        &self,
+        name: &str,
+        started_at: DateTime<Utc>,
+        duration: Duration,
+        outcome: Result<u64, String>,
+    ) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record(name, duration);
+        }
+
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(status) = jobs.get_mut(name) else {
+            return;
+        };
+        status.last_run_at = Some(started_at);
+        status.last_duration = duration;
+        status.runs += 1;
+        match outcome {
+            Ok(rows) => {
+                status.last_rows = rows;
+                status.last_error = None;
+                status.last_success_at = Some(started_at);
+                status.rows_processed += rows;
+            }
+            Err(error) => {
+                status.last_rows = 0;
+                status.last_error = Some(error);
+                status.failures += 1;
+            }
+        }
+    }
+
+    // Run a job, recording its duration and outcome
+    pub async fn track<E: Display>(
+        &self,
+        name: &str,
+        run: impl Future<Output = Result<u64, E>>,
+    ) -> Result<u64, E> {
+        let started_at = timestamps::now();
+        let started = Instant::now();
+        let result = run.await;
+        self.record(
+            name,
+            started_at,
+            started.elapsed(),
+            result.as_ref().map(|rows| *rows).map_err(|e| e.to_string()),
+        );
+        result
+    }
+
+    // Status of every registered job, ordered by name
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs.lock().unwrap().values().cloned().collect()
+    }
+}
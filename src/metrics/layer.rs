@@ -0,0 +1,88 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use tower::{Layer, Service};
+
+use super::RpcMetrics;
+
+// Method name of a gRPC request path ("/mls.v1.MlsDeliveryService/FetchMessages" ->
+// "FetchMessages"), if it is one
+pub fn rpc_method(path: &str) -> Option<&str> {
+    let (_, method) = path.trim_start_matches('/').split_once('/')?;
+    (!method.is_empty() && !method.contains('/')).then_some(method)
+}
+
+// Tower layer that records how long each RPC takes to produce its response. For streaming
+// RPCs that is the time until the stream starts.
+#[derive(Clone)]
+pub struct MetricsLayer {
+    metrics: RpcMetrics,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: RpcMetrics) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = Metrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Metrics {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Metrics<S> {
+    inner: S,
+    metrics: RpcMetrics,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Metrics<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Timed<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let method = rpc_method(request.uri().path()).map(str::to_string);
+        Timed {
+            inner: Box::pin(self.inner.call(request)),
+            started: Instant::now(),
+            method,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+// Response future that records its latency when it completes
+pub struct Timed<F> {
+    inner: Pin<Box<F>>,
+    started: Instant,
+    method: Option<String>,
+    metrics: RpcMetrics,
+}
+
+impl<F: Future> Future for Timed<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let output = std::task::ready!(self.inner.as_mut().poll(cx));
+        if let Some(method) = self.method.take() {
+            self.metrics.record(&method, self.started.elapsed());
+        }
+        Poll::Ready(output)
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::timestamps;
+
+pub mod delivery;
+pub mod jobs;
+pub mod layer;
+pub mod prometheus;
+pub mod slo;
+
+pub use delivery::DeliveryMetrics;
+pub use jobs::{JobStatus, JobTracker};
+pub use layer::MetricsLayer;
+pub use prometheus::PrometheusLayer;
+pub use slo::{LatencySlo, SloConfig, SloMonitor, SloStatus};
+
+// Upper bounds of the latency histogram buckets, in milliseconds. Slower requests fall in a
+// final overflow bucket.
+pub const LATENCY_BUCKETS_MS: &[u64] = &[5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+// Width of the time slices request latencies are aggregated in
+const SLICE_SECS: i64 = 10;
+
+// Count of requests per latency bucket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, latency: Duration) {
+        let millis = latency.as_secs_f64() * 1000.0;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| millis <= *bound as f64)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+
+    // Number of requests observed
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Requests not known to have completed within the threshold: those in buckets whose upper
+    // bound is above it. Thresholds between two bounds are effectively rounded down.
+    pub fn count_over(&self, threshold: Duration) -> u64 {
+        let millis = threshold.as_secs_f64() * 1000.0;
+        LATENCY_BUCKETS_MS
+            .iter()
+            .map(|bound| *bound as f64 > millis)
+            .chain([true])
+            .zip(&self.counts)
+            .filter(|(over, _)| *over)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    // Upper bound, in milliseconds, of the bucket holding the given quantile (0.0 to 1.0).
+    // None if nothing was observed or the quantile falls in the overflow bucket.
+    pub fn quantile_ms(&self, quantile: f64) -> Option<u64> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+
+        let rank = (quantile * total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bound, count) in LATENCY_BUCKETS_MS.iter().zip(&self.counts) {
+            seen += count;
+            if seen >= rank {
+                return Some(*bound);
+            }
+        }
+        None
+    }
+}
+
+// Histograms of one method, oldest first, keyed by slice number (unix seconds / SLICE_SECS)
+type Slices = VecDeque<(i64, LatencyHistogram)>;
+
+// In-memory latency histograms per RPC method, kept in time slices for a retention period
+// so objectives can be evaluated over a sliding window
+#[derive(Clone)]
+pub struct RpcMetrics {
+    methods: Arc<Mutex<HashMap<String, Slices>>>,
+    retention: chrono::Duration,
+}
+
+impl RpcMetrics {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            methods: Arc::default(),
+            retention: chrono::Duration::from_std(retention).unwrap_or(chrono::Duration::MAX),
+        }
+    }
+
+    pub fn record(&self, method: &str, latency: Duration) {
+        self.record_at(method, latency, timestamps::now());
+    }
+
+    // Record a request that completed at the given time
+    pub fn record_at(&self, method: &str, latency: Duration, at: DateTime<Utc>) {
+        let slice = at.timestamp().div_euclid(SLICE_SECS);
+        let oldest = at
+            .checked_sub_signed(self.retention)
+            .map_or(i64::MIN, |oldest| oldest.timestamp().div_euclid(SLICE_SECS));
+
+        let mut methods = self.methods.lock().unwrap();
+        let slices = methods.entry(method.to_string()).or_default();
+        while slices.front().is_some_and(|(s, _)| *s < oldest) {
+            slices.pop_front();
+        }
+
+        match slices.iter_mut().rev().find(|(s, _)| *s == slice) {
+            Some((_, histogram)) => histogram.observe(latency),
+            None => {
+                let mut histogram = LatencyHistogram::new();
+                histogram.observe(latency);
+                // Keep the slices ordered, in case a request was recorded out of order
+                let position = slices.partition_point(|(s, _)| *s < slice);
+                slices.insert(position, (slice, histogram));
+            }
+        }
+    }
+
+    // Latency histogram of a method's requests completed since the given time. Slices are
+    // included whole, so the window is rounded down to the slice width.
+    pub fn histogram_since(&self, method: &str, since: DateTime<Utc>) -> LatencyHistogram {
+        let first = since.timestamp().div_euclid(SLICE_SECS);
+        let mut histogram = LatencyHistogram::new();
+        if let Some(slices) = self.methods.lock().unwrap().get(method) {
+            for (_, slice) in slices.iter().filter(|(s, _)| *s >= first) {
+                histogram.merge(slice);
+            }
+        }
+        histogram
+    }
+
+    // Methods with recorded requests, ordered by name
+    pub fn methods(&self) -> Vec<String> {
+        let mut methods: Vec<_> = self.methods.lock().unwrap().keys().cloned().collect();
+        methods.sort();
+        methods
+    }
+}
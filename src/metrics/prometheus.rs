@@ -0,0 +1,90 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Method};
+use tonic::body::Body;
+use tower::{BoxError, Layer, Service};
+
+use super::DeliveryMetrics;
+
+// Path Prometheus scrapes
+pub const PROMETHEUS_PATH: &str = "/metrics";
+
+// Content type of the Prometheus text exposition format
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// Tower layer answering `GET /metrics` with the metrics in the Prometheus text format, on the
+// same port as the gRPC services. Every other request, and every request while disabled, is
+// passed through.
+#[derive(Clone)]
+pub struct PrometheusLayer {
+    delivery: Option<DeliveryMetrics>,
+}
+
+impl PrometheusLayer {
+    pub fn new(delivery: DeliveryMetrics) -> Self {
+        Self {
+            delivery: Some(delivery),
+        }
+    }
+
+    // Layer that serves no metrics endpoint
+    pub fn disabled() -> Self {
+        Self { delivery: None }
+    }
+}
+
+impl<S> Layer<S> for PrometheusLayer {
+    type Service = Prometheus<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Prometheus {
+            inner,
+            delivery: self.delivery.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Prometheus<S> {
+    inner: S,
+    delivery: Option<DeliveryMetrics>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Prometheus<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = http::Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let scrape = request.method() == Method::GET && request.uri().path() == PROMETHEUS_PATH;
+        let delivery = self.delivery.as_ref().filter(|_| scrape);
+        let Some(delivery) = delivery else {
+            let response = self.inner.call(request);
+            return Box::pin(async move { Ok(response.await?.map(Body::new)) });
+        };
+
+        let mut text = String::new();
+        delivery.render_prometheus(&mut text);
+        let mut response =
+            http::Response::new(Body::new(http_body_util::Full::new(Bytes::from(text))));
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(PROMETHEUS_CONTENT_TYPE),
+        );
+        Box::pin(async move { Ok(response) })
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+use super::RpcMetrics;
+use crate::timestamps;
+
+// Latency objective for one RPC method, written like "FetchMessages p99<100ms": 99% of
+// FetchMessages requests complete within 100ms
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySlo {
+    pub method: String,
+    // Percentage of requests that must meet the threshold, e.g. 99.0 or 99.9
+    pub percentile: f64,
+    pub threshold: Duration,
+}
+
+impl LatencySlo {
+    // Share of requests allowed to miss the threshold
+    pub fn error_budget(&self) -> f64 {
+        1.0 - self.percentile / 100.0
+    }
+}
+
+impl fmt::Display for LatencySlo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} p{}<{}ms",
+            self.method,
+            self.percentile,
+            self.threshold.as_millis()
+        )
+    }
+}
+
+impl FromStr for LatencySlo {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid SLO {:?}, expected e.g. \"FetchMessages p99<100ms\"",
+                value
+            )
+        };
+
+        let (method, objective) = value.trim().split_once(' ').ok_or_else(invalid)?;
+        let (percentile, threshold) = objective
+            .trim()
+            .strip_prefix('p')
+            .and_then(|objective| objective.split_once('<'))
+            .ok_or_else(invalid)?;
+        let percentile: f64 = percentile.parse().map_err(|_| invalid())?;
+        let threshold: u64 = threshold
+            .strip_suffix("ms")
+            .and_then(|ms| ms.parse().ok())
+            .ok_or_else(invalid)?;
+
+        if method.is_empty() || !(percentile > 0.0 && percentile < 100.0) || threshold == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            method: method.to_string(),
+            percentile,
+            threshold: Duration::from_millis(threshold),
+        })
+    }
+}
+
+// Parse a comma-separated list of SLOs
+pub fn parse_slos(value: &str) -> Result<Vec<LatencySlo>, String> {
+    value
+        .split(',')
+        .filter(|slo| !slo.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+// How SLOs are evaluated and when they alert
+#[derive(Debug, Clone)]
+pub struct SloConfig {
+    pub slos: Vec<LatencySlo>,
+    // Sliding window the burn rate is measured over
+    pub window: Duration,
+    // Alert when the error budget is being used this many times faster than sustainable
+    pub burn_rate_alert: f64,
+    // Windows with fewer requests never alert, so a couple of slow calls can't page anyone
+    pub min_requests: u64,
+    pub interval: Duration,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            slos: vec![LatencySlo {
+                method: "FetchMessages".to_string(),
+                percentile: 99.0,
+                threshold: Duration::from_millis(100),
+            }],
+            window: Duration::from_secs(300),
+            burn_rate_alert: 10.0,
+            min_requests: 20,
+            interval: Duration::from_secs(30),
+        }
+    }
+}
+
+// An SLO measured over the window
+#[derive(Debug, Clone, PartialEq)]
+pub struct SloStatus {
+    pub slo: LatencySlo,
+    pub requests: u64,
+    // Requests that missed the threshold
+    pub slow: u64,
+    // Share of slow requests relative to the error budget; 1.0 uses it up exactly on pace
+    pub burn_rate: f64,
+}
+
+// Measure an SLO from the requests recorded in the window ending at `now`
+pub fn evaluate(
+    metrics: &RpcMetrics,
+    slo: &LatencySlo,
+    window: Duration,
+    now: DateTime<Utc>,
+) -> SloStatus {
+    let since = chrono::Duration::from_std(window)
+        .ok()
+        .and_then(|window| now.checked_sub_signed(window))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let histogram = metrics.histogram_since(&slo.method, since);
+    let requests = histogram.count();
+    let slow = histogram.count_over(slo.threshold);
+    let burn_rate = if requests == 0 {
+        0.0
+    } else {
+        slow as f64 / requests as f64 / slo.error_budget()
+    };
+
+    SloStatus {
+        slo: slo.clone(),
+        requests,
+        slow,
+        burn_rate,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    // The SLO started burning its error budget faster than the alert threshold
+    Firing,
+    // A firing SLO is back under the threshold
+    Resolved,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SloAlert {
+    pub state: AlertState,
+    pub status: SloStatus,
+}
+
+// Receives SLO alerts when they start firing and when they resolve
+#[async_trait]
+pub trait AlertHook: Send + Sync {
+    async fn notify(&self, alert: &SloAlert);
+}
+
+// Alert hook that writes to the service log
+pub struct LogAlertHook;
+
+#[async_trait]
+impl AlertHook for LogAlertHook {
+    async fn notify(&self, alert: &SloAlert) {
+        let status = &alert.status;
+        match alert.state {
+            AlertState::Firing => warn!(
+                "SLO {} is burning its error budget {:.1}x too fast ({} of {} requests too slow)",
+                status.slo, status.burn_rate, status.slow, status.requests
+            ),
+            AlertState::Resolved => info!(
+                "SLO {} recovered, burn rate {:.1}x",
+                status.slo, status.burn_rate
+            ),
+        }
+    }
+}
+
+// Alert hook that POSTs each alert as JSON to a URL
+pub struct WebhookAlertHook {
+    client: reqwest::Client,
+    url: String,
+}
+
+// JSON body posted by `WebhookAlertHook`
+#[derive(Debug, Serialize)]
+struct WebhookAlert<'a> {
+    state: AlertState,
+    slo: String,
+    method: &'a str,
+    requests: u64,
+    slow: u64,
+    burn_rate: f64,
+}
+
+impl WebhookAlertHook {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("HTTP client configuration is valid"),
+            url: url.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl AlertHook for WebhookAlertHook {
+    async fn notify(&self, alert: &SloAlert) {
+        let status = &alert.status;
+        let body = WebhookAlert {
+            state: alert.state,
+            slo: status.slo.to_string(),
+            method: &status.slo.method,
+            requests: status.requests,
+            slow: status.slow,
+            burn_rate: status.burn_rate,
+        };
+
+        let result = self
+            .client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            error!("Failed to deliver SLO alert for {}: {}", status.slo, e);
+        }
+    }
+}
+
+// Periodically evaluates the SLOs and notifies the alert hooks when one starts or stops
+// burning its error budget faster than the configured rate
+pub struct SloMonitor {
+    metrics: RpcMetrics,
+    config: SloConfig,
+    hooks: Vec<Arc<dyn AlertHook>>,
+    firing: HashSet<String>,
+}
+
+impl SloMonitor {
+    pub fn new(metrics: RpcMetrics, config: SloConfig) -> Self {
+        Self {
+            metrics,
+            config,
+            hooks: Vec::new(),
+            firing: HashSet::new(),
+        }
+    }
+
+    pub fn with_hook(mut self, hook: Arc<dyn AlertHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // Evaluate every SLO at `now`, notifying the hooks of alerts that fired or resolved
+    pub async fn check_at(&mut self, now: DateTime<Utc>) -> Vec<SloStatus> {
+        let mut statuses = Vec::with_capacity(self.config.slos.len());
+        for slo in &self.config.slos {
+            let status = evaluate(&self.metrics, slo, self.config.window, now);
+            let burning = status.requests >= self.config.min_requests
+                && status.burn_rate >= self.config.burn_rate_alert;
+
+            let key = slo.to_string();
+            let state = if burning && self.firing.insert(key.clone()) {
+                Some(AlertState::Firing)
+            } else if !burning && self.firing.remove(&key) {
+                Some(AlertState::Resolved)
+            } else {
+                None
+            };
+
+            if let Some(state) = state {
+                let alert = SloAlert {
+                    state,
+                    status: status.clone(),
+                };
+                for hook in &self.hooks {
+                    hook.notify(&alert).await;
+                }
+            }
+            statuses.push(status);
+        }
+        statuses
+    }
+
+    // Spawn the evaluation loop onto the tokio runtime
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.interval);
+            loop {
+                interval.tick().await;
+                self.check_at(timestamps::now()).await;
+            }
+        })
+    }
+}
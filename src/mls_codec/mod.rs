@@ -0,0 +1,264 @@
+use openmls::credentials::{BasicCredential, Credential, CredentialWithKey};
+use openmls::key_packages::{KeyPackage, KeyPackageIn};
+use openmls::prelude::{
+    Ciphersuite, ContentType as MlsContentType, HashType, MlsMessageIn, OpenMlsCrypto,
+    OpenMlsProvider, OpenMlsRand, ProtocolMessage, Sender as MlsSender, SignatureScheme,
+};
+use openmls::versions::ProtocolVersion;
+use openmls_basic_credential::SignatureKeyPair;
+use openmls_rust_crypto::OpenMlsRustCrypto;
+use openmls_traits::signatures::Signer;
+use thiserror::Error;
+use tls_codec::{Deserialize as TlsDeserialize, Serialize as TlsSerialize};
+
+use crate::redact;
+
+// The only module that touches OpenMLS. Its API changes between releases, so handlers,
+// validators and authentication go through the crate-owned types and functions here, and an
+// upgrade only has to be followed in this file.
+
+// Ciphersuite of the key packages and init keys the service generates itself
+const DEFAULT_CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+// Errors decoding MLS structures or running MLS crypto. The messages are phrased for clients,
+// since malformed and invalid input is reported back to them.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    #[error("Invalid {what} format: {reason}")]
+    Malformed { what: &'static str, reason: String },
+
+    #[error("{what} validation failed: {reason}")]
+    Invalid { what: &'static str, reason: String },
+
+    #[error("Failed to {action}: {reason}")]
+    Crypto {
+        action: &'static str,
+        reason: String,
+    },
+}
+
+impl CodecError {
+    // OpenMLS errors only implement Debug, which can print the bytes they failed on
+    fn crypto(action: &'static str, reason: impl std::fmt::Debug) -> Self {
+        Self::Crypto {
+            action,
+            reason: redact::scrub(&format!("{:?}", reason)).into_owned(),
+        }
+    }
+}
+
+// What the service reads from a validated key package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPackageInfo {
+    // IANA ciphersuite identifier
+    pub ciphersuite: u16,
+}
+
+// Who sent a framed message, as far as the framing reveals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramedSender {
+    // The member at a leaf index
+    Member(u32),
+    // One of the group's external senders
+    External(u32),
+    // A non-member proposing to join or committing its own join
+    NewMember,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramedContent {
+    Application,
+    Proposal,
+    Commit,
+}
+
+// The cleartext header of an MLS public or private message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framing {
+    pub group_id: Vec<u8>,
+    pub epoch: u64,
+    pub content: FramedContent,
+    // None for private messages, whose sender is encrypted
+    pub sender: Option<FramedSender>,
+}
+
+// Read the framing of a TLS-serialized MLSMessage carrying a public or private message
+pub fn parse_framing(message: &[u8]) -> Result<Framing, CodecError> {
+    let message = MlsMessageIn::tls_deserialize(&mut &message[..])
+        .map_err(|e| CodecError::Malformed {
+            what: "MLS message",
+            reason: e.to_string(),
+        })?
+        .try_into_protocol_message()
+        .map_err(|_| CodecError::Malformed {
+            what: "MLS message",
+            reason: "not a public or private message".to_string(),
+        })?;
+
+    let content = match message.content_type() {
+        MlsContentType::Application => FramedContent::Application,
+        MlsContentType::Proposal => FramedContent::Proposal,
+        MlsContentType::Commit => FramedContent::Commit,
+    };
+    let sender = match &message {
+        ProtocolMessage::PublicMessage(public) => Some(match public.sender() {
+            MlsSender::Member(leaf) => FramedSender::Member(leaf.u32()),
+            MlsSender::External(index) => FramedSender::External(index.index() as u32),
+            MlsSender::NewMemberProposal | MlsSender::NewMemberCommit => FramedSender::NewMember,
+        }),
+        ProtocolMessage::PrivateMessage(_) => None,
+    };
+
+    Ok(Framing {
+        group_id: message.group_id().as_slice().to_vec(),
+        epoch: message.epoch().as_u64(),
+        content,
+        sender,
+    })
+}
+
+// A TLS-serialized BasicCredential for the identity
+pub fn basic_credential(identity: &[u8]) -> Result<Vec<u8>, CodecError> {
+    let credential: Credential = BasicCredential::new(identity.to_vec()).into();
+    credential
+        .tls_serialize_detached()
+        .map_err(|e| CodecError::crypto("serialize credential", e))
+}
+
+// SHA-256 of the data
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    OpenMlsRustCrypto::default()
+        .crypto()
+        .hash(HashType::Sha2_256, data)
+        .expect("SHA-256 is always supported")
+}
+
+// Security level, in bits, of an IANA ciphersuite (RFC 9420, section 17.1). None for suites
+// without a registered level, such as GREASE and private-use values.
+pub fn ciphersuite_security_bits(ciphersuite: u16) -> Option<u16> {
+    match ciphersuite {
+        // MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519, MLS_128_DHKEMP256_AES128GCM_SHA256_P256,
+        // MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519
+        1..=3 => Some(128),
+        // MLS_256_DHKEMX448_AES256GCM_SHA512_Ed448, MLS_256_DHKEMP521_AES256GCM_SHA512_P521,
+        // MLS_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448, MLS_256_DHKEMP384_AES256GCM_SHA384_P384
+        4..=7 => Some(256),
+        _ => None,
+    }
+}
+
+// MLS protocol versions the key packages and messages the service validates may use
+pub const PROTOCOL_VERSIONS: &[&str] = &["mls10"];
+
+// Crypto provider for the operations that need one. Cheap to share behind an Arc.
+#[derive(Default)]
+pub struct MlsCrypto {
+    provider: OpenMlsRustCrypto,
+}
+
+impl MlsCrypto {
+    // Check an Ed25519 signature over the data
+    pub fn verify_ed25519(
+        &self,
+        data: &[u8],
+        public_key: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CodecError> {
+        self.provider
+            .crypto()
+            .verify_signature(SignatureScheme::ED25519, data, public_key, signature)
+            .map_err(|e| CodecError::crypto("verify signature", e))
+    }
+
+    // IANA identifiers of the ciphersuites the provider implements, in its order of preference
+    pub fn supported_ciphersuites(&self) -> Vec<u16> {
+        self.provider
+            .crypto()
+            .supported_ciphersuites()
+            .into_iter()
+            .map(|ciphersuite| ciphersuite as u16)
+            .collect()
+    }
+
+    // Decode and validate a TLS-serialized key package
+    pub fn validate_key_package(&self, key_package: &[u8]) -> Result<KeyPackageInfo, CodecError> {
+        let key_package_in = KeyPackageIn::tls_deserialize(&mut &key_package[..]).map_err(|e| {
+            CodecError::Malformed {
+                what: "key package",
+                reason: e.to_string(),
+            }
+        })?;
+        let key_package = key_package_in
+            .validate(self.provider.crypto(), ProtocolVersion::Mls10)
+            .map_err(|e| CodecError::Invalid {
+                what: "Key package",
+                reason: e.to_string(),
+            })?;
+
+        Ok(KeyPackageInfo {
+            ciphersuite: key_package.ciphersuite() as u16,
+        })
+    }
+
+    // A fresh TLS-serialized HPKE init key
+    pub fn generate_init_key(&self) -> Result<Vec<u8>, CodecError> {
+        let random_bytes = self
+            .provider
+            .rand()
+            .random_vec(32)
+            .map_err(|e| CodecError::crypto("generate random bytes", e.to_string()))?;
+        let key_pair = self
+            .provider
+            .crypto()
+            .derive_hpke_keypair(DEFAULT_CIPHERSUITE.hpke_config(), &random_bytes)
+            .map_err(|e| CodecError::crypto("derive HPKE key pair", e))?;
+        key_pair
+            .public
+            .tls_serialize_detached()
+            .map_err(|e| CodecError::crypto("serialize init key", e))
+    }
+
+    // A TLS-serialized key package for the TLS-serialized credential, signed with a fresh key
+    pub fn build_key_package(&self, credential: &[u8]) -> Result<Vec<u8>, CodecError> {
+        let credential = Credential::tls_deserialize(&mut &credential[..])
+            .map_err(|e| CodecError::crypto("deserialize credential", e))?;
+        let signature_key = SignatureKeyPair::new(DEFAULT_CIPHERSUITE.signature_algorithm())
+            .map_err(|e| CodecError::crypto("generate signature key pair", e))?;
+        let credential_with_key = CredentialWithKey {
+            credential,
+            signature_key: signature_key.public().into(),
+        };
+
+        let key_package_bundle = KeyPackage::builder()
+            .build(
+                DEFAULT_CIPHERSUITE,
+                &self.provider,
+                &signature_key,
+                credential_with_key,
+            )
+            .map_err(|e| CodecError::crypto("build key package", e))?;
+        key_package_bundle
+            .key_package()
+            .tls_serialize_detached()
+            .map_err(|e| CodecError::crypto("serialize key package", e))
+    }
+}
+
+// An Ed25519 signature key held by the service itself
+pub struct SigningKey(SignatureKeyPair);
+
+impl SigningKey {
+    pub fn generate() -> Result<Self, CodecError> {
+        SignatureKeyPair::new(SignatureScheme::ED25519)
+            .map(Self)
+            .map_err(|e| CodecError::crypto("create signature key", e))
+    }
+
+    pub fn public(&self) -> &[u8] {
+        self.0.public()
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CodecError> {
+        self.0.sign(data).map_err(|e| CodecError::crypto("sign", e))
+    }
+}
@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{DatabaseInterface, DbResult, Message, MessageType, SYSTEM_SENDER_ID};
+use crate::ids::IdGenerator;
+use crate::timestamps;
+
+// Version of the system message envelope written by this release
+pub const ENVELOPE_VERSION: u32 = 1;
+
+// Payload of a `system` message. Serialized as JSON so clients can dispatch on
+// `kind` without MLS processing; system messages are not MLS-protected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemEnvelope {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    // Always false; lets clients tell these apart from MLS-protected content at a glance
+    #[serde(default)]
+    pub mls_protected: bool,
+    #[serde(flatten)]
+    pub notice: SystemNotice,
+}
+
+fn default_version() -> u32 {
+    ENVELOPE_VERSION
+}
+
+// The notices the delivery service can enqueue, tagged by `kind`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SystemNotice {
+    // The recipient client is running out of unused key packages
+    KeyPackageLow {
+        client_id: Uuid,
+        remaining: i64,
+    },
+    // A member of the group has been deactivated and should be removed
+    ClientDeactivated {
+        client_id: Uuid,
+    },
+    // The group has been deactivated and no longer accepts messages
+    GroupFrozen {
+        group_id: Uuid,
+    },
+    // A frozen group has been reactivated
+    GroupUnfrozen {
+        group_id: Uuid,
+    },
+    // Messages in the group will be deleted by retention at `expires_at`
+    RetentionWarning {
+        group_id: Uuid,
+        expires_at: DateTime<Utc>,
+    },
+    // An operational announcement from a group admin, e.g. scheduled maintenance
+    Announcement {
+        sender_id: Uuid,
+        text: String,
+    },
+    // A member lost its welcome; admins are asked to re-add it with a fresh Add/Welcome
+    WelcomeResendRequested {
+        membership_id: Uuid,
+        client_id: Uuid,
+    },
+    // The group's epoch is older than its key rotation policy allows; members are asked to
+    // commit an Update to refresh their keys
+    KeyRotationDue {
+        group_id: Uuid,
+        epoch: i64,
+        epoch_started_at: DateTime<Utc>,
+        max_epoch_age_secs: i64,
+    },
+    // Joiners that haven't fetched their welcomes despite push reminders; admins are asked to
+    // check on them, or re-add them with a fresh Add/Welcome
+    WelcomesStranded {
+        group_id: Uuid,
+        joiners: Vec<StrandedJoiner>,
+    },
+    // A member is too far behind the group's epoch
+    MemberStuck(StuckMemberDetails),
+    // Admins are asked to commit a Remove for a stuck member
+    MemberRemovalRequested(StuckMemberDetails),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StuckMemberDetails {
+    pub membership_id: Uuid,
+    pub client_id: Uuid,
+    pub last_acked_epoch: i64,
+    pub current_epoch: i64,
+    pub epochs_behind: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StrandedJoiner {
+    pub membership_id: Uuid,
+    pub client_id: Uuid,
+    // The welcome it hasn't acknowledged
+    pub message_id: Uuid,
+    pub stored_at: DateTime<Utc>,
+    // Push reminders sent for it
+    pub reminders: i64,
+}
+
+impl SystemEnvelope {
+    pub fn new(notice: SystemNotice) -> Self {
+        Self {
+            version: ENVELOPE_VERSION,
+            mls_protected: false,
+            notice,
+        }
+    }
+
+    // Parse the payload of a stored system message
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("system envelope is always serializable")
+    }
+}
+
+// Build a system message carrying `notice` for the given recipients.
+// Notices without a group are delivered to the recipients regardless of membership.
+pub fn system_message(
+    ids: &dyn IdGenerator,
+    group_id: Option<Uuid>,
+    epoch: Option<i64>,
+    recipients: Vec<Uuid>,
+    notice: SystemNotice,
+) -> Message {
+    Message {
+        id: ids.generate(),
+        group_id,
+        sender_id: SYSTEM_SENDER_ID,
+        created_at: timestamps::now(),
+        read: false,
+        message_type: MessageType::System,
+        proposal: None,
+        commit: None,
+        welcome: None,
+        system: Some(SystemEnvelope::new(notice).to_bytes()),
+        application: None,
+        proposal_type: None,
+        epoch,
+        recipients: Some(recipients),
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
+    }
+    .with_payload_hash()
+}
+
+// Enqueue a notice to every active member of a group, except `exclude`.
+// Returns the stored message, or None if there was nobody to notify.
+pub async fn notify_group<DB: DatabaseInterface + ?Sized>(
+    db: &DB,
+    ids: &dyn IdGenerator,
+    group_id: Uuid,
+    exclude: Option<Uuid>,
+    notice: SystemNotice,
+) -> DbResult<Option<Message>> {
+    let recipients: Vec<Uuid> = db
+        .list_memberships_by_group(group_id)
+        .await?
+        .into_iter()
+        .filter(|m| m.removed_at.is_none() && Some(m.client_id) != exclude)
+        .map(|m| m.client_id)
+        .collect();
+
+    if recipients.is_empty() {
+        return Ok(None);
+    }
+
+    let message = system_message(ids, Some(group_id), None, recipients, notice);
+    Ok(Some(db.store_message(message).await?))
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use std::fmt;
+
+// Credentials, key packages, MLS messages, backups and the like never appear in logs or in the
+// messages of statuses returned to clients. Types carrying them implement Debug with
+// `redacted_debug!`, which prints only the length of those fields, and text built from
+// third-party errors goes through `scrub` before it is logged or returned.
+
+// Shortest byte list `scrub` redacts; shorter lists are more likely counts or indices than
+// payload fragments
+const MIN_SCRUBBED_BYTES: usize = 8;
+
+// Payload bytes, formatted as their length only
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Redacted(Option<usize>);
+
+impl Redacted {
+    pub fn of<P: Payload + ?Sized>(payload: &P) -> Self {
+        Self(payload.payload_len())
+    }
+}
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(len) => write!(f, "<{} bytes>", len),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+impl fmt::Display for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+// A field holding payload bytes, possibly optional
+pub trait Payload {
+    // None when an optional payload is absent
+    fn payload_len(&self) -> Option<usize>;
+}
+
+impl Payload for [u8] {
+    fn payload_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl Payload for Vec<u8> {
+    fn payload_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<T: Payload> Payload for Option<T> {
+    fn payload_len(&self) -> Option<usize> {
+        self.as_ref().and_then(Payload::payload_len)
+    }
+}
+
+// Implement Debug for a struct, printing the fields after `redacted:` as their length only. The
+// struct is destructured, so adding a field without listing it here fails to compile.
+macro_rules! redacted_debug {
+    ($name:ident { $($field:ident),* $(,)? ; redacted: $($payload:ident),+ $(,)? }) => {
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let Self { $($field,)* $($payload,)+ } = self;
+                f.debug_struct(stringify!($name))
+                    $(.field(stringify!($field), $field))*
+                    $(.field(stringify!($payload), &$crate::redact::Redacted::of($payload)))+
+                    .finish()
+            }
+        }
+    };
+}
+pub(crate) use redacted_debug;
+
+// Text with every Debug-formatted byte list (`[12, 34, ...]`, as `{:?}` prints a Vec<u8>) of at
+// least MIN_SCRUBBED_BYTES elements replaced by its length. For messages of errors raised by
+// libraries, which may quote the bytes they failed on.
+pub fn scrub(text: &str) -> Cow<'_, str> {
+    let mut scrubbed = String::new();
+    // Text up to `copied` is in `scrubbed` already
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(offset) = text[search..].find('[') {
+        let start = search + offset;
+        match byte_list(&text[start..]) {
+            Some((len, count)) if count >= MIN_SCRUBBED_BYTES => {
+                scrubbed.push_str(&text[copied..start]);
+                scrubbed.push_str(&format!("<{} bytes>", count));
+                copied = start + len;
+                search = copied;
+            }
+            _ => search = start + 1,
+        }
+    }
+
+    if copied == 0 {
+        return Cow::Borrowed(text);
+    }
+    scrubbed.push_str(&text[copied..]);
+    Cow::Owned(scrubbed)
+}
+
+// Length in the text and number of elements of the list of integers from 0 to 255 it starts
+// with, if it starts with one
+fn byte_list(text: &str) -> Option<(usize, usize)> {
+    let inner = text.strip_prefix('[')?;
+    let end = inner.find(']')?;
+    let elements = &inner[..end];
+    if elements.trim().is_empty() {
+        return None;
+    }
+    let mut count = 0;
+    for element in elements.split(',') {
+        element.trim().parse::<u8>().ok()?;
+        count += 1;
+    }
+    Some((end + 2, count))
+}
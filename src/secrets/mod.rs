@@ -0,0 +1,144 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use thiserror::Error;
+
+pub mod vault;
+
+pub use vault::{DbCredentials, VaultClient, VaultConfig};
+
+// Prefixes of secret references. Values without one are used as-is.
+pub const ENV_SCHEME: &str = "env:";
+pub const FILE_SCHEME: &str = "file:";
+pub const KMS_SCHEME: &str = "kms:";
+
+// Errors resolving a secret reference. They name the config variable and the reference, never
+// the secret itself.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SecretError {
+    #[error("{name}: environment variable {var} is not set")]
+    MissingEnv { name: String, var: String },
+
+    #[error("{name}: failed to read secret file {path}: {cause}")]
+    File {
+        name: String,
+        path: String,
+        cause: String,
+    },
+
+    #[error("{name}: kms: references need KMS_DECRYPT_COMMAND to be set")]
+    NoKmsCommand { name: String },
+
+    #[error("{name}: failed to decrypt {reference}: {cause}")]
+    Kms {
+        name: String,
+        reference: String,
+        cause: String,
+    },
+}
+
+// Resolves secret-bearing config values at startup, so plaintext secrets don't have to sit in
+// config files. `env:NAME` reads another environment variable, `file:/path` a file (such as a
+// mounted Kubernetes or Docker secret), and `kms:<reference>` runs the KMS decrypt command with
+// the reference as its last argument and takes its standard output.
+#[derive(Debug, Clone, Default)]
+pub struct SecretResolver {
+    kms_command: Option<Vec<String>>,
+}
+
+impl SecretResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Take the KMS decrypt command from KMS_DECRYPT_COMMAND, split on whitespace
+    pub fn from_env() -> Self {
+        let resolver = Self::new();
+        match env::var("KMS_DECRYPT_COMMAND") {
+            Ok(command) if !command.trim().is_empty() => {
+                resolver.with_kms_command(command.split_whitespace())
+            }
+            _ => resolver,
+        }
+    }
+
+    // Decrypt kms: references with this program and leading arguments
+    pub fn with_kms_command(
+        mut self,
+        command: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.kms_command = Some(command.into_iter().map(Into::into).collect());
+        self
+    }
+
+    // Resolve the value of the config variable `name`. Resolved secrets have surrounding
+    // whitespace trimmed, since files and command output usually end with a newline.
+    pub fn resolve(&self, name: &str, value: &str) -> Result<String, SecretError> {
+        if let Some(var) = value.strip_prefix(ENV_SCHEME) {
+            return env::var(var)
+                .map(|secret| secret.trim().to_string())
+                .map_err(|_| SecretError::MissingEnv {
+                    name: name.to_string(),
+                    var: var.to_string(),
+                });
+        }
+
+        if let Some(path) = value.strip_prefix(FILE_SCHEME) {
+            return fs::read_to_string(path)
+                .map(|secret| secret.trim().to_string())
+                .map_err(|e| SecretError::File {
+                    name: name.to_string(),
+                    path: path.to_string(),
+                    cause: e.to_string(),
+                });
+        }
+
+        if let Some(reference) = value.strip_prefix(KMS_SCHEME) {
+            return self.decrypt(name, reference);
+        }
+
+        Ok(value.to_string())
+    }
+
+    // Read an environment variable and resolve it; None if it's unset
+    pub fn env_var(&self, name: &str) -> Result<Option<String>, SecretError> {
+        env::var(name)
+            .ok()
+            .map(|value| self.resolve(name, &value))
+            .transpose()
+    }
+
+    fn decrypt(&self, name: &str, reference: &str) -> Result<String, SecretError> {
+        let Some((program, args)) = self
+            .kms_command
+            .as_ref()
+            .and_then(|command| command.split_first())
+        else {
+            return Err(SecretError::NoKmsCommand {
+                name: name.to_string(),
+            });
+        };
+        let failed = |cause: String| SecretError::Kms {
+            name: name.to_string(),
+            reference: reference.to_string(),
+            cause,
+        };
+
+        let output = Command::new(program)
+            .args(args)
+            .arg(reference)
+            .output()
+            .map_err(|e| failed(e.to_string()))?;
+        if !output.status.success() {
+            return Err(failed(format!(
+                "{}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map(|secret| secret.trim().to_string())
+            .map_err(|_| failed("output is not UTF-8".to_string()))
+    }
+}
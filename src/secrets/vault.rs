@@ -0,0 +1,179 @@
+use std::env;
+use std::time::Duration;
+
+use log::{error, info};
+use serde::Deserialize;
+use sqlx::postgres::{PgConnectOptions, PgPool};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use super::{SecretError, SecretResolver};
+
+// Delay before retrying a failed credential fetch, within the current lease
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VaultError {
+    #[error("Vault request failed: {0}")]
+    Request(String),
+
+    #[error("Unexpected Vault response: {0}")]
+    Response(String),
+}
+
+// Where to get database credentials from: a role of Vault's database secrets engine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultConfig {
+    pub addr: String,
+    pub token: String,
+    // e.g. database/creds/hermetic-mls
+    pub creds_path: String,
+}
+
+impl VaultConfig {
+    // Read VAULT_ADDR, VAULT_TOKEN (which may be a secret reference) and VAULT_DB_CREDS_PATH.
+    // Vault is only used when VAULT_DB_CREDS_PATH is set.
+    pub fn from_env(secrets: &SecretResolver) -> Result<Option<Self>, SecretError> {
+        let Some(creds_path) = env::var("VAULT_DB_CREDS_PATH")
+            .ok()
+            .filter(|v| !v.is_empty())
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            addr: env::var("VAULT_ADDR").unwrap_or_else(|_| "http://127.0.0.1:8200".to_string()),
+            token: secrets.env_var("VAULT_TOKEN")?.unwrap_or_default(),
+            creds_path,
+        }))
+    }
+}
+
+// Short-lived Postgres credentials leased from Vault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbCredentials {
+    pub username: String,
+    pub password: String,
+    pub lease_id: String,
+    pub lease_duration: Duration,
+}
+
+// Response of a read of a database secrets engine role
+#[derive(Deserialize)]
+struct LeaseResponse {
+    lease_id: String,
+    lease_duration: u64,
+    data: LeaseData,
+}
+
+#[derive(Deserialize)]
+struct LeaseData {
+    username: String,
+    password: String,
+}
+
+impl DbCredentials {
+    // Parse the JSON body Vault returns for a credentials read
+    pub fn from_response(body: &str) -> Result<Self, VaultError> {
+        let response: LeaseResponse =
+            serde_json::from_str(body).map_err(|e| VaultError::Response(e.to_string()))?;
+        if response.lease_duration == 0 {
+            return Err(VaultError::Response(
+                "credentials have no lease duration".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            username: response.data.username,
+            password: response.data.password,
+            lease_id: response.lease_id,
+            lease_duration: Duration::from_secs(response.lease_duration),
+        })
+    }
+
+    // Connect with these credentials instead of those in the options
+    pub fn apply(&self, options: PgConnectOptions) -> PgConnectOptions {
+        options.username(&self.username).password(&self.password)
+    }
+
+    // When to fetch the next credentials: halfway through the lease
+    pub fn refresh_after(&self) -> Duration {
+        self.lease_duration / 2
+    }
+
+    // How long a pooled connection may live. Connections opened just before a refresh still
+    // close before their lease expires.
+    pub fn connection_max_lifetime(&self) -> Duration {
+        self.lease_duration / 3
+    }
+}
+
+#[derive(Clone)]
+pub struct VaultClient {
+    http: reqwest::Client,
+    config: VaultConfig,
+}
+
+impl VaultClient {
+    pub fn new(config: VaultConfig) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("HTTP client configuration is valid"),
+            config,
+        }
+    }
+
+    // Lease a new set of database credentials
+    pub async fn database_credentials(&self) -> Result<DbCredentials, VaultError> {
+        let url = format!(
+            "{}/v1/{}",
+            self.config.addr.trim_end_matches('/'),
+            self.config.creds_path.trim_start_matches('/')
+        );
+        let body = self
+            .http
+            .get(&url)
+            .header("X-Vault-Token", &self.config.token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| VaultError::Request(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| VaultError::Request(e.to_string()))?;
+        DbCredentials::from_response(&body)
+    }
+
+    // Keep the pools' credentials current: halfway through each lease, lease new credentials
+    // and use them for the pools' new connections. The pools' connection max lifetime should
+    // come from `DbCredentials::connection_max_lifetime`, so connections on the old
+    // credentials are closed before their lease expires.
+    pub fn spawn_rotation(self, pools: Vec<PgPool>, current: DbCredentials) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut delay = current.refresh_after();
+            loop {
+                tokio::time::sleep(delay).await;
+                match self.database_credentials().await {
+                    Ok(credentials) => {
+                        for pool in &pools {
+                            let options = credentials.apply((*pool.connect_options()).clone());
+                            pool.set_connect_options(options);
+                        }
+                        info!(
+                            "Rotated database credentials, lease {} for {}s",
+                            credentials.lease_id,
+                            credentials.lease_duration.as_secs()
+                        );
+                        delay = credentials.refresh_after();
+                    }
+                    Err(e) => {
+                        error!("Failed to rotate database credentials: {}", e);
+                        delay = RETRY_DELAY;
+                    }
+                }
+            }
+        })
+    }
+}
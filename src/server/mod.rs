@@ -0,0 +1,617 @@
+// Embedding the delivery service. `ServerBuilder` sets up the services, background jobs and
+// layers the way the `hermetic-mls` binary does, from a `Config` and any `DatabaseInterface`
+// implementation, so another binary can serve them itself: alongside its own gRPC services, or
+// behind its own tower layers. `run_server` does what the binary's `serve` command does. The
+// admin service has a router and listener of its own, so it is never served to clients.
+
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::net::TcpListener;
+use tonic::service::{Routes, RoutesBuilder};
+use tonic::transport::server::Router;
+use tonic::transport::Server;
+use tonic_health::ServingStatus;
+use tonic_reflection::server::Builder as ReflectionBuilder;
+use tower::layer::util::Stack;
+use tower_http::cors::CorsLayer;
+
+use crate::auth::admin::AdminAuthLayer;
+use crate::auth::token::{JwtValidator, TokenAuthLayer};
+use crate::auth::{AuthMode, ClientAuthLayer, ClientKeys, NonceStore};
+use crate::config::Config;
+use crate::db::{CachePrimer, DatabaseInterface};
+use crate::events::publisher::{connect_publisher, EventForwarder};
+use crate::events::EventBus;
+use crate::flags::FeatureFlags;
+use crate::ids;
+use crate::janitor::welcome_sla::WebhookStrandedWelcomeHook;
+use crate::janitor::Janitor;
+use crate::metrics::prometheus::PROMETHEUS_PATH;
+use crate::metrics::slo::{LogAlertHook, WebhookAlertHook};
+use crate::metrics::{
+    DeliveryMetrics, JobTracker, MetricsLayer, PrometheusLayer, RpcMetrics, SloMonitor,
+};
+use crate::secrets::SecretResolver;
+use crate::service::admission::{AdmissionController, AdmissionLayer};
+use crate::service::deprecations::DeprecationLayer;
+use crate::service::json_debug::{JsonDebugLayer, JSON_DEBUG_PREFIX};
+use crate::service::legacy::mls::mls_delivery_service_server::MlsDeliveryServiceServer as LegacyDeliveryServiceServer;
+use crate::service::legacy::LegacyDeliveryService;
+use crate::service::membership_cache::MembershipCache;
+use crate::service::mls;
+use crate::service::mls::mls_admin_service_server::MlsAdminServiceServer;
+use crate::service::mls::mls_delivery_service_server::MlsDeliveryServiceServer;
+use crate::service::pagination::PageTokens;
+use crate::service::probe::{ReadinessProbe, SelfTestJob};
+use crate::service::rate_limit::{RateLimitLayer, RateLimiter};
+use crate::service::MLSServiceImpl;
+use crate::settings::{MaintenanceLayer, RequestSignatureMode, SettingsHandle};
+use crate::tls::{ReloadableCert, TlsListener};
+use crate::trace::VerboseTracing;
+
+pub type ServerResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// The delivery service's layers on top of an embedder's `L`, innermost first. Requests pass
+/// through the embedder's layers before any of these.
+pub type MlsLayers<L> = Stack<
+    RateLimitLayer,
+    Stack<
+        ClientAuthLayer,
+        Stack<
+            TokenAuthLayer,
+            Stack<
+                AdmissionLayer,
+                Stack<
+                    MaintenanceLayer,
+                    Stack<
+                        MetricsLayer,
+                        Stack<DeprecationLayer, Stack<PrometheusLayer, Stack<CorsLayer, L>>>,
+                    >,
+                >,
+            >,
+        >,
+    >,
+>;
+
+/// The admin service's layers on top of an embedder's `L`, innermost first. Every request is
+/// authenticated with the admin token before the embedder's layers see it.
+pub type AdminLayers<L> =
+    Stack<MetricsLayer, Stack<DeprecationLayer, Stack<JsonDebugLayer, Stack<AdminAuthLayer, L>>>>;
+
+/// Serve the delivery and admin services from `db` with the given configuration, until the
+/// server stops. The runtime settings, secrets and event bus are set up as `ServerBuilder`
+/// defaults them.
+pub async fn run_server<DB: DatabaseInterface + 'static>(
+    config: Config,
+    db: Arc<DB>,
+) -> ServerResult<()> {
+    ServerBuilder::new(config, db).serve().await
+}
+
+/// Sets up the delivery service from a `Config` and a database.
+pub struct ServerBuilder<DB: DatabaseInterface + 'static> {
+    config: Config,
+    db: Arc<DB>,
+    background_db: Option<Arc<DB>>,
+    settings: Option<SettingsHandle>,
+    secrets: Option<SecretResolver>,
+    events: Option<EventBus>,
+}
+
+impl<DB: DatabaseInterface + 'static> ServerBuilder<DB> {
+    pub fn new(config: Config, db: Arc<DB>) -> Self {
+        Self {
+            config,
+            db,
+            background_db: None,
+            settings: None,
+            secrets: None,
+            events: None,
+        }
+    }
+
+    /// Run the background jobs against a separate database handle, so a long sweep can't take
+    /// the connections clients need. By default they share the client-facing one.
+    pub fn with_background_db(mut self, db: Arc<DB>) -> Self {
+        self.background_db = Some(db);
+        self
+    }
+
+    /// Use already loaded runtime settings. By default they are loaded from the environment
+    /// and the config's settings file, with the config's retention and limits as defaults.
+    pub fn with_settings(mut self, settings: SettingsHandle) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Resolve the config's secret references with `secrets` instead of from the environment.
+    pub fn with_secrets(mut self, secrets: SecretResolver) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// Publish the service's events on `events`, e.g. one that database notifications from
+    /// other instances are also published on.
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Set up the services and layers and start the background jobs. Nothing is served until
+    /// the returned server is. An invalid config fails the build before any job starts.
+    pub async fn build(self) -> ServerResult<MlsServer<DB>> {
+        let config = self.config;
+        let db = self.db;
+        let background_db = self.background_db.unwrap_or_else(|| db.clone());
+        let settings = match self.settings {
+            Some(settings) => settings,
+            None => SettingsHandle::load_with_defaults(
+                config.settings_defaults(),
+                config.settings_file.clone(),
+            )?,
+        };
+        let secrets = self.secrets.unwrap_or_else(SecretResolver::from_env);
+        let events = self.events.unwrap_or_default();
+
+        // Strategy for generating ids of new rows
+        let ids = ids::from_name(&config.server.id_generator)
+            .ok_or_else(|| format!("Invalid ID_GENERATOR {:?}", config.server.id_generator))?;
+
+        // How delivery callers authenticate: with their registered signature key, a bearer
+        // token, or both
+        let auth = config.auth.auth_config();
+        let token_auth = config.auth.token_auth_config();
+        let token_validator = match token_auth.mode {
+            AuthMode::Off => None,
+            _ => Some(JwtValidator::from_config(&token_auth)?),
+        };
+
+        // CORS for browser clients. While requests are authenticated only the configured
+        // origins are allowed.
+        let cors = config.cors.cors_config().and_then(|cors| {
+            cors.layer(
+                settings.current().request_signatures != RequestSignatureMode::Off
+                    || auth.mode != AuthMode::Off
+                    || token_auth.mode != AuthMode::Off,
+            )
+        })?;
+
+        // Proposals, commits, welcomes and membership changes are published to an external bus
+        // when EVENT_PUBLISHER is set
+        let publisher = config.events.publisher_config()?;
+
+        // Per-RPC latency objectives, evaluated from the recorded latencies; alerts are logged
+        // and optionally posted to a webhook
+        let slo_config = config
+            .slo
+            .slo_config()
+            .map_err(|e| format!("Invalid LATENCY_SLOS: {}", e))?;
+        let rpc_metrics = RpcMetrics::new(slo_config.window);
+        let mut slo_monitor =
+            SloMonitor::new(rpc_metrics.clone(), slo_config).with_hook(Arc::new(LogAlertHook));
+        if let Some(url) = &config.slo.alert_webhook_url {
+            let url = url.resolve("SLO_ALERT_WEBHOOK_URL", &secrets)?;
+            slo_monitor = slo_monitor.with_hook(Arc::new(WebhookAlertHook::new(url)));
+        }
+        slo_monitor.spawn();
+
+        // Health of the background jobs, for the admin service; their durations are recorded
+        // alongside the RPC latencies
+        let jobs = JobTracker::new().with_metrics(rpc_metrics.clone());
+
+        // Start the background janitor; its sweep policies come from the runtime settings.
+        // Welcomes it finds stranded are also posted to WELCOME_ALERT_WEBHOOK_URL.
+        let janitor_interval = Duration::from_secs(config.jobs.janitor_interval_secs);
+        let mut janitor = Janitor::new(
+            background_db.clone(),
+            settings.current().janitor_config(janitor_interval),
+        )
+        .with_id_generator(ids.clone())
+        .with_settings(settings.clone())
+        .with_jobs(jobs.clone())
+        .with_event_bus(events.clone());
+        if let Some(url) = &config.jobs.welcome_alert_webhook_url {
+            let url = url.resolve("WELCOME_ALERT_WEBHOOK_URL", &secrets)?;
+            janitor = janitor.with_welcome_hook(Arc::new(WebhookStrandedWelcomeHook::new(url)));
+        }
+        janitor.spawn();
+
+        // Feature flags are changed through the admin service; other instances' changes are
+        // picked up on the refresh interval
+        let flags = FeatureFlags::load(db.as_ref()).await?;
+        flags.spawn_refresh(
+            background_db.clone(),
+            Duration::from_secs(config.jobs.feature_flag_refresh_secs),
+            jobs.clone(),
+        );
+
+        // Verbose traces are turned on through the admin service; those turned on through
+        // other instances are picked up on the refresh interval
+        let traces = VerboseTracing::new(config.limits.verbose_trace_lines_per_minute);
+        traces.refresh(db.as_ref()).await?;
+        traces.spawn_refresh(
+            background_db.clone(),
+            Duration::from_secs(config.jobs.verbose_trace_refresh_secs),
+            jobs.clone(),
+        );
+
+        // Device attestations are checked by the webhook configured for their format
+        let attestation = config.attestation.verifiers();
+        for format in attestation.formats() {
+            info!("Verifying {} attestations", format.as_str());
+        }
+
+        // Admin listing page tokens are signed with PAGE_TOKEN_SECRET, so they work on every
+        // instance sharing it
+        let page_token_secret = config
+            .server
+            .page_token_secret
+            .as_ref()
+            .map(|secret| secret.resolve("PAGE_TOKEN_SECRET", &secrets))
+            .transpose()?;
+        let page_tokens = match page_token_secret {
+            Some(secret) if !secret.is_empty() => PageTokens::new(secret),
+            _ => {
+                warn!("PAGE_TOKEN_SECRET is not set; page tokens only work on this instance until it restarts");
+                PageTokens::random()
+            }
+        };
+
+        // End-to-end delivery latency, exported for Prometheus when METRICS_ENDPOINT is on
+        let delivery_metrics = DeliveryMetrics::new();
+
+        // Recently active groups are loaded before the instance reports ready, when
+        // CACHE_PRIMING is on, so a restart doesn't greet the first wave of fetches with cold
+        // queries
+        let primer = config
+            .cache_priming
+            .priming_config()
+            .map(|config| (config.timeout, CachePrimer::new(db.clone(), config)));
+
+        // Active members of recently used groups are cached, so membership checks and fetches
+        // don't join the memberships table every time; MEMBERSHIP_CACHE_SIZE=0 turns this off
+        let memberships = MembershipCache::new(config.limits.membership_cache_size);
+        memberships.spawn_sync(&events);
+
+        // Authentication nonces are recorded in the database, so a request can't be replayed
+        // against another instance
+        let nonces: Arc<dyn NonceStore> = db.clone();
+
+        // Create the MLS service implementation, shared by the public and admin services
+        let mls_service = Arc::new(
+            MLSServiceImpl::new(db)
+                .with_validation_policy(config.validation)
+                .with_id_generator(ids)
+                .with_event_bus(events)
+                .with_settings(settings.clone())
+                .with_feature_flags(flags)
+                .with_verbose_tracing(traces)
+                .with_jobs(jobs.clone())
+                .with_delivery_metrics(delivery_metrics.clone())
+                .with_attestation(attestation)
+                .with_page_tokens(page_tokens)
+                .with_auth_modes(auth.mode, token_auth.mode)
+                .with_membership_cache(memberships.clone()),
+        );
+
+        if let Some(publisher) = publisher {
+            let publisher = connect_publisher(&publisher).await?;
+            EventForwarder::new(publisher, mls_service.events().clone()).spawn();
+        }
+
+        // Callers of the delivery service authenticate with the signature key they registered.
+        // The keys are kept in memory, so checking a request never touches the database.
+        let client_keys = if auth.mode == AuthMode::Off {
+            ClientKeys::new()
+        } else {
+            let keys = ClientKeys::load(background_db.as_ref()).await?;
+            keys.spawn_sync(
+                background_db,
+                mls_service.events(),
+                auth.key_refresh_interval,
+                jobs.clone(),
+            );
+            keys
+        };
+
+        // Deployments behind an identity provider can require bearer tokens, so `user_id`
+        // fields are checked against the token instead of trusted as sent
+        let token_layer = match token_validator {
+            Some(validator) => {
+                info!(
+                    "Validating bearer tokens issued by {}",
+                    token_auth.issuer.as_deref().unwrap_or_default()
+                );
+                TokenAuthLayer::new(token_auth.mode, Arc::new(validator))
+            }
+            None => TokenAuthLayer::disabled(),
+        };
+
+        // Standard gRPC health service. In "deep" readiness mode the delivery service only
+        // reports serving while the periodic self-test passes; in "shallow" mode it does once
+        // started. With cache priming on, neither reports serving before priming is done or has
+        // timed out.
+        let (health_reporter, health_service) = tonic_health::server::health_reporter();
+        let mut self_test = SelfTestJob::new(
+            mls_service.clone(),
+            Duration::from_secs(config.jobs.self_test_interval_secs),
+        )
+        .with_jobs(jobs);
+        let deep_readiness = config.server.readiness_probe == ReadinessProbe::Deep;
+        if deep_readiness || primer.is_some() {
+            health_reporter
+                .set_service_status("", ServingStatus::NotServing)
+                .await;
+            health_reporter
+                .set_not_serving::<MlsDeliveryServiceServer<MLSServiceImpl<DB>>>()
+                .await;
+        }
+        if deep_readiness {
+            // Not ready until the first self-test passes
+            self_test = self_test.with_health_reporter(health_reporter.clone());
+        }
+        tokio::spawn(async move {
+            if let Some((timeout, primer)) = primer {
+                let generation = memberships.generation();
+                match tokio::time::timeout(timeout, primer.run()).await {
+                    Ok(Ok(primed)) => {
+                        memberships.prime(&primed, generation);
+                        info!(
+                            "Primed {} active groups and {} memberships in {:?}",
+                            primed.groups.len(),
+                            primed.membership_count(),
+                            primed.elapsed
+                        );
+                    }
+                    Ok(Err(e)) => warn!("Cache priming failed: {}", e),
+                    Err(_) => warn!("Cache priming didn't finish within {:?}", timeout),
+                }
+            }
+            if !deep_readiness {
+                health_reporter
+                    .set_service_status("", ServingStatus::Serving)
+                    .await;
+                health_reporter
+                    .set_serving::<MlsDeliveryServiceServer<MLSServiceImpl<DB>>>()
+                    .await;
+            }
+            self_test.spawn();
+        });
+
+        // Under overload, shed application messages and list calls before commits and welcomes
+        let admission = AdmissionController::new(config.limits.admission_config());
+
+        // Fetches and key package claims are rate limited per authenticated caller and per peer
+        // address
+        let rate_limiter = RateLimiter::new(config.limits.rate_limit_config());
+
+        // TLS is terminated here when TLS_CERT_PATH and TLS_KEY_PATH are set, instead of by a
+        // proxy in front. SIGHUP reloads the certificate along with the runtime settings.
+        let tls = match config.tls.tls_config() {
+            Some(config) => {
+                info!(
+                    "Serving TLS with the certificate in {}",
+                    config.cert_path.display()
+                );
+                let cert = Arc::new(ReloadableCert::load(config)?);
+                cert.spawn_sighup_listener()?;
+                Some(cert)
+            }
+            None => None,
+        };
+
+        // Create the reflection service using your file descriptor set
+        let reflection_service = ReflectionBuilder::configure()
+            .register_encoded_file_descriptor_set(mls::FILE_DESCRIPTOR_SET)
+            .build_v1()?;
+
+        // Operators can call any unary RPC with protobuf JSON during incidents. Off by default,
+        // and only served on the admin address, behind the admin token.
+        let json_debug = config.server.json_debug_endpoint;
+        let json_debug_layer = if json_debug {
+            warn!(
+                "Serving the JSON debug endpoint under {} on {}",
+                JSON_DEBUG_PREFIX, config.server.admin_addr
+            );
+            JsonDebugLayer::new()
+        } else {
+            JsonDebugLayer::disabled()
+        };
+
+        // Prometheus scrapes the metrics from the gRPC port over plain HTTP/1.1
+        let metrics_endpoint = config.server.metrics_endpoint;
+        let prometheus_layer = if metrics_endpoint {
+            info!("Serving Prometheus metrics under {}", PROMETHEUS_PATH);
+            PrometheusLayer::new(delivery_metrics)
+        } else {
+            PrometheusLayer::disabled()
+        };
+
+        // Both delivery APIs authenticate their callers
+        let client_auth = ClientAuthLayer::new(&auth, client_keys, nonces);
+        let mut routes = RoutesBuilder::default();
+        routes
+            .add_service(reflection_service)
+            .add_service(health_service)
+            .add_service(MlsDeliveryServiceServer::from_arc(mls_service.clone()));
+
+        // Serve the unversioned API alongside mls.v1 until the deprecation window closes
+        if config.server.legacy_api {
+            routes.add_service(LegacyDeliveryServiceServer::new(
+                LegacyDeliveryService::new(mls_service.clone()),
+            ));
+        }
+
+        // The admin service is served on its own address, to operators presenting ADMIN_TOKEN.
+        // Without a token every admin call is rejected.
+        let admin_token = config
+            .server
+            .admin_token
+            .as_ref()
+            .map(|token| token.resolve("ADMIN_TOKEN", &secrets))
+            .transpose()?;
+        let admin_auth = AdminAuthLayer::new(admin_token.as_deref());
+        if !admin_auth.is_configured() {
+            warn!("ADMIN_TOKEN is not set; the admin service rejects every call");
+        }
+        let mut admin_routes = Routes::new(MlsAdminServiceServer::from_arc(mls_service.clone()));
+        // The JSON debug endpoint reaches the delivery service from there too
+        if json_debug {
+            admin_routes =
+                admin_routes.add_service(MlsDeliveryServiceServer::from_arc(mls_service.clone()));
+        }
+
+        Ok(MlsServer {
+            addr: config.server.addr,
+            admin_addr: config.server.admin_addr,
+            service: mls_service,
+            routes: routes.routes(),
+            admin_routes,
+            admin_auth,
+            tls,
+            // HTTP/1.1 is only accepted while the metrics or JSON debug endpoint is on, so
+            // Prometheus and plain curl can call them
+            accept_http1: metrics_endpoint,
+            admin_accept_http1: json_debug,
+            cors,
+            prometheus_layer,
+            json_debug_layer,
+            rpc_metrics,
+            settings,
+            admission,
+            token_layer,
+            rate_limiter,
+            client_auth,
+        })
+    }
+
+    /// Build the server and serve it on the configured address until it stops.
+    pub async fn serve(self) -> ServerResult<()> {
+        self.build().await?.serve().await
+    }
+}
+
+/// The delivery service, set up and with its background jobs running, ready to be served.
+pub struct MlsServer<DB: DatabaseInterface + 'static> {
+    addr: SocketAddr,
+    admin_addr: SocketAddr,
+    service: Arc<MLSServiceImpl<DB>>,
+    routes: Routes,
+    admin_routes: Routes,
+    admin_auth: AdminAuthLayer,
+    tls: Option<Arc<ReloadableCert>>,
+    accept_http1: bool,
+    admin_accept_http1: bool,
+    cors: CorsLayer,
+    prometheus_layer: PrometheusLayer,
+    json_debug_layer: JsonDebugLayer,
+    rpc_metrics: RpcMetrics,
+    settings: SettingsHandle,
+    admission: AdmissionController,
+    token_layer: TokenAuthLayer,
+    rate_limiter: RateLimiter,
+    client_auth: ClientAuthLayer,
+}
+
+impl<DB: DatabaseInterface + 'static> MlsServer<DB> {
+    /// The address from the config, which `serve` listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The address from the config, which `serve` serves the admin service on.
+    pub fn admin_addr(&self) -> SocketAddr {
+        self.admin_addr
+    }
+
+    /// The service behind both the delivery and admin APIs, e.g. to subscribe to its events.
+    pub fn service(&self) -> &Arc<MLSServiceImpl<DB>> {
+        &self.service
+    }
+
+    /// The certificate TLS is terminated with, when the config sets one. Embedders serving
+    /// the router themselves pass it to a `TlsListener`.
+    pub fn tls(&self) -> Option<&Arc<ReloadableCert>> {
+        self.tls.as_ref()
+    }
+
+    /// Add the delivery service's layers and services to `server`, whose own layers stay
+    /// outermost. Further services can be added to the returned router. It doesn't include
+    /// the admin service; see `admin_router`.
+    pub fn router<L: Clone>(&self, server: Server<L>) -> Router<MlsLayers<L>> {
+        let mut server = server
+            .accept_http1(self.accept_http1)
+            .layer(self.cors.clone())
+            .layer(self.prometheus_layer.clone())
+            .layer(DeprecationLayer::new())
+            .layer(MetricsLayer::new(self.rpc_metrics.clone()))
+            .layer(MaintenanceLayer::new(self.settings.subscribe()))
+            .layer(AdmissionLayer::new(self.admission.clone()))
+            .layer(self.token_layer.clone())
+            .layer(self.client_auth.clone())
+            .layer(RateLimitLayer::new(self.rate_limiter.clone()));
+        server.add_routes(self.routes.clone())
+    }
+
+    /// Add the admin service and its layers to `server`, to be served on an address only
+    /// operators can reach. Every request must carry the admin token. The JSON debug endpoint,
+    /// when on, is served here as well.
+    pub fn admin_router<L: Clone>(&self, server: Server<L>) -> Router<AdminLayers<L>> {
+        let mut server = server
+            .accept_http1(self.admin_accept_http1)
+            .layer(self.admin_auth.clone())
+            .layer(self.json_debug_layer.clone())
+            .layer(DeprecationLayer::new())
+            .layer(MetricsLayer::new(self.rpc_metrics.clone()));
+        server.add_routes(self.admin_routes.clone())
+    }
+
+    /// Serve the delivery and admin services on their configured addresses, over TLS when
+    /// configured, until either stops.
+    pub async fn serve(self) -> ServerResult<()> {
+        let addr = self.addr;
+        let admin_addr = self.admin_addr;
+        let accept_http1 = self.accept_http1;
+        let admin_accept_http1 = self.admin_accept_http1;
+        info!("Starting MLS Delivery Service on {}", addr);
+        info!("Serving the admin service on {}", admin_addr);
+
+        let router = self.router(Server::builder());
+        let admin_router = self.admin_router(Server::builder());
+        let delivery = async {
+            match self.tls.clone() {
+                Some(cert) => {
+                    let listener = TcpListener::bind(addr).await?;
+                    router
+                        .serve_with_incoming(
+                            TlsListener::new(listener, cert, accept_http1).into_incoming(),
+                        )
+                        .await?
+                }
+                None => router.serve(addr).await?,
+            }
+            ServerResult::Ok(())
+        };
+        let admin = async {
+            match self.tls.clone() {
+                Some(cert) => {
+                    let listener = TcpListener::bind(admin_addr).await?;
+                    admin_router
+                        .serve_with_incoming(
+                            TlsListener::new(listener, cert, admin_accept_http1).into_incoming(),
+                        )
+                        .await?
+                }
+                None => admin_router.serve(admin_addr).await?,
+            }
+            ServerResult::Ok(())
+        };
+        tokio::try_join!(delivery, admin)?;
+
+        Ok(())
+    }
+}
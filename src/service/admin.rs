@@ -0,0 +1,1191 @@
+use std::sync::Arc;
+
+use log::info;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::auth::admin::require_admin;
+use crate::db::{
+    AbuseReport, AffectedRows, DatabaseInterface, DbError, EntityKind, FeatureFlag, Filter,
+    FilterField, FilterOp, FilterValue, GroupStorageStats, IntegrityIssueKind, MembershipChange,
+    PagePosition, VerboseTrace, ABUSE_REPORT_FILTER_FIELDS, CLIENT_FILTER_FIELDS,
+    GROUP_FILTER_FIELDS, KEY_PACKAGE_CLAIM_FILTER_FIELDS,
+};
+use crate::error::ServiceError;
+use crate::events::DomainEvent;
+use crate::flags;
+use crate::notices::{self, SystemNotice};
+use crate::timestamps;
+use crate::trace::{DEFAULT_TRACE_DURATION_SECS, MAX_TRACE_DURATION_SECS};
+
+use super::mls;
+use super::pagination::PageTokens;
+use super::validation::{
+    Validator, DEFAULT_ADMIN_LIST_LIMIT, DEFAULT_STORAGE_STATS_LIMIT, MAX_ADMIN_LIST_LIMIT,
+    MAX_DEVICE_NAME_LEN, MAX_FILTER_LEN, MAX_FLAG_NAME_LEN, MAX_FLAG_TARGETS, MAX_IDENTITY_LEN,
+    MAX_REPORT_REASON_LEN, MAX_STORAGE_STATS_LIMIT, MAX_TRACE_REASON_LEN,
+};
+use super::MLSServiceImpl;
+
+impl Validator {
+    // Parse a required entity type field into the database entity kind
+    fn entity_kind(&mut self, field: &str, entity_type: i32) -> EntityKind {
+        match self.enum_value(field, entity_type) {
+            Some(mls::EntityType::Client) => EntityKind::Client,
+            Some(mls::EntityType::Group) => EntityKind::Group,
+            Some(mls::EntityType::Membership) => EntityKind::Membership,
+            Some(mls::EntityType::KeyPackage) => EntityKind::KeyPackage,
+            Some(mls::EntityType::Message) => EntityKind::Message,
+            // Invalid values are recorded as violations, so `finish` rejects the request
+            Some(mls::EntityType::Unspecified) | None => EntityKind::Client,
+        }
+    }
+
+    // Check a feature flag name: a short lowercase identifier
+    fn flag_name(&mut self, field: &str, name: &str) {
+        self.string(field, name, MAX_FLAG_NAME_LEN);
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-.".contains(c))
+        {
+            self.violation(
+                field,
+                "must only contain lowercase letters, digits, '_', '-' and '.'",
+            );
+        }
+    }
+}
+
+impl Validator {
+    // Parse an admin listing filter over the given fields
+    fn filter(&mut self, field: &str, input: &str, fields: &[FilterField]) -> Filter {
+        if input.len() > MAX_FILTER_LEN {
+            self.violation(field, format!("must be at most {} bytes", MAX_FILTER_LEN));
+            return Filter::default();
+        }
+        Filter::parse(input, fields).unwrap_or_else(|e| {
+            self.violation(field, e.to_string());
+            Filter::default()
+        })
+    }
+
+    // Check the page token of an admin listing, returning the position it resumes from
+    fn page_token(
+        &mut self,
+        field: &str,
+        tokens: &PageTokens,
+        listing: &str,
+        filter: &str,
+        token: &str,
+    ) -> Option<PagePosition> {
+        tokens.open(listing, filter, token).unwrap_or_else(|e| {
+            self.violation(field, e.to_string());
+            None
+        })
+    }
+
+    // Check the row limit of an admin listing, 0 meaning the default
+    fn list_limit(&mut self, field: &str, limit: u32) -> i64 {
+        if limit > MAX_ADMIN_LIST_LIMIT {
+            self.violation(field, format!("must be at most {}", MAX_ADMIN_LIST_LIMIT));
+        }
+        match limit {
+            0 => DEFAULT_ADMIN_LIST_LIMIT as i64,
+            limit => limit as i64,
+        }
+    }
+}
+
+// Helper function to convert an integrity issue kind to its proto representation
+fn integrity_issue_kind_to_proto(kind: IntegrityIssueKind) -> mls::IntegrityIssueKind {
+    match kind {
+        IntegrityIssueKind::PayloadHashMismatch => mls::IntegrityIssueKind::PayloadHashMismatch,
+        IntegrityIssueKind::OrphanedMessage => mls::IntegrityIssueKind::OrphanedMessage,
+        IntegrityIssueKind::OrphanedMembership => mls::IntegrityIssueKind::OrphanedMembership,
+    }
+}
+
+// Helper function to convert a group's storage stats to their proto representation
+fn storage_stats_to_proto(stats: GroupStorageStats) -> mls::GroupStorageStats {
+    let fanout = if stats.payload_bytes > 0 {
+        stats.delivered_bytes as f64 / stats.payload_bytes as f64
+    } else {
+        0.0
+    };
+    mls::GroupStorageStats {
+        group_id: stats.group_id.to_string(),
+        messages: stats.messages as u64,
+        active_members: stats.active_members as u64,
+        payload_bytes: stats.payload_bytes as u64,
+        delivered_bytes: stats.delivered_bytes as u64,
+        fanout,
+        payload_bytes_last_day: stats.payload_bytes_last_day as u64,
+        payload_bytes_last_week: stats.payload_bytes_last_week as u64,
+        bytes_per_day: stats.payload_bytes_last_week as f64 / 7.0,
+    }
+}
+
+// Helper function to convert the rows an operation would change to their proto representation
+fn affected_to_proto(affected: Vec<AffectedRows>) -> Vec<mls::AffectedRows> {
+    affected
+        .into_iter()
+        .map(|a| mls::AffectedRows {
+            table: a.table.to_string(),
+            rows: a.rows as u64,
+        })
+        .collect()
+}
+
+// The rows an operation would change, for the log, e.g. "2 messages, 1 groups"
+fn describe_affected(affected: &[AffectedRows]) -> String {
+    affected
+        .iter()
+        .filter(|a| a.rows > 0)
+        .map(|a| format!("{} {}", a.rows, a.table))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Helper function to convert a feature flag to its proto representation
+fn flag_to_proto(flag: FeatureFlag) -> mls::FeatureFlag {
+    mls::FeatureFlag {
+        name: flag.name,
+        enabled: flag.enabled,
+        percentage: flag.percentage as u32,
+        group_ids: flag.group_ids.iter().map(Uuid::to_string).collect(),
+        user_ids: flag.user_ids.iter().map(Uuid::to_string).collect(),
+        updated_at: timestamps::to_rfc3339(flag.updated_at),
+    }
+}
+
+// Helper function to convert a verbose trace and the lines it logged here to its proto
+// representation
+fn trace_to_proto(
+    trace: VerboseTrace,
+    (lines_logged, lines_dropped): (u64, u64),
+) -> mls::VerboseTrace {
+    mls::VerboseTrace {
+        id: trace.id.to_string(),
+        group_id: trace.group_id.map(|id| id.to_string()).unwrap_or_default(),
+        client_id: trace.client_id.map(|id| id.to_string()).unwrap_or_default(),
+        reason: trace.reason,
+        created_at: timestamps::to_rfc3339(trace.created_at),
+        expires_at: timestamps::to_rfc3339(trace.expires_at),
+        lines_logged,
+        lines_dropped,
+    }
+}
+
+// Helper function to convert an abuse report to its proto representation
+fn abuse_report_to_proto(report: AbuseReport) -> mls::AbuseReport {
+    mls::AbuseReport {
+        id: report.id.to_string(),
+        group_id: report.group_id.to_string(),
+        reporter_id: report.reporter_id.to_string(),
+        message_ids: report.message_ids.iter().map(Uuid::to_string).collect(),
+        reason: report.reason,
+        excerpt: report.excerpt.unwrap_or_default(),
+        created_at: timestamps::to_rfc3339(report.created_at),
+        resolved_at: report
+            .resolved_at
+            .map(timestamps::to_rfc3339)
+            .unwrap_or_default(),
+        resolution: report.resolution.unwrap_or_default(),
+    }
+}
+
+// Implement the admin gRPC service trait
+#[tonic::async_trait]
+impl<DB: DatabaseInterface + Send + Sync + 'static> mls::mls_admin_service_server::MlsAdminService
+    for MLSServiceImpl<DB>
+{
+    // Diagnostics
+    async fn get_group_diagnostics(
+        &self,
+        request: Request<mls::GetGroupDiagnosticsRequest>,
+    ) -> Result<Response<mls::GetGroupDiagnosticsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        v.finish()?;
+
+        // Get the group and its active memberships
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        let memberships = self
+            .db
+            .list_memberships_by_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        // Work out how far behind the current epoch each member is
+        let members = memberships
+            .into_iter()
+            .filter(|m| m.removed_at.is_none())
+            .map(|m| {
+                let last_acked_epoch = m.last_acked_epoch.unwrap_or_default();
+                mls::MemberEpochStatus {
+                    membership_id: m.id.to_string(),
+                    client_id: m.client_id.to_string(),
+                    role: m.role.to_string(),
+                    last_acked_epoch: last_acked_epoch as u64,
+                    last_acked_at: timestamps::to_rfc3339_opt(m.last_acked_at),
+                    epochs_behind: (group.epoch - last_acked_epoch).max(0) as u64,
+                    member_role: mls::MembershipRole::from(m.role) as i32,
+                }
+            })
+            .collect();
+
+        Ok(Response::new(mls::GetGroupDiagnosticsResponse {
+            group_id: group.id.to_string(),
+            current_epoch: group.epoch as u64,
+            members,
+        }))
+    }
+
+    async fn get_storage_stats(
+        &self,
+        request: Request<mls::GetStorageStatsRequest>,
+    ) -> Result<Response<mls::GetStorageStatsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.optional_uuid("group_id", &req.group_id);
+        if req.limit > MAX_STORAGE_STATS_LIMIT {
+            v.violation(
+                "limit",
+                format!("must be at most {}", MAX_STORAGE_STATS_LIMIT),
+            );
+        }
+        v.finish()?;
+        let limit = match req.limit {
+            0 => DEFAULT_STORAGE_STATS_LIMIT,
+            limit => limit,
+        };
+
+        let stats = self
+            .db
+            .group_storage_stats(group_id, timestamps::now(), limit as i64)
+            .await
+            .map_err(Self::map_db_error)?;
+        if group_id.is_some() && stats.is_empty() {
+            return Err(ServiceError::NotFound { entity: "Group" }.into());
+        }
+
+        let groups: Vec<_> = stats.into_iter().map(storage_stats_to_proto).collect();
+        Ok(Response::new(mls::GetStorageStatsResponse {
+            payload_bytes: groups.iter().map(|g| g.payload_bytes).sum(),
+            delivered_bytes: groups.iter().map(|g| g.delivered_bytes).sum(),
+            bytes_per_day: groups.iter().map(|g| g.bytes_per_day).sum(),
+            groups,
+        }))
+    }
+
+    // Admin listings
+    async fn list_all_clients(
+        &self,
+        request: Request<mls::ListAllClientsRequest>,
+    ) -> Result<Response<mls::ListAllClientsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let mut filter = v.filter("filter", &req.filter, CLIENT_FILTER_FIELDS);
+        let limit = v.list_limit("limit", req.limit);
+        let after = v.page_token(
+            "page_token",
+            &self.page_tokens,
+            "ListAllClients",
+            &req.filter,
+            &req.page_token,
+        );
+        v.finish()?;
+        if let Some(position) = after {
+            filter = filter.after("created_at", "id", position);
+        }
+
+        let clients = self
+            .db
+            .list_clients(&filter, limit)
+            .await
+            .map_err(Self::map_db_error)?;
+        let next_page_token =
+            self.next_page_token("ListAllClients", &req.filter, limit, &clients, |c| {
+                PagePosition {
+                    at: c.created_at,
+                    id: c.id,
+                }
+            });
+
+        Ok(Response::new(mls::ListAllClientsResponse {
+            clients: clients.into_iter().map(Self::client_to_proto).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn list_all_groups(
+        &self,
+        request: Request<mls::ListAllGroupsRequest>,
+    ) -> Result<Response<mls::ListAllGroupsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let mut filter = v.filter("filter", &req.filter, GROUP_FILTER_FIELDS);
+        let limit = v.list_limit("limit", req.limit);
+        let after = v.page_token(
+            "page_token",
+            &self.page_tokens,
+            "ListAllGroups",
+            &req.filter,
+            &req.page_token,
+        );
+        v.finish()?;
+        if let Some(position) = after {
+            filter = filter.after("created_at", "id", position);
+        }
+
+        let groups = self
+            .db
+            .list_groups(&filter, limit)
+            .await
+            .map_err(Self::map_db_error)?;
+        let next_page_token =
+            self.next_page_token("ListAllGroups", &req.filter, limit, &groups, |g| {
+                PagePosition {
+                    at: g.created_at,
+                    id: g.id,
+                }
+            });
+
+        Ok(Response::new(mls::ListAllGroupsResponse {
+            groups: groups.into_iter().map(Self::group_to_proto).collect(),
+            next_page_token,
+        }))
+    }
+
+    // Deletion lifecycle
+    async fn soft_delete(
+        &self,
+        request: Request<mls::SoftDeleteRequest>,
+    ) -> Result<Response<mls::SoftDeleteResponse>, Status> {
+        // Deletions can't be undone once purged, so they are never left to the listener alone
+        require_admin(&request)?;
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let kind = v.entity_kind("entity_type", req.entity_type);
+        let id = v.uuid("id", &req.id);
+        v.finish()?;
+
+        if req.dry_run {
+            let affected = self
+                .db
+                .preview_soft_delete(kind, id)
+                .await
+                .map_err(Self::map_db_error)?;
+            info!(
+                "Dry run: soft-deleting {:?} {} would change {}",
+                kind,
+                id,
+                describe_affected(&affected)
+            );
+            return Ok(Response::new(mls::SoftDeleteResponse {
+                success: true,
+                affected: affected_to_proto(affected),
+            }));
+        }
+
+        // Mark the entity deleted; the janitor purges it after the grace period
+        self.db
+            .soft_delete(kind, id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.events.publish(DomainEvent::EntityDeleted { kind, id });
+        info!("Soft-deleted {:?} {}", kind, id);
+
+        // Let the affected groups know
+        match kind {
+            EntityKind::Client => self.notify_client_deactivated(id).await?,
+            EntityKind::Group => {
+                self.notify_group(id, None, SystemNotice::GroupFrozen { group_id: id })
+                    .await?
+            }
+            _ => {}
+        }
+
+        Ok(Response::new(mls::SoftDeleteResponse {
+            success: true,
+            affected: Vec::new(),
+        }))
+    }
+
+    async fn force_purge(
+        &self,
+        request: Request<mls::ForcePurgeRequest>,
+    ) -> Result<Response<mls::ForcePurgeResponse>, Status> {
+        // Deletions can't be undone once purged, so they are never left to the listener alone
+        require_admin(&request)?;
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let kind = v.entity_kind("entity_type", req.entity_type);
+        let id = v.uuid("id", &req.id);
+        v.finish()?;
+
+        if req.dry_run {
+            let affected = self
+                .db
+                .preview_purge(kind, id)
+                .await
+                .map_err(Self::map_db_error)?;
+            info!(
+                "Dry run: purging {:?} {} would delete {}",
+                kind,
+                id,
+                describe_affected(&affected)
+            );
+            return Ok(Response::new(mls::ForcePurgeResponse {
+                success: true,
+                affected: affected_to_proto(affected),
+            }));
+        }
+
+        // Purge a soft-deleted entity without waiting for the grace period
+        self.db
+            .purge_entity(kind, id)
+            .await
+            .map_err(Self::map_db_error)?;
+        if let Some(change) = MembershipChange::of_purged(kind, id) {
+            self.memberships.invalidate(change);
+        }
+        self.events.publish(DomainEvent::EntityPurged { kind, id });
+        info!("Purged {:?} {}", kind, id);
+
+        Ok(Response::new(mls::ForcePurgeResponse {
+            success: true,
+            affected: Vec::new(),
+        }))
+    }
+
+    async fn cancel_purge(
+        &self,
+        request: Request<mls::CancelPurgeRequest>,
+    ) -> Result<Response<mls::CancelPurgeResponse>, Status> {
+        // Deletions can't be undone once purged, so they are never left to the listener alone
+        require_admin(&request)?;
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let kind = v.entity_kind("entity_type", req.entity_type);
+        let id = v.uuid("id", &req.id);
+        v.finish()?;
+
+        // Restore a soft-deleted entity that hasn't been purged yet
+        self.db
+            .restore_deleted(kind, id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.events
+            .publish(DomainEvent::EntityRestored { kind, id });
+
+        Ok(Response::new(mls::CancelPurgeResponse { success: true }))
+    }
+
+    // Service accounts
+    async fn register_service_client(
+        &self,
+        request: Request<mls::RegisterServiceClientRequest>,
+    ) -> Result<Response<mls::RegisterServiceClientResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let user_id = v.uuid("user_id", &req.user_id);
+        v.string("identity", &req.identity, MAX_IDENTITY_LEN);
+        v.string("name", &req.name, MAX_DEVICE_NAME_LEN);
+        v.finish()?;
+
+        let client = self.new_client(user_id, &req.identity, req.name, true)?;
+        let client_id = client.id;
+
+        self.db
+            .register_client(client)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.events.publish(DomainEvent::ClientRegistered {
+            client_id,
+            user_id,
+            is_service: true,
+        });
+
+        Ok(Response::new(mls::RegisterServiceClientResponse {
+            client_id: client_id.to_string(),
+        }))
+    }
+
+    async fn list_service_clients(
+        &self,
+        request: Request<mls::ListServiceClientsRequest>,
+    ) -> Result<Response<mls::ListServiceClientsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let user_id = v.uuid("user_id", &req.user_id);
+        v.finish()?;
+
+        let clients = self
+            .db
+            .list_clients_by_user(user_id, true)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::ListServiceClientsResponse {
+            clients: clients
+                .into_iter()
+                .filter(|c| c.is_service)
+                .map(Self::client_to_proto)
+                .collect(),
+        }))
+    }
+
+    async fn revoke_service_client(
+        &self,
+        request: Request<mls::RevokeServiceClientRequest>,
+    ) -> Result<Response<mls::RevokeServiceClientResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+
+        // Human devices go through SoftDelete instead
+        let client = self
+            .db
+            .get_client(client_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        if !client.is_service {
+            return Err(ServiceError::FailedPrecondition(
+                "Client is not a service client".to_string(),
+            )
+            .into());
+        }
+
+        self.db
+            .soft_delete(EntityKind::Client, client_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.events.publish(DomainEvent::EntityDeleted {
+            kind: EntityKind::Client,
+            id: client_id,
+        });
+        self.notify_client_deactivated(client_id).await?;
+
+        Ok(Response::new(mls::RevokeServiceClientResponse {
+            success: true,
+        }))
+    }
+
+    // Key package audit
+    async fn list_key_package_claims(
+        &self,
+        request: Request<mls::ListKeyPackageClaimsRequest>,
+    ) -> Result<Response<mls::ListKeyPackageClaimsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let client_id = v.optional_uuid("client_id", &req.client_id);
+        let mut filter = v.filter("filter", &req.filter, KEY_PACKAGE_CLAIM_FILTER_FIELDS);
+        let limit = v.list_limit("limit", req.limit);
+        // Tokens are bound to the client_id as well as the filter
+        let scope = format!("{}\n{}", req.client_id, req.filter);
+        let after = v.page_token(
+            "page_token",
+            &self.page_tokens,
+            "ListKeyPackageClaims",
+            &scope,
+            &req.page_token,
+        );
+        v.finish()?;
+        if let Some(client_id) = client_id {
+            filter = filter.and("client_id", FilterOp::Eq, FilterValue::Uuid(client_id));
+        }
+        if let Some(position) = after {
+            filter = filter.after("claimed_at", "key_package_id", position);
+        }
+
+        // Claims are kept after the client and its key packages are purged
+        let claims = self
+            .db
+            .list_key_package_claims(&filter, limit)
+            .await
+            .map_err(Self::map_db_error)?;
+        let next_page_token =
+            self.next_page_token("ListKeyPackageClaims", &scope, limit, &claims, |c| {
+                PagePosition {
+                    at: c.claimed_at,
+                    id: c.key_package_id,
+                }
+            });
+
+        Ok(Response::new(mls::ListKeyPackageClaimsResponse {
+            claims: claims
+                .into_iter()
+                .map(|claim| mls::KeyPackageClaim {
+                    key_package_id: claim.key_package_id.to_string(),
+                    client_id: claim.client_id.to_string(),
+                    hash: claim.hash,
+                    published_at: timestamps::to_rfc3339(claim.published_at),
+                    claimed_at: timestamps::to_rfc3339(claim.claimed_at),
+                    group_id: claim.group_id.to_string(),
+                    claimed_by: claim.claimed_by.to_string(),
+                })
+                .collect(),
+            next_page_token,
+        }))
+    }
+
+    // Runtime settings
+    async fn reload_settings(
+        &self,
+        _request: Request<mls::ReloadSettingsRequest>,
+    ) -> Result<Response<mls::ReloadSettingsResponse>, Status> {
+        let settings = self
+            .settings
+            .reload()
+            .map_err(|e| ServiceError::FailedPrecondition(e.to_string()))?;
+        info!("Reloaded runtime settings");
+
+        Ok(Response::new(mls::ReloadSettingsResponse {
+            settings: serde_json::to_string(&settings)
+                .map_err(|e| ServiceError::internal("Serialization error", e))?,
+        }))
+    }
+
+    // Data residency
+    async fn get_residency_report(
+        &self,
+        _request: Request<mls::GetResidencyReportRequest>,
+    ) -> Result<Response<mls::GetResidencyReportResponse>, Status> {
+        let settings = self.settings.current();
+
+        // Count what each tenant tagged with another region has stored here
+        let mut tenants = Vec::new();
+        for &user_id in settings.tenant_regions.keys() {
+            let Some(region) = settings.misplaced_region(user_id) else {
+                continue;
+            };
+            let clients = self
+                .db
+                .list_clients_by_user(user_id, false)
+                .await
+                .map_err(Self::map_db_error)?;
+            let mut tenant = mls::MisplacedTenant {
+                user_id: user_id.to_string(),
+                region: region.to_string(),
+                clients: clients.len() as u64,
+                key_packages: 0,
+                groups: 0,
+                backups: 0,
+            };
+            for client in &clients {
+                tenant.key_packages += self
+                    .db
+                    .list_key_packages_by_client(client.id, false)
+                    .await
+                    .map_err(Self::map_db_error)?
+                    .len() as u64;
+                let created =
+                    Filter::default().and("creator_id", FilterOp::Eq, FilterValue::Uuid(client.id));
+                tenant.groups += self
+                    .db
+                    .list_groups(&created, i64::MAX)
+                    .await
+                    .map_err(Self::map_db_error)?
+                    .len() as u64;
+                match self.db.get_client_backup(client.id).await {
+                    Ok(_) => tenant.backups += 1,
+                    Err(DbError::NotFound) => {}
+                    Err(e) => return Err(Self::map_db_error(e)),
+                }
+            }
+            if !clients.is_empty() {
+                tenants.push(tenant);
+            }
+        }
+
+        Ok(Response::new(mls::GetResidencyReportResponse {
+            data_region: settings.data_region.unwrap_or_default(),
+            tenants,
+        }))
+    }
+
+    // Feature flags
+    async fn set_feature_flag(
+        &self,
+        request: Request<mls::SetFeatureFlagRequest>,
+    ) -> Result<Response<mls::SetFeatureFlagResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        if req.flag.is_none() {
+            v.violation("flag", "is required");
+        }
+        let flag = req.flag.unwrap_or_default();
+        v.flag_name("flag.name", &flag.name);
+        if flag.percentage > 100 {
+            v.violation("flag.percentage", "must be at most 100");
+        }
+        let group_ids = v.capped_uuids("flag.group_ids", &flag.group_ids, MAX_FLAG_TARGETS);
+        let user_ids = v.capped_uuids("flag.user_ids", &flag.user_ids, MAX_FLAG_TARGETS);
+        v.finish()?;
+
+        let flag = FeatureFlag {
+            name: flag.name,
+            enabled: flag.enabled,
+            percentage: flag.percentage as i32,
+            group_ids,
+            user_ids,
+            updated_at: timestamps::now(),
+        };
+        self.db
+            .upsert_feature_flag(flag.clone())
+            .await
+            .map_err(Self::map_db_error)?;
+        self.flags.insert(flag.clone());
+        info!(
+            "Set feature flag {} (enabled: {}, {}% of groups)",
+            flag.name, flag.enabled, flag.percentage
+        );
+
+        Ok(Response::new(mls::SetFeatureFlagResponse {
+            flag: Some(flag_to_proto(flag)),
+        }))
+    }
+
+    async fn list_feature_flags(
+        &self,
+        request: Request<mls::ListFeatureFlagsRequest>,
+    ) -> Result<Response<mls::ListFeatureFlagsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.optional_uuid("group_id", &req.group_id);
+        let user_id = v.optional_uuid("user_id", &req.user_id);
+        v.finish()?;
+
+        // Read from the database so the answer doesn't depend on this instance's snapshot
+        let stored = self
+            .db
+            .list_feature_flags()
+            .await
+            .map_err(Self::map_db_error)?;
+
+        let enabled_flags = if group_id.is_some() || user_id.is_some() {
+            stored
+                .iter()
+                .filter(|flag| flags::is_enabled_for(flag, group_id, user_id))
+                .map(|flag| flag.name.clone())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Response::new(mls::ListFeatureFlagsResponse {
+            flags: stored.into_iter().map(flag_to_proto).collect(),
+            enabled_flags,
+        }))
+    }
+
+    async fn delete_feature_flag(
+        &self,
+        request: Request<mls::DeleteFeatureFlagRequest>,
+    ) -> Result<Response<mls::DeleteFeatureFlagResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        v.flag_name("name", &req.name);
+        v.finish()?;
+
+        self.db
+            .delete_feature_flag(&req.name)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.flags.remove(&req.name);
+        info!("Deleted feature flag {}", req.name);
+
+        Ok(Response::new(mls::DeleteFeatureFlagResponse {
+            success: true,
+        }))
+    }
+
+    // Verbose tracing
+    async fn enable_verbose_tracing(
+        &self,
+        request: Request<mls::EnableVerboseTracingRequest>,
+    ) -> Result<Response<mls::EnableVerboseTracingResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.optional_uuid("group_id", &req.group_id);
+        let client_id = v.optional_uuid("client_id", &req.client_id);
+        if req.group_id.is_empty() == req.client_id.is_empty() {
+            v.violation("group_id", "set exactly one of group_id and client_id");
+        }
+        if req.duration_secs as u64 > MAX_TRACE_DURATION_SECS {
+            v.violation(
+                "duration_secs",
+                format!("must be at most {} seconds", MAX_TRACE_DURATION_SECS),
+            );
+        }
+        v.string("reason", &req.reason, MAX_TRACE_REASON_LEN);
+        if req.reason.trim().is_empty() {
+            v.violation("reason", "is required");
+        }
+        v.finish()?;
+
+        let duration_secs = match req.duration_secs {
+            0 => DEFAULT_TRACE_DURATION_SECS,
+            secs => secs as u64,
+        };
+        let created_at = timestamps::now();
+        let trace = VerboseTrace {
+            id: self.ids.generate(),
+            group_id,
+            client_id,
+            reason: req.reason,
+            created_at,
+            expires_at: created_at + chrono::Duration::seconds(duration_secs as i64),
+        };
+        self.db
+            .create_verbose_trace(trace.clone())
+            .await
+            .map_err(Self::map_db_error)?;
+        self.traces.insert(trace.clone());
+        let traced = match (group_id, client_id) {
+            (Some(group_id), _) => format!("group {}", group_id),
+            (None, client_id) => format!("client {}", client_id.unwrap_or_default()),
+        };
+        info!(
+            "Enabled verbose trace {} of {} for {}s: {}",
+            trace.id, traced, duration_secs, trace.reason
+        );
+
+        Ok(Response::new(mls::EnableVerboseTracingResponse {
+            trace: Some(trace_to_proto(trace, (0, 0))),
+        }))
+    }
+
+    async fn list_verbose_traces(
+        &self,
+        _request: Request<mls::ListVerboseTracesRequest>,
+    ) -> Result<Response<mls::ListVerboseTracesResponse>, Status> {
+        // Read from the database so the answer doesn't depend on this instance's snapshot
+        let traces = self
+            .db
+            .list_verbose_traces(timestamps::now())
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::ListVerboseTracesResponse {
+            traces: traces
+                .into_iter()
+                .map(|trace| {
+                    let stats = self.traces.stats(trace.id);
+                    trace_to_proto(trace, stats)
+                })
+                .collect(),
+        }))
+    }
+
+    async fn disable_verbose_tracing(
+        &self,
+        request: Request<mls::DisableVerboseTracingRequest>,
+    ) -> Result<Response<mls::DisableVerboseTracingResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let trace_id = v.uuid("trace_id", &req.trace_id);
+        v.finish()?;
+
+        self.db
+            .delete_verbose_trace(trace_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.traces.remove(trace_id);
+        info!("Disabled verbose trace {}", trace_id);
+
+        Ok(Response::new(mls::DisableVerboseTracingResponse {
+            success: true,
+        }))
+    }
+
+    // Self-test
+    async fn run_self_test(
+        &self,
+        request: Request<mls::RunSelfTestRequest>,
+    ) -> Result<Response<mls::RunSelfTestResponse>, Status> {
+        let req = request.into_inner();
+        let result = if req.cached {
+            self.latest_self_test().ok_or_else(|| {
+                ServiceError::FailedPrecondition("No self-test has run yet".to_string())
+            })?
+        } else {
+            MLSServiceImpl::run_self_test(self).await
+        };
+
+        Ok(Response::new(result.to_proto()))
+    }
+
+    // Background jobs
+    async fn list_job_statuses(
+        &self,
+        _request: Request<mls::ListJobStatusesRequest>,
+    ) -> Result<Response<mls::ListJobStatusesResponse>, Status> {
+        let now = timestamps::now();
+
+        Ok(Response::new(mls::ListJobStatusesResponse {
+            jobs: self
+                .jobs
+                .statuses()
+                .into_iter()
+                .map(|job| mls::JobStatus {
+                    stalled: job.is_stalled(now),
+                    name: job.name,
+                    interval_secs: job.interval.as_secs(),
+                    last_run_at: timestamps::to_rfc3339_opt(job.last_run_at),
+                    last_duration_ms: job.last_duration.as_millis() as u64,
+                    last_rows: job.last_rows,
+                    last_error: job.last_error.unwrap_or_default(),
+                    last_success_at: timestamps::to_rfc3339_opt(job.last_success_at),
+                    runs: job.runs,
+                    failures: job.failures,
+                    rows_processed: job.rows_processed,
+                })
+                .collect(),
+        }))
+    }
+
+    // Integrity scan
+    async fn run_integrity_scan(
+        &self,
+        request: Request<mls::RunIntegrityScanRequest>,
+    ) -> Result<Response<mls::RunIntegrityScanResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let limit = v.list_limit("limit", req.limit);
+        v.finish()?;
+
+        let scanned_at = timestamps::now();
+        let found = self
+            .db
+            .find_integrity_issues(limit)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        // Orphaned rows are retired through the usual lifecycle; a corrupted payload can't be
+        // recovered, so it's only reported
+        let mut issues = Vec::with_capacity(found.len());
+        let mut repaired = 0;
+        for issue in found {
+            let repair = match issue.kind {
+                IntegrityIssueKind::PayloadHashMismatch => None,
+                IntegrityIssueKind::OrphanedMessage => Some("soft_delete_message"),
+                IntegrityIssueKind::OrphanedMembership => Some("remove_membership"),
+            };
+            let fixed = if req.repair && repair.is_some() {
+                let result = match issue.kind {
+                    IntegrityIssueKind::OrphanedMessage => {
+                        self.db.soft_delete(EntityKind::Message, issue.id).await
+                    }
+                    _ => {
+                        let removed = self.db.remove_membership(issue.id).await;
+                        if let Some(client_id) = issue.related_id {
+                            self.memberships
+                                .invalidate(MembershipChange::Client(client_id));
+                        }
+                        removed
+                    }
+                };
+                match result {
+                    // Fixed by someone else since the scan
+                    Ok(()) | Err(DbError::NotFound) => true,
+                    Err(e) => return Err(Self::map_db_error(e)),
+                }
+            } else {
+                false
+            };
+            repaired += fixed as u64;
+            issues.push(mls::IntegrityIssue {
+                kind: integrity_issue_kind_to_proto(issue.kind) as i32,
+                id: issue.id.to_string(),
+                related_id: issue
+                    .related_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_default(),
+                repair: repair.unwrap_or_default().to_string(),
+                repaired: fixed,
+            });
+        }
+        if !issues.is_empty() {
+            info!(
+                "Integrity scan found {} issues, repaired {}",
+                issues.len(),
+                repaired
+            );
+        }
+
+        Ok(Response::new(mls::RunIntegrityScanResponse {
+            issues,
+            repaired,
+            scanned_at: timestamps::to_rfc3339(scanned_at),
+        }))
+    }
+
+    // Abuse report review
+    async fn list_abuse_reports(
+        &self,
+        request: Request<mls::ListAbuseReportsRequest>,
+    ) -> Result<Response<mls::ListAbuseReportsResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let mut filter = v.filter("filter", &req.filter, ABUSE_REPORT_FILTER_FIELDS);
+        let limit = v.list_limit("limit", req.limit);
+        let after = v.page_token(
+            "page_token",
+            &self.page_tokens,
+            "ListAbuseReports",
+            &req.filter,
+            &req.page_token,
+        );
+        v.finish()?;
+        if let Some(position) = after {
+            filter = filter.after("created_at", "id", position);
+        }
+
+        let reports = self
+            .db
+            .list_abuse_reports(&filter, limit)
+            .await
+            .map_err(Self::map_db_error)?;
+        let next_page_token =
+            self.next_page_token("ListAbuseReports", &req.filter, limit, &reports, |r| {
+                PagePosition {
+                    at: r.created_at,
+                    id: r.id,
+                }
+            });
+
+        Ok(Response::new(mls::ListAbuseReportsResponse {
+            reports: reports.into_iter().map(abuse_report_to_proto).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn resolve_abuse_report(
+        &self,
+        request: Request<mls::ResolveAbuseReportRequest>,
+    ) -> Result<Response<mls::ResolveAbuseReportResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let report_id = v.uuid("report_id", &req.report_id);
+        v.string("resolution", &req.resolution, MAX_REPORT_REASON_LEN);
+        v.finish()?;
+
+        let resolved_at = timestamps::now();
+        self.db
+            .resolve_abuse_report(report_id, &req.resolution, resolved_at)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => ServiceError::NotFound {
+                    entity: "AbuseReport",
+                }
+                .into(),
+                DbError::Conflict(reason) => ServiceError::FailedPrecondition(reason).into(),
+                e => Self::map_db_error(e),
+            })?;
+        info!("Resolved abuse report {}", report_id);
+
+        Ok(Response::new(mls::ResolveAbuseReportResponse {
+            resolved_at: timestamps::to_rfc3339(resolved_at),
+        }))
+    }
+
+    // Tenant offboarding
+    async fn destroy_tenant_key(
+        &self,
+        request: Request<mls::DestroyTenantKeyRequest>,
+    ) -> Result<Response<mls::DestroyTenantKeyResponse>, Status> {
+        // The tenant's data is gone for good once its key is
+        require_admin(&request)?;
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let tenant_id = v.uuid("tenant_id", &req.tenant_id);
+        v.finish()?;
+
+        let destroyed_at = timestamps::now();
+        let encrypted = self
+            .db
+            .destroy_tenant_key(tenant_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        if !encrypted {
+            return Err(ServiceError::FailedPrecondition(
+                "Storage encryption is not configured".to_string(),
+            )
+            .into());
+        }
+        info!("Destroyed the storage key of tenant {}", tenant_id);
+
+        Ok(Response::new(mls::DestroyTenantKeyResponse {
+            destroyed_at: timestamps::to_rfc3339(destroyed_at),
+        }))
+    }
+}
+
+impl<DB: DatabaseInterface + Send + Sync + 'static> MLSServiceImpl<DB> {
+    // Token for the page after `rows` of a listing, or empty if it was the last page
+    fn next_page_token<T>(
+        &self,
+        listing: &str,
+        filter: &str,
+        limit: i64,
+        rows: &[T],
+        position: impl Fn(&T) -> PagePosition,
+    ) -> String {
+        match rows.last() {
+            Some(last) if rows.len() as i64 == limit => {
+                self.page_tokens.issue(listing, filter, position(last))
+            }
+            _ => String::new(),
+        }
+    }
+
+    // Ask the other members of each of the client's groups to remove it
+    async fn notify_client_deactivated(&self, client_id: Uuid) -> Result<(), Status> {
+        let memberships = self
+            .db
+            .list_memberships_by_client(client_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        for membership in memberships.iter().filter(|m| m.removed_at.is_none()) {
+            self.notify_group(
+                membership.group_id,
+                Some(client_id),
+                SystemNotice::ClientDeactivated { client_id },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Enqueue a notice to a group's active members and announce it to subscribers
+    pub(super) async fn notify_group(
+        &self,
+        group_id: Uuid,
+        exclude: Option<Uuid>,
+        notice: SystemNotice,
+    ) -> Result<(), Status> {
+        let message = notices::notify_group(
+            self.db.as_ref(),
+            self.ids.as_ref(),
+            group_id,
+            exclude,
+            notice,
+        )
+        .await
+        .map_err(Self::map_db_error)?;
+        if let Some(message) = message {
+            self.events
+                .publish(DomainEvent::MessageStored(Arc::new(message)));
+        }
+        Ok(())
+    }
+}
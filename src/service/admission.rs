@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use crate::metrics::layer::rpc_method;
+
+// Delivery RPCs that advance group state. They are small and clients can't make progress
+// without them, so they are the last to be shed.
+const CRITICAL_METHODS: &[&str] = &[
+    "StoreCommit",
+    "StoreWelcome",
+    "StoreProposal",
+    "RequestWelcomeResend",
+    "AcknowledgeEpoch",
+];
+
+// Application message sends and list calls are shed first: they are the bulk of the load and
+// clients can retry them without falling behind their groups
+const BULK_METHODS: &[&str] = &["StoreApplicationMessage"];
+const BULK_PREFIXES: &[&str] = &["List"];
+
+// Percentage of the in-flight limit each class may fill. Lower classes leave headroom that
+// only higher ones can use.
+const STANDARD_SHARE: usize = 75;
+const BULK_SHARE: usize = 50;
+
+// Priority class of a delivery RPC, highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RpcClass {
+    Critical,
+    Standard,
+    Bulk,
+}
+
+impl RpcClass {
+    pub const ALL: [RpcClass; 3] = [RpcClass::Critical, RpcClass::Standard, RpcClass::Bulk];
+
+    // Class of a gRPC request path, or None for requests admission control doesn't apply to:
+    // the admin service, health checks and reflection
+    pub fn of_path(path: &str) -> Option<Self> {
+        let (service, _) = path.trim_start_matches('/').split_once('/')?;
+        if !service.ends_with(".MlsDeliveryService") {
+            return None;
+        }
+
+        let method = rpc_method(path)?;
+        if CRITICAL_METHODS.contains(&method) {
+            Some(Self::Critical)
+        } else if BULK_METHODS.contains(&method)
+            || BULK_PREFIXES
+                .iter()
+                .any(|prefix| method.starts_with(prefix))
+        {
+            Some(Self::Bulk)
+        } else {
+            Some(Self::Standard)
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// Limits of the admission controller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdmissionConfig {
+    // Delivery RPCs handled at once; 0 disables admission control
+    pub max_in_flight: usize,
+    // Requests of each class (indexed like `RpcClass::ALL`) that may wait for a slot. Once a
+    // class's queue is full its requests are rejected right away.
+    pub max_queued: [usize; 3],
+    // How long a queued request waits for a slot before it's rejected
+    pub queue_timeout: Duration,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 0,
+            max_queued: [256, 64, 16],
+            queue_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl AdmissionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_in_flight > 0
+    }
+
+    // In-flight requests above which a class has to wait. Every class may use at least one slot.
+    pub fn class_limit(&self, class: RpcClass) -> usize {
+        let share = match class {
+            RpcClass::Critical => 100,
+            RpcClass::Standard => STANDARD_SHARE,
+            RpcClass::Bulk => BULK_SHARE,
+        };
+        (self.max_in_flight * share / 100).max(1)
+    }
+}
+
+#[derive(Default)]
+struct AdmissionState {
+    in_flight: usize,
+    // Waiting requests of each class, oldest first
+    queues: [VecDeque<oneshot::Sender<()>>; 3],
+    rejected: [u64; 3],
+}
+
+// Priority-aware admission control for delivery RPCs. A request runs right away if fewer than
+// its class's limit are in flight, waits in its class's queue if that has room, and is
+// rejected otherwise. Freed slots go to the highest class waiting.
+#[derive(Clone)]
+pub struct AdmissionController {
+    config: Arc<AdmissionConfig>,
+    state: Arc<Mutex<AdmissionState>>,
+}
+
+// A slot held by a running request, released when dropped
+pub struct AdmissionPermit {
+    controller: AdmissionController,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.controller.release();
+    }
+}
+
+impl AdmissionController {
+    pub fn new(config: AdmissionConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            state: Arc::default(),
+        }
+    }
+
+    // Wait for a slot for a request of the given class, or reject it with UNAVAILABLE
+    pub async fn admit(&self, class: RpcClass) -> Result<AdmissionPermit, Status> {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            if state.in_flight < self.config.class_limit(class) {
+                state.in_flight += 1;
+                return Ok(self.permit());
+            }
+            if state.queues[class.index()].len() >= self.config.max_queued[class.index()] {
+                state.rejected[class.index()] += 1;
+                return Err(Self::overloaded());
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.queues[class.index()].push_back(sender);
+            receiver
+        };
+
+        let mut waiting = Waiting {
+            controller: self,
+            receiver,
+        };
+        match tokio::time::timeout(self.config.queue_timeout, &mut waiting.receiver).await {
+            Ok(Ok(())) => Ok(self.permit()),
+            _ => {
+                self.state.lock().unwrap().rejected[class.index()] += 1;
+                Err(Self::overloaded())
+            }
+        }
+    }
+
+    // Requests of each class (indexed like `RpcClass::ALL`) rejected so far
+    pub fn rejected(&self) -> [u64; 3] {
+        self.state.lock().unwrap().rejected
+    }
+
+    // Requests currently holding a slot
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+
+    fn permit(&self) -> AdmissionPermit {
+        AdmissionPermit {
+            controller: self.clone(),
+        }
+    }
+
+    fn overloaded() -> Status {
+        Status::unavailable("The service is overloaded; try again later")
+    }
+
+    // Free a slot, handing it to the oldest waiting request of the highest class that may use it
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        for class in RpcClass::ALL {
+            while state.in_flight < self.config.class_limit(class) {
+                let Some(waiter) = state.queues[class.index()].pop_front() else {
+                    break;
+                };
+                // Waiters that gave up have dropped their receiver
+                if waiter.send(()).is_ok() {
+                    state.in_flight += 1;
+                }
+            }
+        }
+    }
+}
+
+// A queued request. If it's dropped (timed out or cancelled) after a slot was handed to it,
+// the slot is released again.
+struct Waiting<'a> {
+    controller: &'a AdmissionController,
+    receiver: oneshot::Receiver<()>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.receiver.close();
+        if self.receiver.try_recv().is_ok() {
+            self.controller.release();
+        }
+    }
+}
+
+// Tower layer that applies admission control to delivery RPCs
+#[derive(Clone)]
+pub struct AdmissionLayer {
+    controller: AdmissionController,
+}
+
+impl AdmissionLayer {
+    pub fn new(controller: AdmissionController) -> Self {
+        Self { controller }
+    }
+}
+
+impl<S> Layer<S> for AdmissionLayer {
+    type Service = Admission<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Admission {
+            inner,
+            controller: self.controller.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Admission<S> {
+    inner: S,
+    controller: AdmissionController,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Admission<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let Some(class) =
+            RpcClass::of_path(request.uri().path()).filter(|_| self.controller.config.is_enabled())
+        else {
+            return Box::pin(self.inner.call(request));
+        };
+
+        // The ready inner service is used for this request, a clone is left for the next
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let controller = self.controller.clone();
+        Box::pin(async move {
+            let _permit = match controller.admit(class).await {
+                Ok(permit) => permit,
+                Err(status) => return Ok(status.into_http()),
+            };
+            inner.call(request).await
+        })
+    }
+}
@@ -0,0 +1,146 @@
+use tonic::{Extensions, Request, Status};
+use uuid::Uuid;
+
+use super::MLSServiceImpl;
+use crate::auth::token::AuthenticatedUser;
+use crate::auth::AuthenticatedClient;
+use crate::db::{DatabaseInterface, DbError};
+use crate::error::ServiceError;
+
+// Who made a request, as far as client and token authentication established
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Caller {
+    client_id: Option<Uuid>,
+    user_id: Option<Uuid>,
+}
+
+impl Caller {
+    pub(crate) fn of<T>(request: &Request<T>) -> Self {
+        Self::from_extensions(request.extensions())
+    }
+
+    // For handlers that take the request apart
+    pub(crate) fn from_extensions(extensions: &Extensions) -> Self {
+        Self {
+            client_id: extensions
+                .get::<AuthenticatedClient>()
+                .map(|client| client.0),
+            user_id: extensions.get::<AuthenticatedUser>().map(|user| user.0),
+        }
+    }
+
+    pub(crate) fn client_id(&self) -> Option<Uuid> {
+        self.client_id
+    }
+}
+
+// Whose resources an RPC reads or acts for
+pub(crate) enum Owner {
+    // The client's messages, key packages and backup, or a request it sends
+    Client(Uuid),
+    // The user's clients
+    User(Uuid),
+}
+
+impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
+    // Check the caller may access the owner's resources. An authenticated client may only act
+    // as itself and see its own user's clients; a token's user only its own clients.
+    // Unauthenticated callers are left to the authentication modes.
+    pub(crate) async fn authorize(&self, caller: Caller, owner: Owner) -> Result<(), Status> {
+        let allowed = match owner {
+            Owner::Client(client_id) => {
+                caller
+                    .client_id
+                    .is_none_or(|caller_id| caller_id == client_id)
+                    && match caller.user_id {
+                        // Unknown clients are left for the handler to report
+                        Some(user_id) => self
+                            .client_user(client_id)
+                            .await?
+                            .is_none_or(|owner_id| owner_id == user_id),
+                        None => true,
+                    }
+            }
+            Owner::User(user_id) => {
+                caller.user_id.is_none_or(|caller_id| caller_id == user_id)
+                    && match caller.client_id {
+                        Some(client_id) => self.client_user(client_id).await? == Some(user_id),
+                        None => true,
+                    }
+            }
+        };
+
+        if !allowed {
+            return Err(ServiceError::PermissionDenied(
+                "Caller may only access its own resources".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // Check the caller may read the group: an authenticated client must be one of its active
+    // members, and a token's user must have a client that is. Unauthenticated callers are left
+    // to the authentication modes.
+    pub(crate) async fn authorize_member(
+        &self,
+        caller: Caller,
+        group_id: Uuid,
+    ) -> Result<(), Status> {
+        let is_member = match (caller.client_id, caller.user_id) {
+            (Some(client_id), _) => {
+                self.authorize(caller, Owner::Client(client_id)).await?;
+                self.is_active_member(client_id, group_id).await?
+            }
+            (None, Some(user_id)) => {
+                let clients = self
+                    .db
+                    .list_clients_by_user(user_id, false)
+                    .await
+                    .map_err(Self::map_db_error)?;
+                let mut is_member = false;
+                for client in clients {
+                    if self.is_active_member(client.id, group_id).await? {
+                        is_member = true;
+                        break;
+                    }
+                }
+                is_member
+            }
+            (None, None) => true,
+        };
+
+        if !is_member {
+            return Err(ServiceError::PermissionDenied(
+                "Only active members can read the group".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // Check the sender may write to the group: only its active members can send it messages,
+    // so a client can't store into a group it was never added to or has left
+    pub(crate) async fn authorize_sender(
+        &self,
+        sender_id: Uuid,
+        group_id: Uuid,
+    ) -> Result<(), Status> {
+        if !self.is_active_member(sender_id, group_id).await? {
+            return Err(ServiceError::PermissionDenied(
+                "Only active members can write to the group".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // The user the client belongs to, or None if it doesn't exist
+    async fn client_user(&self, client_id: Uuid) -> Result<Option<Uuid>, Status> {
+        match self.db.get_client(client_id).await {
+            Ok(client) => Ok(Some(client.user_id)),
+            Err(DbError::NotFound) => Ok(None),
+            Err(e) => Err(Self::map_db_error(e)),
+        }
+    }
+}
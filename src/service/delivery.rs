@@ -0,0 +1,99 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::db::{DeliveryCursor, Message};
+
+// Version byte leading every ack token, so the format can change without misreading old tokens.
+// Version 1 carried message positions, before messages had sequences.
+const TOKEN_VERSION: u8 = 2;
+// Group id and sequence of one cursor
+const CURSOR_LEN: usize = 16 + 8;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("is not a valid ack token")]
+pub struct InvalidAckToken;
+
+// Cursor acknowledging a message and everything before it in its group
+pub fn cursor(message: &Message) -> DeliveryCursor {
+    DeliveryCursor {
+        group_id: message.group_id,
+        sequence: message.sequence,
+    }
+}
+
+// Cursors acknowledging a fetch. `matched` holds the ids and cursors of the messages the fetch
+// matched; each group's cursor stops before the first of them that wasn't returned, so messages
+// a fetch held back are delivered again.
+pub fn delivered_through(
+    matched: &[(Uuid, DeliveryCursor)],
+    returned: &HashSet<Uuid>,
+) -> Vec<DeliveryCursor> {
+    let mut held_back: HashMap<Option<Uuid>, i64> = HashMap::new();
+    for (_, cursor) in matched.iter().filter(|(id, _)| !returned.contains(id)) {
+        let first = held_back.entry(cursor.group_id).or_insert(cursor.sequence);
+        *first = (*first).min(cursor.sequence);
+    }
+
+    let mut cursors: BTreeMap<Option<Uuid>, i64> = BTreeMap::new();
+    for (_, cursor) in matched.iter().filter(|(id, _)| returned.contains(id)) {
+        if held_back
+            .get(&cursor.group_id)
+            .is_some_and(|&first| cursor.sequence > first)
+        {
+            continue;
+        }
+        let sequence = cursors.entry(cursor.group_id).or_insert(cursor.sequence);
+        *sequence = (*sequence).max(cursor.sequence);
+    }
+
+    cursors
+        .into_iter()
+        .map(|(group_id, sequence)| DeliveryCursor { group_id, sequence })
+        .collect()
+}
+
+// Opaque token carrying the cursors, empty when there are none
+pub fn ack_token(cursors: &[DeliveryCursor]) -> String {
+    if cursors.is_empty() {
+        return String::new();
+    }
+
+    let mut token = Vec::with_capacity(1 + cursors.len() * CURSOR_LEN);
+    token.push(TOKEN_VERSION);
+    for cursor in cursors {
+        token.extend_from_slice(cursor.group_id.unwrap_or_else(Uuid::nil).as_bytes());
+        token.extend_from_slice(&cursor.sequence.to_be_bytes());
+    }
+    BASE64.encode(token)
+}
+
+// Cursors an ack token carries; the empty token carries none
+pub fn open_ack_token(token: &str) -> Result<Vec<DeliveryCursor>, InvalidAckToken> {
+    if token.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let token = BASE64.decode(token).map_err(|_| InvalidAckToken)?;
+    let Some((&TOKEN_VERSION, cursors)) = token.split_first() else {
+        return Err(InvalidAckToken);
+    };
+    if cursors.is_empty() || cursors.len() % CURSOR_LEN != 0 {
+        return Err(InvalidAckToken);
+    }
+
+    cursors
+        .chunks(CURSOR_LEN)
+        .map(|cursor| {
+            let group_id = Uuid::from_slice(&cursor[..16]).map_err(|_| InvalidAckToken)?;
+            let sequence = i64::from_be_bytes(cursor[16..].try_into().expect("slice is 8 bytes"));
+            Ok(DeliveryCursor {
+                group_id: Some(group_id).filter(|g| !g.is_nil()),
+                sequence,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,207 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use http::HeaderValue;
+use tower::{Layer, Service};
+
+use super::mls;
+
+// Response metadata carrying one warning per deprecation that applies to the called RPC
+pub const DEPRECATION_HEADER: &str = "deprecation-warning";
+
+// Sunset of the string fields replaced by proto enums
+const ENUM_STRING_SUNSET: &str = "2027-03-31";
+
+// Something clients should stop using: a service, an RPC ("Service/Method") or a field
+// ("Message.field")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    pub target: &'static str,
+    pub replacement: &'static str,
+    // Date (YYYY-MM-DD) after which it may be removed
+    pub sunset: &'static str,
+    // Request paths of the RPCs whose responses carry the warning. A path ending in '/' covers
+    // every RPC of the service.
+    pub rpcs: &'static [&'static str],
+}
+
+// Everything currently deprecated. Proto migrations add their entry here when the replacement
+// ships, so clients see the sunset date on every affected call.
+pub const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        target: "mls.MlsDeliveryService",
+        replacement: "mls.v1.MlsDeliveryService",
+        sunset: "2027-03-31",
+        rpcs: &["/mls.MlsDeliveryService/"],
+    },
+    Deprecation {
+        target: "mls.v1.Client.scheme",
+        replacement: "credential_scheme",
+        sunset: ENUM_STRING_SUNSET,
+        rpcs: &[
+            "/mls.v1.MlsDeliveryService/GetClient",
+            "/mls.v1.MlsDeliveryService/ListClients",
+            "/mls.v1.MlsAdminService/ListServiceClients",
+            "/mls.v1.MlsAdminService/ListAllClients",
+        ],
+    },
+    Deprecation {
+        target: "mls.v1.AddMemberRequest.role",
+        replacement: "member_role",
+        sunset: ENUM_STRING_SUNSET,
+        rpcs: &["/mls.v1.MlsDeliveryService/AddMember"],
+    },
+    Deprecation {
+        target: "mls.v1.Membership.role",
+        replacement: "member_role",
+        sunset: ENUM_STRING_SUNSET,
+        rpcs: &[
+            "/mls.v1.MlsDeliveryService/ListMemberships",
+            "/mls.v1.MlsDeliveryService/GetMembership",
+            "/mls.v1.MlsDeliveryService/ListMembershipHistory",
+        ],
+    },
+    Deprecation {
+        target: "mls.v1.MemberEpochStatus.role",
+        replacement: "member_role",
+        sunset: ENUM_STRING_SUNSET,
+        rpcs: &["/mls.v1.MlsAdminService/GetGroupDiagnostics"],
+    },
+    Deprecation {
+        target: "mls.v1.StoreProposalRequest.proposal_type",
+        replacement: "type",
+        sunset: ENUM_STRING_SUNSET,
+        rpcs: &["/mls.v1.MlsDeliveryService/StoreProposal"],
+    },
+    Deprecation {
+        target: "mls.v1.FetchMessagesRequest.message_types",
+        replacement: "types",
+        sunset: ENUM_STRING_SUNSET,
+        rpcs: &["/mls.v1.MlsDeliveryService/FetchMessages"],
+    },
+    Deprecation {
+        target: "mls.v1.Message.message_type",
+        replacement: "type",
+        sunset: ENUM_STRING_SUNSET,
+        rpcs: &[
+            "/mls.v1.MlsDeliveryService/FetchMessages",
+            "/mls.v1.MlsDeliveryService/SubscribeMessages",
+        ],
+    },
+    Deprecation {
+        target: "mls.v1.MlsDeliveryService/MarkMessagesRead",
+        replacement: "AckMessages",
+        sunset: "2027-09-30",
+        rpcs: &["/mls.v1.MlsDeliveryService/MarkMessagesRead"],
+    },
+];
+
+impl Deprecation {
+    pub fn applies_to(&self, path: &str) -> bool {
+        self.rpcs.iter().any(|rpc| {
+            if rpc.ends_with('/') {
+                path.starts_with(rpc)
+            } else {
+                path == *rpc
+            }
+        })
+    }
+
+    // Value of the warning header, e.g.
+    // "mls.v1.Message.message_type; replacement=type; sunset=2027-03-31"
+    pub fn warning(&self) -> String {
+        format!(
+            "{}; replacement={}; sunset={}",
+            self.target, self.replacement, self.sunset
+        )
+    }
+
+    pub fn to_proto(&self) -> mls::Deprecation {
+        mls::Deprecation {
+            target: self.target.to_string(),
+            replacement: self.replacement.to_string(),
+            sunset: self.sunset.to_string(),
+            rpcs: self
+                .rpcs
+                .iter()
+                .map(|rpc| rpc.trim_start_matches('/').to_string())
+                .collect(),
+        }
+    }
+}
+
+// Deprecations that apply to a gRPC request path
+pub fn deprecations_for(path: &str) -> impl Iterator<Item = &'static Deprecation> + '_ {
+    DEPRECATIONS.iter().filter(move |d| d.applies_to(path))
+}
+
+// Tower layer that adds a deprecation warning header to the responses of deprecated RPCs and
+// of RPCs carrying deprecated fields
+#[derive(Clone, Default)]
+pub struct DeprecationLayer;
+
+impl DeprecationLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for DeprecationLayer {
+    type Service = Deprecations<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Deprecations { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct Deprecations<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Deprecations<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Warned<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let warnings = deprecations_for(request.uri().path())
+            .filter_map(|d| HeaderValue::from_str(&d.warning()).ok())
+            .collect();
+        Warned {
+            inner: Box::pin(self.inner.call(request)),
+            warnings,
+        }
+    }
+}
+
+// Response future that adds the warnings to the response headers
+pub struct Warned<F> {
+    inner: Pin<Box<F>>,
+    warnings: Vec<HeaderValue>,
+}
+
+impl<F, ResBody, E> Future for Warned<F>
+where
+    F: Future<Output = Result<http::Response<ResBody>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut output = std::task::ready!(self.inner.as_mut().poll(cx));
+        if let Ok(response) = &mut output {
+            for warning in self.warnings.drain(..) {
+                response.headers_mut().append(DEPRECATION_HEADER, warning);
+            }
+        }
+        Poll::Ready(output)
+    }
+}
@@ -0,0 +1,135 @@
+use std::str::FromStr;
+
+use super::mls;
+use super::validation::Validator;
+use crate::db;
+
+// A database enum with a proto counterpart. Both have the same variants, and the
+// proto one adds an unspecified zero value.
+pub trait ProtoEnum: FromStr + Copy {
+    type Proto: TryFrom<i32>;
+
+    // Stored string of every variant, as accepted by the deprecated string fields
+    const NAMES: &'static [&'static str];
+
+    // None for the unspecified value
+    fn from_proto(value: Self::Proto) -> Option<Self>;
+}
+
+macro_rules! proto_enum {
+    ($name:ident { $($variant:ident),+ $(,)? }) => {
+        impl From<db::$name> for mls::$name {
+            fn from(value: db::$name) -> Self {
+                match value {
+                    $(db::$name::$variant => mls::$name::$variant,)+
+                }
+            }
+        }
+
+        impl ProtoEnum for db::$name {
+            type Proto = mls::$name;
+
+            const NAMES: &'static [&'static str] = db::$name::NAMES;
+
+            fn from_proto(value: mls::$name) -> Option<Self> {
+                match value {
+                    mls::$name::Unspecified => None,
+                    $(mls::$name::$variant => Some(db::$name::$variant),)+
+                }
+            }
+        }
+    };
+}
+
+proto_enum!(MessageType {
+    Proposal,
+    Commit,
+    Welcome,
+    Application,
+    System,
+});
+
+proto_enum!(ProposalType {
+    Add,
+    Update,
+    Remove,
+    Psk,
+    Reinit,
+    ExternalInit,
+    GroupContextExtensions,
+});
+
+proto_enum!(MembershipRole {
+    Admin,
+    Member,
+    Moderator,
+});
+
+proto_enum!(CredentialScheme { Basic, X509 });
+
+proto_enum!(AttestationVerdict {
+    Unattested,
+    Passed,
+    Failed,
+});
+
+impl Validator {
+    // Parse a required proto enum field into its database enum
+    pub fn proto_enum<E: ProtoEnum>(&mut self, field: &str, value: i32) -> Option<E> {
+        self.enum_value(field, value).and_then(E::from_proto)
+    }
+
+    // Parse a required proto enum field, or its deprecated string field when the enum is unset
+    pub fn enum_or_legacy<E: ProtoEnum>(
+        &mut self,
+        field: &str,
+        value: i32,
+        legacy_field: &str,
+        legacy: &str,
+    ) -> Option<E> {
+        if value != 0 {
+            self.proto_enum(field, value)
+        } else if !legacy.is_empty() {
+            self.legacy_enum(legacy_field, legacy)
+        } else {
+            self.violation(field, "is required");
+            None
+        }
+    }
+
+    // Parse a repeated proto enum field, or its deprecated string field when the enum list is empty
+    pub fn enums_or_legacy<E: ProtoEnum>(
+        &mut self,
+        field: &str,
+        values: &[i32],
+        legacy_field: &str,
+        legacy: &[String],
+    ) -> Vec<E> {
+        if !values.is_empty() {
+            values
+                .iter()
+                .enumerate()
+                .filter_map(|(i, value)| {
+                    self.enum_value(&format!("{}[{}]", field, i), *value)
+                        .and_then(E::from_proto)
+                })
+                .collect()
+        } else {
+            legacy
+                .iter()
+                .enumerate()
+                .filter_map(|(i, value)| {
+                    self.legacy_enum(&format!("{}[{}]", legacy_field, i), value)
+                })
+                .collect()
+        }
+    }
+
+    // Parse the string form of an enum
+    fn legacy_enum<E: ProtoEnum>(&mut self, field: &str, value: &str) -> Option<E> {
+        value.parse().ok().or_else(|| {
+            self.violation(field, format!("must be one of: {}", E::NAMES.join(", ")));
+            None
+        })
+    }
+}
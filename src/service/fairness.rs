@@ -0,0 +1,80 @@
+use std::collections::{HashMap, VecDeque};
+
+use uuid::Uuid;
+
+use crate::db::{Message, MessageType};
+
+// How FetchMessages orders and limits application messages, so one member flooding a group
+// can't bury everyone else's messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FetchFairness {
+    // Round-robin application messages by sender between handshake messages
+    pub interleave_senders: bool,
+    // Application messages returned per sender; the rest wait for a later fetch. 0 is unlimited.
+    pub max_application_per_sender: usize,
+}
+
+impl FetchFairness {
+    pub fn is_chronological(&self) -> bool {
+        !self.interleave_senders && self.max_application_per_sender == 0
+    }
+
+    // Reorder and cap chronologically ordered messages. Handshake messages (and system notices)
+    // are never dropped or moved, so they keep their strict order and each application message
+    // stays between the same handshake messages, i.e. in its epoch.
+    pub fn apply(&self, messages: Vec<Message>) -> Vec<Message> {
+        if self.is_chronological() {
+            return messages;
+        }
+
+        let mut returned: HashMap<Uuid, usize> = HashMap::new();
+        let mut ordered = Vec::with_capacity(messages.len());
+        let mut run = Vec::new();
+        for message in messages {
+            if message.message_type != MessageType::Application {
+                ordered.extend(self.order_run(std::mem::take(&mut run)));
+                ordered.push(message);
+                continue;
+            }
+
+            let count = returned.entry(message.sender_id).or_default();
+            if self.max_application_per_sender == 0 || *count < self.max_application_per_sender {
+                *count += 1;
+                run.push(message);
+            }
+        }
+        ordered.extend(self.order_run(run));
+        ordered
+    }
+
+    // Order a run of application messages with no handshake message in between
+    fn order_run(&self, run: Vec<Message>) -> Vec<Message> {
+        if !self.interleave_senders {
+            return run;
+        }
+
+        // Senders take turns in the order they first sent in the run, each oldest first
+        let mut senders: Vec<Uuid> = Vec::new();
+        let mut queues: HashMap<Uuid, VecDeque<Message>> = HashMap::new();
+        for message in run {
+            let queue = queues.entry(message.sender_id).or_insert_with(|| {
+                senders.push(message.sender_id);
+                VecDeque::new()
+            });
+            queue.push_back(message);
+        }
+
+        let mut interleaved = Vec::new();
+        while !queues.is_empty() {
+            for sender in &senders {
+                if let Some(queue) = queues.get_mut(sender) {
+                    interleaved.extend(queue.pop_front());
+                    if queue.is_empty() {
+                        queues.remove(sender);
+                    }
+                }
+            }
+        }
+        interleaved
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use prost_types::FieldMask;
+
+use super::mls;
+use super::validation::Validator;
+
+// Fields a read mask can select, by resource message
+pub const CLIENT_FIELDS: &[&str] = &[
+    "id",
+    "user_id",
+    "credential",
+    "scheme",
+    "device_name",
+    "last_seen",
+    "created_at",
+    "is_service",
+    "credential_scheme",
+    "signature_key",
+    "attestation_verdict",
+];
+pub const KEY_PACKAGE_FIELDS: &[&str] = &["id", "client_id", "data", "created_at", "used"];
+pub const MESSAGE_FIELDS: &[&str] = &[
+    "id",
+    "group_id",
+    "sender_id",
+    "created_at",
+    "read",
+    "message_type",
+    "proposal",
+    "commit",
+    "welcome",
+    "system",
+    "application",
+    "type",
+    "extra",
+    "payload_hash",
+    "expires_at",
+    "sequence",
+];
+
+// Fields holding the payload bytes, which the database only loads when one of them is selected
+const CLIENT_PAYLOAD_FIELDS: &[&str] = &["credential", "signature_key"];
+const KEY_PACKAGE_PAYLOAD_FIELDS: &[&str] = &["data"];
+const MESSAGE_PAYLOAD_FIELDS: &[&str] = &["proposal", "commit", "welcome", "system", "application"];
+
+// The fields of a resource message a read returns. An empty google.protobuf.FieldMask selects
+// every field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadMask {
+    paths: Option<HashSet<String>>,
+}
+
+impl ReadMask {
+    pub fn includes(&self, field: &str) -> bool {
+        self.paths
+            .as_ref()
+            .is_none_or(|paths| paths.contains(field))
+    }
+
+    fn includes_any(&self, fields: &[&str]) -> bool {
+        fields.iter().any(|field| self.includes(field))
+    }
+
+    // Whether the database has to load the payload columns of each resource
+    pub fn client_payload(&self) -> bool {
+        self.includes_any(CLIENT_PAYLOAD_FIELDS)
+    }
+
+    pub fn key_package_payload(&self) -> bool {
+        self.includes_any(KEY_PACKAGE_PAYLOAD_FIELDS)
+    }
+
+    pub fn message_payload(&self) -> bool {
+        self.includes_any(MESSAGE_PAYLOAD_FIELDS)
+    }
+
+    // Helper function to reset a field that isn't selected to its default
+    fn keep<T: Default>(&self, field: &str, value: T) -> T {
+        if self.includes(field) {
+            value
+        } else {
+            T::default()
+        }
+    }
+
+    pub fn client(&self, c: mls::Client) -> mls::Client {
+        mls::Client {
+            id: self.keep("id", c.id),
+            user_id: self.keep("user_id", c.user_id),
+            credential: self.keep("credential", c.credential),
+            scheme: self.keep("scheme", c.scheme),
+            device_name: self.keep("device_name", c.device_name),
+            last_seen: self.keep("last_seen", c.last_seen),
+            created_at: self.keep("created_at", c.created_at),
+            is_service: self.keep("is_service", c.is_service),
+            credential_scheme: self.keep("credential_scheme", c.credential_scheme),
+            signature_key: self.keep("signature_key", c.signature_key),
+            attestation_verdict: self.keep("attestation_verdict", c.attestation_verdict),
+        }
+    }
+
+    pub fn key_package(&self, kp: mls::KeyPackage) -> mls::KeyPackage {
+        mls::KeyPackage {
+            id: self.keep("id", kp.id),
+            client_id: self.keep("client_id", kp.client_id),
+            data: self.keep("data", kp.data),
+            created_at: self.keep("created_at", kp.created_at),
+            used: self.keep("used", kp.used),
+        }
+    }
+
+    pub fn message(&self, m: mls::Message) -> mls::Message {
+        use mls::message::Content;
+
+        // The content oneof is selected by the name of the field that is set
+        let content = m.content.filter(|content| {
+            self.includes(match content {
+                Content::Proposal(_) => "proposal",
+                Content::Commit(_) => "commit",
+                Content::Welcome(_) => "welcome",
+                Content::System(_) => "system",
+                Content::Application(_) => "application",
+            })
+        });
+        mls::Message {
+            id: self.keep("id", m.id),
+            group_id: self.keep("group_id", m.group_id),
+            sender_id: self.keep("sender_id", m.sender_id),
+            created_at: self.keep("created_at", m.created_at),
+            read: self.keep("read", m.read),
+            message_type: self.keep("message_type", m.message_type),
+            content,
+            r#type: self.keep("type", m.r#type),
+            extra: self.keep("extra", m.extra),
+            payload_hash: self.keep("payload_hash", m.payload_hash),
+            expires_at: self.keep("expires_at", m.expires_at),
+            sequence: self.keep("sequence", m.sequence),
+            // Only streamed messages carry one, and streams take no read mask
+            resume_token: m.resume_token,
+        }
+    }
+}
+
+impl Validator {
+    // Parse a read mask over a resource message with the given fields
+    pub fn read_mask(
+        &mut self,
+        field: &str,
+        mask: &Option<FieldMask>,
+        fields: &[&str],
+    ) -> ReadMask {
+        let paths = match mask {
+            Some(mask) if !mask.paths.is_empty() => &mask.paths,
+            _ => return ReadMask::default(),
+        };
+
+        for (i, path) in paths.iter().enumerate() {
+            if !fields.contains(&path.as_str()) {
+                self.violation(
+                    format!("{}.paths[{}]", field, i),
+                    format!("must be one of: {}", fields.join(", ")),
+                );
+            }
+        }
+        ReadMask {
+            paths: Some(paths.iter().cloned().collect()),
+        }
+    }
+}
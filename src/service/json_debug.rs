@@ -0,0 +1,298 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::{HeaderValue, StatusCode, Uri};
+use http_body_util::{BodyExt, Full, Limited};
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MethodDescriptor};
+use serde_json::json;
+use tonic::body::Body;
+use tonic::{Code, Status};
+use tower::{BoxError, Layer, Service, ServiceExt};
+
+use super::mls;
+
+// Path prefix of the debug endpoint; the rest of the path is the RPC's gRPC path
+pub const JSON_DEBUG_PREFIX: &str = "/debug/json";
+
+// Largest JSON request body the endpoint reads
+const MAX_JSON_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+// Response headers that only make sense for gRPC
+const GRPC_ONLY_HEADERS: &[&str] = &[
+    "content-type",
+    "content-length",
+    "grpc-status",
+    "grpc-message",
+    "grpc-status-details-bin",
+];
+
+// Tower layer serving a debug endpoint that takes and returns protobuf JSON for any unary RPC,
+// so operators can call the service with curl. `POST /debug/json/<service>/<method>` with a
+// JSON body is transcoded with the descriptor set served for reflection and sent down the
+// stack as the equivalent gRPC request, so it passes through the same authentication and
+// checks. Disabled, it passes every request through.
+#[derive(Clone)]
+pub struct JsonDebugLayer {
+    pool: Option<DescriptorPool>,
+}
+
+impl JsonDebugLayer {
+    pub fn new() -> Self {
+        let pool = DescriptorPool::decode(mls::FILE_DESCRIPTOR_SET)
+            .expect("the embedded descriptor set is valid");
+        Self { pool: Some(pool) }
+    }
+
+    // Layer that serves no debug endpoint
+    pub fn disabled() -> Self {
+        Self { pool: None }
+    }
+}
+
+impl Default for JsonDebugLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for JsonDebugLayer {
+    type Service = JsonDebug<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        JsonDebug {
+            inner,
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JsonDebug<S> {
+    inner: S,
+    pool: Option<DescriptorPool>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for JsonDebug<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Send,
+    ReqBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = http::Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let debug = self
+            .pool
+            .as_ref()
+            .zip(request.uri().path().strip_prefix(JSON_DEBUG_PREFIX))
+            .map(|(pool, rpc_path)| (unary_method(pool, rpc_path), rpc_path.to_string()));
+        let Some((method, rpc_path)) = debug else {
+            let response = self.inner.call(request.map(Body::new));
+            return Box::pin(async move { Ok(response.await?.map(Body::new)) });
+        };
+
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            match method {
+                Ok(method) => transcode(inner, method, &rpc_path, request).await,
+                Err(status) => Ok(error_response(&status)),
+            }
+        })
+    }
+}
+
+// The unary RPC a gRPC path names
+fn unary_method(pool: &DescriptorPool, rpc_path: &str) -> Result<MethodDescriptor, Status> {
+    let method = rpc_path
+        .trim_start_matches('/')
+        .split_once('/')
+        .and_then(|(service, method)| {
+            pool.get_service_by_name(service)?
+                .methods()
+                .find(|m| m.name() == method)
+        })
+        .ok_or_else(|| Status::not_found(format!("Unknown RPC {}", rpc_path)))?;
+
+    if method.is_client_streaming() || method.is_server_streaming() {
+        return Err(Status::unimplemented(
+            "Only unary RPCs can be called through the JSON debug endpoint",
+        ));
+    }
+    Ok(method)
+}
+
+// Send a JSON request to the RPC as gRPC, and its response back as JSON
+async fn transcode<S, ReqBody, ResBody>(
+    inner: S,
+    method: MethodDescriptor,
+    rpc_path: &str,
+    request: http::Request<ReqBody>,
+) -> Result<http::Response<Body>, S::Error>
+where
+    S: Service<http::Request<Body>, Response = http::Response<ResBody>>,
+    ReqBody: http_body::Body<Data = Bytes>,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: http_body::Body<Data = Bytes>,
+    ResBody::Error: Into<BoxError>,
+{
+    let (mut parts, body) = request.into_parts();
+    let json = match Limited::new(body, MAX_JSON_BODY_BYTES).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => {
+            return Ok(error_response(&Status::invalid_argument(format!(
+                "Request body is unreadable or larger than {} bytes",
+                MAX_JSON_BODY_BYTES
+            ))))
+        }
+    };
+    let message = match from_json(&method, &json) {
+        Ok(message) => message,
+        Err(e) => {
+            return Ok(error_response(&Status::invalid_argument(format!(
+                "Invalid JSON request: {}",
+                e
+            ))))
+        }
+    };
+
+    // Everything but the body and path stays, so authentication and signature headers apply
+    parts.uri = match Uri::try_from(rpc_path) {
+        Ok(uri) => uri,
+        Err(_) => return Ok(error_response(&Status::not_found("Invalid RPC path"))),
+    };
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc+proto"),
+    );
+    parts
+        .headers
+        .insert("te", HeaderValue::from_static("trailers"));
+    let request = http::Request::from_parts(parts, Body::new(Full::new(frame(&message))));
+
+    let (mut parts, body) = inner.oneshot(request).await?.into_parts();
+    let body = match body.collect().await {
+        Ok(body) => body,
+        Err(_) => {
+            return Ok(error_response(&Status::internal(
+                "Response was interrupted",
+            )))
+        }
+    };
+    let status = Status::from_header_map(&parts.headers)
+        .or_else(|| body.trailers().and_then(Status::from_header_map))
+        .unwrap_or_else(|| Status::internal("Response carried no gRPC status"));
+    if status.code() != Code::Ok {
+        return Ok(error_response(&status));
+    }
+
+    let response = match unframe(&body.to_bytes())
+        .and_then(|payload| DynamicMessage::decode(method.output(), payload).ok())
+    {
+        Some(response) => response,
+        None => {
+            return Ok(error_response(&Status::internal(
+                "Response is not a valid message",
+            )))
+        }
+    };
+    let json = match serde_json::to_vec(&response) {
+        Ok(json) => json,
+        Err(e) => {
+            return Ok(error_response(&Status::internal(format!(
+                "Failed to encode response: {}",
+                e
+            ))))
+        }
+    };
+
+    for header in GRPC_ONLY_HEADERS {
+        parts.headers.remove(*header);
+    }
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    parts.status = StatusCode::OK;
+    Ok(http::Response::from_parts(
+        parts,
+        Body::new(Full::new(Bytes::from(json))),
+    ))
+}
+
+// Parse a JSON body as the RPC's request message; an empty body is the empty message
+fn from_json(method: &MethodDescriptor, json: &[u8]) -> Result<DynamicMessage, serde_json::Error> {
+    if json.iter().all(u8::is_ascii_whitespace) {
+        return Ok(DynamicMessage::new(method.input()));
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_slice(json);
+    let message = DynamicMessage::deserialize(method.input(), &mut deserializer)?;
+    deserializer.end()?;
+    Ok(message)
+}
+
+// A message in gRPC's length-prefixed framing, uncompressed
+fn frame(message: &DynamicMessage) -> Bytes {
+    let len = message.encoded_len();
+    let mut framed = BytesMut::with_capacity(5 + len);
+    framed.put_u8(0);
+    framed.put_u32(len as u32);
+    message
+        .encode(&mut framed)
+        .expect("the buffer has room for the message");
+    framed.freeze()
+}
+
+// The single uncompressed message of a unary gRPC response body
+fn unframe(body: &[u8]) -> Option<&[u8]> {
+    let (&compressed, rest) = body.split_first()?;
+    let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    let payload = rest.get(4..)?;
+    (compressed == 0 && payload.len() == len).then_some(payload)
+}
+
+// A google.rpc.Status-shaped JSON error, with the HTTP status closest to the gRPC code
+fn error_response(status: &Status) -> http::Response<Body> {
+    let body = json!({
+        "code": status.code() as i32,
+        "message": status.message(),
+    });
+    http::Response::builder()
+        .status(http_status(status.code()))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::new(Full::new(Bytes::from(body.to_string()))))
+        .expect("the response is well-formed")
+}
+
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Cancelled => StatusCode::REQUEST_TIMEOUT,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::{Arc, Once};
+
+use log::warn;
+use prost::Message;
+use tonic::{Request, Response, Status};
+
+use super::mls::mls_delivery_service_server::MlsDeliveryService as _;
+use super::MLSServiceImpl;
+use crate::db::DatabaseInterface;
+use crate::error::ServiceError;
+
+// Generated code for the frozen, unversioned `mls` package
+pub mod mls {
+    include!(concat!(env!("OUT_DIR"), "/mls.rs"));
+}
+
+static DEPRECATION_WARNING: Once = Once::new();
+
+// Serves the unversioned `mls.MlsDeliveryService` by forwarding to the `mls.v1` implementation.
+// The legacy messages are wire-compatible with v1, so requests and responses are transcoded
+// by re-encoding; v1-only fields are dropped on the way back.
+pub struct LegacyDeliveryService<DB: DatabaseInterface> {
+    inner: Arc<MLSServiceImpl<DB>>,
+}
+
+impl<DB: DatabaseInterface> LegacyDeliveryService<DB> {
+    pub fn new(inner: Arc<MLSServiceImpl<DB>>) -> Self {
+        Self { inner }
+    }
+}
+
+// Helper function to convert a message between the legacy and v1 packages
+fn transcode<From: Message, To: Message + Default>(message: From) -> Result<To, Status> {
+    To::decode(message.encode_to_vec().as_slice())
+        .map_err(|e| ServiceError::internal("Failed to transcode legacy message", e).into())
+}
+
+// Helper function to forward a legacy request to the v1 service and convert the response
+async fn forward<LegacyReq, Req, Resp, LegacyResp, F, Fut>(
+    request: Request<LegacyReq>,
+    call: F,
+) -> Result<Response<LegacyResp>, Status>
+where
+    LegacyReq: Message,
+    Req: Message + Default,
+    Resp: Message,
+    LegacyResp: Message + Default,
+    F: FnOnce(Request<Req>) -> Fut,
+    Fut: std::future::Future<Output = Result<Response<Resp>, Status>>,
+{
+    DEPRECATION_WARNING.call_once(|| {
+        warn!("Serving the deprecated unversioned mls API; clients should move to mls.v1");
+    });
+
+    let (metadata, extensions, message) = request.into_parts();
+    let request = Request::from_parts(metadata, extensions, transcode(message)?);
+
+    let (metadata, message, extensions) = call(request).await?.into_parts();
+    Ok(Response::from_parts(
+        metadata,
+        transcode(message)?,
+        extensions,
+    ))
+}
+
+// Implement every legacy RPC by forwarding it to the v1 method of the same name, optionally
+// adjusting the v1 request first so legacy clients keep their old behaviour
+macro_rules! legacy_rpcs {
+    ($($method:ident($request:ident) -> $response:ident $(, |$req:ident| $adapt:expr)?;)*) => {
+        #[tonic::async_trait]
+        impl<DB: DatabaseInterface + Send + Sync + 'static> mls::mls_delivery_service_server::MlsDeliveryService
+            for LegacyDeliveryService<DB>
+        {
+            $(
+                async fn $method(
+                    &self,
+                    request: Request<mls::$request>,
+                ) -> Result<Response<mls::$response>, Status> {
+                    forward(request, |request: Request<super::mls::$request>| {
+                        $(
+                            let mut request = request;
+                            let $req = request.get_mut();
+                            $adapt;
+                        )?
+                        self.inner.$method(request)
+                    })
+                    .await
+                }
+            )*
+        }
+    };
+}
+
+legacy_rpcs! {
+    register_client(RegisterClientRequest) -> RegisterClientResponse;
+    get_client(GetClientRequest) -> GetClientResponse;
+    list_clients(ListClientsRequest) -> ListClientsResponse;
+    publish_key_package(PublishKeyPackageRequest) -> PublishKeyPackageResponse;
+    get_key_package(GetKeyPackageRequest) -> GetKeyPackageResponse;
+    list_key_packages(ListKeyPackagesRequest) -> ListKeyPackagesResponse;
+    create_group(CreateGroupRequest) -> CreateGroupResponse;
+    get_group(GetGroupRequest) -> GetGroupResponse;
+    // Legacy ListGroups always returned each group's state
+    list_groups(ListGroupsRequest) -> ListGroupsResponse, |req| req.include_state = true;
+    add_member(AddMemberRequest) -> AddMemberResponse;
+    remove_member(RemoveMemberRequest) -> RemoveMemberResponse;
+    list_memberships(ListMembershipsRequest) -> ListMembershipsResponse;
+    acknowledge_epoch(AcknowledgeEpochRequest) -> AcknowledgeEpochResponse;
+    store_proposal(StoreProposalRequest) -> StoreProposalResponse;
+    store_commit(StoreCommitRequest) -> StoreCommitResponse;
+    store_welcome(StoreWelcomeRequest) -> StoreWelcomeResponse;
+    fetch_messages(FetchMessagesRequest) -> FetchMessagesResponse;
+}
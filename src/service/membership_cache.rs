@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tonic::Status;
+use uuid::Uuid;
+
+use super::MLSServiceImpl;
+use crate::db::{DatabaseInterface, Membership, MembershipChange, MembershipRole, PrimedGroups};
+use crate::events::{DomainEvent, EventBus};
+
+// Groups whose members are cached by default
+pub const DEFAULT_MEMBERSHIP_CACHE_SIZE: usize = 10_000;
+
+// Cached member sets are reloaded after this long even if no change was announced, in case a
+// notification was lost
+const MAX_ENTRY_AGE: Duration = Duration::from_secs(5 * 60);
+
+// Active members of a group, with their roles
+pub type MemberSet = Arc<HashMap<Uuid, MembershipRole>>;
+
+// The member set of a group's memberships, leaving out the removed ones
+pub fn member_set(memberships: &[Membership]) -> MemberSet {
+    Arc::new(
+        memberships
+            .iter()
+            .filter(|m| m.removed_at.is_none())
+            .map(|m| (m.client_id, m.role))
+            .collect(),
+    )
+}
+
+// Snapshots of the active members of recently used groups, so membership checks and fetches
+// don't join the memberships table on every request. Bounded to a number of groups, the least
+// recently used dropped first. Entries are dropped when the service changes a group's
+// memberships, when another instance announces a change (see `MembershipFanout`), and once
+// they reach MAX_ENTRY_AGE.
+#[derive(Clone)]
+pub struct MembershipCache {
+    // None while disabled
+    state: Option<Arc<Mutex<CacheState>>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+struct CacheState {
+    capacity: usize,
+    entries: HashMap<Uuid, CacheEntry>,
+    // Groups by when they were last used
+    recency: BTreeMap<u64, Uuid>,
+    clock: u64,
+    // Bumped by every invalidation, so snapshots loaded before one aren't cached after it
+    generation: u64,
+}
+
+struct CacheEntry {
+    members: MemberSet,
+    loaded_at: Instant,
+    used: u64,
+}
+
+impl Default for MembershipCache {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl MembershipCache {
+    // A cache of the members of up to `capacity` groups; disabled if it is 0
+    pub fn new(capacity: usize) -> Self {
+        let state = (capacity > 0).then(|| {
+            Arc::new(Mutex::new(CacheState {
+                capacity,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                clock: 0,
+                generation: 0,
+            }))
+        });
+        Self {
+            state,
+            hits: Arc::default(),
+            misses: Arc::default(),
+        }
+    }
+
+    // A cache that never holds anything
+    pub fn disabled() -> Self {
+        Self {
+            state: None,
+            hits: Arc::default(),
+            misses: Arc::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state.is_some()
+    }
+
+    // The cached members of a group, if a fresh snapshot is cached
+    pub fn get(&self, group_id: Uuid) -> Option<MemberSet> {
+        let state = self.state.as_ref()?;
+        let mut state = state.lock().unwrap();
+        let members = state.touch(group_id);
+        let counter = match members {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        members
+    }
+
+    // Generation to pass to `insert` for a snapshot about to be loaded
+    pub fn generation(&self) -> u64 {
+        self.state
+            .as_ref()
+            .map_or(0, |state| state.lock().unwrap().generation)
+    }
+
+    // Cache a snapshot loaded at `generation`. Dropped if an invalidation came in since, as it
+    // may predate the change.
+    pub fn insert(&self, group_id: Uuid, members: MemberSet, generation: u64) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let mut state = state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        state.insert(group_id, members);
+    }
+
+    // Cache the member sets of the groups startup priming loaded, at the generation taken
+    // before it started. The most recently active groups are kept if they don't all fit.
+    pub fn prime(&self, primed: &PrimedGroups, generation: u64) {
+        for group in primed.groups.iter().rev() {
+            let memberships = primed
+                .memberships
+                .get(&group.id)
+                .map_or(&[][..], Vec::as_slice);
+            self.insert(group.id, member_set(memberships), generation);
+        }
+    }
+
+    // Drop the snapshots a change may have made stale
+    pub fn invalidate(&self, change: MembershipChange) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let mut state = state.lock().unwrap();
+        state.generation += 1;
+        match change {
+            MembershipChange::Group(group_id) => state.remove(group_id),
+            MembershipChange::Client(client_id) => {
+                let groups: Vec<Uuid> = state
+                    .entries
+                    .iter()
+                    .filter(|(_, entry)| entry.members.contains_key(&client_id))
+                    .map(|(group_id, _)| *group_id)
+                    .collect();
+                for group_id in groups {
+                    state.remove(group_id);
+                }
+            }
+            MembershipChange::All => {
+                state.entries.clear();
+                state.recency.clear();
+            }
+        }
+    }
+
+    // Number of groups cached
+    pub fn len(&self) -> usize {
+        self.state
+            .as_ref()
+            .map_or(0, |state| state.lock().unwrap().entries.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Lookups answered from the cache and lookups that had to load the members
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    // Drop snapshots as membership changes are published: this instance's, and those other
+    // instances announce. Everything is dropped if the cache falls behind the bus.
+    pub fn spawn_sync(&self, events: &EventBus) -> JoinHandle<()> {
+        let cache = self.clone();
+        let mut events = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => cache.apply(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Membership cache missed {} events; clearing it", missed);
+                        cache.invalidate(MembershipChange::All);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        })
+    }
+
+    fn apply(&self, event: &DomainEvent) {
+        let change = match *event {
+            DomainEvent::MemberAdded { group_id, .. }
+            | DomainEvent::MemberRemoved { group_id, .. }
+            | DomainEvent::GroupCreated { group_id, .. } => MembershipChange::Group(group_id),
+            DomainEvent::EntityPurged { kind, id } => match MembershipChange::of_purged(kind, id) {
+                Some(change) => change,
+                None => return,
+            },
+            DomainEvent::MembershipsChanged(change) => change,
+            _ => return,
+        };
+        self.invalidate(change);
+    }
+}
+
+impl CacheState {
+    // A fresh snapshot of the group, marked as just used
+    fn touch(&mut self, group_id: Uuid) -> Option<MemberSet> {
+        let entry = self.entries.get(&group_id)?;
+        if entry.loaded_at.elapsed() >= MAX_ENTRY_AGE {
+            self.remove(group_id);
+            return None;
+        }
+        let used = entry.used;
+        self.clock += 1;
+        let clock = self.clock;
+        self.recency.remove(&used);
+        self.recency.insert(clock, group_id);
+        let entry = self.entries.get_mut(&group_id)?;
+        entry.used = clock;
+        Some(entry.members.clone())
+    }
+
+    fn insert(&mut self, group_id: Uuid, members: MemberSet) {
+        self.remove(group_id);
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.clock += 1;
+        self.recency.insert(self.clock, group_id);
+        self.entries.insert(
+            group_id,
+            CacheEntry {
+                members,
+                loaded_at: Instant::now(),
+                used: self.clock,
+            },
+        );
+    }
+
+    fn remove(&mut self, group_id: Uuid) {
+        if let Some(entry) = self.entries.remove(&group_id) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
+    // Active members of the group, from the membership cache when it holds them
+    pub(crate) async fn active_members(&self, group_id: Uuid) -> Result<MemberSet, Status> {
+        if let Some(members) = self.memberships.get(group_id) {
+            return Ok(members);
+        }
+        let generation = self.memberships.generation();
+        let memberships = self
+            .db
+            .list_memberships_by_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        let members = member_set(&memberships);
+        self.memberships
+            .insert(group_id, members.clone(), generation);
+        Ok(members)
+    }
+
+    // Role of the client's active membership in the group, if it has one
+    pub(crate) async fn member_role(
+        &self,
+        client_id: Uuid,
+        group_id: Uuid,
+    ) -> Result<Option<MembershipRole>, Status> {
+        Ok(self
+            .active_members(group_id)
+            .await?
+            .get(&client_id)
+            .copied())
+    }
+}
@@ -1,113 +1,513 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
-use openmls::credentials::{BasicCredential, Credential};
-use openmls::prelude::{KeyPackageIn, OpenMlsCrypto, OpenMlsProvider, OpenMlsRand};
-use openmls_rust_crypto::OpenMlsRustCrypto;
-use tls_codec::{Deserialize as TlsDeserialize, Serialize as TlsSerialize};
-use tonic::{Request, Response, Status};
+use log::warn;
+use tonic::{Request, Response, Status, Streaming};
 use uuid::Uuid;
 
-use crate::db::{DatabaseInterface, DbError};
+use crate::attestation::{AttestationEvidence, AttestationFormat, AttestationVerifiers};
+use crate::auth::AuthMode;
+use crate::db::{
+    AbuseReport, AttestationVerdict, CredentialScheme, DatabaseInterface, DbError, DeliveryCursor,
+    KeyPackageClaim, MembershipChange, MembershipRole, MessageType, ProposalType,
+};
+use crate::error::ServiceError;
+use crate::events::{DomainEvent, EventBus};
+use crate::flags::FeatureFlags;
+use crate::ids::{IdGenerator, RandomIds};
+use crate::metrics::{DeliveryMetrics, JobTracker};
+use crate::mls_codec::{self, MlsCrypto};
+use crate::notices::{self, SystemNotice};
+use crate::redact::Redacted;
+use crate::settings::SettingsHandle;
+use crate::timestamps;
+use crate::trace::{self, VerboseTracing};
+use authz::{Caller, Owner};
+use membership_cache::MembershipCache;
+use pagination::PageTokens;
+use roles::{acting_member, Permission};
+use signatures::Actor;
+use validation::{
+    ValidationPolicy, Validator, ED25519_PUBLIC_KEY_LEN, MAX_ACK_WATERMARKS, MAX_ANNOUNCEMENT_LEN,
+    MAX_ATTESTATION_TOKEN_BYTES, MAX_CLIENT_BACKUP_BYTES, MAX_DEVICE_NAME_LEN,
+    MAX_GROUP_HANDLE_LEN, MAX_GROUP_STATE_BYTES, MAX_IDENTITY_LEN, MAX_INITIAL_MEMBERS,
+    MAX_MARK_READ_MESSAGES, MAX_MESSAGE_EXTRA_BYTES, MAX_MLS_GROUP_ID_LEN, MAX_MLS_MESSAGE_BYTES,
+    MAX_REPORTED_MESSAGES, MAX_REPORT_EXCERPT_BYTES, MAX_REPORT_REASON_LEN, MAX_WELCOME_RECIPIENTS,
+};
+
+pub mod admin;
+pub mod admission;
+mod authz;
+mod delivery;
+pub mod deprecations;
+pub mod enums;
+pub mod fairness;
+pub mod field_mask;
+pub mod json_debug;
+pub mod legacy;
+pub mod membership_cache;
+pub mod pagination;
+pub mod probe;
+pub mod rate_limit;
+pub mod roles;
+pub mod server_info;
+pub mod signatures;
+mod state_upload;
+pub mod subscribe;
+pub mod validation;
+
+// Lifetime OpenMLS gives the key packages built by PublishKeyPackage. Unused key packages older
+// than this are counted as expired.
+pub const KEY_PACKAGE_LIFETIME_DAYS: i64 = 84;
+
+// Longest epoch age a group can set with SetGroupKeyRotation. Reminders any rarer than yearly
+// wouldn't help keep keys fresh.
+pub const MAX_EPOCH_AGE_SECS: u64 = 366 * 24 * 60 * 60;
 
 pub mod mls {
-    // Include the generated proto code
-    include!(concat!(env!("OUT_DIR"), "/mls.rs"));
+    // Include the generated proto code for the current API version
+    include!(concat!(env!("OUT_DIR"), "/mls.v1.rs"));
 
     // Manually define the file descriptor set
     pub const FILE_DESCRIPTOR_SET: &[u8] =
         include_bytes!(concat!(env!("OUT_DIR"), "/mls_descriptor.bin"));
 }
 
+impl Validator {
+    // Parse the members a group is created with, which must be distinct and not the creator
+    fn initial_members(
+        &mut self,
+        members: &[mls::InitialMember],
+        creator_id: Uuid,
+    ) -> Vec<(Uuid, MembershipRole)> {
+        if members.len() > MAX_INITIAL_MEMBERS {
+            self.violation(
+                "initial_members",
+                format!("must have at most {} entries", MAX_INITIAL_MEMBERS),
+            );
+            return Vec::new();
+        }
+
+        let mut seen = HashSet::from([creator_id]);
+        members
+            .iter()
+            .enumerate()
+            .filter_map(|(i, member)| {
+                let field = format!("initial_members[{}].client_id", i);
+                let client_id = self.uuid(&field, &member.client_id);
+                if !client_id.is_nil() && !seen.insert(client_id) {
+                    self.violation(field, "is the creator or listed more than once");
+                }
+                let role = self.proto_enum(&format!("initial_members[{}].role", i), member.role)?;
+                Some((client_id, role))
+            })
+            .collect()
+    }
+}
+
 // Define our MLS service implementation
 pub struct MLSServiceImpl<DB: DatabaseInterface> {
     db: Arc<DB>,
-    crypto: OpenMlsRustCrypto,
-    skip_validation: bool,
+    crypto: MlsCrypto,
+    ids: Arc<dyn IdGenerator>,
+    events: EventBus,
+    settings: SettingsHandle,
+    flags: FeatureFlags,
+    jobs: JobTracker,
+    delivery: DeliveryMetrics,
+    attestation: AttestationVerifiers,
+    page_tokens: PageTokens,
+    auth_modes: server_info::AuthModes,
+    memberships: MembershipCache,
+    traces: VerboseTracing,
+    self_test: Mutex<Option<probe::SelfTestResult>>,
+    validation: ValidationPolicy,
 }
 
 impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
     pub fn new(db: Arc<DB>) -> Self {
-        let crypto = OpenMlsRustCrypto::default();
+        let crypto = MlsCrypto::default();
         Self {
             db,
             crypto,
-            skip_validation: false,
+            ids: Arc::new(RandomIds),
+            events: EventBus::default(),
+            settings: SettingsHandle::default(),
+            flags: FeatureFlags::default(),
+            jobs: JobTracker::default(),
+            delivery: DeliveryMetrics::default(),
+            attestation: AttestationVerifiers::default(),
+            page_tokens: PageTokens::default(),
+            auth_modes: server_info::AuthModes::default(),
+            memberships: MembershipCache::default(),
+            traces: VerboseTracing::default(),
+            self_test: Mutex::new(None),
+            validation: ValidationPolicy::default(),
         }
     }
 
-    // Create a test version that skips validation
-    // Note: No cfg(test) attribute so it's available for both tests and normal code
-    pub fn new_skip_validation(db: Arc<DB>) -> Self {
-        let crypto = OpenMlsRustCrypto::default();
-        Self {
-            db,
-            crypto,
-            skip_validation: true,
-        }
+    // Check uploaded MLS payloads under another policy; the default is strict
+    pub fn with_validation_policy(mut self, validation: ValidationPolicy) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    // Use a different strategy for generating ids of new rows
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    // Publish domain events on a bus shared with other subsystems
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    // Share runtime settings that ReloadSettings reloads
+    pub fn with_settings(mut self, settings: SettingsHandle) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    // Share feature flags with other subsystems; the admin service keeps them up to date
+    pub fn with_feature_flags(mut self, flags: FeatureFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    // Share the background jobs' tracker, so the admin service can report their health
+    pub fn with_jobs(mut self, jobs: JobTracker) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    // Record how long messages take to reach their recipients in these metrics
+    pub fn with_delivery_metrics(mut self, delivery: DeliveryMetrics) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    // Check the device attestations clients register with using these verifiers
+    pub fn with_attestation(mut self, attestation: AttestationVerifiers) -> Self {
+        self.attestation = attestation;
+        self
+    }
+
+    // Sign admin listing page tokens with this key, so they work across instances and restarts
+    pub fn with_page_tokens(mut self, page_tokens: PageTokens) -> Self {
+        self.page_tokens = page_tokens;
+        self
+    }
+
+    // Auth modes the interceptors enforce, advertised by GetServerInfo
+    pub fn with_auth_modes(mut self, client_auth: AuthMode, token_auth: AuthMode) -> Self {
+        self.auth_modes.client_auth = client_auth;
+        self.auth_modes.token_auth = token_auth;
+        self
+    }
+
+    // Answer membership checks from this cache; keep it in sync with `MembershipCache::spawn_sync`
+    pub fn with_membership_cache(mut self, memberships: MembershipCache) -> Self {
+        self.memberships = memberships;
+        self
+    }
+
+    // Share verbose traces with other subsystems; the admin service keeps them up to date
+    pub fn with_verbose_tracing(mut self, traces: VerboseTracing) -> Self {
+        self.traces = traces;
+        self
+    }
+
+    // Feature flags gating risky behaviors
+    pub fn feature_flags(&self) -> &FeatureFlags {
+        &self.flags
+    }
+
+    // The bus every successful mutation is published on
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    // Hash a message's payload, store the message and announce it to subscribers
+    async fn deliver(&self, message: crate::db::Message) -> Result<(), Status> {
+        let message = self
+            .db
+            .store_message(message.with_payload_hash())
+            .await
+            .map_err(Self::map_db_error)?;
+        self.trace_stored(&message);
+        self.events
+            .publish(DomainEvent::MessageStored(Arc::new(message)));
+        Ok(())
+    }
+
+    // Log a stored message to the verbose traces on for its group, sender or recipients
+    fn trace_stored(&self, message: &crate::db::Message) {
+        let mut clients = vec![message.sender_id];
+        clients.extend(message.recipients.iter().flatten());
+        self.traces
+            .trace(message.group_id.as_slice(), &clients, || {
+                let mut line = format!(
+                    "Stored {} from {}",
+                    trace::describe(message),
+                    message.sender_id
+                );
+                if let Some(recipients) = &message.recipients {
+                    line.push_str(&format!(" to {} recipients", recipients.len()));
+                }
+                line
+            });
     }
 
     // Helper method to convert DbError to gRPC Status
     fn map_db_error(err: DbError) -> Status {
-        match err {
-            DbError::NotFound => Status::not_found("Resource not found"),
-            DbError::ConnectionError(msg) => Status::unavailable(msg),
-            DbError::QueryError(msg) => Status::internal(format!("Database query error: {}", msg)),
-            DbError::SerializationError(msg) => {
-                Status::internal(format!("Serialization error: {}", msg))
+        ServiceError::from(err).into()
+    }
+
+    // Build a client record with a fresh BasicCredential and init key
+    fn new_client(
+        &self,
+        user_id: Uuid,
+        identity: &str,
+        device_name: String,
+        is_service: bool,
+    ) -> Result<crate::db::Client, Status> {
+        // Generate a BasicCredential using the identity
+        let credential_bytes =
+            mls_codec::basic_credential(identity.as_bytes()).map_err(ServiceError::from)?;
+
+        // Generate an initial HPKE init key for the client
+        let init_key_bytes = self
+            .crypto
+            .generate_init_key()
+            .map_err(ServiceError::from)?;
+
+        Ok(crate::db::Client {
+            id: self.ids.generate(),
+            user_id,
+            credential: credential_bytes,
+            scheme: CredentialScheme::Basic, // We're generating a BasicCredential
+            device_name,
+            last_seen: timestamps::now(),
+            created_at: timestamps::now(),
+            init_key: Some(init_key_bytes),
+            signature_key: None,
+            is_service,
+            attestation_verdict: AttestationVerdict::Unattested,
+            deleted_at: None,
+        })
+    }
+
+    // Helper method to convert a client row to its proto representation
+    fn client_to_proto(c: crate::db::Client) -> mls::Client {
+        mls::Client {
+            id: c.id.to_string(),
+            user_id: c.user_id.to_string(),
+            credential: c.credential,
+            scheme: c.scheme.to_string(),
+            device_name: c.device_name,
+            last_seen: timestamps::to_rfc3339(c.last_seen),
+            created_at: timestamps::to_rfc3339(c.created_at),
+            is_service: c.is_service,
+            credential_scheme: mls::CredentialScheme::from(c.scheme) as i32,
+            signature_key: c.signature_key.unwrap_or_default(),
+            attestation_verdict: mls::AttestationVerdict::from(c.attestation_verdict) as i32,
+        }
+    }
+
+    // Whether the client is a service account (bot). Unknown clients are treated as regular ones.
+    async fn is_service_client(&self, client_id: Uuid) -> Result<bool, Status> {
+        match self.db.get_client(client_id).await {
+            Ok(client) => Ok(client.is_service),
+            Err(DbError::NotFound) => Ok(false),
+            Err(e) => Err(Self::map_db_error(e)),
+        }
+    }
+
+    // Check a registering device's attestation with the verifier of its format. Formats
+    // without a verifier are rejected rather than recorded as unattested, so clients know the
+    // evidence they sent wasn't checked.
+    async fn verify_attestation(
+        &self,
+        evidence: AttestationEvidence,
+    ) -> Result<AttestationVerdict, Status> {
+        let Some(verifier) = self.attestation.get(evidence.format) else {
+            return Err(ServiceError::FailedPrecondition(format!(
+                "{} attestation is not supported by this server",
+                evidence.format.as_str()
+            ))
+            .into());
+        };
+
+        verifier.verify(&evidence).await.map_err(|e| {
+            warn!(
+                "Failed to verify {} attestation: {}",
+                evidence.format.as_str(),
+                e
+            );
+            ServiceError::Unavailable("Attestation could not be verified; try again later".into())
+                .into()
+        })
+    }
+
+    // Service accounts can't create groups or change membership or group state
+    async fn deny_service_client(&self, client_id: Uuid, action: &str) -> Result<(), Status> {
+        if self.is_service_client(client_id).await? {
+            return Err(ServiceError::PermissionDenied(format!(
+                "Service clients cannot {}",
+                action
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    // Refuse to write a tenant's data on an instance outside the tenant's residency region
+    fn check_residency(&self, user_id: Uuid) -> Result<(), Status> {
+        match self.settings.current().misplaced_region(user_id) {
+            Some(region) => Err(ServiceError::FailedPrecondition(format!(
+                "Data of user {} must be stored in region {}",
+                user_id, region
+            ))
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    // Refuse ciphersuites weaker than the tenant's floor, so a group can't be downgraded to a
+    // deprecated or weak suite
+    fn check_ciphersuite(&self, tenant_id: Option<Uuid>, ciphersuite: u16) -> Result<(), Status> {
+        let floor = self.settings.current().ciphersuite_floor(tenant_id);
+        match mls_codec::ciphersuite_security_bits(ciphersuite) {
+            Some(bits) if bits >= floor => Ok(()),
+            bits => Err(ServiceError::FailedPrecondition(format!(
+                "Ciphersuite {:#06x} ({}) is below the {}-bit ciphersuite floor",
+                ciphersuite,
+                bits.map_or("unknown strength".to_string(), |bits| format!(
+                    "{}-bit",
+                    bits
+                )),
+                floor
+            ))
+            .into()),
+        }
+    }
+
+    // Check that every welcome recipient is a registered client, and belongs to `user_id` if
+    // given, reporting each bad recipient by its index in the request
+    async fn check_welcome_recipients(
+        &self,
+        recipient_ids: &[String],
+        recipients: &[Uuid],
+        user_id: Option<Uuid>,
+    ) -> Result<(), Status> {
+        let clients: HashMap<Uuid, Uuid> = self
+            .db
+            .get_clients(recipients)
+            .await
+            .map_err(Self::map_db_error)?
+            .into_iter()
+            .map(|client| (client.id, client.user_id))
+            .collect();
+
+        let mut v = Validator::new();
+        for (i, recipient_id) in recipient_ids.iter().enumerate() {
+            let field = format!("recipient_ids[{}]", i);
+            match Uuid::parse_str(recipient_id).map(|id| clients.get(&id)) {
+                Ok(Some(owner)) if user_id.is_some_and(|user_id| *owner != user_id) => {
+                    v.violation(field, "belongs to a different user than recipient_user_id")
+                }
+                Ok(Some(_)) => {}
+                _ => v.violation(field, "is not a registered client"),
             }
         }
+        v.finish()
+    }
+
+    // Helper method to convert a group row to its proto representation
+    fn group_to_proto(g: crate::db::Group) -> mls::Group {
+        mls::Group {
+            id: g.id.to_string(),
+            creator_id: g.creator_id.to_string(),
+            epoch: g.epoch as u64, // Convert from i64 to u64 for the proto response
+            state: g.state.unwrap_or_default(),
+            created_at: timestamps::to_rfc3339(g.created_at),
+            updated_at: timestamps::to_rfc3339(g.updated_at),
+            is_active: g.is_active,
+            mls_group_id: g.mls_group_id.unwrap_or_default(),
+            handle: g.handle.unwrap_or_default(),
+        }
+    }
+
+    // Response to a CreateGroup retried with the handle of an existing group: the group, and
+    // the memberships the requested members have in it
+    async fn existing_group_response(
+        &self,
+        group_id: Uuid,
+        initial_members: &[(Uuid, MembershipRole)],
+    ) -> Result<Response<mls::CreateGroupResponse>, Status> {
+        let memberships: HashMap<Uuid, Uuid> = self
+            .db
+            .list_memberships_by_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?
+            .into_iter()
+            .filter(|m| m.removed_at.is_none())
+            .map(|m| (m.client_id, m.id))
+            .collect();
+
+        Ok(Response::new(mls::CreateGroupResponse {
+            group_id: group_id.to_string(),
+            membership_ids: initial_members
+                .iter()
+                .filter_map(|(client_id, _)| memberships.get(client_id))
+                .map(|id| id.to_string())
+                .collect(),
+            existing: true,
+        }))
     }
 
-    // Helper method to parse UUIDs from strings
-    fn parse_uuid(s: &str) -> Result<Uuid, Status> {
-        Uuid::parse_str(s).map_err(|_| Status::invalid_argument("Invalid UUID format"))
+    // Helper method to convert a membership row to its proto representation
+    fn membership_to_proto(m: crate::db::Membership) -> mls::Membership {
+        mls::Membership {
+            id: m.id.to_string(),
+            client_id: m.client_id.to_string(),
+            group_id: m.group_id.to_string(),
+            role: m.role.to_string(),
+            added_at: timestamps::to_rfc3339(m.added_at),
+            removed_at: timestamps::to_rfc3339_opt(m.removed_at),
+            last_acked_epoch: m.last_acked_epoch.unwrap_or_default() as u64,
+            last_acked_at: timestamps::to_rfc3339_opt(m.last_acked_at),
+            member_role: mls::MembershipRole::from(m.role) as i32,
+        }
     }
 
     // Validate an MLS key package using OpenMLS
     #[allow(dead_code)]
     fn validate_key_package(&self, key_package_bytes: &[u8]) -> Result<(), Status> {
-        // Skip validation if flag is set (for testing)
-        if self.skip_validation {
+        if !self.validation.checks_payloads() {
             return Ok(());
         }
 
-        use openmls::prelude::tls_codec::Deserialize;
-        use openmls::versions::ProtocolVersion;
-
         if key_package_bytes.is_empty() {
-            return Err(Status::invalid_argument("Empty key package"));
+            return Err(ServiceError::validation("Empty key package").into());
         }
 
-        // First deserialize the bytes to a KeyPackageIn
-        let key_package_in = match KeyPackageIn::tls_deserialize(&mut &key_package_bytes[..]) {
-            Ok(kp) => kp,
-            Err(e) => {
-                return Err(Status::invalid_argument(format!(
-                    "Invalid key package format: {}",
-                    e
-                )))
-            }
-        };
-
-        // Then validate the KeyPackageIn to get a validated KeyPackage
-        match key_package_in.validate(self.crypto.crypto(), ProtocolVersion::Mls10) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(Status::invalid_argument(format!(
-                "Key package validation failed: {}",
-                e
-            ))),
-        }
+        self.crypto
+            .validate_key_package(key_package_bytes)
+            .map_err(ServiceError::from)?;
+        Ok(())
     }
 
     // Validate MLS group state
     fn validate_group_state(&self, group_state_bytes: &[u8]) -> Result<(), Status> {
-        // Skip validation if flag is set (for testing)
-        if self.skip_validation {
+        if !self.validation.checks_payloads() {
             return Ok(());
         }
 
         if group_state_bytes.is_empty() {
-            return Err(Status::invalid_argument("Empty group state"));
+            return Err(ServiceError::validation("Empty group state").into());
         }
 
         // Group state validation would normally require more context
@@ -119,13 +519,12 @@ impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
 
     // Validate an MLS proposal
     fn validate_proposal(&self, proposal_bytes: &[u8]) -> Result<(), Status> {
-        // Skip validation if flag is set (for testing)
-        if self.skip_validation {
+        if !self.validation.checks_payloads() {
             return Ok(());
         }
 
         if proposal_bytes.is_empty() {
-            return Err(Status::invalid_argument("Empty proposal"));
+            return Err(ServiceError::validation("Empty proposal").into());
         }
 
         // Basic check for now - full validation would need MlsGroup context
@@ -135,13 +534,12 @@ impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
 
     // Validate an MLS commit
     fn validate_commit(&self, commit_bytes: &[u8]) -> Result<(), Status> {
-        // Skip validation if flag is set (for testing)
-        if self.skip_validation {
+        if !self.validation.checks_payloads() {
             return Ok(());
         }
 
         if commit_bytes.is_empty() {
-            return Err(Status::invalid_argument("Empty commit"));
+            return Err(ServiceError::validation("Empty commit").into());
         }
 
         // Basic check for now - full validation would need MlsGroup context
@@ -151,18 +549,100 @@ impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
 
     // Validate an MLS welcome message
     fn validate_welcome(&self, welcome_bytes: &[u8]) -> Result<(), Status> {
-        // Skip validation if flag is set (for testing)
-        if self.skip_validation {
+        if !self.validation.checks_payloads() {
             return Ok(());
         }
 
         if welcome_bytes.is_empty() {
-            return Err(Status::invalid_argument("Empty welcome message"));
+            return Err(ServiceError::validation("Empty welcome message").into());
         }
 
         // Basic check for now - full validation would need additional context
         Ok(())
     }
+
+    // Validate a published GroupInfo
+    fn validate_group_info(&self, group_info_bytes: &[u8]) -> Result<(), Status> {
+        if !self.validation.checks_payloads() {
+            return Ok(());
+        }
+
+        if group_info_bytes.is_empty() {
+            return Err(ServiceError::validation("Empty GroupInfo").into());
+        }
+
+        // Basic check for now - verifying the signature would need the signer's leaf node
+        Ok(())
+    }
+
+    // Check that an MLS message is framed for the group it is addressed to, so a commit for
+    // one group can't end up in another group's queue. Only groups registered with an MLS
+    // group id can be checked.
+    async fn check_group_framing(
+        &self,
+        group_id: Uuid,
+        field: &str,
+        message_bytes: &[u8],
+    ) -> Result<(), Status> {
+        if !self.validation.checks_payloads() {
+            return Ok(());
+        }
+
+        let mls_group_id = match self.db.get_group(group_id).await {
+            Ok(group) => group.mls_group_id,
+            Err(DbError::NotFound) => None,
+            Err(e) => return Err(Self::map_db_error(e)),
+        };
+        let Some(mls_group_id) = mls_group_id else {
+            return Ok(());
+        };
+
+        let framing = mls_codec::parse_framing(message_bytes).map_err(|_| {
+            ServiceError::validation(format!("{} is not an MLS public or private message", field))
+        })?;
+
+        if framing.group_id != mls_group_id {
+            warn!(
+                "Rejected {} ({}) addressed to group {} but framed for another MLS group",
+                field,
+                Redacted::of(message_bytes),
+                group_id
+            );
+            return Err(ServiceError::validation(format!(
+                "{} is framed for a different MLS group",
+                field
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    // Reject application messages from epochs too far behind the group, which members have
+    // already deleted the keys for. The error carries the group's current epoch.
+    async fn check_application_epoch(&self, group_id: Uuid, epoch: i64) -> Result<(), Status> {
+        let tolerance = self.settings.current().application_epoch_tolerance;
+        if tolerance < 0 {
+            return Ok(());
+        }
+
+        let current_epoch = match self.db.get_group(group_id).await {
+            Ok(group) => group.epoch,
+            Err(DbError::NotFound) => return Ok(()),
+            Err(e) => return Err(Self::map_db_error(e)),
+        };
+        let min_epoch = current_epoch.saturating_sub(tolerance);
+        if epoch >= min_epoch {
+            return Ok(());
+        }
+
+        Err(ServiceError::EpochTooOld {
+            epoch,
+            current_epoch,
+            min_epoch,
+        }
+        .into())
+    }
 }
 
 // Implement the gRPC service trait
@@ -175,65 +655,65 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         &self,
         request: Request<mls::RegisterClientRequest>,
     ) -> Result<Response<mls::RegisterClientResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
 
-        // Create a client record
-        let client_id = Uuid::new_v4();
-        let user_id = Self::parse_uuid(&req.user_id)?;
-
-        // Generate a BasicCredential using the identity
-        let identity = req.identity.as_bytes().to_vec();
-        let basic_credential = BasicCredential::new(identity);
-
-        // Convert to Credential (from trait implementation)
-        let credential: Credential = basic_credential.into();
-
-        // Serialize the credential for storage
-        let credential_bytes = credential
-            .tls_serialize_detached()
-            .map_err(|e| Status::internal(format!("Failed to serialize credential: {}", e)))?;
-
-        // Generate random bytes for key derivation
-        let random_bytes = self
-            .crypto
-            .rand()
-            .random_vec(32)
-            .map_err(|e| Status::internal(format!("Failed to generate random bytes: {}", e)))?;
-
-        // Generate an initial HPKE key pair for the client using derive_hpke_keypair
-        let key_pair = self
-            .crypto
-            .crypto()
-            .derive_hpke_keypair(
-                openmls::prelude::Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519
-                    .hpke_config(),
-                &random_bytes,
+        let mut v = Validator::new();
+        let user_id = v.uuid("user_id", &req.user_id);
+        v.string("identity", &req.identity, MAX_IDENTITY_LEN);
+        v.string("device_name", &req.device_name, MAX_DEVICE_NAME_LEN);
+        if !req.signature_key.is_empty() && req.signature_key.len() != ED25519_PUBLIC_KEY_LEN {
+            v.violation("signature_key", "must be an Ed25519 public key");
+        }
+        let attestation = req.attestation.as_ref().map(|attestation| {
+            v.bytes(
+                "attestation.token",
+                &attestation.token,
+                MAX_ATTESTATION_TOKEN_BYTES,
+            );
+            let format = v.enum_value("attestation.format", attestation.format);
+            (
+                format.and_then(attestation_format),
+                attestation.token.clone(),
             )
-            .map_err(|e| Status::internal(format!("Failed to derive HPKE key pair: {}", e)))?;
-
-        // Serialize the init_key for storage
-        let init_key_bytes = key_pair
-            .public
-            .tls_serialize_detached()
-            .map_err(|e| Status::internal(format!("Failed to serialize init key: {}", e)))?;
-
-        let client = crate::db::Client {
-            id: client_id,
-            user_id,
-            credential: credential_bytes,
-            scheme: "basic".to_string(), // Set to "basic" since we're generating a BasicCredential
-            device_name: req.device_name,
-            last_seen: chrono::Utc::now(),
-            created_at: chrono::Utc::now(),
-            init_key: Some(init_key_bytes),
+        });
+        v.finish()?;
+        self.authorize(caller, Owner::User(user_id)).await?;
+        self.check_residency(user_id)?;
+
+        let signature_key = Some(req.signature_key).filter(|key| !key.is_empty());
+        let attestation_verdict = match attestation {
+            Some((Some(format), token)) => {
+                self.verify_attestation(AttestationEvidence {
+                    format,
+                    token,
+                    user_id,
+                    identity: req.identity.clone(),
+                    device_name: req.device_name.clone(),
+                    signature_key: signature_key.clone(),
+                })
+                .await?
+            }
+            _ => AttestationVerdict::Unattested,
         };
 
+        let mut client = self.new_client(user_id, &req.identity, req.device_name, false)?;
+        client.signature_key = signature_key;
+        client.attestation_verdict = attestation_verdict;
+        let client_id = client.id;
+
         // Store in database
         self.db
             .register_client(client)
             .await
             .map_err(Self::map_db_error)?;
 
+        self.events.publish(DomainEvent::ClientRegistered {
+            client_id,
+            user_id,
+            is_service: false,
+        });
+
         Ok(Response::new(mls::RegisterClientResponse {
             client_id: client_id.to_string(),
         }))
@@ -243,8 +723,12 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         &self,
         request: Request<mls::GetClientRequest>,
     ) -> Result<Response<mls::GetClientResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let client_id = Self::parse_uuid(&req.client_id)?;
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let mask = v.read_mask("read_mask", &req.read_mask, field_mask::CLIENT_FIELDS);
+        v.finish()?;
 
         // Get client from database
         let client = self
@@ -252,21 +736,14 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
             .get_client(client_id)
             .await
             .map_err(Self::map_db_error)?;
+        self.authorize(caller, Owner::User(client.user_id)).await?;
 
         // Update last seen timestamp
         let _ = self.db.update_client_last_seen(client_id).await;
 
         // Convert to proto response
         let response = mls::GetClientResponse {
-            client: Some(mls::Client {
-                id: client.id.to_string(),
-                user_id: client.user_id.to_string(),
-                credential: client.credential,
-                scheme: client.scheme,
-                device_name: client.device_name,
-                last_seen: client.last_seen.to_rfc3339(),
-                created_at: client.created_at.to_rfc3339(),
-            }),
+            client: Some(mask.client(Self::client_to_proto(client))),
         };
 
         Ok(Response::new(response))
@@ -276,13 +753,18 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         &self,
         request: Request<mls::ListClientsRequest>,
     ) -> Result<Response<mls::ListClientsResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let user_id = Self::parse_uuid(&req.user_id)?;
+        let mut v = Validator::new();
+        let user_id = v.uuid("user_id", &req.user_id);
+        let mask = v.read_mask("read_mask", &req.read_mask, field_mask::CLIENT_FIELDS);
+        v.finish()?;
+        self.authorize(caller, Owner::User(user_id)).await?;
 
-        // Get clients for the user
+        // Get clients for the user, loading credentials and keys only if they were asked for
         let clients = self
             .db
-            .list_clients_by_user(user_id)
+            .list_clients_by_user(user_id, mask.client_payload())
             .await
             .map_err(Self::map_db_error)?;
 
@@ -290,107 +772,124 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         let response = mls::ListClientsResponse {
             clients: clients
                 .into_iter()
-                .map(|c| mls::Client {
-                    id: c.id.to_string(),
-                    user_id: c.user_id.to_string(),
-                    credential: c.credential,
-                    scheme: c.scheme,
-                    device_name: c.device_name,
-                    last_seen: c.last_seen.to_rfc3339(),
-                    created_at: c.created_at.to_rfc3339(),
-                })
+                .map(|c| mask.client(Self::client_to_proto(c)))
                 .collect(),
         };
 
         Ok(Response::new(response))
     }
 
-    // KeyPackage operations
-    async fn publish_key_package(
+    async fn store_client_backup(
         &self,
-        request: Request<mls::PublishKeyPackageRequest>,
-    ) -> Result<Response<mls::PublishKeyPackageResponse>, Status> {
-        let req = request.into_inner();
-        let client_id = Self::parse_uuid(&req.client_id)?;
-
-        // Get client data from database
+        request: Request<mls::StoreClientBackupRequest>,
+    ) -> Result<Response<mls::StoreClientBackupResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.bytes("backup", &req.backup, MAX_CLIENT_BACKUP_BYTES);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+        self.verify_request(
+            &metadata,
+            "StoreClientBackup",
+            &req,
+            Actor::Client(client_id),
+        )
+        .await?;
+
+        // Make sure the client exists
         let client = self
             .db
             .get_client(client_id)
             .await
             .map_err(Self::map_db_error)?;
+        self.check_residency(client.user_id)?;
 
-        // Deserialize the credential using TlsDeserialize trait
-        let mut credential_slice = client.credential.as_slice();
-        let credential = Credential::tls_deserialize(&mut credential_slice)
-            .map_err(|e| Status::internal(format!("Failed to deserialize credential: {}", e)))?;
-
-        // Generate random bytes for key derivation
-        let random_bytes = self
-            .crypto
-            .rand()
-            .random_vec(32)
-            .map_err(|e| Status::internal(format!("Failed to generate random bytes: {}", e)))?;
+        // The blob is encrypted by the client and stored as-is
+        let backup = self
+            .db
+            .store_client_backup(client_id, req.backup, req.expected_version as i64)
+            .await
+            .map_err(Self::map_db_error)?;
 
-        // Generate a fresh HPKE key pair for this key package using derive_hpke_keypair
-        let hpke_keypair = self
-            .crypto
-            .crypto()
-            .derive_hpke_keypair(
-                openmls::prelude::Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519
-                    .hpke_config(),
-                &random_bytes,
-            )
-            .map_err(|e| Status::internal(format!("Failed to derive HPKE key pair: {}", e)))?;
+        self.events.publish(DomainEvent::ClientBackupStored {
+            client_id,
+            version: backup.version,
+        });
 
-        // Get the public key to use as init key
-        let _init_key = hpke_keypair.public.clone();
+        Ok(Response::new(mls::StoreClientBackupResponse {
+            version: backup.version as u64,
+        }))
+    }
 
-        // Select ciphersuite (could be made configurable in the future)
-        let ciphersuite =
-            openmls::prelude::Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+    async fn get_client_backup(
+        &self,
+        request: Request<mls::GetClientBackupRequest>,
+    ) -> Result<Response<mls::GetClientBackupResponse>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
 
-        // Import necessary types for key package creation
-        use openmls::credentials::CredentialWithKey;
-        use openmls::key_packages::KeyPackage;
-        use openmls_basic_credential::SignatureKeyPair;
+        let backup = self
+            .db
+            .get_client_backup(client_id)
+            .await
+            .map_err(Self::map_db_error)?;
 
-        // To create a key package we need a signature key
-        let signature_key =
-            SignatureKeyPair::new(ciphersuite.signature_algorithm()).map_err(|e| {
-                Status::internal(format!("Failed to generate signature key pair: {}", e))
-            })?;
+        Ok(Response::new(mls::GetClientBackupResponse {
+            backup: backup.data,
+            version: backup.version as u64,
+            updated_at: timestamps::to_rfc3339(backup.updated_at),
+        }))
+    }
 
-        // Create the credential with key
-        let credential_with_key = CredentialWithKey {
-            credential,
-            signature_key: signature_key.public().into(),
-        };
+    // KeyPackage operations
+    async fn publish_key_package(
+        &self,
+        request: Request<mls::PublishKeyPackageRequest>,
+    ) -> Result<Response<mls::PublishKeyPackageResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+        self.verify_request(
+            &metadata,
+            "PublishKeyPackage",
+            &req,
+            Actor::Client(client_id),
+        )
+        .await?;
 
-        // Create a KeyPackage using the OpenMLS SDK
-        let key_package_bundle = KeyPackage::builder()
-            .build(
-                ciphersuite,
-                &self.crypto,
-                &signature_key,
-                credential_with_key,
-            )
-            .map_err(|e| Status::internal(format!("Failed to build key package: {}", e)))?;
+        // Get client data from database
+        let client = self
+            .db
+            .get_client(client_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.check_residency(client.user_id)?;
 
-        // Serialize the key package for storage
-        let key_package_bytes = key_package_bundle
-            .key_package()
-            .tls_serialize_detached()
-            .map_err(|e| Status::internal(format!("Failed to serialize key package: {}", e)))?;
+        // Build a key package for the client's credential with the OpenMLS SDK
+        let key_package_bytes = self
+            .crypto
+            .build_key_package(&client.credential)
+            .map_err(ServiceError::from)?;
 
         // Create key package record
-        let key_package_id = Uuid::new_v4();
+        let key_package_id = self.ids.generate();
         let key_package_record = crate::db::KeyPackage {
             id: key_package_id,
             client_id,
             data: key_package_bytes,
-            created_at: chrono::Utc::now(),
+            created_at: timestamps::now(),
             used: false,
+            used_at: None,
+            deleted_at: None,
             // In a production system, you would store the private key securely
             // This might require extending the KeyPackage struct to include a private_key field
         };
@@ -401,6 +900,11 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
             .await
             .map_err(Self::map_db_error)?;
 
+        self.events.publish(DomainEvent::KeyPackagePublished {
+            client_id,
+            key_package_id,
+        });
+
         Ok(Response::new(mls::PublishKeyPackageResponse {
             key_package_id: key_package_id.to_string(),
         }))
@@ -410,8 +914,12 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         &self,
         request: Request<mls::GetKeyPackageRequest>,
     ) -> Result<Response<mls::GetKeyPackageResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let key_package_id = Self::parse_uuid(&req.key_package_id)?;
+        let mut v = Validator::new();
+        let key_package_id = v.uuid("key_package_id", &req.key_package_id);
+        let mask = v.read_mask("read_mask", &req.read_mask, field_mask::KEY_PACKAGE_FIELDS);
+        v.finish()?;
 
         // Get key package from database
         let key_package = self
@@ -419,16 +927,13 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
             .get_key_package(key_package_id)
             .await
             .map_err(Self::map_db_error)?;
+        // Others get key packages by claiming them
+        self.authorize(caller, Owner::Client(key_package.client_id))
+            .await?;
 
         // Convert to proto response
         let response = mls::GetKeyPackageResponse {
-            key_package: Some(mls::KeyPackage {
-                id: key_package.id.to_string(),
-                client_id: key_package.client_id.to_string(),
-                data: key_package.data,
-                created_at: key_package.created_at.to_rfc3339(),
-                used: key_package.used,
-            }),
+            key_package: Some(mask.key_package(key_package_to_proto(key_package))),
         };
 
         Ok(Response::new(response))
@@ -438,13 +943,18 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         &self,
         request: Request<mls::ListKeyPackagesRequest>,
     ) -> Result<Response<mls::ListKeyPackagesResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let client_id = Self::parse_uuid(&req.client_id)?;
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let mask = v.read_mask("read_mask", &req.read_mask, field_mask::KEY_PACKAGE_FIELDS);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
 
-        // Get key packages for the client
+        // Get key packages for the client, loading their data only if it was asked for
         let key_packages = self
             .db
-            .list_key_packages_by_client(client_id)
+            .list_key_packages_by_client(client_id, mask.key_package_payload())
             .await
             .map_err(Self::map_db_error)?;
 
@@ -452,150 +962,748 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         let response = mls::ListKeyPackagesResponse {
             key_packages: key_packages
                 .into_iter()
-                .map(|kp| mls::KeyPackage {
-                    id: kp.id.to_string(),
-                    client_id: kp.client_id.to_string(),
-                    data: kp.data,
-                    created_at: kp.created_at.to_rfc3339(),
-                    used: kp.used,
-                })
+                .map(|kp| mask.key_package(key_package_to_proto(kp)))
                 .collect(),
         };
 
         Ok(Response::new(response))
     }
 
-    // Group operations
-    async fn create_group(
+    async fn claim_key_package(
         &self,
-        request: Request<mls::CreateGroupRequest>,
-    ) -> Result<Response<mls::CreateGroupResponse>, Status> {
-        let req = request.into_inner();
-        let creator_id = Self::parse_uuid(&req.creator_id)?;
+        request: Request<mls::ClaimKeyPackageRequest>,
+    ) -> Result<Response<mls::ClaimKeyPackageResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let group_id = v.uuid("group_id", &req.group_id);
+        let claimer_id = v.uuid("claimer_id", &req.claimer_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(claimer_id)).await?;
+        self.verify_request(
+            &metadata,
+            "ClaimKeyPackage",
+            &req,
+            Actor::Client(claimer_id),
+        )
+        .await?;
+
+        // Claims are only recorded for groups that exist
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
 
-        // Validate the initial state with OpenMLS
-        let group_state = req.initial_state.clone();
-        self.validate_group_state(&group_state)?;
+        // Policy may keep devices that didn't pass attestation out of new groups
+        if self
+            .settings
+            .current()
+            .key_package_claims_require_attestation
+        {
+            let client = self
+                .db
+                .get_client(client_id)
+                .await
+                .map_err(Self::map_db_error)?;
+            if client.attestation_verdict != AttestationVerdict::Passed {
+                return Err(ServiceError::FailedPrecondition(format!(
+                    "Key packages can only be claimed for attested clients; client {} is {}",
+                    client_id, client.attestation_verdict
+                ))
+                .into());
+            }
+        }
 
-        // Create group record
-        let group_id = Uuid::new_v4();
-        let group = crate::db::Group {
-            id: group_id,
-            creator_id,
-            epoch: 0, // Initial epoch is 0 (i64)
-            state: Some(group_state),
-            created_at: chrono::Utc::now(),
-            updated_at: chrono::Utc::now(),
-            is_active: true,
+        // The key package's ciphersuite becomes the suite the client joins with. One below the
+        // group tenant's floor is handed back unused, since groups under a laxer policy can still
+        // use it, and the client's next package is tried. Packages that don't decode are the
+        // client's to reject, as before.
+        let mut refused = Vec::new();
+        let mut below_floor = None;
+        let key_package = loop {
+            let key_package = match self.db.claim_key_package(client_id, &refused).await {
+                Ok(key_package) => key_package,
+                Err(DbError::NotFound) => {
+                    return Err(below_floor.unwrap_or_else(|| {
+                        ServiceError::NotFound {
+                            entity: "Unused key package",
+                        }
+                        .into()
+                    }))
+                }
+                Err(e) => return Err(Self::map_db_error(e)),
+            };
+            let Ok(info) = self.crypto.validate_key_package(&key_package.data) else {
+                break key_package;
+            };
+            match self.check_ciphersuite(group.tenant_id, info.ciphersuite) {
+                Ok(()) => break key_package,
+                Err(status) => {
+                    self.db
+                        .release_key_package(key_package.id)
+                        .await
+                        .map_err(Self::map_db_error)?;
+                    refused.push(key_package.id);
+                    below_floor = Some(status);
+                }
+            }
         };
 
-        // Store in database
+        // Keep an audit record that outlives the key package itself
+        let hash = mls_codec::sha256(&key_package.data);
         self.db
-            .create_group(group)
+            .record_key_package_claim(KeyPackageClaim {
+                key_package_id: key_package.id,
+                client_id,
+                hash,
+                published_at: key_package.created_at,
+                claimed_at: timestamps::now(),
+                group_id,
+                claimed_by: claimer_id,
+            })
             .await
             .map_err(Self::map_db_error)?;
 
-        // Add creator as a member
-        let membership = crate::db::Membership {
-            id: Uuid::new_v4(),
-            client_id: creator_id,
+        self.events.publish(DomainEvent::KeyPackageClaimed {
+            client_id,
+            key_package_id: key_package.id,
             group_id,
-            role: "admin".to_string(), // Creator is admin by default
-            added_at: chrono::Utc::now(),
-            removed_at: None,
-        };
-
-        self.db
-            .add_membership(membership)
-            .await
-            .map_err(Self::map_db_error)?;
+        });
 
-        Ok(Response::new(mls::CreateGroupResponse {
-            group_id: group_id.to_string(),
+        Ok(Response::new(mls::ClaimKeyPackageResponse {
+            key_package: Some(key_package_to_proto(key_package)),
         }))
     }
 
-    async fn get_group(
+    async fn get_key_package_count(
         &self,
-        request: Request<mls::GetGroupRequest>,
-    ) -> Result<Response<mls::GetGroupResponse>, Status> {
+        request: Request<mls::GetKeyPackageCountRequest>,
+    ) -> Result<Response<mls::GetKeyPackageCountResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let group_id = Self::parse_uuid(&req.group_id)?;
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
 
-        // Get group from database
-        let group = self
+        self.db
+            .get_client(client_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        let expired_before = timestamps::now() - chrono::Duration::days(KEY_PACKAGE_LIFETIME_DAYS);
+        let counts = self
             .db
-            .get_group(group_id)
+            .count_unused_key_packages(client_id, expired_before)
             .await
             .map_err(Self::map_db_error)?;
 
-        // Convert to proto response
-        let response = mls::GetGroupResponse {
-            group: Some(mls::Group {
-                id: group.id.to_string(),
-                creator_id: group.creator_id.to_string(),
-                epoch: group.epoch as u64, // Convert from i64 to u64 for the proto response
-                state: group.state.unwrap_or_default(),
-                created_at: group.created_at.to_rfc3339(),
-                updated_at: group.updated_at.to_rfc3339(),
-                is_active: group.is_active,
-            }),
-        };
-
-        Ok(Response::new(response))
+        Ok(Response::new(mls::GetKeyPackageCountResponse {
+            unused: counts.unused as u64,
+            used: counts.used as u64,
+            expired: counts.expired as u64,
+        }))
     }
 
-    async fn list_groups(
+    // Group operations
+    async fn create_group(
         &self,
-        request: Request<mls::ListGroupsRequest>,
+        request: Request<mls::CreateGroupRequest>,
+    ) -> Result<Response<mls::CreateGroupResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let creator_id = v.uuid("creator_id", &req.creator_id);
+        v.bytes("initial_state", &req.initial_state, MAX_GROUP_STATE_BYTES);
+        if req.mls_group_id.len() > MAX_MLS_GROUP_ID_LEN {
+            v.violation(
+                "mls_group_id",
+                format!("must be at most {} bytes", MAX_MLS_GROUP_ID_LEN),
+            );
+        }
+        if req.handle.len() > MAX_GROUP_HANDLE_LEN {
+            v.violation(
+                "handle",
+                format!("must be at most {} bytes", MAX_GROUP_HANDLE_LEN),
+            );
+        }
+        let initial_members = v.initial_members(&req.initial_members, creator_id);
+        let ciphersuite = u16::try_from(req.ciphersuite).unwrap_or_else(|_| {
+            v.violation("ciphersuite", "must be a 16-bit ciphersuite id");
+            0
+        });
+        v.finish()?;
+        self.authorize(caller, Owner::Client(creator_id)).await?;
+        self.verify_request(&metadata, "CreateGroup", &req, Actor::Client(creator_id))
+            .await?;
+
+        // Memberships must belong to registered clients, starting with the creator's
+        let client_ids: Vec<Uuid> = std::iter::once(creator_id)
+            .chain(initial_members.iter().map(|(client_id, _)| *client_id))
+            .collect();
+        let registered: HashMap<Uuid, Uuid> = self
+            .db
+            .get_clients(&client_ids)
+            .await
+            .map_err(Self::map_db_error)?
+            .into_iter()
+            .map(|client| (client.id, client.user_id))
+            .collect();
+        let Some(&creator_user_id) = registered.get(&creator_id) else {
+            return Err(ServiceError::NotFound {
+                entity: "Creator client",
+            }
+            .into());
+        };
+        self.check_residency(creator_user_id)?;
+        if ciphersuite != 0 {
+            self.check_ciphersuite(Some(creator_user_id), ciphersuite)?;
+        }
+
+        // A retry of a creation that went through returns the group it created
+        let handle = (!req.handle.is_empty()).then(|| req.handle.clone());
+        if let Some(handle) = &handle {
+            match self.db.get_group_by_handle(creator_user_id, handle).await {
+                Ok(group) => {
+                    return self
+                        .existing_group_response(group.id, &initial_members)
+                        .await
+                }
+                Err(DbError::NotFound) => {}
+                Err(e) => return Err(Self::map_db_error(e)),
+            }
+        }
+
+        let mut v = Validator::new();
+        for (i, (client_id, _)) in initial_members.iter().enumerate() {
+            if !registered.contains_key(client_id) {
+                v.violation(
+                    format!("initial_members[{}].client_id", i),
+                    "is not a registered client",
+                );
+            }
+        }
+        v.finish()?;
+
+        self.deny_service_client(creator_id, "create groups")
+            .await?;
+        for (client_id, role) in &initial_members {
+            if *role != MembershipRole::Member {
+                self.deny_service_client(*client_id, "be group admins or moderators")
+                    .await?;
+            }
+        }
+
+        // Validate the initial state with OpenMLS
+        let group_state = req.initial_state.clone();
+        self.validate_group_state(&group_state)?;
+
+        // Create group record
+        let group_id = self.ids.generate();
+        let group = crate::db::Group {
+            id: group_id,
+            creator_id,
+            epoch: 0, // Initial epoch is 0 (i64)
+            state: Some(group_state),
+            mls_group_id: (!req.mls_group_id.is_empty()).then(|| req.mls_group_id.clone()),
+            tenant_id: Some(creator_user_id),
+            handle: handle.clone(),
+            created_at: timestamps::now(),
+            updated_at: timestamps::now(),
+            is_active: true,
+            deleted_at: None,
+        };
+
+        // Add creator as an admin, then the initial members. Everyone starts at the initial epoch.
+        let memberships: Vec<_> = std::iter::once((creator_id, MembershipRole::Admin))
+            .chain(initial_members.iter().copied())
+            .map(|(client_id, role)| crate::db::Membership {
+                id: self.ids.generate(),
+                client_id,
+                group_id,
+                role,
+                added_at: timestamps::now(),
+                removed_at: None,
+                last_acked_epoch: Some(0),
+                last_acked_at: None,
+            })
+            .collect();
+        let added: Vec<_> = memberships.iter().map(|m| (m.id, m.client_id)).collect();
+
+        // Store them all at once, so a failure can't leave a group without its members. If a
+        // concurrent retry created the group with this handle first, return that one.
+        if let Err(e) = self.db.create_group_with_members(group, memberships).await {
+            if let (DbError::Conflict(_), Some(handle)) = (&e, &handle) {
+                if let Ok(existing) = self.db.get_group_by_handle(creator_user_id, handle).await {
+                    return self
+                        .existing_group_response(existing.id, &initial_members)
+                        .await;
+                }
+            }
+            return Err(Self::map_db_error(e));
+        }
+
+        self.memberships
+            .invalidate(MembershipChange::Group(group_id));
+        self.events.publish(DomainEvent::GroupCreated {
+            group_id,
+            creator_id,
+        });
+        for (membership_id, client_id) in &added {
+            self.events.publish(DomainEvent::MemberAdded {
+                membership_id: *membership_id,
+                group_id,
+                client_id: *client_id,
+            });
+        }
+
+        Ok(Response::new(mls::CreateGroupResponse {
+            group_id: group_id.to_string(),
+            membership_ids: added[1..].iter().map(|(id, _)| id.to_string()).collect(),
+            existing: false,
+        }))
+    }
+
+    async fn get_group(
+        &self,
+        request: Request<mls::GetGroupRequest>,
+    ) -> Result<Response<mls::GetGroupResponse>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        v.finish()?;
+        self.authorize_member(caller, group_id).await?;
+
+        // Get group from database
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        // Convert to proto response
+        let response = mls::GetGroupResponse {
+            group: Some(Self::group_to_proto(group)),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_group_by_mls_group_id(
+        &self,
+        request: Request<mls::GetGroupByMlsGroupIdRequest>,
+    ) -> Result<Response<mls::GetGroupByMlsGroupIdResponse>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        if req.mls_group_id.is_empty() {
+            v.violation("mls_group_id", "is required");
+        } else if req.mls_group_id.len() > MAX_MLS_GROUP_ID_LEN {
+            v.violation(
+                "mls_group_id",
+                format!("must be at most {} bytes", MAX_MLS_GROUP_ID_LEN),
+            );
+        }
+        v.finish()?;
+
+        let group = self
+            .db
+            .get_group_by_mls_group_id(&req.mls_group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.authorize_member(caller, group.id).await?;
+
+        Ok(Response::new(mls::GetGroupByMlsGroupIdResponse {
+            group: Some(Self::group_to_proto(group)),
+        }))
+    }
+
+    async fn list_groups(
+        &self,
+        request: Request<mls::ListGroupsRequest>,
     ) -> Result<Response<mls::ListGroupsResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let client_id = Self::parse_uuid(&req.client_id)?;
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
 
-        // Get groups for the client
+        // Get groups for the client, without their state unless asked for
         let groups = self
             .db
-            .list_groups_by_client(client_id)
+            .list_groups_by_client(client_id, req.include_state)
             .await
             .map_err(Self::map_db_error)?;
 
         // Convert to proto response
         let response = mls::ListGroupsResponse {
-            groups: groups
-                .into_iter()
-                .map(|g| mls::Group {
-                    id: g.id.to_string(),
-                    creator_id: g.creator_id.to_string(),
-                    epoch: g.epoch as u64, // Convert from i64 to u64 for the proto response
-                    state: g.state.unwrap_or_default(),
-                    created_at: g.created_at.to_rfc3339(),
-                    updated_at: g.updated_at.to_rfc3339(),
-                    is_active: g.is_active,
-                })
-                .collect(),
+            groups: groups.into_iter().map(Self::group_to_proto).collect(),
         };
 
         Ok(Response::new(response))
     }
 
+    async fn publish_group_info(
+        &self,
+        request: Request<mls::PublishGroupInfoRequest>,
+    ) -> Result<Response<mls::PublishGroupInfoResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.bytes("group_info", &req.group_info, MAX_MLS_MESSAGE_BYTES);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(
+            &metadata,
+            "PublishGroupInfo",
+            &req,
+            Actor::Client(sender_id),
+        )
+        .await?;
+
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        // Only a current member holds the group secrets a GroupInfo is signed with
+        let is_member = self.is_active_member(sender_id, group_id).await?;
+        if !is_member {
+            return Err(ServiceError::PermissionDenied(
+                "Only active members of the group can publish its GroupInfo".to_string(),
+            )
+            .into());
+        }
+
+        // External joiners can only commit on top of the current epoch
+        if req.epoch as i64 != group.epoch {
+            return Err(ServiceError::FailedPrecondition(format!(
+                "GroupInfo is for epoch {}, the group is at epoch {}",
+                req.epoch, group.epoch
+            ))
+            .into());
+        }
+
+        self.validate_group_info(&req.group_info)?;
+
+        let replaced = match self.db.get_group_info(group_id, Some(group.epoch)).await {
+            Ok(_) => true,
+            Err(DbError::NotFound) => false,
+            Err(e) => return Err(Self::map_db_error(e)),
+        };
+        self.db
+            .store_group_info(crate::db::GroupInfo {
+                group_id,
+                epoch: group.epoch,
+                data: req.group_info,
+                publisher_id: sender_id,
+                published_at: timestamps::now(),
+            })
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::PublishGroupInfoResponse { replaced }))
+    }
+
+    async fn get_group_info(
+        &self,
+        request: Request<mls::GetGroupInfoRequest>,
+    ) -> Result<Response<mls::GetGroupInfoResponse>, Status> {
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        v.finish()?;
+
+        // Joiners aren't members yet, so anyone may fetch a group's GroupInfo
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        let epoch = (req.epoch > 0).then_some(req.epoch as i64);
+        let group_info = self
+            .db
+            .get_group_info(group_id, epoch)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => ServiceError::NotFound {
+                    entity: "GroupInfo",
+                }
+                .into(),
+                e => Self::map_db_error(e),
+            })?;
+
+        Ok(Response::new(mls::GetGroupInfoResponse {
+            group_info: group_info.data,
+            epoch: group_info.epoch as u64,
+            publisher_id: group_info.publisher_id.to_string(),
+            published_at: timestamps::to_rfc3339(group_info.published_at),
+            current: group_info.epoch == group.epoch,
+        }))
+    }
+
+    async fn upload_ratchet_tree(
+        &self,
+        request: Request<mls::UploadRatchetTreeRequest>,
+    ) -> Result<Response<mls::UploadRatchetTreeResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.bytes("ratchet_tree", &req.ratchet_tree, MAX_GROUP_STATE_BYTES);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(
+            &metadata,
+            "UploadRatchetTree",
+            &req,
+            Actor::Client(sender_id),
+        )
+        .await?;
+
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        if !self.is_active_member(sender_id, group_id).await? {
+            return Err(ServiceError::PermissionDenied(
+                "Only active members of the group can upload its ratchet tree".to_string(),
+            )
+            .into());
+        }
+
+        // Members joining from a welcome need the tree of the epoch the welcome is for, which
+        // is the one the group just moved to
+        if req.epoch as i64 != group.epoch {
+            return Err(ServiceError::FailedPrecondition(format!(
+                "Ratchet tree is for epoch {}, the group is at epoch {}",
+                req.epoch, group.epoch
+            ))
+            .into());
+        }
+
+        let replaced = match self.db.get_ratchet_tree(group_id, Some(group.epoch)).await {
+            Ok(_) => true,
+            Err(DbError::NotFound) => false,
+            Err(e) => return Err(Self::map_db_error(e)),
+        };
+        self.db
+            .store_ratchet_tree(crate::db::RatchetTree {
+                group_id,
+                epoch: group.epoch,
+                data: req.ratchet_tree,
+                uploader_id: sender_id,
+                uploaded_at: timestamps::now(),
+            })
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::UploadRatchetTreeResponse { replaced }))
+    }
+
+    async fn upload_group_state(
+        &self,
+        request: Request<Streaming<mls::UploadGroupStateRequest>>,
+    ) -> Result<Response<mls::UploadGroupStateResponse>, Status> {
+        self.receive_group_state(request).await
+    }
+
+    async fn get_ratchet_tree(
+        &self,
+        request: Request<mls::GetRatchetTreeRequest>,
+    ) -> Result<Response<mls::GetRatchetTreeResponse>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+
+        // Joiners from a welcome are members already; external joiners get the tree from the
+        // GroupInfo's ratchet_tree extension instead
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        if !self.is_active_member(client_id, group_id).await? {
+            return Err(ServiceError::PermissionDenied(
+                "Only active members of the group can fetch its ratchet tree".to_string(),
+            )
+            .into());
+        }
+
+        let epoch = (req.epoch > 0).then_some(req.epoch as i64);
+        let tree = self
+            .db
+            .get_ratchet_tree(group_id, epoch)
+            .await
+            .map_err(|e| match e {
+                DbError::NotFound => ServiceError::NotFound {
+                    entity: "RatchetTree",
+                }
+                .into(),
+                e => Self::map_db_error(e),
+            })?;
+
+        Ok(Response::new(mls::GetRatchetTreeResponse {
+            ratchet_tree: tree.data,
+            epoch: tree.epoch as u64,
+            uploader_id: tree.uploader_id.to_string(),
+            uploaded_at: timestamps::to_rfc3339(tree.uploaded_at),
+            current: tree.epoch == group.epoch,
+        }))
+    }
+
+    async fn set_group_frozen(
+        &self,
+        request: Request<mls::SetGroupFrozenRequest>,
+    ) -> Result<Response<mls::SetGroupFrozenResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(&metadata, "SetGroupFrozen", &req, Actor::Client(sender_id))
+            .await?;
+
+        self.db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.require_permission(sender_id, group_id, Permission::FreezeGroup)
+            .await?;
+
+        let changed = self
+            .db
+            .set_group_active(group_id, !req.frozen)
+            .await
+            .map_err(Self::map_db_error)?;
+        if changed {
+            let notice = if req.frozen {
+                SystemNotice::GroupFrozen { group_id }
+            } else {
+                SystemNotice::GroupUnfrozen { group_id }
+            };
+            self.notify_group(group_id, None, notice).await?;
+        }
+
+        Ok(Response::new(mls::SetGroupFrozenResponse { changed }))
+    }
+
+    async fn set_group_key_rotation(
+        &self,
+        request: Request<mls::SetGroupKeyRotationRequest>,
+    ) -> Result<Response<mls::SetGroupKeyRotationResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        if req.use_server_default && req.max_epoch_age_secs != 0 {
+            v.violation(
+                "max_epoch_age_secs",
+                "must be 0 when use_server_default is set",
+            );
+        } else if req.max_epoch_age_secs > MAX_EPOCH_AGE_SECS {
+            v.violation(
+                "max_epoch_age_secs",
+                format!("must be at most {} seconds", MAX_EPOCH_AGE_SECS),
+            );
+        }
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(
+            &metadata,
+            "SetGroupKeyRotation",
+            &req,
+            Actor::Client(sender_id),
+        )
+        .await?;
+
+        self.db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.require_permission(sender_id, group_id, Permission::ManageKeyRotation)
+            .await?;
+
+        // Validated above to fit
+        let max_epoch_age_secs = (!req.use_server_default).then_some(req.max_epoch_age_secs as i64);
+        self.db
+            .set_group_max_epoch_age(group_id, max_epoch_age_secs)
+            .await
+            .map_err(Self::map_db_error)?;
+        let in_effect = max_epoch_age_secs
+            .unwrap_or_else(|| self.settings.current().key_rotation_max_epoch_age_secs)
+            .max(0);
+
+        Ok(Response::new(mls::SetGroupKeyRotationResponse {
+            max_epoch_age_secs: in_effect as u64,
+        }))
+    }
+
     // Membership operations
     async fn add_member(
         &self,
         request: Request<mls::AddMemberRequest>,
     ) -> Result<Response<mls::AddMemberResponse>, Status> {
-        let req = request.into_inner();
-        let group_id = Self::parse_uuid(&req.group_id)?;
-        let client_id = Self::parse_uuid(&req.client_id)?;
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let client_id = v.uuid("client_id", &req.client_id);
+        let role = v.enum_or_legacy("member_role", req.member_role, "role", &req.role);
+        let sender_id = acting_member(&mut v, &req.sender_id, caller, &metadata);
+        v.finish()?;
+        // Invalid values are recorded as violations, so `finish` has rejected the request
+        let role = role.unwrap_or(MembershipRole::Member);
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(&metadata, "AddMember", &req, Actor::Client(sender_id))
+            .await?;
+
+        // New members join at the group's current epoch
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        // Granting a role above member is reserved to those who manage roles
+        let permission = if role == MembershipRole::Member {
+            Permission::AddMembers
+        } else {
+            Permission::ManageRoles
+        };
+        self.require_permission(sender_id, group_id, permission)
+            .await?;
+
+        if role != MembershipRole::Member {
+            self.deny_service_client(client_id, "be group admins or moderators")
+                .await?;
+        }
 
         // Create membership record
-        let membership_id = Uuid::new_v4();
+        let membership_id = self.ids.generate();
         let membership = crate::db::Membership {
             id: membership_id,
             client_id,
             group_id,
-            role: req.role,
-            added_at: chrono::Utc::now(),
+            role,
+            added_at: timestamps::now(),
             removed_at: None,
+            last_acked_epoch: Some(group.epoch),
+            last_acked_at: None,
         };
 
         // Store in database
@@ -604,6 +1712,20 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
             .await
             .map_err(Self::map_db_error)?;
 
+        self.traces.trace(&[group_id], &[client_id, sender_id], || {
+            format!(
+                "Added {} as {} by {} at epoch {}",
+                client_id, role, sender_id, group.epoch
+            )
+        });
+        self.memberships
+            .invalidate(MembershipChange::Group(group_id));
+        self.events.publish(DomainEvent::MemberAdded {
+            membership_id,
+            group_id,
+            client_id,
+        });
+
         Ok(Response::new(mls::AddMemberResponse {
             membership_id: membership_id.to_string(),
         }))
@@ -613,14 +1735,50 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         &self,
         request: Request<mls::RemoveMemberRequest>,
     ) -> Result<Response<mls::RemoveMemberResponse>, Status> {
-        let req = request.into_inner();
-        let membership_id = Self::parse_uuid(&req.membership_id)?;
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let membership_id = v.uuid("membership_id", &req.membership_id);
+        let sender_id = acting_member(&mut v, &req.sender_id, caller, &metadata);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(&metadata, "RemoveMember", &req, Actor::Client(sender_id))
+            .await?;
+
+        let membership = self
+            .db
+            .get_membership(membership_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        // Members may always leave. Removing someone else takes a permission, and removing an
+        // admin or moderator the permission to manage roles.
+        if sender_id != membership.client_id {
+            let permission = if membership.role == MembershipRole::Member {
+                Permission::RemoveMembers
+            } else {
+                Permission::ManageRoles
+            };
+            self.require_permission(sender_id, membership.group_id, permission)
+                .await?;
+        }
 
         // Remove membership from database (soft delete)
         self.db
             .remove_membership(membership_id)
             .await
             .map_err(Self::map_db_error)?;
+        self.traces.trace(
+            &[membership.group_id],
+            &[membership.client_id, sender_id],
+            || format!("Removed {} by {}", membership.client_id, sender_id),
+        );
+        self.memberships
+            .invalidate(MembershipChange::Group(membership.group_id));
+        self.events.publish(DomainEvent::MemberRemoved {
+            membership_id,
+            group_id: membership.group_id,
+            client_id: membership.client_id,
+        });
 
         Ok(Response::new(mls::RemoveMemberResponse { success: true }))
     }
@@ -629,8 +1787,12 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         &self,
         request: Request<mls::ListMembershipsRequest>,
     ) -> Result<Response<mls::ListMembershipsResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let group_id = Self::parse_uuid(&req.group_id)?;
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        v.finish()?;
+        self.authorize_member(caller, group_id).await?;
 
         // Get memberships for the group
         let memberships = self
@@ -643,143 +1805,451 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         let response = mls::ListMembershipsResponse {
             memberships: memberships
                 .into_iter()
-                .map(|m| mls::Membership {
-                    id: m.id.to_string(),
-                    client_id: m.client_id.to_string(),
-                    group_id: m.group_id.to_string(),
-                    role: m.role,
-                    added_at: m.added_at.to_rfc3339(),
-                    removed_at: m.removed_at.map(|dt| dt.to_rfc3339()).unwrap_or_default(),
-                })
+                .map(Self::membership_to_proto)
                 .collect(),
         };
 
         Ok(Response::new(response))
     }
 
+    async fn get_membership(
+        &self,
+        request: Request<mls::GetMembershipRequest>,
+    ) -> Result<Response<mls::GetMembershipResponse>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let membership_id = v.uuid("membership_id", &req.membership_id);
+        v.finish()?;
+
+        // Removed memberships are returned too, with removed_at set
+        let membership = self
+            .db
+            .get_membership(membership_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        // A client can always see its own membership, even after being removed
+        if self
+            .authorize(caller, Owner::Client(membership.client_id))
+            .await
+            .is_err()
+        {
+            self.authorize_member(caller, membership.group_id).await?;
+        }
+
+        Ok(Response::new(mls::GetMembershipResponse {
+            membership: Some(Self::membership_to_proto(membership)),
+        }))
+    }
+
+    async fn list_membership_history(
+        &self,
+        request: Request<mls::ListMembershipHistoryRequest>,
+    ) -> Result<Response<mls::ListMembershipHistoryResponse>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let group_id = v.optional_uuid("group_id", &req.group_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+
+        let memberships = self
+            .db
+            .list_membership_history(client_id, group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::ListMembershipHistoryResponse {
+            memberships: memberships
+                .into_iter()
+                .map(Self::membership_to_proto)
+                .collect(),
+        }))
+    }
+
+    async fn acknowledge_epoch(
+        &self,
+        request: Request<mls::AcknowledgeEpochRequest>,
+    ) -> Result<Response<mls::AcknowledgeEpochResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+        self.verify_request(
+            &metadata,
+            "AcknowledgeEpoch",
+            &req,
+            Actor::Client(client_id),
+        )
+        .await?;
+
+        // A client cannot acknowledge an epoch the group hasn't reached yet
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        if req.epoch as i64 > group.epoch {
+            return Err(ServiceError::validation(format!(
+                "Epoch {} is ahead of the group's current epoch {}",
+                req.epoch, group.epoch
+            ))
+            .into());
+        }
+
+        // Record the acknowledgement on the active membership
+        self.db
+            .acknowledge_epoch(group_id, client_id, req.epoch as i64)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.traces.trace(&[group_id], &[client_id], || {
+            format!(
+                "{} acknowledged epoch {} (group at epoch {})",
+                client_id, req.epoch, group.epoch
+            )
+        });
+
+        self.events.publish(DomainEvent::EpochAcknowledged {
+            group_id,
+            client_id,
+            epoch: req.epoch as i64,
+        });
+
+        Ok(Response::new(mls::AcknowledgeEpochResponse {
+            success: true,
+        }))
+    }
+
+    async fn broadcast_system_message(
+        &self,
+        request: Request<mls::BroadcastSystemMessageRequest>,
+    ) -> Result<Response<mls::BroadcastSystemMessageResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.string("text", &req.text, MAX_ANNOUNCEMENT_LEN);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(
+            &metadata,
+            "BroadcastSystemMessage",
+            &req,
+            Actor::Client(sender_id),
+        )
+        .await?;
+
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        // Announcements go to every active member; only admins and permitted moderators may
+        // send them
+        self.require_permission(sender_id, group_id, Permission::Broadcast)
+            .await?;
+        let members: Vec<_> = self
+            .db
+            .list_memberships_by_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?
+            .into_iter()
+            .filter(|m| m.removed_at.is_none())
+            .collect();
+
+        let message = notices::system_message(
+            self.ids.as_ref(),
+            Some(group_id),
+            Some(group.epoch),
+            members.iter().map(|m| m.client_id).collect(),
+            SystemNotice::Announcement {
+                sender_id,
+                text: req.text,
+            },
+        );
+        let message_id = message.id;
+
+        self.deliver(message).await?;
+
+        Ok(Response::new(mls::BroadcastSystemMessageResponse {
+            message_id: message_id.to_string(),
+        }))
+    }
+
     // MLS Message operations
     async fn store_proposal(
         &self,
         request: Request<mls::StoreProposalRequest>,
     ) -> Result<Response<mls::StoreProposalResponse>, Status> {
-        let req = request.into_inner();
-        let group_id = Self::parse_uuid(&req.group_id)?;
-        let sender_id = Self::parse_uuid(&req.sender_id)?;
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.bytes("proposal", &req.proposal, MAX_MLS_MESSAGE_BYTES);
+        let extra = v.json_object("extra", &req.extra, MAX_MESSAGE_EXTRA_BYTES);
+        let proposal_type =
+            v.enum_or_legacy("type", req.r#type, "proposal_type", &req.proposal_type);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(&metadata, "StoreProposal", &req, Actor::Client(sender_id))
+            .await?;
+        // Invalid values are recorded as violations, so `finish` has rejected the request
+        let proposal_type = proposal_type.unwrap_or(ProposalType::Add);
+
+        self.deny_service_client(sender_id, "store proposals")
+            .await?;
+        self.authorize_sender(sender_id, group_id).await?;
 
         // Validate the proposal
         self.validate_proposal(&req.proposal)?;
+        self.check_group_framing(group_id, "proposal", &req.proposal)
+            .await?;
 
         // Create message record
-        let message_id = Uuid::new_v4();
+        let message_id = self.ids.generate();
         let message = crate::db::Message {
             id: message_id,
-            group_id,
+            group_id: Some(group_id),
             sender_id,
-            created_at: chrono::Utc::now(),
+            created_at: timestamps::now(),
             read: false,
-            message_type: "proposal".to_string(),
+            message_type: MessageType::Proposal,
             proposal: Some(req.proposal),
             commit: None,
             welcome: None,
-            proposal_type: Some(req.proposal_type),
+            system: None,
+            application: None,
+            proposal_type: Some(proposal_type),
             epoch: None,
             recipients: None,
+            extra,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
         };
 
-        // Store in database
-        self.db
-            .store_message(message)
+        // Store the proposal and queue it under the group's current epoch, so a commit in that
+        // epoch can include it by reference
+        let message = self
+            .db
+            .store_proposal(message)
             .await
             .map_err(Self::map_db_error)?;
-
-        Ok(Response::new(mls::StoreProposalResponse {
+        self.trace_stored(&message);
+        let response = mls::StoreProposalResponse {
             message_id: message_id.to_string(),
-        }))
+            proposal_ref: message.payload_hash.clone().unwrap_or_default(),
+            epoch: message.epoch.unwrap_or_default() as u64,
+        };
+        self.events
+            .publish(DomainEvent::MessageStored(Arc::new(message)));
+
+        Ok(Response::new(response))
     }
 
     async fn store_commit(
         &self,
         request: Request<mls::StoreCommitRequest>,
     ) -> Result<Response<mls::StoreCommitResponse>, Status> {
-        let req = request.into_inner();
-        let group_id = Self::parse_uuid(&req.group_id)?;
-        let sender_id = Self::parse_uuid(&req.sender_id)?;
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.bytes("commit", &req.commit, MAX_MLS_MESSAGE_BYTES);
+        let extra = v.json_object("extra", &req.extra, MAX_MESSAGE_EXTRA_BYTES);
+        let proposal_refs = v.proposal_refs("proposal_refs", &req.proposal_refs);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(&metadata, "StoreCommit", &req, Actor::Client(sender_id))
+            .await?;
+
+        self.deny_service_client(sender_id, "store commits").await?;
+        self.authorize_sender(sender_id, group_id).await?;
 
         // Validate the commit
         self.validate_commit(&req.commit)?;
+        self.check_group_framing(group_id, "commit", &req.commit)
+            .await?;
 
         // Create message record
-        let message_id = Uuid::new_v4();
+        let message_id = self.ids.generate();
         let message = crate::db::Message {
             id: message_id,
-            group_id,
+            group_id: Some(group_id),
             sender_id,
-            created_at: chrono::Utc::now(),
+            created_at: timestamps::now(),
             read: false,
-            message_type: "commit".to_string(),
+            message_type: MessageType::Commit,
             proposal: None,
             commit: Some(req.commit),
             welcome: None,
+            system: None,
+            application: None,
             proposal_type: None,
             epoch: Some(req.epoch as i64), // Convert from u64 to i64
             recipients: None,
+            extra,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
         };
 
-        // Store in database
-        self.db
-            .store_message(message)
+        // Store the commit and advance the group's epoch together. A commit that doesn't build
+        // on the current epoch lost a race with another member's and is rejected with the
+        // current epoch, so its sender can rebase. The proposals it includes by reference must
+        // be pending, and are marked committed.
+        let message = match self
+            .db
+            .store_commit(
+                message.with_payload_hash(),
+                req.epoch as i64, // Convert from u64 to i64
+                &proposal_refs,
+            )
             .await
-            .map_err(Self::map_db_error)?;
+        {
+            Ok(message) => message,
+            Err(e) => {
+                self.traces.trace(&[group_id], &[sender_id], || {
+                    format!(
+                        "Rejected commit from {} at epoch {} with {} proposal refs: {}",
+                        sender_id,
+                        req.epoch,
+                        proposal_refs.len(),
+                        e
+                    )
+                });
+                return Err(Self::map_db_error(e));
+            }
+        };
+        self.trace_stored(&message);
+        self.events
+            .publish(DomainEvent::MessageStored(Arc::new(message)));
 
-        // Update group epoch
-        self.db
-            .update_group_epoch(group_id, req.epoch as i64) // Convert from u64 to i64
-            .await
-            .map_err(Self::map_db_error)?;
+        self.events.publish(DomainEvent::GroupEpochAdvanced {
+            group_id,
+            epoch: req.epoch as i64,
+        });
 
         Ok(Response::new(mls::StoreCommitResponse {
             message_id: message_id.to_string(),
         }))
     }
 
+    async fn list_pending_proposals(
+        &self,
+        request: Request<mls::ListPendingProposalsRequest>,
+    ) -> Result<Response<mls::ListPendingProposalsResponse>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        v.finish()?;
+        self.authorize_member(caller, group_id).await?;
+
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        let proposals = self
+            .db
+            .list_pending_proposals(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::ListPendingProposalsResponse {
+            epoch: group.epoch as u64,
+            proposals: proposals
+                .into_iter()
+                .map(|p| mls::PendingProposal {
+                    proposal_ref: p.proposal_ref,
+                    message_id: p.message_id.to_string(),
+                    sender_id: p.sender_id.to_string(),
+                    r#type: p
+                        .proposal_type
+                        .map_or(mls::ProposalType::Unspecified, mls::ProposalType::from)
+                        as i32,
+                    created_at: timestamps::to_rfc3339(p.created_at),
+                })
+                .collect(),
+        }))
+    }
+
     async fn store_welcome(
         &self,
         request: Request<mls::StoreWelcomeRequest>,
     ) -> Result<Response<mls::StoreWelcomeResponse>, Status> {
-        let req = request.into_inner();
-        let group_id = Self::parse_uuid(&req.group_id)?;
-        let sender_id = Self::parse_uuid(&req.sender_id)?;
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.bytes("welcome", &req.welcome, MAX_MLS_MESSAGE_BYTES);
+        let extra = v.json_object("extra", &req.extra, MAX_MESSAGE_EXTRA_BYTES);
+        let recipients =
+            v.capped_uuids("recipient_ids", &req.recipient_ids, MAX_WELCOME_RECIPIENTS);
+        if req.recipient_ids.is_empty() {
+            v.violation("recipient_ids", "must list at least one recipient");
+        }
+        let recipient_user_id = v.optional_uuid("recipient_user_id", &req.recipient_user_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(&metadata, "StoreWelcome", &req, Actor::Client(sender_id))
+            .await?;
+
+        self.deny_service_client(sender_id, "store welcomes")
+            .await?;
+        self.authorize_sender(sender_id, group_id).await?;
+
+        // A welcome for a mistyped recipient would strand the new member
+        self.check_welcome_recipients(&req.recipient_ids, &recipients, recipient_user_id)
+            .await?;
 
         // Validate the welcome
         self.validate_welcome(&req.welcome)?;
 
-        // Convert recipient IDs to UUIDs
-        let recipients = req
-            .recipient_ids
-            .iter()
-            .map(|id| Self::parse_uuid(id))
-            .collect::<Result<Vec<Uuid>, Status>>()?;
-
         // Create message record
-        let message_id = Uuid::new_v4();
+        let message_id = self.ids.generate();
         let message = crate::db::Message {
             id: message_id,
-            group_id,
+            group_id: Some(group_id),
             sender_id,
-            created_at: chrono::Utc::now(),
+            created_at: timestamps::now(),
             read: false,
-            message_type: "welcome".to_string(),
+            message_type: MessageType::Welcome,
             proposal: None,
             commit: None,
             welcome: Some(req.welcome),
+            system: None,
+            application: None,
             proposal_type: None,
             epoch: None,
-            recipients: Some(recipients),
+            recipients: Some(recipients.clone()),
+            extra,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
         };
 
         // Store in database
+        self.deliver(message).await?;
+
+        // A fresh welcome fulfils any resend the recipients asked for, and restarts their
+        // delivery deadline
         self.db
-            .store_message(message)
+            .clear_welcome_requests(group_id, &recipients)
             .await
             .map_err(Self::map_db_error)?;
 
@@ -788,22 +2258,276 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         }))
     }
 
+    async fn request_welcome_resend(
+        &self,
+        request: Request<mls::RequestWelcomeResendRequest>,
+    ) -> Result<Response<mls::RequestWelcomeResendResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let client_id = v.uuid("client_id", &req.client_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+        self.verify_request(
+            &metadata,
+            "RequestWelcomeResend",
+            &req,
+            Actor::Client(client_id),
+        )
+        .await?;
+
+        let members: Vec<_> = self
+            .db
+            .list_memberships_by_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?
+            .into_iter()
+            .filter(|m| m.removed_at.is_none())
+            .collect();
+
+        // Only a client the group still counts as a member can be re-welcomed
+        let membership = members
+            .iter()
+            .find(|m| m.client_id == client_id)
+            .ok_or_else(|| {
+                ServiceError::FailedPrecondition(
+                    "Client is not an active member of the group".to_string(),
+                )
+            })?;
+
+        // Notify admins only once per pending request
+        if !self
+            .db
+            .mark_welcome_requested(membership.id)
+            .await
+            .map_err(Self::map_db_error)?
+        {
+            return Ok(Response::new(mls::RequestWelcomeResendResponse {
+                already_pending: true,
+            }));
+        }
+
+        self.events.publish(DomainEvent::WelcomeResendRequested {
+            membership_id: membership.id,
+            group_id,
+            client_id,
+        });
+
+        // Admins issue the new Add/Welcome; without any, any other member can
+        let others = members.iter().filter(|m| m.client_id != client_id);
+        let mut recipients: Vec<_> = others
+            .clone()
+            .filter(|m| m.role == MembershipRole::Admin)
+            .map(|m| m.client_id)
+            .collect();
+        if recipients.is_empty() {
+            recipients = others.map(|m| m.client_id).collect();
+        }
+
+        if !recipients.is_empty() {
+            let message = notices::system_message(
+                self.ids.as_ref(),
+                Some(group_id),
+                None,
+                recipients,
+                SystemNotice::WelcomeResendRequested {
+                    membership_id: membership.id,
+                    client_id,
+                },
+            );
+            self.deliver(message).await?;
+        }
+
+        Ok(Response::new(mls::RequestWelcomeResendResponse {
+            already_pending: false,
+        }))
+    }
+
+    async fn store_application_message(
+        &self,
+        request: Request<mls::StoreApplicationMessageRequest>,
+    ) -> Result<Response<mls::StoreApplicationMessageResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let group_id = v.uuid("group_id", &req.group_id);
+        let sender_id = v.uuid("sender_id", &req.sender_id);
+        v.bytes("message", &req.message, MAX_MLS_MESSAGE_BYTES);
+        let extra = v.json_object("extra", &req.extra, MAX_MESSAGE_EXTRA_BYTES);
+        let max_ttl_secs = self.settings.current().max_message_ttl_secs;
+        if req.ttl_secs > 0 && max_ttl_secs <= 0 {
+            v.violation("ttl_secs", "must be 0; message expiry is disabled");
+        } else if i64::from(req.ttl_secs) > max_ttl_secs {
+            v.violation(
+                "ttl_secs",
+                format!("must be at most {} seconds", max_ttl_secs),
+            );
+        }
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        self.verify_request(
+            &metadata,
+            "StoreApplicationMessage",
+            &req,
+            Actor::Client(sender_id),
+        )
+        .await?;
+
+        // Service clients too may only post to groups they've been added to
+        self.authorize_sender(sender_id, group_id).await?;
+
+        self.check_group_framing(group_id, "message", &req.message)
+            .await?;
+        self.check_application_epoch(group_id, req.epoch as i64)
+            .await?;
+
+        // Create message record, expiring after the sender's TTL if one was requested
+        let message_id = self.ids.generate();
+        let created_at = timestamps::now();
+        let message = crate::db::Message {
+            id: message_id,
+            group_id: Some(group_id),
+            sender_id,
+            created_at,
+            read: false,
+            message_type: MessageType::Application,
+            proposal: None,
+            commit: None,
+            welcome: None,
+            system: None,
+            application: Some(req.message),
+            proposal_type: None,
+            epoch: Some(req.epoch as i64), // Convert from u64 to i64
+            recipients: None,
+            extra,
+            payload_hash: None,
+            expires_at: (req.ttl_secs > 0)
+                .then(|| created_at + chrono::Duration::seconds(req.ttl_secs.into())),
+            deleted_at: None,
+            sequence: 0,
+        };
+
+        // Store in database
+        self.deliver(message).await?;
+
+        // Cap the members' queues of the group, evicting their oldest application messages. The
+        // message is already stored, so a failure here only leaves the queues over the cap until
+        // the next one.
+        let max_queued = self.settings.current().max_queued_application_messages;
+        if max_queued > 0 {
+            if let Err(e) = self
+                .db
+                .evict_application_messages(group_id, max_queued)
+                .await
+            {
+                warn!(
+                    "Failed to evict queued application messages of group {}: {}",
+                    group_id, e
+                );
+            }
+        }
+
+        Ok(Response::new(mls::StoreApplicationMessageResponse {
+            message_id: message_id.to_string(),
+        }))
+    }
+
     async fn fetch_messages(
         &self,
         request: Request<mls::FetchMessagesRequest>,
     ) -> Result<Response<mls::FetchMessagesResponse>, Status> {
+        let caller = Caller::of(&request);
         let req = request.into_inner();
-        let client_id = Self::parse_uuid(&req.client_id)?;
-        let group_id = if req.group_id.is_empty() {
-            None
-        } else {
-            Some(Self::parse_uuid(&req.group_id)?)
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let group_id = v.optional_uuid("group_id", &req.group_id);
+        let message_types: Vec<MessageType> =
+            v.enums_or_legacy("types", &req.types, "message_types", &req.message_types);
+        let mask = v.read_mask("read_mask", &req.read_mask, field_mask::MESSAGE_FIELDS);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+
+        // Fetch messages for the client, filtered by type in the query and with their content
+        // only if it was asked for. A group's messages skip the membership join when the cache
+        // knows the client to be a member.
+        let known_member = match group_id {
+            Some(group_id) if self.memberships.is_enabled() => {
+                self.is_active_member(client_id, group_id).await?
+            }
+            _ => false,
+        };
+        let messages = match group_id {
+            Some(group_id) if known_member => {
+                self.db
+                    .fetch_messages_for_member(
+                        client_id,
+                        group_id,
+                        req.include_read,
+                        &message_types,
+                        mask.message_payload(),
+                    )
+                    .await
+            }
+            _ => {
+                self.db
+                    .fetch_messages_for_client(
+                        client_id,
+                        group_id,
+                        req.include_read,
+                        &message_types,
+                        mask.message_payload(),
+                    )
+                    .await
+            }
+        }
+        .map_err(Self::map_db_error)?;
+
+        // Keep a flooding sender from burying the other members' messages
+        let matched: Vec<_> = messages
+            .iter()
+            .map(|m| (m.id, delivery::cursor(m)))
+            .collect();
+        let messages = fairness::FetchFairness {
+            interleave_senders: req.interleave_senders,
+            max_application_per_sender: req.max_application_per_sender as usize,
+        }
+        .apply(messages);
+
+        self.traces
+            .trace(&fetched_groups(group_id, &messages), &[client_id], || {
+                format!(
+                    "Fetched {} messages for {} (include_read: {}): {}",
+                    messages.len(),
+                    client_id,
+                    req.include_read,
+                    trace::describe_all(&messages)
+                )
+            });
+
+        // Messages past the client's cursors are being delivered; with include_read the fetch
+        // can also return messages delivered before
+        if !req.include_read {
+            let now = timestamps::now();
+            for message in &messages {
+                self.delivery.record_delivery(message, now);
+            }
+        }
+
+        // Acknowledging a fetch filtered by type would skip the messages of the other types
+        let ack_token = match message_types.is_empty() {
+            true => {
+                let returned = messages.iter().map(|m| m.id).collect();
+                delivery::ack_token(&delivery::delivered_through(&matched, &returned))
+            }
+            false => String::new(),
         };
 
-        // Fetch messages for the client
-        let messages = self
+        // Tell the client about application messages evicted from its queues since it last
+        // fetched them
+        let truncations = self
             .db
-            .fetch_messages_for_client(client_id, group_id, req.include_read)
+            .take_queue_truncations(client_id, group_id)
             .await
             .map_err(Self::map_db_error)?;
 
@@ -811,33 +2535,410 @@ impl<DB: DatabaseInterface + Send + Sync + 'static>
         let response = mls::FetchMessagesResponse {
             messages: messages
                 .into_iter()
-                .map(|m| {
-                    let mut msg = mls::Message {
-                        id: m.id.to_string(),
-                        group_id: m.group_id.to_string(),
-                        sender_id: m.sender_id.to_string(),
-                        created_at: m.created_at.to_rfc3339(),
-                        read: m.read,
-                        message_type: m.message_type.clone(),
-                        content: None, // We'll set this based on the message type below
-                    };
-
-                    // Set the appropriate content field
-                    if let Some(proposal) = m.proposal {
-                        msg.content = Some(mls::message::Content::Proposal(proposal));
-                    } else if let Some(commit) = m.commit {
-                        msg.content = Some(mls::message::Content::Commit(commit));
-                    } else if let Some(welcome) = m.welcome {
-                        msg.content = Some(mls::message::Content::Welcome(welcome));
-                    }
-
-                    msg
+                .map(|m| mask.message(message_to_proto(m)))
+                .collect(),
+            truncations: truncations
+                .into_iter()
+                .map(|t| mls::QueueTruncation {
+                    group_id: t.group_id.to_string(),
+                    evicted_messages: t.evicted_messages as u64,
                 })
                 .collect(),
+            ack_token,
         };
 
         Ok(Response::new(response))
     }
+
+    async fn mark_messages_read(
+        &self,
+        request: Request<mls::MarkMessagesReadRequest>,
+    ) -> Result<Response<mls::MarkMessagesReadResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let message_ids = v.capped_uuids("message_ids", &req.message_ids, MAX_MARK_READ_MESSAGES);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+        self.verify_request(
+            &metadata,
+            "MarkMessagesRead",
+            &req,
+            Actor::Client(client_id),
+        )
+        .await?;
+
+        // A client can only mark the messages FetchMessages would return to it. Unknown ids are
+        // refused the same way, so the call doesn't reveal which messages exist.
+        let addressed: HashMap<Uuid, _> = self
+            .db
+            .fetch_addressed_messages(client_id, None, &message_ids)
+            .await
+            .map_err(Self::map_db_error)?
+            .iter()
+            .map(|m| (m.id, delivery::cursor(m)))
+            .collect();
+        if let Some(message_id) = message_ids.iter().find(|id| !addressed.contains_key(id)) {
+            return Err(ServiceError::PermissionDenied(format!(
+                "Client {} is not a recipient of message {}",
+                client_id, message_id
+            ))
+            .into());
+        }
+
+        // Read state is the client's own: marking a message read acknowledges it, and everything
+        // before it in its group, without flagging it for the other recipients
+        let mut cursors: HashMap<Option<Uuid>, i64> = HashMap::new();
+        for cursor in message_ids.iter().map(|id| addressed[id]) {
+            let sequence = cursors.entry(cursor.group_id).or_insert(cursor.sequence);
+            *sequence = (*sequence).max(cursor.sequence);
+        }
+        let cursors: Vec<DeliveryCursor> = cursors
+            .into_iter()
+            .map(|(group_id, sequence)| DeliveryCursor { group_id, sequence })
+            .collect();
+
+        self.db
+            .advance_delivery_cursors(client_id, &cursors)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::MarkMessagesReadResponse {
+            success: true,
+        }))
+    }
+
+    async fn ack_messages(
+        &self,
+        request: Request<mls::AckMessagesRequest>,
+    ) -> Result<Response<mls::AckMessagesResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let mut cursors = delivery::open_ack_token(&req.ack_token).unwrap_or_else(|e| {
+            v.violation("ack_token", e.to_string());
+            Vec::new()
+        });
+        if req.watermarks.len() > MAX_ACK_WATERMARKS {
+            v.violation(
+                "watermarks",
+                format!("must have at most {} entries", MAX_ACK_WATERMARKS),
+            );
+        } else {
+            for (i, watermark) in req.watermarks.iter().enumerate() {
+                let group_id =
+                    v.optional_uuid(&format!("watermarks[{}].group_id", i), &watermark.group_id);
+                if watermark.sequence < 1 {
+                    v.violation(format!("watermarks[{}].sequence", i), "must be positive");
+                }
+                cursors.push(DeliveryCursor {
+                    group_id,
+                    sequence: watermark.sequence,
+                });
+            }
+        }
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+        self.verify_request(&metadata, "AckMessages", &req, Actor::Client(client_id))
+            .await?;
+
+        self.db
+            .advance_delivery_cursors(client_id, &cursors)
+            .await
+            .map_err(Self::map_db_error)?;
+        let acked_groups: Vec<_> = cursors.iter().filter_map(|c| c.group_id).collect();
+        self.traces.trace(&acked_groups, &[client_id], || {
+            let through: Vec<_> = cursors
+                .iter()
+                .map(|c| match c.group_id {
+                    Some(group_id) => format!("group {} through #{}", group_id, c.sequence),
+                    None => format!("notices through #{}", c.sequence),
+                })
+                .collect();
+            format!("Acknowledged for {}: {}", client_id, through.join(", "))
+        });
+
+        Ok(Response::new(mls::AckMessagesResponse {}))
+    }
+
+    type SubscribeMessagesStream = subscribe::MessageStream;
+
+    async fn subscribe_messages(
+        &self,
+        request: Request<mls::SubscribeMessagesRequest>,
+    ) -> Result<Response<Self::SubscribeMessagesStream>, Status> {
+        let caller = Caller::of(&request);
+        let req = request.into_inner();
+        let mut v = Validator::new();
+        let client_id = v.uuid("client_id", &req.client_id);
+        let group_id = v.optional_uuid("group_id", &req.group_id);
+        let resume_after = subscribe::open_resume_token(&req.resume_token).unwrap_or_else(|e| {
+            v.violation("resume_token", e.to_string());
+            None
+        });
+        v.finish()?;
+        self.authorize(caller, Owner::Client(client_id)).await?;
+
+        // Taken before subscribing, so a message stored in between is replayed on resume
+        let start = match &resume_after {
+            Some(positions) => positions.clone(),
+            None => {
+                let groups: Vec<Option<Uuid>> = match group_id {
+                    Some(group_id) => vec![Some(group_id)],
+                    None => self
+                        .db
+                        .list_memberships_by_client(client_id)
+                        .await
+                        .map_err(Self::map_db_error)?
+                        .into_iter()
+                        .filter(|m| m.removed_at.is_none())
+                        .map(|m| Some(m.group_id))
+                        .chain([None])
+                        .collect(),
+                };
+                self.db
+                    .last_message_sequences(&groups)
+                    .await
+                    .map_err(Self::map_db_error)?
+            }
+        };
+
+        // Subscribe before reading memberships so no change in between is missed
+        let events = self.events.subscribe();
+        let groups = self
+            .db
+            .list_memberships_by_client(client_id)
+            .await
+            .map_err(Self::map_db_error)?
+            .into_iter()
+            .filter(|m| m.removed_at.is_none())
+            .map(|m| m.group_id)
+            .collect();
+
+        // A resumed subscription first streams what was stored since its last message
+        let replay = match &resume_after {
+            Some(after) => {
+                let replay = self
+                    .db
+                    .fetch_messages_after(
+                        client_id,
+                        group_id,
+                        after,
+                        subscribe::MAX_REPLAYED_MESSAGES as i64 + 1,
+                    )
+                    .await
+                    .map_err(Self::map_db_error)?;
+                if replay.len() > subscribe::MAX_REPLAYED_MESSAGES {
+                    return Err(ServiceError::FailedPrecondition(format!(
+                        "More than {} messages were stored since the resume token; fetch them with FetchMessages and subscribe again without a token",
+                        subscribe::MAX_REPLAYED_MESSAGES
+                    ))
+                    .into());
+                }
+                replay
+            }
+            None => Vec::new(),
+        };
+
+        let subscription = subscribe::MessageSubscription::new(
+            events,
+            client_id,
+            group_id,
+            groups,
+            start.clone(),
+            replay,
+            self.delivery.clone(),
+        );
+        let mut response = Response::new(subscription.into_stream());
+        response.metadata_mut().insert(
+            subscribe::RESUME_TOKEN_HEADER,
+            subscribe::resume_token(&start)
+                .parse()
+                .expect("base64url is valid metadata"),
+        );
+        Ok(response)
+    }
+
+    // Abuse reporting
+    async fn report_abuse(
+        &self,
+        request: Request<mls::ReportAbuseRequest>,
+    ) -> Result<Response<mls::ReportAbuseResponse>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let mut v = Validator::new();
+        let reporter_id = v.uuid("reporter_id", &req.reporter_id);
+        let group_id = v.uuid("group_id", &req.group_id);
+        let message_ids = v.capped_uuids("message_ids", &req.message_ids, MAX_REPORTED_MESSAGES);
+        if req.message_ids.is_empty() {
+            v.violation("message_ids", "is required");
+        }
+        v.string("reason", &req.reason, MAX_REPORT_REASON_LEN);
+        if req.excerpt.len() > MAX_REPORT_EXCERPT_BYTES {
+            v.violation(
+                "excerpt",
+                format!("must be at most {} bytes", MAX_REPORT_EXCERPT_BYTES),
+            );
+        }
+        v.finish()?;
+        self.authorize(caller, Owner::Client(reporter_id)).await?;
+        self.verify_request(&metadata, "ReportAbuse", &req, Actor::Client(reporter_id))
+            .await?;
+
+        let is_member = self.is_active_member(reporter_id, group_id).await?;
+        if !is_member {
+            return Err(ServiceError::PermissionDenied(
+                "Only active members of the group can report its messages".to_string(),
+            )
+            .into());
+        }
+
+        // A member can only report messages it received in the group. Unknown ids are refused
+        // the same way, so the call doesn't reveal which messages exist.
+        let received: HashSet<Uuid> = self
+            .db
+            .fetch_addressed_messages(reporter_id, Some(group_id), &message_ids)
+            .await
+            .map_err(Self::map_db_error)?
+            .into_iter()
+            .map(|m| m.id)
+            .collect();
+        if let Some(message_id) = message_ids.iter().find(|id| !received.contains(id)) {
+            return Err(ServiceError::PermissionDenied(format!(
+                "Client {} is not a recipient of message {} in the group",
+                reporter_id, message_id
+            ))
+            .into());
+        }
+
+        // The excerpt stays encrypted to the moderators; the service never sees its plaintext
+        let report = AbuseReport {
+            id: self.ids.generate(),
+            group_id,
+            reporter_id,
+            message_ids,
+            reason: req.reason,
+            excerpt: (!req.excerpt.is_empty()).then_some(req.excerpt),
+            created_at: timestamps::now(),
+            resolved_at: None,
+            resolution: None,
+        };
+        let report_id = report.id;
+        self.db
+            .store_abuse_report(report)
+            .await
+            .map_err(Self::map_db_error)?;
+        self.events.publish(DomainEvent::AbuseReported {
+            report_id,
+            group_id,
+            reporter_id,
+        });
+
+        Ok(Response::new(mls::ReportAbuseResponse {
+            report_id: report_id.to_string(),
+        }))
+    }
+
+    // API lifecycle
+    async fn list_deprecations(
+        &self,
+        _request: Request<mls::ListDeprecationsRequest>,
+    ) -> Result<Response<mls::ListDeprecationsResponse>, Status> {
+        Ok(Response::new(mls::ListDeprecationsResponse {
+            deprecations: deprecations::DEPRECATIONS
+                .iter()
+                .map(|d| d.to_proto())
+                .collect(),
+        }))
+    }
+
+    async fn get_server_info(
+        &self,
+        _request: Request<mls::GetServerInfoRequest>,
+    ) -> Result<Response<mls::GetServerInfoResponse>, Status> {
+        let info = server_info::ServerInfo {
+            auth: server_info::AuthModes {
+                request_signatures: self.settings.current().request_signatures,
+                ..self.auth_modes
+            },
+            ..server_info::ServerInfo::new(&self.crypto)
+        };
+        Ok(Response::new(info.to_proto()))
+    }
+}
+
+// Attestation format of a proto value, None for the unspecified one
+fn attestation_format(format: mls::AttestationFormat) -> Option<AttestationFormat> {
+    match format {
+        mls::AttestationFormat::Unspecified => None,
+        mls::AttestationFormat::PlayIntegrity => Some(AttestationFormat::PlayIntegrity),
+        mls::AttestationFormat::AppAttest => Some(AttestationFormat::AppAttest),
+        mls::AttestationFormat::Custom => Some(AttestationFormat::Custom),
+    }
+}
+
+// Convert a stored key package to its proto representation
+fn key_package_to_proto(kp: crate::db::KeyPackage) -> mls::KeyPackage {
+    mls::KeyPackage {
+        id: kp.id.to_string(),
+        client_id: kp.client_id.to_string(),
+        data: kp.data,
+        created_at: timestamps::to_rfc3339(kp.created_at),
+        used: kp.used,
+    }
+}
+
+// Groups a fetch touched, for matching verbose traces: the one asked for, or those of the
+// messages returned
+fn fetched_groups(group_id: Option<Uuid>, messages: &[crate::db::Message]) -> Vec<Uuid> {
+    match group_id {
+        Some(group_id) => vec![group_id],
+        None => messages
+            .iter()
+            .filter_map(|m| m.group_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect(),
+    }
+}
+
+// Convert a stored message to its proto representation
+fn message_to_proto(m: crate::db::Message) -> mls::Message {
+    let mut msg = mls::Message {
+        id: m.id.to_string(),
+        group_id: m.group_id.map(|id| id.to_string()).unwrap_or_default(),
+        sender_id: m.sender_id.to_string(),
+        created_at: timestamps::to_rfc3339(m.created_at),
+        read: m.read,
+        message_type: m.message_type.to_string(),
+        content: None, // We'll set this based on the message type below
+        r#type: mls::MessageType::from(m.message_type) as i32,
+        extra: m
+            .extra
+            .map(|extra| extra.to_string().into_bytes())
+            .unwrap_or_default(),
+        payload_hash: m.payload_hash.unwrap_or_default(),
+        expires_at: m.expires_at.map(timestamps::to_rfc3339).unwrap_or_default(),
+        sequence: m.sequence,
+        resume_token: String::new(),
+    };
+
+    // Set the appropriate content field
+    if let Some(proposal) = m.proposal {
+        msg.content = Some(mls::message::Content::Proposal(proposal));
+    } else if let Some(commit) = m.commit {
+        msg.content = Some(mls::message::Content::Commit(commit));
+    } else if let Some(welcome) = m.welcome {
+        msg.content = Some(mls::message::Content::Welcome(welcome));
+    } else if let Some(system) = m.system {
+        msg.content = Some(mls::message::Content::System(system));
+    } else if let Some(application) = m.application {
+        msg.content = Some(mls::message::Content::Application(application));
+    }
+
+    msg
 }
 
 // #[cfg(test)]
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use chrono::DateTime;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::db::PagePosition;
+
+// Version byte leading every page token, so the format can change without misreading old tokens
+const TOKEN_VERSION: u8 = 1;
+// Bytes of the filter hash a token carries
+const FILTER_HASH_LEN: usize = 16;
+// Version, seconds, nanoseconds, id and filter hash
+const PAYLOAD_LEN: usize = 1 + 8 + 4 + 16 + FILTER_HASH_LEN;
+const MAC_LEN: usize = 32;
+
+// Listings that page with these tokens, as advertised by GetServerInfo
+pub const PAGE_TOKEN_RPCS: &[&str] = &[
+    "mls.v1.MlsAdminService/ListAllClients",
+    "mls.v1.MlsAdminService/ListAllGroups",
+    "mls.v1.MlsAdminService/ListKeyPackageClaims",
+    "mls.v1.MlsAdminService/ListAbuseReports",
+];
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageTokenError {
+    #[error("is not a valid page token")]
+    Invalid,
+
+    #[error("was issued for a different filter")]
+    FilterChanged,
+}
+
+// Issues and checks the opaque page tokens of admin listings. A token carries the position the
+// previous page ended at and a hash of the filter it was issued for, and is HMAC-signed, so
+// clients can neither forge positions nor reuse a token with other filters.
+#[derive(Clone)]
+pub struct PageTokens {
+    key: Arc<[u8]>,
+}
+
+impl PageTokens {
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: Arc::from(key.as_ref()),
+        }
+    }
+
+    // Sign with a random key. Tokens then stop working when the process restarts, and are only
+    // accepted by the instance that issued them.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        getrandom::getrandom(&mut key).expect("the system random number generator is available");
+        Self::new(key)
+    }
+
+    // Token for the page after `position`, in a listing queried with the given filter.
+    // `listing` names the RPC, so a token can't be used with another listing.
+    pub fn issue(&self, listing: &str, filter: &str, position: PagePosition) -> String {
+        let mut token = Vec::with_capacity(PAYLOAD_LEN + MAC_LEN);
+        token.push(TOKEN_VERSION);
+        token.extend_from_slice(&position.at.timestamp().to_be_bytes());
+        token.extend_from_slice(&position.at.timestamp_subsec_nanos().to_be_bytes());
+        token.extend_from_slice(position.id.as_bytes());
+        token.extend_from_slice(&filter_hash(filter));
+        let mac = self.mac(listing, &token).finalize().into_bytes();
+        token.extend_from_slice(&mac);
+        BASE64.encode(token)
+    }
+
+    // Position a token issued for the listing resumes from, or None for an empty token
+    pub fn open(
+        &self,
+        listing: &str,
+        filter: &str,
+        token: &str,
+    ) -> Result<Option<PagePosition>, PageTokenError> {
+        if token.is_empty() {
+            return Ok(None);
+        }
+
+        let token = BASE64.decode(token).map_err(|_| PageTokenError::Invalid)?;
+        if token.len() != PAYLOAD_LEN + MAC_LEN || token[0] != TOKEN_VERSION {
+            return Err(PageTokenError::Invalid);
+        }
+        let (payload, mac) = token.split_at(PAYLOAD_LEN);
+        self.mac(listing, payload)
+            .verify_slice(mac)
+            .map_err(|_| PageTokenError::Invalid)?;
+        if payload[PAYLOAD_LEN - FILTER_HASH_LEN..] != filter_hash(filter) {
+            return Err(PageTokenError::FilterChanged);
+        }
+
+        let secs = i64::from_be_bytes(payload[1..9].try_into().expect("slice is 8 bytes"));
+        let nanos = u32::from_be_bytes(payload[9..13].try_into().expect("slice is 4 bytes"));
+        let id = Uuid::from_slice(&payload[13..29]).map_err(|_| PageTokenError::Invalid)?;
+        let at = DateTime::from_timestamp(secs, nanos).ok_or(PageTokenError::Invalid)?;
+        Ok(Some(PagePosition { at, id }))
+    }
+
+    fn mac(&self, listing: &str, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(listing.as_bytes());
+        mac.update(&[0]);
+        mac.update(payload);
+        mac
+    }
+}
+
+impl Default for PageTokens {
+    fn default() -> Self {
+        Self::random()
+    }
+}
+
+// Truncated SHA-256 of the filter a listing was queried with
+fn filter_hash(filter: &str) -> [u8; FILTER_HASH_LEN] {
+    let digest = Sha256::digest(filter.as_bytes());
+    digest[..FILTER_HASH_LEN]
+        .try_into()
+        .expect("SHA-256 is longer than the filter hash")
+}
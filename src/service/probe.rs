@@ -0,0 +1,453 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use prost::Message;
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Status};
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use uuid::Uuid;
+
+use super::mls::{self, mls_delivery_service_server::MlsDeliveryService};
+use super::signatures::{
+    request_digest, NONCE_HEADER, SIGNATURE_HEADER, SIGNER_HEADER, TIMESTAMP_HEADER,
+};
+use super::MLSServiceImpl;
+use crate::db::{DatabaseInterface, DbError, EntityKind};
+use crate::error::ServiceError;
+use crate::metrics::{JobTracker, RpcMetrics};
+use crate::mls_codec::SigningKey;
+use crate::timestamps;
+
+// Identity of the ephemeral clients a self-test registers
+pub const SELF_TEST_IDENTITY: &str = "hermetic-mls-self-test";
+
+// Method name self-test latencies are recorded under in the RPC metrics
+pub const SELF_TEST_METRIC: &str = "SelfTest";
+
+// When the gRPC health service reports the delivery service ready
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessProbe {
+    // Once the server has started
+    #[default]
+    Shallow,
+    // While the periodic self-test passes
+    Deep,
+}
+
+impl FromStr for ReadinessProbe {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "shallow" => Ok(Self::Shallow),
+            "deep" => Ok(Self::Deep),
+            _ => Err(format!(
+                "unknown readiness probe {:?}, expected shallow or deep",
+                value
+            )),
+        }
+    }
+}
+
+// One RPC a self-test exercised
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub latency: Duration,
+}
+
+// Outcome of one self-test run
+#[derive(Debug, Clone)]
+pub struct SelfTestResult {
+    pub passed: bool,
+    pub failed_step: Option<&'static str>,
+    pub error: Option<String>,
+    // Duration of the whole run, clean-up included
+    pub latency: Duration,
+    pub steps: Vec<SelfTestStep>,
+    pub finished_at: DateTime<Utc>,
+}
+
+impl SelfTestResult {
+    pub fn to_proto(&self) -> mls::RunSelfTestResponse {
+        mls::RunSelfTestResponse {
+            passed: self.passed,
+            failed_step: self.failed_step.unwrap_or_default().to_string(),
+            error: self.error.clone().unwrap_or_default(),
+            latency_ms: self.latency.as_millis() as u64,
+            steps: self
+                .steps
+                .iter()
+                .map(|step| mls::SelfTestStep {
+                    name: step.name.to_string(),
+                    latency_ms: step.latency.as_millis() as u64,
+                })
+                .collect(),
+            finished_at: timestamps::to_rfc3339(self.finished_at),
+        }
+    }
+}
+
+// An ephemeral client the self-test acts as. Requests are always signed, so the self-test
+// passes whatever the request signature mode.
+struct ProbeClient {
+    id: Uuid,
+    key: SigningKey,
+}
+
+impl ProbeClient {
+    fn sign<M: Message>(&self, method: &str, message: M) -> Result<Request<M>, Status> {
+        let timestamp = timestamps::now().timestamp();
+        let nonce = Uuid::new_v4().simple().to_string();
+        let digest = request_digest(method, timestamp, &nonce, &message.encode_to_vec());
+        let signature = self.key.sign(&digest).map_err(ServiceError::from)?;
+
+        let mut request = Request::new(message);
+        let metadata = request.metadata_mut();
+        // UUIDs and integers are always valid ASCII metadata
+        metadata.insert(SIGNER_HEADER, self.id.to_string().parse().unwrap());
+        metadata.insert(TIMESTAMP_HEADER, MetadataValue::from(timestamp));
+        metadata.insert(NONCE_HEADER, nonce.parse().unwrap());
+        metadata.insert_bin(SIGNATURE_HEADER, MetadataValue::from_bytes(&signature));
+        Ok(request)
+    }
+}
+
+// Progress of a run: the steps taken so far and the rows to clean up afterwards
+#[derive(Default)]
+struct ProbeRun {
+    steps: Vec<SelfTestStep>,
+    clients: Vec<Uuid>,
+    group: Option<Uuid>,
+}
+
+// The step a run failed at
+struct ProbeFailure {
+    step: &'static str,
+    error: String,
+}
+
+impl ProbeRun {
+    // Time one step of the run
+    async fn step<T>(
+        &mut self,
+        name: &'static str,
+        call: impl Future<Output = Result<T, Status>>,
+    ) -> Result<T, ProbeFailure> {
+        let started = Instant::now();
+        let result = call.await;
+        self.steps.push(SelfTestStep {
+            name,
+            latency: started.elapsed(),
+        });
+        result.map_err(|status| ProbeFailure {
+            step: name,
+            error: format!("{:?}: {}", status.code(), status.message()),
+        })
+    }
+}
+
+impl<DB: DatabaseInterface + Send + Sync + 'static> MLSServiceImpl<DB> {
+    // Exercise the full delivery path with two ephemeral clients, then remove every row the
+    // run created. The result is also kept as the latest self-test result.
+    pub async fn run_self_test(&self) -> SelfTestResult {
+        let started = Instant::now();
+        let mut run = ProbeRun::default();
+        let outcome = self.probe(&mut run).await;
+        self.clean_up_probe(&run).await;
+
+        let result = SelfTestResult {
+            passed: outcome.is_ok(),
+            failed_step: outcome.as_ref().err().map(|failure| failure.step),
+            error: outcome.err().map(|failure| failure.error),
+            latency: started.elapsed(),
+            steps: run.steps,
+            finished_at: timestamps::now(),
+        };
+        *self.self_test.lock().unwrap() = Some(result.clone());
+        result
+    }
+
+    // Result of the most recent self-test, if one has run
+    pub fn latest_self_test(&self) -> Option<SelfTestResult> {
+        self.self_test.lock().unwrap().clone()
+    }
+
+    async fn probe(&self, run: &mut ProbeRun) -> Result<(), ProbeFailure> {
+        let sender = run
+            .step("RegisterClient", self.register_probe_client("sender"))
+            .await?;
+        run.clients.push(sender.id);
+        let recipient = run
+            .step("RegisterClient", self.register_probe_client("recipient"))
+            .await?;
+        run.clients.push(recipient.id);
+
+        run.step("PublishKeyPackage", async {
+            let request = recipient.sign(
+                "PublishKeyPackage",
+                mls::PublishKeyPackageRequest {
+                    client_id: recipient.id.to_string(),
+                },
+            )?;
+            self.publish_key_package(request).await
+        })
+        .await?;
+
+        let group_id = run
+            .step("CreateGroup", async {
+                let request = sender.sign(
+                    "CreateGroup",
+                    mls::CreateGroupRequest {
+                        creator_id: sender.id.to_string(),
+                        initial_state: SELF_TEST_IDENTITY.as_bytes().to_vec(),
+                        mls_group_id: Vec::new(),
+                        initial_members: Vec::new(),
+                        handle: String::new(),
+                        ciphersuite: 0,
+                    },
+                )?;
+                let response = self.create_group(request).await?.into_inner();
+                Uuid::parse_str(&response.group_id).map_err(|_| {
+                    ServiceError::Internal("CreateGroup returned an invalid group id".to_string())
+                        .into()
+                })
+            })
+            .await?;
+        run.group = Some(group_id);
+
+        run.step("ClaimKeyPackage", async {
+            let request = sender.sign(
+                "ClaimKeyPackage",
+                mls::ClaimKeyPackageRequest {
+                    client_id: recipient.id.to_string(),
+                    group_id: group_id.to_string(),
+                    claimer_id: sender.id.to_string(),
+                },
+            )?;
+            self.claim_key_package(request).await
+        })
+        .await?;
+
+        run.step("AddMember", async {
+            let request = sender.sign(
+                "AddMember",
+                mls::AddMemberRequest {
+                    group_id: group_id.to_string(),
+                    client_id: recipient.id.to_string(),
+                    role: String::new(),
+                    member_role: mls::MembershipRole::Member as i32,
+                    sender_id: sender.id.to_string(),
+                },
+            )?;
+            self.add_member(request).await
+        })
+        .await?;
+
+        let message_id = run
+            .step("StoreApplicationMessage", async {
+                let request = sender.sign(
+                    "StoreApplicationMessage",
+                    mls::StoreApplicationMessageRequest {
+                        group_id: group_id.to_string(),
+                        sender_id: sender.id.to_string(),
+                        message: SELF_TEST_IDENTITY.as_bytes().to_vec(),
+                        epoch: 0,
+                        extra: Vec::new(),
+                        ttl_secs: 0,
+                    },
+                )?;
+                Ok(self
+                    .store_application_message(request)
+                    .await?
+                    .into_inner()
+                    .message_id)
+            })
+            .await?;
+
+        run.step("FetchMessages", async {
+            let messages = self
+                .fetch_messages(Request::new(mls::FetchMessagesRequest {
+                    client_id: recipient.id.to_string(),
+                    group_id: group_id.to_string(),
+                    include_read: false,
+                    message_types: Vec::new(),
+                    types: vec![mls::MessageType::Application as i32],
+                    interleave_senders: false,
+                    max_application_per_sender: 0,
+                    read_mask: None,
+                }))
+                .await?
+                .into_inner()
+                .messages;
+            if !messages.iter().any(|m| m.id == message_id) {
+                return Err(ServiceError::NotFound {
+                    entity: "Delivered message",
+                }
+                .into());
+            }
+            Ok(())
+        })
+        .await?;
+
+        run.step("AcknowledgeEpoch", async {
+            let request = recipient.sign(
+                "AcknowledgeEpoch",
+                mls::AcknowledgeEpochRequest {
+                    group_id: group_id.to_string(),
+                    client_id: recipient.id.to_string(),
+                    epoch: 0,
+                },
+            )?;
+            self.acknowledge_epoch(request).await
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn register_probe_client(&self, device_name: &str) -> Result<ProbeClient, Status> {
+        let key = SigningKey::generate().map_err(ServiceError::from)?;
+        let response = self
+            .register_client(Request::new(mls::RegisterClientRequest {
+                user_id: Uuid::new_v4().to_string(),
+                identity: SELF_TEST_IDENTITY.to_string(),
+                device_name: device_name.to_string(),
+                signature_key: key.public().to_vec(),
+                attestation: None,
+            }))
+            .await?
+            .into_inner();
+        let id = Uuid::parse_str(&response.client_id).map_err(|_| {
+            ServiceError::Internal("RegisterClient returned an invalid client id".to_string())
+        })?;
+        Ok(ProbeClient { id, key })
+    }
+
+    // Hard-delete everything a run created. Goes straight to the database, so members and
+    // clients aren't sent the notices an admin deletion would send.
+    async fn clean_up_probe(&self, run: &ProbeRun) {
+        let mut messages = HashSet::new();
+        for client_id in &run.clients {
+            match self
+                .db
+                .fetch_messages_for_client(*client_id, None, true, &[], false)
+                .await
+            {
+                Ok(found) => messages.extend(found.into_iter().map(|m| m.id)),
+                Err(e) => warn!("Self-test could not list messages to clean up: {}", e),
+            }
+        }
+
+        // Purging a group or client also purges its memberships and key packages
+        let rows = messages
+            .into_iter()
+            .map(|id| (EntityKind::Message, id))
+            .chain(run.group.map(|id| (EntityKind::Group, id)))
+            .chain(run.clients.iter().map(|id| (EntityKind::Client, *id)));
+        for (kind, id) in rows {
+            let result = match self.db.soft_delete(kind, id).await {
+                Ok(()) => self.db.purge_entity(kind, id).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) | Err(DbError::NotFound) => {}
+                Err(e) => warn!("Self-test could not clean up {:?} {}: {}", kind, id, e),
+            }
+        }
+    }
+}
+
+// Periodically runs the self-test, recording its latency with the RPC metrics and, in deep
+// readiness mode, reporting the delivery service as not serving while it fails
+pub struct SelfTestJob<DB: DatabaseInterface> {
+    service: Arc<MLSServiceImpl<DB>>,
+    interval: Duration,
+    metrics: Option<RpcMetrics>,
+    jobs: Option<JobTracker>,
+    health: Option<HealthReporter>,
+}
+
+impl<DB: DatabaseInterface + Send + Sync + 'static> SelfTestJob<DB> {
+    pub fn new(service: Arc<MLSServiceImpl<DB>>, interval: Duration) -> Self {
+        Self {
+            service,
+            interval,
+            metrics: None,
+            jobs: None,
+            health: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: RpcMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Report each run to the job tracker, which also records its latency if it has metrics
+    pub fn with_jobs(mut self, jobs: JobTracker) -> Self {
+        jobs.register(SELF_TEST_METRIC, self.interval);
+        self.jobs = Some(jobs);
+        self
+    }
+
+    // Drive the health service's readiness from the self-test results
+    pub fn with_health_reporter(mut self, health: HealthReporter) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    // Run one self-test and report its result
+    pub async fn run_once(&self) -> SelfTestResult {
+        let result = self.service.run_self_test().await;
+        match (result.failed_step, &result.error) {
+            (Some(step), Some(error)) => warn!("Self-test failed at {}: {}", step, error),
+            _ => debug!("Self-test passed in {}ms", result.latency.as_millis()),
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record(SELF_TEST_METRIC, result.latency);
+        }
+        if let Some(jobs) = &self.jobs {
+            let started_at =
+                result.finished_at - chrono::Duration::from_std(result.latency).unwrap_or_default();
+            let outcome = match (result.failed_step, &result.error) {
+                (Some(step), Some(error)) => Err(format!("{}: {}", step, error)),
+                _ => Ok(result.steps.len() as u64),
+            };
+            jobs.record(SELF_TEST_METRIC, started_at, result.latency, outcome);
+        }
+        if let Some(health) = &self.health {
+            let status = if result.passed {
+                ServingStatus::Serving
+            } else {
+                ServingStatus::NotServing
+            };
+            health.set_service_status("", status).await;
+            health
+                .set_service_status(mls::mls_delivery_service_server::SERVICE_NAME, status)
+                .await;
+        }
+        result
+    }
+
+    // Spawn the self-test loop onto the tokio runtime
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                self.run_once().await;
+            }
+        })
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tonic::Status;
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use crate::auth::token::AuthenticatedUser;
+use crate::auth::AuthenticatedClient;
+use crate::error::ServiceError;
+use crate::metrics::layer::rpc_method;
+
+// Metadata telling a rate-limited caller how many seconds to wait before retrying
+pub const RETRY_AFTER_HEADER: &str = "retry-after";
+
+// Delivery RPCs expensive enough to be rate limited: fetches scan the caller's queues, and
+// every claim uses up a key package its owner has to replace
+const RATE_LIMITED_METHODS: &[&str] = &["FetchMessages", "ClaimKeyPackage"];
+
+// Buckets are pruned once there are this many
+const BUCKET_PRUNE_THRESHOLD: usize = 4096;
+
+// Token bucket limits on the expensive delivery RPCs. Each authenticated caller and each IP
+// address gets a bucket holding up to `burst` requests, refilled at `per_sec` requests a second.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    // 0 disables the per-client limit
+    pub client_per_sec: u32,
+    pub client_burst: u32,
+    // 0 disables the per-IP limit
+    pub ip_per_sec: u32,
+    pub ip_burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            client_per_sec: 0,
+            client_burst: 20,
+            ip_per_sec: 0,
+            ip_burst: 100,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.client_per_sec > 0 || self.ip_per_sec > 0
+    }
+
+    // Refill rate and capacity of the bucket for a key, or None if its limit is off. A bucket
+    // always holds at least one request.
+    fn limit(&self, key: &RateLimitKey) -> Option<(f64, f64)> {
+        let (per_sec, burst) = match key {
+            RateLimitKey::Client(_) => (self.client_per_sec, self.client_burst),
+            RateLimitKey::Ip(_) => (self.ip_per_sec, self.ip_burst),
+        };
+        (per_sec > 0).then(|| (f64::from(per_sec), f64::from(burst.max(1))))
+    }
+}
+
+// Whether a delivery RPC path is rate limited
+pub fn is_rate_limited(path: &str) -> bool {
+    path.trim_start_matches('/')
+        .split_once('/')
+        .is_some_and(|(service, _)| service.ends_with(".MlsDeliveryService"))
+        && rpc_method(path).is_some_and(|method| RATE_LIMITED_METHODS.contains(&method))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RateLimitKey {
+    Client(Uuid),
+    Ip(IpAddr),
+}
+
+impl RateLimitKey {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Client(_) => "client",
+            Self::Ip(_) => "ip",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, per_sec: f64, burst: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_sec).min(burst);
+        self.updated = now;
+    }
+
+    // How long until the bucket holds a request
+    fn wait(&self, per_sec: f64) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / per_sec)
+        }
+    }
+}
+
+#[derive(Default)]
+struct RateLimitState {
+    buckets: HashMap<RateLimitKey, Bucket>,
+    prune_at: usize,
+    // Requests rejected by the per-client and per-IP limits
+    rejected: [u64; 2],
+}
+
+impl RateLimitState {
+    // Drop the buckets that have refilled, which are the same as no bucket at all
+    fn prune(&mut self, config: &RateLimitConfig, now: Instant) {
+        if self.buckets.len() < self.prune_at.max(BUCKET_PRUNE_THRESHOLD) {
+            return;
+        }
+        self.buckets.retain(|key, bucket| match config.limit(key) {
+            Some((per_sec, burst)) => {
+                bucket.refill(per_sec, burst, now);
+                bucket.tokens < burst
+            }
+            None => false,
+        });
+        self.prune_at = self.buckets.len() * 2;
+    }
+}
+
+// Per-client and per-IP token buckets. A request is let through only if both its client's and
+// its address's buckets hold a request, and then takes one from each; rejected requests take
+// nothing.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    state: Arc<Mutex<RateLimitState>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            state: Arc::default(),
+        }
+    }
+
+    // Take a request from the buckets of the given client and address, or reject it with
+    // RESOURCE_EXHAUSTED and how long until both hold a request again
+    pub fn check(
+        &self,
+        client_id: Option<Uuid>,
+        ip: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), ServiceError> {
+        let keys = [
+            client_id.map(RateLimitKey::Client),
+            ip.map(RateLimitKey::Ip),
+        ];
+        let mut state = self.state.lock().unwrap();
+        state.prune(&self.config, now);
+
+        let mut exhausted: Option<(RateLimitKey, Duration)> = None;
+        for key in keys.iter().flatten() {
+            let Some((per_sec, burst)) = self.config.limit(key) else {
+                continue;
+            };
+            let bucket = state.buckets.entry(*key).or_insert(Bucket {
+                tokens: burst,
+                updated: now,
+            });
+            bucket.refill(per_sec, burst, now);
+            let wait = bucket.wait(per_sec);
+            if wait > exhausted.map_or(Duration::ZERO, |(_, wait)| wait) {
+                exhausted = Some((*key, wait));
+            }
+        }
+
+        if let Some((key, retry_after)) = exhausted {
+            let index = match key {
+                RateLimitKey::Client(_) => 0,
+                RateLimitKey::Ip(_) => 1,
+            };
+            state.rejected[index] += 1;
+            return Err(ServiceError::RateLimited {
+                limit: key.name(),
+                retry_after,
+            });
+        }
+        for key in keys.iter().flatten() {
+            if let Some(bucket) = state.buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    // Requests rejected so far by the per-client and per-IP limits
+    pub fn rejected(&self) -> [u64; 2] {
+        self.state.lock().unwrap().rejected
+    }
+}
+
+// Who the per-client limit holds a request to: the client its signature key authenticated, or
+// the user its bearer token did. The layer runs inside the authentication layers, so only
+// verified identities get a bucket; a caller can't spend another client's requests, or dodge
+// its own limit, by naming someone else in its headers. Unauthenticated requests are only held
+// to their address's limit.
+fn verified_caller<B>(request: &http::Request<B>) -> Option<Uuid> {
+    let extensions = request.extensions();
+    extensions
+        .get::<AuthenticatedClient>()
+        .map(|client| client.0)
+        .or_else(|| extensions.get::<AuthenticatedUser>().map(|user| user.0))
+}
+
+// The address of the peer a request came from, over plain TCP or TLS
+fn peer_ip<B>(request: &http::Request<B>) -> Option<IpAddr> {
+    let extensions = request.extensions();
+    extensions
+        .get::<TcpConnectInfo>()
+        .and_then(TcpConnectInfo::remote_addr)
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .and_then(|info| info.get_ref().remote_addr())
+        })
+        .map(|addr| addr.ip())
+}
+
+// Tower layer applying the rate limits to the expensive delivery RPCs. It must sit inside the
+// authentication layers, which attach the identities it keys the per-client limit on.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: RateLimiter,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: RateLimiter) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: RateLimiter,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for RateLimit<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>> + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        if self.limiter.config.is_enabled() && is_rate_limited(request.uri().path()) {
+            let client_id = verified_caller(&request);
+            let ip = peer_ip(&request);
+            if let Err(err) = self.limiter.check(client_id, ip, Instant::now()) {
+                return Box::pin(async move {
+                    Ok::<S::Response, S::Error>(Status::from(err).into_http())
+                });
+            }
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
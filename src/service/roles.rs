@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+use uuid::Uuid;
+
+use super::authz::Caller;
+use super::signatures::request_signer;
+use super::validation::Validator;
+use super::MLSServiceImpl;
+use crate::db::{DatabaseInterface, MembershipRole};
+use crate::error::ServiceError;
+
+// Group operations that need more than plain membership
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // Add plain members
+    AddMembers,
+    // Remove members other than admins and moderators
+    RemoveMembers,
+    // Freeze and unfreeze the group
+    FreezeGroup,
+    // Send announcements with BroadcastSystemMessage
+    Broadcast,
+    // Set how long the group's epochs may last with SetGroupKeyRotation
+    ManageKeyRotation,
+    // Grant the admin or moderator role, or remove a member holding one. Only admins have it.
+    ManageRoles,
+}
+
+impl Permission {
+    // What moderators may do unless the runtime settings say otherwise
+    pub const MODERATOR_DEFAULTS: &'static [Permission] =
+        &[Permission::RemoveMembers, Permission::FreezeGroup];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AddMembers => "add_members",
+            Self::RemoveMembers => "remove_members",
+            Self::FreezeGroup => "freeze_group",
+            Self::Broadcast => "broadcast",
+            Self::ManageKeyRotation => "manage_key_rotation",
+            Self::ManageRoles => "manage_roles",
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "add_members" => Ok(Self::AddMembers),
+            "remove_members" => Ok(Self::RemoveMembers),
+            "freeze_group" => Ok(Self::FreezeGroup),
+            "broadcast" => Ok(Self::Broadcast),
+            "manage_key_rotation" => Ok(Self::ManageKeyRotation),
+            "manage_roles" => Ok(Self::ManageRoles),
+            _ => Err(format!("unknown permission {:?}", value)),
+        }
+    }
+}
+
+// Parse a comma-separated list of permissions
+pub fn parse_permissions(value: &str) -> Option<Vec<Permission>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| entry.parse().ok())
+        .collect()
+}
+
+// Which roles may perform which group operations. Admins may do everything, and the other roles
+// what they were granted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RolePolicy {
+    pub moderator: Vec<Permission>,
+    pub member: Vec<Permission>,
+}
+
+impl RolePolicy {
+    // Whether a member with `role` may perform an operation. Managing roles is never granted to
+    // moderators or members, so they can't change who governs the group.
+    pub fn permits(&self, role: MembershipRole, permission: Permission) -> bool {
+        let granted = match role {
+            MembershipRole::Admin => return true,
+            MembershipRole::Moderator => &self.moderator,
+            MembershipRole::Member => &self.member,
+        };
+        permission != Permission::ManageRoles && granted.contains(&permission)
+    }
+}
+
+// The member making a membership change: the request's sender_id, else the authenticated or
+// signing client
+pub(crate) fn acting_member(
+    v: &mut Validator,
+    sender_id: &str,
+    caller: Caller,
+    metadata: &MetadataMap,
+) -> Uuid {
+    let acting = v
+        .optional_uuid("sender_id", sender_id)
+        .or(caller.client_id())
+        .or_else(|| request_signer(metadata));
+    acting.unwrap_or_else(|| {
+        v.violation(
+            "sender_id",
+            "is required unless the request is signed or authenticated",
+        );
+        Uuid::nil()
+    })
+}
+
+impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
+    // Check that the client is an active member of the group whose role grants the permission
+    pub(crate) async fn require_permission(
+        &self,
+        client_id: Uuid,
+        group_id: Uuid,
+        permission: Permission,
+    ) -> Result<(), Status> {
+        let role = self.member_role(client_id, group_id).await?;
+
+        let policy = self.settings.current().role_policy();
+        match role {
+            Some(role) if policy.permits(role, permission) => Ok(()),
+            Some(_) => Err(ServiceError::PermissionDenied(format!(
+                "Group role does not grant the {} permission",
+                permission.as_str()
+            ))
+            .into()),
+            None => Err(ServiceError::PermissionDenied(
+                "Client is not a member of the group".to_string(),
+            )
+            .into()),
+        }
+    }
+}
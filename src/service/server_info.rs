@@ -0,0 +1,146 @@
+use crate::auth::{self, AuthMode};
+use crate::db::MessageType;
+use crate::mls_codec::{self, MlsCrypto};
+use crate::settings::RequestSignatureMode;
+
+use super::mls;
+use super::pagination::PAGE_TOKEN_RPCS;
+use super::validation::MAX_ADMIN_LIST_LIMIT;
+
+// Version of this build, from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Commit this build was made from, or "unknown"; see build.rs
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
+
+// Cargo features that change what the server can do, with whether this build has them
+const FEATURES: &[(&str, bool)] = &[
+    ("memory-db", cfg!(feature = "memory-db")),
+    ("nats", cfg!(feature = "nats")),
+    ("kafka", cfg!(feature = "kafka")),
+];
+
+// How callers of the delivery service authenticate. Client auth and bearer tokens are set at
+// startup, request signatures by the runtime settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AuthModes {
+    pub client_auth: AuthMode,
+    pub token_auth: AuthMode,
+    pub request_signatures: RequestSignatureMode,
+}
+
+// What a running server is, so clients can adapt to it and operators can check a deployment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    // Build features compiled in
+    pub features: Vec<&'static str>,
+    // IANA ids of the supported MLS ciphersuites
+    pub ciphersuites: Vec<u16>,
+    pub protocol_versions: &'static [&'static str],
+    pub auth: AuthModes,
+}
+
+impl ServerInfo {
+    pub fn new(crypto: &MlsCrypto) -> Self {
+        Self {
+            version: VERSION,
+            git_commit: GIT_COMMIT,
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(feature, _)| *feature)
+                .collect(),
+            ciphersuites: crypto.supported_ciphersuites(),
+            protocol_versions: mls_codec::PROTOCOL_VERSIONS,
+            auth: AuthModes::default(),
+        }
+    }
+
+    // One line describing the build, logged at startup
+    pub fn banner(&self) -> String {
+        let list = |items: Vec<String>| match items.is_empty() {
+            true => "none".to_string(),
+            false => items.join(", "),
+        };
+        format!(
+            "hermetic-mls {} ({}); features: {}; ciphersuites: {}; protocol versions: {}",
+            self.version,
+            self.git_commit,
+            list(self.features.iter().map(|f| f.to_string()).collect()),
+            list(self.ciphersuites.iter().map(|c| c.to_string()).collect()),
+            list(
+                self.protocol_versions
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect()
+            ),
+        )
+    }
+
+    pub fn to_proto(&self) -> mls::GetServerInfoResponse {
+        mls::GetServerInfoResponse {
+            version: self.version.to_string(),
+            git_commit: self.git_commit.to_string(),
+            features: self.features.iter().map(|f| f.to_string()).collect(),
+            ciphersuites: self.ciphersuites.iter().map(|&c| c.into()).collect(),
+            protocol_versions: self
+                .protocol_versions
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+            capabilities: Some(self.capabilities()),
+        }
+    }
+
+    // What the delivery API supports. Everything but the auth modes is fixed by the build.
+    fn capabilities(&self) -> mls::Capabilities {
+        mls::Capabilities {
+            message_types: MessageType::ALL
+                .iter()
+                .map(|&t| mls::MessageType::from(t).into())
+                .collect(),
+            pagination: Some(mls::PaginationCapabilities {
+                page_token_rpcs: PAGE_TOKEN_RPCS.iter().map(|r| r.to_string()).collect(),
+                max_page_size: MAX_ADMIN_LIST_LIMIT,
+            }),
+            streaming: Some(mls::StreamingCapabilities {
+                subscribe_messages: true,
+                resume_tokens: true,
+            }),
+            idempotency: Some(mls::IdempotencyCapabilities {
+                create_group_handles: true,
+            }),
+            auth: Some(mls::AuthCapabilities {
+                client_auth: mls::AuthRequirement::from(self.auth.client_auth).into(),
+                token_auth: mls::AuthRequirement::from(self.auth.token_auth).into(),
+                request_signatures: mls::AuthRequirement::from(self.auth.request_signatures).into(),
+                unauthenticated_rpcs: auth::UNAUTHENTICATED_METHODS
+                    .iter()
+                    .map(|m| format!("mls.v1.MlsDeliveryService/{}", m))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl From<AuthMode> for mls::AuthRequirement {
+    fn from(mode: AuthMode) -> Self {
+        match mode {
+            AuthMode::Off => Self::NotChecked,
+            AuthMode::Optional => Self::Optional,
+            AuthMode::Required => Self::Required,
+        }
+    }
+}
+
+impl From<RequestSignatureMode> for mls::AuthRequirement {
+    fn from(mode: RequestSignatureMode) -> Self {
+        match mode {
+            RequestSignatureMode::Off => Self::NotChecked,
+            RequestSignatureMode::Optional => Self::Optional,
+            RequestSignatureMode::Required => Self::Required,
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use chrono::{DateTime, Utc};
+use prost::Message;
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+use uuid::Uuid;
+
+use super::MLSServiceImpl;
+use crate::db::{DatabaseInterface, DbError};
+use crate::error::ServiceError;
+use crate::mls_codec;
+use crate::settings::RequestSignatureMode;
+use crate::timestamps;
+
+// Metadata carrying a request signature
+pub const SIGNER_HEADER: &str = "x-mls-signer";
+pub const TIMESTAMP_HEADER: &str = "x-mls-timestamp";
+pub const NONCE_HEADER: &str = "x-mls-nonce";
+pub const SIGNATURE_HEADER: &str = "x-mls-signature-bin";
+
+pub const MAX_NONCE_LEN: usize = 64;
+
+// Domain separation label, so request signatures can't be confused with MLS signatures
+const DIGEST_LABEL: &[u8] = b"hermetic-mls request v1";
+
+// SHA-256 digest a client signs with its signature key: the label, the RPC method name
+// (e.g. "StoreCommit"), the unix timestamp in seconds, the nonce, and the serialized
+// request message, each followed by a zero byte
+pub fn request_digest(method: &str, timestamp: i64, nonce: &str, message: &[u8]) -> Vec<u8> {
+    let mut data =
+        Vec::with_capacity(DIGEST_LABEL.len() + method.len() + nonce.len() + message.len() + 32);
+    for part in [
+        DIGEST_LABEL,
+        method.as_bytes(),
+        timestamp.to_string().as_bytes(),
+        nonce.as_bytes(),
+        message,
+    ] {
+        data.extend_from_slice(part);
+        data.push(0);
+    }
+
+    mls_codec::sha256(&data)
+}
+
+// The client a mutating request acts for, which must be the one that signed it
+pub(crate) enum Actor {
+    // The client named in the request (sender_id, creator_id, client_id)
+    Client(Uuid),
+}
+
+// Signature metadata attached to a request
+struct RequestSignature {
+    signer: Uuid,
+    timestamp: i64,
+    nonce: String,
+    signature: Vec<u8>,
+}
+
+impl RequestSignature {
+    // Parse the signature metadata. Returns None if the request isn't signed at all.
+    fn from_metadata(metadata: &MetadataMap) -> Result<Option<Self>, ServiceError> {
+        let signer = metadata.get(SIGNER_HEADER);
+        let timestamp = metadata.get(TIMESTAMP_HEADER);
+        let nonce = metadata.get(NONCE_HEADER);
+        let signature = metadata.get_bin(SIGNATURE_HEADER);
+
+        let (signer, timestamp, nonce, signature) = match (signer, timestamp, nonce, signature) {
+            (None, None, None, None) => return Ok(None),
+            (Some(signer), Some(timestamp), Some(nonce), Some(signature)) => {
+                (signer, timestamp, nonce, signature)
+            }
+            _ => {
+                return Err(ServiceError::Unauthenticated(format!(
+                    "Signed requests need all of {}, {}, {} and {}",
+                    SIGNER_HEADER, TIMESTAMP_HEADER, NONCE_HEADER, SIGNATURE_HEADER
+                )))
+            }
+        };
+
+        let signer = signer
+            .to_str()
+            .ok()
+            .and_then(|s| Uuid::parse_str(s).ok())
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(format!("{} must be a UUID", SIGNER_HEADER))
+            })?;
+        let timestamp = timestamp
+            .to_str()
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(format!("{} must be unix seconds", TIMESTAMP_HEADER))
+            })?;
+        let nonce = nonce
+            .to_str()
+            .ok()
+            .filter(|s| !s.is_empty() && s.len() <= MAX_NONCE_LEN)
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(format!(
+                    "{} must be 1 to {} characters",
+                    NONCE_HEADER, MAX_NONCE_LEN
+                ))
+            })?
+            .to_string();
+        let signature = signature
+            .to_bytes()
+            .map_err(|_| {
+                ServiceError::Unauthenticated(format!("{} is not valid", SIGNATURE_HEADER))
+            })?
+            .to_vec();
+
+        Ok(Some(Self {
+            signer,
+            timestamp,
+            nonce,
+            signature,
+        }))
+    }
+}
+
+// The client that signed a request, if it claims to be signed. The signature itself is
+// checked by `verify_request`.
+pub(crate) fn request_signer(metadata: &MetadataMap) -> Option<Uuid> {
+    metadata
+        .get(SIGNER_HEADER)
+        .and_then(|signer| signer.to_str().ok())
+        .and_then(|signer| Uuid::parse_str(signer).ok())
+}
+
+impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
+    // Check the signature of a mutating request, according to the runtime signature mode.
+    // A signed request must be signed by `actor`, within the replay window, with a nonce the
+    // signer hasn't used before.
+    pub(crate) async fn verify_request<M: Message>(
+        &self,
+        metadata: &MetadataMap,
+        method: &str,
+        message: &M,
+        actor: Actor,
+    ) -> Result<(), Status> {
+        let settings = self.settings.current();
+        if settings.request_signatures == RequestSignatureMode::Off {
+            return Ok(());
+        }
+
+        let Some(signature) = RequestSignature::from_metadata(metadata)? else {
+            return self
+                .allow_unsigned(settings.request_signatures, &actor)
+                .await;
+        };
+
+        let Actor::Client(client_id) = actor;
+        if client_id != signature.signer {
+            return Err(ServiceError::PermissionDenied(
+                "Request is signed by a different client than it acts for".to_string(),
+            )
+            .into());
+        }
+
+        let now = timestamps::now();
+        let window = chrono::Duration::seconds(settings.request_signature_window_secs);
+        let signed_at = DateTime::<Utc>::from_timestamp(signature.timestamp, 0)
+            .filter(|signed_at| (now - *signed_at).abs() <= window)
+            .ok_or_else(|| {
+                ServiceError::Unauthenticated(
+                    "Request timestamp is outside the replay window".to_string(),
+                )
+            })?;
+
+        let signature_key = match self.db.get_client(signature.signer).await {
+            Ok(client) => client.signature_key,
+            Err(DbError::NotFound) => None,
+            Err(e) => return Err(Self::map_db_error(e)),
+        }
+        .ok_or_else(|| {
+            ServiceError::Unauthenticated("Request signer has no signature key".to_string())
+        })?;
+
+        let digest = request_digest(
+            method,
+            signature.timestamp,
+            &signature.nonce,
+            &message.encode_to_vec(),
+        );
+        self.crypto
+            .verify_ed25519(&digest, &signature_key, &signature.signature)
+            .map_err(|_| ServiceError::Unauthenticated("Invalid request signature".to_string()))?;
+
+        // Only a correctly signed request uses up its nonce
+        let fresh = self
+            .db
+            .record_request_nonce(signature.signer, &signature.nonce, signed_at + window)
+            .await
+            .map_err(Self::map_db_error)?;
+        if !fresh {
+            return Err(ServiceError::Unauthenticated(
+                "Request nonce was already used".to_string(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    // Whether an unsigned request may proceed. In optional mode, clients that registered a
+    // signature key are protected from requests made in their name without it.
+    async fn allow_unsigned(
+        &self,
+        mode: RequestSignatureMode,
+        actor: &Actor,
+    ) -> Result<(), Status> {
+        let requires_signature = match (mode, actor) {
+            (RequestSignatureMode::Required, _) => true,
+            (RequestSignatureMode::Optional, Actor::Client(client_id)) => {
+                match self.db.get_client(*client_id).await {
+                    Ok(client) => client.signature_key.is_some(),
+                    Err(DbError::NotFound) => false,
+                    Err(e) => return Err(Self::map_db_error(e)),
+                }
+            }
+            _ => false,
+        };
+
+        if requires_signature {
+            return Err(ServiceError::Unauthenticated("Request must be signed".to_string()).into());
+        }
+        Ok(())
+    }
+
+    // Whether the client has an active membership in the group
+    pub(crate) async fn is_active_member(
+        &self,
+        client_id: Uuid,
+        group_id: Uuid,
+    ) -> Result<bool, Status> {
+        Ok(self.member_role(client_id, group_id).await?.is_some())
+    }
+}
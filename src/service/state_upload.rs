@@ -0,0 +1,135 @@
+use futures_core::Stream;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use tonic::{Request, Response, Status};
+
+use super::authz::{Caller, Owner};
+use super::mls::{self, upload_group_state_request::Part};
+use super::signatures::Actor;
+use super::validation::{Validator, MAX_GROUP_STATE_CHUNK_BYTES, MAX_STREAMED_GROUP_STATE_BYTES};
+use super::MLSServiceImpl;
+use crate::db::DatabaseInterface;
+use crate::error::ServiceError;
+
+// Next part of an UploadGroupState stream, or None once the client closed it
+async fn next_part<S>(parts: &mut S) -> Result<Option<Part>, Status>
+where
+    S: Stream<Item = Result<mls::UploadGroupStateRequest, Status>> + Unpin,
+{
+    match parts.next().await.transpose()? {
+        Some(message) => message
+            .part
+            .map(Some)
+            .ok_or_else(|| ServiceError::validation("UploadGroupState message is empty").into()),
+        None => Ok(None),
+    }
+}
+
+impl<DB: DatabaseInterface> MLSServiceImpl<DB> {
+    // Reassemble an UploadGroupState stream and store the state. The header is checked before
+    // any chunk is read, so uploads that would be refused don't transfer the whole state first.
+    // Generic over the stream so it can be driven without a transport.
+    pub async fn receive_group_state<S>(
+        &self,
+        request: Request<S>,
+    ) -> Result<Response<mls::UploadGroupStateResponse>, Status>
+    where
+        S: Stream<Item = Result<mls::UploadGroupStateRequest, Status>> + Unpin,
+    {
+        let (metadata, extensions, mut parts) = request.into_parts();
+        let caller = Caller::from_extensions(&extensions);
+        let Some(Part::Header(header)) = next_part(&mut parts).await? else {
+            return Err(
+                ServiceError::validation("UploadGroupState must start with a header").into(),
+            );
+        };
+
+        let mut v = Validator::new();
+        let group_id = v.uuid("header.group_id", &header.group_id);
+        let sender_id = v.uuid("header.sender_id", &header.sender_id);
+        v.finish()?;
+        self.authorize(caller, Owner::Client(sender_id)).await?;
+        // The signature covers the header; the checksum ties the chunks to it
+        self.verify_request(
+            &metadata,
+            "UploadGroupState",
+            &header,
+            Actor::Client(sender_id),
+        )
+        .await?;
+
+        let group = self
+            .db
+            .get_group(group_id)
+            .await
+            .map_err(Self::map_db_error)?;
+        if !self.is_active_member(sender_id, group_id).await? {
+            return Err(ServiceError::PermissionDenied(
+                "Only active members of the group can upload its state".to_string(),
+            )
+            .into());
+        }
+        if header.epoch as i64 != group.epoch {
+            return Err(ServiceError::FailedPrecondition(format!(
+                "Group state is for epoch {}, the group is at epoch {}",
+                header.epoch, group.epoch
+            ))
+            .into());
+        }
+
+        let mut state = Vec::new();
+        let checksum = loop {
+            let mut v = Validator::new();
+            match next_part(&mut parts).await? {
+                Some(Part::Chunk(chunk)) => {
+                    v.bytes("chunk", &chunk, MAX_GROUP_STATE_CHUNK_BYTES);
+                    if state.len() + chunk.len() > MAX_STREAMED_GROUP_STATE_BYTES {
+                        v.violation(
+                            "chunk",
+                            format!(
+                                "takes the state past {} bytes",
+                                MAX_STREAMED_GROUP_STATE_BYTES
+                            ),
+                        );
+                    }
+                    v.finish()?;
+                    state.extend_from_slice(&chunk);
+                }
+                Some(Part::Sha256(checksum)) => break checksum,
+                Some(Part::Header(_)) => {
+                    return Err(
+                        ServiceError::validation("UploadGroupState takes a single header").into(),
+                    )
+                }
+                None => {
+                    return Err(ServiceError::validation(
+                        "UploadGroupState ended without a checksum",
+                    )
+                    .into())
+                }
+            }
+        };
+        if next_part(&mut parts).await?.is_some() {
+            return Err(
+                ServiceError::validation("UploadGroupState must end with the checksum").into(),
+            );
+        }
+
+        let mut v = Validator::new();
+        if state.is_empty() {
+            v.violation("chunk", "is required");
+        }
+        if Sha256::digest(&state).as_slice() != checksum.as_slice() {
+            v.violation("sha256", "does not match the uploaded state");
+        }
+        v.finish()?;
+
+        let size = state.len() as u64;
+        self.db
+            .update_group_state(group_id, state)
+            .await
+            .map_err(Self::map_db_error)?;
+
+        Ok(Response::new(mls::UploadGroupStateResponse { size }))
+    }
+}
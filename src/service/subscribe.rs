@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::pin::Pin;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use futures_core::Stream;
+use thiserror::Error;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::db::{DeliveryCursor, Message};
+use crate::events::DomainEvent;
+use crate::metrics::DeliveryMetrics;
+use crate::timestamps;
+
+use super::{message_to_proto, mls};
+
+pub type MessageStream = Pin<Box<dyn Stream<Item = Result<mls::Message, Status>> + Send>>;
+
+// Response metadata carrying the resume token of the point a subscription starts from, so a
+// client that disconnects before any message arrives can resume too
+pub const RESUME_TOKEN_HEADER: &str = "resume-token";
+
+// Version byte leading every resume token, so the format can change without misreading old tokens
+const TOKEN_VERSION: u8 = 2;
+// Each position is a group id, nil for the notices outside any group, and a sequence
+const POSITION_LEN: usize = 16 + 8;
+// Most positions a token may carry
+const MAX_TOKEN_POSITIONS: usize = 10_000;
+
+// Most messages a resumed subscription replays; a client that missed more reconciles with
+// FetchMessages instead
+pub(super) const MAX_REPLAYED_MESSAGES: usize = 1000;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("is not a valid resume token")]
+pub struct InvalidResumeToken;
+
+// Opaque token resuming a subscription right after the last sequence delivered of each group
+pub fn resume_token(positions: &[DeliveryCursor]) -> String {
+    let mut token = Vec::with_capacity(1 + positions.len() * POSITION_LEN);
+    token.push(TOKEN_VERSION);
+    for position in positions {
+        token.extend_from_slice(position.group_id.unwrap_or_default().as_bytes());
+        token.extend_from_slice(&position.sequence.to_be_bytes());
+    }
+    BASE64.encode(token)
+}
+
+// Positions a resume token resumes after, or None for an empty token
+pub fn open_resume_token(token: &str) -> Result<Option<Vec<DeliveryCursor>>, InvalidResumeToken> {
+    if token.is_empty() {
+        return Ok(None);
+    }
+
+    let token = BASE64.decode(token).map_err(|_| InvalidResumeToken)?;
+    let Some((&TOKEN_VERSION, positions)) = token.split_first() else {
+        return Err(InvalidResumeToken);
+    };
+    if positions.len() % POSITION_LEN != 0 || positions.len() / POSITION_LEN > MAX_TOKEN_POSITIONS {
+        return Err(InvalidResumeToken);
+    }
+    positions
+        .chunks(POSITION_LEN)
+        .map(|position| {
+            let group_id = Uuid::from_slice(&position[..16]).map_err(|_| InvalidResumeToken)?;
+            let sequence = i64::from_be_bytes(position[16..].try_into().expect("slice is 8 bytes"));
+            Ok(DeliveryCursor {
+                group_id: (!group_id.is_nil()).then_some(group_id),
+                sequence,
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+// Filters the event bus down to new messages for one client
+pub(super) struct MessageSubscription {
+    events: broadcast::Receiver<DomainEvent>,
+    client_id: Uuid,
+    group_id: Option<Uuid>,
+    // Groups the client is an active member of, kept current from membership events
+    groups: HashSet<Uuid>,
+    // Last sequence delivered of each group, and of the notices under None
+    positions: BTreeMap<Option<Uuid>, i64>,
+    // Messages stored since the subscription being resumed, streamed before any event
+    replay: VecDeque<Message>,
+    // Ids of the replayed messages, whose events may still be queued on the bus
+    replayed: HashSet<Uuid>,
+    delivery: DeliveryMetrics,
+}
+
+impl MessageSubscription {
+    pub(super) fn new(
+        events: broadcast::Receiver<DomainEvent>,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        groups: HashSet<Uuid>,
+        start: Vec<DeliveryCursor>,
+        replay: Vec<Message>,
+        delivery: DeliveryMetrics,
+    ) -> Self {
+        Self {
+            events,
+            client_id,
+            group_id,
+            groups,
+            positions: start
+                .into_iter()
+                .map(|c| (c.group_id, c.sequence))
+                .collect(),
+            replayed: replay.iter().map(|m| m.id).collect(),
+            replay: replay.into(),
+            delivery,
+        }
+    }
+
+    pub(super) fn into_stream(self) -> MessageStream {
+        Box::pin(futures_util::stream::unfold(
+            Some(self),
+            |subscription| async move {
+                let mut subscription = subscription?;
+                if let Some(message) = subscription.replay.pop_front() {
+                    let message = subscription.deliver(message);
+                    return Some((Ok(message), Some(subscription)));
+                }
+                loop {
+                    match subscription.events.recv().await {
+                        Ok(event) => {
+                            if let Some(message) = subscription.handle(event) {
+                                return Some((Ok(message), Some(subscription)));
+                            }
+                        }
+                        // The client has to resume from the last message it received
+                        Err(RecvError::Lagged(skipped)) => {
+                            let status = Status::aborted(format!(
+                                "Subscription fell {} events behind; resubscribe with the last resume token",
+                                skipped
+                            ));
+                            return Some((Err(status), None));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        ))
+    }
+
+    fn handle(&mut self, event: DomainEvent) -> Option<mls::Message> {
+        match event {
+            DomainEvent::MemberAdded {
+                group_id,
+                client_id,
+                ..
+            } if client_id == self.client_id => {
+                self.groups.insert(group_id);
+                None
+            }
+            DomainEvent::MemberRemoved {
+                group_id,
+                client_id,
+                ..
+            } if client_id == self.client_id => {
+                self.groups.remove(&group_id);
+                None
+            }
+            DomainEvent::MessageStored(message) | DomainEvent::MessageRelayed(message)
+                if self.wants(&message) && !self.replayed.contains(&message.id) =>
+            {
+                Some(self.deliver(Message::clone(&message)))
+            }
+            _ => None,
+        }
+    }
+
+    // Stream a message with the token resuming right after it, recording its delivery latency
+    fn deliver(&mut self, message: Message) -> mls::Message {
+        self.delivery.record_delivery(&message, timestamps::now());
+        let position = self.positions.entry(message.group_id).or_default();
+        *position = (*position).max(message.sequence);
+        let positions: Vec<DeliveryCursor> = self
+            .positions
+            .iter()
+            .map(|(&group_id, &sequence)| DeliveryCursor { group_id, sequence })
+            .collect();
+        mls::Message {
+            resume_token: resume_token(&positions),
+            ..message_to_proto(message)
+        }
+    }
+
+    fn wants(&self, message: &Message) -> bool {
+        if self.group_id.is_some() && message.group_id != self.group_id {
+            return false;
+        }
+        match (&message.recipients, message.group_id) {
+            // Addressed messages only go to their recipients
+            (Some(recipients), _) => recipients.contains(&self.client_id),
+            (None, Some(group_id)) => self.groups.contains(&group_id),
+            (None, None) => false,
+        }
+    }
+}
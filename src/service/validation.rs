@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, FieldViolation, StatusExt};
+use uuid::Uuid;
+
+// Length caps for free-form request fields
+pub const MAX_IDENTITY_LEN: usize = 256;
+pub const MAX_DEVICE_NAME_LEN: usize = 128;
+pub const MAX_ANNOUNCEMENT_LEN: usize = 4096;
+pub const MAX_FLAG_NAME_LEN: usize = 64;
+pub const MAX_TRACE_REASON_LEN: usize = 1024;
+
+// Cap on the groups or users a feature flag can target explicitly
+pub const MAX_FLAG_TARGETS: usize = 1000;
+
+// Cap on the recipients of a single welcome
+pub const MAX_WELCOME_RECIPIENTS: usize = 1000;
+
+// Cap on the messages a single MarkMessagesRead call can mark
+pub const MAX_MARK_READ_MESSAGES: usize = 1000;
+
+// Cap on the watermarks a single AckMessages call can carry
+pub const MAX_ACK_WATERMARKS: usize = 1000;
+
+// Caps on an abuse report: the messages it references, its reason, and its encrypted excerpt
+pub const MAX_REPORTED_MESSAGES: usize = 100;
+pub const MAX_REPORT_REASON_LEN: usize = 4096;
+pub const MAX_REPORT_EXCERPT_BYTES: usize = 64 * 1024;
+
+// Cap on the proposals a single commit can include by reference, and the length of a
+// proposal ref (a SHA-256 hash)
+pub const MAX_COMMIT_PROPOSALS: usize = 1000;
+pub const PROPOSAL_REF_LEN: usize = 32;
+
+// Cap on the members a group can be created with
+pub const MAX_INITIAL_MEMBERS: usize = 1000;
+
+// Default and cap for the groups GetStorageStats returns
+pub const DEFAULT_STORAGE_STATS_LIMIT: u32 = 20;
+pub const MAX_STORAGE_STATS_LIMIT: u32 = 1000;
+
+// Default and cap for the rows admin listings return, and the length cap of their filters
+pub const DEFAULT_ADMIN_LIST_LIMIT: u32 = 100;
+pub const MAX_ADMIN_LIST_LIMIT: u32 = 1000;
+pub const MAX_FILTER_LEN: usize = 1024;
+
+// Length of the Ed25519 public keys clients sign requests with
+pub const ED25519_PUBLIC_KEY_LEN: usize = 32;
+
+// Size cap for device attestation evidence
+pub const MAX_ATTESTATION_TOKEN_BYTES: usize = 64 * 1024;
+
+// Size cap for the MLS group id clients register for a group
+pub const MAX_MLS_GROUP_ID_LEN: usize = 256;
+
+// Length cap for the handle a client chooses for a group
+pub const MAX_GROUP_HANDLE_LEN: usize = 256;
+
+// Size cap for opaque MLS payloads (key packages, proposals, commits, welcomes)
+pub const MAX_MLS_MESSAGE_BYTES: usize = 1024 * 1024;
+
+// Size cap for the JSON metadata attached to a message
+pub const MAX_MESSAGE_EXTRA_BYTES: usize = 16 * 1024;
+
+// Size cap for encrypted client backups
+pub const MAX_CLIENT_BACKUP_BYTES: usize = 8 * 1024 * 1024;
+
+// Size cap for serialized group state
+pub const MAX_GROUP_STATE_BYTES: usize = 16 * 1024 * 1024;
+
+// Size caps for group state streamed with UploadGroupState, in total and per chunk
+pub const MAX_STREAMED_GROUP_STATE_BYTES: usize = 64 * 1024 * 1024;
+pub const MAX_GROUP_STATE_CHUNK_BYTES: usize = 1024 * 1024;
+
+// How the MLS payloads clients upload (key packages, proposals, commits, welcomes, GroupInfo,
+// group state) are checked before they're stored. Only the test-utils feature can turn the
+// checks off, so a release build always validates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ValidationPolicy {
+    // Key packages are checked with OpenMLS, the other payloads for content and framing
+    #[default]
+    Strict,
+    // Payloads are stored unchecked, for tests that upload placeholder bytes
+    #[cfg(feature = "test-utils")]
+    Unchecked,
+}
+
+impl ValidationPolicy {
+    pub fn checks_payloads(self) -> bool {
+        match self {
+            Self::Strict => true,
+            #[cfg(feature = "test-utils")]
+            Self::Unchecked => false,
+        }
+    }
+}
+
+impl FromStr for ValidationPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "strict" => Ok(Self::Strict),
+            #[cfg(feature = "test-utils")]
+            "unchecked" => Ok(Self::Unchecked),
+            #[cfg(not(feature = "test-utils"))]
+            "unchecked" => {
+                Err("unchecked validation needs a build with the test-utils feature".to_string())
+            }
+            _ => Err(format!("unknown validation policy {:?}", value)),
+        }
+    }
+}
+
+impl TryFrom<String> for ValidationPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+// Collects every invalid field in a request so they can be reported together.
+// Accessors return a placeholder on failure; check `finish` before using the results.
+#[derive(Debug, Default)]
+pub struct Validator {
+    violations: Vec<FieldViolation>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record a violation for a field
+    pub fn violation(&mut self, field: impl Into<String>, description: impl Into<String>) {
+        self.violations
+            .push(FieldViolation::new(field, description));
+    }
+
+    // Parse a required UUID field
+    pub fn uuid(&mut self, field: &str, value: &str) -> Uuid {
+        if value.is_empty() {
+            self.violation(field, "is required");
+            return Uuid::nil();
+        }
+
+        Uuid::parse_str(value).unwrap_or_else(|_| {
+            self.violation(field, "must be a valid UUID");
+            Uuid::nil()
+        })
+    }
+
+    // Parse an optional UUID field, where an empty string means unset
+    pub fn optional_uuid(&mut self, field: &str, value: &str) -> Option<Uuid> {
+        if value.is_empty() {
+            None
+        } else {
+            Some(self.uuid(field, value))
+        }
+    }
+
+    // Parse a repeated UUID field, reporting each bad element by index
+    pub fn uuids(&mut self, field: &str, values: &[String]) -> Vec<Uuid> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| self.uuid(&format!("{}[{}]", field, i), value))
+            .collect()
+    }
+
+    // Parse a repeated UUID field with a cap on its length, dropping duplicates. The cap is
+    // checked before parsing so an oversized list is rejected without being walked.
+    pub fn capped_uuids(&mut self, field: &str, values: &[String], max_len: usize) -> Vec<Uuid> {
+        if values.len() > max_len {
+            self.violation(field, format!("must have at most {} entries", max_len));
+            return Vec::new();
+        }
+
+        let mut seen = HashSet::with_capacity(values.len());
+        self.uuids(field, values)
+            .into_iter()
+            .filter(|id| seen.insert(*id))
+            .collect()
+    }
+
+    // Check a repeated field of proposal refs with a cap on its length, dropping duplicates
+    pub fn proposal_refs(&mut self, field: &str, values: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        if values.len() > MAX_COMMIT_PROPOSALS {
+            self.violation(
+                field,
+                format!("must have at most {} entries", MAX_COMMIT_PROPOSALS),
+            );
+            return Vec::new();
+        }
+
+        let mut seen = HashSet::with_capacity(values.len());
+        for (i, value) in values.iter().enumerate() {
+            if value.len() != PROPOSAL_REF_LEN {
+                self.violation(
+                    format!("{}[{}]", field, i),
+                    format!("must be {} bytes", PROPOSAL_REF_LEN),
+                );
+            }
+        }
+        values
+            .iter()
+            .filter(|value| seen.insert(value.as_slice()))
+            .cloned()
+            .collect()
+    }
+
+    // Check that a string field is set and within its length cap
+    pub fn string(&mut self, field: &str, value: &str, max_len: usize) {
+        if value.is_empty() {
+            self.violation(field, "is required");
+        } else if value.len() > max_len {
+            self.violation(field, format!("must be at most {} bytes", max_len));
+        }
+    }
+
+    // Check that a bytes field is set and within its size cap
+    pub fn bytes(&mut self, field: &str, value: &[u8], max_len: usize) {
+        if value.is_empty() {
+            self.violation(field, "is required");
+        } else if value.len() > max_len {
+            self.violation(field, format!("must be at most {} bytes", max_len));
+        }
+    }
+
+    // Parse an optional JSON object field, where empty bytes mean unset
+    pub fn json_object(
+        &mut self,
+        field: &str,
+        value: &[u8],
+        max_len: usize,
+    ) -> Option<serde_json::Value> {
+        if value.is_empty() {
+            return None;
+        }
+        if value.len() > max_len {
+            self.violation(field, format!("must be at most {} bytes", max_len));
+            return None;
+        }
+
+        match serde_json::from_slice(value) {
+            Ok(object @ serde_json::Value::Object(_)) => Some(object),
+            _ => {
+                self.violation(field, "must be a JSON object");
+                None
+            }
+        }
+    }
+
+    // Parse a required proto enum field, rejecting unknown and unspecified (zero) values
+    pub fn enum_value<E: TryFrom<i32>>(&mut self, field: &str, value: i32) -> Option<E> {
+        match E::try_from(value) {
+            Ok(parsed) if value != 0 => Some(parsed),
+            _ => {
+                self.violation(field, format!("{} is not a valid value", value));
+                None
+            }
+        }
+    }
+
+    // Return INVALID_ARGUMENT with a BadRequest detail listing every violation, if any
+    pub fn finish(self) -> Result<(), Status> {
+        if self.violations.is_empty() {
+            return Ok(());
+        }
+
+        let message = self
+            .violations
+            .iter()
+            .map(|v| format!("{} {}", v.field, v.description))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        Err(Status::with_error_details(
+            Code::InvalidArgument,
+            format!("Invalid request: {}", message),
+            ErrorDetails::with_bad_request(self.violations),
+        ))
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use http::{HeaderName, HeaderValue, Method, Uri};
+use thiserror::Error;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::auth;
+use crate::service::deprecations::DEPRECATION_HEADER;
+use crate::service::rate_limit::RETRY_AFTER_HEADER;
+use crate::service::signatures::{NONCE_HEADER, SIGNATURE_HEADER, SIGNER_HEADER, TIMESTAMP_HEADER};
+use crate::service::subscribe::RESUME_TOKEN_HEADER;
+
+// Request headers browsers may send: those gRPC-web clients use, plus the request signature and
+// authentication headers
+pub const ALLOWED_HEADERS: &[&str] = &[
+    "content-type",
+    "x-grpc-web",
+    "x-user-agent",
+    "grpc-timeout",
+    SIGNER_HEADER,
+    TIMESTAMP_HEADER,
+    NONCE_HEADER,
+    SIGNATURE_HEADER,
+    auth::CLIENT_HEADER,
+    auth::TIMESTAMP_HEADER,
+    auth::NONCE_HEADER,
+    auth::SIGNATURE_HEADER,
+    auth::token::AUTHORIZATION_HEADER,
+];
+
+// Response headers browser clients need to read the outcome of a call, deprecation warnings,
+// subscription resume tokens and when to retry after being rate limited
+pub const EXPOSED_HEADERS: &[&str] = &[
+    "grpc-status",
+    "grpc-message",
+    "grpc-status-details-bin",
+    DEPRECATION_HEADER,
+    RESUME_TOKEN_HEADER,
+    RETRY_AFTER_HEADER,
+];
+
+// How long browsers may cache a preflight response
+pub const DEFAULT_CORS_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CorsError {
+    #[error("Invalid CORS origin {0:?}, expected scheme://host[:port]")]
+    InvalidOrigin(String),
+
+    #[error(
+        "CORS_ALLOWED_ORIGINS must list the allowed origins while request signatures are enabled"
+    )]
+    AnyOriginWithAuth,
+}
+
+// Origins browsers may call the service from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+impl AllowedOrigins {
+    // Parse a comma-separated list of origins; "*" (or nothing) allows any origin
+    pub fn parse(value: &str) -> Result<Self, CorsError> {
+        let value = value.trim();
+        if value.is_empty() || value == "*" {
+            return Ok(Self::Any);
+        }
+
+        value
+            .split(',')
+            .map(|origin| {
+                let origin = origin.trim();
+                let invalid = || CorsError::InvalidOrigin(origin.to_string());
+                let uri: Uri = origin.parse().map_err(|_| invalid())?;
+                match (uri.scheme_str(), uri.authority()) {
+                    (Some(scheme @ ("http" | "https")), Some(authority))
+                        if origin == format!("{}://{}", scheme, authority) =>
+                    {
+                        HeaderValue::from_str(origin).map_err(|_| invalid())
+                    }
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(Self::List)
+    }
+}
+
+// CORS policy for browser (gRPC-web) clients. Set at startup, unlike the runtime settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    pub origins: AllowedOrigins,
+    pub max_age: Duration,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: AllowedOrigins::Any,
+            max_age: DEFAULT_CORS_MAX_AGE,
+        }
+    }
+}
+
+impl CorsConfig {
+    // Build the layer. Listed origins are reflected back with credentials allowed; any origin
+    // is only allowed without credentials, and not at all while requests are authenticated.
+    pub fn layer(&self, auth_enabled: bool) -> Result<CorsLayer, CorsError> {
+        let layer = CorsLayer::new()
+            .allow_methods([Method::POST])
+            .allow_headers(header_names(ALLOWED_HEADERS))
+            .expose_headers(header_names(EXPOSED_HEADERS))
+            .max_age(self.max_age);
+
+        match &self.origins {
+            AllowedOrigins::List(origins) => Ok(layer
+                .allow_origin(AllowOrigin::list(origins.clone()))
+                .allow_credentials(true)),
+            AllowedOrigins::Any if auth_enabled => Err(CorsError::AnyOriginWithAuth),
+            AllowedOrigins::Any => Ok(layer.allow_origin(Any)),
+        }
+    }
+}
+
+fn header_names(names: &[&'static str]) -> Vec<HeaderName> {
+    names
+        .iter()
+        .map(|name| HeaderName::from_static(name))
+        .collect()
+}
@@ -0,0 +1,80 @@
+use std::future::{ready, Ready};
+use std::task::{Context, Poll};
+
+use futures_util::future::Either;
+use tokio::sync::watch;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use super::RuntimeSettings;
+
+// Method name prefixes of delivery RPCs that don't change any state
+const READ_ONLY_PREFIXES: &[&str] = &["Get", "List", "Fetch", "Subscribe"];
+
+// Whether a gRPC request path is for a mutating delivery RPC, which maintenance mode
+// rejects. The admin service stays available so operators can turn it off again.
+pub fn is_blocked_in_maintenance(path: &str) -> bool {
+    let Some((service, method)) = path.trim_start_matches('/').split_once('/') else {
+        return false;
+    };
+
+    service.ends_with(".MlsDeliveryService")
+        && !READ_ONLY_PREFIXES
+            .iter()
+            .any(|prefix| method.starts_with(prefix))
+}
+
+// Tower layer that rejects mutating delivery RPCs with UNAVAILABLE while the runtime
+// settings have maintenance mode on
+#[derive(Clone)]
+pub struct MaintenanceLayer {
+    settings: watch::Receiver<RuntimeSettings>,
+}
+
+impl MaintenanceLayer {
+    pub fn new(settings: watch::Receiver<RuntimeSettings>) -> Self {
+        Self { settings }
+    }
+}
+
+impl<S> Layer<S> for MaintenanceLayer {
+    type Service = Maintenance<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Maintenance {
+            inner,
+            settings: self.settings.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Maintenance<S> {
+    inner: S,
+    settings: watch::Receiver<RuntimeSettings>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Maintenance<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<Ready<Result<S::Response, S::Error>>, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        if self.settings.borrow().maintenance_mode
+            && is_blocked_in_maintenance(request.uri().path())
+        {
+            let status = Status::unavailable("The service is in maintenance mode; try again later");
+            return Either::Left(ready(Ok(status.into_http())));
+        }
+
+        Either::Right(self.inner.call(request))
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, info, LevelFilter};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::db::MessageType;
+use crate::janitor::{
+    JanitorConfig, KeyRotationPolicy, RetentionPolicy, StuckMemberAction, StuckMemberPolicy,
+    WelcomeSlaPolicy,
+};
+use crate::service::roles::{parse_permissions, Permission, RolePolicy};
+
+pub mod cors;
+pub mod maintenance;
+
+pub use cors::CorsConfig;
+pub use maintenance::MaintenanceLayer;
+
+// Errors that can occur while loading runtime settings
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("Failed to read settings file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to parse settings: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("Invalid log level {0:?}")]
+    InvalidLogLevel(String),
+
+    #[error("Invalid value {value:?} for {name}")]
+    InvalidVariable { name: String, value: String },
+}
+
+// Which mutating requests must carry a valid client signature (see `service::signatures`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestSignatureMode {
+    // Signature headers are ignored
+    #[default]
+    Off,
+    // Signed requests are verified, and clients that registered a signature key must sign
+    Optional,
+    // Every mutating request must be signed
+    Required,
+}
+
+impl FromStr for RequestSignatureMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "off" => Ok(Self::Off),
+            "optional" => Ok(Self::Optional),
+            "required" => Ok(Self::Required),
+            _ => Err(format!("unknown request signature mode {:?}", value)),
+        }
+    }
+}
+
+// Settings that can change while the server runs. Structural settings (address,
+// database, schema) still need a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeSettings {
+    pub log_level: String,
+    // Reject mutating delivery RPCs with UNAVAILABLE; reads and the admin service keep working
+    pub maintenance_mode: bool,
+    pub stuck_member_max_epochs: i64,
+    pub stuck_member_max_days: i64,
+    pub stuck_member_auto_remove: bool,
+    pub purge_grace_days: i64,
+    pub key_package_low_threshold: i64,
+    // Days the janitor keeps messages of each type before deleting them; types left out are
+    // kept. Welcomes are deleted delivered or not, other types once every recipient acked them.
+    pub message_retention_days: BTreeMap<MessageType, i64>,
+    // Days the janitor keeps key packages after they were used; 0 keeps them
+    pub used_key_package_retention_days: i64,
+    // Seconds a group's epoch may last before its members are reminded to commit an Update,
+    // for groups without a limit of their own; 0 disables the default
+    pub key_rotation_max_epoch_age_secs: i64,
+    // Seconds a joining member has to acknowledge its welcome before a push reminder is
+    // published; 0 disables welcome delivery tracking
+    pub welcome_deadline_secs: i64,
+    // Push reminders, a deadline apart, before a welcome is reported stranded
+    pub welcome_push_retries: i64,
+    // Rows of each table the janitor rewrites per pass while their blobs are in an older stored
+    // format; 0 disables the upgrade
+    pub blob_upgrade_batch: i64,
+    pub request_signatures: RequestSignatureMode,
+    // How far a signed request's timestamp may be from the server's clock
+    pub request_signature_window_secs: i64,
+    // How many epochs behind its group an application message may be; negative disables the check
+    pub application_epoch_tolerance: i64,
+    // Residency region of the database this instance writes to; unset disables residency checks
+    pub data_region: Option<String>,
+    // Residency region of each tagged tenant (user). Their data may only be written by instances
+    // in that region.
+    pub tenant_regions: BTreeMap<Uuid, String>,
+    // What group moderators may do. Managing roles is never granted to them.
+    pub moderator_permissions: Vec<Permission>,
+    // What plain group members may do beyond sending messages, such as adding other members
+    pub member_permissions: Vec<Permission>,
+    // Only let key packages of clients whose attestation passed be claimed, keeping other
+    // devices out of new groups
+    pub key_package_claims_require_attestation: bool,
+    // Longest TTL senders may request for an application message; 0 disables message expiry
+    pub max_message_ttl_secs: i64,
+    // Undelivered application messages kept per recipient of a group; storing more evicts the
+    // oldest. 0 disables the cap.
+    pub max_queued_application_messages: i64,
+    // Weakest ciphersuite, by security level in bits, that groups may be created with and
+    // claimed key packages may use. Suites without a known level never meet it.
+    pub min_ciphersuite_bits: u16,
+    // Ciphersuite floors of tenants (users) whose policy differs from `min_ciphersuite_bits`
+    pub tenant_min_ciphersuite_bits: BTreeMap<Uuid, u16>,
+}
+
+impl Default for RuntimeSettings {
+    fn default() -> Self {
+        let janitor = JanitorConfig::default();
+        Self {
+            log_level: "info".to_string(),
+            maintenance_mode: false,
+            stuck_member_max_epochs: janitor.stuck_members.max_epochs_behind,
+            stuck_member_max_days: janitor.stuck_members.max_days_behind,
+            stuck_member_auto_remove: false,
+            purge_grace_days: janitor.purge_grace_period.num_days(),
+            key_package_low_threshold: janitor.key_package_low_threshold,
+            message_retention_days: BTreeMap::new(),
+            used_key_package_retention_days: 0,
+            key_rotation_max_epoch_age_secs: 0,
+            welcome_deadline_secs: 0,
+            welcome_push_retries: janitor.welcome_sla.push_retries,
+            blob_upgrade_batch: janitor.blob_upgrade_batch,
+            request_signatures: RequestSignatureMode::Off,
+            request_signature_window_secs: 300,
+            application_epoch_tolerance: 1,
+            data_region: None,
+            tenant_regions: BTreeMap::new(),
+            moderator_permissions: Permission::MODERATOR_DEFAULTS.to_vec(),
+            member_permissions: Vec::new(),
+            key_package_claims_require_attestation: false,
+            max_message_ttl_secs: 30 * 24 * 60 * 60,
+            max_queued_application_messages: 10_000,
+            min_ciphersuite_bits: 128,
+            tenant_min_ciphersuite_bits: BTreeMap::new(),
+        }
+    }
+}
+
+// Parse a comma-separated list of `user_id=region` tenant tags
+pub fn parse_tenant_regions(value: &str) -> Option<BTreeMap<Uuid, String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (user_id, region) = entry.split_once('=')?;
+            let region = region.trim();
+            Some((user_id.trim().parse().ok()?, region.to_string())).filter(|_| !region.is_empty())
+        })
+        .collect()
+}
+
+// Parse a comma-separated list of `user_id=bits` tenant ciphersuite floors
+pub fn parse_tenant_ciphersuite_floors(value: &str) -> Option<BTreeMap<Uuid, u16>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (user_id, bits) = entry.split_once('=')?;
+            Some((user_id.trim().parse().ok()?, bits.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+// Parse a comma-separated list of `message_type=days` retentions
+pub fn parse_message_retention(value: &str) -> Option<BTreeMap<MessageType, i64>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (message_type, days) = entry.split_once('=')?;
+            let days: i64 = days.trim().parse().ok().filter(|days| *days > 0)?;
+            Some((message_type.trim().parse().ok()?, days))
+        })
+        .collect()
+}
+
+// Helper function to parse an environment variable with `parse`, keeping the default only if
+// it's unset
+fn parse_var<T>(
+    env: &dyn Fn(&str) -> Option<String>,
+    name: &str,
+    default: T,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<T, SettingsError> {
+    match env(name) {
+        None => Ok(default),
+        Some(value) => match parse(&value) {
+            Some(parsed) => Ok(parsed),
+            None => Err(SettingsError::InvalidVariable {
+                name: name.to_string(),
+                value,
+            }),
+        },
+    }
+}
+
+// Helper function to parse an environment variable, keeping the default only if it's unset
+fn env_or<T: FromStr>(
+    env: &dyn Fn(&str) -> Option<String>,
+    name: &str,
+    default: T,
+) -> Result<T, SettingsError> {
+    parse_var(env, name, default, |v| v.parse().ok())
+}
+
+impl RuntimeSettings {
+    // Read settings from environment variables, falling back to the defaults
+    pub fn from_env() -> Result<Self, SettingsError> {
+        Self::from_env_with_defaults(Self::default())
+    }
+
+    // Read settings from environment variables, falling back to `defaults`, such as those of
+    // the startup configuration. A variable that's set but invalid is an error rather than
+    // falling back, so a typo can't silently relax a policy.
+    pub fn from_env_with_defaults(defaults: Self) -> Result<Self, SettingsError> {
+        Self::from_vars(defaults, |name| env::var(name).ok())
+    }
+
+    // Like `from_env_with_defaults`, reading the variables `env` returns
+    pub fn from_vars(
+        defaults: Self,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, SettingsError> {
+        let env: &dyn Fn(&str) -> Option<String> = &env;
+        Ok(Self {
+            log_level: env("LOG_LEVEL").unwrap_or(defaults.log_level),
+            maintenance_mode: env("MAINTENANCE_MODE")
+                .map_or(defaults.maintenance_mode, |v| v == "true"),
+            stuck_member_max_epochs: env_or(
+                env,
+                "STUCK_MEMBER_MAX_EPOCHS",
+                defaults.stuck_member_max_epochs,
+            )?,
+            stuck_member_max_days: env_or(
+                env,
+                "STUCK_MEMBER_MAX_DAYS",
+                defaults.stuck_member_max_days,
+            )?,
+            stuck_member_auto_remove: env("STUCK_MEMBER_AUTO_REMOVE")
+                .map_or(defaults.stuck_member_auto_remove, |v| v == "true"),
+            purge_grace_days: env_or(env, "PURGE_GRACE_DAYS", defaults.purge_grace_days)?,
+            key_package_low_threshold: env_or(
+                env,
+                "KEY_PACKAGE_LOW_THRESHOLD",
+                defaults.key_package_low_threshold,
+            )?,
+            message_retention_days: parse_var(
+                env,
+                "MESSAGE_RETENTION_DAYS",
+                defaults.message_retention_days,
+                parse_message_retention,
+            )?,
+            used_key_package_retention_days: env_or(
+                env,
+                "USED_KEY_PACKAGE_RETENTION_DAYS",
+                defaults.used_key_package_retention_days,
+            )?,
+            key_rotation_max_epoch_age_secs: env_or(
+                env,
+                "KEY_ROTATION_MAX_EPOCH_AGE_SECS",
+                defaults.key_rotation_max_epoch_age_secs,
+            )?,
+            welcome_deadline_secs: env_or(
+                env,
+                "WELCOME_DEADLINE_SECS",
+                defaults.welcome_deadline_secs,
+            )?,
+            welcome_push_retries: env_or(
+                env,
+                "WELCOME_PUSH_RETRIES",
+                defaults.welcome_push_retries,
+            )?,
+            blob_upgrade_batch: env_or(env, "BLOB_UPGRADE_BATCH", defaults.blob_upgrade_batch)?,
+            request_signatures: env_or(env, "REQUEST_SIGNATURES", defaults.request_signatures)?,
+            request_signature_window_secs: env_or(
+                env,
+                "REQUEST_SIGNATURE_WINDOW_SECS",
+                defaults.request_signature_window_secs,
+            )?,
+            application_epoch_tolerance: env_or(
+                env,
+                "APPLICATION_EPOCH_TOLERANCE",
+                defaults.application_epoch_tolerance,
+            )?,
+            data_region: env("DATA_REGION")
+                .map_or(defaults.data_region, |v| Some(v).filter(|v| !v.is_empty())),
+            tenant_regions: parse_var(
+                env,
+                "TENANT_REGIONS",
+                defaults.tenant_regions,
+                parse_tenant_regions,
+            )?,
+            moderator_permissions: parse_var(
+                env,
+                "MODERATOR_PERMISSIONS",
+                defaults.moderator_permissions,
+                parse_permissions,
+            )?,
+            member_permissions: parse_var(
+                env,
+                "MEMBER_PERMISSIONS",
+                defaults.member_permissions,
+                parse_permissions,
+            )?,
+            key_package_claims_require_attestation: env("KEY_PACKAGE_CLAIMS_REQUIRE_ATTESTATION")
+                .map_or(defaults.key_package_claims_require_attestation, |v| {
+                    v == "true"
+                }),
+            max_message_ttl_secs: env_or(
+                env,
+                "MAX_MESSAGE_TTL_SECS",
+                defaults.max_message_ttl_secs,
+            )?,
+            max_queued_application_messages: env_or(
+                env,
+                "MAX_QUEUED_APPLICATION_MESSAGES",
+                defaults.max_queued_application_messages,
+            )?,
+            min_ciphersuite_bits: env_or(
+                env,
+                "MIN_CIPHERSUITE_BITS",
+                defaults.min_ciphersuite_bits,
+            )?,
+            tenant_min_ciphersuite_bits: parse_var(
+                env,
+                "TENANT_MIN_CIPHERSUITE_BITS",
+                defaults.tenant_min_ciphersuite_bits,
+                parse_tenant_ciphersuite_floors,
+            )?,
+        })
+    }
+
+    // Read settings from the environment, then override them with the fields set in a
+    // JSON settings file, if any
+    pub fn load(file: Option<&Path>) -> Result<Self, SettingsError> {
+        Self::load_with_defaults(Self::default(), file)
+    }
+
+    // Like `load`, falling back to `defaults` for the settings neither sets
+    pub fn load_with_defaults(defaults: Self, file: Option<&Path>) -> Result<Self, SettingsError> {
+        let settings = Self::from_env_with_defaults(defaults)?;
+        let settings = match file {
+            Some(path) => settings.merge_json(&std::fs::read_to_string(path)?)?,
+            None => settings,
+        };
+        settings.level_filter()?;
+        Ok(settings)
+    }
+
+    // Override fields with those in a (partial) JSON object
+    pub fn merge_json(&self, json: &str) -> Result<Self, SettingsError> {
+        let mut merged = serde_json::to_value(self)?;
+        let overrides: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)?;
+        if let serde_json::Value::Object(fields) = &mut merged {
+            fields.extend(overrides);
+        }
+        Ok(serde_json::from_value(merged)?)
+    }
+
+    pub fn level_filter(&self) -> Result<LevelFilter, SettingsError> {
+        self.log_level
+            .parse()
+            .map_err(|_| SettingsError::InvalidLogLevel(self.log_level.clone()))
+    }
+
+    // Region a tenant's data belongs in, if that isn't this instance's region
+    pub fn misplaced_region(&self, user_id: Uuid) -> Option<&str> {
+        let data_region = self.data_region.as_deref()?;
+        self.tenant_regions
+            .get(&user_id)
+            .map(String::as_str)
+            .filter(|region| *region != data_region)
+    }
+
+    // Weakest ciphersuite security level a tenant's groups may use; groups without a tenant
+    // get the instance-wide floor
+    pub fn ciphersuite_floor(&self, tenant_id: Option<Uuid>) -> u16 {
+        tenant_id
+            .and_then(|user_id| self.tenant_min_ciphersuite_bits.get(&user_id))
+            .copied()
+            .unwrap_or(self.min_ciphersuite_bits)
+    }
+
+    // Which group roles may perform which operations
+    pub fn role_policy(&self) -> RolePolicy {
+        RolePolicy {
+            moderator: self.moderator_permissions.clone(),
+            member: self.member_permissions.clone(),
+        }
+    }
+
+    // Janitor configuration with the reloadable policies taken from these settings
+    pub fn janitor_config(&self, interval: Duration) -> JanitorConfig {
+        JanitorConfig {
+            interval,
+            stuck_members: StuckMemberPolicy {
+                max_epochs_behind: self.stuck_member_max_epochs,
+                max_days_behind: self.stuck_member_max_days,
+                action: if self.stuck_member_auto_remove {
+                    StuckMemberAction::RequestRemoval
+                } else {
+                    StuckMemberAction::NotifyAdmins
+                },
+            },
+            purge_grace_period: chrono::Duration::days(self.purge_grace_days),
+            key_package_low_threshold: self.key_package_low_threshold,
+            retention: RetentionPolicy {
+                messages: self
+                    .message_retention_days
+                    .iter()
+                    .filter(|(_, days)| **days > 0)
+                    .map(|(message_type, days)| (*message_type, chrono::Duration::days(*days)))
+                    .collect(),
+                used_key_packages: (self.used_key_package_retention_days > 0)
+                    .then(|| chrono::Duration::days(self.used_key_package_retention_days)),
+            },
+            key_rotation: KeyRotationPolicy {
+                default_max_epoch_age: (self.key_rotation_max_epoch_age_secs > 0)
+                    .then(|| chrono::Duration::seconds(self.key_rotation_max_epoch_age_secs)),
+            },
+            welcome_sla: WelcomeSlaPolicy {
+                deadline: (self.welcome_deadline_secs > 0)
+                    .then(|| chrono::Duration::seconds(self.welcome_deadline_secs)),
+                push_retries: self.welcome_push_retries.max(0),
+            },
+            blob_upgrade_batch: self.blob_upgrade_batch,
+        }
+    }
+}
+
+// Shared handle to the current runtime settings. Consumers either read `current` when
+// they need a value or `subscribe` to be woken on every change.
+#[derive(Clone)]
+pub struct SettingsHandle {
+    sender: Arc<watch::Sender<RuntimeSettings>>,
+    file: Option<PathBuf>,
+    // What `reload` falls back to for the settings neither the environment nor the file sets
+    defaults: RuntimeSettings,
+}
+
+impl Default for SettingsHandle {
+    fn default() -> Self {
+        Self::new(RuntimeSettings::default())
+    }
+}
+
+impl SettingsHandle {
+    pub fn new(settings: RuntimeSettings) -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(settings)),
+            file: None,
+            defaults: RuntimeSettings::default(),
+        }
+    }
+
+    // Load settings from the environment and `file`, which `reload` reads again
+    pub fn load(file: Option<PathBuf>) -> Result<Self, SettingsError> {
+        Self::load_with_defaults(RuntimeSettings::default(), file)
+    }
+
+    // Like `load`, starting from `defaults` instead of the built-in ones, both now and on reload
+    pub fn load_with_defaults(
+        defaults: RuntimeSettings,
+        file: Option<PathBuf>,
+    ) -> Result<Self, SettingsError> {
+        let settings = RuntimeSettings::load_with_defaults(defaults.clone(), file.as_deref())?;
+        Ok(Self {
+            sender: Arc::new(watch::Sender::new(settings)),
+            file,
+            defaults,
+        })
+    }
+
+    pub fn current(&self) -> RuntimeSettings {
+        self.sender.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<RuntimeSettings> {
+        self.sender.subscribe()
+    }
+
+    // Replace the settings and notify subscribers if anything changed
+    pub fn set(&self, settings: RuntimeSettings) {
+        self.sender.send_if_modified(|current| {
+            let changed = *current != settings;
+            *current = settings;
+            changed
+        });
+    }
+
+    // Read the settings again. On error the current settings are kept.
+    pub fn reload(&self) -> Result<RuntimeSettings, SettingsError> {
+        let settings =
+            RuntimeSettings::load_with_defaults(self.defaults.clone(), self.file.as_deref())?;
+        self.set(settings.clone());
+        Ok(settings)
+    }
+
+    // Apply the log level now and whenever it changes
+    pub fn spawn_log_level_watcher(&self) -> tokio::task::JoinHandle<()> {
+        let mut settings = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                if let Ok(level) = settings.borrow_and_update().level_filter() {
+                    log::set_max_level(level);
+                }
+                if settings.changed().await.is_err() {
+                    return;
+                }
+            }
+        })
+    }
+
+    // Reload the settings whenever the process receives SIGHUP
+    #[cfg(unix)]
+    pub fn spawn_sighup_listener(&self) -> std::io::Result<tokio::task::JoinHandle<()>> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup())?;
+        let handle = self.clone();
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                match handle.reload() {
+                    Ok(_) => info!("Reloaded runtime settings"),
+                    Err(e) => error!("Failed to reload runtime settings: {}", e),
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,49 @@
+use chrono::{DateTime, SecondsFormat, SubsecRound, Utc};
+
+// All timestamps the service produces are UTC and truncated to microseconds,
+// the precision Postgres TIMESTAMPTZ stores, so a value read back from the
+// database compares equal to the one that was written.
+const PRECISION_DIGITS: u16 = 6;
+
+// The current time, at database precision
+pub fn now() -> DateTime<Utc> {
+    truncate(Utc::now())
+}
+
+// Drop anything finer than a microsecond (truncation, never rounding up)
+pub fn truncate(dt: DateTime<Utc>) -> DateTime<Utc> {
+    dt.trunc_subsecs(PRECISION_DIGITS)
+}
+
+// Format for API responses, e.g. `2024-05-01T12:30:00.123456Z`
+pub fn to_rfc3339(dt: DateTime<Utc>) -> String {
+    truncate(dt).to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+// Format an optional timestamp, using the empty string for None
+pub fn to_rfc3339_opt(dt: Option<DateTime<Utc>>) -> String {
+    dt.map(to_rfc3339).unwrap_or_default()
+}
+
+// Parse an RFC 3339 timestamp with any offset, normalized to UTC
+pub fn parse_rfc3339(value: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    let dt = DateTime::parse_from_rfc3339(value)?;
+    Ok(truncate(dt.with_timezone(&Utc)))
+}
+
+// Convert to a protobuf well-known Timestamp
+pub fn to_timestamp(dt: DateTime<Utc>) -> prost_types::Timestamp {
+    let dt = truncate(dt);
+    prost_types::Timestamp {
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as i32,
+    }
+}
+
+// Convert from a protobuf Timestamp, or None if it is out of range
+pub fn from_timestamp(ts: &prost_types::Timestamp) -> Option<DateTime<Utc>> {
+    if !(0..1_000_000_000).contains(&ts.nanos) {
+        return None;
+    }
+    DateTime::from_timestamp(ts.seconds, ts.nanos as u32).map(truncate)
+}
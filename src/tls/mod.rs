@@ -0,0 +1,211 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use log::{debug, error, info, warn};
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+// Handshakes taking longer are abandoned, so stalled clients can't pile up connections
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Connections done with their handshake and waiting for the server to take them
+const ACCEPT_BACKLOG: usize = 128;
+
+// Pause after failing to accept a connection, such as when out of file descriptors
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+// Errors loading the server certificate. They name the files, never their contents.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TlsError {
+    #[error("failed to read {path}: {cause}")]
+    Read { path: String, cause: String },
+
+    #[error("{path} holds no PEM certificate: {cause}")]
+    InvalidCertificate { path: String, cause: String },
+
+    #[error("{path} holds no usable PEM private key: {cause}")]
+    InvalidKey { path: String, cause: String },
+
+    #[error("the certificate in {cert} doesn't match the private key in {key}: {cause}")]
+    KeyMismatch {
+        cert: String,
+        key: String,
+        cause: String,
+    },
+}
+
+// Where the server certificate chain and its private key are read from, both PEM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    // Read and check the certificate chain and key
+    pub fn load(&self) -> Result<CertifiedKey, TlsError> {
+        let cert_path = self.cert_path.display().to_string();
+        let key_path = self.key_path.display().to_string();
+        let certs = CertificateDer::pem_slice_iter(&read(&self.cert_path)?)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())
+            .and_then(|certs| match certs.is_empty() {
+                true => Err("no certificate found".to_string()),
+                false => Ok(certs),
+            })
+            .map_err(|cause| TlsError::InvalidCertificate {
+                path: cert_path.clone(),
+                cause,
+            })?;
+        let invalid_key = |cause: String| TlsError::InvalidKey {
+            path: key_path.clone(),
+            cause,
+        };
+        let key = PrivateKeyDer::from_pem_slice(&read(&self.key_path)?)
+            .map_err(|e| invalid_key(e.to_string()))?;
+        let key = ring::sign::any_supported_type(&key).map_err(|e| invalid_key(e.to_string()))?;
+
+        let certified = CertifiedKey::new(certs, key);
+        certified.keys_match().map_err(|e| TlsError::KeyMismatch {
+            cert: cert_path,
+            key: key_path,
+            cause: e.to_string(),
+        })?;
+        Ok(certified)
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, TlsError> {
+    fs::read(path).map_err(|e| TlsError::Read {
+        path: path.display().to_string(),
+        cause: e.to_string(),
+    })
+}
+
+// The server certificate, read again by `reload` so certificates can be rotated without a
+// restart. Handshakes after a reload present the new certificate; established connections keep
+// the one they were opened with.
+#[derive(Debug)]
+pub struct ReloadableCert {
+    config: TlsConfig,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCert {
+    pub fn load(config: TlsConfig) -> Result<Self, TlsError> {
+        let current = RwLock::new(Arc::new(config.load()?));
+        Ok(Self { config, current })
+    }
+
+    // Read the certificate and key again. On error the current ones are kept.
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let certified = Arc::new(self.config.load()?);
+        *self.current.write().unwrap() = certified;
+        Ok(())
+    }
+
+    // The certificate chain and key new connections are handshaken with
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap().clone()
+    }
+
+    // Reload the certificate whenever the process receives SIGHUP
+    #[cfg(unix)]
+    pub fn spawn_sighup_listener(self: &Arc<Self>) -> std::io::Result<tokio::task::JoinHandle<()>> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup())?;
+        let cert = self.clone();
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                match cert.reload() {
+                    Ok(()) => info!(
+                        "Reloaded TLS certificate from {}",
+                        cert.config.cert_path.display()
+                    ),
+                    Err(e) => error!("Failed to reload TLS certificate: {}", e),
+                }
+            }
+        }))
+    }
+}
+
+impl ResolvesServerCert for ReloadableCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+// Accepts TCP connections and completes their TLS handshakes, for tonic to serve the ones that
+// succeed. Each handshake runs on its own task, so a slow client doesn't hold up the others.
+pub struct TlsListener {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl TlsListener {
+    // With `accept_http1`, clients may negotiate HTTP/1.1 as well as HTTP/2
+    pub fn new(listener: TcpListener, cert: Arc<ReloadableCert>, accept_http1: bool) -> Self {
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default TLS versions")
+            .with_no_client_auth()
+            .with_cert_resolver(cert);
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        if accept_http1 {
+            config.alpn_protocols.push(b"http/1.1".to_vec());
+        }
+        Self {
+            listener,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        }
+    }
+
+    // Connections ready to serve, for `Router::serve_with_incoming`. Accepting stops once the
+    // stream is dropped.
+    pub fn into_incoming(self) -> impl Stream<Item = io::Result<TlsStream<TcpStream>>> {
+        let (tx, rx) = mpsc::channel(ACCEPT_BACKLOG);
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let (stream, peer) = match self.listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept a connection: {}", e);
+                        tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                        continue;
+                    }
+                };
+                if let Err(e) = stream.set_nodelay(true) {
+                    debug!("Failed to set TCP_NODELAY for {}: {}", peer, e);
+                }
+                let acceptor = self.acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send(stream).await;
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", peer, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", peer),
+                    }
+                });
+            }
+        });
+        stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|stream| (Ok(stream), rx))
+        })
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::db::{DatabaseInterface, DbError, DbResult, Message, VerboseTrace};
+use crate::metrics::jobs::VERBOSE_TRACE_REFRESH_JOB;
+use crate::metrics::JobTracker;
+use crate::timestamps;
+
+// Log target of trace lines, so they can be routed or filtered apart from the rest of the log
+pub const TRACE_LOG_TARGET: &str = "hermetic_mls::trace";
+
+// How long a trace stays on when the admin doesn't say, and the longest it can be on for
+pub const DEFAULT_TRACE_DURATION_SECS: u64 = 60 * 60;
+pub const MAX_TRACE_DURATION_SECS: u64 = 24 * 60 * 60;
+
+// Lines each trace logs per minute unless VERBOSE_TRACE_LINES_PER_MINUTE says otherwise
+pub const DEFAULT_TRACE_LINES_PER_MINUTE: u32 = 120;
+
+// Messages a trace line lists one by one; the rest are only counted
+pub const MAX_DESCRIBED_MESSAGES: usize = 20;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+// A message as trace lines show it, e.g. "commit 5f0c… in group 9a1e… #12 at epoch 4, 512 bytes"
+pub fn describe(message: &Message) -> String {
+    let mut description = format!("{} {}", message.message_type, message.id);
+    if let Some(group_id) = message.group_id {
+        let _ = write!(description, " in group {}", group_id);
+    }
+    let _ = write!(description, " #{}", message.sequence);
+    if let Some(epoch) = message.epoch {
+        let _ = write!(description, " at epoch {}", epoch);
+    }
+    match message.payload() {
+        Some(payload) => {
+            let _ = write!(description, ", {} bytes", payload.len());
+        }
+        None => description.push_str(", payload not loaded"),
+    }
+    description
+}
+
+// The first MAX_DESCRIBED_MESSAGES messages, described, and how many more there are
+pub fn describe_all(messages: &[Message]) -> String {
+    let mut descriptions: Vec<_> = messages
+        .iter()
+        .take(MAX_DESCRIBED_MESSAGES)
+        .map(describe)
+        .collect();
+    if messages.len() > MAX_DESCRIBED_MESSAGES {
+        descriptions.push(format!(
+            "and {} more",
+            messages.len() - MAX_DESCRIBED_MESSAGES
+        ));
+    }
+    descriptions.join("; ")
+}
+
+// What one trace has logged on this instance
+struct LineBudget {
+    window_start: Instant,
+    in_window: u32,
+    // Dropped since the last logged line, reported with the next one
+    unreported: u64,
+    logged: u64,
+    dropped: u64,
+}
+
+impl LineBudget {
+    fn new() -> Self {
+        Self {
+            window_start: Instant::now(),
+            in_window: 0,
+            unreported: 0,
+            logged: 0,
+            dropped: 0,
+        }
+    }
+
+    // Take a line from the budget, returning how many were dropped since the last one, or None
+    // if the line is over the rate limit
+    fn admit(&mut self, lines_per_minute: u32) -> Option<u64> {
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= RATE_WINDOW {
+            self.window_start = now;
+            self.in_window = 0;
+        }
+        if self.in_window >= lines_per_minute {
+            self.unreported += 1;
+            self.dropped += 1;
+            return None;
+        }
+        self.in_window += 1;
+        self.logged += 1;
+        Some(std::mem::take(&mut self.unreported))
+    }
+}
+
+// In-memory snapshot of the verbose traces stored in the database. While a trace is on, the
+// service logs a line with payload sizes, epochs and sequences for each message-flow operation
+// on its group or client, at info level under TRACE_LOG_TARGET, so one customer's desync can be
+// debugged without turning on debug logging for the whole fleet. Each trace is rate-limited, and
+// stops on its own once it expires. Checking for a trace never touches the database; the
+// snapshot is updated by the admin service and refreshed periodically so traces turned on
+// through other instances are picked up.
+#[derive(Clone)]
+pub struct VerboseTracing {
+    traces: Arc<RwLock<HashMap<Uuid, VerboseTrace>>>,
+    budgets: Arc<Mutex<HashMap<Uuid, LineBudget>>>,
+    lines_per_minute: u32,
+}
+
+impl Default for VerboseTracing {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACE_LINES_PER_MINUTE)
+    }
+}
+
+impl VerboseTracing {
+    pub fn new(lines_per_minute: u32) -> Self {
+        Self {
+            traces: Arc::default(),
+            budgets: Arc::default(),
+            lines_per_minute,
+        }
+    }
+
+    // Replace the snapshot with the unexpired traces currently in the database
+    pub async fn refresh<DB: DatabaseInterface + ?Sized>(&self, db: &DB) -> DbResult<()> {
+        let traces = db.list_verbose_traces(timestamps::now()).await?;
+        let traces: HashMap<_, _> = traces.into_iter().map(|trace| (trace.id, trace)).collect();
+        self.budgets
+            .lock()
+            .unwrap()
+            .retain(|id, _| traces.contains_key(id));
+        *self.traces.write().unwrap() = traces;
+        Ok(())
+    }
+
+    // The traces that are on, oldest first
+    pub fn list(&self) -> Vec<VerboseTrace> {
+        let now = timestamps::now();
+        let mut traces: Vec<_> = self
+            .traces
+            .read()
+            .unwrap()
+            .values()
+            .filter(|trace| trace.expires_at > now)
+            .cloned()
+            .collect();
+        traces.sort_by_key(|trace| (trace.created_at, trace.id));
+        traces
+    }
+
+    pub fn insert(&self, trace: VerboseTrace) {
+        self.traces.write().unwrap().insert(trace.id, trace);
+    }
+
+    pub fn remove(&self, trace_id: Uuid) {
+        self.traces.write().unwrap().remove(&trace_id);
+        self.budgets.lock().unwrap().remove(&trace_id);
+    }
+
+    // Lines this instance logged and dropped over the rate limit for a trace
+    pub fn stats(&self, trace_id: Uuid) -> (u64, u64) {
+        self.budgets
+            .lock()
+            .unwrap()
+            .get(&trace_id)
+            .map_or((0, 0), |budget| (budget.logged, budget.dropped))
+    }
+
+    // Ids of the unexpired traces on for any of the groups or clients
+    fn matching(&self, group_ids: &[Uuid], client_ids: &[Uuid]) -> Vec<Uuid> {
+        let traces = self.traces.read().unwrap();
+        if traces.is_empty() {
+            return Vec::new();
+        }
+        let now = timestamps::now();
+        traces
+            .values()
+            .filter(|trace| trace.expires_at > now)
+            .filter(|trace| {
+                trace.group_id.is_some_and(|id| group_ids.contains(&id))
+                    || trace.client_id.is_some_and(|id| client_ids.contains(&id))
+            })
+            .map(|trace| trace.id)
+            .collect()
+    }
+
+    // Log a line about an operation on the groups by or for the clients, once for each trace on
+    // for any of them that has budget left. The line is only built if a trace is on.
+    pub fn trace(&self, group_ids: &[Uuid], client_ids: &[Uuid], line: impl FnOnce() -> String) {
+        let traces = self.matching(group_ids, client_ids);
+        if traces.is_empty() {
+            return;
+        }
+        let line = line();
+        let mut budgets = self.budgets.lock().unwrap();
+        for trace_id in traces {
+            let budget = budgets.entry(trace_id).or_insert_with(LineBudget::new);
+            match budget.admit(self.lines_per_minute) {
+                Some(0) => info!(target: TRACE_LOG_TARGET, "[trace {}] {}", trace_id, line),
+                Some(dropped) => info!(
+                    target: TRACE_LOG_TARGET,
+                    "[trace {}] {} ({} earlier lines dropped over the rate limit)",
+                    trace_id,
+                    line,
+                    dropped
+                ),
+                None => {}
+            }
+        }
+    }
+
+    // Refresh the snapshot from the database on a fixed interval, reporting each run to the
+    // job tracker
+    pub fn spawn_refresh<DB: DatabaseInterface + ?Sized + 'static>(
+        &self,
+        db: Arc<DB>,
+        every: Duration,
+        jobs: JobTracker,
+    ) -> JoinHandle<()> {
+        let tracing = self.clone();
+        jobs.register(VERBOSE_TRACE_REFRESH_JOB, every);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let refresh = async {
+                    tracing.refresh(db.as_ref()).await?;
+                    Ok::<_, DbError>(tracing.traces.read().unwrap().len() as u64)
+                };
+                if let Err(e) = jobs.track(VERBOSE_TRACE_REFRESH_JOB, refresh).await {
+                    error!("Failed to refresh verbose traces: {}", e);
+                }
+            }
+        })
+    }
+}
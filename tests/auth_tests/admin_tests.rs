@@ -0,0 +1,86 @@
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::task::{Context, Poll};
+
+use hermetic_mls::auth::admin::{require_admin, AdminAuthLayer, AdminPrincipal};
+use hermetic_mls::auth::token::AUTHORIZATION_HEADER;
+use tonic::{Code, Request};
+use tower::{Layer, Service};
+
+const ADMIN_TOKEN: &str = "correct horse battery staple";
+
+// Inner service that answers 200 if the request reached it as an operator's
+#[derive(Clone)]
+struct EchoAdmin;
+
+impl Service<http::Request<()>> for EchoAdmin {
+    type Response = http::Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<()>) -> Self::Future {
+        assert!(request.extensions().get::<AdminPrincipal>().is_some());
+        ready(Ok(http::Response::new(String::new())))
+    }
+}
+
+// Helper function to call the admin service with an optional authorization header, returning
+// the gRPC status code, or None if the request got through
+async fn call(layer: &AdminAuthLayer, authorization: Option<&str>) -> Option<String> {
+    let mut request = http::Request::post("/mls.v1.MlsAdminService/ForcePurge");
+    if let Some(value) = authorization {
+        request = request.header(AUTHORIZATION_HEADER, value);
+    }
+    let response = layer
+        .layer(EchoAdmin)
+        .call(request.body(()).unwrap())
+        .await
+        .unwrap();
+    response
+        .headers()
+        .get("grpc-status")
+        .map(|status| status.to_str().unwrap().to_string())
+}
+
+/// Only requests carrying the admin token reach the admin service
+#[tokio::test]
+async fn test_admin_token_required() {
+    let layer = AdminAuthLayer::new(Some(ADMIN_TOKEN));
+    let unauthenticated = Some((Code::Unauthenticated as i32).to_string());
+
+    let bearer = format!("Bearer {}", ADMIN_TOKEN);
+    assert_eq!(call(&layer, Some(&bearer)).await, None);
+    assert_eq!(call(&layer, None).await, unauthenticated);
+    assert_eq!(call(&layer, Some(ADMIN_TOKEN)).await, unauthenticated);
+    assert_eq!(
+        call(&layer, Some("Bearer correct horse")).await,
+        unauthenticated
+    );
+}
+
+/// Without a configured token the admin service rejects every call
+#[tokio::test]
+async fn test_unconfigured_admin_fails_closed() {
+    let unauthenticated = Some((Code::Unauthenticated as i32).to_string());
+    for layer in [AdminAuthLayer::new(None), AdminAuthLayer::new(Some(""))] {
+        assert!(!layer.is_configured());
+        assert_eq!(call(&layer, None).await, unauthenticated);
+        assert_eq!(call(&layer, Some("Bearer ")).await, unauthenticated);
+        assert_eq!(call(&layer, Some("Bearer anything")).await, unauthenticated);
+    }
+}
+
+/// Handlers can require an operator themselves
+#[test]
+fn test_require_admin() {
+    let status = require_admin(&Request::new(())).unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let mut request = Request::new(());
+    request.extensions_mut().insert(AdminPrincipal);
+    assert!(require_admin(&request).is_ok());
+}
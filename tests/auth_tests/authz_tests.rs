@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use hermetic_mls::{
+    auth::{token::AuthenticatedUser, AuthenticatedClient},
+    fixtures::{GroupFixture, KeyPackageFixture, MembershipFixture},
+    service::{
+        mls::{
+            mls_delivery_service_server::MlsDeliveryService, FetchMessagesRequest,
+            GetClientRequest, GetGroupRequest, GetKeyPackageRequest, GetMembershipRequest,
+            GetRatchetTreeRequest, ListKeyPackagesRequest, ListMembershipHistoryRequest,
+            ListMembershipsRequest, ListPendingProposalsRequest, RegisterClientRequest,
+        },
+        MLSServiceImpl,
+    },
+};
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+// Helper function to register a client for the user
+async fn register(service: &MLSServiceImpl<MockDatabase>, user_id: Uuid) -> Uuid {
+    let client_id = service
+        .register_client(Request::new(RegisterClientRequest {
+            user_id: user_id.to_string(),
+            identity: "alice".to_string(),
+            device_name: "phone".to_string(),
+            signature_key: Vec::new(),
+            attestation: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .client_id;
+    Uuid::parse_str(&client_id).unwrap()
+}
+
+// Helper function to attach an authenticated identity to a request
+fn as_caller<T, E: Clone + Send + Sync + 'static>(message: T, identity: E) -> Request<T> {
+    let mut request = Request::new(message);
+    request.extensions_mut().insert(identity);
+    request
+}
+
+/// Authenticated clients only read their own messages and key packages, and the clients of
+/// their own user
+#[tokio::test]
+async fn test_clients_access_own_resources() {
+    let service = MLSServiceImpl::new(Arc::new(MockDatabase::new()));
+    let user_id = Uuid::new_v4();
+    let phone = register(&service, user_id).await;
+    let laptop = register(&service, user_id).await;
+    let stranger = register(&service, Uuid::new_v4()).await;
+    let caller = AuthenticatedClient(phone);
+
+    let fetch = |client_id: Uuid| FetchMessagesRequest {
+        client_id: client_id.to_string(),
+        ..Default::default()
+    };
+    let list_key_packages = |client_id: Uuid| ListKeyPackagesRequest {
+        client_id: client_id.to_string(),
+        ..Default::default()
+    };
+    let get_client = |client_id: Uuid| GetClientRequest {
+        client_id: client_id.to_string(),
+        ..Default::default()
+    };
+
+    service
+        .fetch_messages(as_caller(fetch(phone), caller))
+        .await
+        .unwrap();
+    service
+        .list_key_packages(as_caller(list_key_packages(phone), caller))
+        .await
+        .unwrap();
+    for client_id in [phone, laptop] {
+        service
+            .get_client(as_caller(get_client(client_id), caller))
+            .await
+            .unwrap();
+    }
+
+    for client_id in [laptop, stranger] {
+        let status = service
+            .fetch_messages(as_caller(fetch(client_id), caller))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        let status = service
+            .list_key_packages(as_caller(list_key_packages(client_id), caller))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+    let status = service
+        .get_client(as_caller(get_client(stranger), caller))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    // Unauthenticated requests are left to the authentication modes
+    service
+        .fetch_messages(Request::new(fetch(stranger)))
+        .await
+        .unwrap();
+}
+
+/// A bearer token's user can access all of its own clients, and no one else's
+#[tokio::test]
+async fn test_token_users_access_own_clients() {
+    let service = MLSServiceImpl::new(Arc::new(MockDatabase::new()));
+    let user_id = Uuid::new_v4();
+    let phone = register(&service, user_id).await;
+    let stranger = register(&service, Uuid::new_v4()).await;
+    let caller = AuthenticatedUser(user_id);
+
+    let fetch = |client_id: Uuid| FetchMessagesRequest {
+        client_id: client_id.to_string(),
+        ..Default::default()
+    };
+    service
+        .fetch_messages(as_caller(fetch(phone), caller))
+        .await
+        .unwrap();
+    let status = service
+        .fetch_messages(as_caller(fetch(stranger), caller))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = service
+        .get_client(as_caller(
+            GetClientRequest {
+                client_id: stranger.to_string(),
+                ..Default::default()
+            },
+            caller,
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    // Unknown clients are still reported as such
+    let status = service
+        .get_client(as_caller(
+            GetClientRequest {
+                client_id: Uuid::new_v4().to_string(),
+                ..Default::default()
+            },
+            caller,
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+/// Group reads are limited to the group's members, whether they authenticate as a client or
+/// with a bearer token, and key packages and membership history to their own client
+#[tokio::test]
+async fn test_group_reads_require_membership() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let user_id = Uuid::new_v4();
+    let member = register(&service, user_id).await;
+    let stranger_user = Uuid::new_v4();
+    let stranger = register(&service, stranger_user).await;
+    let group = GroupFixture::new()
+        .with_creator(member)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    let membership = MembershipFixture::new(group.id, member)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    let key_package = KeyPackageFixture::new(member)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let group_id = group.id.to_string();
+    let get_group = || GetGroupRequest {
+        group_id: group_id.clone(),
+    };
+    let list_memberships = || ListMembershipsRequest {
+        group_id: group_id.clone(),
+    };
+    let get_membership = || GetMembershipRequest {
+        membership_id: membership.id.to_string(),
+    };
+    let list_pending_proposals = || ListPendingProposalsRequest {
+        group_id: group_id.clone(),
+    };
+    let get_key_package = || GetKeyPackageRequest {
+        key_package_id: key_package.id.to_string(),
+        ..Default::default()
+    };
+    let history = |client_id: Uuid| ListMembershipHistoryRequest {
+        client_id: client_id.to_string(),
+        ..Default::default()
+    };
+
+    for (allowed, caller) in [
+        (true, AuthenticatedClient(member)),
+        (false, AuthenticatedClient(stranger)),
+    ] {
+        let results = [
+            service
+                .get_group(as_caller(get_group(), caller))
+                .await
+                .err(),
+            service
+                .list_memberships(as_caller(list_memberships(), caller))
+                .await
+                .err(),
+            service
+                .get_membership(as_caller(get_membership(), caller))
+                .await
+                .err(),
+            service
+                .list_pending_proposals(as_caller(list_pending_proposals(), caller))
+                .await
+                .err(),
+            service
+                .get_key_package(as_caller(get_key_package(), caller))
+                .await
+                .err(),
+            service
+                .list_membership_history(as_caller(history(member), caller))
+                .await
+                .err(),
+        ];
+        for status in results {
+            assert_eq!(
+                status.map(|status| status.code()),
+                (!allowed).then_some(Code::PermissionDenied)
+            );
+        }
+    }
+
+    for (allowed, caller) in [
+        (true, AuthenticatedUser(user_id)),
+        (false, AuthenticatedUser(stranger_user)),
+    ] {
+        let status = service
+            .get_group(as_caller(get_group(), caller))
+            .await
+            .err();
+        assert_eq!(
+            status.map(|status| status.code()),
+            (!allowed).then_some(Code::PermissionDenied)
+        );
+    }
+
+    // The ratchet tree is fetched as the authenticated client, not whichever client the request
+    // names
+    let status = service
+        .get_ratchet_tree(as_caller(
+            GetRatchetTreeRequest {
+                group_id: group_id.clone(),
+                client_id: member.to_string(),
+                epoch: 0,
+            },
+            AuthenticatedClient(stranger),
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
@@ -0,0 +1,235 @@
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::Utc;
+use hermetic_mls::{
+    auth::{
+        auth_digest, AuthConfig, AuthMode, AuthenticatedClient, ClientAuthLayer, ClientKeys,
+        CLIENT_HEADER, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+    },
+    db::{DatabaseInterface, EntityKind},
+    metrics::JobTracker,
+    service::{
+        mls::{mls_delivery_service_server::MlsDeliveryService, RegisterClientRequest},
+        MLSServiceImpl,
+    },
+};
+use openmls::prelude::SignatureScheme;
+use openmls_basic_credential::SignatureKeyPair;
+use openmls_traits::signatures::Signer;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::Request;
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+const AUTHENTICATED_HEADER: &str = "x-authenticated-client";
+
+// Inner service that answers with the client the request was authenticated as, if any
+#[derive(Clone)]
+struct EchoClient;
+
+impl Service<http::Request<()>> for EchoClient {
+    type Response = http::Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<()>) -> Self::Future {
+        let mut response = http::Response::new(String::new());
+        if let Some(client) = request.extensions().get::<AuthenticatedClient>() {
+            response
+                .headers_mut()
+                .insert(AUTHENTICATED_HEADER, client.0.to_string().parse().unwrap());
+        }
+        ready(Ok(response))
+    }
+}
+
+// Helper function to build an authenticated request for a path
+fn authenticated(
+    path: &str,
+    client_id: Uuid,
+    key: &SignatureKeyPair,
+    timestamp: i64,
+    nonce: &str,
+) -> http::Request<()> {
+    let signature = key
+        .sign(&auth_digest(path, client_id, timestamp, nonce))
+        .unwrap();
+    let mut metadata = MetadataMap::new();
+    metadata.insert(CLIENT_HEADER, client_id.to_string().parse().unwrap());
+    metadata.insert(TIMESTAMP_HEADER, timestamp.to_string().parse().unwrap());
+    metadata.insert(NONCE_HEADER, nonce.parse().unwrap());
+    metadata.insert_bin(SIGNATURE_HEADER, MetadataValue::from_bytes(&signature));
+
+    let mut request = http::Request::builder().uri(path).body(()).unwrap();
+    *request.headers_mut() = metadata.into_headers();
+    request
+}
+
+/// Required mode rejects unauthenticated delivery RPCs except RegisterClient and GetServerInfo,
+/// and attaches the verified client to authenticated ones
+#[tokio::test]
+async fn test_auth_interceptor() {
+    let key = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    let other_key = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    let client_id = Uuid::new_v4();
+    let keys = ClientKeys::new();
+    keys.insert(client_id, key.public().to_vec());
+
+    let config = AuthConfig {
+        mode: AuthMode::Required,
+        ..AuthConfig::default()
+    };
+    let db = Arc::new(MockDatabase::new());
+    let mut service = ClientAuthLayer::new(&config, keys, db).layer(EchoClient);
+    let unauthenticated = |path: &str| http::Request::builder().uri(path).body(()).unwrap();
+    let grpc_status = |response: &http::Response<_>| {
+        response
+            .headers()
+            .get("grpc-status")
+            .map(|status| status.to_str().unwrap().to_string())
+    };
+    let denied = Some((tonic::Code::Unauthenticated as i32).to_string());
+    let fetch = "/mls.v1.MlsDeliveryService/FetchMessages";
+    let now = Utc::now().timestamp();
+
+    let response = service.call(unauthenticated(fetch)).await.unwrap();
+    assert_eq!(grpc_status(&response), denied);
+    for path in [
+        "/mls.v1.MlsDeliveryService/RegisterClient",
+        "/mls.v1.MlsDeliveryService/GetServerInfo",
+        "/mls.v1.MlsAdminService/ListAllClients",
+    ] {
+        let response = service.call(unauthenticated(path)).await.unwrap();
+        assert_eq!(grpc_status(&response), None);
+        assert!(response.headers().get(AUTHENTICATED_HEADER).is_none());
+    }
+
+    let response = service
+        .call(authenticated(fetch, client_id, &key, now, "n-1"))
+        .await
+        .unwrap();
+    assert_eq!(grpc_status(&response), None);
+    assert_eq!(
+        response.headers().get(AUTHENTICATED_HEADER).unwrap(),
+        &client_id.to_string()
+    );
+
+    // Replays, signatures for another RPC, other keys, unknown clients and stale timestamps are
+    // rejected
+    let mut signed_for_fetch = authenticated(fetch, client_id, &key, now, "n-3");
+    *signed_for_fetch.uri_mut() = "/mls.v1.MlsDeliveryService/RemoveMember".parse().unwrap();
+    for request in [
+        authenticated(fetch, client_id, &key, now, "n-1"),
+        signed_for_fetch,
+        authenticated(fetch, client_id, &other_key, now, "n-2"),
+        authenticated(fetch, Uuid::new_v4(), &key, now, "n-2"),
+        authenticated(fetch, client_id, &key, now - 3600, "n-2"),
+    ] {
+        let response = service.call(request).await.unwrap();
+        assert_eq!(grpc_status(&response), denied);
+    }
+}
+
+/// Nonces are recorded in the database, so a request accepted by one instance can't be replayed
+/// against another
+#[tokio::test]
+async fn test_nonces_are_shared_between_instances() {
+    let key = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    let client_id = Uuid::new_v4();
+    let keys = ClientKeys::new();
+    keys.insert(client_id, key.public().to_vec());
+
+    let config = AuthConfig {
+        mode: AuthMode::Required,
+        ..AuthConfig::default()
+    };
+    let db = Arc::new(MockDatabase::new());
+    let mut first = ClientAuthLayer::new(&config, keys.clone(), db.clone()).layer(EchoClient);
+    let mut second = ClientAuthLayer::new(&config, keys, db.clone()).layer(EchoClient);
+    let fetch = "/mls.v1.MlsDeliveryService/FetchMessages";
+    let now = Utc::now().timestamp();
+
+    let response = first
+        .call(authenticated(fetch, client_id, &key, now, "n-1"))
+        .await
+        .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+    let response = second
+        .call(authenticated(fetch, client_id, &key, now, "n-1"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get("grpc-status").unwrap(),
+        &(tonic::Code::Unauthenticated as i32).to_string()
+    );
+
+    // Request signatures keep their own nonces
+    assert!(db
+        .record_request_nonce(client_id, "n-1", Utc::now())
+        .await
+        .unwrap());
+}
+
+/// The key snapshot follows registrations and deletions made through the service
+#[tokio::test]
+async fn test_client_keys_follow_events() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let keys = ClientKeys::load(db.as_ref()).await.unwrap();
+    assert!(keys.is_empty());
+    keys.spawn_sync(
+        db.clone(),
+        service.events(),
+        Duration::from_secs(3600),
+        JobTracker::new(),
+    );
+
+    let key = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    let client_id = service
+        .register_client(Request::new(RegisterClientRequest {
+            user_id: Uuid::new_v4().to_string(),
+            identity: "alice".to_string(),
+            device_name: "phone".to_string(),
+            signature_key: key.public().to_vec(),
+            attestation: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .client_id;
+    let client_id = Uuid::parse_str(&client_id).unwrap();
+
+    let wait_for = |present: bool| {
+        let keys = keys.clone();
+        async move {
+            for _ in 0..100 {
+                if keys.get(client_id).is_some() == present {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("key snapshot did not catch up");
+        }
+    };
+    wait_for(true).await;
+    assert_eq!(keys.get(client_id).unwrap(), key.public());
+
+    db.soft_delete(EntityKind::Client, client_id).await.unwrap();
+    service
+        .events()
+        .publish(hermetic_mls::events::DomainEvent::EntityDeleted {
+            kind: EntityKind::Client,
+            id: client_id,
+        });
+    wait_for(false).await;
+}
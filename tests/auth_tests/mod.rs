@@ -0,0 +1,4 @@
+pub mod admin_tests;
+pub mod authz_tests;
+pub mod interceptor_tests;
+pub mod token_tests;
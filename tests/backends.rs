@@ -0,0 +1,141 @@
+use std::env;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use futures_util::FutureExt;
+use hermetic_mls::db::memory::MemoryDatabase;
+use hermetic_mls::db::{DbNamespace, PostgresDatabase};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Executor, PgPool};
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt};
+
+/// The base tables, which the migrations extend
+const SCHEMA: &str = include_str!("../schema.sql");
+
+/// Postgres image the container backend starts
+const POSTGRES_TAG: &str = "16-alpine";
+
+/// Run a handler test case against every backend. Each case is an async fn generic over the
+/// database, taking it as an `Arc<DB>`; the macro turns it into a module of the same name with
+/// one test per backend:
+///
+/// - `memory`: the in-memory database the other tests use (exported to them as `MockDatabase`)
+/// - `postgres`: Postgres, in a fresh schema that is dropped afterwards. The server is the one
+///   TEST_DATABASE_URL points at, or a container started for the test if TEST_POSTGRES_CONTAINER
+///   is set. It's ignored by default, since it needs one of them; run it with `--ignored`, and
+///   it fails if neither is set.
+///
+/// A case that passes on one backend and fails on another has found a difference in how the
+/// backends filter rows, which the service sees as different RPC behavior.
+#[macro_export]
+macro_rules! backend_tests {
+    ($($case:ident),+ $(,)?) => {
+        $(
+            mod $case {
+                #[tokio::test]
+                async fn memory() {
+                    $crate::backends::run_memory(super::$case).await;
+                }
+
+                #[tokio::test]
+                #[ignore = "needs TEST_DATABASE_URL or TEST_POSTGRES_CONTAINER"]
+                async fn postgres() {
+                    $crate::backends::run_postgres(super::$case).await;
+                }
+            }
+        )+
+    };
+}
+
+pub async fn run_memory<F, Fut>(case: F)
+where
+    F: FnOnce(Arc<MemoryDatabase>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    case(Arc::new(MemoryDatabase::new())).await;
+}
+
+pub async fn run_postgres<F, Fut>(case: F)
+where
+    F: FnOnce(Arc<PostgresDatabase>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let server = PostgresServer::start()
+        .await
+        .expect("Set TEST_DATABASE_URL or TEST_POSTGRES_CONTAINER to run the Postgres tests");
+
+    // A schema of its own, so tests sharing a server don't see each other's rows
+    let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+    let namespace = DbNamespace::new(Some(schema.clone()), "").unwrap();
+    let pool = PgPoolOptions::new()
+        .max_connections(4)
+        .connect_with(namespace.connect_options(server.options.clone()))
+        .await
+        .expect("Could not connect to the test database");
+    create_schema(&pool, &schema).await;
+    let db = PostgresDatabase::new(pool.clone()).with_namespace(namespace);
+    db.run_migrations()
+        .await
+        .expect("Failed to run database migrations");
+
+    // Drop the schema even if the case fails, then fail the test with its panic
+    let outcome = AssertUnwindSafe(case(Arc::new(db))).catch_unwind().await;
+    pool.execute(format!("DROP SCHEMA {} CASCADE", schema).as_str())
+        .await
+        .expect("Failed to drop the test schema");
+    pool.close().await;
+    if let Err(panic) = outcome {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+/// Create the schema and its base tables, as setup_db.sh does for a deployment
+async fn create_schema(pool: &PgPool, schema: &str) {
+    pool.execute(format!("CREATE SCHEMA {}", schema).as_str())
+        .await
+        .expect("Failed to create the test schema");
+    // Tests creating the extension at the same time would race, and no table needs it
+    let tables = SCHEMA.replace("CREATE EXTENSION IF NOT EXISTS \"uuid-ossp\";", "");
+    pool.execute(tables.as_str())
+        .await
+        .expect("Failed to create the base tables");
+}
+
+/// Where the Postgres tests connect, keeping the container they started, if any, running
+struct PostgresServer {
+    options: PgConnectOptions,
+    _container: Option<ContainerAsync<Postgres>>,
+}
+
+impl PostgresServer {
+    async fn start() -> Option<Self> {
+        if let Ok(url) = env::var("TEST_DATABASE_URL") {
+            return Some(Self {
+                options: url
+                    .parse()
+                    .expect("TEST_DATABASE_URL is not a Postgres URL"),
+                _container: None,
+            });
+        }
+        env::var_os("TEST_POSTGRES_CONTAINER")?;
+
+        let container = Postgres::default()
+            .with_tag(POSTGRES_TAG)
+            .start()
+            .await
+            .expect("Could not start a Postgres container");
+        let options = PgConnectOptions::new()
+            .host(&container.get_host().await.unwrap().to_string())
+            .port(container.get_host_port_ipv4(5432).await.unwrap())
+            .username("postgres")
+            .password("postgres")
+            .database("postgres");
+        Some(Self {
+            options,
+            _container: Some(container),
+        })
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use clap::Parser;
+use hermetic_mls::cli::{self, AdminCommand, Cli, Command};
+use hermetic_mls::db::{DatabaseInterface, DbError, EntityKind, MessageType};
+use hermetic_mls::fixtures::{ClientFixture, GroupFixture, MembershipFixture, MessageFixture};
+use hermetic_mls::service::MLSServiceImpl;
+use tonic::Code;
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+// Helper function to set up a group with a member and two messages of 100 payload bytes
+async fn setup_group(db: &MockDatabase) -> Uuid {
+    let client = ClientFixture::new().insert(db).await.unwrap();
+    let group = GroupFixture::new()
+        .with_creator(client.id)
+        .insert(db)
+        .await
+        .unwrap();
+    MembershipFixture::new(group.id, client.id)
+        .insert(db)
+        .await
+        .unwrap();
+    for message_type in [MessageType::Commit, MessageType::Application] {
+        MessageFixture::new(message_type)
+            .in_group(group.id)
+            .sent_by(client.id)
+            .with_payload(vec![0; 100])
+            .insert(db)
+            .await
+            .unwrap();
+    }
+    group.id
+}
+
+/// Without a subcommand the binary serves, as it did before it had subcommands
+#[test]
+fn test_parse_commands() {
+    let cli = Cli::try_parse_from(["hermetic-mls"]).unwrap();
+    assert_eq!(cli.command(), Command::Serve);
+    assert!(!cli.dev);
+
+    // --dev is accepted before or after the subcommand
+    let cli = Cli::try_parse_from(["hermetic-mls", "serve", "--dev"]).unwrap();
+    assert_eq!(cli.command(), Command::Serve);
+    assert!(cli.dev);
+    let cli = Cli::try_parse_from(["hermetic-mls", "--dev"]).unwrap();
+    assert!(cli.dev);
+
+    let cli = Cli::try_parse_from(["hermetic-mls", "migrate"]).unwrap();
+    assert_eq!(cli.command(), Command::Migrate);
+
+    let cli = Cli::try_parse_from(["hermetic-mls", "import", "export.json"]).unwrap();
+    assert_eq!(
+        cli.command(),
+        Command::Import {
+            path: "export.json".into()
+        }
+    );
+
+    let group_id = Uuid::new_v4();
+    let cli = Cli::try_parse_from([
+        "hermetic-mls",
+        "admin",
+        "purge-group",
+        &group_id.to_string(),
+        "--dry-run",
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.command(),
+        Command::Admin(AdminCommand::PurgeGroup {
+            group_id,
+            dry_run: true
+        })
+    );
+
+    let cli = Cli::try_parse_from(["hermetic-mls", "admin", "stats"]).unwrap();
+    assert_eq!(
+        cli.command(),
+        Command::Admin(AdminCommand::Stats {
+            group: None,
+            limit: 20
+        })
+    );
+}
+
+/// Malformed command lines are rejected before anything starts
+#[test]
+fn test_parse_errors() {
+    for args in [
+        vec!["hermetic-mls", "admin", "purge-group", "not-a-uuid"],
+        vec!["hermetic-mls", "admin", "purge-group"],
+        vec!["hermetic-mls", "admin", "stats", "--limit", "-1"],
+        vec!["hermetic-mls", "import"],
+        vec!["hermetic-mls", "vacuum"],
+    ] {
+        assert!(Cli::try_parse_from(&args).is_err(), "{:?}", args);
+    }
+}
+
+/// purge-group only purges soft-deleted groups, previewing the purge on a dry run
+#[tokio::test]
+async fn test_admin_purge_group() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let group_id = setup_group(&db).await;
+    let purge = |dry_run| AdminCommand::PurgeGroup { group_id, dry_run };
+
+    // A live group is left alone, and the error says why
+    let error = cli::admin::run(&service, purge(false)).await.unwrap_err();
+    assert_eq!(error.code(), Code::NotFound);
+    assert!(
+        error.message().contains("soft-deleted"),
+        "{}",
+        error.message()
+    );
+    assert!(db.get_group(group_id).await.is_ok());
+
+    db.soft_delete(EntityKind::Group, group_id).await.unwrap();
+    let report = cli::admin::run(&service, purge(true)).await.unwrap();
+    assert!(report.starts_with(&format!("Purging group {} would delete:", group_id)));
+    assert!(report.contains("messages"), "{}", report);
+
+    let report = cli::admin::run(&service, purge(false)).await.unwrap();
+    assert_eq!(report, format!("Purged group {}", group_id));
+    assert!(matches!(
+        db.get_group(group_id).await,
+        Err(DbError::NotFound)
+    ));
+}
+
+/// stats prints a row per group and the totals
+#[tokio::test]
+async fn test_admin_stats() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let first = setup_group(&db).await;
+    let second = setup_group(&db).await;
+
+    let report = cli::admin::run(
+        &service,
+        AdminCommand::Stats {
+            group: None,
+            limit: 20,
+        },
+    )
+    .await
+    .unwrap();
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", report);
+    assert!(lines[0].starts_with("GROUP"));
+    assert!(lines[3].starts_with("TOTAL (2 groups)"));
+    assert!(lines[3].contains(" 400 "), "{}", report);
+
+    let report = cli::admin::run(
+        &service,
+        AdminCommand::Stats {
+            group: Some(second),
+            limit: 20,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(report.contains(&second.to_string()));
+    assert!(!report.contains(&first.to_string()));
+
+    let error = cli::admin::run(
+        &service,
+        AdminCommand::Stats {
+            group: Some(Uuid::new_v4()),
+            limit: 20,
+        },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.code(), Code::NotFound);
+}
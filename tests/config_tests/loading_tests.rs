@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use hermetic_mls::attestation::AttestationFormat;
+use hermetic_mls::auth::AuthMode;
+use hermetic_mls::config::{Config, ConfigError};
+use hermetic_mls::db::MessageType;
+use hermetic_mls::events::publisher::PublisherConfig;
+use hermetic_mls::secrets::SecretResolver;
+use hermetic_mls::service::probe::ReadinessProbe;
+use hermetic_mls::service::validation::ValidationPolicy;
+use hermetic_mls::settings::cors::AllowedOrigins;
+use hermetic_mls::settings::SettingsHandle;
+
+// Helper function to write a config file to a unique temporary path
+fn write_config_file(contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("config-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+// Helper function to load a config from a file and the given environment variables
+fn load(file: Option<&str>, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+    let env: HashMap<String, String> = env
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    let path = file.map(write_config_file);
+    let config = Config::from_sources(path.as_deref(), |name| env.get(name).cloned());
+    if let Some(path) = path {
+        std::fs::remove_file(path).unwrap();
+    }
+    config
+}
+
+// Helper function to get the problems of an invalid config
+fn problems(result: Result<Config, ConfigError>) -> Vec<String> {
+    match result {
+        Err(ConfigError::Invalid(problems)) => problems,
+        other => panic!("expected an invalid config, got {:?}", other),
+    }
+}
+
+/// Without a file or variables, the config holds the defaults the server always had
+#[test]
+fn test_defaults() {
+    let config = load(None, &[]).unwrap();
+    assert_eq!(config, Config::default());
+    assert_eq!(config.server.addr.to_string(), "0.0.0.0:50051");
+    assert_eq!(config.server.admin_addr.to_string(), "127.0.0.1:50052");
+    assert!(config.server.admin_token.is_none());
+    assert_eq!(config.server.readiness_probe, ReadinessProbe::Shallow);
+    assert_eq!(config.validation, ValidationPolicy::Strict);
+    assert!(config.database.run_migrations);
+    assert!(config.database.encryption_key.is_none());
+    assert!(config.server.legacy_api);
+    assert!(config.tls.tls_config().is_none());
+
+    let pools = config.database.pool_config();
+    assert_eq!(pools.interactive_connections, 5);
+    assert_eq!(pools.background_connections, 2);
+    assert_eq!(pools.acquire_timeout, Duration::from_secs(30));
+    assert_eq!(
+        config.cors.cors_config().unwrap().origins,
+        AllowedOrigins::Any
+    );
+    assert_eq!(
+        config.slo.slo_config().unwrap().slos[0].to_string(),
+        "FetchMessages p99<100ms"
+    );
+}
+
+/// Values in the file are used, and environment variables override them
+#[test]
+fn test_environment_overrides_file() {
+    let file = r#"
+        validation = "unchecked"
+
+        [server]
+        addr = "127.0.0.1:6000"
+        readiness_probe = "deep"
+
+        [database]
+        url = "env:MLS_DATABASE_URL"
+        schema = "tenant_a"
+        pool_size = 20
+
+        [cors]
+        allowed_origins = ["https://app.example.com", "https://admin.example.com"]
+
+        [limits]
+        membership_cache_size = 50
+    "#;
+    let config = load(
+        Some(file),
+        &[
+            ("DATABASE_POOL_SIZE", "40"),
+            ("MEMBERSHIP_CACHE_SIZE", "0"),
+            ("TABLE_PREFIX", "mls_"),
+            ("TLS_CERT_PATH", "/etc/tls/cert.pem"),
+            ("TLS_KEY_PATH", "/etc/tls/key.pem"),
+            // Blank variables count as unset
+            ("ADDR", ""),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(config.validation, ValidationPolicy::Unchecked);
+    assert_eq!(config.server.addr.to_string(), "127.0.0.1:6000");
+    assert_eq!(config.server.readiness_probe, ReadinessProbe::Deep);
+    assert_eq!(config.database.pool_size, 40);
+    assert_eq!(config.limits.membership_cache_size, 0);
+    let namespace = config.database.namespace().unwrap();
+    assert_eq!(namespace.schema(), Some("tenant_a"));
+    assert_eq!(namespace.table_prefix(), "mls_");
+    assert_eq!(
+        config.tls.tls_config().unwrap().cert_path.to_str(),
+        Some("/etc/tls/cert.pem")
+    );
+    match config.cors.cors_config().unwrap().origins {
+        AllowedOrigins::List(origins) => assert_eq!(origins.len(), 2),
+        other => panic!("expected listed origins, got {:?}", other),
+    }
+
+    // The URL is a secret reference, resolved when used and never shown in Debug output
+    std::env::set_var("MLS_DATABASE_URL", "postgres://mls:hunter2@db/mls");
+    let url = config.database.url.as_ref().unwrap();
+    assert_eq!(
+        url.resolve("DATABASE_URL", &SecretResolver::new()).unwrap(),
+        "postgres://mls:hunter2@db/mls"
+    );
+    assert!(!format!("{:?}", config).contains("MLS_DATABASE_URL"));
+}
+
+/// Every invalid value is reported at once, naming its variable and config file key
+#[test]
+fn test_invalid_values_reported_together() {
+    let file = r#"
+        [jobs]
+        janitor_interval_secs = 0
+
+        [retention]
+        purge_grace_days = -1
+        message_retention_days = { commit = 0 }
+    "#;
+    let problems = problems(load(
+        Some(file),
+        &[
+            ("ADDR", "localhost"),
+            ("DATABASE_POOL_SIZE", "ten"),
+            ("RUN_MIGRATIONS", "no"),
+            ("ID_GENERATOR", "sequential"),
+            ("TABLE_PREFIX", "Bad-Prefix"),
+            ("TLS_CERT_PATH", "/etc/tls/cert.pem"),
+            ("CORS_ALLOWED_ORIGINS", "app.example.com"),
+            ("LATENCY_SLOS", "FetchMessages fast"),
+        ],
+    ));
+
+    for expected in [
+        "ADDR (server.addr): invalid value \"localhost\"",
+        "DATABASE_POOL_SIZE (database.pool_size): invalid value \"ten\"",
+        "RUN_MIGRATIONS (database.run_migrations): invalid value \"no\"",
+        "ID_GENERATOR (server.id_generator)",
+        "DATABASE_SCHEMA or TABLE_PREFIX (database)",
+        "TLS_CERT_PATH and TLS_KEY_PATH (tls.cert_path, tls.key_path): must be set together",
+        "CORS_ALLOWED_ORIGINS (cors.allowed_origins)",
+        "LATENCY_SLOS (slo.latency_slos)",
+        "JANITOR_INTERVAL_SECS (jobs.janitor_interval_secs): must be at least 1 second",
+        "retention.purge_grace_days: must not be negative",
+        "retention.message_retention_days.commit",
+    ] {
+        assert!(
+            problems.iter().any(|problem| problem.starts_with(expected)),
+            "{:?} not in {:#?}",
+            expected,
+            problems
+        );
+    }
+    assert_eq!(problems.len(), 11);
+}
+
+/// Authentication, attestation, cache priming and event publishing come from the file and
+/// the environment like the rest of the config
+#[test]
+fn test_auth_attestation_priming_and_events() {
+    let file = r#"
+        [auth]
+        client_auth = "required"
+        token_auth = "optional"
+        jwt_issuer = "https://idp.example.com"
+        jwt_audience = "mls"
+
+        [attestation]
+        app_attest_url = "https://attest.example.com/app-attest"
+
+        [cache_priming]
+        enabled = true
+        max_groups = 500
+    "#;
+    let config = load(
+        Some(file),
+        &[
+            ("JWT_JWKS_URL", "https://idp.example.com/jwks.json"),
+            ("CLIENT_KEY_REFRESH_SECS", "10"),
+            ("CACHE_PRIMING_CONCURRENCY", "8"),
+        ],
+    )
+    .unwrap();
+
+    let auth = config.auth.auth_config();
+    assert_eq!(auth.mode, AuthMode::Required);
+    assert_eq!(auth.key_refresh_interval, Duration::from_secs(10));
+    let token_auth = config.auth.token_auth_config();
+    assert_eq!(token_auth.mode, AuthMode::Optional);
+    assert_eq!(
+        token_auth.jwks_url.as_deref(),
+        Some("https://idp.example.com/jwks.json")
+    );
+    assert_eq!(token_auth.user_id_claim, "sub");
+
+    assert_eq!(
+        config.attestation.verifiers().formats(),
+        vec![AttestationFormat::AppAttest]
+    );
+
+    let priming = config.cache_priming.priming_config().unwrap();
+    assert_eq!(priming.max_groups, 500);
+    assert_eq!(priming.concurrency, 8);
+    assert!(Config::default().cache_priming.priming_config().is_none());
+
+    // Nothing is published without EVENT_PUBLISHER
+    assert_eq!(config.events.publisher_config(), Ok(None));
+    if cfg!(feature = "nats") {
+        let config = load(None, &[("EVENT_PUBLISHER", "nats")]).unwrap();
+        assert!(matches!(
+            config.events.publisher_config(),
+            Ok(Some(PublisherConfig::Nats { .. }))
+        ));
+    }
+}
+
+/// A typo in the authentication, attestation, priming or publisher settings stops the server
+/// instead of turning the feature off
+#[test]
+fn test_invalid_auth_attestation_priming_and_events() {
+    let problems = problems(load(
+        None,
+        &[
+            ("CLIENT_AUTH", "requried"),
+            ("TOKEN_AUTH", "required"),
+            ("JWT_ISSUER", "https://idp.example.com"),
+            ("JWT_JWKS_URL", "idp.example.com/jwks.json"),
+            ("CLIENT_KEY_REFRESH_SECS", "0"),
+            ("ATTESTATION_CUSTOM_URL", "not a url"),
+            ("CACHE_PRIMING", "yes"),
+            ("CACHE_PRIMING_CONCURRENCY", "0"),
+            ("EVENT_PUBLISHER", "carrier-pigeon"),
+        ],
+    ));
+
+    for expected in [
+        "CLIENT_AUTH (auth.client_auth): invalid value \"requried\"",
+        "JWT_AUDIENCE (auth.jwt_audience): must be set while TOKEN_AUTH is on",
+        "JWT_JWKS_URL (auth.jwt_jwks_url): invalid URL",
+        "CLIENT_KEY_REFRESH_SECS (auth.client_key_refresh_secs): must be at least 1 second",
+        "ATTESTATION_CUSTOM_URL (attestation.custom_url): invalid URL",
+        "CACHE_PRIMING (cache_priming.enabled): invalid value \"yes\"",
+        "CACHE_PRIMING_CONCURRENCY (cache_priming.concurrency): must be at least 1",
+        "EVENT_PUBLISHER (events.publisher): unknown event publisher",
+    ] {
+        assert!(
+            problems.iter().any(|problem| problem.starts_with(expected)),
+            "{:?} not in {:#?}",
+            expected,
+            problems
+        );
+    }
+    assert_eq!(problems.len(), 8);
+}
+
+/// The admin service can't share the delivery service's address
+#[test]
+fn test_admin_addr_must_differ() {
+    let problems = problems(load(
+        None,
+        &[("ADDR", "0.0.0.0:7000"), ("ADMIN_ADDR", "0.0.0.0:7000")],
+    ));
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].starts_with("ADMIN_ADDR (server.admin_addr): must differ from ADDR"));
+
+    let config = load(None, &[("ADMIN_ADDR", "10.0.0.5:7001")]).unwrap();
+    assert_eq!(config.server.admin_addr.to_string(), "10.0.0.5:7001");
+}
+
+/// Unknown keys and mistyped values in the file are rejected with their location
+#[test]
+fn test_invalid_file() {
+    let error = load(Some("[database]\npool_sise = 10\n"), &[]).unwrap_err();
+    assert!(matches!(error, ConfigError::Parse { .. }));
+    assert!(error.to_string().contains("pool_sise"), "{}", error);
+
+    let error = load(Some("[server]\nlegacy_api = \"yes\"\n"), &[]).unwrap_err();
+    assert!(matches!(error, ConfigError::Parse { .. }));
+
+    let missing = std::env::temp_dir().join(format!("missing-{}.toml", uuid::Uuid::new_v4()));
+    assert!(matches!(
+        Config::from_sources(Some(&missing), |_| None),
+        Err(ConfigError::Io { .. })
+    ));
+}
+
+/// The retention and limits in the file are the defaults of the runtime settings, which the
+/// settings file can still override
+#[test]
+fn test_retention_defaults_runtime_settings() {
+    let config = Config::from_toml(
+        r#"
+        [retention]
+        purge_grace_days = 7
+        message_retention_days = { welcome = 14 }
+
+        [limits]
+        max_queued_application_messages = 500
+    "#,
+    )
+    .unwrap();
+    let defaults = config.settings_defaults();
+    assert_eq!(defaults.purge_grace_days, 7);
+    assert_eq!(defaults.message_retention_days[&MessageType::Welcome], 14);
+    assert_eq!(defaults.max_queued_application_messages, 500);
+
+    let path = std::env::temp_dir().join(format!("settings-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&path, r#"{"purge_grace_days": 3}"#).unwrap();
+    let handle = SettingsHandle::load_with_defaults(defaults, Some(path.clone())).unwrap();
+    assert_eq!(handle.current().purge_grace_days, 3);
+    assert_eq!(handle.current().max_queued_application_messages, 500);
+
+    // Reloading falls back to the same defaults
+    std::fs::write(&path, "{}").unwrap();
+    let reloaded = handle.reload().unwrap();
+    assert_eq!(reloaded.purge_grace_days, 7);
+    std::fs::remove_file(path).unwrap();
+}
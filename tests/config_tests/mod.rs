@@ -0,0 +1 @@
+pub mod loading_tests;
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use base64::Engine;
+use chrono::Utc;
+use hermetic_mls::db::{
+    sealed_tenant, DatabaseInterface, DbError, MasterKey, PostgresDatabase, StorageEncryption,
+    TenantKey,
+};
+use hermetic_mls::fixtures::{ClientFixture, GroupFixture, MembershipFixture, MessageFixture};
+use uuid::Uuid;
+
+fn master_key(byte: u8) -> MasterKey {
+    MasterKey::parse(&base64::engine::general_purpose::STANDARD.encode([byte; 32])).unwrap()
+}
+
+/// Master keys are 32 base64-encoded bytes, and never show up in debug output
+#[test]
+fn test_master_key_parsing() {
+    let encoded = base64::engine::general_purpose::STANDARD.encode([7u8; 32]);
+    let key = MasterKey::parse(&format!(" {}\n", encoded)).unwrap();
+    assert!(!format!("{:?}", key).contains(&encoded));
+
+    let short = base64::engine::general_purpose::STANDARD.encode([7u8; 16]);
+    let err = MasterKey::parse(&short).unwrap_err();
+    assert!(err.to_string().contains("32 bytes"));
+    assert!(MasterKey::parse("not base64!").is_err());
+}
+
+/// A tenant's blobs open only with the key derived for that tenant, from the same master key
+/// and salt
+#[test]
+fn test_tenant_keys_are_separate() {
+    let tenant = Uuid::new_v4();
+    let key = TenantKey::derive(&master_key(1), tenant, &[1; 32]);
+    let sealed = key.seal(b"group state").unwrap();
+    assert_eq!(sealed_tenant(&sealed), Some(tenant));
+    assert!(!sealed.windows(11).any(|window| window == b"group state"));
+    assert_eq!(key.open(&sealed).unwrap(), b"group state");
+
+    // The same key seals the same payload differently each time
+    assert_ne!(key.seal(b"group state").unwrap(), sealed);
+
+    // Another tenant's key, another salt, or another master key can't open it
+    let other_tenant = TenantKey::derive(&master_key(1), Uuid::new_v4(), &[1; 32]);
+    assert!(other_tenant.open(&sealed).is_err());
+    let other_salt = TenantKey::derive(&master_key(1), tenant, &[2; 32]);
+    assert!(matches!(
+        other_salt.open(&sealed),
+        Err(DbError::SerializationError(_))
+    ));
+    let other_master = TenantKey::derive(&master_key(2), tenant, &[1; 32]);
+    assert!(other_master.open(&sealed).is_err());
+
+    // Nor can a blob that was tampered with or cut short
+    let mut tampered = sealed.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(key.open(&tampered).is_err());
+    assert!(key.open(&sealed[..20]).is_err());
+}
+
+/// Tenants' group states and message payloads read back through their key until it's destroyed;
+/// afterwards they can't be read or written and are left out of listings and fetches, while other
+/// tenants' groups and groups without a tenant are unaffected
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL or TEST_POSTGRES_CONTAINER"]
+async fn test_destroyed_tenant_key_on_postgres() {
+    crate::backends::run_postgres(|db: Arc<PostgresDatabase>| async move {
+        let encryption = Arc::new(StorageEncryption::new(master_key(3)));
+        let db = Arc::try_unwrap(db)
+            .expect("The test case holds the only handle")
+            .with_storage_encryption(encryption);
+
+        let offboarded = ClientFixture::new().insert(&db).await.unwrap();
+        let staying = ClientFixture::new().insert(&db).await.unwrap();
+        let group = GroupFixture::new()
+            .with_creator(offboarded.id)
+            .with_handle(offboarded.user_id, "offboarded")
+            .with_state(Some(vec![1, 2, 3]))
+            .insert(&db)
+            .await
+            .unwrap();
+        let other_tenant = GroupFixture::new()
+            .with_creator(staying.id)
+            .with_handle(staying.user_id, "staying")
+            .with_state(Some(vec![4, 5, 6]))
+            .insert(&db)
+            .await
+            .unwrap();
+        let untenanted = GroupFixture::new()
+            .with_creator(staying.id)
+            .with_state(Some(vec![7, 8, 9]))
+            .insert(&db)
+            .await
+            .unwrap();
+
+        for (group_id, client_id) in [
+            (group.id, offboarded.id),
+            (group.id, staying.id),
+            (other_tenant.id, staying.id),
+        ] {
+            MembershipFixture::new(group_id, client_id)
+                .insert(&db)
+                .await
+                .unwrap();
+        }
+        MessageFixture::application(group.id)
+            .with_payload(vec![10, 11])
+            .insert(&db)
+            .await
+            .unwrap();
+        MessageFixture::application(other_tenant.id)
+            .with_payload(vec![15])
+            .insert(&db)
+            .await
+            .unwrap();
+        let messages = db
+            .fetch_messages_for_client(offboarded.id, Some(group.id), true, &[], true)
+            .await
+            .unwrap();
+        assert_eq!(messages[0].application, Some(vec![10, 11]));
+
+        db.update_group_state(group.id, vec![12, 13]).await.unwrap();
+        assert_eq!(
+            db.get_group(group.id).await.unwrap().state,
+            Some(vec![12, 13])
+        );
+        assert!(db.find_integrity_issues(100).await.unwrap().is_empty());
+
+        assert!(db.destroy_tenant_key(offboarded.user_id).await.unwrap());
+        assert!(matches!(
+            db.get_group(group.id).await,
+            Err(DbError::TenantKeyDestroyed(tenant)) if tenant == offboarded.user_id
+        ));
+        assert!(db
+            .fetch_messages_for_client(offboarded.id, Some(group.id), true, &[], true)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            db.update_group_state(group.id, vec![14]).await,
+            Err(DbError::TenantKeyDestroyed(_))
+        ));
+
+        assert_eq!(
+            db.get_group(other_tenant.id).await.unwrap().state,
+            Some(vec![4, 5, 6])
+        );
+        assert_eq!(
+            db.get_group(untenanted.id).await.unwrap().state,
+            Some(vec![7, 8, 9])
+        );
+
+        // A client in both tenants' groups still lists and fetches the other tenant's
+        let groups = db.list_groups_by_client(staying.id, true).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].id, other_tenant.id);
+        let messages = db
+            .fetch_messages_for_client(staying.id, None, true, &[], true)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].application, Some(vec![15]));
+
+        // Destroying a key twice, or the key of a tenant that never stored anything, is fine,
+        // and nothing can be stored for that tenant afterwards
+        assert!(db.destroy_tenant_key(offboarded.user_id).await.unwrap());
+        let unseen = Uuid::new_v4();
+        assert!(db.destroy_tenant_key(unseen).await.unwrap());
+        assert!(matches!(
+            GroupFixture::new()
+                .with_creator(staying.id)
+                .with_handle(unseen, "too-late")
+                .insert(&db)
+                .await,
+            Err(DbError::TenantKeyDestroyed(_))
+        ));
+    })
+    .await;
+}
+
+/// Storage stats count payload bytes only, leaving out the version byte of every stored blob
+/// and the tenant id, nonce and tag of encrypted ones
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL or TEST_POSTGRES_CONTAINER"]
+async fn test_storage_stats_exclude_blob_overhead_on_postgres() {
+    crate::backends::run_postgres(|db: Arc<PostgresDatabase>| async move {
+        let encryption = Arc::new(StorageEncryption::new(master_key(4)));
+        let db = Arc::try_unwrap(db)
+            .expect("The test case holds the only handle")
+            .with_storage_encryption(encryption);
+
+        let client = ClientFixture::new().insert(&db).await.unwrap();
+        let encrypted = GroupFixture::new()
+            .with_creator(client.id)
+            .with_handle(client.user_id, "encrypted")
+            .insert(&db)
+            .await
+            .unwrap();
+        let plain = GroupFixture::new()
+            .with_creator(client.id)
+            .insert(&db)
+            .await
+            .unwrap();
+        for group_id in [encrypted.id, plain.id] {
+            MessageFixture::application(group_id)
+                .with_payload(vec![1; 10])
+                .insert(&db)
+                .await
+                .unwrap();
+        }
+
+        for group_id in [encrypted.id, plain.id] {
+            let stats = db
+                .group_storage_stats(Some(group_id), Utc::now(), 10)
+                .await
+                .unwrap();
+            assert_eq!(stats[0].messages, 1);
+            assert_eq!(stats[0].payload_bytes, 10);
+        }
+    })
+    .await;
+}
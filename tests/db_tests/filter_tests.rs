@@ -0,0 +1,96 @@
+use chrono::{TimeZone, Utc};
+use hermetic_mls::db::{
+    AttestationVerdict, Client, CredentialScheme, Filter, FilterOp, FilterValue,
+    CLIENT_FILTER_FIELDS, GROUP_FILTER_FIELDS,
+};
+use uuid::Uuid;
+
+/// Filters become allowlisted columns compared with numbered parameters
+#[test]
+fn test_filter_to_sql() {
+    let filter = Filter::parse(
+        r#"is_service=true AND device_name != "Alice's phone"  deleted_at=null created_at>=2024-01-01T00:00:00Z"#,
+        CLIENT_FILTER_FIELDS,
+    )
+    .unwrap();
+    assert_eq!(
+        filter.to_sql(1),
+        "is_service = $1 AND device_name <> $2 AND deleted_at IS NULL AND created_at >= $3"
+    );
+    assert_eq!(filter.param_count(), 3);
+    assert_eq!(
+        filter.terms[1].value,
+        FilterValue::Text("Alice's phone".to_string())
+    );
+
+    // The empty filter matches everything
+    let filter = Filter::parse("  ", CLIENT_FILTER_FIELDS).unwrap();
+    assert_eq!(filter.to_sql(1), "TRUE");
+    assert_eq!(filter.param_count(), 0);
+}
+
+/// Anything outside the grammar or the allowlist is rejected rather than reaching SQL
+#[test]
+fn test_filter_rejects_invalid_input() {
+    for input in [
+        "credential=abc",
+        "id=1; DROP TABLE clients",
+        "is_service=yes",
+        "is_service>true",
+        "epoch=1",
+        "user_id=not-a-uuid",
+        "created_at>yesterday",
+        "deleted_at>null",
+        "last_seen=null",
+        "is_service=true OR is_service=false",
+        "is_service=true AND",
+        "device_name=\"unterminated",
+        "device_name",
+        "=true",
+    ] {
+        assert!(
+            Filter::parse(input, CLIENT_FILTER_FIELDS).is_err(),
+            "{}",
+            input
+        );
+    }
+    assert!(Filter::parse("epoch>=3 and is_active=false", GROUP_FILTER_FIELDS).is_ok());
+}
+
+/// Filters evaluated in memory agree with their SQL meaning
+#[test]
+fn test_filter_matches() {
+    let client = Client {
+        id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        credential: vec![],
+        scheme: CredentialScheme::Basic,
+        device_name: "phone".to_string(),
+        last_seen: Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        created_at: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        init_key: None,
+        signature_key: None,
+        is_service: true,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
+    };
+    let matches = |input: &str| {
+        Filter::parse(input, CLIENT_FILTER_FIELDS)
+            .unwrap()
+            .matches(&client)
+    };
+
+    assert!(matches(""));
+    assert!(matches("is_service=true device_name=phone"));
+    assert!(!matches("is_service=true device_name=laptop"));
+    assert!(matches("created_at<2024-06-01T00:00:00Z"));
+    assert!(matches("last_seen<=2024-06-01T00:00:00Z"));
+    assert!(!matches("last_seen>2024-06-01T00:00:00Z"));
+    assert!(matches("deleted_at=null"));
+    assert!(!matches("deleted_at!=null"));
+    // Like SQL, comparisons with a missing value match nothing
+    assert!(!matches("deleted_at<2030-01-01T00:00:00Z"));
+
+    let filter = Filter::default().and("id", FilterOp::Eq, FilterValue::Uuid(client.id));
+    assert!(filter.matches(&client));
+}
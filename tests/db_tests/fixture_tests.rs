@@ -0,0 +1,88 @@
+use hermetic_mls::db::{DatabaseInterface, MembershipRole, MessageType};
+use hermetic_mls::fixtures::{
+    ClientFixture, GroupFixture, KeyPackageFixture, MembershipFixture, MessageFixture,
+};
+use hermetic_mls::mls_codec::MlsCrypto;
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Fixtures insert rows the database reads back unchanged
+#[tokio::test]
+async fn test_fixtures_round_trip() {
+    let db = MockDatabase::new();
+    let user_id = Uuid::new_v4();
+    let client = ClientFixture::new()
+        .with_user(user_id)
+        .with_device_name("laptop")
+        .insert(&db)
+        .await
+        .unwrap();
+    assert_eq!(client.user_id, user_id);
+    assert_eq!(
+        db.get_client(client.id).await.unwrap().device_name,
+        "laptop"
+    );
+
+    let group = GroupFixture::new()
+        .with_creator(client.id)
+        .with_epoch(3)
+        .insert(&db)
+        .await
+        .unwrap();
+    assert_eq!(db.get_group(group.id).await.unwrap().epoch, 3);
+
+    let membership = MembershipFixture::new(group.id, client.id)
+        .with_role(MembershipRole::Admin)
+        .insert(&db)
+        .await
+        .unwrap();
+    let stored = db.get_membership(membership.id).await.unwrap();
+    assert_eq!(stored.role, MembershipRole::Admin);
+    assert!(stored.removed_at.is_none());
+
+    let key_package = KeyPackageFixture::new(client.id).insert(&db).await.unwrap();
+    assert_eq!(
+        db.get_key_package(key_package.id).await.unwrap().data,
+        key_package.data
+    );
+}
+
+/// Messages carry their payload in the column of their type, and are stored with its hash
+#[tokio::test]
+async fn test_message_fixture_payloads() {
+    let db = MockDatabase::new();
+    let group_id = Uuid::new_v4();
+
+    let commit = MessageFixture::commit(group_id, 4)
+        .with_payload(vec![9, 9])
+        .build();
+    assert_eq!(commit.message_type, MessageType::Commit);
+    assert_eq!(commit.commit, Some(vec![9, 9]));
+    assert_eq!(commit.application, None);
+    assert_eq!(commit.epoch, Some(4));
+
+    let recipient = Uuid::new_v4();
+    let welcome = MessageFixture::welcome(group_id, vec![recipient])
+        .insert(&db)
+        .await
+        .unwrap();
+    assert!(welcome.welcome.is_some());
+    assert_eq!(welcome.recipients, Some(vec![recipient]));
+    assert!(welcome.payload_hash.is_some());
+
+    let proposal = MessageFixture::new(MessageType::Proposal).build();
+    assert!(proposal.proposal.is_some());
+    assert!(proposal.proposal_type.is_some());
+    assert_eq!(proposal.group_id, None);
+}
+
+/// Key packages are real ones, which the service's validation accepts
+#[test]
+fn test_key_package_fixture_is_valid() {
+    let key_package = KeyPackageFixture::for_identity(Uuid::new_v4(), b"alice").build();
+    assert!(!key_package.used);
+    MlsCrypto::default()
+        .validate_key_package(&key_package.data)
+        .unwrap();
+}
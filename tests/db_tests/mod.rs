@@ -0,0 +1,8 @@
+pub mod blob_tests;
+pub mod encryption_tests;
+pub mod filter_tests;
+pub mod fixture_tests;
+pub mod namespace_tests;
+pub mod notify_tests;
+pub mod pool_tests;
+pub mod priming_tests;
@@ -0,0 +1,47 @@
+use hermetic_mls::db::DbNamespace;
+
+/// Table placeholders are replaced with prefixed names, leaving the rest of the query alone
+#[test]
+fn test_prefixed_table_names() {
+    let namespace = DbNamespace::new(Some("mls_staging".to_string()), "ds_").unwrap();
+    assert_eq!(namespace.schema(), Some("mls_staging"));
+    assert_eq!(namespace.table("messages"), "ds_messages");
+    assert_eq!(
+        namespace.sql(
+            "SELECT m.* FROM {messages} m JOIN {memberships} mem ON m.group_id = mem.group_id"
+        ),
+        "SELECT m.* FROM ds_messages m JOIN ds_memberships mem ON m.group_id = mem.group_id"
+    );
+
+    // The default namespace uses the bare names
+    let namespace = DbNamespace::default();
+    assert_eq!(namespace.schema(), None);
+    assert_eq!(
+        namespace.sql("UPDATE {client_backups} SET version = $1"),
+        "UPDATE client_backups SET version = $1"
+    );
+}
+
+/// Only plain lowercase identifiers are accepted, since they end up in SQL text
+#[test]
+fn test_rejects_invalid_identifiers() {
+    for schema in ["", "Staging", "1st", "mls;drop", "mls staging"] {
+        assert!(DbNamespace::new(Some(schema.to_string()), "").is_err());
+    }
+    for prefix in ["DS_", "ds-", "1_", "ds.", "\"ds\""] {
+        assert!(DbNamespace::new(None, prefix).is_err());
+    }
+    assert!(DbNamespace::new(None, "").is_ok());
+}
+
+/// NOTIFY channels are qualified by the schema and prefix, so namespaces don't hear each other
+#[test]
+fn test_channel_names() {
+    let namespace = DbNamespace::new(Some("mls_staging".to_string()), "ds_").unwrap();
+    assert_eq!(namespace.channel("messages"), "mls_staging.ds_messages");
+    assert_eq!(
+        DbNamespace::new(None, "ds_").unwrap().channel("messages"),
+        "ds_messages"
+    );
+    assert_eq!(DbNamespace::default().channel("messages"), "messages");
+}
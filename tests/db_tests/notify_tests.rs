@@ -0,0 +1,31 @@
+use hermetic_mls::db::{DbError, MessageNotification};
+use uuid::Uuid;
+
+/// Message notifications round-trip through their NOTIFY payload, and malformed ones are
+/// rejected
+#[test]
+fn test_message_notification_payload() {
+    let notification = MessageNotification {
+        origin: Uuid::new_v4(),
+        message_id: Uuid::new_v4(),
+    };
+    let payload = notification.to_string();
+    // Far below the 8000 byte NOTIFY limit
+    assert_eq!(payload.len(), 73);
+    assert_eq!(
+        payload.parse::<MessageNotification>().unwrap(),
+        notification
+    );
+
+    for payload in [
+        "",
+        "not-a-uuid",
+        &notification.origin.to_string(),
+        &format!("{} not-a-uuid", notification.origin),
+    ] {
+        assert!(matches!(
+            payload.parse::<MessageNotification>(),
+            Err(DbError::SerializationError(_))
+        ));
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use hermetic_mls::db::{PoolConfig, Workload};
+
+/// Each workload's pool is sized independently, with the shared limits applied to both
+#[test]
+fn test_pool_options_per_workload() {
+    let config = PoolConfig {
+        interactive_connections: 20,
+        background_connections: 3,
+        acquire_timeout: Duration::from_secs(5),
+        max_lifetime: Some(Duration::from_secs(600)),
+    };
+
+    let interactive = config.pool_options(Workload::Interactive);
+    assert_eq!(interactive.get_max_connections(), 20);
+    assert_eq!(interactive.get_acquire_timeout(), Duration::from_secs(5));
+    assert_eq!(
+        interactive.get_max_lifetime(),
+        Some(Duration::from_secs(600))
+    );
+    assert_eq!(
+        config
+            .pool_options(Workload::Background)
+            .get_max_connections(),
+        3
+    );
+
+    // A pool always gets at least one connection
+    let config = PoolConfig {
+        background_connections: 0,
+        ..PoolConfig::default()
+    };
+    assert_eq!(config.connections(Workload::Background), 1);
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use hermetic_mls::db::{CachePrimer, DatabaseInterface, EntityKind, PrimingConfig};
+use hermetic_mls::fixtures::{GroupFixture, MembershipFixture, MessageFixture};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Create a group with the given number of members, one of which has left
+async fn setup_group(db: &MockDatabase, members: usize) -> Uuid {
+    let group = GroupFixture::new().insert(db).await.unwrap();
+    for _ in 0..members {
+        MembershipFixture::new(group.id, Uuid::new_v4())
+            .insert(db)
+            .await
+            .unwrap();
+    }
+    MembershipFixture::new(group.id, Uuid::new_v4())
+        .removed()
+        .insert(db)
+        .await
+        .unwrap();
+    group.id
+}
+
+/// Store an application message in the group, created `age` ago
+async fn store(db: &MockDatabase, group_id: Uuid, age: chrono::Duration) {
+    MessageFixture::application(group_id)
+        .created_at(Utc::now() - age)
+        .insert(db)
+        .await
+        .unwrap();
+}
+
+/// Priming loads the groups active within the window, most recent first, with their active
+/// memberships and without their state
+#[tokio::test]
+async fn test_priming_loads_active_groups() {
+    let db = Arc::new(MockDatabase::new());
+    let recent = setup_group(&db, 3).await;
+    store(&db, recent, chrono::Duration::minutes(5)).await;
+    let busiest = setup_group(&db, 2).await;
+    store(&db, busiest, chrono::Duration::hours(2)).await;
+    store(&db, busiest, chrono::Duration::seconds(10)).await;
+    let idle = setup_group(&db, 2).await;
+    store(&db, idle, chrono::Duration::days(3)).await;
+    setup_group(&db, 2).await;
+
+    let config = PrimingConfig {
+        window: Duration::from_secs(24 * 60 * 60),
+        concurrency: 2,
+        ..PrimingConfig::default()
+    };
+    let primed = CachePrimer::new(db.clone(), config.clone())
+        .run()
+        .await
+        .unwrap();
+    let ids: Vec<Uuid> = primed.groups.iter().map(|g| g.id).collect();
+    assert_eq!(ids, [busiest, recent]);
+    assert!(primed.groups.iter().all(|g| g.state.is_none()));
+    assert_eq!(primed.memberships[&recent].len(), 3);
+    assert_eq!(primed.memberships[&busiest].len(), 2);
+    assert_eq!(primed.membership_count(), 5);
+
+    // At most max_groups, the most recently active kept
+    let primed = CachePrimer::new(
+        db,
+        PrimingConfig {
+            max_groups: 1,
+            ..config
+        },
+    )
+    .run()
+    .await
+    .unwrap();
+    let ids: Vec<Uuid> = primed.groups.iter().map(|g| g.id).collect();
+    assert_eq!(ids, [busiest]);
+    assert!(!primed.memberships.contains_key(&recent));
+}
+
+/// Deleted groups aren't primed
+#[tokio::test]
+async fn test_priming_skips_deleted_groups() {
+    let db = Arc::new(MockDatabase::new());
+    let group_id = setup_group(&db, 1).await;
+    store(&db, group_id, chrono::Duration::minutes(1)).await;
+    db.soft_delete(EntityKind::Group, group_id).await.unwrap();
+
+    let primed = CachePrimer::new(db, PrimingConfig::default())
+        .run()
+        .await
+        .unwrap();
+    assert!(primed.groups.is_empty());
+    assert!(primed.memberships.is_empty());
+}
@@ -0,0 +1,184 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use hermetic_mls::db::{Message, MessageType};
+use hermetic_mls::events::publisher::{EventForwarder, EventPublisher, ExternalEvent, PublishError};
+use hermetic_mls::events::{DomainEvent, EventBus};
+use serde_json::json;
+use uuid::Uuid;
+
+// Publisher keeping what it was given, failing while `fail` is set
+#[derive(Default)]
+struct RecordingPublisher {
+    events: Mutex<Vec<ExternalEvent>>,
+    fail: AtomicBool,
+}
+
+#[async_trait]
+impl EventPublisher for RecordingPublisher {
+    async fn publish(&self, event: &ExternalEvent) -> Result<(), PublishError> {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(PublishError::Publish("bus unavailable".to_string()));
+        }
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+impl RecordingPublisher {
+    // Wait for the forwarder to have published `count` events
+    async fn published(&self, count: usize) -> Vec<ExternalEvent> {
+        for _ in 0..100 {
+            let events = self.events.lock().unwrap().clone();
+            if events.len() >= count {
+                return events;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("expected {} published events", count);
+    }
+}
+
+fn message(group_id: Uuid, message_type: MessageType) -> Arc<Message> {
+    Arc::new(Message {
+        id: Uuid::new_v4(),
+        group_id: Some(group_id),
+        sender_id: Uuid::new_v4(),
+        created_at: Utc::now(),
+        read: false,
+        message_type,
+        proposal: None,
+        commit: (message_type == MessageType::Commit).then(|| vec![1]),
+        welcome: (message_type == MessageType::Welcome).then(|| vec![2]),
+        system: None,
+        application: (message_type == MessageType::Application).then(|| vec![3]),
+        proposal_type: None,
+        epoch: Some(4),
+        recipients: None,
+        extra: None,
+        payload_hash: Some(vec![0xab, 0x01]),
+        expires_at: None,
+        deleted_at: None,
+        sequence: 7,
+    })
+}
+
+/// Handshake messages and membership changes this instance stored are forwarded, in order
+#[tokio::test]
+async fn test_forwards_handshake_and_membership_events() {
+    let events = EventBus::default();
+    let publisher = Arc::new(RecordingPublisher::default());
+    EventForwarder::new(publisher.clone(), events.clone()).spawn();
+
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    let commit = message(group_id, MessageType::Commit);
+    events.publish(DomainEvent::MessageStored(commit.clone()));
+    // Application messages, notices, relayed messages and other events aren't published
+    events.publish(DomainEvent::MessageStored(message(
+        group_id,
+        MessageType::Application,
+    )));
+    events.publish(DomainEvent::MessageStored(message(
+        group_id,
+        MessageType::System,
+    )));
+    events.publish(DomainEvent::MessageRelayed(message(
+        group_id,
+        MessageType::Commit,
+    )));
+    events.publish(DomainEvent::GroupCreated {
+        group_id,
+        creator_id: client_id,
+    });
+    events.publish(DomainEvent::MemberAdded {
+        membership_id: Uuid::new_v4(),
+        group_id,
+        client_id,
+    });
+    let welcome = message(group_id, MessageType::Welcome);
+    events.publish(DomainEvent::MessageStored(welcome.clone()));
+
+    let published = publisher.published(3).await;
+    let kinds: Vec<_> = published.iter().map(ExternalEvent::kind).collect();
+    assert_eq!(kinds, ["commit", "member_added", "welcome"]);
+    assert!(published.iter().all(|event| event.group_id() == group_id));
+    match &published[0] {
+        ExternalEvent::Commit(stored) => assert_eq!(stored.message_id, commit.id),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    // Events that fail to publish are dropped without stopping the forwarder
+    publisher.fail.store(true, Ordering::SeqCst);
+    events.publish(DomainEvent::MessageStored(message(
+        group_id,
+        MessageType::Proposal,
+    )));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    publisher.fail.store(false, Ordering::SeqCst);
+    events.publish(DomainEvent::MemberRemoved {
+        membership_id: Uuid::new_v4(),
+        group_id,
+        client_id,
+    });
+    let published = publisher.published(4).await;
+    assert_eq!(published.len(), 4);
+    assert_eq!(published[3].kind(), "member_removed");
+}
+
+/// Events are published as JSON metadata, without the payload
+#[test]
+fn test_external_event_json() {
+    let group_id = Uuid::new_v4();
+    let mut welcome = Message::clone(&message(group_id, MessageType::Welcome));
+    let recipient = Uuid::new_v4();
+    welcome.recipients = Some(vec![recipient]);
+
+    let event =
+        ExternalEvent::from_domain(&DomainEvent::MessageStored(Arc::new(welcome.clone()))).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&event.to_json()).unwrap();
+    assert_eq!(
+        json,
+        json!({
+            "type": "welcome",
+            "message_id": welcome.id,
+            "group_id": group_id,
+            "sender_id": welcome.sender_id,
+            "epoch": 4,
+            "sequence": 7,
+            "created_at": welcome.created_at,
+            "payload_hash": "ab01",
+            "recipients": [recipient],
+        })
+    );
+
+    let membership_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    let event = ExternalEvent::from_domain(&DomainEvent::MemberRemoved {
+        membership_id,
+        group_id,
+        client_id,
+    })
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&event.to_json()).unwrap();
+    assert_eq!(
+        json,
+        json!({
+            "type": "member_removed",
+            "membership_id": membership_id,
+            "group_id": group_id,
+            "client_id": client_id,
+        })
+    );
+
+    // Notices outside any group aren't published
+    let mut notice = Message::clone(&message(group_id, MessageType::Commit));
+    notice.group_id = None;
+    assert_eq!(
+        ExternalEvent::from_domain(&DomainEvent::MessageStored(Arc::new(notice))),
+        None
+    );
+}
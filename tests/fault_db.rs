@@ -0,0 +1,954 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hermetic_mls::db::{
+    AbuseReport, AffectedRows, Client, ClientBackup, DatabaseInterface, DbError, DbResult,
+    DeliveryCursor, EntityKind, FeatureFlag, Filter, Group, GroupInfo, GroupStorageStats,
+    IntegrityIssue, KeyPackage, KeyPackageClaim, KeyPackageCounts, KeyPackageInventory, Membership,
+    Message, MessageType, OverdueWelcome, QueueTruncation, QueuedProposal, RatchetTree,
+    StaleEpochGroup, StuckMembership, VerboseTrace,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use uuid::Uuid;
+
+/// A failure injected into one database call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The call fails before reaching the database, like a refused connection
+    Error,
+    /// The connection drops after the database applied the call, so the caller sees an error
+    /// for a write that happened
+    Drop,
+}
+
+/// Random faults applied to every call
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    /// Extra latency per call, chosen uniformly between the bounds
+    pub latency: Option<(Duration, Duration)>,
+    /// Probability that a call fails with `Fault::Error`
+    pub error_rate: f64,
+    /// Probability that a call fails with `Fault::Drop`
+    pub drop_rate: f64,
+}
+
+/// A database decorator that injects latency, transient errors, and dropped connections, to
+/// test how the service behaves when calls fail partway through a request.
+///
+/// Random faults come from a seeded generator, so a failing run can be reproduced. Faults can
+/// also be scripted for the next calls of a named operation (the `DatabaseInterface` method
+/// name), which is how tests target one step of a multi-step request.
+pub struct FaultInjectingDatabase<DB: DatabaseInterface> {
+    inner: Arc<DB>,
+    config: Mutex<FaultConfig>,
+    rng: Mutex<StdRng>,
+    scripted: Mutex<HashMap<&'static str, VecDeque<Fault>>>,
+    calls: Mutex<Vec<&'static str>>,
+}
+
+impl<DB: DatabaseInterface> FaultInjectingDatabase<DB> {
+    pub fn new(inner: Arc<DB>, seed: u64) -> Self {
+        Self {
+            inner,
+            config: Mutex::new(FaultConfig::default()),
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+            scripted: Mutex::new(HashMap::new()),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_config(self, config: FaultConfig) -> Self {
+        self.set_config(config);
+        self
+    }
+
+    /// Change the random faults, e.g. to turn them off once the chaotic part of a test is done
+    pub fn set_config(&self, config: FaultConfig) {
+        *self.config.lock().unwrap() = config;
+    }
+
+    /// Fail the next call of an operation, on top of any random faults
+    pub fn fail_next(&self, operation: &'static str, fault: Fault) {
+        self.scripted
+            .lock()
+            .unwrap()
+            .entry(operation)
+            .or_default()
+            .push_back(fault);
+    }
+
+    /// Operations called so far, in order
+    pub fn calls(&self) -> Vec<&'static str> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The wrapped database, to inspect what was actually stored
+    pub fn inner(&self) -> &DB {
+        &self.inner
+    }
+
+    // Decide the fault, if any, for a call
+    fn next_fault(&self, operation: &'static str) -> (Option<Fault>, Option<Duration>) {
+        self.calls.lock().unwrap().push(operation);
+
+        let scripted = self
+            .scripted
+            .lock()
+            .unwrap()
+            .get_mut(operation)
+            .and_then(VecDeque::pop_front);
+
+        let config = self.config.lock().unwrap().clone();
+        let mut rng = self.rng.lock().unwrap();
+        let latency = config.latency.map(|(min, max)| rng.gen_range(min..=max));
+        let fault = scripted.or_else(|| {
+            let roll: f64 = rng.gen();
+            if roll < config.error_rate {
+                Some(Fault::Error)
+            } else if roll < config.error_rate + config.drop_rate {
+                Some(Fault::Drop)
+            } else {
+                None
+            }
+        });
+        (fault, latency)
+    }
+
+    // Run a call against the wrapped database, injecting any fault
+    async fn inject<T>(
+        &self,
+        operation: &'static str,
+        call: impl Future<Output = DbResult<T>>,
+    ) -> DbResult<T> {
+        let (fault, latency) = self.next_fault(operation);
+        if let Some(latency) = latency {
+            tokio::time::sleep(latency).await;
+        }
+
+        match fault {
+            None => call.await,
+            Some(Fault::Error) => Err(DbError::ConnectionError(format!(
+                "injected error in {}",
+                operation
+            ))),
+            Some(Fault::Drop) => {
+                // The outcome is lost with the connection
+                let _ = call.await;
+                Err(DbError::ConnectionError(format!(
+                    "injected connection drop in {}",
+                    operation
+                )))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<DB: DatabaseInterface> DatabaseInterface for FaultInjectingDatabase<DB> {
+    // Client operations
+    async fn register_client(&self, client: Client) -> DbResult<()> {
+        self.inject("register_client", self.inner.register_client(client))
+            .await
+    }
+
+    async fn get_client(&self, client_id: Uuid) -> DbResult<Client> {
+        self.inject("get_client", self.inner.get_client(client_id))
+            .await
+    }
+
+    async fn get_clients(&self, client_ids: &[Uuid]) -> DbResult<Vec<Client>> {
+        self.inject("get_clients", self.inner.get_clients(client_ids))
+            .await
+    }
+
+    async fn list_clients_by_user(
+        &self,
+        user_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<Client>> {
+        self.inject(
+            "list_clients_by_user",
+            self.inner.list_clients_by_user(user_id, include_payload),
+        )
+        .await
+    }
+
+    async fn list_clients(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Client>> {
+        self.inject("list_clients", self.inner.list_clients(filter, limit))
+            .await
+    }
+
+    async fn update_client_last_seen(&self, client_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "update_client_last_seen",
+            self.inner.update_client_last_seen(client_id),
+        )
+        .await
+    }
+
+    // Client backup operations
+    async fn store_client_backup(
+        &self,
+        client_id: Uuid,
+        data: Vec<u8>,
+        expected_version: i64,
+    ) -> DbResult<ClientBackup> {
+        self.inject(
+            "store_client_backup",
+            self.inner
+                .store_client_backup(client_id, data, expected_version),
+        )
+        .await
+    }
+
+    async fn get_client_backup(&self, client_id: Uuid) -> DbResult<ClientBackup> {
+        self.inject("get_client_backup", self.inner.get_client_backup(client_id))
+            .await
+    }
+
+    // Request replay protection
+    async fn record_request_nonce(
+        &self,
+        client_id: Uuid,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> DbResult<bool> {
+        self.inject(
+            "record_request_nonce",
+            self.inner
+                .record_request_nonce(client_id, nonce, expires_at),
+        )
+        .await
+    }
+
+    async fn purge_expired_request_nonces(&self, now: DateTime<Utc>) -> DbResult<u64> {
+        self.inject(
+            "purge_expired_request_nonces",
+            self.inner.purge_expired_request_nonces(now),
+        )
+        .await
+    }
+
+    async fn list_signature_keys(&self) -> DbResult<Vec<(Uuid, Vec<u8>)>> {
+        self.inject("list_signature_keys", self.inner.list_signature_keys())
+            .await
+    }
+
+    // Feature flag operations
+    async fn upsert_feature_flag(&self, flag: FeatureFlag) -> DbResult<()> {
+        self.inject("upsert_feature_flag", self.inner.upsert_feature_flag(flag))
+            .await
+    }
+
+    async fn list_feature_flags(&self) -> DbResult<Vec<FeatureFlag>> {
+        self.inject("list_feature_flags", self.inner.list_feature_flags())
+            .await
+    }
+
+    async fn delete_feature_flag(&self, name: &str) -> DbResult<()> {
+        self.inject("delete_feature_flag", self.inner.delete_feature_flag(name))
+            .await
+    }
+
+    // Verbose tracing operations
+    async fn create_verbose_trace(&self, trace: VerboseTrace) -> DbResult<()> {
+        self.inject(
+            "create_verbose_trace",
+            self.inner.create_verbose_trace(trace),
+        )
+        .await
+    }
+
+    async fn list_verbose_traces(&self, now: DateTime<Utc>) -> DbResult<Vec<VerboseTrace>> {
+        self.inject("list_verbose_traces", self.inner.list_verbose_traces(now))
+            .await
+    }
+
+    async fn delete_verbose_trace(&self, trace_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "delete_verbose_trace",
+            self.inner.delete_verbose_trace(trace_id),
+        )
+        .await
+    }
+
+    // KeyPackage operations
+    async fn store_key_package(&self, key_package: KeyPackage) -> DbResult<()> {
+        self.inject(
+            "store_key_package",
+            self.inner.store_key_package(key_package),
+        )
+        .await
+    }
+
+    async fn get_key_package(&self, key_package_id: Uuid) -> DbResult<KeyPackage> {
+        self.inject(
+            "get_key_package",
+            self.inner.get_key_package(key_package_id),
+        )
+        .await
+    }
+
+    async fn list_key_packages_by_client(
+        &self,
+        client_id: Uuid,
+        include_payload: bool,
+    ) -> DbResult<Vec<KeyPackage>> {
+        self.inject(
+            "list_key_packages_by_client",
+            self.inner
+                .list_key_packages_by_client(client_id, include_payload),
+        )
+        .await
+    }
+
+    async fn mark_key_package_used(&self, key_package_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "mark_key_package_used",
+            self.inner.mark_key_package_used(key_package_id),
+        )
+        .await
+    }
+
+    async fn claim_key_package(&self, client_id: Uuid, skip: &[Uuid]) -> DbResult<KeyPackage> {
+        self.inject(
+            "claim_key_package",
+            self.inner.claim_key_package(client_id, skip),
+        )
+        .await
+    }
+
+    async fn release_key_package(&self, key_package_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "release_key_package",
+            self.inner.release_key_package(key_package_id),
+        )
+        .await
+    }
+
+    async fn record_key_package_claim(&self, claim: KeyPackageClaim) -> DbResult<()> {
+        self.inject(
+            "record_key_package_claim",
+            self.inner.record_key_package_claim(claim),
+        )
+        .await
+    }
+
+    async fn list_key_package_claims(
+        &self,
+        filter: &Filter,
+        limit: i64,
+    ) -> DbResult<Vec<KeyPackageClaim>> {
+        self.inject(
+            "list_key_package_claims",
+            self.inner.list_key_package_claims(filter, limit),
+        )
+        .await
+    }
+
+    async fn list_clients_low_on_key_packages(
+        &self,
+        min_unused: i64,
+    ) -> DbResult<Vec<KeyPackageInventory>> {
+        self.inject(
+            "list_clients_low_on_key_packages",
+            self.inner.list_clients_low_on_key_packages(min_unused),
+        )
+        .await
+    }
+
+    async fn mark_key_packages_low_notified(&self, client_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "mark_key_packages_low_notified",
+            self.inner.mark_key_packages_low_notified(client_id),
+        )
+        .await
+    }
+
+    async fn count_unused_key_packages(
+        &self,
+        client_id: Uuid,
+        expired_before: DateTime<Utc>,
+    ) -> DbResult<KeyPackageCounts> {
+        self.inject(
+            "count_unused_key_packages",
+            self.inner
+                .count_unused_key_packages(client_id, expired_before),
+        )
+        .await
+    }
+
+    async fn purge_used_key_packages(&self, before: DateTime<Utc>) -> DbResult<u64> {
+        self.inject(
+            "purge_used_key_packages",
+            self.inner.purge_used_key_packages(before),
+        )
+        .await
+    }
+
+    // Group operations
+    async fn create_group(&self, group: Group) -> DbResult<()> {
+        self.inject("create_group", self.inner.create_group(group))
+            .await
+    }
+
+    async fn create_group_with_members(
+        &self,
+        group: Group,
+        members: Vec<Membership>,
+    ) -> DbResult<()> {
+        self.inject(
+            "create_group_with_members",
+            self.inner.create_group_with_members(group, members),
+        )
+        .await
+    }
+
+    async fn get_group(&self, group_id: Uuid) -> DbResult<Group> {
+        self.inject("get_group", self.inner.get_group(group_id))
+            .await
+    }
+
+    async fn get_group_by_handle(&self, tenant_id: Uuid, handle: &str) -> DbResult<Group> {
+        self.inject(
+            "get_group_by_handle",
+            self.inner.get_group_by_handle(tenant_id, handle),
+        )
+        .await
+    }
+
+    async fn get_group_by_mls_group_id(&self, mls_group_id: &[u8]) -> DbResult<Group> {
+        self.inject(
+            "get_group_by_mls_group_id",
+            self.inner.get_group_by_mls_group_id(mls_group_id),
+        )
+        .await
+    }
+
+    async fn list_groups_by_client(
+        &self,
+        client_id: Uuid,
+        include_state: bool,
+    ) -> DbResult<Vec<Group>> {
+        self.inject(
+            "list_groups_by_client",
+            self.inner.list_groups_by_client(client_id, include_state),
+        )
+        .await
+    }
+
+    async fn list_groups(&self, filter: &Filter, limit: i64) -> DbResult<Vec<Group>> {
+        self.inject("list_groups", self.inner.list_groups(filter, limit))
+            .await
+    }
+
+    async fn list_active_groups(&self, since: DateTime<Utc>, limit: i64) -> DbResult<Vec<Group>> {
+        self.inject(
+            "list_active_groups",
+            self.inner.list_active_groups(since, limit),
+        )
+        .await
+    }
+
+    async fn update_group_epoch(&self, group_id: Uuid, epoch: i64) -> DbResult<()> {
+        self.inject(
+            "update_group_epoch",
+            self.inner.update_group_epoch(group_id, epoch),
+        )
+        .await
+    }
+
+    async fn update_group_state(&self, group_id: Uuid, state: Vec<u8>) -> DbResult<()> {
+        self.inject(
+            "update_group_state",
+            self.inner.update_group_state(group_id, state),
+        )
+        .await
+    }
+
+    async fn set_group_active(&self, group_id: Uuid, active: bool) -> DbResult<bool> {
+        self.inject(
+            "set_group_active",
+            self.inner.set_group_active(group_id, active),
+        )
+        .await
+    }
+
+    async fn set_group_max_epoch_age(
+        &self,
+        group_id: Uuid,
+        max_epoch_age_secs: Option<i64>,
+    ) -> DbResult<()> {
+        self.inject(
+            "set_group_max_epoch_age",
+            self.inner
+                .set_group_max_epoch_age(group_id, max_epoch_age_secs),
+        )
+        .await
+    }
+
+    async fn get_group_max_epoch_age(&self, group_id: Uuid) -> DbResult<Option<i64>> {
+        self.inject(
+            "get_group_max_epoch_age",
+            self.inner.get_group_max_epoch_age(group_id),
+        )
+        .await
+    }
+
+    async fn list_stale_epoch_groups(
+        &self,
+        default_max_age_secs: i64,
+        now: DateTime<Utc>,
+    ) -> DbResult<Vec<StaleEpochGroup>> {
+        self.inject(
+            "list_stale_epoch_groups",
+            self.inner
+                .list_stale_epoch_groups(default_max_age_secs, now),
+        )
+        .await
+    }
+
+    async fn mark_rotation_reminded(&self, group_id: Uuid, epoch: i64) -> DbResult<()> {
+        self.inject(
+            "mark_rotation_reminded",
+            self.inner.mark_rotation_reminded(group_id, epoch),
+        )
+        .await
+    }
+
+    async fn store_group_info(&self, group_info: GroupInfo) -> DbResult<()> {
+        self.inject("store_group_info", self.inner.store_group_info(group_info))
+            .await
+    }
+
+    async fn get_group_info(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<GroupInfo> {
+        self.inject("get_group_info", self.inner.get_group_info(group_id, epoch))
+            .await
+    }
+
+    async fn store_ratchet_tree(&self, tree: RatchetTree) -> DbResult<()> {
+        self.inject("store_ratchet_tree", self.inner.store_ratchet_tree(tree))
+            .await
+    }
+
+    async fn get_ratchet_tree(&self, group_id: Uuid, epoch: Option<i64>) -> DbResult<RatchetTree> {
+        self.inject(
+            "get_ratchet_tree",
+            self.inner.get_ratchet_tree(group_id, epoch),
+        )
+        .await
+    }
+
+    // Membership operations
+    async fn add_membership(&self, membership: Membership) -> DbResult<()> {
+        self.inject("add_membership", self.inner.add_membership(membership))
+            .await
+    }
+
+    async fn remove_membership(&self, membership_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "remove_membership",
+            self.inner.remove_membership(membership_id),
+        )
+        .await
+    }
+
+    async fn list_memberships_by_group(&self, group_id: Uuid) -> DbResult<Vec<Membership>> {
+        self.inject(
+            "list_memberships_by_group",
+            self.inner.list_memberships_by_group(group_id),
+        )
+        .await
+    }
+
+    async fn list_memberships_by_client(&self, client_id: Uuid) -> DbResult<Vec<Membership>> {
+        self.inject(
+            "list_memberships_by_client",
+            self.inner.list_memberships_by_client(client_id),
+        )
+        .await
+    }
+
+    async fn list_memberships_by_groups(&self, group_ids: &[Uuid]) -> DbResult<Vec<Membership>> {
+        self.inject(
+            "list_memberships_by_groups",
+            self.inner.list_memberships_by_groups(group_ids),
+        )
+        .await
+    }
+
+    async fn get_membership(&self, membership_id: Uuid) -> DbResult<Membership> {
+        self.inject("get_membership", self.inner.get_membership(membership_id))
+            .await
+    }
+
+    async fn list_membership_history(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<Membership>> {
+        self.inject(
+            "list_membership_history",
+            self.inner.list_membership_history(client_id, group_id),
+        )
+        .await
+    }
+
+    async fn acknowledge_epoch(&self, group_id: Uuid, client_id: Uuid, epoch: i64) -> DbResult<()> {
+        self.inject(
+            "acknowledge_epoch",
+            self.inner.acknowledge_epoch(group_id, client_id, epoch),
+        )
+        .await
+    }
+
+    async fn list_stuck_memberships(
+        &self,
+        max_epochs_behind: i64,
+        idle_since: DateTime<Utc>,
+    ) -> DbResult<Vec<StuckMembership>> {
+        self.inject(
+            "list_stuck_memberships",
+            self.inner
+                .list_stuck_memberships(max_epochs_behind, idle_since),
+        )
+        .await
+    }
+
+    async fn mark_membership_escalated(&self, membership_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "mark_membership_escalated",
+            self.inner.mark_membership_escalated(membership_id),
+        )
+        .await
+    }
+
+    async fn mark_welcome_requested(&self, membership_id: Uuid) -> DbResult<bool> {
+        self.inject(
+            "mark_welcome_requested",
+            self.inner.mark_welcome_requested(membership_id),
+        )
+        .await
+    }
+
+    async fn clear_welcome_requests(&self, group_id: Uuid, client_ids: &[Uuid]) -> DbResult<()> {
+        self.inject(
+            "clear_welcome_requests",
+            self.inner.clear_welcome_requests(group_id, client_ids),
+        )
+        .await
+    }
+
+    async fn list_overdue_welcomes(
+        &self,
+        stored_before: DateTime<Utc>,
+    ) -> DbResult<Vec<OverdueWelcome>> {
+        self.inject(
+            "list_overdue_welcomes",
+            self.inner.list_overdue_welcomes(stored_before),
+        )
+        .await
+    }
+
+    async fn record_welcome_reminder(
+        &self,
+        membership_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> DbResult<()> {
+        self.inject(
+            "record_welcome_reminder",
+            self.inner.record_welcome_reminder(membership_id, at),
+        )
+        .await
+    }
+
+    async fn mark_welcome_escalated(&self, membership_id: Uuid) -> DbResult<()> {
+        self.inject(
+            "mark_welcome_escalated",
+            self.inner.mark_welcome_escalated(membership_id),
+        )
+        .await
+    }
+
+    // Deletion lifecycle operations
+    async fn soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        self.inject("soft_delete", self.inner.soft_delete(kind, id))
+            .await
+    }
+
+    async fn restore_deleted(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        self.inject("restore_deleted", self.inner.restore_deleted(kind, id))
+            .await
+    }
+
+    async fn purge_entity(&self, kind: EntityKind, id: Uuid) -> DbResult<()> {
+        self.inject("purge_entity", self.inner.purge_entity(kind, id))
+            .await
+    }
+
+    async fn purge_deleted_before(&self, kind: EntityKind, cutoff: DateTime<Utc>) -> DbResult<u64> {
+        self.inject(
+            "purge_deleted_before",
+            self.inner.purge_deleted_before(kind, cutoff),
+        )
+        .await
+    }
+
+    async fn preview_soft_delete(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>> {
+        self.inject(
+            "preview_soft_delete",
+            self.inner.preview_soft_delete(kind, id),
+        )
+        .await
+    }
+
+    async fn preview_purge(&self, kind: EntityKind, id: Uuid) -> DbResult<Vec<AffectedRows>> {
+        self.inject("preview_purge", self.inner.preview_purge(kind, id))
+            .await
+    }
+
+    // Message operations
+    async fn store_message(&self, message: Message) -> DbResult<Message> {
+        self.inject("store_message", self.inner.store_message(message))
+            .await
+    }
+
+    async fn store_proposal(&self, message: Message) -> DbResult<Message> {
+        self.inject("store_proposal", self.inner.store_proposal(message))
+            .await
+    }
+
+    async fn list_pending_proposals(&self, group_id: Uuid) -> DbResult<Vec<QueuedProposal>> {
+        self.inject(
+            "list_pending_proposals",
+            self.inner.list_pending_proposals(group_id),
+        )
+        .await
+    }
+
+    async fn store_commit(
+        &self,
+        message: Message,
+        epoch: i64,
+        proposal_refs: &[Vec<u8>],
+    ) -> DbResult<Message> {
+        self.inject(
+            "store_commit",
+            self.inner.store_commit(message, epoch, proposal_refs),
+        )
+        .await
+    }
+
+    async fn fetch_messages_for_client(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
+    ) -> DbResult<Vec<Message>> {
+        self.inject(
+            "fetch_messages_for_client",
+            self.inner.fetch_messages_for_client(
+                client_id,
+                group_id,
+                include_acked,
+                message_types,
+                include_payload,
+            ),
+        )
+        .await
+    }
+
+    async fn fetch_addressed_messages(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        message_ids: &[Uuid],
+    ) -> DbResult<Vec<Message>> {
+        self.inject(
+            "fetch_addressed_messages",
+            self.inner
+                .fetch_addressed_messages(client_id, group_id, message_ids),
+        )
+        .await
+    }
+
+    async fn fetch_messages_after(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+        after: &[DeliveryCursor],
+        limit: i64,
+    ) -> DbResult<Vec<Message>> {
+        self.inject(
+            "fetch_messages_after",
+            self.inner
+                .fetch_messages_after(client_id, group_id, after, limit),
+        )
+        .await
+    }
+
+    async fn last_message_sequences(
+        &self,
+        group_ids: &[Option<Uuid>],
+    ) -> DbResult<Vec<DeliveryCursor>> {
+        self.inject(
+            "last_message_sequences",
+            self.inner.last_message_sequences(group_ids),
+        )
+        .await
+    }
+
+    async fn fetch_messages_for_member(
+        &self,
+        client_id: Uuid,
+        group_id: Uuid,
+        include_acked: bool,
+        message_types: &[MessageType],
+        include_payload: bool,
+    ) -> DbResult<Vec<Message>> {
+        self.inject(
+            "fetch_messages_for_member",
+            self.inner.fetch_messages_for_member(
+                client_id,
+                group_id,
+                include_acked,
+                message_types,
+                include_payload,
+            ),
+        )
+        .await
+    }
+
+    async fn purge_expired_messages(&self, now: DateTime<Utc>) -> DbResult<u64> {
+        self.inject(
+            "purge_expired_messages",
+            self.inner.purge_expired_messages(now),
+        )
+        .await
+    }
+
+    async fn purge_retained_messages(
+        &self,
+        message_type: MessageType,
+        before: DateTime<Utc>,
+        delivered_only: bool,
+    ) -> DbResult<u64> {
+        self.inject(
+            "purge_retained_messages",
+            self.inner
+                .purge_retained_messages(message_type, before, delivered_only),
+        )
+        .await
+    }
+
+    async fn evict_application_messages(&self, group_id: Uuid, keep: i64) -> DbResult<u64> {
+        self.inject(
+            "evict_application_messages",
+            self.inner.evict_application_messages(group_id, keep),
+        )
+        .await
+    }
+
+    async fn take_queue_truncations(
+        &self,
+        client_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> DbResult<Vec<QueueTruncation>> {
+        self.inject(
+            "take_queue_truncations",
+            self.inner.take_queue_truncations(client_id, group_id),
+        )
+        .await
+    }
+
+    async fn mark_messages_read(&self, message_ids: Vec<Uuid>) -> DbResult<()> {
+        self.inject(
+            "mark_messages_read",
+            self.inner.mark_messages_read(message_ids),
+        )
+        .await
+    }
+
+    async fn advance_delivery_cursors(
+        &self,
+        client_id: Uuid,
+        cursors: &[DeliveryCursor],
+    ) -> DbResult<()> {
+        self.inject(
+            "advance_delivery_cursors",
+            self.inner.advance_delivery_cursors(client_id, cursors),
+        )
+        .await
+    }
+
+    async fn group_storage_stats(
+        &self,
+        group_id: Option<Uuid>,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> DbResult<Vec<GroupStorageStats>> {
+        self.inject(
+            "group_storage_stats",
+            self.inner.group_storage_stats(group_id, now, limit),
+        )
+        .await
+    }
+
+    async fn find_integrity_issues(&self, limit: i64) -> DbResult<Vec<IntegrityIssue>> {
+        self.inject(
+            "find_integrity_issues",
+            self.inner.find_integrity_issues(limit),
+        )
+        .await
+    }
+
+    async fn upgrade_blob_formats(&self, limit: i64) -> DbResult<u64> {
+        self.inject(
+            "upgrade_blob_formats",
+            self.inner.upgrade_blob_formats(limit),
+        )
+        .await
+    }
+
+    async fn destroy_tenant_key(&self, tenant_id: Uuid) -> DbResult<bool> {
+        self.inject(
+            "destroy_tenant_key",
+            self.inner.destroy_tenant_key(tenant_id),
+        )
+        .await
+    }
+
+    async fn store_abuse_report(&self, report: AbuseReport) -> DbResult<()> {
+        self.inject("store_abuse_report", self.inner.store_abuse_report(report))
+            .await
+    }
+
+    async fn list_abuse_reports(&self, filter: &Filter, limit: i64) -> DbResult<Vec<AbuseReport>> {
+        self.inject(
+            "list_abuse_reports",
+            self.inner.list_abuse_reports(filter, limit),
+        )
+        .await
+    }
+
+    async fn resolve_abuse_report(
+        &self,
+        report_id: Uuid,
+        resolution: &str,
+        resolved_at: DateTime<Utc>,
+    ) -> DbResult<()> {
+        self.inject(
+            "resolve_abuse_report",
+            self.inner
+                .resolve_abuse_report(report_id, resolution, resolved_at),
+        )
+        .await
+    }
+}
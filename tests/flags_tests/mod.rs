@@ -0,0 +1 @@
+pub mod rollout_tests;
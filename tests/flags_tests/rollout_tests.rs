@@ -0,0 +1,103 @@
+use chrono::Utc;
+use hermetic_mls::{
+    db::{DatabaseInterface, FeatureFlag},
+    flags::{is_enabled_for, rollout_bucket, FeatureFlags},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+// Helper function to build a flag
+fn flag(name: &str, enabled: bool, percentage: i32) -> FeatureFlag {
+    FeatureFlag {
+        name: name.to_string(),
+        enabled,
+        percentage,
+        group_ids: vec![],
+        user_ids: vec![],
+        updated_at: Utc::now(),
+    }
+}
+
+/// A percentage rollout covers roughly that share of groups, and grows monotonically
+#[test]
+fn test_percentage_rollout() {
+    let groups: Vec<Uuid> = (0..2000).map(|_| Uuid::new_v4()).collect();
+    let covered = |percentage| {
+        let flag = flag("new_fetch_path", true, percentage);
+        groups
+            .iter()
+            .filter(|id| is_enabled_for(&flag, Some(**id), None))
+            .copied()
+            .collect::<Vec<_>>()
+    };
+
+    assert!(covered(0).is_empty());
+    assert_eq!(covered(100).len(), groups.len());
+
+    let ten = covered(10);
+    let fifty = covered(50);
+    assert!(
+        (100..300).contains(&ten.len()),
+        "{} groups at 10%",
+        ten.len()
+    );
+    assert!(
+        (800..1200).contains(&fifty.len()),
+        "{} groups at 50%",
+        fifty.len()
+    );
+    assert!(ten.iter().all(|id| fifty.contains(id)));
+
+    // Buckets are stable, and differ between flags
+    let group_id = groups[0];
+    assert_eq!(
+        rollout_bucket("new_fetch_path", group_id),
+        rollout_bucket("new_fetch_path", group_id)
+    );
+    assert!(groups
+        .iter()
+        .any(|id| rollout_bucket("a", *id) != rollout_bucket("b", *id)));
+}
+
+/// Explicit groups and users are on regardless of the percentage, unless the flag is disabled
+#[test]
+fn test_targeted_groups_and_users() {
+    let group_id = Uuid::new_v4();
+    let user_id = Uuid::new_v4();
+    let mut canary = flag("strict_validation", true, 0);
+    canary.group_ids = vec![group_id];
+    canary.user_ids = vec![user_id];
+
+    assert!(is_enabled_for(&canary, Some(group_id), None));
+    assert!(is_enabled_for(&canary, Some(Uuid::new_v4()), Some(user_id)));
+    assert!(!is_enabled_for(
+        &canary,
+        Some(Uuid::new_v4()),
+        Some(Uuid::new_v4())
+    ));
+    assert!(!is_enabled_for(&canary, None, None));
+
+    canary.enabled = false;
+    assert!(!is_enabled_for(&canary, Some(group_id), Some(user_id)));
+    assert!(!is_enabled_for(&flag("killed", false, 100), None, None));
+}
+
+/// The snapshot picks up flags written to the database on refresh
+#[tokio::test]
+async fn test_refresh_from_database() {
+    let db = MockDatabase::new();
+    let flags = FeatureFlags::load(&db).await.unwrap();
+    assert!(!flags.is_enabled("fan_out_worker", None, None));
+
+    db.upsert_feature_flag(flag("fan_out_worker", true, 100))
+        .await
+        .unwrap();
+    assert!(!flags.is_enabled("fan_out_worker", None, None));
+    flags.refresh(&db).await.unwrap();
+    assert!(flags.is_enabled("fan_out_worker", None, None));
+
+    db.delete_feature_flag("fan_out_worker").await.unwrap();
+    flags.refresh(&db).await.unwrap();
+    assert!(flags.list().is_empty());
+}
@@ -0,0 +1,102 @@
+use hermetic_mls::{
+    db::DatabaseInterface,
+    ids::RandomIds,
+    import::{import_bundle, ImportBundle, ImportError},
+};
+use serde_json::json;
+
+use crate::mock_db::MockDatabase;
+
+// An export with two devices of one user, one device of another, and a shared group
+fn sample_bundle() -> ImportBundle {
+    serde_json::from_value(json!({
+        "source": "openmls-ds",
+        "clients": [
+            {
+                "external_id": "alice-phone",
+                "external_user_id": "alice",
+                "identity": "alice",
+                "device_name": "phone",
+                "key_packages": ["AQIDBA==", "BQYHCA=="]
+            },
+            {
+                "external_id": "alice-laptop",
+                "external_user_id": "alice",
+                "identity": "alice",
+                "device_name": "laptop"
+            },
+            {
+                "external_id": "bob-phone",
+                "external_user_id": "bob",
+                "credential": "CQoLDA==",
+                "device_name": "phone"
+            }
+        ],
+        "groups": [
+            {
+                "external_id": "group-1",
+                "creator": "alice-phone",
+                "epoch": 4,
+                "members": [
+                    { "client": "alice-phone", "role": "admin" },
+                    { "client": "bob-phone", "role": "member" }
+                ]
+            }
+        ]
+    }))
+    .unwrap()
+}
+
+/// Test importing clients, key packages, groups, and memberships
+#[tokio::test]
+async fn test_import_bundle() {
+    let db = MockDatabase::new();
+
+    let report = import_bundle(&db, &RandomIds, sample_bundle())
+        .await
+        .unwrap();
+    assert_eq!(report.source, "openmls-ds");
+    assert_eq!(report.users.len(), 2);
+    assert_eq!(report.clients.len(), 3);
+    assert_eq!(report.key_packages, 2);
+    assert_eq!(report.memberships, 2);
+
+    // Devices of the same source user share a user id
+    let alice_phone = db.get_client(report.clients["alice-phone"]).await.unwrap();
+    let alice_laptop = db.get_client(report.clients["alice-laptop"]).await.unwrap();
+    assert_eq!(alice_phone.user_id, report.users["alice"]);
+    assert_eq!(alice_laptop.user_id, report.users["alice"]);
+    assert!(!alice_phone.credential.is_empty());
+
+    // Exported credentials are kept as-is
+    let bob = db.get_client(report.clients["bob-phone"]).await.unwrap();
+    assert_eq!(bob.credential, vec![9, 10, 11, 12]);
+
+    let key_packages = db
+        .list_key_packages_by_client(alice_phone.id, true)
+        .await
+        .unwrap();
+    assert_eq!(key_packages.len(), 2);
+
+    // Imported members are considered caught up to the exported epoch
+    let group = db.get_group(report.groups["group-1"]).await.unwrap();
+    assert_eq!(group.epoch, 4);
+    assert_eq!(group.creator_id, alice_phone.id);
+    let memberships = db.list_memberships_by_group(group.id).await.unwrap();
+    assert_eq!(memberships.len(), 2);
+    assert!(memberships
+        .iter()
+        .all(|membership| membership.last_acked_epoch == Some(4)));
+}
+
+/// Test that a group referencing an unexported client is rejected
+#[tokio::test]
+async fn test_import_unknown_member() {
+    let db = MockDatabase::new();
+
+    let mut bundle = sample_bundle();
+    bundle.groups[0].members[1].client = "carol-phone".to_string();
+
+    let result = import_bundle(&db, &RandomIds, bundle).await;
+    assert!(matches!(result, Err(ImportError::UnknownClient(id)) if id == "carol-phone"));
+}
@@ -0,0 +1 @@
+pub mod import_bundle_tests;
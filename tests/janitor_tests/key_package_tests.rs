@@ -0,0 +1,60 @@
+use hermetic_mls::{
+    db::DatabaseInterface,
+    fixtures::{ClientFixture, KeyPackageFixture},
+    ids::RandomIds,
+    janitor::key_packages::sweep_low_key_packages,
+    notices::{SystemEnvelope, SystemNotice},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Create a client with the given number of unused key packages
+async fn setup_client(db: &MockDatabase, key_packages: usize) -> Uuid {
+    let client = ClientFixture::new().insert(db).await.unwrap();
+    for _ in 0..key_packages {
+        KeyPackageFixture::new(client.id).insert(db).await.unwrap();
+    }
+    client.id
+}
+
+/// Clients below the threshold get one key package low notice, outside any group
+#[tokio::test]
+async fn test_sweep_low_key_packages() {
+    let db = MockDatabase::new();
+    let low_client = setup_client(&db, 1).await;
+    let stocked_client = setup_client(&db, 3).await;
+
+    let notified = sweep_low_key_packages(&db, &RandomIds, 3).await.unwrap();
+    assert_eq!(notified, 1);
+
+    let messages = db
+        .fetch_messages_for_client(low_client, None, false, &[], true)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].group_id, None);
+    let envelope = SystemEnvelope::from_bytes(messages[0].system.as_ref().unwrap()).unwrap();
+    assert_eq!(
+        envelope.notice,
+        SystemNotice::KeyPackageLow {
+            client_id: low_client,
+            remaining: 1,
+        }
+    );
+
+    // The notice is addressed to its recipient only
+    assert!(db
+        .fetch_messages_for_client(stocked_client, None, false, &[], true)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Not notified again until a new key package is published
+    assert_eq!(sweep_low_key_packages(&db, &RandomIds, 3).await.unwrap(), 0);
+    KeyPackageFixture::new(low_client)
+        .insert(&db)
+        .await
+        .unwrap();
+    assert_eq!(sweep_low_key_packages(&db, &RandomIds, 3).await.unwrap(), 1);
+}
@@ -0,0 +1,130 @@
+use chrono::{Duration, Utc};
+use hermetic_mls::{
+    db::{DatabaseInterface, MessageType},
+    fixtures::{GroupFixture, MembershipFixture},
+    ids::RandomIds,
+    janitor::{key_rotation::sweep_stale_epochs, KeyRotationPolicy},
+    notices::{SystemEnvelope, SystemNotice},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Create a group that entered its current epoch `age` ago, with two members
+async fn setup_group(db: &MockDatabase, age: Duration) -> (Uuid, Vec<Uuid>) {
+    let group = GroupFixture::new()
+        .with_epoch(4)
+        .created_at(Utc::now() - age)
+        .insert(db)
+        .await
+        .unwrap();
+    let members = vec![Uuid::new_v4(), Uuid::new_v4()];
+    for client_id in &members {
+        MembershipFixture::new(group.id, *client_id)
+            .insert(db)
+            .await
+            .unwrap();
+    }
+    (group.id, members)
+}
+
+fn week_policy() -> KeyRotationPolicy {
+    KeyRotationPolicy {
+        default_max_epoch_age: Some(Duration::days(7)),
+    }
+}
+
+/// Groups that haven't committed within the default age are reminded once per epoch
+#[tokio::test]
+async fn test_sweep_reminds_stale_groups() {
+    let db = MockDatabase::new();
+    let (group_id, members) = setup_group(&db, Duration::days(10)).await;
+    let (fresh_id, _) = setup_group(&db, Duration::days(1)).await;
+
+    let reminded = sweep_stale_epochs(&db, &RandomIds, &week_policy(), Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(reminded, 1);
+
+    // Every member is asked to commit an Update
+    for client_id in members {
+        let messages = db
+            .fetch_messages_for_client(client_id, Some(group_id), false, &[], true)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message_type, MessageType::System);
+        let envelope = SystemEnvelope::from_bytes(messages[0].system.as_ref().unwrap()).unwrap();
+        match envelope.notice {
+            SystemNotice::KeyRotationDue {
+                group_id: notice_group,
+                epoch,
+                max_epoch_age_secs,
+                ..
+            } => {
+                assert_eq!(notice_group, group_id);
+                assert_eq!(epoch, 4);
+                assert_eq!(max_epoch_age_secs, Duration::days(7).num_seconds());
+            }
+            notice => panic!("unexpected notice {:?}", notice),
+        }
+    }
+    let stale = db
+        .list_stale_epoch_groups(Duration::days(7).num_seconds(), Utc::now())
+        .await
+        .unwrap();
+    assert!(stale.iter().all(|g| g.group_id != fresh_id));
+
+    // Not again for the same epoch
+    let reminded = sweep_stale_epochs(&db, &RandomIds, &week_policy(), Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(reminded, 0);
+
+    // A commit starts a fresh epoch, which is reminded about once it is stale in turn
+    db.update_group_epoch(group_id, 5).await.unwrap();
+    let reminded = sweep_stale_epochs(&db, &RandomIds, &week_policy(), Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(reminded, 0);
+    let later = Utc::now() + Duration::days(8);
+    let reminded = sweep_stale_epochs(&db, &RandomIds, &week_policy(), later)
+        .await
+        .unwrap();
+    assert_eq!(reminded, 2);
+}
+
+/// A group's own limit overrides the default, and 0 turns its reminders off
+#[tokio::test]
+async fn test_sweep_uses_group_policy() {
+    let db = MockDatabase::new();
+    let (short_id, _) = setup_group(&db, Duration::hours(2)).await;
+    let (opted_out_id, _) = setup_group(&db, Duration::days(30)).await;
+    db.set_group_max_epoch_age(short_id, Some(3600))
+        .await
+        .unwrap();
+    db.set_group_max_epoch_age(opted_out_id, Some(0))
+        .await
+        .unwrap();
+
+    // Without a default, only groups with a limit of their own are checked
+    let stale = db.list_stale_epoch_groups(0, Utc::now()).await.unwrap();
+    assert_eq!(stale.len(), 1);
+    assert_eq!(stale[0].group_id, short_id);
+    assert_eq!(stale[0].max_epoch_age_secs, 3600);
+
+    let reminded = sweep_stale_epochs(&db, &RandomIds, &week_policy(), Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(reminded, 1);
+
+    // Frozen groups aren't reminded
+    db.set_group_max_epoch_age(opted_out_id, None)
+        .await
+        .unwrap();
+    db.set_group_active(opted_out_id, false).await.unwrap();
+    let reminded = sweep_stale_epochs(&db, &RandomIds, &week_policy(), Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(reminded, 0);
+}
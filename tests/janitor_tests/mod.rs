@@ -0,0 +1,6 @@
+pub mod key_package_tests;
+pub mod key_rotation_tests;
+pub mod purge_tests;
+pub mod retention_tests;
+pub mod stuck_member_tests;
+pub mod welcome_sla_tests;
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use hermetic_mls::{
+    db::{AttestationVerdict, Client, CredentialScheme, DatabaseInterface, EntityKind, KeyPackage},
+    janitor::{Janitor, JanitorConfig},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Create a client with one key package
+async fn setup_client(db: &MockDatabase) -> (Uuid, Uuid) {
+    let client_id = Uuid::new_v4();
+    let client = Client {
+        id: client_id,
+        user_id: Uuid::new_v4(),
+        credential: vec![1, 2, 3, 4],
+        scheme: CredentialScheme::Basic,
+        device_name: "test-device".to_string(),
+        last_seen: Utc::now(),
+        created_at: Utc::now(),
+        init_key: None,
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
+    };
+    db.register_client(client).await.unwrap();
+
+    let key_package_id = Uuid::new_v4();
+    let key_package = KeyPackage {
+        id: key_package_id,
+        client_id,
+        data: vec![5, 6, 7, 8],
+        created_at: Utc::now(),
+        used: false,
+        used_at: None,
+        deleted_at: None,
+    };
+    db.store_key_package(key_package).await.unwrap();
+
+    (client_id, key_package_id)
+}
+
+/// Soft-deleted entities are purged only after the grace period
+#[tokio::test]
+async fn test_purge_respects_grace_period() {
+    let db = Arc::new(MockDatabase::new());
+    let (client_id, key_package_id) = setup_client(&db).await;
+
+    db.soft_delete(EntityKind::Client, client_id).await.unwrap();
+
+    // Within the grace period nothing is purged and the client can be restored
+    let janitor = Janitor::new(db.clone(), JanitorConfig::default());
+    assert_eq!(janitor.purge_deleted().await.unwrap(), 0);
+    assert!(db.get_key_package(key_package_id).await.is_ok());
+
+    // With no grace period the client and its key packages are purged
+    let janitor = Janitor::new(
+        db.clone(),
+        JanitorConfig {
+            purge_grace_period: Duration::zero(),
+            ..JanitorConfig::default()
+        },
+    );
+    assert_eq!(janitor.purge_deleted().await.unwrap(), 1);
+    assert!(db.get_key_package(key_package_id).await.is_err());
+    assert!(db
+        .restore_deleted(EntityKind::Client, client_id)
+        .await
+        .is_err());
+}
@@ -0,0 +1,198 @@
+use chrono::{Duration, Utc};
+use hermetic_mls::{
+    db::{
+        AttestationVerdict, Client, CredentialScheme, DatabaseInterface, DeliveryCursor,
+        KeyPackage, Membership, MembershipRole, Message, MessageType,
+    },
+    janitor::{retention, RetentionPolicy},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Create a client that is an active member of the group
+async fn setup_member(db: &MockDatabase, group_id: Uuid) -> Uuid {
+    let client_id = Uuid::new_v4();
+    db.register_client(Client {
+        id: client_id,
+        user_id: Uuid::new_v4(),
+        credential: vec![1, 2, 3, 4],
+        scheme: CredentialScheme::Basic,
+        device_name: "test-device".to_string(),
+        last_seen: Utc::now(),
+        created_at: Utc::now(),
+        init_key: None,
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+    client_id
+}
+
+/// Store a message of the type in the group, created `age` ago
+async fn store(
+    db: &MockDatabase,
+    group_id: Uuid,
+    message_type: MessageType,
+    age: Duration,
+    recipients: Option<Vec<Uuid>>,
+) -> Message {
+    let message = Message {
+        id: Uuid::new_v4(),
+        group_id: Some(group_id),
+        sender_id: Uuid::new_v4(),
+        created_at: Utc::now() - age,
+        read: false,
+        message_type,
+        proposal: None,
+        commit: (message_type == MessageType::Commit).then(|| vec![1]),
+        welcome: (message_type == MessageType::Welcome).then(|| vec![2]),
+        system: None,
+        application: None,
+        proposal_type: None,
+        epoch: Some(1),
+        recipients,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
+    };
+    db.store_message(message).await.unwrap()
+}
+
+/// Acknowledge the group's messages up to the message
+async fn ack(db: &MockDatabase, client_id: Uuid, message: &Message) {
+    db.advance_delivery_cursors(
+        client_id,
+        &[DeliveryCursor {
+            group_id: message.group_id,
+            sequence: message.sequence,
+        }],
+    )
+    .await
+    .unwrap();
+}
+
+/// Handshake messages past their retention are deleted once every member acknowledged them,
+/// welcomes whether or not they were
+#[tokio::test]
+async fn test_message_retention() {
+    let db = MockDatabase::new();
+    let group_id = Uuid::new_v4();
+    let alice = setup_member(&db, group_id).await;
+    let bob = setup_member(&db, group_id).await;
+    let joiner = Uuid::new_v4();
+
+    let delivered = store(&db, group_id, MessageType::Commit, Duration::days(40), None).await;
+    let pending = store(&db, group_id, MessageType::Commit, Duration::days(35), None).await;
+    let recent = store(&db, group_id, MessageType::Commit, Duration::days(1), None).await;
+    let welcome = store(
+        &db,
+        group_id,
+        MessageType::Welcome,
+        Duration::days(20),
+        Some(vec![joiner]),
+    )
+    .await;
+    ack(&db, alice, &recent).await;
+    ack(&db, bob, &delivered).await;
+
+    let policy = RetentionPolicy {
+        messages: [
+            (MessageType::Commit, Duration::days(30)),
+            (MessageType::Welcome, Duration::days(14)),
+        ]
+        .into(),
+        used_key_packages: None,
+    };
+    let purged = retention::sweep_messages(&db, &policy, Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(purged, 2);
+
+    let remaining: Vec<Uuid> = db
+        .fetch_messages_for_client(alice, None, true, &[], false)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect();
+    assert_eq!(remaining, vec![pending.id, recent.id]);
+    assert!(db
+        .fetch_messages_for_client(joiner, Some(group_id), true, &[], false)
+        .await
+        .unwrap()
+        .iter()
+        .all(|m| m.id != welcome.id));
+
+    // Once the last member acknowledges it, the pending commit goes too
+    ack(&db, bob, &pending).await;
+    let purged = retention::sweep_messages(&db, &policy, Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(purged, 1);
+}
+
+/// Key packages used longer ago than their retention are deleted, however long ago they were
+/// published; unused ones never are
+#[tokio::test]
+async fn test_used_key_package_retention() {
+    let db = MockDatabase::new();
+    let client_id = setup_member(&db, Uuid::new_v4()).await;
+    let mut ids = Vec::new();
+    for (age, used_age) in [(40, Some(40)), (40, None), (1, Some(1)), (40, Some(1))] {
+        let id = Uuid::new_v4();
+        db.store_key_package(KeyPackage {
+            id,
+            client_id,
+            data: vec![5, 6, 7, 8],
+            created_at: Utc::now() - Duration::days(age),
+            used: used_age.is_some(),
+            used_at: used_age.map(|days| Utc::now() - Duration::days(days)),
+            deleted_at: None,
+        })
+        .await
+        .unwrap();
+        ids.push(id);
+    }
+
+    // Nothing is deleted without a retention
+    let policy = RetentionPolicy::default();
+    assert_eq!(
+        retention::sweep_key_packages(&db, &policy, Utc::now())
+            .await
+            .unwrap(),
+        0
+    );
+
+    let policy = RetentionPolicy {
+        used_key_packages: Some(Duration::days(30)),
+        ..RetentionPolicy::default()
+    };
+    assert_eq!(
+        retention::sweep_key_packages(&db, &policy, Utc::now())
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(db.get_key_package(ids[0]).await.is_err());
+    assert!(db.get_key_package(ids[1]).await.is_ok());
+    assert!(db.get_key_package(ids[2]).await.is_ok());
+    assert!(db.get_key_package(ids[3]).await.is_ok());
+}
@@ -0,0 +1,129 @@
+use chrono::{Duration, Utc};
+use hermetic_mls::{
+    db::{DatabaseInterface, Group, Membership, MembershipRole, MessageType},
+    ids::RandomIds,
+    janitor::{stuck_members::sweep_stuck_members, StuckMemberAction, StuckMemberPolicy},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Create a group at the given epoch with an admin and one member
+async fn setup_group(
+    db: &MockDatabase,
+    epoch: i64,
+    member_acked: i64,
+    member_last_ack: chrono::DateTime<Utc>,
+) -> (Uuid, Uuid, Uuid) {
+    let group_id = Uuid::new_v4();
+    let admin_id = Uuid::new_v4();
+    let member_id = Uuid::new_v4();
+
+    let group = Group {
+        id: group_id,
+        creator_id: admin_id,
+        epoch,
+        state: Some(vec![1, 2, 3]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    for (client_id, role, acked, acked_at) in [
+        (admin_id, MembershipRole::Admin, epoch, Utc::now()),
+        (
+            member_id,
+            MembershipRole::Member,
+            member_acked,
+            member_last_ack,
+        ),
+    ] {
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role,
+            added_at: acked_at,
+            removed_at: None,
+            last_acked_epoch: Some(acked),
+            last_acked_at: Some(acked_at),
+        };
+        db.add_membership(membership).await.unwrap();
+    }
+
+    (group_id, admin_id, member_id)
+}
+
+/// Members too many epochs behind are reported to admins once
+#[tokio::test]
+async fn test_sweep_escalates_epoch_lag() {
+    let db = MockDatabase::new();
+    let (group_id, admin_id, member_id) = setup_group(&db, 60, 5, Utc::now()).await;
+
+    let policy = StuckMemberPolicy::default();
+    let escalated = sweep_stuck_members(&db, &RandomIds, &policy).await.unwrap();
+    assert_eq!(escalated, 1);
+
+    // The admin receives a system message about the stuck member
+    let messages = db
+        .fetch_messages_for_client(admin_id, Some(group_id), false, &[], true)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].message_type, MessageType::System);
+    let payload: serde_json::Value =
+        serde_json::from_slice(messages[0].system.as_ref().unwrap()).unwrap();
+    assert_eq!(payload["kind"], "member_stuck");
+    assert_eq!(payload["client_id"], member_id.to_string());
+    assert_eq!(payload["epochs_behind"], 55);
+
+    // The stuck member itself doesn't see the notice
+    let messages = db
+        .fetch_messages_for_client(member_id, Some(group_id), false, &[], true)
+        .await
+        .unwrap();
+    assert!(messages.is_empty());
+
+    // A second sweep doesn't escalate the same member again
+    let escalated = sweep_stuck_members(&db, &RandomIds, &policy).await.unwrap();
+    assert_eq!(escalated, 0);
+}
+
+/// Members idle for too long while behind are escalated with the configured action
+#[tokio::test]
+async fn test_sweep_escalates_idle_members() {
+    let db = MockDatabase::new();
+    let (group_id, admin_id, _) = setup_group(&db, 3, 2, Utc::now() - Duration::days(30)).await;
+
+    let policy = StuckMemberPolicy {
+        action: StuckMemberAction::RequestRemoval,
+        ..StuckMemberPolicy::default()
+    };
+    let escalated = sweep_stuck_members(&db, &RandomIds, &policy).await.unwrap();
+    assert_eq!(escalated, 1);
+
+    let messages = db
+        .fetch_messages_for_client(admin_id, Some(group_id), false, &[], true)
+        .await
+        .unwrap();
+    let payload: serde_json::Value =
+        serde_json::from_slice(messages[0].system.as_ref().unwrap()).unwrap();
+    assert_eq!(payload["kind"], "member_removal_requested");
+}
+
+/// Members that are only slightly behind and recently active are left alone
+#[tokio::test]
+async fn test_sweep_ignores_healthy_members() {
+    let db = MockDatabase::new();
+    setup_group(&db, 10, 8, Utc::now()).await;
+
+    let escalated = sweep_stuck_members(&db, &RandomIds, &StuckMemberPolicy::default())
+        .await
+        .unwrap();
+    assert_eq!(escalated, 0);
+}
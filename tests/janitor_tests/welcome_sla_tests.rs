@@ -0,0 +1,222 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use hermetic_mls::{
+    db::{DatabaseInterface, DeliveryCursor, MembershipRole, MessageType, OverdueWelcome},
+    events::{DomainEvent, EventBus},
+    fixtures::{GroupFixture, MembershipFixture, MessageFixture},
+    ids::RandomIds,
+    janitor::{
+        welcome_sla::{sweep_overdue_welcomes, WelcomeSweep},
+        StrandedWelcomeHook, WelcomeSlaPolicy,
+    },
+    notices::{SystemEnvelope, SystemNotice},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Hook keeping the stranded welcomes it was told about
+#[derive(Default)]
+struct RecordingHook(Mutex<Vec<OverdueWelcome>>);
+
+#[async_trait]
+impl StrandedWelcomeHook for RecordingHook {
+    async fn notify(&self, stranded: &[OverdueWelcome]) {
+        self.0.lock().unwrap().extend_from_slice(stranded);
+    }
+}
+
+fn hour_policy() -> WelcomeSlaPolicy {
+    WelcomeSlaPolicy {
+        deadline: Some(Duration::hours(1)),
+        push_retries: 2,
+    }
+}
+
+/// Create a group with an admin and a joiner, and a welcome for the joiner stored `age` ago.
+/// Returns the group, admin and joiner ids.
+async fn setup_group(db: &MockDatabase, age: Duration) -> (Uuid, Uuid, Uuid) {
+    let group = GroupFixture::new().insert(db).await.unwrap();
+    let admin_id = Uuid::new_v4();
+    let joiner_id = Uuid::new_v4();
+    MembershipFixture::new(group.id, admin_id)
+        .with_role(MembershipRole::Admin)
+        .insert(db)
+        .await
+        .unwrap();
+    MembershipFixture::new(group.id, joiner_id)
+        .insert(db)
+        .await
+        .unwrap();
+    MessageFixture::welcome(group.id, vec![joiner_id])
+        .sent_by(admin_id)
+        .created_at(Utc::now() - age)
+        .insert(db)
+        .await
+        .unwrap();
+    (group.id, admin_id, joiner_id)
+}
+
+/// Overdue welcomes get push reminders a deadline apart, then are reported stranded once
+#[tokio::test]
+async fn test_sweep_reminds_then_reports_stranded() {
+    let db = MockDatabase::new();
+    let events = EventBus::default();
+    let mut received = events.subscribe();
+    let recording = Arc::new(RecordingHook::default());
+    let hooks: Vec<Arc<dyn StrandedWelcomeHook>> = vec![recording.clone()];
+    let (group_id, admin_id, joiner_id) = setup_group(&db, Duration::hours(2)).await;
+    let policy = hour_policy();
+    let now = Utc::now();
+
+    let sweep = |at| sweep_overdue_welcomes(&db, &RandomIds, &events, &policy, &hooks, at);
+
+    // The first reminder goes out once the deadline has passed
+    let result = sweep(now).await.unwrap();
+    assert_eq!(
+        result,
+        WelcomeSweep {
+            reminded: 1,
+            stranded: 0
+        }
+    );
+    match received.try_recv().unwrap() {
+        DomainEvent::WelcomeReminder {
+            group_id: event_group,
+            client_id,
+            attempt,
+            ..
+        } => {
+            assert_eq!(event_group, group_id);
+            assert_eq!(client_id, joiner_id);
+            assert_eq!(attempt, 1);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    // The next one only a deadline later
+    assert_eq!(sweep(now).await.unwrap(), WelcomeSweep::default());
+    let result = sweep(now + Duration::minutes(61)).await.unwrap();
+    assert_eq!(result.reminded, 1);
+    assert!(matches!(
+        received.try_recv().unwrap(),
+        DomainEvent::WelcomeReminder { attempt: 2, .. }
+    ));
+
+    // With the reminders used up, the welcome is reported stranded
+    let result = sweep(now + Duration::minutes(122)).await.unwrap();
+    assert_eq!(
+        result,
+        WelcomeSweep {
+            reminded: 0,
+            stranded: 1
+        }
+    );
+    assert!(received.try_recv().is_err());
+
+    let messages = db
+        .fetch_messages_for_client(
+            admin_id,
+            Some(group_id),
+            false,
+            &[MessageType::System],
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    let envelope = SystemEnvelope::from_bytes(messages[0].system.as_ref().unwrap()).unwrap();
+    match envelope.notice {
+        SystemNotice::WelcomesStranded {
+            group_id: notice_group,
+            joiners,
+        } => {
+            assert_eq!(notice_group, group_id);
+            assert_eq!(joiners.len(), 1);
+            assert_eq!(joiners[0].client_id, joiner_id);
+            assert_eq!(joiners[0].reminders, 2);
+        }
+        notice => panic!("unexpected notice {:?}", notice),
+    }
+    let reported = recording.0.lock().unwrap().clone();
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].client_id, joiner_id);
+
+    // Reported only once
+    let result = sweep(now + Duration::hours(10)).await.unwrap();
+    assert_eq!(result, WelcomeSweep::default());
+    assert_eq!(recording.0.lock().unwrap().len(), 1);
+}
+
+/// Acknowledged welcomes, welcomes replaced by a newer one and fresh welcomes aren't overdue
+#[tokio::test]
+async fn test_overdue_welcomes() {
+    let db = MockDatabase::new();
+    let cutoff = Utc::now() - Duration::hours(1);
+
+    // Acknowledged through the joiner's delivery cursor
+    let (acked_group, _, acked_joiner) = setup_group(&db, Duration::hours(2)).await;
+    let welcome = db
+        .fetch_messages_for_client(acked_joiner, Some(acked_group), false, &[], false)
+        .await
+        .unwrap()
+        .remove(0);
+    db.advance_delivery_cursors(
+        acked_joiner,
+        &[DeliveryCursor {
+            group_id: Some(acked_group),
+            sequence: welcome.sequence,
+        }],
+    )
+    .await
+    .unwrap();
+
+    // Replaced by a welcome stored since
+    let (resent_group, admin_id, resent_joiner) = setup_group(&db, Duration::hours(2)).await;
+    MessageFixture::welcome(resent_group, vec![resent_joiner])
+        .sent_by(admin_id)
+        .insert(&db)
+        .await
+        .unwrap();
+
+    // Still overdue
+    let (overdue_group, _, overdue_joiner) = setup_group(&db, Duration::hours(2)).await;
+
+    let overdue = db.list_overdue_welcomes(cutoff).await.unwrap();
+    assert_eq!(overdue.len(), 1);
+    assert_eq!(overdue[0].group_id, overdue_group);
+    assert_eq!(overdue[0].client_id, overdue_joiner);
+    assert_eq!(overdue[0].reminders, 0);
+    assert!(overdue[0].last_reminded_at.is_none());
+
+    // A reported welcome is tracked again once a new one is stored for its joiner
+    db.mark_welcome_escalated(overdue[0].membership_id)
+        .await
+        .unwrap();
+    assert!(db.list_overdue_welcomes(cutoff).await.unwrap().is_empty());
+    db.clear_welcome_requests(overdue_group, &[overdue_joiner])
+        .await
+        .unwrap();
+    assert_eq!(db.list_overdue_welcomes(cutoff).await.unwrap().len(), 1);
+}
+
+/// Without a deadline nothing is tracked
+#[tokio::test]
+async fn test_sweep_disabled_without_deadline() {
+    let db = MockDatabase::new();
+    setup_group(&db, Duration::days(3)).await;
+
+    let result = sweep_overdue_welcomes(
+        &db,
+        &RandomIds,
+        &EventBus::default(),
+        &WelcomeSlaPolicy::default(),
+        &[],
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result, WelcomeSweep::default());
+}
@@ -1,20 +1,77 @@
 // Mock database for testing
 pub mod mock_db;
 
+// Fault-injecting database wrapper for testing partial failures
+pub mod fault_db;
+
+// Runs handler test cases against each database backend
+pub mod backends;
+
+// Client authentication tests
+pub mod auth_tests;
+
+// Command line tests
+pub mod cli_tests;
+
+// Startup configuration tests
+pub mod config_tests;
+
+// Database helper tests
+pub mod db_tests;
+
+// Event bus publisher tests
+pub mod events_tests;
+
+// Feature flag tests
+pub mod flags_tests;
+
+// Import tests
+pub mod import_tests;
+
+// Background job tests
+pub mod janitor_tests;
+
+// RPC metrics and SLO tests
+pub mod metrics_tests;
+
+// MLS codec tests
+pub mod mls_codec_tests;
+
+// Payload redaction tests
+pub mod redact_tests;
+
+// Secret reference tests
+pub mod secrets_tests;
+
+// Embedded server tests
+pub mod server_tests;
+
 // Service tests
 pub mod service_tests;
 
+// Runtime settings tests
+pub mod settings_tests;
+
+// Timestamp conversion tests
+pub mod timestamp_tests;
+
+// TLS termination tests
+pub mod tls_tests;
+
+// Verbose tracing tests
+pub mod trace_tests;
+
 #[cfg(test)]
 mod tests {
     use crate::mock_db::MockDatabase;
-    use hermetic_mls::service::MLSServiceImpl;
+    use hermetic_mls::service::{validation::ValidationPolicy, MLSServiceImpl};
     use std::sync::Arc;
 
     #[test]
     fn it_works() {
         // Basic sanity test
         let db = Arc::new(MockDatabase::new());
-        let _service = MLSServiceImpl::new_skip_validation(db);
+        let _service = MLSServiceImpl::new(db).with_validation_policy(ValidationPolicy::Unchecked);
         assert!(true);
     }
 }
@@ -0,0 +1,199 @@
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use chrono::{Duration, Utc};
+use hermetic_mls::db::{DatabaseInterface, Membership, MembershipRole, Message, MessageType};
+use hermetic_mls::metrics::{DeliveryMetrics, PrometheusLayer};
+use hermetic_mls::service::mls::{
+    mls_delivery_service_server::MlsDeliveryService, FetchMessagesRequest,
+};
+use hermetic_mls::service::MLSServiceImpl;
+use http_body_util::BodyExt;
+use tonic::Request;
+use tower::{Layer, Service, ServiceExt};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+// Inner service that answers every request with a 200 saying "grpc"
+#[derive(Clone)]
+struct Grpc;
+
+impl Service<http::Request<()>> for Grpc {
+    type Response = http::Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: http::Request<()>) -> Self::Future {
+        ready(Ok(http::Response::new("grpc".to_string())))
+    }
+}
+
+fn commit(group_id: Uuid, created_at: chrono::DateTime<Utc>) -> Message {
+    Message {
+        id: Uuid::new_v4(),
+        group_id: Some(group_id),
+        sender_id: Uuid::new_v4(),
+        created_at,
+        read: false,
+        message_type: MessageType::Commit,
+        proposal: None,
+        commit: Some(vec![1]),
+        welcome: None,
+        system: None,
+        application: None,
+        proposal_type: None,
+        epoch: Some(1),
+        recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
+    }
+}
+
+/// Deliveries are bucketed by latency per message type and rendered as a Prometheus histogram
+#[test]
+fn test_delivery_histogram() {
+    let metrics = DeliveryMetrics::new();
+    let now = Utc::now();
+    let group_id = Uuid::new_v4();
+    metrics.record_delivery(&commit(group_id, now - Duration::seconds(3)), now);
+    metrics.record_delivery(&commit(group_id, now - Duration::days(2)), now);
+    // Stored "after" its delivery by a skewed clock
+    metrics.record_delivery(&commit(group_id, now + Duration::seconds(1)), now);
+
+    let histogram = metrics.histogram(MessageType::Commit);
+    assert_eq!(histogram.count(), 3);
+    assert_eq!(histogram.counts[0], 1);
+    assert_eq!(histogram.counts.last(), Some(&1));
+    assert_eq!(histogram.sum_secs, 3.0 + 2.0 * 86400.0);
+    assert_eq!(metrics.histogram(MessageType::Welcome).count(), 0);
+
+    let mut text = String::new();
+    metrics.render_prometheus(&mut text);
+    let lines: Vec<_> = text.lines().collect();
+    assert!(lines.contains(&"# TYPE mls_delivery_latency_seconds histogram"));
+    assert!(lines
+        .contains(&r#"mls_delivery_latency_seconds_bucket{message_type="commit",le="0.05"} 1"#));
+    assert!(
+        lines.contains(&r#"mls_delivery_latency_seconds_bucket{message_type="commit",le="2.5"} 1"#)
+    );
+    assert!(
+        lines.contains(&r#"mls_delivery_latency_seconds_bucket{message_type="commit",le="5"} 2"#)
+    );
+    assert!(lines
+        .contains(&r#"mls_delivery_latency_seconds_bucket{message_type="commit",le="+Inf"} 3"#));
+    assert!(lines.contains(&r#"mls_delivery_latency_seconds_sum{message_type="commit"} 172803"#));
+    assert!(lines.contains(&r#"mls_delivery_latency_seconds_count{message_type="commit"} 3"#));
+    // Types nothing was delivered of are left out
+    assert!(!text.contains("welcome"));
+}
+
+/// Fetching unacknowledged messages records their delivery; refetching acknowledged ones doesn't
+#[tokio::test]
+async fn test_fetch_records_delivery_latency() {
+    let db = Arc::new(MockDatabase::new());
+    let metrics = DeliveryMetrics::new();
+    let service = MLSServiceImpl::new(db.clone()).with_delivery_metrics(metrics.clone());
+
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+    db.store_message(commit(group_id, Utc::now() - Duration::minutes(2)))
+        .await
+        .unwrap();
+
+    let fetch = |include_read| FetchMessagesRequest {
+        client_id: client_id.to_string(),
+        group_id: String::new(),
+        include_read,
+        message_types: vec![],
+        types: vec![],
+        interleave_senders: false,
+        max_application_per_sender: 0,
+        read_mask: None,
+    };
+    let response = service
+        .fetch_messages(Request::new(fetch(false)))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.messages.len(), 1);
+
+    let histogram = metrics.histogram(MessageType::Commit);
+    assert_eq!(histogram.count(), 1);
+    // Between 1 and 5 minutes
+    assert_eq!(histogram.counts[10], 1);
+
+    service
+        .fetch_messages(Request::new(fetch(true)))
+        .await
+        .unwrap();
+    assert_eq!(metrics.histogram(MessageType::Commit).count(), 1);
+}
+
+/// The layer answers GET /metrics and passes everything else through
+#[tokio::test]
+async fn test_prometheus_endpoint() {
+    let metrics = DeliveryMetrics::new();
+    let now = Utc::now();
+    metrics.record_delivery(&commit(Uuid::new_v4(), now), now);
+
+    let get = |path: &str| http::Request::get(path).body(()).unwrap();
+    let body = |response: http::Response<tonic::body::Body>| async move {
+        String::from_utf8(
+            response
+                .into_body()
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes()
+                .to_vec(),
+        )
+        .unwrap()
+    };
+
+    let layer = PrometheusLayer::new(metrics);
+    let response = layer.layer(Grpc).oneshot(get("/metrics")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/plain; version=0.0.4"
+    );
+    assert!(body(response)
+        .await
+        .contains(r#"mls_delivery_latency_seconds_count{message_type="commit"} 1"#));
+
+    let response = layer
+        .layer(Grpc)
+        .oneshot(get("/mls.v1.MlsDeliveryService/FetchMessages"))
+        .await
+        .unwrap();
+    assert_eq!(body(response).await, "grpc");
+
+    let response = PrometheusLayer::disabled()
+        .layer(Grpc)
+        .oneshot(get("/metrics"))
+        .await
+        .unwrap();
+    assert_eq!(body(response).await, "grpc");
+}
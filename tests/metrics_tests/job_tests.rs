@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use hermetic_mls::metrics::{JobTracker, RpcMetrics};
+
+/// Runs update the last-run fields and the totals, and durations go to the metrics
+#[tokio::test]
+async fn test_job_tracker_records_runs() {
+    let metrics = RpcMetrics::new(Duration::from_secs(300));
+    let jobs = JobTracker::new().with_metrics(metrics.clone());
+    jobs.register("PurgeSweep", Duration::from_secs(60));
+
+    let status = &jobs.statuses()[0];
+    assert_eq!(status.name, "PurgeSweep");
+    assert_eq!((status.runs, status.last_run_at), (0, None));
+
+    jobs.track("PurgeSweep", async { Ok::<_, String>(7) })
+        .await
+        .unwrap();
+    jobs.track("PurgeSweep", async { Err::<u64, _>("connection refused") })
+        .await
+        .unwrap_err();
+
+    let status = &jobs.statuses()[0];
+    assert_eq!((status.runs, status.failures), (2, 1));
+    assert_eq!((status.last_rows, status.rows_processed), (0, 7));
+    assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+    assert!(status.last_success_at.is_some());
+    assert!(status.last_run_at >= status.last_success_at);
+    assert_eq!(
+        metrics
+            .histogram_since("PurgeSweep", Utc::now() - chrono::Duration::minutes(1))
+            .count(),
+        2
+    );
+
+    // Runs of jobs that aren't registered, e.g. disabled ones, aren't reported
+    jobs.unregister("PurgeSweep");
+    jobs.track("PurgeSweep", async { Ok::<_, String>(1) })
+        .await
+        .unwrap();
+    assert!(jobs.statuses().is_empty());
+}
+
+/// A job is stalled once it hasn't succeeded for three intervals
+#[test]
+fn test_job_stalled() {
+    let jobs = JobTracker::new();
+    jobs.register("NoncePurge", Duration::from_secs(60));
+    let registered_at = jobs.statuses()[0].registered_at;
+    let minutes = |m| registered_at + chrono::Duration::minutes(m);
+
+    // Before its first run, it's measured from registration
+    assert!(!jobs.statuses()[0].is_stalled(minutes(3)));
+    assert!(jobs.statuses()[0].is_stalled(minutes(4)));
+
+    // Failed runs don't count
+    jobs.record("NoncePurge", minutes(2), Duration::ZERO, Ok(0));
+    jobs.record(
+        "NoncePurge",
+        minutes(4),
+        Duration::ZERO,
+        Err("timeout".to_string()),
+    );
+    assert!(!jobs.statuses()[0].is_stalled(minutes(5)));
+    assert!(jobs.statuses()[0].is_stalled(minutes(6)));
+}
@@ -0,0 +1,3 @@
+pub mod delivery_tests;
+pub mod job_tests;
+pub mod slo_tests;
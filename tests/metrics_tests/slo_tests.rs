@@ -0,0 +1,159 @@
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use hermetic_mls::metrics::{
+    layer::rpc_method,
+    slo::{parse_slos, AlertHook, AlertState, SloAlert},
+    LatencyHistogram, LatencySlo, MetricsLayer, RpcMetrics, SloConfig, SloMonitor,
+};
+use tower::{Layer, Service};
+
+// Inner service that answers every request with an empty 200
+#[derive(Clone)]
+struct Ok200;
+
+impl Service<http::Request<()>> for Ok200 {
+    type Response = http::Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: http::Request<()>) -> Self::Future {
+        ready(Ok(http::Response::new(String::new())))
+    }
+}
+
+// Alert hook that keeps every alert it receives
+#[derive(Default)]
+struct RecordingHook {
+    alerts: Mutex<Vec<SloAlert>>,
+}
+
+#[async_trait]
+impl AlertHook for RecordingHook {
+    async fn notify(&self, alert: &SloAlert) {
+        self.alerts.lock().unwrap().push(alert.clone());
+    }
+}
+
+/// SLOs parse from and print back to their written form
+#[test]
+fn test_parse_slos() {
+    let slos = parse_slos("FetchMessages p99<100ms, StoreCommit p99.9<250ms").unwrap();
+    assert_eq!(
+        slos[0],
+        LatencySlo {
+            method: "FetchMessages".to_string(),
+            percentile: 99.0,
+            threshold: Duration::from_millis(100),
+        }
+    );
+    assert_eq!(slos[1].to_string(), "StoreCommit p99.9<250ms");
+    assert!((slos[1].error_budget() - 0.001).abs() < 1e-9);
+
+    for invalid in [
+        "FetchMessages",
+        "FetchMessages p100<10ms",
+        "FetchMessages p99<fast",
+    ] {
+        assert!(parse_slos(invalid).is_err(), "{}", invalid);
+    }
+}
+
+/// Histograms count requests over a threshold and estimate quantiles from bucket bounds
+#[test]
+fn test_latency_histogram() {
+    let mut histogram = LatencyHistogram::new();
+    for millis in [1, 20, 40, 90, 300, 9000] {
+        histogram.observe(Duration::from_millis(millis));
+    }
+
+    assert_eq!(histogram.count(), 6);
+    assert_eq!(histogram.count_over(Duration::from_millis(100)), 2);
+    // Thresholds between bucket bounds round down
+    assert_eq!(histogram.count_over(Duration::from_millis(60)), 3);
+    assert_eq!(histogram.quantile_ms(0.5), Some(50));
+    assert_eq!(histogram.quantile_ms(1.0), None);
+    assert_eq!(LatencyHistogram::new().quantile_ms(0.5), None);
+}
+
+/// The metrics layer records latency per RPC method
+#[tokio::test]
+async fn test_metrics_layer_records_methods() {
+    assert_eq!(
+        rpc_method("/mls.v1.MlsDeliveryService/FetchMessages"),
+        Some("FetchMessages")
+    );
+    assert_eq!(rpc_method("/health"), None);
+
+    let metrics = RpcMetrics::new(Duration::from_secs(60));
+    let mut service = MetricsLayer::new(metrics.clone()).layer(Ok200);
+    for path in [
+        "/mls.v1.MlsDeliveryService/FetchMessages",
+        "/mls.v1.MlsDeliveryService/FetchMessages",
+        "/mls.v1.MlsAdminService/ReloadSettings",
+    ] {
+        let request = http::Request::builder().uri(path).body(()).unwrap();
+        service.call(request).await.unwrap();
+    }
+
+    assert_eq!(metrics.methods(), vec!["FetchMessages", "ReloadSettings"]);
+    let since = Utc::now() - chrono::Duration::seconds(60);
+    assert_eq!(metrics.histogram_since("FetchMessages", since).count(), 2);
+}
+
+/// Alerts fire once when the burn rate crosses the threshold, and resolve when it recovers
+#[tokio::test]
+async fn test_slo_burn_rate_alerts() {
+    let metrics = RpcMetrics::new(Duration::from_secs(600));
+    let hook = Arc::new(RecordingHook::default());
+    let config = SloConfig {
+        window: Duration::from_secs(300),
+        ..SloConfig::default()
+    };
+    let mut monitor = SloMonitor::new(metrics.clone(), config).with_hook(hook.clone());
+
+    // 5% of requests over 100ms burns a 1% budget 5x too fast: not enough to alert
+    let start = Utc::now() - chrono::Duration::seconds(900);
+    for i in 0..100 {
+        let millis = if i < 5 { 400 } else { 20 };
+        metrics.record_at("FetchMessages", Duration::from_millis(millis), start);
+    }
+    let statuses = monitor.check_at(start).await;
+    assert_eq!(statuses[0].requests, 100);
+    assert_eq!(statuses[0].slow, 5);
+    assert!((statuses[0].burn_rate - 5.0).abs() < 1e-9);
+    assert!(hook.alerts.lock().unwrap().is_empty());
+
+    // 20% slow fires once, however often it is checked
+    let later = start + chrono::Duration::seconds(400);
+    for i in 0..100 {
+        let millis = if i < 20 { 400 } else { 20 };
+        metrics.record_at("FetchMessages", Duration::from_millis(millis), later);
+    }
+    monitor.check_at(later).await;
+    monitor.check_at(later).await;
+    {
+        let alerts = hook.alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].state, AlertState::Firing);
+        assert_eq!(alerts[0].status.requests, 100);
+    }
+
+    // Once the slow requests leave the window, the alert resolves
+    let statuses = monitor
+        .check_at(later + chrono::Duration::seconds(400))
+        .await;
+    assert_eq!(statuses[0].requests, 0);
+    let alerts = hook.alerts.lock().unwrap();
+    assert_eq!(alerts.len(), 2);
+    assert_eq!(alerts[1].state, AlertState::Resolved);
+}
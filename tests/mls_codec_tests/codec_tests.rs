@@ -0,0 +1,75 @@
+use hermetic_mls::mls_codec::{self, CodecError, FramedContent, MlsCrypto, SigningKey};
+use openmls::credentials::{BasicCredential, CredentialWithKey};
+use openmls::prelude::{GroupId, MlsGroup, SignatureScheme};
+use openmls_basic_credential::SignatureKeyPair;
+use openmls_rust_crypto::OpenMlsRustCrypto;
+use tls_codec::Serialize;
+
+/// The framing of an application message carries its group and epoch
+#[test]
+fn test_parse_framing() {
+    let provider = OpenMlsRustCrypto::default();
+    let signer = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    let credential_with_key = CredentialWithKey {
+        credential: BasicCredential::new(b"alice".to_vec()).into(),
+        signature_key: signer.public().into(),
+    };
+    let mut group = MlsGroup::builder()
+        .with_group_id(GroupId::from_slice(b"codec-group"))
+        .build(&provider, &signer, credential_with_key)
+        .unwrap();
+    let message = group
+        .create_message(&provider, &signer, b"hello")
+        .unwrap()
+        .tls_serialize_detached()
+        .unwrap();
+
+    let framing = mls_codec::parse_framing(&message).unwrap();
+    assert_eq!(framing.group_id, b"codec-group");
+    assert_eq!(framing.epoch, 0);
+    assert_eq!(framing.content, FramedContent::Application);
+    // Application messages are encrypted, sender included
+    assert_eq!(framing.sender, None);
+
+    assert!(matches!(
+        mls_codec::parse_framing(&[0xff, 0x01]),
+        Err(CodecError::Malformed { .. })
+    ));
+}
+
+/// Key packages the service builds pass its own validation
+#[test]
+fn test_key_package_round_trip() {
+    let crypto = MlsCrypto::default();
+    let credential = mls_codec::basic_credential(b"alice").unwrap();
+
+    let key_package = crypto.build_key_package(&credential).unwrap();
+    let info = crypto.validate_key_package(&key_package).unwrap();
+    // MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519
+    assert_eq!(info.ciphersuite, 1);
+
+    assert!(matches!(
+        crypto.build_key_package(&[0xff]),
+        Err(CodecError::Crypto { .. })
+    ));
+}
+
+/// Signatures verify against the signing key and nothing else
+#[test]
+fn test_sign_and_verify() {
+    let crypto = MlsCrypto::default();
+    let key = SigningKey::generate().unwrap();
+    let other = SigningKey::generate().unwrap();
+    let digest = mls_codec::sha256(b"payload");
+
+    let signature = key.sign(&digest).unwrap();
+    assert!(crypto
+        .verify_ed25519(&digest, key.public(), &signature)
+        .is_ok());
+    assert!(crypto
+        .verify_ed25519(&digest, other.public(), &signature)
+        .is_err());
+    assert!(crypto
+        .verify_ed25519(&mls_codec::sha256(b"other"), key.public(), &signature)
+        .is_err());
+}
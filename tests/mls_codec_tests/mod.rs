@@ -0,0 +1 @@
+pub mod codec_tests;
@@ -1,2 +1,21 @@
+pub mod auth_tests;
+pub mod backends;
+pub mod cli_tests;
+pub mod config_tests;
+pub mod db_tests;
+pub mod events_tests;
+pub mod fault_db;
+pub mod flags_tests;
+pub mod import_tests;
+pub mod janitor_tests;
+pub mod metrics_tests;
+pub mod mls_codec_tests;
 pub mod mock_db;
+pub mod redact_tests;
+pub mod secrets_tests;
+pub mod server_tests;
 pub mod service_tests;
+pub mod settings_tests;
+pub mod timestamp_tests;
+pub mod tls_tests;
+pub mod trace_tests;
@@ -0,0 +1 @@
+pub mod redaction_tests;
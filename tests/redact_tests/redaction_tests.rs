@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+
+use chrono::Utc;
+use hermetic_mls::db::{
+    AttestationVerdict, Client, CredentialScheme, KeyPackage, Message, MessageType,
+};
+use hermetic_mls::error::ServiceError;
+use hermetic_mls::redact::{scrub, Redacted};
+use tonic::Status;
+use uuid::Uuid;
+
+/// Debug output of stored records shows the length of payloads, never their bytes
+#[test]
+fn test_debug_redacts_payloads() {
+    let client = Client {
+        id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        credential: vec![201; 12],
+        scheme: CredentialScheme::Basic,
+        device_name: "phone".to_string(),
+        last_seen: Utc::now(),
+        created_at: Utc::now(),
+        init_key: Some(vec![202; 32]),
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
+    };
+    let debug = format!("{:?}", client);
+    assert!(debug.contains(&format!("id: {:?}", client.id)));
+    assert!(debug.contains("device_name: \"phone\""));
+    assert!(debug.contains("credential: <12 bytes>"));
+    assert!(debug.contains("init_key: <32 bytes>"));
+    assert!(debug.contains("signature_key: None"));
+    assert!(!debug.contains("201, 201") && !debug.contains("202, 202"));
+
+    let key_package = KeyPackage {
+        id: Uuid::new_v4(),
+        client_id: client.id,
+        data: vec![203; 100],
+        created_at: Utc::now(),
+        used: false,
+        used_at: None,
+        deleted_at: None,
+    };
+    let debug = format!("{:#?}", key_package);
+    assert!(debug.contains("data: <100 bytes>"));
+    assert!(!debug.contains("203,"));
+
+    let message = Message {
+        id: Uuid::new_v4(),
+        group_id: Some(Uuid::new_v4()),
+        sender_id: client.id,
+        created_at: Utc::now(),
+        read: false,
+        message_type: MessageType::Application,
+        proposal: None,
+        commit: None,
+        welcome: None,
+        system: None,
+        application: Some(b"meet at noon".to_vec()),
+        proposal_type: None,
+        epoch: Some(3),
+        recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 9,
+    };
+    let debug = format!("{:?}", message);
+    assert!(debug.contains("application: <12 bytes>"));
+    assert!(debug.contains("sequence: 9"));
+    assert!(!debug.contains("109, 101, 101, 116"));
+}
+
+#[test]
+fn test_redacted_wrapper() {
+    assert_eq!(format!("{}", Redacted::of(&vec![1u8, 2, 3])), "<3 bytes>");
+    assert_eq!(format!("{:?}", Redacted::of(&b"abcd"[..])), "<4 bytes>");
+    assert_eq!(format!("{:?}", Redacted::of(&None::<Vec<u8>>)), "None");
+}
+
+/// Byte lists in library error text are replaced by their length
+#[test]
+fn test_scrub() {
+    assert_eq!(
+        scrub("Invalid(DecodingError([1, 2, 3, 4, 5, 6, 7, 8, 255]))"),
+        "Invalid(DecodingError(<9 bytes>))"
+    );
+    assert_eq!(
+        scrub("a [0,0,0,0,0,0,0,0] and b [9, 9, 9, 9, 9, 9, 9, 9, 9, 9]"),
+        "a <8 bytes> and b <10 bytes>"
+    );
+    // Short lists, lists of other values and unclosed brackets are left alone
+    for text in [
+        "leaf indices [1, 2, 3]",
+        "[256, 1, 2, 3, 4, 5, 6, 7]",
+        "[\"a\", \"b\"]",
+        "unclosed [1, 2, 3, 4, 5, 6, 7, 8",
+        "[]",
+    ] {
+        assert!(matches!(scrub(text), Cow::Borrowed(t) if t == text));
+    }
+}
+
+/// Statuses returned to clients are scrubbed too
+#[test]
+fn test_status_messages_scrubbed() {
+    let status: Status = ServiceError::internal(
+        "Failed to build key package",
+        format!("{:?}", vec![42u8; 16]),
+    )
+    .into();
+    assert_eq!(status.message(), "Failed to build key package: <16 bytes>");
+
+    let status: Status =
+        ServiceError::validation("Invalid key package format: [7, 7, 7, 7, 7, 7, 7, 7]").into();
+    assert_eq!(status.message(), "Invalid key package format: <8 bytes>");
+}
@@ -0,0 +1,2 @@
+pub mod resolver_tests;
+pub mod vault_tests;
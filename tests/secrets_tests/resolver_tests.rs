@@ -0,0 +1,74 @@
+use hermetic_mls::secrets::{SecretError, SecretResolver};
+
+/// Plain values pass through, and env: and file: references are read and trimmed
+#[test]
+fn test_resolve_env_and_file_references() {
+    let resolver = SecretResolver::new();
+    assert_eq!(
+        resolver
+            .resolve("DATABASE_URL", "postgres://localhost/mls")
+            .unwrap(),
+        "postgres://localhost/mls"
+    );
+
+    let var = format!("SECRET_TEST_{}", uuid::Uuid::new_v4().simple());
+    std::env::set_var(&var, "hunter2\n");
+    assert_eq!(
+        resolver
+            .resolve("DATABASE_PASSWORD", &format!("env:{}", var))
+            .unwrap(),
+        "hunter2"
+    );
+    std::env::remove_var(&var);
+    assert_eq!(
+        resolver
+            .resolve("DATABASE_PASSWORD", &format!("env:{}", var))
+            .unwrap_err(),
+        SecretError::MissingEnv {
+            name: "DATABASE_PASSWORD".to_string(),
+            var,
+        }
+    );
+
+    let path = std::env::temp_dir().join(format!("secret-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "s3cret\n").unwrap();
+    let reference = format!("file:{}", path.display());
+    assert_eq!(
+        resolver.resolve("DATABASE_PASSWORD", &reference).unwrap(),
+        "s3cret"
+    );
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        resolver.resolve("DATABASE_PASSWORD", &reference),
+        Err(SecretError::File { .. })
+    ));
+}
+
+/// kms: references are decrypted by the configured command, and failures name the reference
+#[test]
+fn test_resolve_kms_references() {
+    assert_eq!(
+        SecretResolver::new()
+            .resolve("DATABASE_PASSWORD", "kms:projects/p/keys/db")
+            .unwrap_err(),
+        SecretError::NoKmsCommand {
+            name: "DATABASE_PASSWORD".to_string()
+        }
+    );
+
+    let resolver = SecretResolver::new().with_kms_command(["echo", "decrypted"]);
+    assert_eq!(
+        resolver
+            .resolve("DATABASE_PASSWORD", "kms:projects/p/keys/db")
+            .unwrap(),
+        "decrypted projects/p/keys/db"
+    );
+
+    let resolver = SecretResolver::new().with_kms_command(["false"]);
+    let error = resolver
+        .resolve("DATABASE_PASSWORD", "kms:projects/p/keys/db")
+        .unwrap_err();
+    assert!(
+        matches!(&error, SecretError::Kms { reference, .. } if reference == "projects/p/keys/db")
+    );
+}
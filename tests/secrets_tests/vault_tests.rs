@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use hermetic_mls::secrets::{vault::VaultError, DbCredentials, VaultClient, VaultConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const LEASE: &str = r#"{
+    "request_id": "6b0e5b2c",
+    "lease_id": "database/creds/hermetic-mls/abc123",
+    "lease_duration": 3600,
+    "renewable": true,
+    "data": { "username": "v-mls-abc", "password": "A1a-secret" }
+}"#;
+
+/// Lease responses parse, and the refresh and connection lifetimes fit within the lease
+#[test]
+fn test_parse_lease_response() {
+    let credentials = DbCredentials::from_response(LEASE).unwrap();
+    assert_eq!(credentials.username, "v-mls-abc");
+    assert_eq!(credentials.password, "A1a-secret");
+    assert_eq!(credentials.lease_id, "database/creds/hermetic-mls/abc123");
+    assert_eq!(credentials.refresh_after(), Duration::from_secs(1800));
+    assert_eq!(
+        credentials.connection_max_lifetime(),
+        Duration::from_secs(1200)
+    );
+
+    assert!(matches!(
+        DbCredentials::from_response(r#"{"errors": ["permission denied"]}"#),
+        Err(VaultError::Response(_))
+    ));
+    assert!(matches!(
+        DbCredentials::from_response(&LEASE.replace("3600", "0")),
+        Err(VaultError::Response(_))
+    ));
+}
+
+/// Credentials are read from the role's path with the Vault token
+#[tokio::test]
+async fn test_fetch_database_credentials() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![0; 4096];
+        let len = socket.read(&mut request).await.unwrap();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            LEASE.len(),
+            LEASE
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8_lossy(&request[..len]).to_lowercase()
+    });
+
+    let client = VaultClient::new(VaultConfig {
+        addr: format!("http://{}/", addr),
+        token: "s.token".to_string(),
+        creds_path: "database/creds/hermetic-mls".to_string(),
+    });
+    let credentials = client.database_credentials().await.unwrap();
+    assert_eq!(credentials.username, "v-mls-abc");
+
+    let request = server.await.unwrap();
+    assert!(request.starts_with("get /v1/database/creds/hermetic-mls "));
+    assert!(request.contains("x-vault-token: s.token"));
+}
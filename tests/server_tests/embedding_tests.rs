@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hermetic_mls::auth::AuthMode;
+use hermetic_mls::config::Config;
+use hermetic_mls::server::ServerBuilder;
+use hermetic_mls::service::mls::{self, mls_delivery_service_server::MlsDeliveryService};
+use hermetic_mls::settings::{RuntimeSettings, SettingsHandle};
+use tonic::transport::Server;
+use tonic::Request;
+use tower::layer::util::Identity;
+
+use crate::mock_db::MockDatabase;
+
+// Helper function to build a server from the default config and the in-memory database
+async fn build() -> hermetic_mls::server::MlsServer<MockDatabase> {
+    ServerBuilder::new(Config::default(), Arc::new(MockDatabase::new()))
+        .with_settings(SettingsHandle::new(RuntimeSettings::default()))
+        .build()
+        .await
+        .unwrap()
+}
+
+/// An embedder's own database implementation backs the service the builder sets up
+#[tokio::test]
+async fn test_build_with_custom_database() {
+    let server = build().await;
+    assert_eq!(server.addr(), Config::default().server.addr);
+    assert_eq!(server.admin_addr(), Config::default().server.admin_addr);
+    assert!(server.tls().is_none());
+
+    let info = server
+        .service()
+        .get_server_info(Request::new(mls::GetServerInfoRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+}
+
+/// The embedder's layers wrap the delivery service's
+#[tokio::test]
+async fn test_router_with_embedder_layers() {
+    let server = build().await;
+
+    let embedder = Server::builder()
+        .timeout(Duration::from_secs(30))
+        .layer(Identity::new());
+    let _router = server.router(embedder.clone());
+    let _admin_router = server.admin_router(embedder);
+}
+
+/// A config that wasn't loaded through `Config::load`, and so wasn't checked, fails the build
+/// with an error instead of panicking
+#[tokio::test]
+async fn test_build_rejects_invalid_config() {
+    let build = |config: Config| async move {
+        ServerBuilder::new(config, Arc::new(MockDatabase::new()))
+            .with_settings(SettingsHandle::new(RuntimeSettings::default()))
+            .build()
+            .await
+    };
+
+    let mut config = Config::default();
+    config.auth.token_auth = AuthMode::Required;
+    let error = build(config).await.err().unwrap();
+    assert!(error.to_string().contains("JWT_ISSUER"), "{}", error);
+
+    let mut config = Config::default();
+    config.cors.allowed_origins = vec!["app.example.com".to_string()];
+    assert!(build(config).await.is_err());
+
+    let mut config = Config::default();
+    config.server.id_generator = "sequential".to_string();
+    assert!(build(config).await.is_err());
+
+    let mut config = Config::default();
+    config.slo.latency_slos = vec!["FetchMessages fast".to_string()];
+    assert!(build(config).await.is_err());
+
+    let mut config = Config::default();
+    config.events.publisher = Some("carrier-pigeon".to_string());
+    assert!(build(config).await.is_err());
+}
@@ -0,0 +1,1496 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use hermetic_mls::{
+    db::{
+        AbuseReport, AttestationVerdict, Client, CredentialScheme, DatabaseInterface, Group,
+        Membership, MembershipRole, Message, MessageType,
+    },
+    fixtures::{GroupFixture, MembershipFixture},
+    janitor::{Janitor, JanitorConfig},
+    metrics::JobTracker,
+    notices::{SystemEnvelope, SystemNotice},
+    service::{
+        mls::{
+            self, mls_admin_service_server::MlsAdminService,
+            mls_delivery_service_server::MlsDeliveryService, AddMemberRequest, CancelPurgeRequest,
+            CreateGroupRequest, DeleteFeatureFlagRequest, DestroyTenantKeyRequest,
+            DisableVerboseTracingRequest, EnableVerboseTracingRequest, EntityType,
+            FetchMessagesRequest, ForcePurgeRequest, GetGroupDiagnosticsRequest,
+            GetResidencyReportRequest, GetStorageStatsRequest, IntegrityIssueKind,
+            ListAbuseReportsRequest, ListAllClientsRequest, ListAllGroupsRequest,
+            ListFeatureFlagsRequest, ListJobStatusesRequest, ListServiceClientsRequest,
+            ListVerboseTracesRequest, RegisterClientRequest, RegisterServiceClientRequest,
+            ReloadSettingsRequest, ResolveAbuseReportRequest, RevokeServiceClientRequest,
+            RunIntegrityScanRequest, SetFeatureFlagRequest, SoftDeleteRequest,
+            StoreApplicationMessageRequest, StoreCommitRequest,
+        },
+        pagination::PageTokens,
+        validation::ValidationPolicy,
+        MLSServiceImpl,
+    },
+    settings::{RuntimeSettings, SettingsHandle},
+    trace::VerboseTracing,
+};
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+use super::{admin_request, register_client};
+use crate::mock_db::MockDatabase;
+
+/// Test the GetGroupDiagnostics admin RPC
+#[tokio::test]
+async fn test_get_group_diagnostics() {
+    // Create a mock database
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    // Create a group at epoch 10
+    let group_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 10,
+        state: Some(vec![1, 2, 3]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    // One member is up to date, one is stuck at epoch 4
+    let current_client = Uuid::new_v4();
+    let stuck_client = Uuid::new_v4();
+    for (client_id, acked) in [(current_client, 10), (stuck_client, 4)] {
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: Some(acked),
+            last_acked_at: Some(Utc::now()),
+        };
+        db.add_membership(membership).await.unwrap();
+    }
+
+    // Request diagnostics
+    let request = Request::new(GetGroupDiagnosticsRequest {
+        group_id: group_id.to_string(),
+    });
+    let response = service.get_group_diagnostics(request).await.unwrap();
+    let response = response.into_inner();
+
+    // Verify the lag of each member
+    assert_eq!(response.current_epoch, 10);
+    assert_eq!(response.members.len(), 2);
+
+    let stuck = response
+        .members
+        .iter()
+        .find(|m| m.client_id == stuck_client.to_string())
+        .expect("Stuck member not found");
+    assert_eq!(stuck.last_acked_epoch, 4);
+    assert_eq!(stuck.epochs_behind, 6);
+
+    let current = response
+        .members
+        .iter()
+        .find(|m| m.client_id == current_client.to_string())
+        .expect("Current member not found");
+    assert_eq!(current.epochs_behind, 0);
+}
+
+/// Storage stats count payload bytes, fan-out to recipients, and recent growth per group
+#[tokio::test]
+async fn test_get_storage_stats() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    // A busy group with two members, and an empty one
+    let busy_group = Uuid::new_v4();
+    let empty_group = Uuid::new_v4();
+    for group_id in [busy_group, empty_group] {
+        db.create_group(Group {
+            id: group_id,
+            creator_id: Uuid::new_v4(),
+            epoch: 1,
+            state: None,
+            mls_group_id: None,
+            tenant_id: None,
+            handle: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            is_active: true,
+            deleted_at: None,
+        })
+        .await
+        .unwrap();
+    }
+    let members = [Uuid::new_v4(), Uuid::new_v4()];
+    for client_id in members {
+        db.add_membership(Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id: busy_group,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: Some(1),
+            last_acked_at: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    // A fresh application message to both members, a welcome to one of them, and an
+    // application message from ten days ago
+    let messages = [
+        (MessageType::Application, 100, None, 0),
+        (MessageType::Welcome, 50, Some(vec![members[0]]), 0),
+        (MessageType::Application, 20, None, 10),
+    ];
+    for (message_type, len, recipients, days_ago) in messages {
+        let payload = Some(vec![0; len]);
+        let is_welcome = message_type == MessageType::Welcome;
+        db.store_message(Message {
+            id: Uuid::new_v4(),
+            group_id: Some(busy_group),
+            sender_id: members[1],
+            created_at: Utc::now() - chrono::Duration::days(days_ago),
+            read: false,
+            message_type,
+            proposal: None,
+            commit: None,
+            welcome: if is_welcome { payload.clone() } else { None },
+            system: None,
+            application: if is_welcome { None } else { payload },
+            proposal_type: None,
+            epoch: Some(1),
+            recipients,
+            extra: None,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
+        })
+        .await
+        .unwrap();
+    }
+
+    // The largest group comes first
+    let response = service
+        .get_storage_stats(Request::new(GetStorageStatsRequest {
+            group_id: String::new(),
+            limit: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.groups.len(), 2);
+    let busy = &response.groups[0];
+    assert_eq!(busy.group_id, busy_group.to_string());
+    assert_eq!(busy.messages, 3);
+    assert_eq!(busy.active_members, 2);
+    assert_eq!(busy.payload_bytes, 170);
+    assert_eq!(busy.delivered_bytes, 100 * 2 + 50 + 20 * 2);
+    assert!((busy.fanout - 290.0 / 170.0).abs() < 1e-9);
+    assert_eq!(busy.payload_bytes_last_day, 150);
+    assert_eq!(busy.payload_bytes_last_week, 150);
+    assert_eq!(response.groups[1].payload_bytes, 0);
+    assert_eq!(response.payload_bytes, 170);
+
+    // One group, by id
+    let response = service
+        .get_storage_stats(Request::new(GetStorageStatsRequest {
+            group_id: empty_group.to_string(),
+            limit: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.groups.len(), 1);
+    assert_eq!(response.groups[0].fanout, 0.0);
+
+    let status = service
+        .get_storage_stats(Request::new(GetStorageStatsRequest {
+            group_id: Uuid::new_v4().to_string(),
+            limit: 0,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+/// Test the SoftDelete and CancelPurge admin RPCs
+#[tokio::test]
+async fn test_soft_delete_and_cancel_purge() {
+    // Create a mock database
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    // Create a group
+    let group_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 0,
+        state: Some(vec![1, 2, 3]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    // Soft-delete the group
+    let request = admin_request(SoftDeleteRequest {
+        entity_type: EntityType::Group as i32,
+        id: group_id.to_string(),
+        dry_run: false,
+    });
+    service.soft_delete(request).await.unwrap();
+
+    // The group is hidden from reads
+    assert!(db.get_group(group_id).await.is_err());
+
+    // Cancel the pending purge
+    let request = admin_request(CancelPurgeRequest {
+        entity_type: EntityType::Group as i32,
+        id: group_id.to_string(),
+    });
+    service.cancel_purge(request).await.unwrap();
+
+    // The group is visible and active again
+    let group = db.get_group(group_id).await.unwrap();
+    assert!(group.is_active);
+    assert!(group.deleted_at.is_none());
+
+    // Cancelling again fails since nothing is pending
+    let request = admin_request(CancelPurgeRequest {
+        entity_type: EntityType::Group as i32,
+        id: group_id.to_string(),
+    });
+    let status = service.cancel_purge(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
+/// Deletions and purges need an operator, even if the admin service is reached without the
+/// admin listener's authentication
+#[tokio::test]
+async fn test_deletions_require_admin() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let client_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+
+    let status = service
+        .soft_delete(Request::new(SoftDeleteRequest {
+            entity_type: EntityType::Client as i32,
+            id: client_id.to_string(),
+            dry_run: false,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    let status = service
+        .force_purge(Request::new(ForcePurgeRequest {
+            entity_type: EntityType::Client as i32,
+            id: client_id.to_string(),
+            dry_run: true,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    let status = service
+        .cancel_purge(Request::new(CancelPurgeRequest {
+            entity_type: EntityType::Client as i32,
+            id: client_id.to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    // Nothing was deleted
+    assert!(db.get_client(client_id).await.is_ok());
+}
+
+/// Destroying a tenant's storage key needs an operator, and a backend that encrypts its blobs
+#[tokio::test]
+async fn test_destroy_tenant_key() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let tenant_id = Uuid::new_v4().to_string();
+
+    let status = service
+        .destroy_tenant_key(Request::new(DestroyTenantKeyRequest {
+            tenant_id: tenant_id.clone(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = service
+        .destroy_tenant_key(admin_request(DestroyTenantKeyRequest {
+            tenant_id: "not-a-uuid".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // The in-memory backend stores nothing at rest, so it has no keys to destroy
+    let status = service
+        .destroy_tenant_key(admin_request(DestroyTenantKeyRequest { tenant_id }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert!(status.message().contains("not configured"));
+}
+
+/// Test that soft-deleting a client notifies the other members of its groups
+#[tokio::test]
+async fn test_soft_delete_client_notifies_groups() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let deactivated = Uuid::new_v4();
+    let peer = Uuid::new_v4();
+    for client_id in [deactivated, peer] {
+        let client = Client {
+            id: client_id,
+            user_id: Uuid::new_v4(),
+            credential: vec![1, 2, 3, 4],
+            scheme: CredentialScheme::Basic,
+            device_name: "test-device".to_string(),
+            last_seen: Utc::now(),
+            created_at: Utc::now(),
+            init_key: None,
+            signature_key: None,
+            is_service: false,
+            attestation_verdict: AttestationVerdict::Unattested,
+            deleted_at: None,
+        };
+        db.register_client(client).await.unwrap();
+
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: Some(0),
+            last_acked_at: None,
+        };
+        db.add_membership(membership).await.unwrap();
+    }
+
+    let request = admin_request(SoftDeleteRequest {
+        entity_type: EntityType::Client as i32,
+        id: deactivated.to_string(),
+        dry_run: false,
+    });
+    service.soft_delete(request).await.unwrap();
+
+    // The remaining member receives a typed client deactivated notice
+    let messages = db
+        .fetch_messages_for_client(peer, Some(group_id), false, &[MessageType::System], true)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    let envelope = SystemEnvelope::from_bytes(messages[0].system.as_ref().unwrap()).unwrap();
+    assert_eq!(
+        envelope.notice,
+        SystemNotice::ClientDeactivated {
+            client_id: deactivated
+        }
+    );
+}
+
+/// Test the ForcePurge admin RPC
+#[tokio::test]
+async fn test_force_purge() {
+    // Create a mock database
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    // Create a group with a member and a message
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: client_id,
+        epoch: 0,
+        state: Some(vec![1, 2, 3]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    let membership = Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Admin,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: Some(0),
+        last_acked_at: None,
+    };
+    db.add_membership(membership).await.unwrap();
+
+    let message = Message {
+        id: Uuid::new_v4(),
+        group_id: Some(group_id),
+        sender_id: client_id,
+        created_at: Utc::now(),
+        read: false,
+        message_type: MessageType::Commit,
+        proposal: None,
+        commit: Some(vec![4, 5, 6]),
+        welcome: None,
+        system: None,
+        application: None,
+        proposal_type: None,
+        epoch: Some(1),
+        recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
+    };
+    db.store_message(message).await.unwrap();
+
+    // Purging an entity that isn't soft-deleted fails
+    let request = admin_request(ForcePurgeRequest {
+        entity_type: EntityType::Group as i32,
+        id: group_id.to_string(),
+        dry_run: false,
+    });
+    let status = service.force_purge(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+
+    // Soft-delete and then force the purge
+    let request = admin_request(SoftDeleteRequest {
+        entity_type: EntityType::Group as i32,
+        id: group_id.to_string(),
+        dry_run: false,
+    });
+    service.soft_delete(request).await.unwrap();
+
+    let request = admin_request(ForcePurgeRequest {
+        entity_type: EntityType::Group as i32,
+        id: group_id.to_string(),
+        dry_run: false,
+    });
+    service.force_purge(request).await.unwrap();
+
+    // The group and its dependent rows are gone for good
+    let request = admin_request(CancelPurgeRequest {
+        entity_type: EntityType::Group as i32,
+        id: group_id.to_string(),
+    });
+    assert!(service.cancel_purge(request).await.is_err());
+    assert!(db
+        .list_memberships_by_group(group_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(db
+        .fetch_messages_for_client(client_id, Some(group_id), true, &[], true)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// Dry runs of SoftDelete and ForcePurge report the rows they would change and change nothing
+#[tokio::test]
+async fn test_dry_run_deletion() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let client_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: client_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+    let group_uuid = Uuid::parse_str(&group_id).unwrap();
+    for _ in 0..2 {
+        db.store_message(Message {
+            id: Uuid::new_v4(),
+            group_id: Some(group_uuid),
+            sender_id: client_id,
+            created_at: Utc::now(),
+            read: false,
+            message_type: MessageType::Application,
+            proposal: None,
+            commit: None,
+            welcome: None,
+            system: None,
+            application: Some(vec![1, 2, 3]),
+            proposal_type: None,
+            epoch: Some(0),
+            recipients: None,
+            extra: None,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
+        })
+        .await
+        .unwrap();
+    }
+
+    let soft_delete = |dry_run: bool| {
+        service.soft_delete(admin_request(SoftDeleteRequest {
+            entity_type: EntityType::Group as i32,
+            id: group_id.clone(),
+            dry_run,
+        }))
+    };
+    let force_purge = |dry_run: bool| {
+        service.force_purge(admin_request(ForcePurgeRequest {
+            entity_type: EntityType::Group as i32,
+            id: group_id.clone(),
+            dry_run,
+        }))
+    };
+    let affected = |affected: Vec<mls::AffectedRows>| {
+        affected
+            .into_iter()
+            .filter(|a| a.rows > 0)
+            .map(|a| (a.table, a.rows))
+            .collect::<Vec<_>>()
+    };
+
+    let response = soft_delete(true).await.unwrap().into_inner();
+    assert_eq!(affected(response.affected), [("groups".to_string(), 1)]);
+    assert!(db.get_group(group_uuid).await.unwrap().is_active);
+
+    // Dry runs fail the way the real operation would
+    assert_eq!(
+        force_purge(true).await.unwrap_err().code(),
+        tonic::Code::NotFound
+    );
+
+    // Deleting the group for real posts a notice to it, a third message
+    soft_delete(false).await.unwrap();
+    let response = force_purge(true).await.unwrap().into_inner();
+    assert_eq!(
+        affected(response.affected),
+        [
+            ("message_sequences".to_string(), 1),
+            ("messages".to_string(), 3),
+            ("memberships".to_string(), 1),
+            ("groups".to_string(), 1),
+        ]
+    );
+    assert_eq!(
+        db.list_memberships_by_group(group_uuid)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // The real purge still has everything to delete
+    assert!(force_purge(false)
+        .await
+        .unwrap()
+        .into_inner()
+        .affected
+        .is_empty());
+    assert_eq!(
+        force_purge(true).await.unwrap_err().code(),
+        tonic::Code::NotFound
+    );
+}
+
+/// Test registering, constraining, and revoking a service client
+#[tokio::test]
+async fn test_service_clients() {
+    let db = Arc::new(MockDatabase::new());
+    let service =
+        MLSServiceImpl::new(db.clone()).with_validation_policy(ValidationPolicy::Unchecked);
+
+    let owner_id = Uuid::new_v4();
+    let response = service
+        .register_service_client(Request::new(RegisterServiceClientRequest {
+            user_id: owner_id.to_string(),
+            identity: "deploy-bot".to_string(),
+            name: "Deploy Bot".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let bot_id = Uuid::parse_str(&response.client_id).unwrap();
+    assert!(db.get_client(bot_id).await.unwrap().is_service);
+
+    let clients = service
+        .list_service_clients(Request::new(ListServiceClientsRequest {
+            user_id: owner_id.to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .clients;
+    assert_eq!(clients.len(), 1);
+    assert_eq!(clients[0].device_name, "Deploy Bot");
+    assert!(clients[0].is_service);
+
+    let group_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    let admin_id = Uuid::new_v4();
+    let membership = Membership {
+        id: Uuid::new_v4(),
+        client_id: admin_id,
+        group_id,
+        role: MembershipRole::Admin,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    };
+    db.add_membership(membership).await.unwrap();
+
+    let post = || {
+        service.store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: bot_id.to_string(),
+            message: vec![1, 2, 3],
+            epoch: 0,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+    };
+
+    // Bots can only post to groups they've been added to
+    let status = post().await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    // ...and only as regular members
+    let status = service
+        .add_member(Request::new(AddMemberRequest {
+            group_id: group_id.to_string(),
+            client_id: bot_id.to_string(),
+            role: String::new(),
+            member_role: mls::MembershipRole::Admin as i32,
+            sender_id: admin_id.to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    service
+        .add_member(Request::new(AddMemberRequest {
+            group_id: group_id.to_string(),
+            client_id: bot_id.to_string(),
+            role: String::new(),
+            member_role: mls::MembershipRole::Member as i32,
+            sender_id: admin_id.to_string(),
+        }))
+        .await
+        .unwrap();
+    post().await.unwrap();
+
+    // Bots can't administer groups
+    let status = service
+        .store_commit(Request::new(StoreCommitRequest {
+            group_id: group_id.to_string(),
+            sender_id: bot_id.to_string(),
+            commit: vec![1, 2, 3],
+            epoch: 1,
+            extra: vec![],
+            proposal_refs: vec![],
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    let status = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: bot_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    // Revoking deactivates the bot
+    service
+        .revoke_service_client(Request::new(RevokeServiceClientRequest {
+            client_id: bot_id.to_string(),
+        }))
+        .await
+        .unwrap();
+    assert!(db.get_client(bot_id).await.is_err());
+}
+
+/// Admin listings narrow clients and groups across all users with a filter expression
+#[tokio::test]
+async fn test_list_all_with_filters() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let owner_id = Uuid::new_v4();
+    let alice = register_client(db.as_ref(), owner_id).await;
+    register_client(db.as_ref(), Uuid::new_v4()).await;
+    let bot_id = service
+        .register_service_client(Request::new(RegisterServiceClientRequest {
+            user_id: owner_id.to_string(),
+            identity: "deploy-bot".to_string(),
+            name: "Deploy Bot".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .client_id;
+
+    let list_clients = |filter: &str, limit: u32| {
+        service.list_all_clients(Request::new(ListAllClientsRequest {
+            filter: filter.to_string(),
+            limit,
+            page_token: String::new(),
+        }))
+    };
+    let ids =
+        |clients: Vec<mls::Client>| -> Vec<String> { clients.into_iter().map(|c| c.id).collect() };
+
+    let clients = list_clients("", 0).await.unwrap().into_inner().clients;
+    assert_eq!(clients.len(), 3);
+    let clients = list_clients("is_service=true", 0)
+        .await
+        .unwrap()
+        .into_inner()
+        .clients;
+    assert_eq!(ids(clients), vec![bot_id]);
+    let filter = format!("user_id={} AND is_service=false", owner_id);
+    let clients = list_clients(&filter, 0).await.unwrap().into_inner().clients;
+    assert_eq!(ids(clients), vec![alice.to_string()]);
+    let clients = list_clients("", 2).await.unwrap().into_inner().clients;
+    assert_eq!(clients.len(), 2);
+
+    // Bad filters and limits are reported like any other invalid field
+    for (filter, limit) in [("credential=abc", 0), ("is_service=maybe", 0), ("", 1001)] {
+        let status = list_clients(filter, limit).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", filter);
+    }
+
+    // Groups are listed without their state, soft-deleted ones included
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: alice.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+    service
+        .soft_delete(admin_request(SoftDeleteRequest {
+            entity_type: EntityType::Group as i32,
+            id: group_id.clone(),
+            dry_run: false,
+        }))
+        .await
+        .unwrap();
+
+    let list_groups = |filter: &str| {
+        service.list_all_groups(Request::new(ListAllGroupsRequest {
+            filter: filter.to_string(),
+            limit: 0,
+            page_token: String::new(),
+        }))
+    };
+    let groups = list_groups("deleted_at!=null epoch>=0")
+        .await
+        .unwrap()
+        .into_inner()
+        .groups;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].id, group_id);
+    assert!(groups[0].state.is_empty());
+    let groups = list_groups("deleted_at=null")
+        .await
+        .unwrap()
+        .into_inner()
+        .groups;
+    assert!(groups.is_empty());
+}
+
+/// Test reloading the runtime settings through the admin service
+#[tokio::test]
+async fn test_reload_settings() {
+    let path = std::env::temp_dir().join(format!("settings-{}.json", Uuid::new_v4()));
+    std::fs::write(&path, r#"{"key_package_low_threshold": 3}"#).unwrap();
+
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::load(Some(path.clone())).unwrap();
+    let service = MLSServiceImpl::new(db).with_settings(settings.clone());
+    assert_eq!(settings.current().key_package_low_threshold, 3);
+
+    std::fs::write(&path, r#"{"key_package_low_threshold": 8}"#).unwrap();
+    let response = service
+        .reload_settings(Request::new(ReloadSettingsRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    let reloaded: RuntimeSettings = serde_json::from_str(&response.settings).unwrap();
+    assert_eq!(reloaded.key_package_low_threshold, 8);
+    assert_eq!(settings.current(), reloaded);
+
+    // Invalid settings are rejected and the current ones kept
+    std::fs::write(&path, r#"{"log_level": "loud"}"#).unwrap();
+    let status = service
+        .reload_settings(Request::new(ReloadSettingsRequest {}))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    assert_eq!(settings.current().key_package_low_threshold, 8);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Tenants tagged with another region can't write here, and rows they stored before being
+/// tagged are reported
+#[tokio::test]
+async fn test_tenant_residency() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::default();
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings.clone());
+
+    let eu_user = Uuid::new_v4();
+    let us_user = Uuid::new_v4();
+    let eu_client = register_client(db.as_ref(), eu_user).await;
+    let create_group = |creator_id: Uuid| {
+        Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        })
+    };
+    service.create_group(create_group(eu_client)).await.unwrap();
+
+    settings.set(RuntimeSettings {
+        data_region: Some("us".to_string()),
+        tenant_regions: [(eu_user, "eu".to_string()), (us_user, "us".to_string())].into(),
+        ..settings.current()
+    });
+    let register = |user_id: Uuid| {
+        Request::new(RegisterClientRequest {
+            user_id: user_id.to_string(),
+            identity: "identity".to_string(),
+            device_name: "phone".to_string(),
+            signature_key: vec![],
+            attestation: None,
+        })
+    };
+    let status = service
+        .register_client(register(eu_user))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let status = service
+        .create_group(create_group(eu_client))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    service.register_client(register(us_user)).await.unwrap();
+
+    let report = service
+        .get_residency_report(Request::new(GetResidencyReportRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(report.data_region, "us");
+    assert_eq!(
+        report.tenants,
+        vec![mls::MisplacedTenant {
+            user_id: eu_user.to_string(),
+            region: "eu".to_string(),
+            clients: 1,
+            key_packages: 0,
+            groups: 1,
+            backups: 0,
+        }]
+    );
+}
+
+/// Test managing feature flags through the admin service
+#[tokio::test]
+async fn test_feature_flags() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let canary_group = Uuid::new_v4();
+
+    let set = |percentage, group_ids: Vec<String>| {
+        service.set_feature_flag(Request::new(SetFeatureFlagRequest {
+            flag: Some(mls::FeatureFlag {
+                name: "strict_validation".to_string(),
+                enabled: true,
+                percentage,
+                group_ids,
+                user_ids: vec![],
+                updated_at: String::new(),
+            }),
+        }))
+    };
+
+    // Invalid percentages and group ids are rejected
+    let status = set(101, vec!["not-a-uuid".to_string()]).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    let flag = set(0, vec![canary_group.to_string()])
+        .await
+        .unwrap()
+        .into_inner()
+        .flag
+        .unwrap();
+    assert!(!flag.updated_at.is_empty());
+    assert_eq!(db.list_feature_flags().await.unwrap().len(), 1);
+
+    // The service's own snapshot is updated immediately
+    let flags = service.feature_flags();
+    assert!(flags.is_enabled("strict_validation", Some(canary_group), None));
+    assert!(!flags.is_enabled("strict_validation", Some(Uuid::new_v4()), None));
+
+    let response = service
+        .list_feature_flags(Request::new(ListFeatureFlagsRequest {
+            group_id: canary_group.to_string(),
+            user_id: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.flags.len(), 1);
+    assert_eq!(
+        response.enabled_flags,
+        vec!["strict_validation".to_string()]
+    );
+
+    service
+        .delete_feature_flag(Request::new(DeleteFeatureFlagRequest {
+            name: "strict_validation".to_string(),
+        }))
+        .await
+        .unwrap();
+    assert!(!flags.is_enabled("strict_validation", Some(canary_group), None));
+
+    let status = service
+        .delete_feature_flag(Request::new(DeleteFeatureFlagRequest {
+            name: "strict_validation".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
+/// Test turning verbose tracing of a group on and off through the admin service, and which
+/// operations it traces
+#[tokio::test]
+async fn test_verbose_tracing() {
+    let db = Arc::new(MockDatabase::new());
+    let traces = VerboseTracing::default();
+    let service = MLSServiceImpl::new(db.clone())
+        .with_validation_policy(ValidationPolicy::Unchecked)
+        .with_verbose_tracing(traces.clone());
+    let (group_id, other_group) = (Uuid::new_v4(), Uuid::new_v4());
+    let member_id = Uuid::new_v4();
+    for id in [group_id, other_group] {
+        GroupFixture::new()
+            .with_id(id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+        MembershipFixture::new(id, member_id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+
+    let enable = |group_id: String, client_id: String, duration_secs, reason: &str| {
+        service.enable_verbose_tracing(Request::new(EnableVerboseTracingRequest {
+            group_id,
+            client_id,
+            duration_secs,
+            reason: reason.to_string(),
+        }))
+    };
+
+    // Exactly one target, a reason and at most a day are required
+    for (group, client, duration_secs, reason) in [
+        (String::new(), String::new(), 0, "TICKET-1234"),
+        (
+            group_id.to_string(),
+            member_id.to_string(),
+            0,
+            "TICKET-1234",
+        ),
+        (
+            group_id.to_string(),
+            String::new(),
+            24 * 60 * 60 + 1,
+            "TICKET-1234",
+        ),
+        (group_id.to_string(), String::new(), 0, " "),
+    ] {
+        let status = enable(group, client, duration_secs, reason)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    // Traces last an hour by default
+    let trace = enable(group_id.to_string(), String::new(), 0, "TICKET-1234")
+        .await
+        .unwrap()
+        .into_inner()
+        .trace
+        .unwrap();
+    let lifetime = chrono::DateTime::parse_from_rfc3339(&trace.expires_at).unwrap()
+        - chrono::DateTime::parse_from_rfc3339(&trace.created_at).unwrap();
+    assert_eq!(lifetime, chrono::Duration::hours(1));
+    assert_eq!(db.list_verbose_traces(Utc::now()).await.unwrap().len(), 1);
+
+    // Storing to and fetching from the traced group are logged, the other group's traffic isn't
+    for id in [group_id, other_group] {
+        service
+            .store_application_message(Request::new(StoreApplicationMessageRequest {
+                group_id: id.to_string(),
+                sender_id: member_id.to_string(),
+                message: vec![1, 2, 3],
+                epoch: 0,
+                extra: vec![],
+                ttl_secs: 0,
+            }))
+            .await
+            .unwrap();
+        service
+            .fetch_messages(Request::new(FetchMessagesRequest {
+                client_id: member_id.to_string(),
+                group_id: id.to_string(),
+                include_read: false,
+                message_types: vec![],
+                types: vec![],
+                interleave_senders: false,
+                max_application_per_sender: 0,
+                read_mask: None,
+            }))
+            .await
+            .unwrap();
+    }
+    let listed = service
+        .list_verbose_traces(Request::new(ListVerboseTracesRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .traces;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].group_id, group_id.to_string());
+    assert_eq!((listed[0].lines_logged, listed[0].lines_dropped), (2, 0));
+
+    let disable = || {
+        service.disable_verbose_tracing(Request::new(DisableVerboseTracingRequest {
+            trace_id: trace.id.clone(),
+        }))
+    };
+    disable().await.unwrap();
+    assert!(traces.list().is_empty());
+    assert_eq!(disable().await.unwrap_err().code(), Code::NotFound);
+}
+
+/// Admin listings page with signed tokens, bound to the listing and filter they were issued for
+#[tokio::test]
+async fn test_admin_listing_page_tokens() {
+    let db = Arc::new(MockDatabase::new());
+    let service =
+        MLSServiceImpl::new(db.clone()).with_page_tokens(PageTokens::new("page-token-secret"));
+
+    let user_id = Uuid::new_v4();
+    for _ in 0..5 {
+        register_client(db.as_ref(), user_id).await;
+    }
+    register_client(db.as_ref(), Uuid::new_v4()).await;
+    let filter = format!("user_id={}", user_id);
+    let list_clients = |filter: &str, page_token: &str| {
+        service.list_all_clients(Request::new(ListAllClientsRequest {
+            filter: filter.to_string(),
+            limit: 2,
+            page_token: page_token.to_string(),
+        }))
+    };
+
+    let mut seen = Vec::new();
+    let mut page_token = String::new();
+    let mut pages = 0;
+    loop {
+        let response = list_clients(&filter, &page_token)
+            .await
+            .unwrap()
+            .into_inner();
+        seen.extend(response.clients.into_iter().map(|c| c.id));
+        pages += 1;
+        if response.next_page_token.is_empty() {
+            break;
+        }
+        page_token = response.next_page_token;
+    }
+    assert_eq!(pages, 3);
+    assert_eq!(seen.len(), 5);
+    seen.sort();
+    seen.dedup();
+    assert_eq!(seen.len(), 5);
+
+    // Tokens are rejected with another filter, listing or key, or once tampered with
+    let page_token = list_clients(&filter, "")
+        .await
+        .unwrap()
+        .into_inner()
+        .next_page_token;
+    let status = list_clients("", &page_token).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let mut tampered = page_token.clone().into_bytes();
+    tampered[4] = if tampered[4] == b'A' { b'B' } else { b'A' };
+    let status = list_clients(&filter, &String::from_utf8(tampered).unwrap())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = service
+        .list_all_groups(Request::new(ListAllGroupsRequest {
+            filter: filter.clone(),
+            limit: 2,
+            page_token: page_token.clone(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let other = MLSServiceImpl::new(db.clone());
+    let status = other
+        .list_all_clients(Request::new(ListAllClientsRequest {
+            filter: filter.clone(),
+            limit: 2,
+            page_token: page_token.clone(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // Instances sharing the key accept each other's tokens
+    let other = MLSServiceImpl::new(db).with_page_tokens(PageTokens::new("page-token-secret"));
+    let clients = other
+        .list_all_clients(Request::new(ListAllClientsRequest {
+            filter,
+            limit: 2,
+            page_token,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .clients;
+    assert_eq!(clients.len(), 2);
+}
+
+/// The janitor's sweeps are reported through ListJobStatuses
+#[tokio::test]
+async fn test_list_job_statuses() {
+    let db = Arc::new(MockDatabase::new());
+    let jobs = JobTracker::new();
+    let service = MLSServiceImpl::new(db.clone()).with_jobs(jobs.clone());
+    let janitor = Janitor::new(db, JanitorConfig::default()).with_jobs(jobs);
+
+    let list = || async {
+        service
+            .list_job_statuses(Request::new(ListJobStatusesRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .jobs
+    };
+    let statuses = list().await;
+    let names: Vec<_> = statuses.iter().map(|job| job.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "KeyPackageSweep",
+            "MessageExpiry",
+            "NoncePurge",
+            "PurgeSweep",
+            "StuckMemberSweep"
+        ]
+    );
+    assert!(statuses
+        .iter()
+        .all(|job| job.runs == 0 && job.last_run_at.is_empty() && job.interval_secs == 300));
+
+    janitor.run_once().await;
+    for job in list().await {
+        assert_eq!((job.runs, job.failures), (1, 0), "{}", job.name);
+        assert!(!job.last_run_at.is_empty() && !job.stalled);
+        assert!(job.last_error.is_empty());
+    }
+}
+
+/// The integrity scan reports corrupted payloads and orphaned rows, and repairs the orphans
+#[tokio::test]
+async fn test_run_integrity_scan() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let client_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let group_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: client_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+
+    // An intact message, one whose payload changed after it was hashed, and one whose group is
+    // gone
+    let message = |group_id: Uuid| {
+        Message {
+            id: Uuid::new_v4(),
+            group_id: Some(group_id),
+            sender_id: client_id,
+            created_at: Utc::now(),
+            read: false,
+            message_type: MessageType::Application,
+            proposal: None,
+            commit: None,
+            welcome: None,
+            system: None,
+            application: Some(b"hello".to_vec()),
+            proposal_type: None,
+            epoch: Some(0),
+            recipients: None,
+            extra: None,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
+        }
+        .with_payload_hash()
+    };
+    db.store_message(message(group_id)).await.unwrap();
+    let corrupted = Message {
+        application: Some(b"hellO".to_vec()),
+        ..message(group_id)
+    };
+    db.store_message(corrupted.clone()).await.unwrap();
+    let orphaned_group = Uuid::new_v4();
+    let orphaned = message(orphaned_group);
+    db.store_message(orphaned.clone()).await.unwrap();
+
+    // A live member, and an active membership of a client that was never registered
+    let unknown_client = Uuid::new_v4();
+    let mut orphaned_membership = None;
+    for member in [client_id, unknown_client] {
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id: member,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        };
+        if member == unknown_client {
+            orphaned_membership = Some(membership.id);
+        }
+        db.add_membership(membership).await.unwrap();
+    }
+
+    let scan = |repair: bool| {
+        service.run_integrity_scan(Request::new(RunIntegrityScanRequest { repair, limit: 0 }))
+    };
+
+    // A dry scan reports every issue and changes nothing
+    let report = scan(false).await.unwrap().into_inner();
+    assert_eq!(report.repaired, 0);
+    assert!(!report.scanned_at.is_empty());
+    let issues: Vec<_> = report
+        .issues
+        .iter()
+        .map(|issue| {
+            (
+                issue.kind(),
+                issue.id.clone(),
+                issue.related_id.clone(),
+                issue.repair.as_str(),
+                issue.repaired,
+            )
+        })
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            (
+                IntegrityIssueKind::PayloadHashMismatch,
+                corrupted.id.to_string(),
+                group_id.to_string(),
+                "",
+                false
+            ),
+            (
+                IntegrityIssueKind::OrphanedMessage,
+                orphaned.id.to_string(),
+                orphaned_group.to_string(),
+                "soft_delete_message",
+                false
+            ),
+            (
+                IntegrityIssueKind::OrphanedMembership,
+                orphaned_membership.unwrap().to_string(),
+                unknown_client.to_string(),
+                "remove_membership",
+                false
+            ),
+        ]
+    );
+
+    // Repairing retires the orphans; the corrupted payload is left for an operator
+    let report = scan(true).await.unwrap().into_inner();
+    assert_eq!(report.repaired, 2);
+    assert!(report
+        .issues
+        .iter()
+        .all(|issue| issue.repaired == (issue.kind() != IntegrityIssueKind::PayloadHashMismatch)));
+    assert!(db
+        .get_membership(orphaned_membership.unwrap())
+        .await
+        .unwrap()
+        .removed_at
+        .is_some());
+
+    let report = scan(true).await.unwrap().into_inner();
+    assert_eq!(report.repaired, 0);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(
+        report.issues[0].kind(),
+        IntegrityIssueKind::PayloadHashMismatch
+    );
+}
+
+/// Moderators list open abuse reports and resolve each once
+#[tokio::test]
+async fn test_abuse_report_review() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let report = |group_id: Uuid, reason: &str| AbuseReport {
+        id: Uuid::new_v4(),
+        group_id,
+        reporter_id: Uuid::new_v4(),
+        message_ids: vec![Uuid::new_v4()],
+        reason: reason.to_string(),
+        excerpt: None,
+        created_at: Utc::now(),
+        resolved_at: None,
+        resolution: None,
+    };
+    let group_id = Uuid::new_v4();
+    let first = report(group_id, "spam");
+    let second = report(Uuid::new_v4(), "harassment");
+    db.store_abuse_report(first.clone()).await.unwrap();
+    db.store_abuse_report(second.clone()).await.unwrap();
+
+    let list = |filter: &str| {
+        service.list_abuse_reports(Request::new(ListAbuseReportsRequest {
+            filter: filter.to_string(),
+            limit: 0,
+            page_token: String::new(),
+        }))
+    };
+    let resolve = |report_id: Uuid| {
+        service.resolve_abuse_report(Request::new(ResolveAbuseReportRequest {
+            report_id: report_id.to_string(),
+            resolution: "Removed the sender".to_string(),
+        }))
+    };
+
+    let reports = list("").await.unwrap().into_inner().reports;
+    assert_eq!(reports.len(), 2);
+    let reports = list(&format!("group_id={}", group_id))
+        .await
+        .unwrap()
+        .into_inner()
+        .reports;
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].reason, "spam");
+    assert!(reports[0].resolved_at.is_empty());
+
+    resolve(first.id).await.unwrap();
+    let status = resolve(first.id).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let status = resolve(Uuid::new_v4()).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    // Only the second report is still open
+    let open = list("resolved_at=null").await.unwrap().into_inner().reports;
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].id, second.id.to_string());
+    let resolved = list("resolved_at!=null")
+        .await
+        .unwrap()
+        .into_inner()
+        .reports;
+    assert_eq!(resolved[0].resolution, "Removed the sender");
+}
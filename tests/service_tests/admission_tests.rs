@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use hermetic_mls::service::admission::{
+    AdmissionConfig, AdmissionController, AdmissionLayer, RpcClass,
+};
+use tower::{Layer, Service};
+
+use crate::settings_tests::Ok200;
+
+/// Commits and welcomes are critical, application messages and list calls are shed first, and
+/// other services aren't subject to admission control
+#[test]
+fn test_rpc_classes() {
+    let class = |method: &str| RpcClass::of_path(&format!("/mls.v1.MlsDeliveryService/{}", method));
+    assert_eq!(class("StoreCommit"), Some(RpcClass::Critical));
+    assert_eq!(class("StoreWelcome"), Some(RpcClass::Critical));
+    assert_eq!(class("StoreApplicationMessage"), Some(RpcClass::Bulk));
+    assert_eq!(class("ListGroups"), Some(RpcClass::Bulk));
+    assert_eq!(class("FetchMessages"), Some(RpcClass::Standard));
+    assert_eq!(
+        RpcClass::of_path("/mls.MlsDeliveryService/StoreCommit"),
+        Some(RpcClass::Critical)
+    );
+    assert_eq!(
+        RpcClass::of_path("/mls.v1.MlsAdminService/ForcePurge"),
+        None
+    );
+    assert_eq!(RpcClass::of_path("/grpc.health.v1.Health/Check"), None);
+}
+
+/// Lower classes leave headroom for higher ones, queue up to their limit, and freed slots go
+/// to the highest class waiting
+#[tokio::test]
+async fn test_priority_admission() {
+    let controller = AdmissionController::new(AdmissionConfig {
+        max_in_flight: 4,
+        max_queued: [1, 1, 1],
+        queue_timeout: Duration::from_secs(5),
+    });
+
+    // Bulk requests may only fill half the slots; the next one waits and the one after that is
+    // rejected
+    let bulk_1 = controller.admit(RpcClass::Bulk).await.unwrap();
+    let bulk_2 = controller.admit(RpcClass::Bulk).await.unwrap();
+    let queued_bulk = tokio::spawn({
+        let controller = controller.clone();
+        async move { controller.admit(RpcClass::Bulk).await.map(|_| ()) }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let status = controller.admit(RpcClass::Bulk).await.err().unwrap();
+    assert_eq!(status.code(), tonic::Code::Unavailable);
+    assert_eq!(controller.rejected(), [0, 0, 1]);
+
+    // Higher classes still get the remaining slots
+    let standard = controller.admit(RpcClass::Standard).await.unwrap();
+    let critical = controller.admit(RpcClass::Critical).await.unwrap();
+    assert_eq!(controller.in_flight(), 4);
+    let queued_critical = tokio::spawn({
+        let controller = controller.clone();
+        async move { controller.admit(RpcClass::Critical).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // A freed slot goes to the waiting commit, not the bulk request queued before it
+    drop(bulk_1);
+    let handed_over = queued_critical.await.unwrap().unwrap();
+    assert_eq!(controller.in_flight(), 4);
+    assert!(!queued_bulk.is_finished());
+
+    // The bulk request runs once load is back under its share
+    drop(standard);
+    drop(critical);
+    drop(handed_over);
+    queued_bulk.await.unwrap().unwrap();
+    drop(bulk_2);
+    assert_eq!(controller.in_flight(), 0);
+}
+
+/// Queued requests are rejected after the queue timeout, and the layer answers them with
+/// UNAVAILABLE without calling the service
+#[tokio::test]
+async fn test_admission_layer() {
+    let controller = AdmissionController::new(AdmissionConfig {
+        max_in_flight: 1,
+        max_queued: [1, 1, 1],
+        queue_timeout: Duration::from_millis(20),
+    });
+    let mut service = AdmissionLayer::new(controller.clone()).layer(Ok200);
+    let request = |path: &str| http::Request::builder().uri(path).body(()).unwrap();
+
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/FetchMessages"))
+        .await
+        .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+    assert_eq!(controller.in_flight(), 0);
+
+    let permit = controller.admit(RpcClass::Critical).await.unwrap();
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/FetchMessages"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get("grpc-status").unwrap(),
+        &(tonic::Code::Unavailable as i32).to_string()
+    );
+    assert_eq!(controller.rejected(), [0, 1, 0]);
+
+    // The admin service is never held back
+    let response = service
+        .call(request("/mls.v1.MlsAdminService/ListJobStatuses"))
+        .await
+        .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+    drop(permit);
+    assert_eq!(controller.in_flight(), 0);
+}
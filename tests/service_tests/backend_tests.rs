@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use chrono::Duration;
+use hermetic_mls::{
+    db::{DatabaseInterface, EntityKind, MembershipRole, MessageType},
+    fixtures::{ClientFixture, GroupFixture, KeyPackageFixture, MembershipFixture, MessageFixture},
+    service::{
+        mls::{
+            mls_admin_service_server::MlsAdminService,
+            mls_delivery_service_server::MlsDeliveryService, AckMessagesRequest,
+            ClaimKeyPackageRequest, FetchMessagesRequest, FetchMessagesResponse,
+            GetMembershipRequest, ListAllGroupsRequest, ListGroupsRequest, ListMembershipsRequest,
+            RemoveMemberRequest,
+        },
+        MLSServiceImpl,
+    },
+    timestamps,
+};
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+// Handler tests whose outcome depends on which rows the backend filters out, run against every
+// backend
+crate::backend_tests!(
+    test_claim_skips_used_key_packages,
+    test_used_key_packages_purged_by_use,
+    test_removed_membership_is_inactive,
+    test_fetch_requires_membership,
+    test_fetch_skips_acknowledged,
+    test_list_groups_skips_inactive_and_deleted,
+    test_group_listings_include_handles,
+);
+
+/// A group created by a fresh client, who is its admin
+async fn setup_group<DB: DatabaseInterface>(db: &DB) -> (Uuid, Uuid) {
+    let admin = ClientFixture::new().insert(db).await.unwrap();
+    let group = GroupFixture::new()
+        .with_creator(admin.id)
+        .insert(db)
+        .await
+        .unwrap();
+    MembershipFixture::new(group.id, admin.id)
+        .with_role(MembershipRole::Admin)
+        .insert(db)
+        .await
+        .unwrap();
+    (group.id, admin.id)
+}
+
+/// Fetch the client's messages, of one group or all
+async fn fetch<DB: DatabaseInterface + 'static>(
+    service: &MLSServiceImpl<DB>,
+    client_id: Uuid,
+    group_id: Option<Uuid>,
+    include_read: bool,
+) -> FetchMessagesResponse {
+    service
+        .fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.map(|id| id.to_string()).unwrap_or_default(),
+            include_read,
+            message_types: vec![],
+            types: vec![],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+/// Claiming hands out the oldest unused key package, and never a used or deleted one
+async fn test_claim_skips_used_key_packages<DB: DatabaseInterface + 'static>(db: Arc<DB>) {
+    let service = MLSServiceImpl::new(db.clone());
+    let (group_id, admin_id) = setup_group(db.as_ref()).await;
+    let client = ClientFixture::new().insert(db.as_ref()).await.unwrap();
+    let now = timestamps::now();
+    KeyPackageFixture::new(client.id)
+        .created_at(now - Duration::minutes(3))
+        .used()
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    let deleted = KeyPackageFixture::new(client.id)
+        .created_at(now - Duration::minutes(2))
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    db.soft_delete(EntityKind::KeyPackage, deleted.id)
+        .await
+        .unwrap();
+    let unused = KeyPackageFixture::new(client.id)
+        .created_at(now - Duration::minutes(1))
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let claim = || {
+        service.claim_key_package(Request::new(ClaimKeyPackageRequest {
+            client_id: client.id.to_string(),
+            group_id: group_id.to_string(),
+            claimer_id: admin_id.to_string(),
+        }))
+    };
+    let claimed = claim().await.unwrap().into_inner().key_package.unwrap();
+    assert_eq!(claimed.id, unused.id.to_string());
+    assert_eq!(claim().await.unwrap_err().code(), Code::NotFound);
+}
+
+/// Used key packages are purged by when they were claimed, not when they were published
+async fn test_used_key_packages_purged_by_use<DB: DatabaseInterface + 'static>(db: Arc<DB>) {
+    let service = MLSServiceImpl::new(db.clone());
+    let (group_id, admin_id) = setup_group(db.as_ref()).await;
+    let client = ClientFixture::new().insert(db.as_ref()).await.unwrap();
+    let key_package = KeyPackageFixture::new(client.id)
+        .created_at(timestamps::now() - Duration::days(40))
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    service
+        .claim_key_package(Request::new(ClaimKeyPackageRequest {
+            client_id: client.id.to_string(),
+            group_id: group_id.to_string(),
+            claimer_id: admin_id.to_string(),
+        }))
+        .await
+        .unwrap();
+
+    let purged = db
+        .purge_used_key_packages(timestamps::now() - Duration::days(30))
+        .await
+        .unwrap();
+    assert_eq!(purged, 0);
+    let purged = db
+        .purge_used_key_packages(timestamps::now() + Duration::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(purged, 1);
+    assert!(db.get_key_package(key_package.id).await.is_err());
+}
+
+/// A removed membership no longer counts: it's left out of the group's memberships and the
+/// client's groups, but can still be looked up
+async fn test_removed_membership_is_inactive<DB: DatabaseInterface + 'static>(db: Arc<DB>) {
+    let service = MLSServiceImpl::new(db.clone());
+    let (group_id, admin_id) = setup_group(db.as_ref()).await;
+    let member = ClientFixture::new().insert(db.as_ref()).await.unwrap();
+    let membership = MembershipFixture::new(group_id, member.id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    service
+        .remove_member(Request::new(RemoveMemberRequest {
+            membership_id: membership.id.to_string(),
+            sender_id: admin_id.to_string(),
+        }))
+        .await
+        .unwrap();
+
+    let memberships = service
+        .list_memberships(Request::new(ListMembershipsRequest {
+            group_id: group_id.to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .memberships;
+    let clients: Vec<_> = memberships.iter().map(|m| m.client_id.clone()).collect();
+    assert_eq!(clients, vec![admin_id.to_string()]);
+
+    let groups = service
+        .list_groups(Request::new(ListGroupsRequest {
+            client_id: member.id.to_string(),
+            include_state: false,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .groups;
+    assert!(groups.is_empty());
+
+    let removed = service
+        .get_membership(Request::new(GetMembershipRequest {
+            membership_id: membership.id.to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .membership
+        .unwrap();
+    assert!(!removed.removed_at.is_empty());
+}
+
+/// A group's messages go to clients with a membership in it, removed members included so they
+/// get the commit removing them, and never to other clients
+async fn test_fetch_requires_membership<DB: DatabaseInterface + 'static>(db: Arc<DB>) {
+    let service = MLSServiceImpl::new(db.clone());
+    let (group_id, admin_id) = setup_group(db.as_ref()).await;
+    let removed = ClientFixture::new().insert(db.as_ref()).await.unwrap();
+    MembershipFixture::new(group_id, removed.id)
+        .removed()
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    let outsider = ClientFixture::new().insert(db.as_ref()).await.unwrap();
+    let commit = MessageFixture::commit(group_id, 1)
+        .sent_by(admin_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    for client_id in [admin_id, removed.id] {
+        for group in [Some(group_id), None] {
+            let messages = fetch(&service, client_id, group, true).await.messages;
+            let ids: Vec<_> = messages.iter().map(|m| m.id.clone()).collect();
+            assert_eq!(
+                ids,
+                vec![commit.id.to_string()],
+                "{} {:?}",
+                client_id,
+                group
+            );
+        }
+    }
+    for group in [Some(group_id), None] {
+        assert!(fetch(&service, outsider.id, group, true)
+            .await
+            .messages
+            .is_empty());
+    }
+}
+
+/// Acknowledged messages are only fetched again when read ones are asked for
+async fn test_fetch_skips_acknowledged<DB: DatabaseInterface + 'static>(db: Arc<DB>) {
+    let service = MLSServiceImpl::new(db.clone());
+    let (group_id, admin_id) = setup_group(db.as_ref()).await;
+    for message_type in [MessageType::Commit, MessageType::Application] {
+        MessageFixture::new(message_type)
+            .in_group(group_id)
+            .sent_by(admin_id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+
+    let fetched = fetch(&service, admin_id, Some(group_id), false).await;
+    assert_eq!(fetched.messages.len(), 2);
+    service
+        .ack_messages(Request::new(AckMessagesRequest {
+            client_id: admin_id.to_string(),
+            ack_token: fetched.ack_token,
+            watermarks: vec![],
+        }))
+        .await
+        .unwrap();
+
+    let later = MessageFixture::application(group_id)
+        .sent_by(admin_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    let messages = fetch(&service, admin_id, Some(group_id), false)
+        .await
+        .messages;
+    let ids: Vec<_> = messages.iter().map(|m| m.id.clone()).collect();
+    assert_eq!(ids, vec![later.id.to_string()]);
+    let messages = fetch(&service, admin_id, Some(group_id), true)
+        .await
+        .messages;
+    assert_eq!(messages.len(), 3);
+}
+
+/// A client's groups leave out inactive and soft-deleted ones
+async fn test_list_groups_skips_inactive_and_deleted<DB: DatabaseInterface + 'static>(db: Arc<DB>) {
+    let service = MLSServiceImpl::new(db.clone());
+    let client = ClientFixture::new().insert(db.as_ref()).await.unwrap();
+    let mut active = None;
+    for group in [
+        GroupFixture::new(),
+        GroupFixture::new().inactive(),
+        GroupFixture::new().deleted(),
+    ] {
+        let group = group
+            .with_creator(client.id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+        MembershipFixture::new(group.id, client.id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+        active.get_or_insert(group.id);
+    }
+
+    let groups = service
+        .list_groups(Request::new(ListGroupsRequest {
+            client_id: client.id.to_string(),
+            include_state: false,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .groups;
+    let ids: Vec<_> = groups.iter().map(|g| g.id.clone()).collect();
+    assert_eq!(ids, vec![active.unwrap().to_string()]);
+}
+
+/// Both group listings read every column of the group rows, handles included
+async fn test_group_listings_include_handles<DB: DatabaseInterface + 'static>(db: Arc<DB>) {
+    let service = MLSServiceImpl::new(db.clone());
+    let client = ClientFixture::new().insert(db.as_ref()).await.unwrap();
+    let group = GroupFixture::new()
+        .with_creator(client.id)
+        .with_handle(client.user_id, "book-club")
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    MembershipFixture::new(group.id, client.id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let groups = service
+        .list_groups(Request::new(ListGroupsRequest {
+            client_id: client.id.to_string(),
+            include_state: false,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .groups;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].id, group.id.to_string());
+    assert_eq!(groups[0].handle, "book-club");
+
+    let groups = service
+        .list_all_groups(Request::new(ListAllGroupsRequest {
+            filter: String::new(),
+            limit: 0,
+            page_token: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .groups;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].id, group.id.to_string());
+    assert_eq!(groups[0].handle, "book-club");
+}
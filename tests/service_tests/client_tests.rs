@@ -1,13 +1,20 @@
 use std::sync::Arc;
 
+use async_trait::async_trait;
 use chrono::Utc;
 use hermetic_mls::{
-    db::{Client, DatabaseInterface},
+    attestation::{
+        AttestationError, AttestationEvidence, AttestationFormat, AttestationVerifier,
+        AttestationVerifiers, WebhookVerifier,
+    },
+    db::{AttestationVerdict, Client, CredentialScheme, DatabaseInterface},
+    ids::SequentialIds,
     service::{
         mls::{self, mls_delivery_service_server::MlsDeliveryService, RegisterClientRequest},
         MLSServiceImpl,
     },
 };
+use prost_types::FieldMask;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
@@ -26,6 +33,8 @@ async fn test_register_client() {
         user_id: user_id.to_string(),
         identity: "test-identity".to_string(),
         device_name: "test-device".to_string(),
+        signature_key: vec![],
+        attestation: None,
     });
 
     // Call the service
@@ -38,11 +47,145 @@ async fn test_register_client() {
     // Verify client was stored in database
     let client = db.get_client(client_id).await.unwrap();
     assert_eq!(client.user_id, user_id);
-    assert_eq!(client.scheme, "basic");
+    assert_eq!(client.scheme, CredentialScheme::Basic);
     assert_eq!(client.device_name, "test-device");
     // We don't assert on credential as it's now generated from identity
 }
 
+// Passes evidence reading "genuine", fails other evidence and can't be reached for "offline"
+struct TokenVerifier;
+
+#[async_trait]
+impl AttestationVerifier for TokenVerifier {
+    async fn verify(
+        &self,
+        evidence: &AttestationEvidence,
+    ) -> Result<AttestationVerdict, AttestationError> {
+        assert_eq!(evidence.device_name, "phone");
+        match evidence.token.as_slice() {
+            b"genuine" => Ok(AttestationVerdict::Passed),
+            b"offline" => Err(AttestationError::Request("connection refused".to_string())),
+            _ => Ok(AttestationVerdict::Failed),
+        }
+    }
+}
+
+/// Test checking device attestations at registration
+#[tokio::test]
+async fn test_register_client_attestation() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone()).with_attestation(
+        AttestationVerifiers::default()
+            .with_verifier(AttestationFormat::PlayIntegrity, Arc::new(TokenVerifier)),
+    );
+
+    let register = |attestation: Option<mls::Attestation>| {
+        service.register_client(Request::new(RegisterClientRequest {
+            user_id: Uuid::new_v4().to_string(),
+            identity: "alice".to_string(),
+            device_name: "phone".to_string(),
+            signature_key: vec![],
+            attestation,
+        }))
+    };
+    let attestation = |format: mls::AttestationFormat, token: &[u8]| {
+        Some(mls::Attestation {
+            format: format as i32,
+            token: token.to_vec(),
+        })
+    };
+    let registered = |response: Result<Response<mls::RegisterClientResponse>, Status>| {
+        Uuid::parse_str(&response.unwrap().into_inner().client_id).unwrap()
+    };
+    let verdict = |client_id: Uuid| {
+        let db = db.clone();
+        async move { db.get_client(client_id).await.unwrap().attestation_verdict }
+    };
+
+    // Clients registering without evidence are recorded as unattested
+    let unattested_id = registered(register(None).await);
+    assert_eq!(verdict(unattested_id).await, AttestationVerdict::Unattested);
+
+    // The verifier's verdict is stored on the client, failed ones included
+    let passed_id = registered(
+        register(attestation(
+            mls::AttestationFormat::PlayIntegrity,
+            b"genuine",
+        ))
+        .await,
+    );
+    assert_eq!(verdict(passed_id).await, AttestationVerdict::Passed);
+    let failed_id = registered(
+        register(attestation(
+            mls::AttestationFormat::PlayIntegrity,
+            b"rooted",
+        ))
+        .await,
+    );
+    assert_eq!(verdict(failed_id).await, AttestationVerdict::Failed);
+
+    // ...and returned with the client
+    let client = service
+        .get_client(Request::new(mls::GetClientRequest {
+            client_id: passed_id.to_string(),
+            read_mask: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .client
+        .unwrap();
+    assert_eq!(
+        client.attestation_verdict,
+        mls::AttestationVerdict::Passed as i32
+    );
+
+    // Formats without a verifier, unreachable verifiers and malformed evidence are rejected
+    let status = register(attestation(mls::AttestationFormat::AppAttest, b"genuine"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    let status = register(attestation(
+        mls::AttestationFormat::PlayIntegrity,
+        b"offline",
+    ))
+    .await
+    .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unavailable);
+    let status = register(attestation(mls::AttestationFormat::Unspecified, b""))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    // Webhook verifiers only accept a definite verdict
+    assert_eq!(
+        WebhookVerifier::parse_verdict(r#"{"verdict":"passed"}"#),
+        Ok(AttestationVerdict::Passed)
+    );
+    assert!(WebhookVerifier::parse_verdict(r#"{"verdict":"unattested"}"#).is_err());
+    assert!(WebhookVerifier::parse_verdict("{}").is_err());
+}
+
+/// Test that new rows take their ids from the configured generator
+#[tokio::test]
+async fn test_register_client_uses_id_generator() {
+    let db = Arc::new(MockDatabase::new());
+    let service =
+        MLSServiceImpl::new(db.clone()).with_id_generator(Arc::new(SequentialIds::default()));
+
+    let request = Request::new(RegisterClientRequest {
+        user_id: Uuid::new_v4().to_string(),
+        identity: "test-identity".to_string(),
+        device_name: "test-device".to_string(),
+        signature_key: vec![],
+        attestation: None,
+    });
+
+    let response = service.register_client(request).await.unwrap().into_inner();
+    assert_eq!(response.client_id, Uuid::from_u128(1).to_string());
+    assert!(db.get_client(Uuid::from_u128(1)).await.is_ok());
+}
+
 /// Test the GetClient RPC
 #[tokio::test]
 async fn test_get_client() {
@@ -57,11 +200,15 @@ async fn test_get_client() {
         id: client_id,
         user_id,
         credential: vec![1, 2, 3, 4],
-        scheme: "basic".to_string(),
+        scheme: CredentialScheme::Basic,
         device_name: "test-device".to_string(),
         last_seen: Utc::now(),
         created_at: Utc::now(),
         init_key: Some(vec![1, 2, 3, 4]),
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
     };
 
     // Add it to the mock database
@@ -70,6 +217,7 @@ async fn test_get_client() {
     // Create a request to get the client
     let request = Request::new(mls::GetClientRequest {
         client_id: client_id.to_string(),
+        read_mask: None,
     });
 
     // Call the service
@@ -82,6 +230,10 @@ async fn test_get_client() {
     assert_eq!(response_client.user_id, user_id.to_string());
     assert_eq!(response_client.credential, vec![1, 2, 3, 4]);
     assert_eq!(response_client.scheme, "basic");
+    assert_eq!(
+        response_client.credential_scheme,
+        mls::CredentialScheme::Basic as i32
+    );
     assert_eq!(response_client.device_name, "test-device");
 }
 
@@ -101,21 +253,29 @@ async fn test_list_clients() {
         id: Uuid::new_v4(),
         user_id,
         credential: vec![1, 2, 3, 4],
-        scheme: "basic".to_string(),
+        scheme: CredentialScheme::Basic,
         device_name: "device-1".to_string(),
         last_seen: Utc::now(),
         created_at: Utc::now(),
         init_key: Some(vec![5, 6, 7, 8]),
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
     };
     let client2 = Client {
         id: Uuid::new_v4(),
         user_id,
         credential: vec![5, 6, 7, 8],
-        scheme: "basic".to_string(),
+        scheme: CredentialScheme::Basic,
         device_name: "device-2".to_string(),
         last_seen: Utc::now(),
         created_at: Utc::now(),
         init_key: Some(vec![9, 10, 11, 12]),
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
     };
 
     // Add a client for a different user
@@ -123,11 +283,15 @@ async fn test_list_clients() {
         id: Uuid::new_v4(),
         user_id: other_user_id,
         credential: vec![9, 10, 11, 12],
-        scheme: "basic".to_string(),
+        scheme: CredentialScheme::Basic,
         device_name: "other-device".to_string(),
         last_seen: Utc::now(),
         created_at: Utc::now(),
         init_key: Some(vec![13, 14, 15, 16]),
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
     };
 
     // Store clients in the database
@@ -138,6 +302,7 @@ async fn test_list_clients() {
     // Create a request to list clients for the target user
     let request = Request::new(mls::ListClientsRequest {
         user_id: user_id.to_string(),
+        read_mask: None,
     });
 
     // Call the service
@@ -152,4 +317,88 @@ async fn test_list_clients() {
     assert!(response_ids.contains(&client1.id.to_string()));
     assert!(response_ids.contains(&client2.id.to_string()));
     assert!(!response_ids.contains(&client3.id.to_string()));
+
+    // A read mask returns only the selected fields
+    let response = service
+        .list_clients(Request::new(mls::ListClientsRequest {
+            user_id: user_id.to_string(),
+            read_mask: Some(FieldMask {
+                paths: vec!["id".to_string(), "device_name".to_string()],
+            }),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.clients.len(), 2);
+    for client in &response.clients {
+        assert!(!client.id.is_empty());
+        assert!(client.device_name.starts_with("device-"));
+        assert!(client.credential.is_empty());
+        assert!(client.user_id.is_empty());
+    }
+}
+
+/// Test storing and recovering an encrypted client backup
+#[tokio::test]
+async fn test_client_backup() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let client_id = Uuid::new_v4();
+    let client = Client {
+        id: client_id,
+        user_id: Uuid::new_v4(),
+        credential: vec![1, 2, 3, 4],
+        scheme: CredentialScheme::Basic,
+        device_name: "test-device".to_string(),
+        last_seen: Utc::now(),
+        created_at: Utc::now(),
+        init_key: None,
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
+    };
+    db.register_client(client).await.unwrap();
+
+    let store = |backup: Vec<u8>, expected_version: u64| {
+        service.store_client_backup(Request::new(mls::StoreClientBackupRequest {
+            client_id: client_id.to_string(),
+            backup,
+            expected_version,
+        }))
+    };
+    let get = || {
+        service.get_client_backup(Request::new(mls::GetClientBackupRequest {
+            client_id: client_id.to_string(),
+        }))
+    };
+
+    // No backup has been stored yet
+    assert_eq!(get().await.unwrap_err().code(), tonic::Code::NotFound);
+
+    let response = store(vec![1, 1, 1], 0).await.unwrap().into_inner();
+    assert_eq!(response.version, 1);
+    let response = store(vec![2, 2, 2], 1).await.unwrap().into_inner();
+    assert_eq!(response.version, 2);
+
+    let backup = get().await.unwrap().into_inner();
+    assert_eq!(backup.backup, vec![2, 2, 2]);
+    assert_eq!(backup.version, 2);
+
+    // A device writing over a version it hasn't seen is rejected
+    let status = store(vec![3, 3, 3], 1).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Aborted);
+    assert_eq!(get().await.unwrap().into_inner().backup, vec![2, 2, 2]);
+
+    // Backups of unknown clients are rejected
+    let status = service
+        .store_client_backup(Request::new(mls::StoreClientBackupRequest {
+            client_id: Uuid::new_v4().to_string(),
+            backup: vec![1],
+            expected_version: 0,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
 }
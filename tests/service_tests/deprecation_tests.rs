@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use hermetic_mls::service::deprecations::{
+    deprecations_for, DeprecationLayer, DEPRECATIONS, DEPRECATION_HEADER,
+};
+use hermetic_mls::service::{
+    mls::{self, mls_delivery_service_server::MlsDeliveryService},
+    MLSServiceImpl,
+};
+use tonic::Request;
+use tower::{Layer, Service};
+
+use crate::mock_db::MockDatabase;
+use crate::settings_tests::Ok200;
+
+/// Responses of deprecated RPCs, and of RPCs carrying deprecated fields, get one warning per
+/// deprecation; other responses get none
+#[tokio::test]
+async fn test_deprecation_warning_header() {
+    let mut service = DeprecationLayer::new().layer(Ok200);
+    let warnings = |response: &http::Response<String>| {
+        response
+            .headers()
+            .get_all(DEPRECATION_HEADER)
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    let request = |path: &str| http::Request::builder().uri(path).body(()).unwrap();
+
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/FetchMessages"))
+        .await
+        .unwrap();
+    let fetch_warnings = warnings(&response);
+    assert_eq!(fetch_warnings.len(), 2);
+    assert!(fetch_warnings
+        .contains(&"mls.v1.Message.message_type; replacement=type; sunset=2027-03-31".to_string()));
+
+    // The whole legacy service is deprecated
+    let response = service
+        .call(request("/mls.MlsDeliveryService/StoreCommit"))
+        .await
+        .unwrap();
+    assert_eq!(
+        warnings(&response),
+        ["mls.MlsDeliveryService; replacement=mls.v1.MlsDeliveryService; sunset=2027-03-31"]
+    );
+
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/StoreCommit"))
+        .await
+        .unwrap();
+    assert!(warnings(&response).is_empty());
+    assert_eq!(deprecations_for("/grpc.health.v1.Health/Check").count(), 0);
+}
+
+/// ListDeprecations returns every deprecation with its sunset date
+#[tokio::test]
+async fn test_list_deprecations() {
+    let service = MLSServiceImpl::new(Arc::new(MockDatabase::new()));
+
+    let response = service
+        .list_deprecations(Request::new(mls::ListDeprecationsRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(response.deprecations.len(), DEPRECATIONS.len());
+    let legacy = response
+        .deprecations
+        .iter()
+        .find(|d| d.target == "mls.MlsDeliveryService")
+        .unwrap();
+    assert_eq!(legacy.replacement, "mls.v1.MlsDeliveryService");
+    assert_eq!(legacy.rpcs, ["mls.MlsDeliveryService/"]);
+    assert!(response
+        .deprecations
+        .iter()
+        .all(|d| chrono::NaiveDate::parse_from_str(&d.sunset, "%Y-%m-%d").is_ok()));
+}
@@ -0,0 +1,80 @@
+use hermetic_mls::{
+    db::DbError,
+    error::{ServiceError, ERROR_DOMAIN},
+};
+use tonic::{Code, Status};
+use tonic_types::StatusExt;
+
+/// Test that service errors map to their gRPC codes and carry ErrorInfo details
+#[test]
+fn test_service_error_status_mapping() {
+    let status = Status::from(ServiceError::NotFound { entity: "Group" });
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(status.message(), "Group not found");
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, "NOT_FOUND");
+    assert_eq!(info.domain, ERROR_DOMAIN);
+    assert_eq!(info.metadata["entity"], "Group");
+
+    let status = Status::from(ServiceError::Conflict {
+        reason: "Group epoch changed".to_string(),
+        current_epoch: Some(7),
+    });
+    assert_eq!(status.code(), Code::Aborted);
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, "CONFLICT");
+    assert_eq!(info.metadata["current_epoch"], "7");
+
+    let status = Status::from(ServiceError::QuotaExceeded {
+        quota: "key_packages",
+        limit: 100,
+    });
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, "QUOTA_EXCEEDED");
+    assert_eq!(info.metadata["quota"], "key_packages");
+    assert_eq!(info.metadata["limit"], "100");
+
+    let status = Status::from(ServiceError::validation("Empty commit"));
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(status.message(), "Empty commit");
+    assert_eq!(
+        status.get_details_error_info().unwrap().reason,
+        "VALIDATION_FAILED"
+    );
+
+    // Database errors keep their codes: connection loss is retryable, the rest are internal
+    let codes = [
+        (DbError::NotFound, Code::NotFound),
+        (
+            DbError::ConnectionError("reset".to_string()),
+            Code::Unavailable,
+        ),
+        (DbError::QueryError("syntax".to_string()), Code::Internal),
+        (
+            DbError::SerializationError("bad json".to_string()),
+            Code::Internal,
+        ),
+        (
+            DbError::Conflict("stale version".to_string()),
+            Code::Aborted,
+        ),
+        (DbError::MissingReference("Group"), Code::NotFound),
+    ];
+    for (err, code) in codes {
+        assert_eq!(Status::from(ServiceError::from(err)).code(), code);
+    }
+    let status = Status::from(ServiceError::from(DbError::QueryError(
+        "syntax".to_string(),
+    )));
+    assert_eq!(status.message(), "Database query error: syntax");
+    assert!(status.get_details_error_info().is_none());
+
+    // A write referencing a missing row names the missing entity
+    let status = Status::from(ServiceError::from(DbError::MissingReference("Client")));
+    assert_eq!(status.message(), "Client not found");
+    assert_eq!(
+        status.get_details_error_info().unwrap().metadata["entity"],
+        "Client"
+    );
+}
@@ -0,0 +1,326 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use futures_util::StreamExt;
+use hermetic_mls::{
+    db::{DatabaseInterface, DeliveryCursor, Group, Membership, MembershipRole},
+    events::DomainEvent,
+    fixtures::{GroupFixture, MembershipFixture, MessageFixture},
+    service::{
+        mls::{
+            self, mls_delivery_service_server::MlsDeliveryService, AddMemberRequest,
+            RegisterClientRequest, StoreApplicationMessageRequest, SubscribeMessagesRequest,
+        },
+        subscribe::{open_resume_token, MAX_REPLAYED_MESSAGES, RESUME_TOKEN_HEADER},
+        MLSServiceImpl,
+    },
+};
+use tonic::Request;
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Successful mutations are published on the event bus
+#[tokio::test]
+async fn test_mutations_publish_events() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let mut events = service.events().subscribe();
+
+    let user_id = Uuid::new_v4();
+    let response = service
+        .register_client(Request::new(RegisterClientRequest {
+            user_id: user_id.to_string(),
+            identity: "alice".to_string(),
+            device_name: "phone".to_string(),
+            signature_key: vec![],
+            attestation: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    match events.try_recv().unwrap() {
+        DomainEvent::ClientRegistered {
+            client_id,
+            user_id: registered_user_id,
+            is_service,
+        } => {
+            assert_eq!(client_id.to_string(), response.client_id);
+            assert_eq!(registered_user_id, user_id);
+            assert!(!is_service);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    // Failed requests publish nothing
+    let status = service
+        .add_member(Request::new(AddMemberRequest {
+            group_id: Uuid::new_v4().to_string(),
+            client_id: Uuid::new_v4().to_string(),
+            role: String::new(),
+            member_role: mls::MembershipRole::Member as i32,
+            sender_id: Uuid::new_v4().to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert!(events.try_recv().is_err());
+}
+
+/// Test the SubscribeMessages RPC
+#[tokio::test]
+async fn test_subscribe_messages() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let other_group_id = Uuid::new_v4();
+    let sender_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    for id in [group_id, other_group_id] {
+        let group = Group {
+            id,
+            creator_id: sender_id,
+            epoch: 0,
+            state: None,
+            mls_group_id: None,
+            tenant_id: None,
+            handle: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            is_active: true,
+            deleted_at: None,
+        };
+        db.create_group(group).await.unwrap();
+    }
+    let membership = Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    };
+    db.add_membership(membership).await.unwrap();
+    // The sender is in both groups, and may add members to the other one
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    MembershipFixture::new(other_group_id, sender_id)
+        .with_role(MembershipRole::Admin)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let mut stream = service
+        .subscribe_messages(Request::new(SubscribeMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: String::new(),
+            resume_token: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let post = |group_id: Uuid, message: Vec<u8>| {
+        service.store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            message,
+            epoch: 0,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+    };
+
+    // Messages for groups the client isn't in are skipped
+    post(other_group_id, vec![0]).await.unwrap();
+    post(group_id, vec![1]).await.unwrap();
+
+    let message = stream.next().await.unwrap().unwrap();
+    assert_eq!(message.group_id, group_id.to_string());
+    assert_eq!(
+        message.content,
+        Some(mls::message::Content::Application(vec![1]))
+    );
+
+    // Groups joined after subscribing are streamed too
+    service
+        .add_member(Request::new(AddMemberRequest {
+            group_id: other_group_id.to_string(),
+            client_id: client_id.to_string(),
+            role: String::new(),
+            member_role: mls::MembershipRole::Member as i32,
+            sender_id: sender_id.to_string(),
+        }))
+        .await
+        .unwrap();
+    post(other_group_id, vec![2]).await.unwrap();
+
+    let message = stream.next().await.unwrap().unwrap();
+    assert_eq!(message.group_id, other_group_id.to_string());
+}
+
+/// A subscription resumed with a resume token first streams the messages it missed
+#[tokio::test]
+async fn test_resume_subscription() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let sender_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: sender_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+    let membership = Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    };
+    db.add_membership(membership).await.unwrap();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let subscribe = |resume_token: String| {
+        service.subscribe_messages(Request::new(SubscribeMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: String::new(),
+            resume_token,
+        }))
+    };
+    let post = |message: Vec<u8>| {
+        service.store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            message,
+            epoch: 0,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+    };
+    let content = |message: &mls::Message| message.content.clone();
+
+    // A subscription that received nothing resumes from where it started
+    let response = subscribe(String::new()).await.unwrap();
+    let start = response
+        .metadata()
+        .get(RESUME_TOKEN_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    drop(response);
+    post(vec![1]).await.unwrap();
+
+    let mut stream = subscribe(start).await.unwrap().into_inner();
+    let message = stream.next().await.unwrap().unwrap();
+    assert_eq!(
+        content(&message),
+        Some(mls::message::Content::Application(vec![1]))
+    );
+    assert!(!message.resume_token.is_empty());
+
+    // Messages stored while disconnected are replayed in order, then live ones follow
+    drop(stream);
+    post(vec![2]).await.unwrap();
+    post(vec![3]).await.unwrap();
+    let mut stream = subscribe(message.resume_token).await.unwrap().into_inner();
+    post(vec![4]).await.unwrap();
+    for expected in 2..=4 {
+        let message = stream.next().await.unwrap().unwrap();
+        assert_eq!(
+            content(&message),
+            Some(mls::message::Content::Application(vec![expected]))
+        );
+    }
+
+    let result = subscribe("not-a-token".to_string()).await;
+    assert_eq!(result.err().unwrap().code(), tonic::Code::InvalidArgument);
+}
+
+/// Resume tokens carry the last sequence delivered of each group, and a client that missed more
+/// than a replay's worth of messages is sent to FetchMessages instead
+#[tokio::test]
+async fn test_resume_token_positions() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let client_id = Uuid::new_v4();
+    let quiet = GroupFixture::new().insert(db.as_ref()).await.unwrap();
+    let busy = GroupFixture::new().insert(db.as_ref()).await.unwrap();
+    for group in [&quiet, &busy] {
+        MembershipFixture::new(group.id, client_id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+    let stored = MessageFixture::application(busy.id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let subscribe = |resume_token: String| {
+        service.subscribe_messages(Request::new(SubscribeMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: String::new(),
+            resume_token,
+        }))
+    };
+    let response = subscribe(String::new()).await.unwrap();
+    let start = response
+        .metadata()
+        .get(RESUME_TOKEN_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let mut positions = open_resume_token(&start).unwrap().unwrap();
+    positions.sort_by_key(|c| c.group_id);
+    let mut expected = vec![
+        DeliveryCursor {
+            group_id: None,
+            sequence: 0,
+        },
+        DeliveryCursor {
+            group_id: Some(quiet.id),
+            sequence: 0,
+        },
+        DeliveryCursor {
+            group_id: Some(busy.id),
+            sequence: stored.sequence,
+        },
+    ];
+    expected.sort_by_key(|c| c.group_id);
+    assert_eq!(positions, expected);
+
+    for _ in 0..=MAX_REPLAYED_MESSAGES {
+        MessageFixture::application(busy.id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+    let status = subscribe(start).await.err().unwrap();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+}
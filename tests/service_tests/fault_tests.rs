@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hermetic_mls::{
+    db::{DatabaseInterface, DbError},
+    ids::SequentialIds,
+    service::{
+        mls::{
+            mls_delivery_service_server::MlsDeliveryService, CreateGroupRequest,
+            RegisterClientRequest,
+        },
+        MLSServiceImpl,
+    },
+};
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+use super::register_client;
+use crate::fault_db::{Fault, FaultConfig, FaultInjectingDatabase};
+use crate::mock_db::MockDatabase;
+
+// Helper function to build a service on a fault-injecting wrapper around a mock database
+fn faulty_service(
+    seed: u64,
+) -> (
+    Arc<FaultInjectingDatabase<MockDatabase>>,
+    MLSServiceImpl<FaultInjectingDatabase<MockDatabase>>,
+) {
+    let db = Arc::new(FaultInjectingDatabase::new(
+        Arc::new(MockDatabase::new()),
+        seed,
+    ));
+    let service =
+        MLSServiceImpl::new(db.clone()).with_id_generator(Arc::new(SequentialIds::default()));
+    (db, service)
+}
+
+// Helper function to build a CreateGroup request
+fn create_group_request(creator_id: Uuid) -> Request<CreateGroupRequest> {
+    Request::new(CreateGroupRequest {
+        creator_id: creator_id.to_string(),
+        initial_state: vec![1, 2, 3],
+        mls_group_id: vec![],
+        initial_members: vec![],
+        handle: String::new(),
+        ciphersuite: 0,
+    })
+}
+
+/// Whichever database call of CreateGroup fails, and however, the group is never stored without
+/// its creator's membership
+#[tokio::test]
+async fn test_create_group_never_leaves_partial_state() {
+    let (db, service) = faulty_service(1);
+    let creator_id = register_client(db.inner(), Uuid::new_v4()).await;
+    service
+        .create_group(create_group_request(creator_id))
+        .await
+        .unwrap();
+    let operations = db.calls();
+    assert!(operations.contains(&"create_group_with_members"));
+
+    for operation in operations {
+        for fault in [Fault::Error, Fault::Drop] {
+            let (db, service) = faulty_service(1);
+            let creator_id = register_client(db.inner(), Uuid::new_v4()).await;
+            db.fail_next(operation, fault);
+
+            let status = service
+                .create_group(create_group_request(creator_id))
+                .await
+                .unwrap_err();
+            assert_eq!(
+                status.code(),
+                Code::Unavailable,
+                "{} {:?}",
+                operation,
+                fault
+            );
+
+            // The group takes the first sequential id
+            let group_id = Uuid::from_u128(1);
+            match db.inner().get_group(group_id).await {
+                Ok(_) => {
+                    let members = db
+                        .inner()
+                        .list_memberships_by_group(group_id)
+                        .await
+                        .unwrap();
+                    assert!(
+                        members.iter().any(|m| m.client_id == creator_id),
+                        "{} {:?} left a group without its creator",
+                        operation,
+                        fault
+                    );
+                }
+                Err(DbError::NotFound) => {}
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+}
+
+/// Under random latency and transient errors, every request either fails as retryable or
+/// fully succeeds
+#[tokio::test]
+async fn test_random_faults_are_retryable() {
+    let (db, service) = faulty_service(42);
+    db.set_config(FaultConfig {
+        latency: Some((Duration::ZERO, Duration::from_millis(2))),
+        error_rate: 0.3,
+        drop_rate: 0.0,
+    });
+
+    let user_id = Uuid::new_v4();
+    let mut registered = 0;
+    let mut failed = 0;
+    for i in 0..40 {
+        let request = RegisterClientRequest {
+            user_id: user_id.to_string(),
+            identity: format!("client-{}", i),
+            device_name: "phone".to_string(),
+            signature_key: vec![],
+            attestation: None,
+        };
+        match service.register_client(Request::new(request)).await {
+            Ok(_) => registered += 1,
+            Err(status) => {
+                assert_eq!(status.code(), Code::Unavailable);
+                failed += 1;
+            }
+        }
+    }
+    assert!(registered > 0 && failed > 0);
+
+    // Failed registrations stored nothing
+    let clients = db
+        .inner()
+        .list_clients_by_user(user_id, true)
+        .await
+        .unwrap();
+    assert_eq!(clients.len(), registered);
+
+    // Once the faults clear, requests go through
+    db.set_config(FaultConfig::default());
+    service
+        .create_group(create_group_request(clients[0].id))
+        .await
+        .unwrap();
+}
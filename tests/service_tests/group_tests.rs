@@ -2,18 +2,28 @@ use std::sync::Arc;
 
 use chrono::Utc;
 use hermetic_mls::{
-    db::{Client, DatabaseInterface, Group, Membership},
+    db::{Client, DatabaseInterface, EntityKind, Group, Membership, MembershipRole},
+    fixtures::{GroupFixture, MembershipFixture},
     service::{
         mls::{
-            self, mls_delivery_service_server::MlsDeliveryService, CreateGroupRequest,
-            GetGroupRequest, ListGroupsRequest,
+            self, mls_delivery_service_server::MlsDeliveryService,
+            upload_group_state_request::Part, CreateGroupRequest, GetGroupByMlsGroupIdRequest,
+            GetGroupInfoRequest, GetGroupRequest, GetRatchetTreeRequest, GroupStateUploadHeader,
+            InitialMember, ListGroupsRequest, PublishGroupInfoRequest, SetGroupFrozenRequest,
+            SetGroupKeyRotationRequest, UploadGroupStateRequest, UploadRatchetTreeRequest,
         },
-        MLSServiceImpl,
+        roles::Permission,
+        validation::MAX_GROUP_STATE_CHUNK_BYTES,
+        MLSServiceImpl, MAX_EPOCH_AGE_SECS,
     },
+    settings::{RuntimeSettings, SettingsHandle},
 };
-use tonic::Request;
+use sha2::{Digest, Sha256};
+use tonic::{Code, Request, Status};
+use tonic_types::StatusExt;
 use uuid::Uuid;
 
+use super::register_client;
 use crate::mock_db::MockDatabase;
 
 /// Test the CreateGroup RPC
@@ -24,12 +34,16 @@ async fn test_create_group() {
     let service = MLSServiceImpl::new(db.clone());
 
     // Create a test request
-    let creator_id = Uuid::new_v4();
+    let creator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
     let initial_state = vec![1, 2, 3, 4, 5];
 
     let request = Request::new(CreateGroupRequest {
         creator_id: creator_id.to_string(),
         initial_state: initial_state.clone(),
+        mls_group_id: vec![],
+        initial_members: vec![],
+        handle: String::new(),
+        ciphersuite: 0,
     });
 
     // Call the service
@@ -45,6 +59,247 @@ async fn test_create_group() {
     assert_eq!(group.state, Some(initial_state));
     assert_eq!(group.epoch, 0);
     assert_eq!(group.is_active, true);
+
+    // The creator has to be a registered client
+    let status = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: Uuid::new_v4().to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert_eq!(
+        status.get_details_error_info().unwrap().metadata["entity"],
+        "Creator client"
+    );
+}
+
+/// Test creating a group with its initial members in one call
+#[tokio::test]
+async fn test_create_group_with_initial_members() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let user_id = Uuid::new_v4();
+    let creator_id = register_client(db.as_ref(), user_id).await;
+    let admin_id = register_client(db.as_ref(), user_id).await;
+    let member_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let create = |initial_members: Vec<mls::InitialMember>| {
+        service.create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members,
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+    };
+    let member = |client_id: Uuid, role: mls::MembershipRole| mls::InitialMember {
+        client_id: client_id.to_string(),
+        role: role as i32,
+    };
+
+    let response = create(vec![
+        member(admin_id, mls::MembershipRole::Admin),
+        member(member_id, mls::MembershipRole::Member),
+    ])
+    .await
+    .unwrap()
+    .into_inner();
+    assert_eq!(response.membership_ids.len(), 2);
+
+    let group_id = Uuid::parse_str(&response.group_id).unwrap();
+    let mut members: Vec<_> = db
+        .list_memberships_by_group(group_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|m| (m.client_id, m.role, m.last_acked_epoch))
+        .collect();
+    members.sort_by_key(|(client_id, _, _)| *client_id);
+    let mut expected = vec![
+        (creator_id, MembershipRole::Admin, Some(0)),
+        (admin_id, MembershipRole::Admin, Some(0)),
+        (member_id, MembershipRole::Member, Some(0)),
+    ];
+    expected.sort_by_key(|(client_id, _, _)| *client_id);
+    assert_eq!(members, expected);
+    let membership = db
+        .get_membership(Uuid::parse_str(&response.membership_ids[1]).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(membership.client_id, member_id);
+
+    // Repeated members, the creator, and missing roles are rejected before anything is stored
+    let violated_fields = |status: Status| -> Vec<String> {
+        status
+            .get_details_bad_request()
+            .unwrap()
+            .field_violations
+            .into_iter()
+            .map(|v| v.field)
+            .collect()
+    };
+    let status = create(vec![
+        member(member_id, mls::MembershipRole::Member),
+        member(member_id, mls::MembershipRole::Admin),
+        member(creator_id, mls::MembershipRole::Member),
+        member(admin_id, mls::MembershipRole::Unspecified),
+    ])
+    .await
+    .unwrap_err();
+    assert_eq!(
+        violated_fields(status),
+        vec![
+            "initial_members[1].client_id",
+            "initial_members[2].client_id",
+            "initial_members[3].role",
+        ]
+    );
+
+    // So are clients that aren't registered
+    let status = create(vec![
+        member(member_id, mls::MembershipRole::Member),
+        member(Uuid::new_v4(), mls::MembershipRole::Member),
+    ])
+    .await
+    .unwrap_err();
+    assert_eq!(
+        violated_fields(status),
+        vec!["initial_members[1].client_id"]
+    );
+    assert_eq!(
+        db.list_groups_by_client(member_id, false)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+/// Creating a group with a handle is idempotent per tenant: a retry returns the group the first
+/// call created instead of another one
+#[tokio::test]
+async fn test_create_group_with_handle() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let user_id = Uuid::new_v4();
+    let creator_id = register_client(db.as_ref(), user_id).await;
+    let other_device_id = register_client(db.as_ref(), user_id).await;
+    let member_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let create = |creator_id: Uuid, handle: &str| {
+        service.create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![mls::InitialMember {
+                client_id: member_id.to_string(),
+                role: mls::MembershipRole::Member as i32,
+            }],
+            handle: handle.to_string(),
+            ciphersuite: 0,
+        }))
+    };
+
+    let created = create(creator_id, "team-chat").await.unwrap().into_inner();
+    assert!(!created.existing);
+
+    // Retrying, even from another of the user's devices, returns the same group and memberships
+    let retried = create(other_device_id, "team-chat")
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(retried.existing);
+    assert_eq!(retried.group_id, created.group_id);
+    assert_eq!(retried.membership_ids, created.membership_ids);
+    assert_eq!(
+        db.list_memberships_by_group(Uuid::parse_str(&created.group_id).unwrap())
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let group = service
+        .get_group(Request::new(GetGroupRequest {
+            group_id: created.group_id.clone(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group
+        .unwrap();
+    assert_eq!(group.handle, "team-chat");
+
+    // Handles are only unique per tenant, and groups without one are never deduplicated
+    let other_tenant = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let response = create(other_tenant, "team-chat")
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!response.existing);
+    assert_ne!(response.group_id, created.group_id);
+    let first = create(creator_id, "").await.unwrap().into_inner();
+    let second = create(creator_id, "").await.unwrap().into_inner();
+    assert_ne!(first.group_id, second.group_id);
+}
+
+/// Groups can't be created with a ciphersuite below the creator tenant's floor
+#[tokio::test]
+async fn test_create_group_ciphersuite_floor() {
+    let db = Arc::new(MockDatabase::new());
+    let strict_tenant = Uuid::new_v4();
+    let settings = SettingsHandle::new(RuntimeSettings {
+        tenant_min_ciphersuite_bits: [(strict_tenant, 256)].into(),
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings.clone());
+    let strict_creator = register_client(db.as_ref(), strict_tenant).await;
+    let creator = register_client(db.as_ref(), Uuid::new_v4()).await;
+
+    let create = |creator_id: Uuid, ciphersuite: u32| {
+        service.create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite,
+        }))
+    };
+
+    // MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519 meets the default floor only
+    create(creator, 1).await.unwrap();
+    let status = create(strict_creator, 1).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert!(status.message().contains("(128-bit)"));
+    // MLS_256_DHKEMX448_AES256GCM_SHA512_Ed448 meets both
+    create(strict_creator, 4).await.unwrap();
+    // Groups that don't name their ciphersuite aren't checked
+    create(strict_creator, 0).await.unwrap();
+
+    // Suites of unknown strength, like GREASE values, never meet a floor
+    let status = create(creator, 0x0a0a).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let status = create(creator, 0x10000).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // Lowering the instance-wide floor doesn't lower a tenant's own
+    settings.set(RuntimeSettings {
+        min_ciphersuite_bits: 0,
+        ..settings.current()
+    });
+    assert_eq!(
+        create(strict_creator, 1).await.unwrap_err().code(),
+        Code::FailedPrecondition
+    );
 }
 
 /// Test the GetGroup RPC
@@ -64,9 +319,13 @@ async fn test_get_group() {
         creator_id,
         epoch: 0,
         state: Some(group_state.clone()),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
         is_active: true,
+        deleted_at: None,
     };
 
     // Add it to the mock database
@@ -90,6 +349,54 @@ async fn test_get_group() {
     assert_eq!(response_group.is_active, true);
 }
 
+/// Groups registered with an MLS group id can be looked up by it
+#[tokio::test]
+async fn test_get_group_by_mls_group_id() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let creator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let mls_group_id = b"conversation-42".to_vec();
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: mls_group_id.clone(),
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+
+    let lookup = |mls_group_id: Vec<u8>| {
+        service
+            .get_group_by_mls_group_id(Request::new(GetGroupByMlsGroupIdRequest { mls_group_id }))
+    };
+    let group = lookup(mls_group_id.clone())
+        .await
+        .unwrap()
+        .into_inner()
+        .group
+        .unwrap();
+    assert_eq!(group.id, group_id);
+    assert_eq!(group.mls_group_id, mls_group_id);
+
+    let status = lookup(b"unknown".to_vec()).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let status = lookup(Vec::new()).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // Deleted groups are no longer found
+    db.soft_delete(EntityKind::Group, Uuid::parse_str(&group_id).unwrap())
+        .await
+        .unwrap();
+    let status = lookup(mls_group_id).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
 /// Test the ListGroups RPC
 #[tokio::test]
 async fn test_list_groups() {
@@ -110,9 +417,13 @@ async fn test_list_groups() {
         creator_id: client_id,
         epoch: 0,
         state: Some(vec![1, 2, 3]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
         is_active: true,
+        deleted_at: None,
     };
 
     let group2 = Group {
@@ -120,9 +431,13 @@ async fn test_list_groups() {
         creator_id: other_client_id,
         epoch: 0,
         state: Some(vec![4, 5, 6]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
         is_active: true,
+        deleted_at: None,
     };
 
     // Store groups in the database
@@ -134,18 +449,22 @@ async fn test_list_groups() {
         id: Uuid::new_v4(),
         client_id,
         group_id: group1_id,
-        role: "admin".to_string(),
+        role: MembershipRole::Admin,
         added_at: Utc::now(),
         removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
     };
 
     let membership2 = Membership {
         id: Uuid::new_v4(),
         client_id,
         group_id: group2_id,
-        role: "member".to_string(),
+        role: MembershipRole::Member,
         added_at: Utc::now(),
         removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
     };
 
     // Store memberships
@@ -155,6 +474,7 @@ async fn test_list_groups() {
     // Create a request to list groups for the client
     let request = Request::new(ListGroupsRequest {
         client_id: client_id.to_string(),
+        include_state: false,
     });
 
     // Call the service
@@ -168,4 +488,404 @@ async fn test_list_groups() {
     let response_ids: Vec<String> = response.groups.iter().map(|g| g.id.clone()).collect();
     assert!(response_ids.contains(&group1_id.to_string()));
     assert!(response_ids.contains(&group2_id.to_string()));
+
+    // Group state is left out unless asked for
+    assert!(response.groups.iter().all(|g| g.state.is_empty()));
+    let response = service
+        .list_groups(Request::new(ListGroupsRequest {
+            client_id: client_id.to_string(),
+            include_state: true,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let group1 = response
+        .groups
+        .iter()
+        .find(|g| g.id == group1_id.to_string())
+        .unwrap();
+    assert_eq!(group1.state, vec![1, 2, 3]);
+}
+
+/// Members publish a GroupInfo per epoch, which anyone can fetch to join externally
+#[tokio::test]
+async fn test_publish_and_get_group_info() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let creator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+
+    let publish = |sender_id: Uuid, group_info: Vec<u8>, epoch| {
+        service.publish_group_info(Request::new(PublishGroupInfoRequest {
+            group_id: group_id.clone(),
+            sender_id: sender_id.to_string(),
+            group_info,
+            epoch,
+        }))
+    };
+    let get = |epoch| {
+        service.get_group_info(Request::new(GetGroupInfoRequest {
+            group_id: group_id.clone(),
+            epoch,
+        }))
+    };
+
+    // Nothing is published yet
+    let status = get(0).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    // Outsiders can't publish, and only for the current epoch
+    let outsider = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let status = publish(outsider, vec![1], 0).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = publish(creator_id, vec![1], 1).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let response = publish(creator_id, vec![1], 0).await.unwrap().into_inner();
+    assert!(!response.replaced);
+    let response = publish(creator_id, vec![2], 0).await.unwrap().into_inner();
+    assert!(response.replaced);
+
+    let info = get(0).await.unwrap().into_inner();
+    assert_eq!(info.group_info, [2]);
+    assert_eq!(info.epoch, 0);
+    assert_eq!(info.publisher_id, creator_id.to_string());
+    assert!(info.current);
+
+    // After a commit the old GroupInfo is still there, but no longer current
+    let group_uuid = Uuid::parse_str(&group_id).unwrap();
+    db.update_group_epoch(group_uuid, 1).await.unwrap();
+    assert!(!get(0).await.unwrap().into_inner().current);
+    publish(creator_id, vec![3], 1).await.unwrap();
+    let info = get(0).await.unwrap().into_inner();
+    assert_eq!(
+        (info.epoch, info.group_info, info.current),
+        (1, vec![3], true)
+    );
+    let status = get(7).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+/// Members upload the ratchet tree of the current epoch, and only members can fetch it
+#[tokio::test]
+async fn test_upload_and_get_ratchet_tree() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let creator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let outsider = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+
+    let upload = |sender_id: Uuid, epoch| {
+        service.upload_ratchet_tree(Request::new(UploadRatchetTreeRequest {
+            group_id: group_id.clone(),
+            sender_id: sender_id.to_string(),
+            ratchet_tree: vec![4, 5, 6],
+            epoch,
+        }))
+    };
+    let get = |client_id: Uuid| {
+        service.get_ratchet_tree(Request::new(GetRatchetTreeRequest {
+            group_id: group_id.clone(),
+            client_id: client_id.to_string(),
+            epoch: 0,
+        }))
+    };
+
+    let status = get(creator_id).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let status = upload(outsider, 0).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = upload(creator_id, 3).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    assert!(!upload(creator_id, 0).await.unwrap().into_inner().replaced);
+    assert!(upload(creator_id, 0).await.unwrap().into_inner().replaced);
+
+    let tree = get(creator_id).await.unwrap().into_inner();
+    assert_eq!(tree.ratchet_tree, [4, 5, 6]);
+    assert_eq!(tree.epoch, 0);
+    assert_eq!(tree.uploader_id, creator_id.to_string());
+    assert!(tree.current);
+    let status = get(outsider).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
+
+/// Members stream large group states in chunks, checked against the final checksum
+#[tokio::test]
+async fn test_upload_group_state() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let creator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let outsider = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+
+    let state: Vec<u8> = (0..=255).cycle().take(3000).collect();
+    let checksum = Sha256::digest(&state).to_vec();
+    let header = |sender_id: Uuid, epoch| {
+        Part::Header(GroupStateUploadHeader {
+            group_id: group_id.clone(),
+            sender_id: sender_id.to_string(),
+            epoch,
+        })
+    };
+    let upload = |parts: Vec<Part>| {
+        let messages = parts
+            .into_iter()
+            .map(|part| Ok(UploadGroupStateRequest { part: Some(part) }));
+        service.receive_group_state(Request::new(futures_util::stream::iter(messages)))
+    };
+    let chunks = || state.chunks(1024).map(|chunk| Part::Chunk(chunk.to_vec()));
+
+    let response = upload(
+        std::iter::once(header(creator_id, 0))
+            .chain(chunks())
+            .chain([Part::Sha256(checksum.clone())])
+            .collect(),
+    )
+    .await
+    .unwrap()
+    .into_inner();
+    assert_eq!(response.size, 3000);
+    let group = db
+        .get_group(Uuid::parse_str(&group_id).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(group.state, Some(state.clone()));
+
+    // Outsiders and stale epochs are refused before any chunk is read
+    let status = upload(vec![header(outsider, 0)]).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = upload(vec![header(creator_id, 3)]).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    // Corrupted, truncated and out of order uploads are rejected and leave the state alone
+    let violated_fields = |status: Status| -> Vec<String> {
+        status
+            .get_details_bad_request()
+            .unwrap()
+            .field_violations
+            .into_iter()
+            .map(|v| v.field)
+            .collect()
+    };
+    let mut corrupted: Vec<Part> = std::iter::once(header(creator_id, 0))
+        .chain(chunks())
+        .collect();
+    corrupted.remove(1);
+    corrupted.push(Part::Sha256(checksum.clone()));
+    let status = upload(corrupted).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(violated_fields(status), vec!["sha256"]);
+
+    let truncated = std::iter::once(header(creator_id, 0))
+        .chain(chunks())
+        .collect();
+    let status = upload(truncated).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let status = upload(vec![Part::Chunk(vec![1]), header(creator_id, 0)])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let status = upload(vec![
+        header(creator_id, 0),
+        Part::Chunk(vec![0; MAX_GROUP_STATE_CHUNK_BYTES + 1]),
+    ])
+    .await
+    .unwrap_err();
+    assert_eq!(violated_fields(status), vec!["chunk"]);
+
+    let group = db
+        .get_group(Uuid::parse_str(&group_id).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(group.state, Some(state));
+}
+
+/// Moderators may freeze a group unless the settings take the permission away; members can't
+#[tokio::test]
+async fn test_set_group_frozen() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::default();
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings.clone());
+
+    let creator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let moderator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let member_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let initial_member = |client_id: Uuid, role: mls::MembershipRole| InitialMember {
+        client_id: client_id.to_string(),
+        role: role as i32,
+    };
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![
+                initial_member(moderator_id, mls::MembershipRole::Moderator),
+                initial_member(member_id, mls::MembershipRole::Member),
+            ],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+
+    let set_frozen = |sender_id: Uuid, frozen| {
+        service.set_group_frozen(Request::new(SetGroupFrozenRequest {
+            group_id: group_id.clone(),
+            sender_id: sender_id.to_string(),
+            frozen,
+        }))
+    };
+    let is_active = || async {
+        db.get_group(Uuid::parse_str(&group_id).unwrap())
+            .await
+            .unwrap()
+            .is_active
+    };
+
+    let status = set_frozen(member_id, true).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    assert!(
+        set_frozen(moderator_id, true)
+            .await
+            .unwrap()
+            .into_inner()
+            .changed
+    );
+    assert!(
+        !set_frozen(moderator_id, true)
+            .await
+            .unwrap()
+            .into_inner()
+            .changed
+    );
+    assert!(!is_active().await);
+
+    settings.set(RuntimeSettings {
+        moderator_permissions: vec![Permission::RemoveMembers],
+        ..RuntimeSettings::default()
+    });
+    let status = set_frozen(moderator_id, false).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert!(
+        set_frozen(creator_id, false)
+            .await
+            .unwrap()
+            .into_inner()
+            .changed
+    );
+    assert!(is_active().await);
+}
+
+/// Admins set their group's epoch age limit, or hand it back to the server default
+#[tokio::test]
+async fn test_set_group_key_rotation() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::new(RuntimeSettings {
+        key_rotation_max_epoch_age_secs: 7 * 24 * 60 * 60,
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings);
+
+    let admin_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let member_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let group = GroupFixture::new()
+        .with_creator(admin_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    for (client_id, role) in [
+        (admin_id, MembershipRole::Admin),
+        (member_id, MembershipRole::Member),
+    ] {
+        MembershipFixture::new(group.id, client_id)
+            .with_role(role)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+
+    let set_rotation = |sender_id: Uuid, max_epoch_age_secs, use_server_default| {
+        service.set_group_key_rotation(Request::new(SetGroupKeyRotationRequest {
+            group_id: group.id.to_string(),
+            sender_id: sender_id.to_string(),
+            max_epoch_age_secs,
+            use_server_default,
+        }))
+    };
+
+    let status = set_rotation(member_id, 3600, false).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let response = set_rotation(admin_id, 3600, false)
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.max_epoch_age_secs, 3600);
+    assert_eq!(
+        db.get_group_max_epoch_age(group.id).await.unwrap(),
+        Some(3600)
+    );
+
+    // 0 turns the reminders off for the group, whatever the default
+    let response = set_rotation(admin_id, 0, false).await.unwrap().into_inner();
+    assert_eq!(response.max_epoch_age_secs, 0);
+    assert_eq!(db.get_group_max_epoch_age(group.id).await.unwrap(), Some(0));
+
+    let response = set_rotation(admin_id, 0, true).await.unwrap().into_inner();
+    assert_eq!(response.max_epoch_age_secs, 7 * 24 * 60 * 60);
+    assert_eq!(db.get_group_max_epoch_age(group.id).await.unwrap(), None);
+
+    for (max_epoch_age_secs, use_server_default) in [(3600, true), (MAX_EPOCH_AGE_SECS + 1, false)]
+    {
+        let status = set_rotation(admin_id, max_epoch_age_secs, use_server_default)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
 }
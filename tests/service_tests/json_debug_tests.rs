@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use hermetic_mls::auth::admin::AdminAuthLayer;
+use hermetic_mls::service::json_debug::JsonDebugLayer;
+use hermetic_mls::service::mls::mls_admin_service_server::MlsAdminServiceServer;
+use hermetic_mls::service::mls::mls_delivery_service_server::MlsDeliveryServiceServer;
+use hermetic_mls::service::MLSServiceImpl;
+use http_body_util::{BodyExt, Full};
+use serde_json::{json, Value};
+use tonic::body::Body;
+use tonic::service::Routes;
+use tower::{Layer, ServiceExt};
+use uuid::Uuid;
+
+use super::register_client;
+use crate::mock_db::MockDatabase;
+
+// POST a JSON body to a path, returning the HTTP status and the parsed response
+async fn post(
+    layer: &JsonDebugLayer,
+    db: Arc<MockDatabase>,
+    path: &str,
+    body: &str,
+) -> (u16, Value) {
+    let service = Arc::new(MLSServiceImpl::new(db));
+    let routes = Routes::new(MlsDeliveryServiceServer::from_arc(service.clone()))
+        .add_service(MlsAdminServiceServer::from_arc(service));
+    let request = http::Request::post(path)
+        .header("content-type", "application/json")
+        .body(Body::new(Full::new(bytes::Bytes::from(body.to_string()))))
+        .unwrap();
+
+    let response = layer.layer(routes).oneshot(request).await.unwrap();
+    let status = response.status().as_u16();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Unary RPCs can be called with protobuf JSON, errors come back as JSON statuses
+#[tokio::test]
+async fn test_json_debug_endpoint() {
+    let db = Arc::new(MockDatabase::new());
+    let user_id = Uuid::new_v4();
+    let client_id = register_client(db.as_ref(), user_id).await;
+    let layer = JsonDebugLayer::new();
+
+    let (status, response) = post(
+        &layer,
+        db.clone(),
+        "/debug/json/mls.v1.MlsDeliveryService/GetClient",
+        &json!({ "clientId": client_id.to_string() }).to_string(),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(response["client"]["id"], client_id.to_string());
+    assert_eq!(response["client"]["userId"], user_id.to_string());
+    // Bytes are base64 in protobuf JSON
+    assert_eq!(response["client"]["credential"], "AQIDBA==");
+
+    // The proto field names are accepted too
+    let (status, response) = post(
+        &layer,
+        db.clone(),
+        "/debug/json/mls.v1.MlsDeliveryService/GetClient",
+        &json!({ "client_id": client_id.to_string() }).to_string(),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(response["client"]["id"], client_id.to_string());
+
+    // The admin service is reachable the same way
+    let (status, response) = post(
+        &layer,
+        db.clone(),
+        "/debug/json/mls.v1.MlsAdminService/ListAllClients",
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(response["clients"][0]["id"], client_id.to_string());
+
+    let (status, response) = post(
+        &layer,
+        db.clone(),
+        "/debug/json/mls.v1.MlsDeliveryService/GetClient",
+        &json!({ "clientId": Uuid::new_v4().to_string() }).to_string(),
+    )
+    .await;
+    assert_eq!(status, 404);
+    assert_eq!(response["code"], 5);
+
+    let (status, response) = post(
+        &layer,
+        db.clone(),
+        "/debug/json/mls.v1.MlsDeliveryService/GetClient",
+        r#"{"clientId": 42}"#,
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_eq!(response["code"], 3);
+
+    let (status, _) = post(
+        &layer,
+        db.clone(),
+        "/debug/json/mls.v1.MlsDeliveryService/NoSuchMethod",
+        "{}",
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    // Streaming RPCs can't be transcoded into a single response
+    let (status, response) = post(
+        &layer,
+        db.clone(),
+        "/debug/json/mls.v1.MlsDeliveryService/SubscribeMessages",
+        "{}",
+    )
+    .await;
+    assert_eq!(status, 501);
+    assert_eq!(response["code"], 12);
+}
+
+/// The disabled layer serves no endpoint
+#[tokio::test]
+async fn test_json_debug_disabled() {
+    let db = Arc::new(MockDatabase::new());
+    let client_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+
+    let (_, response) = post(
+        &JsonDebugLayer::disabled(),
+        db,
+        "/debug/json/mls.v1.MlsDeliveryService/GetClient",
+        &json!({ "clientId": client_id.to_string() }).to_string(),
+    )
+    .await;
+    // The request reaches the gRPC router, which doesn't know the path
+    assert_eq!(response, Value::Null);
+}
+
+/// On the admin listener the endpoint sits behind the admin token, like the admin service
+#[tokio::test]
+async fn test_json_debug_requires_admin_token() {
+    let db = Arc::new(MockDatabase::new());
+    let client_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let service = Arc::new(MLSServiceImpl::new(db));
+    let routes = Routes::new(MlsDeliveryServiceServer::from_arc(service));
+    let debug = AdminAuthLayer::new(Some("s3cret")).layer(JsonDebugLayer::new().layer(routes));
+    let request = |token: Option<&str>| {
+        let mut request = http::Request::post("/debug/json/mls.v1.MlsDeliveryService/GetClient")
+            .header("content-type", "application/json");
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        request
+            .body(Body::new(Full::new(bytes::Bytes::from(
+                json!({ "clientId": client_id.to_string() }).to_string(),
+            ))))
+            .unwrap()
+    };
+
+    for token in [None, Some("guess")] {
+        let response = debug.clone().oneshot(request(token)).await.unwrap();
+        assert_eq!(
+            response.headers().get("grpc-status").unwrap(),
+            &(tonic::Code::Unauthenticated as i32).to_string()
+        );
+    }
+
+    let response = debug.oneshot(request(Some("s3cret"))).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let response: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response["client"]["id"], client_id.to_string());
+}
@@ -2,18 +2,24 @@ use std::sync::Arc;
 
 use chrono::Utc;
 use hermetic_mls::{
-    db::{DatabaseInterface, KeyPackage},
+    db::{AttestationVerdict, CredentialScheme, DatabaseInterface, EntityKind, Group, KeyPackage},
+    mls_codec::{self, MlsCrypto},
     service::{
         mls::{
-            self, mls_delivery_service_server::MlsDeliveryService, GetKeyPackageRequest,
+            self, mls_admin_service_server::MlsAdminService,
+            mls_delivery_service_server::MlsDeliveryService, ClaimKeyPackageRequest,
+            GetKeyPackageCountRequest, GetKeyPackageRequest, ListKeyPackageClaimsRequest,
             ListKeyPackagesRequest, PublishKeyPackageRequest,
         },
-        MLSServiceImpl,
+        validation::ValidationPolicy,
+        MLSServiceImpl, KEY_PACKAGE_LIFETIME_DAYS,
     },
+    settings::{RuntimeSettings, SettingsHandle},
 };
 use openmls::credentials::{BasicCredential, Credential};
 use tls_codec::Serialize as TlsSerialize;
 use tonic::{Request, Response, Status};
+use tonic_types::StatusExt;
 use uuid::Uuid;
 
 use crate::mock_db::MockDatabase;
@@ -24,7 +30,8 @@ async fn test_publish_key_package() {
     // Create a mock database
     let db = Arc::new(MockDatabase::new());
     // Create a service with validation disabled for testing
-    let service = MLSServiceImpl::new_skip_validation(db.clone());
+    let service =
+        MLSServiceImpl::new(db.clone()).with_validation_policy(ValidationPolicy::Unchecked);
 
     // First register a client to get credential and init_key
     let client_id = Uuid::new_v4();
@@ -43,11 +50,15 @@ async fn test_publish_key_package() {
         id: client_id,
         user_id,
         credential: credential_bytes,
-        scheme: "basic".to_string(),
+        scheme: CredentialScheme::Basic,
         device_name: "test-device".to_string(),
         last_seen: Utc::now(),
         created_at: Utc::now(),
         init_key: Some(vec![5, 6, 7, 8]), // Mock init key
+        signature_key: None,
+        is_service: false,
+        attestation_verdict: AttestationVerdict::Unattested,
+        deleted_at: None,
     };
 
     // Add the client to the database
@@ -88,6 +99,8 @@ async fn test_get_key_package() {
         data: vec![1, 2, 3, 4, 5],
         created_at: Utc::now(),
         used: false,
+        used_at: None,
+        deleted_at: None,
     };
 
     // Add it to the mock database
@@ -96,6 +109,7 @@ async fn test_get_key_package() {
     // Create a request to get the key package
     let request = Request::new(GetKeyPackageRequest {
         key_package_id: key_package_id.to_string(),
+        read_mask: None,
     });
 
     // Call the service
@@ -128,6 +142,8 @@ async fn test_list_key_packages() {
         data: vec![1, 2, 3, 4, 5],
         created_at: Utc::now(),
         used: false,
+        used_at: None,
+        deleted_at: None,
     };
     let key_package2 = KeyPackage {
         id: Uuid::new_v4(),
@@ -135,6 +151,8 @@ async fn test_list_key_packages() {
         data: vec![6, 7, 8, 9, 10],
         created_at: Utc::now(),
         used: false,
+        used_at: None,
+        deleted_at: None,
     };
 
     // Add a key package for a different client
@@ -144,6 +162,8 @@ async fn test_list_key_packages() {
         data: vec![11, 12, 13, 14, 15],
         created_at: Utc::now(),
         used: false,
+        used_at: None,
+        deleted_at: None,
     };
 
     // Store key packages in the database
@@ -154,6 +174,7 @@ async fn test_list_key_packages() {
     // Create a request to list key packages for the target client
     let request = Request::new(ListKeyPackagesRequest {
         client_id: client_id.to_string(),
+        read_mask: None,
     });
 
     // Call the service
@@ -173,3 +194,345 @@ async fn test_list_key_packages() {
     assert!(response_ids.contains(&key_package2.id.to_string()));
     assert!(!response_ids.contains(&key_package3.id.to_string()));
 }
+
+/// Test claiming key packages and the audit trail they leave behind
+#[tokio::test]
+async fn test_claim_key_package_audit_trail() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let client_id = Uuid::new_v4();
+    let claimer_id = Uuid::new_v4();
+    let group_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: claimer_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+
+    // Two key packages, the first published an hour earlier
+    let published_at = Utc::now() - chrono::Duration::hours(1);
+    let oldest_id = Uuid::new_v4();
+    for (id, created_at, data) in [
+        (oldest_id, published_at, vec![1, 2, 3]),
+        (Uuid::new_v4(), Utc::now(), vec![4, 5, 6]),
+    ] {
+        db.store_key_package(KeyPackage {
+            id,
+            client_id,
+            data,
+            created_at,
+            used: false,
+            used_at: None,
+            deleted_at: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    let claim = || {
+        service.claim_key_package(Request::new(ClaimKeyPackageRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            claimer_id: claimer_id.to_string(),
+        }))
+    };
+
+    // The oldest unused package is handed out first and marked used
+    let claimed = claim().await.unwrap().into_inner().key_package.unwrap();
+    assert_eq!(claimed.id, oldest_id.to_string());
+    assert_eq!(claimed.data, vec![1, 2, 3]);
+    assert!(db.get_key_package(oldest_id).await.unwrap().used);
+    claim().await.unwrap();
+
+    let status = claim().await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, "NOT_FOUND");
+    assert_eq!(info.metadata["entity"], "Unused key package");
+
+    // The audit trail survives purging the key package
+    db.soft_delete(EntityKind::KeyPackage, oldest_id)
+        .await
+        .unwrap();
+    db.purge_entity(EntityKind::KeyPackage, oldest_id)
+        .await
+        .unwrap();
+
+    let claims = service
+        .list_key_package_claims(Request::new(ListKeyPackageClaimsRequest {
+            client_id: client_id.to_string(),
+            filter: String::new(),
+            limit: 0,
+            page_token: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .claims;
+    assert_eq!(claims.len(), 2);
+    let oldest = claims
+        .iter()
+        .find(|c| c.key_package_id == oldest_id.to_string())
+        .unwrap();
+    assert_eq!(oldest.group_id, group_id.to_string());
+    assert_eq!(oldest.claimed_by, claimer_id.to_string());
+    assert_eq!(
+        oldest.published_at,
+        hermetic_mls::timestamps::to_rfc3339(published_at)
+    );
+    assert!(!oldest.hash.is_empty());
+    assert_ne!(claims[0].hash, claims[1].hash);
+
+    // Across clients, the audit log can be filtered and limited
+    let claims = service
+        .list_key_package_claims(Request::new(ListKeyPackageClaimsRequest {
+            client_id: String::new(),
+            filter: format!(
+                "claimed_by={} published_at<{}",
+                claimer_id,
+                hermetic_mls::timestamps::to_rfc3339(Utc::now() - chrono::Duration::minutes(1))
+            ),
+            limit: 1,
+            page_token: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .claims;
+    assert_eq!(claims.len(), 1);
+    assert_eq!(claims[0].key_package_id, oldest_id.to_string());
+}
+
+/// Test restricting key package claims to clients whose attestation passed
+#[tokio::test]
+async fn test_claim_key_package_requires_attestation() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::new(RuntimeSettings {
+        key_package_claims_require_attestation: true,
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings.clone());
+
+    let claimer_id = Uuid::new_v4();
+    let group_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: claimer_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+
+    let mut clients = Vec::new();
+    for verdict in AttestationVerdict::ALL {
+        let client_id = Uuid::new_v4();
+        db.register_client(hermetic_mls::db::Client {
+            id: client_id,
+            user_id: Uuid::new_v4(),
+            credential: vec![1],
+            scheme: CredentialScheme::Basic,
+            device_name: "phone".to_string(),
+            last_seen: Utc::now(),
+            created_at: Utc::now(),
+            init_key: None,
+            signature_key: None,
+            is_service: false,
+            attestation_verdict: *verdict,
+            deleted_at: None,
+        })
+        .await
+        .unwrap();
+        for _ in 0..2 {
+            db.store_key_package(KeyPackage {
+                id: Uuid::new_v4(),
+                client_id,
+                data: vec![1, 2, 3],
+                created_at: Utc::now(),
+                used: false,
+                used_at: None,
+                deleted_at: None,
+            })
+            .await
+            .unwrap();
+        }
+        clients.push((client_id, *verdict));
+    }
+
+    let claim = |client_id: Uuid| {
+        service.claim_key_package(Request::new(ClaimKeyPackageRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            claimer_id: claimer_id.to_string(),
+        }))
+    };
+
+    for (client_id, verdict) in &clients {
+        let result = claim(*client_id).await;
+        if *verdict == AttestationVerdict::Passed {
+            assert!(result.is_ok());
+        } else {
+            assert_eq!(result.unwrap_err().code(), tonic::Code::FailedPrecondition);
+        }
+    }
+
+    // Without the policy, every client's key packages can be claimed
+    settings.set(RuntimeSettings::default());
+    for (client_id, _) in &clients {
+        claim(*client_id).await.unwrap();
+    }
+}
+
+/// Test refusing claims of key packages below the group tenant's ciphersuite floor without
+/// using them up
+#[tokio::test]
+async fn test_claim_key_package_ciphersuite_floor() {
+    let db = Arc::new(MockDatabase::new());
+    let strict_tenant = Uuid::new_v4();
+    let settings = SettingsHandle::new(RuntimeSettings {
+        tenant_min_ciphersuite_bits: [(strict_tenant, 256)].into(),
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings);
+
+    let claimer_id = Uuid::new_v4();
+    let mut group_ids = Vec::new();
+    for tenant_id in [Some(strict_tenant), None] {
+        let group_id = Uuid::new_v4();
+        db.create_group(Group {
+            id: group_id,
+            creator_id: claimer_id,
+            epoch: 0,
+            state: None,
+            mls_group_id: None,
+            tenant_id,
+            handle: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            is_active: true,
+            deleted_at: None,
+        })
+        .await
+        .unwrap();
+        group_ids.push(group_id);
+    }
+
+    // Key packages the service builds use a 128-bit suite
+    let client_id = super::register_client(db.as_ref(), Uuid::new_v4()).await;
+    let crypto = MlsCrypto::default();
+    let credential = mls_codec::basic_credential(b"alice").unwrap();
+    for _ in 0..2 {
+        db.store_key_package(KeyPackage {
+            id: Uuid::new_v4(),
+            client_id,
+            data: crypto.build_key_package(&credential).unwrap(),
+            created_at: Utc::now(),
+            used: false,
+            used_at: None,
+            deleted_at: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    let claim = |group_id: Uuid| {
+        service.claim_key_package(Request::new(ClaimKeyPackageRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            claimer_id: claimer_id.to_string(),
+        }))
+    };
+
+    let status = claim(group_ids[0]).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    assert!(status.message().contains("256-bit ciphersuite floor"));
+
+    // The refused packages weren't used up, and a group with a laxer floor can still claim them
+    let unused = || async {
+        db.list_key_packages_by_client(client_id, false)
+            .await
+            .unwrap()
+            .iter()
+            .filter(|kp| !kp.used)
+            .count()
+    };
+    assert_eq!(unused().await, 2);
+    claim(group_ids[1]).await.unwrap();
+    assert_eq!(unused().await, 1);
+
+    // Only the allowed claim was recorded
+    let claims = service
+        .list_key_package_claims(Request::new(ListKeyPackageClaimsRequest {
+            client_id: client_id.to_string(),
+            filter: String::new(),
+            limit: 0,
+            page_token: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .claims;
+    assert_eq!(claims.len(), 1);
+    assert_eq!(claims[0].group_id, group_ids[1].to_string());
+}
+
+/// Test counting a client's key packages by state
+#[tokio::test]
+async fn test_get_key_package_count() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let client_id = super::register_client(db.as_ref(), Uuid::new_v4()).await;
+    let expired_at = Utc::now() - chrono::Duration::days(KEY_PACKAGE_LIFETIME_DAYS + 1);
+    for (created_at, used) in [
+        (Utc::now(), false),
+        (Utc::now(), false),
+        (Utc::now(), true),
+        (expired_at, false),
+        (expired_at, true),
+    ] {
+        db.store_key_package(KeyPackage {
+            id: Uuid::new_v4(),
+            client_id,
+            data: vec![1, 2, 3],
+            created_at,
+            used,
+            used_at: used.then(Utc::now),
+            deleted_at: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    let count = |client_id: Uuid| {
+        service.get_key_package_count(Request::new(GetKeyPackageCountRequest {
+            client_id: client_id.to_string(),
+        }))
+    };
+    let response = count(client_id).await.unwrap().into_inner();
+    assert_eq!(
+        (response.unused, response.used, response.expired),
+        (2, 2, 1)
+    );
+
+    let status = count(Uuid::new_v4()).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use hermetic_mls::{
+    db::DatabaseInterface,
+    service::{
+        legacy::{
+            mls::{self, mls_delivery_service_server::MlsDeliveryService},
+            LegacyDeliveryService,
+        },
+        MLSServiceImpl,
+    },
+};
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+use super::register_client;
+use crate::mock_db::MockDatabase;
+
+/// Test that the unversioned API is served by the v1 implementation
+#[tokio::test]
+async fn test_legacy_register_and_get_client() {
+    let db = Arc::new(MockDatabase::new());
+    let service = LegacyDeliveryService::new(Arc::new(MLSServiceImpl::new(db.clone())));
+
+    let user_id = Uuid::new_v4();
+    let response = service
+        .register_client(Request::new(mls::RegisterClientRequest {
+            user_id: user_id.to_string(),
+            identity: "legacy-identity".to_string(),
+            device_name: "legacy-device".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let client_id = Uuid::parse_str(&response.client_id).unwrap();
+    assert_eq!(db.get_client(client_id).await.unwrap().user_id, user_id);
+
+    // Responses come back as legacy messages
+    let client = service
+        .get_client(Request::new(mls::GetClientRequest {
+            client_id: client_id.to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .client
+        .unwrap();
+    assert_eq!(client.id, client_id.to_string());
+    assert_eq!(client.device_name, "legacy-device");
+}
+
+/// Test that errors from the v1 implementation pass through unchanged
+#[tokio::test]
+async fn test_legacy_error_passthrough() {
+    let db = Arc::new(MockDatabase::new());
+    let service = LegacyDeliveryService::new(Arc::new(MLSServiceImpl::new(db)));
+
+    let result = service
+        .get_client(Request::new(mls::GetClientRequest {
+            client_id: Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(result.unwrap_err().code(), Code::NotFound);
+}
+
+/// Test that legacy ListGroups keeps returning each group's state
+#[tokio::test]
+async fn test_legacy_list_groups_includes_state() {
+    let db = Arc::new(MockDatabase::new());
+    let service = LegacyDeliveryService::new(Arc::new(MLSServiceImpl::new(db.clone())));
+    let creator_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+
+    service
+        .create_group(Request::new(mls::CreateGroupRequest {
+            creator_id: creator_id.to_string(),
+            initial_state: vec![1, 2, 3],
+        }))
+        .await
+        .unwrap();
+
+    let groups = service
+        .list_groups(Request::new(mls::ListGroupsRequest {
+            client_id: creator_id.to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .groups;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].state, vec![1, 2, 3]);
+}
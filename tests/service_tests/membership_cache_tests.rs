@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hermetic_mls::{
+    db::{
+        DatabaseInterface, EntityKind, Membership, MembershipChange, MembershipRole, MessageType,
+    },
+    events::{DomainEvent, EventBus},
+    fixtures::{GroupFixture, MembershipFixture, MessageFixture},
+    service::{
+        membership_cache::{MemberSet, MembershipCache},
+        mls::{
+            self, mls_delivery_service_server::MlsDeliveryService, AddMemberRequest,
+            FetchMessagesRequest, RemoveMemberRequest,
+        },
+        MLSServiceImpl,
+    },
+};
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+/// Member set of a single member
+fn members_of(client_id: Uuid) -> MemberSet {
+    Arc::new(HashMap::from([(client_id, MembershipRole::Member)]))
+}
+
+/// Create a group with an admin, returning the group and the admin's membership
+async fn setup_group(db: &MockDatabase) -> (Uuid, Membership) {
+    let group = GroupFixture::new().insert(db).await.unwrap();
+    let admin = MembershipFixture::new(group.id, Uuid::new_v4())
+        .with_role(MembershipRole::Admin)
+        .insert(db)
+        .await
+        .unwrap();
+    (group.id, admin)
+}
+
+/// Have the admin add a new client to the group
+async fn add_member(
+    service: &MLSServiceImpl<MockDatabase>,
+    group_id: Uuid,
+    admin_id: Uuid,
+) -> Result<Uuid, tonic::Status> {
+    service
+        .add_member(Request::new(AddMemberRequest {
+            group_id: group_id.to_string(),
+            client_id: Uuid::new_v4().to_string(),
+            role: String::new(),
+            member_role: mls::MembershipRole::Member as i32,
+            sender_id: admin_id.to_string(),
+        }))
+        .await
+        .map(|response| response.into_inner().membership_id.parse().unwrap())
+}
+
+/// Wait for the cache's sync task to drop every snapshot
+async fn until_empty(cache: &MembershipCache) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !cache.is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("cache was not invalidated");
+}
+
+/// The least recently used group is dropped once the cache is full
+#[test]
+fn test_cache_evicts_least_recently_used() {
+    let cache = MembershipCache::new(2);
+    let (first, second, third) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let generation = cache.generation();
+    cache.insert(first, members_of(Uuid::new_v4()), generation);
+    cache.insert(second, members_of(Uuid::new_v4()), generation);
+
+    // Using the first group makes the second the least recently used
+    assert!(cache.get(first).is_some());
+    cache.insert(third, members_of(Uuid::new_v4()), generation);
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(first).is_some());
+    assert!(cache.get(second).is_none());
+    assert!(cache.get(third).is_some());
+    assert_eq!(cache.stats(), (3, 1));
+}
+
+/// Invalidating a group, a client or everything drops the snapshots that may be stale
+#[test]
+fn test_cache_invalidation() {
+    let cache = MembershipCache::new(10);
+    let client_id = Uuid::new_v4();
+    let (first, second, third) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    for group_id in [first, second] {
+        cache.insert(group_id, members_of(client_id), cache.generation());
+    }
+    cache.insert(third, members_of(Uuid::new_v4()), cache.generation());
+
+    cache.invalidate(MembershipChange::Group(first));
+    assert!(cache.get(first).is_none());
+    assert_eq!(cache.len(), 2);
+
+    // Only the groups the client is a member of
+    cache.invalidate(MembershipChange::Client(client_id));
+    assert!(cache.get(second).is_none());
+    assert!(cache.get(third).is_some());
+
+    cache.invalidate(MembershipChange::All);
+    assert!(cache.is_empty());
+}
+
+/// A snapshot loaded before an invalidation isn't cached after it
+#[test]
+fn test_cache_skips_snapshot_loaded_before_invalidation() {
+    let cache = MembershipCache::new(10);
+    let group_id = Uuid::new_v4();
+    let generation = cache.generation();
+    cache.invalidate(MembershipChange::Group(group_id));
+    cache.insert(group_id, members_of(Uuid::new_v4()), generation);
+    assert!(cache.get(group_id).is_none());
+
+    cache.insert(group_id, members_of(Uuid::new_v4()), cache.generation());
+    assert!(cache.get(group_id).is_some());
+}
+
+/// A cache of size 0 holds nothing
+#[test]
+fn test_disabled_cache() {
+    let cache = MembershipCache::new(0);
+    assert!(!cache.is_enabled());
+    cache.insert(
+        Uuid::new_v4(),
+        members_of(Uuid::new_v4()),
+        cache.generation(),
+    );
+    assert!(cache.is_empty());
+    assert!(!MembershipCache::default().is_enabled());
+}
+
+/// Membership changes round-trip through their notification payload
+#[test]
+fn test_membership_change_payload() {
+    let id = Uuid::new_v4();
+    for change in [
+        MembershipChange::Group(id),
+        MembershipChange::Client(id),
+        MembershipChange::All,
+    ] {
+        assert_eq!(
+            change.to_string().parse::<MembershipChange>().unwrap(),
+            change
+        );
+    }
+    assert!("group".parse::<MembershipChange>().is_err());
+    assert!(format!("user {}", id).parse::<MembershipChange>().is_err());
+    assert_eq!(
+        MembershipChange::of_purged(EntityKind::Client, id),
+        Some(MembershipChange::Client(id))
+    );
+    assert_eq!(MembershipChange::of_purged(EntityKind::Message, id), None);
+}
+
+/// Fetch the group's messages for the client
+async fn fetch(
+    service: &MLSServiceImpl<MockDatabase>,
+    group_id: Uuid,
+    client_id: Uuid,
+) -> Vec<mls::Message> {
+    service
+        .fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            include_read: true,
+            message_types: vec![],
+            types: vec![],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .messages
+}
+
+/// The service's own membership changes take effect right away, although the group was cached
+#[tokio::test]
+async fn test_service_invalidates_its_changes() {
+    let db = Arc::new(MockDatabase::new());
+    let cache = MembershipCache::new(100);
+    let service = MLSServiceImpl::new(db.clone()).with_membership_cache(cache.clone());
+    let (group_id, admin) = setup_group(&db).await;
+    add_member(&service, group_id, admin.client_id)
+        .await
+        .unwrap();
+    fetch(&service, group_id, admin.client_id).await;
+    assert_eq!(cache.len(), 1);
+
+    // The admin leaves; their next change is refused
+    service
+        .remove_member(Request::new(RemoveMemberRequest {
+            membership_id: admin.id.to_string(),
+            sender_id: admin.client_id.to_string(),
+        }))
+        .await
+        .unwrap();
+    assert!(cache.is_empty());
+    let status = add_member(&service, group_id, admin.client_id)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
+
+/// Changes announced on the bus, as other instances' are, drop the cached member sets
+#[tokio::test]
+async fn test_announced_change_invalidates_cache() {
+    let db = Arc::new(MockDatabase::new());
+    let events = EventBus::default();
+    let cache = MembershipCache::new(100);
+    cache.spawn_sync(&events);
+    let service = MLSServiceImpl::new(db.clone())
+        .with_event_bus(events.clone())
+        .with_membership_cache(cache.clone());
+    let (group_id, admin) = setup_group(&db).await;
+    fetch(&service, group_id, admin.client_id).await;
+    assert_eq!(cache.len(), 1);
+
+    // Another instance removes the admin and announces it
+    db.remove_membership(admin.id).await.unwrap();
+    events.publish(DomainEvent::MembershipsChanged(MembershipChange::Group(
+        group_id,
+    )));
+    until_empty(&cache).await;
+
+    let status = add_member(&service, group_id, admin.client_id)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
+
+/// Fetching a group's messages for a cached member returns what the membership join does
+#[tokio::test]
+async fn test_fetch_for_cached_member() {
+    let db = Arc::new(MockDatabase::new());
+    let uncached = MLSServiceImpl::new(db.clone());
+    let cache = MembershipCache::new(100);
+    let cached = MLSServiceImpl::new(db.clone()).with_membership_cache(cache.clone());
+    let (group_id, admin) = setup_group(&db).await;
+    for message_type in [MessageType::Commit, MessageType::Application] {
+        MessageFixture::new(message_type)
+            .in_group(group_id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+
+    let expected = fetch(&uncached, group_id, admin.client_id).await;
+    assert_eq!(expected.len(), 2);
+    for _ in 0..2 {
+        assert_eq!(fetch(&cached, group_id, admin.client_id).await, expected);
+    }
+    assert_eq!(cache.stats(), (1, 1));
+}
@@ -2,14 +2,17 @@ use std::sync::Arc;
 
 use chrono::Utc;
 use hermetic_mls::{
-    db::{DatabaseInterface, Group, Membership},
+    db::{DatabaseInterface, Group, Membership, MembershipRole},
     service::{
         mls::{
             self, mls_delivery_service_server::MlsDeliveryService, AddMemberRequest,
-            ListMembershipsRequest, RemoveMemberRequest,
+            GetMembershipRequest, ListMembershipHistoryRequest, ListMembershipsRequest,
+            RemoveMemberRequest,
         },
+        roles::Permission,
         MLSServiceImpl,
     },
+    settings::{RuntimeSettings, SettingsHandle},
 };
 use tonic::Request;
 use uuid::Uuid;
@@ -33,19 +36,39 @@ async fn test_add_member() {
         creator_id: Uuid::new_v4(),
         epoch: 0,
         state: Some(vec![1, 2, 3]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
         is_active: true,
+        deleted_at: None,
     };
 
     // Store the group in the database
     db.create_group(group).await.unwrap();
 
+    // Only admins can add members by default
+    let admin_id = Uuid::new_v4();
+    let admin = Membership {
+        id: Uuid::new_v4(),
+        client_id: admin_id,
+        group_id,
+        role: MembershipRole::Admin,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    };
+    db.add_membership(admin).await.unwrap();
+
     // Create a request to add a member
     let request = Request::new(AddMemberRequest {
         group_id: group_id.to_string(),
         client_id: client_id.to_string(),
-        role: "member".to_string(),
+        role: String::new(),
+        member_role: mls::MembershipRole::Member as i32,
+        sender_id: admin_id.to_string(),
     });
 
     // Call the service
@@ -57,13 +80,12 @@ async fn test_add_member() {
 
     // Verify membership was stored in database
     let memberships = db.list_memberships_by_group(group_id).await.unwrap();
-    assert_eq!(memberships.len(), 1);
+    assert_eq!(memberships.len(), 2);
 
-    let membership = &memberships[0];
-    assert_eq!(membership.id, membership_id);
+    let membership = memberships.iter().find(|m| m.id == membership_id).unwrap();
     assert_eq!(membership.client_id, client_id);
     assert_eq!(membership.group_id, group_id);
-    assert_eq!(membership.role, "member");
+    assert_eq!(membership.role, MembershipRole::Member);
     assert!(membership.removed_at.is_none());
 }
 
@@ -83,17 +105,20 @@ async fn test_remove_member() {
         id: membership_id,
         client_id,
         group_id,
-        role: "member".to_string(),
+        role: MembershipRole::Member,
         added_at: Utc::now(),
         removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
     };
 
     // Store membership in the database
     db.add_membership(membership).await.unwrap();
 
-    // Create a request to remove the member
+    // Create a request for the member to leave
     let request = Request::new(RemoveMemberRequest {
         membership_id: membership_id.to_string(),
+        sender_id: client_id.to_string(),
     });
 
     // Call the service
@@ -102,13 +127,120 @@ async fn test_remove_member() {
 
     assert_eq!(response.success, true);
 
-    // Verify membership was removed in database
+    // Verify membership was removed in database, keeping a removal timestamp
     let memberships = db.list_memberships_by_group(group_id).await.unwrap();
-    assert_eq!(memberships.len(), 1);
+    assert!(memberships.is_empty());
 
-    let membership = &memberships[0];
-    assert_eq!(membership.id, membership_id);
-    assert!(membership.removed_at.is_some()); // Should have a removal timestamp
+    let membership = db.get_membership(membership_id).await.unwrap();
+    assert!(membership.removed_at.is_some());
+}
+
+/// Membership changes are checked against the role policy of the acting member
+#[tokio::test]
+async fn test_membership_changes_follow_role_policy() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    let member_id = Uuid::new_v4();
+    let moderator_id = Uuid::new_v4();
+    let mut membership_ids = Vec::new();
+    for (client_id, role) in [
+        (member_id, MembershipRole::Member),
+        (moderator_id, MembershipRole::Moderator),
+    ] {
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        };
+        membership_ids.push(membership.id);
+        db.add_membership(membership).await.unwrap();
+    }
+
+    let add = |sender_id: Uuid| AddMemberRequest {
+        group_id: group_id.to_string(),
+        client_id: Uuid::new_v4().to_string(),
+        role: String::new(),
+        member_role: mls::MembershipRole::Member as i32,
+        sender_id: sender_id.to_string(),
+    };
+
+    // Neither plain members, moderators nor outsiders can add members by default
+    for sender_id in [member_id, moderator_id, Uuid::new_v4()] {
+        let status = service
+            .add_member(Request::new(add(sender_id)))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    // The policy can grant plain members more, though never over other roles
+    let settings = SettingsHandle::new(RuntimeSettings {
+        member_permissions: vec![Permission::AddMembers],
+        ..RuntimeSettings::default()
+    });
+    let service = service.with_settings(settings);
+    service
+        .add_member(Request::new(add(member_id)))
+        .await
+        .unwrap();
+    let status = service
+        .add_member(Request::new(AddMemberRequest {
+            member_role: mls::MembershipRole::Moderator as i32,
+            ..add(member_id)
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    // A plain member can't remove the moderator, and the moderator can remove the member
+    let status = service
+        .remove_member(Request::new(RemoveMemberRequest {
+            membership_id: membership_ids[1].to_string(),
+            sender_id: member_id.to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    service
+        .remove_member(Request::new(RemoveMemberRequest {
+            membership_id: membership_ids[0].to_string(),
+            sender_id: moderator_id.to_string(),
+        }))
+        .await
+        .unwrap();
+
+    // The acting member must be named unless the request is signed or authenticated
+    let status = service
+        .remove_member(Request::new(RemoveMemberRequest {
+            membership_id: membership_ids[1].to_string(),
+            sender_id: String::new(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
 }
 
 /// Test the ListMemberships RPC
@@ -128,18 +260,22 @@ async fn test_list_memberships() {
         id: Uuid::new_v4(),
         client_id: client1_id,
         group_id,
-        role: "admin".to_string(),
+        role: MembershipRole::Admin,
         added_at: Utc::now(),
         removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
     };
 
     let membership2 = Membership {
         id: Uuid::new_v4(),
         client_id: client2_id,
         group_id,
-        role: "member".to_string(),
+        role: MembershipRole::Member,
         added_at: Utc::now(),
         removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
     };
 
     // Create a removed membership
@@ -147,9 +283,11 @@ async fn test_list_memberships() {
         id: Uuid::new_v4(),
         client_id: Uuid::new_v4(),
         group_id,
-        role: "member".to_string(),
+        role: MembershipRole::Member,
         added_at: Utc::now(),
         removed_at: Some(Utc::now()),
+        last_acked_epoch: None,
+        last_acked_at: None,
     };
 
     // Store memberships in the database
@@ -167,13 +305,13 @@ async fn test_list_memberships() {
     let response = response.into_inner();
 
     // Verify memberships in response
-    assert_eq!(response.memberships.len(), 3); // We include removed memberships in response
+    assert_eq!(response.memberships.len(), 2); // Removed memberships are left out
 
     // Verify membership details are correct
     let membership_ids: Vec<String> = response.memberships.iter().map(|m| m.id.clone()).collect();
     assert!(membership_ids.contains(&membership1.id.to_string()));
     assert!(membership_ids.contains(&membership2.id.to_string()));
-    assert!(membership_ids.contains(&membership3.id.to_string()));
+    assert!(!membership_ids.contains(&membership3.id.to_string()));
 
     // Verify we can find both the admin and member roles
     let roles: Vec<String> = response
@@ -184,3 +322,184 @@ async fn test_list_memberships() {
     assert!(roles.contains(&"admin".to_string()));
     assert!(roles.contains(&"member".to_string()));
 }
+
+/// Test the AcknowledgeEpoch RPC
+#[tokio::test]
+async fn test_acknowledge_epoch() {
+    // Create a mock database
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    // Create test data
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+
+    // Create a group that has advanced to epoch 3
+    let group = Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 3,
+        state: Some(vec![1, 2, 3]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    // Add the client to the group at epoch 1
+    let membership = Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: Some(1),
+        last_acked_at: None,
+    };
+    db.add_membership(membership).await.unwrap();
+
+    // Acknowledge epoch 2
+    let request = Request::new(mls::AcknowledgeEpochRequest {
+        group_id: group_id.to_string(),
+        client_id: client_id.to_string(),
+        epoch: 2,
+    });
+    let response = service.acknowledge_epoch(request).await.unwrap();
+    assert!(response.into_inner().success);
+
+    // Verify the acknowledgement was recorded
+    let memberships = db.list_memberships_by_group(group_id).await.unwrap();
+    assert_eq!(memberships[0].last_acked_epoch, Some(2));
+    assert!(memberships[0].last_acked_at.is_some());
+
+    // An epoch ahead of the group is rejected
+    let request = Request::new(mls::AcknowledgeEpochRequest {
+        group_id: group_id.to_string(),
+        client_id: client_id.to_string(),
+        epoch: 4,
+    });
+    let status = service.acknowledge_epoch(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+/// Test the GetMembership RPC, including removed memberships
+#[tokio::test]
+async fn test_get_membership() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let membership = Membership {
+        id: Uuid::new_v4(),
+        client_id: Uuid::new_v4(),
+        group_id: Uuid::new_v4(),
+        role: MembershipRole::Admin,
+        added_at: Utc::now(),
+        removed_at: Some(Utc::now()),
+        last_acked_epoch: Some(2),
+        last_acked_at: None,
+    };
+    db.add_membership(membership.clone()).await.unwrap();
+
+    let request = Request::new(GetMembershipRequest {
+        membership_id: membership.id.to_string(),
+    });
+    let response = service.get_membership(request).await.unwrap().into_inner();
+
+    let fetched = response.membership.unwrap();
+    assert_eq!(fetched.id, membership.id.to_string());
+    assert_eq!(fetched.client_id, membership.client_id.to_string());
+    assert_eq!(fetched.role, "admin");
+    assert_eq!(fetched.member_role, mls::MembershipRole::Admin as i32);
+    assert!(!fetched.removed_at.is_empty());
+    assert_eq!(fetched.last_acked_epoch, 2);
+
+    // Unknown memberships are reported as NotFound
+    let request = Request::new(GetMembershipRequest {
+        membership_id: Uuid::new_v4().to_string(),
+    });
+    let status = service.get_membership(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
+/// Test the ListMembershipHistory RPC
+#[tokio::test]
+async fn test_list_membership_history() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let client_id = Uuid::new_v4();
+    let group_id = Uuid::new_v4();
+    let other_group_id = Uuid::new_v4();
+    let now = Utc::now();
+
+    // The client joined, left and rejoined the group, and is also in another group
+    let cycles = [
+        (
+            group_id,
+            now - chrono::Duration::days(3),
+            Some(now - chrono::Duration::days(2)),
+        ),
+        (group_id, now - chrono::Duration::days(1), None),
+        (other_group_id, now, None),
+    ];
+    let mut ids = Vec::new();
+    for (group_id, added_at, removed_at) in cycles {
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at,
+            removed_at,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        };
+        ids.push(membership.id.to_string());
+        db.add_membership(membership).await.unwrap();
+    }
+
+    // Another client's membership is never included
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id: Uuid::new_v4(),
+        group_id,
+        role: MembershipRole::Member,
+        added_at: now,
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+
+    let request = Request::new(ListMembershipHistoryRequest {
+        client_id: client_id.to_string(),
+        group_id: String::new(),
+    });
+    let response = service
+        .list_membership_history(request)
+        .await
+        .unwrap()
+        .into_inner();
+    let history: Vec<String> = response.memberships.iter().map(|m| m.id.clone()).collect();
+    assert_eq!(history, ids);
+    assert!(!response.memberships[0].removed_at.is_empty());
+
+    // Restricting to one group keeps only its add/remove cycles
+    let request = Request::new(ListMembershipHistoryRequest {
+        client_id: client_id.to_string(),
+        group_id: group_id.to_string(),
+    });
+    let response = service
+        .list_membership_history(request)
+        .await
+        .unwrap()
+        .into_inner();
+    let history: Vec<String> = response.memberships.iter().map(|m| m.id.clone()).collect();
+    assert_eq!(history, ids[..2]);
+}
@@ -2,18 +2,37 @@ use std::sync::Arc;
 
 use chrono::Utc;
 use hermetic_mls::{
-    db::{DatabaseInterface, Group, Membership, Message},
+    db::{
+        DatabaseInterface, DbError, DeliveryCursor, EntityKind, Group, Membership, MembershipRole,
+        Message, MessageType, ProposalType,
+    },
+    fixtures::{GroupFixture, MembershipFixture},
+    janitor::{Janitor, JanitorConfig},
+    notices::{SystemEnvelope, SystemNotice},
     service::{
         mls::{
-            self, mls_delivery_service_server::MlsDeliveryService, FetchMessagesRequest,
-            StoreCommitRequest, StoreProposalRequest, StoreWelcomeRequest,
+            self, mls_delivery_service_server::MlsDeliveryService, AckMessagesRequest,
+            BroadcastSystemMessageRequest, CreateGroupRequest, FetchMessagesRequest,
+            ListPendingProposalsRequest, MarkMessagesReadRequest, ReportAbuseRequest,
+            RequestWelcomeResendRequest, StoreApplicationMessageRequest, StoreCommitRequest,
+            StoreProposalRequest, StoreWelcomeRequest,
         },
+        validation::ValidationPolicy,
         MLSServiceImpl,
     },
+    settings::{RuntimeSettings, SettingsHandle},
 };
-use tonic::Request;
+use openmls::credentials::{BasicCredential, CredentialWithKey};
+use openmls::prelude::{GroupId, MlsGroup, SignatureScheme};
+use openmls_basic_credential::SignatureKeyPair;
+use openmls_rust_crypto::OpenMlsRustCrypto;
+use prost_types::FieldMask;
+use tls_codec::Serialize;
+use tonic::{Code, Request};
+use tonic_types::StatusExt;
 use uuid::Uuid;
 
+use super::register_client;
 use crate::mock_db::MockDatabase;
 
 /// Test the StoreProposal RPC
@@ -27,13 +46,34 @@ async fn test_store_proposal() {
     let group_id = Uuid::new_v4();
     let sender_id = Uuid::new_v4();
     let proposal_data = vec![1, 2, 3, 4, 5];
+    db.create_group(Group {
+        id: group_id,
+        creator_id: sender_id,
+        epoch: 2,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
 
     // Create a request to store a proposal
     let request = Request::new(StoreProposalRequest {
         group_id: group_id.to_string(),
         sender_id: sender_id.to_string(),
         proposal: proposal_data.clone(),
-        proposal_type: "add".to_string(),
+        proposal_type: String::new(),
+        r#type: mls::ProposalType::Add as i32,
+        extra: vec![],
     });
 
     // Call the service
@@ -45,7 +85,7 @@ async fn test_store_proposal() {
 
     // Verify message was stored in database
     let messages = db
-        .fetch_messages_for_client(sender_id, Some(group_id), true)
+        .fetch_messages_for_client(sender_id, Some(group_id), true, &[], true)
         .await
         .unwrap();
 
@@ -54,12 +94,17 @@ async fn test_store_proposal() {
         .iter()
         .find(|m| m.id == message_id)
         .expect("Message not found");
-    assert_eq!(message.group_id, group_id);
+    assert_eq!(message.group_id, Some(group_id));
     assert_eq!(message.sender_id, sender_id);
-    assert_eq!(message.message_type, "proposal");
+    assert_eq!(message.message_type, MessageType::Proposal);
     assert_eq!(message.proposal, Some(proposal_data));
     assert_eq!(message.commit, None);
     assert_eq!(message.welcome, None);
+
+    // The proposal is queued under the group's current epoch
+    assert_eq!(message.epoch, Some(2));
+    assert_eq!(response.epoch, 2);
+    assert_eq!(Some(response.proposal_ref), message.payload_hash);
 }
 
 /// Test the StoreCommit RPC
@@ -80,13 +125,21 @@ async fn test_store_commit() {
         creator_id: sender_id,
         epoch: 0,
         state: Some(vec![10, 11, 12]),
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
         is_active: true,
+        deleted_at: None,
     };
 
     // Store the group
     db.create_group(group).await.unwrap();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
 
     // Create a request to store a commit
     let request = Request::new(StoreCommitRequest {
@@ -94,6 +147,8 @@ async fn test_store_commit() {
         sender_id: sender_id.to_string(),
         commit: commit_data.clone(),
         epoch: 1, // New epoch
+        extra: vec![],
+        proposal_refs: vec![],
     });
 
     // Call the service
@@ -105,7 +160,7 @@ async fn test_store_commit() {
 
     // Verify message was stored in database
     let messages = db
-        .fetch_messages_for_client(sender_id, Some(group_id), true)
+        .fetch_messages_for_client(sender_id, Some(group_id), true, &[], true)
         .await
         .unwrap();
 
@@ -114,9 +169,9 @@ async fn test_store_commit() {
         .iter()
         .find(|m| m.id == message_id)
         .expect("Message not found");
-    assert_eq!(message.group_id, group_id);
+    assert_eq!(message.group_id, Some(group_id));
     assert_eq!(message.sender_id, sender_id);
-    assert_eq!(message.message_type, "commit");
+    assert_eq!(message.message_type, MessageType::Commit);
     assert_eq!(message.commit, Some(commit_data));
     assert_eq!(message.proposal, None);
     assert_eq!(message.welcome, None);
@@ -136,16 +191,27 @@ async fn test_store_welcome() {
     // Create test data
     let group_id = Uuid::new_v4();
     let sender_id = Uuid::new_v4();
-    let recipient1_id = Uuid::new_v4();
-    let recipient2_id = Uuid::new_v4();
+    let recipient_user_id = Uuid::new_v4();
+    let recipient1_id = register_client(db.as_ref(), recipient_user_id).await;
+    let recipient2_id = register_client(db.as_ref(), recipient_user_id).await;
     let welcome_data = vec![1, 2, 3, 4, 5];
 
+    // The sender and the recipients were added to the group before the welcome
+    for client_id in [sender_id, recipient1_id, recipient2_id] {
+        MembershipFixture::new(group_id, client_id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+
     // Create a request to store a welcome
     let request = Request::new(StoreWelcomeRequest {
         group_id: group_id.to_string(),
         sender_id: sender_id.to_string(),
         welcome: welcome_data.clone(),
         recipient_ids: vec![recipient1_id.to_string(), recipient2_id.to_string()],
+        extra: vec![],
+        recipient_user_id: recipient_user_id.to_string(),
     });
 
     // Call the service
@@ -155,9 +221,9 @@ async fn test_store_welcome() {
     // Parse message_id from response
     let message_id = Uuid::parse_str(&response.message_id).unwrap();
 
-    // Verify message was stored in database
+    // Verify message was stored in database, for its recipients
     let messages = db
-        .fetch_messages_for_client(sender_id, Some(group_id), true)
+        .fetch_messages_for_client(recipient1_id, Some(group_id), true, &[], true)
         .await
         .unwrap();
 
@@ -166,12 +232,64 @@ async fn test_store_welcome() {
         .iter()
         .find(|m| m.id == message_id)
         .expect("Message not found");
-    assert_eq!(message.group_id, group_id);
+    assert_eq!(message.group_id, Some(group_id));
     assert_eq!(message.sender_id, sender_id);
-    assert_eq!(message.message_type, "welcome");
+    assert_eq!(message.message_type, MessageType::Welcome);
     assert_eq!(message.welcome, Some(welcome_data));
     assert_eq!(message.proposal, None);
     assert_eq!(message.commit, None);
+
+    // Other members of the group, like the sender, don't receive it
+    let messages = db
+        .fetch_messages_for_client(sender_id, Some(group_id), true, &[], true)
+        .await
+        .unwrap();
+    assert!(messages.iter().all(|m| m.id != message_id));
+}
+
+/// Test that welcomes are only stored for registered recipients of the intended user
+#[tokio::test]
+async fn test_store_welcome_rejects_unknown_recipients() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let sender_id = Uuid::new_v4();
+    let user_id = Uuid::new_v4();
+    let recipient_id = register_client(db.as_ref(), user_id).await;
+    let other_user_client_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let status = service
+        .store_welcome(Request::new(StoreWelcomeRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            welcome: vec![1, 2, 3],
+            recipient_ids: vec![
+                recipient_id.to_string(),
+                Uuid::new_v4().to_string(),
+                other_user_client_id.to_string(),
+            ],
+            extra: vec![],
+            recipient_user_id: user_id.to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let violations = status.get_details_bad_request().unwrap().field_violations;
+    let fields: Vec<_> = violations.iter().map(|v| v.field.as_str()).collect();
+    assert_eq!(fields, vec!["recipient_ids[1]", "recipient_ids[2]"]);
+    assert_eq!(violations[0].description, "is not a registered client");
+
+    // Nothing was stored
+    let messages = db
+        .fetch_messages_for_client(sender_id, Some(group_id), true, &[], true)
+        .await
+        .unwrap();
+    assert!(messages.is_empty());
 }
 
 /// Test the FetchMessages RPC
@@ -190,52 +308,84 @@ async fn test_fetch_messages() {
         id: Uuid::new_v4(),
         client_id,
         group_id,
-        role: "member".to_string(),
+        role: MembershipRole::Member,
         added_at: Utc::now(),
         removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
     };
     db.add_membership(membership).await.unwrap();
 
     // Create some messages
     let message1 = Message {
         id: Uuid::new_v4(),
-        group_id,
+        group_id: Some(group_id),
         sender_id: Uuid::new_v4(),
-        created_at: Utc::now(),
+        created_at: Utc::now() - chrono::Duration::minutes(1),
         read: false,
-        message_type: "proposal".to_string(),
+        message_type: MessageType::Proposal,
         proposal: Some(vec![1, 2, 3]),
         commit: None,
         welcome: None,
-        proposal_type: Some("add".to_string()),
+        system: None,
+        application: None,
+        proposal_type: Some(ProposalType::Add),
         epoch: None,
         recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
     };
 
     let message2 = Message {
         id: Uuid::new_v4(),
-        group_id,
+        group_id: Some(group_id),
         sender_id: Uuid::new_v4(),
         created_at: Utc::now(),
-        read: true, // This one is already read
-        message_type: "commit".to_string(),
+        read: false,
+        message_type: MessageType::Commit,
         proposal: None,
         commit: Some(vec![4, 5, 6]),
         welcome: None,
+        system: None,
+        application: None,
         proposal_type: None,
         epoch: Some(1),
         recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
     };
 
     // Store messages
-    db.store_message(message1.clone()).await.unwrap();
-    db.store_message(message2.clone()).await.unwrap();
+    let message1 = db.store_message(message1).await.unwrap();
+    let message2 = db.store_message(message2).await.unwrap();
+
+    // The client already acknowledged the first one
+    db.advance_delivery_cursors(
+        client_id,
+        &[DeliveryCursor {
+            group_id: Some(group_id),
+            sequence: message1.sequence,
+        }],
+    )
+    .await
+    .unwrap();
 
     // Create a request to fetch messages
     let request = Request::new(FetchMessagesRequest {
         client_id: client_id.to_string(),
         group_id: group_id.to_string(),
-        include_read: false, // Only unread messages
+        include_read: false, // Only unacknowledged messages
+        message_types: vec![],
+        types: vec![],
+        interleave_senders: false,
+        max_application_per_sender: 0,
+        read_mask: None,
     });
 
     // Call the service
@@ -243,17 +393,22 @@ async fn test_fetch_messages() {
     let response = response.into_inner();
 
     // Verify messages in response
-    assert_eq!(response.messages.len(), 1); // Only the unread message
+    assert_eq!(response.messages.len(), 1); // Only the unacknowledged message
 
     let fetched_message = &response.messages[0];
-    assert_eq!(fetched_message.id, message1.id.to_string());
-    assert_eq!(fetched_message.message_type, "proposal");
+    assert_eq!(fetched_message.id, message2.id.to_string());
+    assert_eq!(fetched_message.message_type, "commit");
 
-    // Now fetch all messages including read ones
+    // Now fetch all messages including acknowledged ones
     let request = Request::new(FetchMessagesRequest {
         client_id: client_id.to_string(),
         group_id: group_id.to_string(),
-        include_read: true, // Include read messages
+        include_read: true, // Include acknowledged messages
+        message_types: vec![],
+        types: vec![],
+        interleave_senders: false,
+        max_application_per_sender: 0,
+        read_mask: None,
     });
 
     let response = service.fetch_messages(request).await.unwrap();
@@ -262,3 +417,1787 @@ async fn test_fetch_messages() {
     // Verify messages in response
     assert_eq!(response.messages.len(), 2); // Both messages
 }
+
+/// Test that a read mask limits the fields FetchMessages returns
+#[tokio::test]
+async fn test_fetch_messages_read_mask() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+    let message = Message {
+        id: Uuid::new_v4(),
+        group_id: Some(group_id),
+        sender_id: Uuid::new_v4(),
+        created_at: Utc::now(),
+        read: false,
+        message_type: MessageType::Commit,
+        proposal: None,
+        commit: Some(vec![4, 5, 6]),
+        welcome: None,
+        system: None,
+        application: None,
+        proposal_type: None,
+        epoch: Some(1),
+        recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
+    };
+    db.store_message(message.clone()).await.unwrap();
+
+    let fetch = |paths: &[&str]| {
+        service.fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            include_read: false,
+            message_types: vec![],
+            types: vec![],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: Some(FieldMask {
+                paths: paths.iter().map(|p| p.to_string()).collect(),
+            }),
+        }))
+    };
+
+    // Metadata only: the content is neither loaded nor returned
+    let messages = fetch(&["id", "type"]).await.unwrap().into_inner().messages;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id, message.id.to_string());
+    assert_eq!(messages[0].r#type, mls::MessageType::Commit as i32);
+    assert!(messages[0].content.is_none());
+    assert!(messages[0].sender_id.is_empty());
+    assert!(messages[0].message_type.is_empty());
+
+    // Content is selected by its oneof field
+    let messages = fetch(&["commit"]).await.unwrap().into_inner().messages;
+    assert_eq!(
+        messages[0].content,
+        Some(mls::message::Content::Commit(vec![4, 5, 6]))
+    );
+    assert!(messages[0].id.is_empty());
+    let messages = fetch(&["application"]).await.unwrap().into_inner().messages;
+    assert!(messages[0].content.is_none());
+
+    // Unknown fields are rejected by index
+    let status = fetch(&["id", "payload"]).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let violations = status.get_details_bad_request().unwrap().field_violations;
+    assert_eq!(violations[0].field, "read_mask.paths[1]");
+}
+
+/// Recipients can mark their messages read, which drops them from their own unread fetches; other
+/// clients can't
+#[tokio::test]
+async fn test_mark_messages_read() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let member_id = Uuid::new_v4();
+    let other_member_id = Uuid::new_v4();
+    let outsider_id = Uuid::new_v4();
+    for client_id in [member_id, other_member_id] {
+        db.add_membership(Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        })
+        .await
+        .unwrap();
+    }
+    let message_id = Uuid::new_v4();
+    db.store_message(Message {
+        id: message_id,
+        group_id: Some(group_id),
+        sender_id: Uuid::new_v4(),
+        created_at: Utc::now(),
+        read: false,
+        message_type: MessageType::Commit,
+        proposal: None,
+        commit: Some(vec![1, 2, 3]),
+        welcome: None,
+        system: None,
+        application: None,
+        proposal_type: None,
+        epoch: Some(1),
+        recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
+    })
+    .await
+    .unwrap();
+
+    let mark = |client_id: Uuid, message_ids: Vec<String>| {
+        service.mark_messages_read(Request::new(MarkMessagesReadRequest {
+            client_id: client_id.to_string(),
+            message_ids,
+        }))
+    };
+    let unread = |client_id: Uuid| {
+        let service = &service;
+        async move {
+            service
+                .fetch_messages(Request::new(FetchMessagesRequest {
+                    client_id: client_id.to_string(),
+                    ..Default::default()
+                }))
+                .await
+                .unwrap()
+                .into_inner()
+                .messages
+                .len()
+        }
+    };
+
+    // Neither outsiders nor unknown ids can be marked
+    let status = mark(outsider_id, vec![message_id.to_string()])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = mark(member_id, vec![Uuid::new_v4().to_string()])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = mark(member_id, vec!["not-a-uuid".to_string()])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(unread(member_id).await, 1);
+
+    // Read state is per recipient: the other member still has the message unread
+    mark(member_id, vec![message_id.to_string()]).await.unwrap();
+    assert_eq!(unread(member_id).await, 0);
+    assert_eq!(unread(other_member_id).await, 1);
+}
+
+/// Each client acknowledges fetched messages with the ack token, and only messages after its
+/// own cursors are fetched again
+#[tokio::test]
+async fn test_ack_messages() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let alice = Uuid::new_v4();
+    let bob = Uuid::new_v4();
+    for client_id in [alice, bob] {
+        db.add_membership(Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    // A sender's two application messages, then a commit
+    let flooder = Uuid::new_v4();
+    let start = Utc::now();
+    let queue = [
+        (flooder, MessageType::Application),
+        (flooder, MessageType::Application),
+        (bob, MessageType::Commit),
+    ];
+    let mut ids = Vec::new();
+    for (i, (sender_id, message_type)) in queue.into_iter().enumerate() {
+        let is_commit = message_type == MessageType::Commit;
+        let id = Uuid::new_v4();
+        ids.push(id.to_string());
+        db.store_message(Message {
+            id,
+            group_id: Some(group_id),
+            sender_id,
+            created_at: start + chrono::Duration::milliseconds(i as i64),
+            read: false,
+            message_type,
+            proposal: None,
+            commit: is_commit.then(|| vec![i as u8]),
+            welcome: None,
+            system: None,
+            application: (!is_commit).then(|| vec![i as u8]),
+            proposal_type: None,
+            epoch: Some(0),
+            recipients: None,
+            extra: None,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
+        })
+        .await
+        .unwrap();
+    }
+
+    let fetch = |request: FetchMessagesRequest| {
+        let service = &service;
+        async move {
+            let response = service
+                .fetch_messages(Request::new(request))
+                .await
+                .unwrap()
+                .into_inner();
+            let ids: Vec<String> = response.messages.into_iter().map(|m| m.id).collect();
+            (ids, response.ack_token)
+        }
+    };
+    let unacked = |client_id: Uuid| FetchMessagesRequest {
+        client_id: client_id.to_string(),
+        ..Default::default()
+    };
+    let ack = |client_id: Uuid, ack_token: String| {
+        service.ack_messages(Request::new(AckMessagesRequest {
+            client_id: client_id.to_string(),
+            ack_token,
+            watermarks: vec![],
+        }))
+    };
+
+    // Messages a fetch held back stay unacknowledged, with the rest of their group
+    let (fetched, capped_token) = fetch(FetchMessagesRequest {
+        max_application_per_sender: 1,
+        ..unacked(alice)
+    })
+    .await;
+    assert_eq!(fetched, vec![ids[0].clone(), ids[2].clone()]);
+    ack(alice, capped_token.clone()).await.unwrap();
+
+    let (fetched, token) = fetch(unacked(alice)).await;
+    assert_eq!(fetched, ids[1..].to_vec());
+    ack(alice, token).await.unwrap();
+    let (fetched, token) = fetch(unacked(alice)).await;
+    assert!(fetched.is_empty());
+    assert!(token.is_empty());
+
+    // Cursors never move back, and other clients keep their own
+    ack(alice, capped_token).await.unwrap();
+    assert!(fetch(unacked(alice)).await.0.is_empty());
+    let (fetched, _) = fetch(FetchMessagesRequest {
+        include_read: true,
+        ..unacked(alice)
+    })
+    .await;
+    assert_eq!(fetched, ids);
+    assert_eq!(fetch(unacked(bob)).await.0, ids);
+
+    // Fetches filtered by type can't be acknowledged
+    let (fetched, token) = fetch(FetchMessagesRequest {
+        types: vec![mls::MessageType::Commit as i32],
+        ..unacked(bob)
+    })
+    .await;
+    assert_eq!(fetched, vec![ids[2].clone()]);
+    assert!(token.is_empty());
+
+    let status = ack(bob, "not-a-token".to_string()).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(fetch(unacked(bob)).await.0, ids);
+}
+
+/// A group's messages are delivered in the order they were stored, and acknowledged up to a
+/// sequence with a watermark
+#[tokio::test]
+async fn test_ack_watermarks() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+
+    // The first message stored has the latest timestamp, as if its server's clock ran ahead
+    let start = Utc::now();
+    let store = |skew: i64| {
+        db.store_message(Message {
+            id: Uuid::new_v4(),
+            group_id: Some(group_id),
+            sender_id: Uuid::new_v4(),
+            created_at: start + chrono::Duration::milliseconds(skew),
+            read: false,
+            message_type: MessageType::Application,
+            proposal: None,
+            commit: None,
+            welcome: None,
+            system: None,
+            application: Some(vec![1]),
+            proposal_type: None,
+            epoch: Some(0),
+            recipients: None,
+            extra: None,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
+        })
+    };
+    let mut stored = Vec::new();
+    for skew in [10, 0, 5] {
+        stored.push(store(skew).await.unwrap());
+    }
+    assert_eq!(
+        stored.iter().map(|m| m.sequence).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+
+    let fetch = || async {
+        service
+            .fetch_messages(Request::new(FetchMessagesRequest {
+                client_id: client_id.to_string(),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .messages
+            .into_iter()
+            .map(|m| (m.id, m.sequence))
+            .collect::<Vec<_>>()
+    };
+    let ack = |group_id: String, sequence: i64| {
+        service.ack_messages(Request::new(AckMessagesRequest {
+            client_id: client_id.to_string(),
+            ack_token: String::new(),
+            watermarks: vec![mls::DeliveryWatermark { group_id, sequence }],
+        }))
+    };
+
+    let expected: Vec<_> = stored
+        .iter()
+        .map(|m| (m.id.to_string(), m.sequence))
+        .collect();
+    assert_eq!(fetch().await, expected);
+
+    ack(group_id.to_string(), 2).await.unwrap();
+    assert_eq!(fetch().await, expected[2..].to_vec());
+
+    // A watermark past the group's last message doesn't hide the messages stored after it
+    ack(group_id.to_string(), 100).await.unwrap();
+    assert!(fetch().await.is_empty());
+    let later = store(20).await.unwrap();
+    assert_eq!(fetch().await, vec![(later.id.to_string(), 4)]);
+
+    for (group_id, sequence) in [(group_id.to_string(), 0), ("not-a-uuid".to_string(), 4)] {
+        let status = ack(group_id, sequence).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+    assert_eq!(fetch().await.len(), 1);
+}
+
+/// Test the StoreApplicationMessage RPC and the message type filter on FetchMessages
+#[tokio::test]
+async fn test_fetch_messages_by_type() {
+    let db = Arc::new(MockDatabase::new());
+    let service =
+        MLSServiceImpl::new(db.clone()).with_validation_policy(ValidationPolicy::Unchecked);
+
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: client_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+
+    let membership = Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    };
+    db.add_membership(membership).await.unwrap();
+    let sender_id = Uuid::new_v4();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    // One commit and one application message in the group
+    service
+        .store_commit(Request::new(StoreCommitRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            commit: vec![1, 2, 3],
+            epoch: 1,
+            extra: vec![],
+            proposal_refs: vec![],
+        }))
+        .await
+        .unwrap();
+    service
+        .store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            message: vec![4, 5, 6],
+            epoch: 1,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+        .await
+        .unwrap();
+
+    // A rejoining client pulls commits first
+    let response = service
+        .fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            include_read: false,
+            message_types: vec![],
+            types: vec![mls::MessageType::Commit as i32],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(response.messages[0].message_type, "commit");
+
+    // ...and defers the application backlog
+    let response = service
+        .fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            include_read: false,
+            message_types: vec![],
+            types: vec![mls::MessageType::Application as i32],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(
+        response.messages[0].content,
+        Some(mls::message::Content::Application(vec![4, 5, 6]))
+    );
+
+    // The deprecated string filter is still accepted
+    let response = service
+        .fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            include_read: false,
+            message_types: vec!["commit".to_string()],
+            types: vec![],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(response.messages[0].r#type, mls::MessageType::Commit as i32);
+    assert_eq!(response.messages[0].message_type, "commit");
+
+    // Unknown types are rejected
+    let status = service
+        .fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            include_read: false,
+            message_types: vec!["handshake".to_string()],
+            types: vec![],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: None,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+/// Test that fairness options interleave and cap application messages by sender while
+/// handshake messages keep their place
+#[tokio::test]
+async fn test_fetch_messages_sender_fairness() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+
+    // A flooder sends four messages, then another member one, then a commit, then one each
+    let flooder = Uuid::new_v4();
+    let other = Uuid::new_v4();
+    let start = Utc::now();
+    let queue = [
+        (flooder, MessageType::Application),
+        (flooder, MessageType::Application),
+        (flooder, MessageType::Application),
+        (flooder, MessageType::Application),
+        (other, MessageType::Application),
+        (other, MessageType::Commit),
+        (flooder, MessageType::Application),
+        (other, MessageType::Application),
+    ];
+    for (i, (sender_id, message_type)) in queue.into_iter().enumerate() {
+        let is_commit = message_type == MessageType::Commit;
+        db.store_message(Message {
+            id: Uuid::from_u128(i as u128 + 1),
+            group_id: Some(group_id),
+            sender_id,
+            created_at: start + chrono::Duration::milliseconds(i as i64),
+            read: false,
+            message_type,
+            proposal: None,
+            commit: is_commit.then(|| vec![i as u8]),
+            welcome: None,
+            system: None,
+            application: (!is_commit).then(|| vec![i as u8]),
+            proposal_type: None,
+            epoch: Some(0),
+            recipients: None,
+            extra: None,
+            payload_hash: None,
+            expires_at: None,
+            deleted_at: None,
+            sequence: 0,
+        })
+        .await
+        .unwrap();
+    }
+
+    let fetch = |interleave_senders: bool, max_application_per_sender: u32| {
+        let service = &service;
+        async move {
+            service
+                .fetch_messages(Request::new(FetchMessagesRequest {
+                    client_id: client_id.to_string(),
+                    group_id: group_id.to_string(),
+                    include_read: false,
+                    message_types: vec![],
+                    types: vec![],
+                    interleave_senders,
+                    max_application_per_sender,
+                    read_mask: None,
+                }))
+                .await
+                .unwrap()
+                .into_inner()
+                .messages
+                .iter()
+                .map(|m| Uuid::parse_str(&m.id).unwrap().as_u128() - 1)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // By default the order is strictly chronological
+    assert_eq!(fetch(false, 0).await, vec![0, 1, 2, 3, 4, 5, 6, 7]);
+
+    // Interleaving lets the other member in early, but never across the commit
+    assert_eq!(fetch(true, 0).await, vec![0, 4, 1, 2, 3, 5, 6, 7]);
+
+    // A cap defers the flooder's backlog to a later fetch; the commit is always returned
+    assert_eq!(fetch(false, 2).await, vec![0, 1, 4, 5, 7]);
+    assert_eq!(fetch(true, 2).await, vec![0, 4, 1, 5, 7]);
+}
+
+/// Test that metadata attached to a message is returned with it
+#[tokio::test]
+async fn test_message_extra_metadata() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let client_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: client_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+    let sender_id = Uuid::new_v4();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let store = |extra: &str| {
+        service.store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            message: vec![1, 2, 3],
+            epoch: 0,
+            extra: extra.as_bytes().to_vec(),
+            ttl_secs: 0,
+        }))
+    };
+
+    // Only JSON objects are accepted
+    for invalid in ["[1, 2]", "\"urgent\"", "{"] {
+        let status = store(invalid).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    store(r#"{"priority": "high", "thread_id": "t-1"}"#)
+        .await
+        .unwrap();
+
+    let response = service
+        .fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.to_string(),
+            include_read: false,
+            message_types: vec![],
+            types: vec![],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.messages.len(), 1);
+    let extra: serde_json::Value = serde_json::from_slice(&response.messages[0].extra).unwrap();
+    assert_eq!(
+        extra,
+        serde_json::json!({"priority": "high", "thread_id": "t-1"})
+    );
+}
+
+/// Messages carry a SHA-256 of their payload from the moment they're stored, also when the
+/// payload itself isn't requested
+#[tokio::test]
+async fn test_message_payload_hash() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let client_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let group_id = service
+        .create_group(Request::new(CreateGroupRequest {
+            creator_id: client_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: vec![],
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .group_id;
+    service
+        .store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.clone(),
+            sender_id: client_id.to_string(),
+            message: b"abc".to_vec(),
+            epoch: 0,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+        .await
+        .unwrap();
+
+    // SHA-256 of "abc"
+    let expected = [
+        0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22,
+        0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00,
+        0x15, 0xad,
+    ];
+    let fetch = |read_mask: Option<FieldMask>| {
+        service.fetch_messages(Request::new(FetchMessagesRequest {
+            client_id: client_id.to_string(),
+            group_id: group_id.clone(),
+            include_read: true,
+            message_types: vec![],
+            types: vec![],
+            interleave_senders: false,
+            max_application_per_sender: 0,
+            read_mask,
+        }))
+    };
+    let messages = fetch(None).await.unwrap().into_inner().messages;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].payload_hash, expected);
+
+    let messages = fetch(Some(FieldMask {
+        paths: vec!["id".to_string(), "payload_hash".to_string()],
+    }))
+    .await
+    .unwrap()
+    .into_inner()
+    .messages;
+    assert_eq!(messages[0].content, None);
+    assert_eq!(messages[0].payload_hash, expected);
+}
+
+/// Test the BroadcastSystemMessage RPC
+#[tokio::test]
+async fn test_broadcast_system_message() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let admin_id = Uuid::new_v4();
+    let member_id = Uuid::new_v4();
+    let group = Group {
+        id: group_id,
+        creator_id: admin_id,
+        epoch: 3,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    };
+    db.create_group(group).await.unwrap();
+    for (client_id, role) in [
+        (admin_id, MembershipRole::Admin),
+        (member_id, MembershipRole::Member),
+    ] {
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: Some(3),
+            last_acked_at: None,
+        };
+        db.add_membership(membership).await.unwrap();
+    }
+
+    // Members can't broadcast
+    let status = service
+        .broadcast_system_message(Request::new(BroadcastSystemMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: member_id.to_string(),
+            text: "Maintenance tonight".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    // Admins can, and every member receives the announcement
+    service
+        .broadcast_system_message(Request::new(BroadcastSystemMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: admin_id.to_string(),
+            text: "Maintenance tonight".to_string(),
+        }))
+        .await
+        .unwrap();
+
+    for client_id in [admin_id, member_id] {
+        let messages = db
+            .fetch_messages_for_client(client_id, Some(group_id), false, &[], true)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+
+        let envelope = SystemEnvelope::from_bytes(messages[0].system.as_ref().unwrap()).unwrap();
+        assert!(!envelope.mls_protected);
+        assert_eq!(
+            envelope.notice,
+            SystemNotice::Announcement {
+                sender_id: admin_id,
+                text: "Maintenance tonight".to_string(),
+            }
+        );
+    }
+}
+
+/// Test the RequestWelcomeResend RPC
+#[tokio::test]
+async fn test_request_welcome_resend() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let admin_id = Uuid::new_v4();
+    let member_id = Uuid::new_v4();
+    let joiner_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let mut joiner_membership_id = Uuid::nil();
+    for (client_id, role) in [
+        (admin_id, MembershipRole::Admin),
+        (member_id, MembershipRole::Member),
+        (joiner_id, MembershipRole::Member),
+    ] {
+        let membership = Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        };
+        if client_id == joiner_id {
+            joiner_membership_id = membership.id;
+        }
+        db.add_membership(membership).await.unwrap();
+    }
+
+    let resend = || {
+        service.request_welcome_resend(Request::new(RequestWelcomeResendRequest {
+            group_id: group_id.to_string(),
+            client_id: joiner_id.to_string(),
+        }))
+    };
+
+    // Only the admin is asked to re-add the joiner
+    let response = resend().await.unwrap().into_inner();
+    assert!(!response.already_pending);
+
+    let messages = db
+        .fetch_messages_for_client(
+            admin_id,
+            Some(group_id),
+            false,
+            &[MessageType::System],
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].recipients, Some(vec![admin_id]));
+    let envelope = SystemEnvelope::from_bytes(messages[0].system.as_ref().unwrap()).unwrap();
+    assert_eq!(
+        envelope.notice,
+        SystemNotice::WelcomeResendRequested {
+            membership_id: joiner_membership_id,
+            client_id: joiner_id,
+        }
+    );
+
+    // Repeated requests don't notify the admins again while one is pending
+    let response = resend().await.unwrap().into_inner();
+    assert!(response.already_pending);
+
+    // A welcome for the joiner fulfils the request, so it can ask again
+    service
+        .store_welcome(Request::new(StoreWelcomeRequest {
+            group_id: group_id.to_string(),
+            sender_id: admin_id.to_string(),
+            welcome: vec![1, 2, 3],
+            recipient_ids: vec![joiner_id.to_string()],
+            extra: vec![],
+            recipient_user_id: String::new(),
+        }))
+        .await
+        .unwrap();
+    let response = resend().await.unwrap().into_inner();
+    assert!(!response.already_pending);
+
+    // Clients outside the group can't ask for a welcome
+    let status = service
+        .request_welcome_resend(Request::new(RequestWelcomeResendRequest {
+            group_id: group_id.to_string(),
+            client_id: Uuid::new_v4().to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+}
+
+// Helper function to frame an application message for the MLS group with the given id
+fn framed_message(mls_group_id: &[u8]) -> Vec<u8> {
+    let provider = OpenMlsRustCrypto::default();
+    let signer = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    let credential_with_key = CredentialWithKey {
+        credential: BasicCredential::new(b"alice".to_vec()).into(),
+        signature_key: signer.public().into(),
+    };
+    let mut group = MlsGroup::builder()
+        .with_group_id(GroupId::from_slice(mls_group_id))
+        .build(&provider, &signer, credential_with_key)
+        .unwrap();
+    group
+        .create_message(&provider, &signer, b"hello")
+        .unwrap()
+        .tls_serialize_detached()
+        .unwrap()
+}
+
+/// Messages framed for another MLS group are rejected
+#[tokio::test]
+async fn test_cross_group_misdirection() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+    let sender_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+
+    let create = |mls_group_id: &[u8]| {
+        service.create_group(Request::new(CreateGroupRequest {
+            creator_id: sender_id.to_string(),
+            initial_state: vec![1, 2, 3],
+            mls_group_id: mls_group_id.to_vec(),
+            initial_members: vec![],
+            handle: String::new(),
+            ciphersuite: 0,
+        }))
+    };
+    let group_a = create(b"group-a").await.unwrap().into_inner().group_id;
+    let group_b = create(b"group-b").await.unwrap().into_inner().group_id;
+
+    // An MLS group id maps to a single group
+    let status = create(b"group-a").await.unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+
+    let send = |group_id: &str, message: Vec<u8>| {
+        service.store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            message,
+            epoch: 0,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+    };
+    send(&group_a, framed_message(b"group-a")).await.unwrap();
+
+    let status = send(&group_b, framed_message(b"group-a"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // Commits are checked the same way, and must be MLS messages at all
+    for commit in [framed_message(b"group-a"), vec![1, 2, 3]] {
+        let status = service
+            .store_commit(Request::new(StoreCommitRequest {
+                group_id: group_b.clone(),
+                sender_id: sender_id.to_string(),
+                commit,
+                epoch: 1,
+                extra: vec![],
+                proposal_refs: vec![],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    let group_b = Uuid::parse_str(&group_b).unwrap();
+    assert_eq!(db.get_group(group_b).await.unwrap().epoch, 0);
+    let messages = db
+        .fetch_messages_for_client(sender_id, Some(group_b), true, &[], true)
+        .await
+        .unwrap();
+    assert!(messages.is_empty());
+}
+
+/// Only active members can store messages in a group: a client can't write into a group it
+/// was never added to, or one it has left
+#[tokio::test]
+async fn test_store_requires_group_membership() {
+    let db = Arc::new(MockDatabase::new());
+    let service =
+        MLSServiceImpl::new(db.clone()).with_validation_policy(ValidationPolicy::Unchecked);
+
+    let sender_id = register_client(db.as_ref(), Uuid::new_v4()).await;
+    let own = GroupFixture::new()
+        .with_creator(sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    let other = GroupFixture::new().insert(db.as_ref()).await.unwrap();
+    let left = GroupFixture::new().insert(db.as_ref()).await.unwrap();
+    MembershipFixture::new(own.id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    MembershipFixture::new(left.id, sender_id)
+        .removed()
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    for group_id in [other.id, left.id] {
+        let statuses = [
+            service
+                .store_proposal(Request::new(StoreProposalRequest {
+                    group_id: group_id.to_string(),
+                    sender_id: sender_id.to_string(),
+                    proposal: vec![1],
+                    proposal_type: String::new(),
+                    r#type: mls::ProposalType::Add as i32,
+                    extra: vec![],
+                }))
+                .await
+                .map(|_| ()),
+            service
+                .store_commit(Request::new(StoreCommitRequest {
+                    group_id: group_id.to_string(),
+                    sender_id: sender_id.to_string(),
+                    commit: vec![2],
+                    epoch: 1,
+                    extra: vec![],
+                    proposal_refs: vec![],
+                }))
+                .await
+                .map(|_| ()),
+            service
+                .store_welcome(Request::new(StoreWelcomeRequest {
+                    group_id: group_id.to_string(),
+                    sender_id: sender_id.to_string(),
+                    welcome: vec![3],
+                    recipient_ids: vec![sender_id.to_string()],
+                    extra: vec![],
+                    recipient_user_id: String::new(),
+                }))
+                .await
+                .map(|_| ()),
+            service
+                .store_application_message(Request::new(StoreApplicationMessageRequest {
+                    group_id: group_id.to_string(),
+                    sender_id: sender_id.to_string(),
+                    message: vec![4],
+                    epoch: 0,
+                    extra: vec![],
+                    ttl_secs: 0,
+                }))
+                .await
+                .map(|_| ()),
+        ];
+        for status in statuses {
+            assert_eq!(status.unwrap_err().code(), Code::PermissionDenied);
+        }
+
+        // Nothing was stored, and the group's epoch didn't move
+        assert_eq!(db.get_group(group_id).await.unwrap().epoch, 0);
+        let member = Uuid::new_v4();
+        MembershipFixture::new(group_id, member)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+        let messages = db
+            .fetch_messages_for_client(member, Some(group_id), true, &[], true)
+            .await
+            .unwrap();
+        assert!(messages.is_empty());
+    }
+
+    // The same client still writes to its own group
+    service
+        .store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: own.id.to_string(),
+            sender_id: sender_id.to_string(),
+            message: vec![4],
+            epoch: 0,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+        .await
+        .unwrap();
+}
+
+/// Application messages from epochs too far behind the group are rejected
+#[tokio::test]
+async fn test_application_epoch_gating() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::default();
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings.clone());
+
+    let group_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 5,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+    let sender_id = Uuid::new_v4();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let send = |epoch| {
+        service.store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            message: vec![1, 2, 3],
+            epoch,
+            extra: vec![],
+            ttl_secs: 0,
+        }))
+    };
+
+    // The default tolerance accepts the previous epoch
+    send(5).await.unwrap();
+    send(4).await.unwrap();
+
+    let status = send(3).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, "EPOCH_TOO_OLD");
+    assert_eq!(info.metadata["current_epoch"], "5");
+    assert_eq!(info.metadata["min_epoch"], "4");
+
+    // A negative tolerance disables the check
+    settings.set(RuntimeSettings {
+        application_epoch_tolerance: -1,
+        ..settings.current()
+    });
+    send(0).await.unwrap();
+}
+
+/// Test sender-requested message TTLs: bounded by policy, hidden once expired, then purged
+#[tokio::test]
+async fn test_message_ttl() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::new(RuntimeSettings {
+        max_message_ttl_secs: 3600,
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings.clone());
+
+    let group_id = Uuid::new_v4();
+    let reader_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: reader_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id: reader_id,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+    let sender_id = Uuid::new_v4();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let send = |ttl_secs| {
+        service.store_application_message(Request::new(StoreApplicationMessageRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            message: vec![1, 2, 3],
+            epoch: 0,
+            extra: vec![],
+            ttl_secs,
+        }))
+    };
+    let fetch = || async {
+        service
+            .fetch_messages(Request::new(FetchMessagesRequest {
+                client_id: reader_id.to_string(),
+                group_id: group_id.to_string(),
+                include_read: true,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .messages
+    };
+
+    // TTLs above the server's maximum are rejected
+    let status = send(3601).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let kept_id = send(0).await.unwrap().into_inner().message_id;
+    let expiring_id = send(60).await.unwrap().into_inner().message_id;
+    let messages = fetch().await;
+    assert_eq!(messages.len(), 2);
+    let expiring = messages.iter().find(|m| m.id == expiring_id).unwrap();
+    let expires_at = chrono::DateTime::parse_from_rfc3339(&expiring.expires_at).unwrap();
+    assert!(expires_at > Utc::now() + chrono::Duration::seconds(50));
+    let kept = messages.iter().find(|m| m.id == kept_id).unwrap();
+    assert!(kept.expires_at.is_empty());
+
+    // Once expired, a message is no longer fetched, and the janitor purges it
+    let expired_id = Uuid::new_v4();
+    db.store_message(Message {
+        id: expired_id,
+        group_id: Some(group_id),
+        sender_id: Uuid::new_v4(),
+        created_at: Utc::now() - chrono::Duration::minutes(2),
+        read: false,
+        message_type: MessageType::Application,
+        proposal: None,
+        commit: None,
+        welcome: None,
+        system: None,
+        application: Some(vec![4, 5, 6]),
+        proposal_type: None,
+        epoch: Some(0),
+        recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: Some(Utc::now() - chrono::Duration::minutes(1)),
+        deleted_at: None,
+        sequence: 0,
+    })
+    .await
+    .unwrap();
+    assert_eq!(fetch().await.len(), 2);
+
+    Janitor::new(db.clone(), JanitorConfig::default())
+        .run_once()
+        .await;
+    let purged = db.soft_delete(EntityKind::Message, expired_id).await;
+    assert!(matches!(purged, Err(DbError::NotFound)));
+    assert_eq!(fetch().await.len(), 2);
+
+    // With expiry disabled, senders can't request a TTL
+    settings.set(RuntimeSettings {
+        max_message_ttl_secs: 0,
+        ..settings.current()
+    });
+    assert_eq!(send(60).await.unwrap_err().code(), Code::InvalidArgument);
+    send(0).await.unwrap();
+}
+
+/// A commit that doesn't build on the group's current epoch is rejected with that epoch
+#[tokio::test]
+async fn test_store_commit_epoch_conflict() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 3,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+
+    // Two members commit on epoch 3; the first one to be stored wins
+    let (winner, loser) = (Uuid::new_v4(), Uuid::new_v4());
+    for sender_id in [winner, loser] {
+        MembershipFixture::new(group_id, sender_id)
+            .insert(db.as_ref())
+            .await
+            .unwrap();
+    }
+    let commit = |sender_id: Uuid, epoch| {
+        service.store_commit(Request::new(StoreCommitRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            commit: vec![1, 2, 3],
+            epoch,
+            extra: vec![],
+            proposal_refs: vec![],
+        }))
+    };
+    commit(winner, 4).await.unwrap();
+
+    let status = commit(loser, 4).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, "STALE_COMMIT");
+    assert_eq!(info.metadata["current_epoch"], "4");
+
+    // Skipping ahead is rejected the same way
+    let status = commit(loser, 6).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    // Only the winning commit was stored
+    assert_eq!(db.get_group(group_id).await.unwrap().epoch, 4);
+    let member = Uuid::new_v4();
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id: member,
+        group_id,
+        role: MembershipRole::Member,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: None,
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+    let messages = db
+        .fetch_messages_for_client(member, Some(group_id), true, &[], true)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].sender_id, winner);
+
+    // The loser rebases onto epoch 4
+    commit(loser, 5).await.unwrap();
+    assert_eq!(db.get_group(group_id).await.unwrap().epoch, 5);
+}
+
+/// Proposals are queued per epoch, and commits include them by reference
+#[tokio::test]
+async fn test_commit_proposals_by_reference() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: Uuid::new_v4(),
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+    let sender_id = Uuid::new_v4();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let propose = |proposal: Vec<u8>| {
+        service.store_proposal(Request::new(StoreProposalRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            proposal,
+            proposal_type: String::new(),
+            r#type: mls::ProposalType::Remove as i32,
+            extra: vec![],
+        }))
+    };
+    let first = propose(vec![1]).await.unwrap().into_inner();
+    let second = propose(vec![2]).await.unwrap().into_inner();
+    assert_eq!((first.epoch, second.epoch), (0, 0));
+
+    // The same proposal can't be queued twice
+    let status = propose(vec![1]).await.unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+
+    let pending = || async {
+        service
+            .list_pending_proposals(Request::new(ListPendingProposalsRequest {
+                group_id: group_id.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+    };
+    let listed = pending().await;
+    assert_eq!(listed.epoch, 0);
+    let refs: Vec<_> = listed
+        .proposals
+        .iter()
+        .map(|p| p.proposal_ref.clone())
+        .collect();
+    assert_eq!(
+        refs,
+        [first.proposal_ref.clone(), second.proposal_ref.clone()]
+    );
+    assert_eq!(listed.proposals[0].message_id, first.message_id);
+    assert_eq!(listed.proposals[0].sender_id, sender_id.to_string());
+    assert_eq!(listed.proposals[0].r#type(), mls::ProposalType::Remove);
+
+    let commit = |epoch, proposal_refs: Vec<Vec<u8>>| {
+        service.store_commit(Request::new(StoreCommitRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            commit: vec![9],
+            epoch,
+            extra: vec![],
+            proposal_refs,
+        }))
+    };
+
+    // Refs must be SHA-256 hashes
+    let status = commit(1, vec![vec![1, 2, 3]]).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // A commit naming an unknown proposal is rejected as a whole
+    let status = commit(1, vec![first.proposal_ref.clone(), vec![0; 32]])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(db.get_group(group_id).await.unwrap().epoch, 0);
+    assert_eq!(pending().await.proposals.len(), 2);
+
+    // Committing the first proposal consumes it; the other one belongs to the old epoch
+    commit(1, vec![first.proposal_ref.clone()]).await.unwrap();
+    let listed = pending().await;
+    assert_eq!(listed.epoch, 1);
+    assert!(listed.proposals.is_empty());
+
+    let status = commit(2, vec![first.proposal_ref.clone()])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert!(status.message().contains("already committed"));
+    let status = commit(2, vec![second.proposal_ref.clone()])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert!(status.message().contains("epoch 0"));
+}
+
+/// Members can report messages they received in the group; outsiders, and messages the
+/// reporter didn't receive, are refused
+#[tokio::test]
+async fn test_report_abuse() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db.clone());
+
+    let group_id = Uuid::new_v4();
+    let other_group_id = Uuid::new_v4();
+    let reporter_id = Uuid::new_v4();
+    for group_id in [group_id, other_group_id] {
+        db.add_membership(Membership {
+            id: Uuid::new_v4(),
+            client_id: reporter_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        })
+        .await
+        .unwrap();
+    }
+    let store = |group_id: Uuid| {
+        let message_id = Uuid::new_v4();
+        let db = db.clone();
+        async move {
+            db.store_message(Message {
+                id: message_id,
+                group_id: Some(group_id),
+                sender_id: Uuid::new_v4(),
+                created_at: Utc::now(),
+                read: false,
+                message_type: MessageType::Application,
+                proposal: None,
+                commit: None,
+                welcome: None,
+                system: None,
+                application: Some(vec![1, 2, 3]),
+                proposal_type: None,
+                epoch: Some(1),
+                recipients: None,
+                extra: None,
+                payload_hash: None,
+                expires_at: None,
+                deleted_at: None,
+                sequence: 0,
+            })
+            .await
+            .unwrap();
+            message_id
+        }
+    };
+    let message_id = store(group_id).await;
+    let elsewhere_id = store(Uuid::new_v4()).await;
+    let other_group_message_id = store(other_group_id).await;
+
+    let report = |reporter_id: Uuid, message_ids: Vec<Uuid>, reason: &str| {
+        service.report_abuse(Request::new(ReportAbuseRequest {
+            reporter_id: reporter_id.to_string(),
+            group_id: group_id.to_string(),
+            message_ids: message_ids.iter().map(Uuid::to_string).collect(),
+            reason: reason.to_string(),
+            excerpt: vec![9; 16],
+        }))
+    };
+
+    let status = report(reporter_id, vec![], "spam").await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = report(reporter_id, vec![message_id], "").await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = report(Uuid::new_v4(), vec![message_id], "spam")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = report(reporter_id, vec![message_id, elsewhere_id], "spam")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    // Messages the reporter received in another of its groups belong to that group's reports
+    let status = report(reporter_id, vec![other_group_message_id], "spam")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    // A report references at most 100 messages
+    let status = report(reporter_id, vec![message_id; 101], "spam")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let report_id = report(reporter_id, vec![message_id], "spam")
+        .await
+        .unwrap()
+        .into_inner()
+        .report_id;
+    let reports = db
+        .list_abuse_reports(&Default::default(), 10)
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].id.to_string(), report_id);
+    assert_eq!(reports[0].message_ids, [message_id]);
+    assert_eq!(reports[0].excerpt, Some(vec![9; 16]));
+    assert!(reports[0].resolved_at.is_none());
+}
+
+/// Test the queue depth cap: storing past it evicts the oldest application messages, never
+/// handshake messages, and the next fetch reports the truncation once, only to the members that
+/// were still waiting for the evicted messages
+#[tokio::test]
+async fn test_queue_depth_cap() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::new(RuntimeSettings {
+        max_queued_application_messages: 3,
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings);
+
+    let group_id = Uuid::new_v4();
+    let reader_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id: reader_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+    let online_id = Uuid::new_v4();
+    let sender_id = Uuid::new_v4();
+    for client_id in [reader_id, online_id, sender_id] {
+        db.add_membership(Membership {
+            id: Uuid::new_v4(),
+            client_id,
+            group_id,
+            role: MembershipRole::Member,
+            added_at: Utc::now(),
+            removed_at: None,
+            last_acked_epoch: None,
+            last_acked_at: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    // An old handshake message waiting alongside the application messages
+    let proposal_id = Uuid::new_v4();
+    db.store_message(Message {
+        id: proposal_id,
+        group_id: Some(group_id),
+        sender_id: Uuid::new_v4(),
+        created_at: Utc::now() - chrono::Duration::minutes(1),
+        read: false,
+        message_type: MessageType::Proposal,
+        proposal: Some(vec![7, 8, 9]),
+        commit: None,
+        welcome: None,
+        system: None,
+        application: None,
+        proposal_type: Some(ProposalType::Add),
+        epoch: Some(0),
+        recipients: None,
+        extra: None,
+        payload_hash: None,
+        expires_at: None,
+        deleted_at: None,
+        sequence: 0,
+    })
+    .await
+    .unwrap();
+
+    let fetch = |client_id: Uuid| {
+        let service = &service;
+        async move {
+            service
+                .fetch_messages(Request::new(FetchMessagesRequest {
+                    client_id: client_id.to_string(),
+                    ..Default::default()
+                }))
+                .await
+                .unwrap()
+                .into_inner()
+        }
+    };
+    let mut sent = Vec::new();
+    for i in 0..5u8 {
+        let message_id = service
+            .store_application_message(Request::new(StoreApplicationMessageRequest {
+                group_id: group_id.to_string(),
+                sender_id: sender_id.to_string(),
+                message: vec![i],
+                epoch: 0,
+                extra: vec![],
+                ttl_secs: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .message_id;
+        sent.push(message_id);
+
+        // The online member receives the first two as they arrive, so its queue stays short
+        if i == 1 {
+            let delivered = fetch(online_id).await.messages;
+            db.advance_delivery_cursors(
+                online_id,
+                &[DeliveryCursor {
+                    group_id: Some(group_id),
+                    sequence: delivered.iter().map(|m| m.sequence).max().unwrap(),
+                }],
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    let response = fetch(reader_id).await;
+    let mut ids: Vec<String> = response.messages.iter().map(|m| m.id.clone()).collect();
+    ids.sort();
+    let mut expected = sent[2..].to_vec();
+    expected.push(proposal_id.to_string());
+    expected.sort();
+    assert_eq!(ids, expected);
+    assert_eq!(
+        response.truncations,
+        vec![mls::QueueTruncation {
+            group_id: group_id.to_string(),
+            evicted_messages: 2,
+        }]
+    );
+
+    // The truncation is only reported once
+    let response = fetch(reader_id).await;
+    assert_eq!(response.messages.len(), 4);
+    assert!(response.truncations.is_empty());
+
+    // The online member had already received the evicted messages
+    let response = fetch(online_id).await;
+    assert_eq!(response.messages.len(), 3);
+    assert!(response.truncations.is_empty());
+}
@@ -1,5 +1,44 @@
+pub mod admin_tests;
+pub mod admission_tests;
+pub mod backend_tests;
 pub mod client_tests;
+pub mod deprecation_tests;
+pub mod error_tests;
+pub mod event_tests;
+pub mod fault_tests;
 pub mod group_tests;
+pub mod json_debug_tests;
 pub mod key_package_tests;
+pub mod legacy_tests;
+pub mod membership_cache_tests;
 pub mod membership_tests;
 pub mod message_tests;
+pub mod probe_tests;
+pub mod rate_limit_tests;
+pub mod server_info_tests;
+pub mod signature_tests;
+pub mod validation_tests;
+
+use hermetic_mls::auth::admin::AdminPrincipal;
+use hermetic_mls::db::{CredentialScheme, DatabaseInterface};
+use hermetic_mls::fixtures::ClientFixture;
+use tonic::Request;
+use uuid::Uuid;
+
+// Helper function to make a request as an operator the admin listener authenticated
+pub fn admin_request<T>(message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request.extensions_mut().insert(AdminPrincipal);
+    request
+}
+
+// Helper function to register a client for the given user straight in the database
+pub async fn register_client<DB: DatabaseInterface>(db: &DB, user_id: Uuid) -> Uuid {
+    ClientFixture::new()
+        .with_user(user_id)
+        .with_credential(CredentialScheme::Basic, vec![1, 2, 3, 4])
+        .insert(db)
+        .await
+        .unwrap()
+        .id
+}
@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hermetic_mls::{
+    db::{DatabaseInterface, DbError},
+    events::DomainEvent,
+    metrics::RpcMetrics,
+    service::{
+        mls::{mls_admin_service_server::MlsAdminService, RunSelfTestRequest},
+        probe::{SelfTestJob, SELF_TEST_METRIC},
+        MLSServiceImpl,
+    },
+    settings::{RequestSignatureMode, RuntimeSettings, SettingsHandle},
+};
+use tonic::{Code, Request};
+
+use crate::mock_db::MockDatabase;
+
+/// The self-test exercises the delivery path with signed requests and removes what it created
+#[tokio::test]
+async fn test_self_test_passes_and_cleans_up() {
+    let db = Arc::new(MockDatabase::new());
+    let settings = SettingsHandle::new(RuntimeSettings {
+        request_signatures: RequestSignatureMode::Required,
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(db.clone()).with_settings(settings);
+    let mut events = service.events().subscribe();
+
+    let response = MlsAdminService::run_self_test(
+        &service,
+        Request::new(RunSelfTestRequest { cached: false }),
+    )
+    .await
+    .unwrap()
+    .into_inner();
+    assert!(
+        response.passed,
+        "{}: {}",
+        response.failed_step, response.error
+    );
+    let steps: Vec<_> = response.steps.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(
+        steps,
+        vec![
+            "RegisterClient",
+            "RegisterClient",
+            "PublishKeyPackage",
+            "CreateGroup",
+            "ClaimKeyPackage",
+            "AddMember",
+            "StoreApplicationMessage",
+            "FetchMessages",
+            "AcknowledgeEpoch",
+        ]
+    );
+
+    // Nothing the run created is left behind
+    let mut clients = HashSet::new();
+    let mut groups = HashSet::new();
+    while let Ok(event) = events.try_recv() {
+        match event {
+            DomainEvent::ClientRegistered { client_id, .. } => {
+                clients.insert(client_id);
+            }
+            DomainEvent::GroupCreated { group_id, .. } => {
+                groups.insert(group_id);
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(clients.len(), 2);
+    assert_eq!(groups.len(), 1);
+    for client_id in clients {
+        assert!(matches!(
+            db.get_client(client_id).await,
+            Err(DbError::NotFound)
+        ));
+        assert!(db
+            .list_key_packages_by_client(client_id, true)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+    for group_id in groups {
+        assert!(matches!(
+            db.get_group(group_id).await,
+            Err(DbError::NotFound)
+        ));
+        assert!(db
+            .list_memberships_by_group(group_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    // The cached result is the run that just finished
+    let cached =
+        MlsAdminService::run_self_test(&service, Request::new(RunSelfTestRequest { cached: true }))
+            .await
+            .unwrap()
+            .into_inner();
+    assert_eq!(cached, response);
+}
+
+/// The periodic job records the self-test latency with the RPC metrics
+#[tokio::test]
+async fn test_self_test_job_records_latency() {
+    let db = Arc::new(MockDatabase::new());
+    let service = Arc::new(MLSServiceImpl::new(db));
+
+    // Before the first run there is no cached result
+    let status = MlsAdminService::run_self_test(
+        service.as_ref(),
+        Request::new(RunSelfTestRequest { cached: true }),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let metrics = RpcMetrics::new(Duration::from_secs(60));
+    let job =
+        SelfTestJob::new(service.clone(), Duration::from_secs(60)).with_metrics(metrics.clone());
+    let result = job.run_once().await;
+    assert!(result.passed, "{:?}", result.error);
+    assert_eq!(metrics.methods(), vec![SELF_TEST_METRIC]);
+    assert_eq!(
+        service.latest_self_test().unwrap().finished_at,
+        result.finished_at
+    );
+}
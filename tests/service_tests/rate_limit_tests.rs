@@ -0,0 +1,163 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+use hermetic_mls::auth::{AuthenticatedClient, CLIENT_HEADER};
+use hermetic_mls::error::ServiceError;
+use hermetic_mls::service::rate_limit::{
+    is_rate_limited, RateLimitConfig, RateLimitLayer, RateLimiter, RETRY_AFTER_HEADER,
+};
+use tonic::{Code, Status};
+use tonic_types::StatusExt;
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use crate::settings_tests::Ok200;
+
+/// Only fetches and key package claims on the delivery service are rate limited
+#[test]
+fn test_rate_limited_methods() {
+    assert!(is_rate_limited("/mls.v1.MlsDeliveryService/FetchMessages"));
+    assert!(is_rate_limited(
+        "/mls.v1.MlsDeliveryService/ClaimKeyPackage"
+    ));
+    assert!(is_rate_limited("/mls.MlsDeliveryService/FetchMessages"));
+    assert!(!is_rate_limited("/mls.v1.MlsDeliveryService/StoreCommit"));
+    assert!(!is_rate_limited("/mls.v1.MlsAdminService/ListAllClients"));
+    assert!(!is_rate_limited("/grpc.health.v1.Health/Check"));
+}
+
+/// Buckets allow a burst, refill at their rate, and a request needs both its client's and its
+/// address's bucket to have room
+#[test]
+fn test_token_buckets() {
+    let limiter = RateLimiter::new(RateLimitConfig {
+        client_per_sec: 2,
+        client_burst: 2,
+        ip_per_sec: 1,
+        ip_burst: 3,
+    });
+    let client = Uuid::new_v4();
+    let other_client = Uuid::new_v4();
+    let ip = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+    let start = Instant::now();
+
+    // The client's burst is used up first
+    limiter.check(Some(client), ip, start).unwrap();
+    limiter.check(Some(client), ip, start).unwrap();
+    let err = limiter.check(Some(client), ip, start).unwrap_err();
+    assert_eq!(
+        err,
+        ServiceError::RateLimited {
+            limit: "client",
+            retry_after: Duration::from_millis(500),
+        }
+    );
+    assert_eq!(limiter.rejected(), [1, 0]);
+
+    // Another client from the same address gets the address's last request
+    limiter.check(Some(other_client), ip, start).unwrap();
+    let err = limiter.check(Some(other_client), ip, start).unwrap_err();
+    assert!(matches!(err, ServiceError::RateLimited { limit: "ip", .. }));
+    assert_eq!(limiter.rejected(), [1, 1]);
+
+    // Half a second refills a request for the client, but not yet for the address
+    let later = start + Duration::from_millis(500);
+    let err = limiter.check(Some(client), ip, later).unwrap_err();
+    assert_eq!(
+        err,
+        ServiceError::RateLimited {
+            limit: "ip",
+            retry_after: Duration::from_millis(500),
+        }
+    );
+    limiter
+        .check(Some(client), ip, start + Duration::from_secs(1))
+        .unwrap();
+
+    // Requests without a client or address are only held to the limit they have
+    limiter.check(None, None, start).unwrap();
+    limiter
+        .check(Some(Uuid::new_v4()), None, start + Duration::from_secs(1))
+        .unwrap();
+}
+
+/// Rate limit errors carry RetryInfo and a retry-after header in whole seconds
+#[test]
+fn test_rate_limited_status() {
+    let status = Status::from(ServiceError::RateLimited {
+        limit: "ip",
+        retry_after: Duration::from_millis(1200),
+    });
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    let info = status.get_details_error_info().unwrap();
+    assert_eq!(info.reason, "RATE_LIMITED");
+    assert_eq!(info.metadata["limit"], "ip");
+    assert_eq!(info.metadata["retry_after_ms"], "1200");
+    assert_eq!(
+        status.get_details_retry_info().unwrap().retry_delay,
+        Some(Duration::from_millis(1200))
+    );
+    assert_eq!(
+        status
+            .metadata()
+            .get(RETRY_AFTER_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap(),
+        "2"
+    );
+}
+
+/// The layer rejects calls over the limit without calling the service, and lets others through
+#[tokio::test]
+async fn test_rate_limit_layer() {
+    let limiter = RateLimiter::new(RateLimitConfig {
+        client_per_sec: 1,
+        client_burst: 1,
+        ..RateLimitConfig::default()
+    });
+    let mut service = RateLimitLayer::new(limiter.clone()).layer(Ok200);
+    let client = Uuid::new_v4();
+    let request = |path: &str| {
+        let mut request = http::Request::builder().uri(path).body(()).unwrap();
+        request.extensions_mut().insert(AuthenticatedClient(client));
+        request
+    };
+
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/FetchMessages"))
+        .await
+        .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/ClaimKeyPackage"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get("grpc-status").unwrap(),
+        &(Code::ResourceExhausted as i32).to_string()
+    );
+    assert_eq!(response.headers().get(RETRY_AFTER_HEADER).unwrap(), "1");
+    assert_eq!(limiter.rejected(), [1, 0]);
+
+    // Other RPCs aren't limited
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/StoreCommit"))
+        .await
+        .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+
+    // Naming the client in the headers without authenticating as it neither spends nor is held
+    // to its bucket
+    for _ in 0..3 {
+        let claimed = http::Request::builder()
+            .uri("/mls.v1.MlsDeliveryService/FetchMessages")
+            .header(CLIENT_HEADER, client.to_string())
+            .body(())
+            .unwrap();
+        let response = service.call(claimed).await.unwrap();
+        assert!(response.headers().get("grpc-status").is_none());
+    }
+    assert_eq!(limiter.rejected(), [1, 0]);
+}
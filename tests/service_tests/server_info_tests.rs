@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use hermetic_mls::auth::AuthMode;
+use hermetic_mls::mls_codec::MlsCrypto;
+use hermetic_mls::service::{
+    mls::{self, mls_delivery_service_server::MlsDeliveryService},
+    server_info::{AuthModes, ServerInfo, GIT_COMMIT},
+    MLSServiceImpl,
+};
+use hermetic_mls::settings::{RequestSignatureMode, RuntimeSettings, SettingsHandle};
+use tonic::Request;
+
+use crate::mock_db::MockDatabase;
+
+/// GetServerInfo describes the running build
+#[tokio::test]
+async fn test_get_server_info() {
+    let service = MLSServiceImpl::new(Arc::new(MockDatabase::new()));
+    let info = service
+        .get_server_info(Request::new(mls::GetServerInfoRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.git_commit, GIT_COMMIT);
+    assert!(!info.git_commit.is_empty());
+    // The tests are built with the in-memory database
+    assert!(info.features.contains(&"memory-db".to_string()));
+    // MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519, the suite the service generates key packages with
+    assert!(info.ciphersuites.contains(&1));
+    assert_eq!(info.protocol_versions, ["mls10"]);
+}
+
+#[test]
+fn test_server_info_banner() {
+    let info = ServerInfo {
+        version: "1.2.3",
+        git_commit: "0123456789ab",
+        features: vec!["nats", "kafka"],
+        ciphersuites: vec![1, 3],
+        protocol_versions: &["mls10"],
+        auth: AuthModes::default(),
+    };
+    assert_eq!(
+        info.banner(),
+        "hermetic-mls 1.2.3 (0123456789ab); features: nats, kafka; ciphersuites: 1, 3; protocol versions: mls10"
+    );
+
+    let info = ServerInfo {
+        features: vec![],
+        ..ServerInfo::new(&MlsCrypto::default())
+    };
+    assert!(info.banner().contains("features: none;"));
+}
+
+/// The capabilities document lists what SDKs can rely on, with the auth modes in force
+#[tokio::test]
+async fn test_server_info_capabilities() {
+    let service = MLSServiceImpl::new(Arc::new(MockDatabase::new()));
+    let capabilities = service
+        .get_server_info(Request::new(mls::GetServerInfoRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .capabilities
+        .unwrap();
+
+    assert_eq!(
+        capabilities.message_types().collect::<Vec<_>>(),
+        [
+            mls::MessageType::Proposal,
+            mls::MessageType::Commit,
+            mls::MessageType::Welcome,
+            mls::MessageType::Application,
+            mls::MessageType::System,
+        ]
+    );
+    let pagination = capabilities.pagination.unwrap();
+    assert!(pagination
+        .page_token_rpcs
+        .contains(&"mls.v1.MlsAdminService/ListAllClients".to_string()));
+    assert_eq!(pagination.max_page_size, 1000);
+    let streaming = capabilities.streaming.unwrap();
+    assert!(streaming.subscribe_messages && streaming.resume_tokens);
+    assert!(capabilities.idempotency.unwrap().create_group_handles);
+
+    let auth = capabilities.auth.unwrap();
+    assert_eq!(auth.client_auth(), mls::AuthRequirement::NotChecked);
+    assert_eq!(auth.token_auth(), mls::AuthRequirement::NotChecked);
+    assert_eq!(auth.request_signatures(), mls::AuthRequirement::NotChecked);
+    assert_eq!(
+        auth.unauthenticated_rpcs,
+        [
+            "mls.v1.MlsDeliveryService/RegisterClient",
+            "mls.v1.MlsDeliveryService/GetServerInfo",
+        ]
+    );
+}
+
+/// Auth modes come from the startup config and, for request signatures, the current settings
+#[tokio::test]
+async fn test_server_info_auth_modes() {
+    let settings = SettingsHandle::new(RuntimeSettings {
+        request_signatures: RequestSignatureMode::Optional,
+        ..RuntimeSettings::default()
+    });
+    let service = MLSServiceImpl::new(Arc::new(MockDatabase::new()))
+        .with_settings(settings.clone())
+        .with_auth_modes(AuthMode::Required, AuthMode::Optional);
+
+    let modes = auth_capabilities(&service).await;
+    assert_eq!(modes.client_auth(), mls::AuthRequirement::Required);
+    assert_eq!(modes.token_auth(), mls::AuthRequirement::Optional);
+    assert_eq!(modes.request_signatures(), mls::AuthRequirement::Optional);
+
+    // Reloaded settings show on the next call
+    settings.set(RuntimeSettings {
+        request_signatures: RequestSignatureMode::Required,
+        ..RuntimeSettings::default()
+    });
+    let modes = auth_capabilities(&service).await;
+    assert_eq!(modes.request_signatures(), mls::AuthRequirement::Required);
+}
+
+async fn auth_capabilities(service: &MLSServiceImpl<MockDatabase>) -> mls::AuthCapabilities {
+    service
+        .get_server_info(Request::new(mls::GetServerInfoRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .capabilities
+        .unwrap()
+        .auth
+        .unwrap()
+}
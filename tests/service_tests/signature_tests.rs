@@ -0,0 +1,372 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use hermetic_mls::{
+    db::{DatabaseInterface, Group, Membership, MembershipRole},
+    service::{
+        mls::{
+            self, mls_delivery_service_server::MlsDeliveryService, AddMemberRequest,
+            RegisterClientRequest, RemoveMemberRequest, StoreCommitRequest,
+        },
+        signatures::{
+            request_digest, NONCE_HEADER, SIGNATURE_HEADER, SIGNER_HEADER, TIMESTAMP_HEADER,
+        },
+        MLSServiceImpl,
+    },
+    settings::{RequestSignatureMode, RuntimeSettings, SettingsHandle},
+};
+use openmls::prelude::SignatureScheme;
+use openmls_basic_credential::SignatureKeyPair;
+use openmls_traits::signatures::Signer;
+use prost::Message;
+use tonic::{metadata::MetadataValue, Code, Request};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+// Helper function to build a service enforcing request signatures in the given mode
+fn service_with_signatures(
+    db: Arc<MockDatabase>,
+    mode: RequestSignatureMode,
+) -> MLSServiceImpl<MockDatabase> {
+    let settings = SettingsHandle::new(RuntimeSettings {
+        request_signatures: mode,
+        ..RuntimeSettings::default()
+    });
+    MLSServiceImpl::new(db).with_settings(settings)
+}
+
+// Helper function to register a client with a fresh signature key
+async fn register_signing_client(
+    service: &MLSServiceImpl<MockDatabase>,
+) -> (Uuid, SignatureKeyPair) {
+    let key = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    let response = service
+        .register_client(Request::new(RegisterClientRequest {
+            user_id: Uuid::new_v4().to_string(),
+            identity: "alice".to_string(),
+            device_name: "phone".to_string(),
+            signature_key: key.public().to_vec(),
+            attestation: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    (Uuid::parse_str(&response.client_id).unwrap(), key)
+}
+
+// Helper function to attach a request signature
+fn signed<M: Message>(
+    message: M,
+    method: &str,
+    signer: Uuid,
+    key: &SignatureKeyPair,
+    timestamp: i64,
+    nonce: &str,
+) -> Request<M> {
+    let digest = request_digest(method, timestamp, nonce, &message.encode_to_vec());
+    let signature = key.sign(&digest).unwrap();
+
+    let mut request = Request::new(message);
+    let metadata = request.metadata_mut();
+    metadata.insert(SIGNER_HEADER, signer.to_string().parse().unwrap());
+    metadata.insert(TIMESTAMP_HEADER, timestamp.to_string().parse().unwrap());
+    metadata.insert(NONCE_HEADER, nonce.parse().unwrap());
+    metadata.insert_bin(SIGNATURE_HEADER, MetadataValue::from_bytes(&signature));
+    request
+}
+
+// Helper function to add an active membership with the given role
+async fn add_membership(
+    db: &MockDatabase,
+    group_id: Uuid,
+    client_id: Uuid,
+    role: MembershipRole,
+) -> Uuid {
+    let membership_id = Uuid::new_v4();
+    db.add_membership(Membership {
+        id: membership_id,
+        client_id,
+        group_id,
+        role,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: Some(0),
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+    membership_id
+}
+
+// Helper function to create an empty group
+async fn create_group(db: &MockDatabase, creator_id: Uuid) -> Uuid {
+    let group_id = Uuid::new_v4();
+    db.create_group(Group {
+        id: group_id,
+        creator_id,
+        epoch: 0,
+        state: None,
+        mls_group_id: None,
+        tenant_id: None,
+        handle: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        is_active: true,
+        deleted_at: None,
+    })
+    .await
+    .unwrap();
+    group_id
+}
+
+/// Signed commits are verified against the sender's key and can't be replayed
+#[tokio::test]
+async fn test_signed_store_commit() {
+    let db = Arc::new(MockDatabase::new());
+    let service = service_with_signatures(db.clone(), RequestSignatureMode::Optional);
+    let (sender_id, key) = register_signing_client(&service).await;
+    let group_id = create_group(&db, sender_id).await;
+    add_membership(&db, group_id, sender_id, MembershipRole::Member).await;
+    assert_eq!(
+        db.get_client(sender_id).await.unwrap().signature_key,
+        Some(key.public().to_vec())
+    );
+
+    let commit = |epoch| StoreCommitRequest {
+        group_id: group_id.to_string(),
+        sender_id: sender_id.to_string(),
+        commit: vec![1, 2, 3],
+        epoch,
+        extra: vec![],
+        proposal_refs: vec![],
+    };
+    let now = Utc::now().timestamp();
+
+    service
+        .store_commit(signed(
+            commit(1),
+            "StoreCommit",
+            sender_id,
+            &key,
+            now,
+            "n-1",
+        ))
+        .await
+        .unwrap();
+
+    // Replaying the same signed request is rejected
+    let status = service
+        .store_commit(signed(
+            commit(1),
+            "StoreCommit",
+            sender_id,
+            &key,
+            now,
+            "n-1",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    // A client with a signature key can't be impersonated by unsigned requests
+    let status = service
+        .store_commit(Request::new(commit(2)))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    // Signatures from another key, for another method, or outside the window are rejected
+    let other_key = SignatureKeyPair::new(SignatureScheme::ED25519).unwrap();
+    for request in [
+        signed(commit(2), "StoreCommit", sender_id, &other_key, now, "n-2"),
+        signed(commit(2), "StoreProposal", sender_id, &key, now, "n-3"),
+        signed(commit(2), "StoreCommit", sender_id, &key, now - 3600, "n-4"),
+    ] {
+        let status = service.store_commit(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    // The signer must be the sender
+    let (other_id, other_key) = register_signing_client(&service).await;
+    let status = service
+        .store_commit(signed(
+            commit(2),
+            "StoreCommit",
+            other_id,
+            &other_key,
+            now,
+            "n-5",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    assert_eq!(db.get_group(group_id).await.unwrap().epoch, 1);
+}
+
+/// In required mode, membership changes must be signed by a member of the group
+#[tokio::test]
+async fn test_required_signatures_for_add_member() {
+    let db = Arc::new(MockDatabase::new());
+    let service = service_with_signatures(db.clone(), RequestSignatureMode::Required);
+    let (admin_id, admin_key) = register_signing_client(&service).await;
+    let (outsider_id, outsider_key) = register_signing_client(&service).await;
+    let group_id = create_group(&db, admin_id).await;
+    db.add_membership(Membership {
+        id: Uuid::new_v4(),
+        client_id: admin_id,
+        group_id,
+        role: MembershipRole::Admin,
+        added_at: Utc::now(),
+        removed_at: None,
+        last_acked_epoch: Some(0),
+        last_acked_at: None,
+    })
+    .await
+    .unwrap();
+
+    let add = || AddMemberRequest {
+        group_id: group_id.to_string(),
+        client_id: Uuid::new_v4().to_string(),
+        role: String::new(),
+        member_role: mls::MembershipRole::Member as i32,
+        sender_id: String::new(),
+    };
+    let now = Utc::now().timestamp();
+
+    let unsigned = AddMemberRequest {
+        sender_id: admin_id.to_string(),
+        ..add()
+    };
+    let status = service
+        .add_member(Request::new(unsigned))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let status = service
+        .add_member(signed(
+            add(),
+            "AddMember",
+            outsider_id,
+            &outsider_key,
+            now,
+            "n-1",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    service
+        .add_member(signed(add(), "AddMember", admin_id, &admin_key, now, "n-1"))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.list_memberships_by_group(group_id).await.unwrap().len(),
+        2
+    );
+}
+
+/// Moderators may remove plain members, but not admins, and may not grant roles
+#[tokio::test]
+async fn test_moderator_membership_changes() {
+    let db = Arc::new(MockDatabase::new());
+    let service = service_with_signatures(db.clone(), RequestSignatureMode::Required);
+    let (admin_id, _) = register_signing_client(&service).await;
+    let (moderator_id, moderator_key) = register_signing_client(&service).await;
+    let (member_id, member_key) = register_signing_client(&service).await;
+    let group_id = create_group(&db, admin_id).await;
+    let admin_membership = add_membership(&db, group_id, admin_id, MembershipRole::Admin).await;
+    add_membership(&db, group_id, moderator_id, MembershipRole::Moderator).await;
+    let member_membership = add_membership(&db, group_id, member_id, MembershipRole::Member).await;
+    let other_membership =
+        add_membership(&db, group_id, Uuid::new_v4(), MembershipRole::Member).await;
+    let now = Utc::now().timestamp();
+
+    let remove = |membership_id: Uuid| RemoveMemberRequest {
+        membership_id: membership_id.to_string(),
+        sender_id: String::new(),
+    };
+    let add = |role: mls::MembershipRole| AddMemberRequest {
+        group_id: group_id.to_string(),
+        client_id: Uuid::new_v4().to_string(),
+        role: String::new(),
+        member_role: role as i32,
+        sender_id: String::new(),
+    };
+
+    // A plain member can't remove others, but can leave
+    let status = service
+        .remove_member(signed(
+            remove(other_membership),
+            "RemoveMember",
+            member_id,
+            &member_key,
+            now,
+            "n-1",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = service
+        .remove_member(signed(
+            remove(admin_membership),
+            "RemoveMember",
+            moderator_id,
+            &moderator_key,
+            now,
+            "n-1",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = service
+        .add_member(signed(
+            add(mls::MembershipRole::Moderator),
+            "AddMember",
+            moderator_id,
+            &moderator_key,
+            now,
+            "n-2",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    service
+        .remove_member(signed(
+            remove(other_membership),
+            "RemoveMember",
+            moderator_id,
+            &moderator_key,
+            now,
+            "n-3",
+        ))
+        .await
+        .unwrap();
+    service
+        .remove_member(signed(
+            remove(member_membership),
+            "RemoveMember",
+            member_id,
+            &member_key,
+            now,
+            "n-2",
+        ))
+        .await
+        .unwrap();
+
+    let active: Vec<_> = db
+        .list_memberships_by_group(group_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.removed_at.is_none())
+        .map(|m| m.client_id)
+        .collect();
+    assert_eq!(active.len(), 2);
+    assert!(active.contains(&admin_id) && active.contains(&moderator_id));
+}
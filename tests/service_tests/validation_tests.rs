@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use hermetic_mls::{
+    db::DatabaseInterface,
+    fixtures::{GroupFixture, MembershipFixture},
+    service::{
+        mls::{
+            mls_admin_service_server::MlsAdminService,
+            mls_delivery_service_server::MlsDeliveryService, AddMemberRequest,
+            RegisterClientRequest, SoftDeleteRequest, StoreCommitRequest, StoreWelcomeRequest,
+        },
+        validation::{ValidationPolicy, MAX_WELCOME_RECIPIENTS},
+        MLSServiceImpl,
+    },
+};
+use tonic::{Code, Request, Status};
+use tonic_types::StatusExt;
+use uuid::Uuid;
+
+use super::admin_request;
+use crate::mock_db::MockDatabase;
+
+// Helper function to list the fields named in a status' BadRequest details
+fn violated_fields(status: &Status) -> Vec<String> {
+    assert_eq!(status.code(), Code::InvalidArgument);
+    status
+        .get_details_bad_request()
+        .expect("missing BadRequest details")
+        .field_violations
+        .into_iter()
+        .map(|v| v.field)
+        .collect()
+}
+
+/// Test that every invalid field is reported at once
+#[tokio::test]
+async fn test_register_client_reports_all_violations() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db);
+
+    let request = Request::new(RegisterClientRequest {
+        user_id: "not-a-uuid".to_string(),
+        identity: String::new(),
+        device_name: "d".repeat(1000),
+        signature_key: vec![],
+        attestation: None,
+    });
+
+    let status = service.register_client(request).await.unwrap_err();
+    assert_eq!(
+        violated_fields(&status),
+        vec!["user_id", "identity", "device_name"]
+    );
+}
+
+/// Test that roles are checked against the known values
+#[tokio::test]
+async fn test_add_member_rejects_unknown_role() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db);
+
+    let request = Request::new(AddMemberRequest {
+        group_id: Uuid::new_v4().to_string(),
+        client_id: String::new(),
+        role: "owner".to_string(),
+        member_role: 0,
+        sender_id: Uuid::new_v4().to_string(),
+    });
+
+    let status = service.add_member(request).await.unwrap_err();
+    assert_eq!(violated_fields(&status), vec!["client_id", "role"]);
+
+    // Out of range enum values are rejected, and a role is required
+    let request = Request::new(AddMemberRequest {
+        group_id: Uuid::new_v4().to_string(),
+        client_id: Uuid::new_v4().to_string(),
+        role: String::new(),
+        member_role: 9,
+        sender_id: Uuid::new_v4().to_string(),
+    });
+    let status = service.add_member(request).await.unwrap_err();
+    assert_eq!(violated_fields(&status), vec!["member_role"]);
+
+    let request = Request::new(AddMemberRequest {
+        group_id: Uuid::new_v4().to_string(),
+        client_id: Uuid::new_v4().to_string(),
+        role: String::new(),
+        member_role: 0,
+        sender_id: Uuid::new_v4().to_string(),
+    });
+    let status = service.add_member(request).await.unwrap_err();
+    assert_eq!(violated_fields(&status), vec!["member_role"]);
+}
+
+/// Test that bad elements of repeated fields are reported by index
+#[tokio::test]
+async fn test_store_welcome_reports_recipient_index() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db).with_validation_policy(ValidationPolicy::Unchecked);
+
+    let request = Request::new(StoreWelcomeRequest {
+        group_id: Uuid::new_v4().to_string(),
+        sender_id: Uuid::new_v4().to_string(),
+        welcome: vec![1, 2, 3],
+        recipient_ids: vec![Uuid::new_v4().to_string(), "bogus".to_string()],
+        extra: vec![],
+        recipient_user_id: String::new(),
+    });
+
+    let status = service.store_welcome(request).await.unwrap_err();
+    assert_eq!(violated_fields(&status), vec!["recipient_ids[1]"]);
+}
+
+/// Test that recipient lists are capped and deduplicated
+#[tokio::test]
+async fn test_store_welcome_caps_and_dedups_recipients() {
+    let db = Arc::new(MockDatabase::new());
+    let service =
+        MLSServiceImpl::new(db.clone()).with_validation_policy(ValidationPolicy::Unchecked);
+    let group_id = Uuid::new_v4();
+    let sender_id = Uuid::new_v4();
+    MembershipFixture::new(group_id, sender_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+
+    let welcome = |recipient_ids: Vec<String>| {
+        service.store_welcome(Request::new(StoreWelcomeRequest {
+            group_id: group_id.to_string(),
+            sender_id: sender_id.to_string(),
+            welcome: vec![1, 2, 3],
+            recipient_ids,
+            extra: vec![],
+            recipient_user_id: String::new(),
+        }))
+    };
+
+    // An oversized list is rejected as a whole, without reporting every element
+    let oversized = vec!["bogus".to_string(); MAX_WELCOME_RECIPIENTS + 1];
+    let status = welcome(oversized).await.unwrap_err();
+    assert_eq!(violated_fields(&status), vec!["recipient_ids"]);
+
+    // Repeated recipients are stored once, in the order given
+    let mut registered = Vec::new();
+    for identity in ["first", "second"] {
+        let response = service
+            .register_client(Request::new(RegisterClientRequest {
+                user_id: Uuid::new_v4().to_string(),
+                identity: identity.to_string(),
+                device_name: "phone".to_string(),
+                signature_key: vec![],
+                attestation: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        registered.push(Uuid::parse_str(&response.client_id).unwrap());
+    }
+    let (first, second) = (registered[0], registered[1]);
+    MembershipFixture::new(group_id, first)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    welcome(vec![
+        first.to_string(),
+        second.to_string(),
+        first.to_string(),
+    ])
+    .await
+    .unwrap();
+    let messages = db
+        .fetch_messages_for_client(first, Some(group_id), true, &[], true)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].recipients, Some(vec![first, second]));
+}
+
+/// Test that unspecified enum values are rejected
+#[tokio::test]
+async fn test_soft_delete_rejects_unspecified_entity_type() {
+    let db = Arc::new(MockDatabase::new());
+    let service = MLSServiceImpl::new(db);
+
+    let request = admin_request(SoftDeleteRequest {
+        entity_type: 0,
+        id: Uuid::new_v4().to_string(),
+        dry_run: false,
+    });
+
+    let status = service.soft_delete(request).await.unwrap_err();
+    assert_eq!(violated_fields(&status), vec!["entity_type"]);
+}
+
+/// Uploaded MLS payloads are checked by default; only a test policy stores them unchecked
+#[tokio::test]
+async fn test_validation_policy() {
+    assert_eq!(ValidationPolicy::default(), ValidationPolicy::Strict);
+    assert!(ValidationPolicy::Strict.checks_payloads());
+    assert!(!ValidationPolicy::Unchecked.checks_payloads());
+
+    let db = Arc::new(MockDatabase::new());
+    let group = GroupFixture::new()
+        .with_mls_group_id(b"group".to_vec())
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    MembershipFixture::new(group.id, group.creator_id)
+        .insert(db.as_ref())
+        .await
+        .unwrap();
+    let commit = || {
+        Request::new(StoreCommitRequest {
+            group_id: group.id.to_string(),
+            sender_id: group.creator_id.to_string(),
+            commit: vec![1, 2, 3],
+            epoch: 1,
+            extra: vec![],
+            proposal_refs: vec![],
+        })
+    };
+
+    // Placeholder bytes aren't an MLS message framed for the group
+    let strict = MLSServiceImpl::new(db.clone());
+    let status = strict.store_commit(commit()).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let unchecked =
+        MLSServiceImpl::new(db.clone()).with_validation_policy(ValidationPolicy::Unchecked);
+    unchecked.store_commit(commit()).await.unwrap();
+    assert_eq!(db.get_group(group.id).await.unwrap().epoch, 1);
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use hermetic_mls::settings::{
+    cors::{AllowedOrigins, CorsError},
+    CorsConfig,
+};
+use http::{header, HeaderValue, Method};
+use tower::{Layer, Service};
+
+use super::Ok200;
+
+// Helper function to build a preflight request from an origin
+fn preflight(origin: &str) -> http::Request<()> {
+    http::Request::builder()
+        .method(Method::OPTIONS)
+        .uri("/mls.v1.MlsDeliveryService/StoreCommit")
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            "content-type,x-grpc-web,x-mls-signature-bin",
+        )
+        .body(())
+        .unwrap()
+}
+
+/// Origins parse from a comma-separated list, and "*" or nothing allows any origin
+#[test]
+fn test_parse_allowed_origins() {
+    assert_eq!(AllowedOrigins::parse("").unwrap(), AllowedOrigins::Any);
+    assert_eq!(AllowedOrigins::parse(" * ").unwrap(), AllowedOrigins::Any);
+    assert_eq!(
+        AllowedOrigins::parse("https://app.example.com, http://localhost:3000").unwrap(),
+        AllowedOrigins::List(vec![
+            HeaderValue::from_static("https://app.example.com"),
+            HeaderValue::from_static("http://localhost:3000"),
+        ])
+    );
+
+    for invalid in [
+        "app.example.com",
+        "https://app.example.com/",
+        "https://app.example.com/path",
+        "ftp://app.example.com",
+        "https://a.example.com,*",
+    ] {
+        assert!(
+            matches!(
+                AllowedOrigins::parse(invalid),
+                Err(CorsError::InvalidOrigin(_))
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+/// With auth enabled, listed origins are reflected with credentials, only the gRPC-web and
+/// signature headers are allowed, and preflights are cached
+#[tokio::test]
+async fn test_cors_with_auth_reflects_listed_origins() {
+    let config = CorsConfig {
+        origins: AllowedOrigins::parse("https://app.example.com").unwrap(),
+        max_age: Duration::from_secs(600),
+    };
+    let mut service = config.layer(true).unwrap().layer(Ok200);
+
+    let response = service
+        .call(preflight("https://app.example.com"))
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "POST");
+    assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+    let allowed = headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+        .to_str()
+        .unwrap();
+    assert!(allowed.contains("x-grpc-web") && allowed.contains("x-mls-signature-bin"));
+    assert!(!allowed.contains('*'));
+
+    // Actual requests expose the gRPC status headers
+    let request = http::Request::builder()
+        .method(Method::POST)
+        .header(header::ORIGIN, "https://app.example.com")
+        .body(())
+        .unwrap();
+    let response = service.call(request).await.unwrap();
+    let exposed = response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+        .to_str()
+        .unwrap();
+    assert!(exposed.contains("grpc-status") && exposed.contains("grpc-message"));
+
+    // Other origins aren't allowed
+    let response = service
+        .call(preflight("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert!(response
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
+
+/// Any origin is only allowed while auth is disabled, and never with credentials
+#[tokio::test]
+async fn test_cors_any_origin_requires_auth_disabled() {
+    let config = CorsConfig::default();
+    assert_eq!(
+        config.layer(true).unwrap_err(),
+        CorsError::AnyOriginWithAuth
+    );
+
+    let mut service = config.layer(false).unwrap().layer(Ok200);
+    let response = service
+        .call(preflight("https://app.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    assert!(response
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+        .is_none());
+}
@@ -0,0 +1,26 @@
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::task::{Context, Poll};
+
+use tower::Service;
+
+pub mod cors_tests;
+pub mod runtime_settings_tests;
+
+// Inner service that answers every request with an empty 200
+#[derive(Clone)]
+pub struct Ok200;
+
+impl Service<http::Request<()>> for Ok200 {
+    type Response = http::Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: http::Request<()>) -> Self::Future {
+        ready(Ok(http::Response::new(String::new())))
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use hermetic_mls::db::MessageType;
+use hermetic_mls::settings::{
+    maintenance::is_blocked_in_maintenance, parse_message_retention,
+    parse_tenant_ciphersuite_floors, parse_tenant_regions, MaintenanceLayer, RequestSignatureMode,
+    RuntimeSettings, SettingsError, SettingsHandle,
+};
+use log::LevelFilter;
+use tower::{Layer, Service};
+
+use super::Ok200;
+
+// Helper function to write a settings file to a unique temporary path
+fn write_settings_file(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("{}-{}.json", name, uuid::Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Fields in a settings file override the defaults; the rest are kept
+#[test]
+fn test_merge_partial_settings() {
+    let settings = RuntimeSettings::default()
+        .merge_json(r#"{"log_level": "debug", "purge_grace_days": 7}"#)
+        .unwrap();
+
+    assert_eq!(settings.log_level, "debug");
+    assert_eq!(settings.level_filter().unwrap(), LevelFilter::Debug);
+    assert_eq!(settings.purge_grace_days, 7);
+    assert_eq!(
+        settings
+            .janitor_config(std::time::Duration::from_secs(60))
+            .purge_grace_period,
+        chrono::Duration::days(7)
+    );
+    assert!(!settings.maintenance_mode);
+
+    // Unknown log levels are rejected when loading
+    let path = write_settings_file("bad-settings", r#"{"log_level": "loud"}"#);
+    assert!(RuntimeSettings::load(Some(&path)).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Unset variables keep the defaults, and a variable that's set but invalid is an error naming
+/// it instead of falling back to the default
+#[test]
+fn test_invalid_variables_rejected() {
+    let defaults = RuntimeSettings {
+        min_ciphersuite_bits: 192,
+        ..RuntimeSettings::default()
+    };
+    let settings = RuntimeSettings::from_vars(defaults.clone(), |name| match name {
+        "REQUEST_SIGNATURES" => Some("required".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    assert_eq!(settings.request_signatures, RequestSignatureMode::Required);
+    assert_eq!(settings.min_ciphersuite_bits, 192);
+
+    for (name, value) in [
+        ("REQUEST_SIGNATURES", "always"),
+        ("MIN_CIPHERSUITE_BITS", "strong"),
+        ("TENANT_MIN_CIPHERSUITE_BITS", "not-a-uuid=256"),
+        ("MEMBER_PERMISSIONS", "post,fly"),
+        ("MODERATOR_PERMISSIONS", "fly"),
+        ("MESSAGE_RETENTION_DAYS", "commit=0"),
+    ] {
+        let result = RuntimeSettings::from_vars(defaults.clone(), |var| {
+            (var == name).then(|| value.to_string())
+        });
+        match result {
+            Err(SettingsError::InvalidVariable {
+                name: bad,
+                value: bad_value,
+            }) => {
+                assert_eq!(bad, name);
+                assert_eq!(bad_value, value);
+            }
+            other => panic!("expected {} to be rejected, got {:?}", name, other),
+        }
+    }
+}
+
+/// Reloading picks up changes to the settings file and notifies subscribers
+#[tokio::test]
+async fn test_reload_settings_file() {
+    let path = write_settings_file("settings", r#"{"maintenance_mode": false}"#);
+    let handle = SettingsHandle::load(Some(path.clone())).unwrap();
+    let mut subscriber = handle.subscribe();
+    assert!(!handle.current().maintenance_mode);
+
+    std::fs::write(&path, r#"{"maintenance_mode": true}"#).unwrap();
+    let reloaded = handle.reload().unwrap();
+    assert!(reloaded.maintenance_mode);
+    assert!(subscriber.has_changed().unwrap());
+    assert!(subscriber.borrow_and_update().maintenance_mode);
+
+    // A broken file keeps the current settings
+    std::fs::write(&path, "{").unwrap();
+    assert!(handle.reload().is_err());
+    assert!(handle.current().maintenance_mode);
+    assert!(!subscriber.has_changed().unwrap());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Maintenance mode only blocks mutating delivery RPCs
+#[tokio::test]
+async fn test_maintenance_layer() {
+    assert!(is_blocked_in_maintenance(
+        "/mls.v1.MlsDeliveryService/StoreCommit"
+    ));
+    assert!(!is_blocked_in_maintenance(
+        "/mls.v1.MlsDeliveryService/FetchMessages"
+    ));
+    assert!(!is_blocked_in_maintenance(
+        "/mls.v1.MlsAdminService/SoftDelete"
+    ));
+
+    let handle = SettingsHandle::default();
+    let mut service = MaintenanceLayer::new(handle.subscribe()).layer(Ok200);
+    let request = |path: &str| http::Request::builder().uri(path).body(()).unwrap();
+
+    // Requests pass through while maintenance mode is off
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/StoreCommit"))
+        .await
+        .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+
+    // Turning it on takes effect without rebuilding the layer
+    handle.set(RuntimeSettings {
+        maintenance_mode: true,
+        ..handle.current()
+    });
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/StoreCommit"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get("grpc-status").unwrap(),
+        &(tonic::Code::Unavailable as i32).to_string()
+    );
+
+    let response = service
+        .call(request("/mls.v1.MlsDeliveryService/FetchMessages"))
+        .await
+        .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+}
+
+/// Tenant tags parse from `user_id=region` pairs, and only tenants tagged with another region
+/// than the instance's are misplaced
+#[test]
+fn test_tenant_regions() {
+    let eu_user = uuid::Uuid::new_v4();
+    let us_user = uuid::Uuid::new_v4();
+    let tenant_regions = parse_tenant_regions(&format!("{}=eu, {}=us", eu_user, us_user)).unwrap();
+    assert_eq!(tenant_regions[&eu_user], "eu");
+    assert!(parse_tenant_regions("not-a-uuid=eu").is_none());
+    assert!(parse_tenant_regions(&format!("{}=", eu_user)).is_none());
+
+    let settings = RuntimeSettings {
+        tenant_regions,
+        ..RuntimeSettings::default()
+    };
+    assert_eq!(settings.misplaced_region(eu_user), None);
+
+    let settings = RuntimeSettings {
+        data_region: Some("us".to_string()),
+        ..settings
+    };
+    assert_eq!(settings.misplaced_region(eu_user), Some("eu"));
+    assert_eq!(settings.misplaced_region(us_user), None);
+    assert_eq!(settings.misplaced_region(uuid::Uuid::new_v4()), None);
+}
+
+/// Tenant ciphersuite floors parse from `user_id=bits` pairs and replace the instance-wide one
+#[test]
+fn test_tenant_ciphersuite_floors() {
+    let strict_user = uuid::Uuid::new_v4();
+    let lax_user = uuid::Uuid::new_v4();
+    let floors =
+        parse_tenant_ciphersuite_floors(&format!("{}=256, {}=0", strict_user, lax_user)).unwrap();
+    assert_eq!(floors[&strict_user], 256);
+    assert!(parse_tenant_ciphersuite_floors("not-a-uuid=256").is_none());
+    assert!(parse_tenant_ciphersuite_floors(&format!("{}=strong", strict_user)).is_none());
+
+    let settings = RuntimeSettings {
+        tenant_min_ciphersuite_bits: floors,
+        ..RuntimeSettings::default()
+    };
+    assert_eq!(settings.ciphersuite_floor(Some(strict_user)), 256);
+    assert_eq!(settings.ciphersuite_floor(Some(lax_user)), 0);
+    assert_eq!(settings.ciphersuite_floor(Some(uuid::Uuid::new_v4())), 128);
+    assert_eq!(settings.ciphersuite_floor(None), 128);
+}
+
+/// Message retentions parse from `message_type=days` pairs, and only positive ones reach the
+/// janitor
+#[test]
+fn test_message_retention() {
+    let retention = parse_message_retention("commit=30, welcome=14").unwrap();
+    assert_eq!(retention[&MessageType::Commit], 30);
+    assert_eq!(retention[&MessageType::Welcome], 14);
+    assert!(parse_message_retention("handshake=30").is_none());
+    assert!(parse_message_retention("commit=0").is_none());
+
+    let settings = RuntimeSettings {
+        message_retention_days: retention,
+        used_key_package_retention_days: 7,
+        ..RuntimeSettings::default()
+    };
+    let policy = settings.janitor_config(Duration::from_secs(60)).retention;
+    assert_eq!(
+        policy.messages[&MessageType::Commit],
+        chrono::Duration::days(30)
+    );
+    assert_eq!(policy.used_key_packages, Some(chrono::Duration::days(7)));
+    assert!(RuntimeSettings::default()
+        .janitor_config(Duration::from_secs(60))
+        .retention
+        .messages
+        .is_empty());
+}
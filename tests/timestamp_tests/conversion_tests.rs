@@ -0,0 +1,56 @@
+use chrono::{DateTime, TimeZone, Timelike, Utc};
+use hermetic_mls::timestamps;
+
+fn sample() -> DateTime<Utc> {
+    // Nanosecond precision, finer than Postgres stores
+    Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0)
+        .unwrap()
+        .with_nanosecond(123_456_789)
+        .unwrap()
+}
+
+/// Timestamps are truncated, never rounded, to microseconds
+#[test]
+fn test_truncate_to_microseconds() {
+    let truncated = timestamps::truncate(sample());
+    assert_eq!(truncated.nanosecond(), 123_456_000);
+    assert_eq!(timestamps::truncate(truncated), truncated);
+    assert_eq!(timestamps::now().nanosecond() % 1_000, 0);
+}
+
+/// RFC 3339 strings are UTC with a fixed microsecond precision
+#[test]
+fn test_rfc3339_round_trip() {
+    let formatted = timestamps::to_rfc3339(sample());
+    assert_eq!(formatted, "2024-05-01T12:30:00.123456Z");
+
+    let parsed = timestamps::parse_rfc3339(&formatted).unwrap();
+    assert_eq!(parsed, timestamps::truncate(sample()));
+    assert_eq!(timestamps::to_rfc3339(parsed), formatted);
+
+    // Other offsets are normalized to UTC
+    let parsed = timestamps::parse_rfc3339("2024-05-01T14:30:00.123456+02:00").unwrap();
+    assert_eq!(parsed, timestamps::truncate(sample()));
+
+    assert!(timestamps::parse_rfc3339("yesterday").is_err());
+    assert_eq!(timestamps::to_rfc3339_opt(None), "");
+}
+
+/// Protobuf Timestamps carry the same precision as RFC 3339 strings
+#[test]
+fn test_timestamp_round_trip() {
+    let ts = timestamps::to_timestamp(sample());
+    assert_eq!(ts.seconds, sample().timestamp());
+    assert_eq!(ts.nanos, 123_456_000);
+    assert_eq!(
+        timestamps::from_timestamp(&ts),
+        Some(timestamps::truncate(sample()))
+    );
+
+    // Out-of-range nanos are rejected rather than carried into the seconds
+    let invalid = prost_types::Timestamp {
+        seconds: 0,
+        nanos: 1_000_000_000,
+    };
+    assert_eq!(timestamps::from_timestamp(&invalid), None);
+}
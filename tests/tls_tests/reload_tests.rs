@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use hermetic_mls::tls::{ReloadableCert, TlsConfig, TlsError, TlsListener};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+
+/// A self-signed certificate for localhost and its key, both PEM
+fn self_signed() -> (String, String) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    (certified.cert.pem(), certified.signing_key.serialize_pem())
+}
+
+/// Write the certificate and key to fresh files
+fn write_pair(cert: &str, key: &str) -> TlsConfig {
+    let dir = std::env::temp_dir();
+    let id = uuid::Uuid::new_v4();
+    let config = TlsConfig {
+        cert_path: dir.join(format!("tls-{}.crt", id)),
+        key_path: dir.join(format!("tls-{}.key", id)),
+    };
+    std::fs::write(&config.cert_path, cert).unwrap();
+    std::fs::write(&config.key_path, key).unwrap();
+    config
+}
+
+fn remove_pair(config: &TlsConfig) {
+    std::fs::remove_file(&config.cert_path).unwrap();
+    std::fs::remove_file(&config.key_path).unwrap();
+}
+
+/// DER of the first certificate in a PEM file
+fn der_of(pem: &str) -> CertificateDer<'static> {
+    CertificateDer::from_pem_slice(pem.as_bytes()).unwrap()
+}
+
+/// Handshake with the listener, trusting the given certificates, and return the certificate
+/// the server presented and the protocol negotiated
+async fn handshake(
+    addr: std::net::SocketAddr,
+    trusted: &[&str],
+) -> (CertificateDer<'static>, Vec<u8>) {
+    let mut roots = RootCertStore::empty();
+    for pem in trusted {
+        roots.add(der_of(pem)).unwrap();
+    }
+    let mut config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .unwrap();
+    let (_, connection) = stream.get_ref();
+    (
+        connection.peer_certificates().unwrap()[0]
+            .clone()
+            .into_owned(),
+        connection.alpn_protocol().unwrap().to_vec(),
+    )
+}
+
+/// Reloading reads the files again; a broken replacement leaves the current certificate in place
+#[test]
+fn test_reload_certificate() {
+    let (cert, key) = self_signed();
+    let config = write_pair(&cert, &key);
+    let reloadable = ReloadableCert::load(config.clone()).unwrap();
+    assert_eq!(reloadable.current().cert[0], der_of(&cert));
+
+    let (new_cert, new_key) = self_signed();
+    std::fs::write(&config.cert_path, &new_cert).unwrap();
+    std::fs::write(&config.key_path, &new_key).unwrap();
+    reloadable.reload().unwrap();
+    assert_eq!(reloadable.current().cert[0], der_of(&new_cert));
+
+    // Halfway through a rotation the certificate no longer matches the key
+    std::fs::write(&config.cert_path, &cert).unwrap();
+    let err = reloadable.reload().unwrap_err();
+    assert!(matches!(err, TlsError::KeyMismatch { .. }), "{:?}", err);
+    assert_eq!(reloadable.current().cert[0], der_of(&new_cert));
+    remove_pair(&config);
+}
+
+/// Missing and malformed files are reported by path, without their contents
+#[test]
+fn test_load_errors() {
+    let (cert, key) = self_signed();
+    let config = write_pair("not a certificate", &key);
+    let err = config.load().unwrap_err();
+    assert!(
+        matches!(err, TlsError::InvalidCertificate { .. }),
+        "{:?}",
+        err
+    );
+    remove_pair(&config);
+
+    let config = write_pair(&cert, "not a key");
+    let err = config.load().unwrap_err();
+    assert!(matches!(err, TlsError::InvalidKey { .. }), "{:?}", err);
+    assert!(!err.to_string().contains("not a key"));
+    remove_pair(&config);
+
+    let config = write_pair(&cert, &key);
+    std::fs::remove_file(&config.key_path).unwrap();
+    match config.load().unwrap_err() {
+        TlsError::Read { path, .. } => assert_eq!(path, config.key_path.display().to_string()),
+        err => panic!("unexpected error: {:?}", err),
+    }
+    std::fs::remove_file(&config.cert_path).unwrap();
+}
+
+/// Connections are handshaken with the current certificate, negotiating HTTP/2, and those
+/// opened after a reload get the new one
+#[tokio::test]
+async fn test_listener_serves_reloaded_certificate() {
+    let (cert, key) = self_signed();
+    let config = write_pair(&cert, &key);
+    let reloadable = Arc::new(ReloadableCert::load(config.clone()).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut incoming =
+        Box::pin(TlsListener::new(listener, reloadable.clone(), false).into_incoming());
+
+    let (presented, protocol) = handshake(addr, &[&cert]).await;
+    assert_eq!(presented, der_of(&cert));
+    assert_eq!(protocol, b"h2");
+    assert!(incoming.next().await.unwrap().is_ok());
+
+    let (new_cert, new_key) = self_signed();
+    std::fs::write(&config.cert_path, &new_cert).unwrap();
+    std::fs::write(&config.key_path, &new_key).unwrap();
+    reloadable.reload().unwrap();
+    let (presented, _) = handshake(addr, &[&cert, &new_cert]).await;
+    assert_eq!(presented, der_of(&new_cert));
+    assert!(incoming.next().await.unwrap().is_ok());
+    remove_pair(&config);
+}
@@ -0,0 +1 @@
+pub mod verbose_tests;
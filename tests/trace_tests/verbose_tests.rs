@@ -0,0 +1,121 @@
+use chrono::Duration;
+use hermetic_mls::{
+    db::{DatabaseInterface, MessageType, VerboseTrace},
+    fixtures::MessageFixture,
+    timestamps,
+    trace::{describe, describe_all, VerboseTracing, MAX_DESCRIBED_MESSAGES},
+};
+use uuid::Uuid;
+
+use crate::mock_db::MockDatabase;
+
+// Helper function to build a trace of a group or client, expiring after `expires_in`
+fn trace_of(group_id: Option<Uuid>, client_id: Option<Uuid>, expires_in: Duration) -> VerboseTrace {
+    let now = timestamps::now();
+    VerboseTrace {
+        id: Uuid::new_v4(),
+        group_id,
+        client_id,
+        reason: "TICKET-1234".to_string(),
+        created_at: now,
+        expires_at: now + expires_in,
+    }
+}
+
+/// A line is logged for the traces on for any of its groups or clients, and not even built
+/// for an untraced operation
+#[test]
+fn test_trace_matches_group_or_client() {
+    let tracing = VerboseTracing::new(100);
+    let (group_id, client_id, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let group_trace = trace_of(Some(group_id), None, Duration::hours(1));
+    let client_trace = trace_of(None, Some(client_id), Duration::hours(1));
+    tracing.insert(group_trace.clone());
+    tracing.insert(client_trace.clone());
+
+    tracing.trace(&[group_id], &[other], || "stored".to_string());
+    assert_eq!(tracing.stats(group_trace.id), (1, 0));
+    assert_eq!(tracing.stats(client_trace.id), (0, 0));
+
+    tracing.trace(&[other], &[other, client_id], || "fetched".to_string());
+    assert_eq!(tracing.stats(client_trace.id), (1, 0));
+
+    tracing.trace(&[other], &[other], || {
+        panic!("built a line for an untraced operation")
+    });
+    assert_eq!(tracing.stats(group_trace.id), (1, 0));
+}
+
+/// Lines over a trace's rate limit are dropped and counted
+#[test]
+fn test_trace_rate_limit() {
+    let tracing = VerboseTracing::new(2);
+    let group_id = Uuid::new_v4();
+    let trace = trace_of(Some(group_id), None, Duration::hours(1));
+    tracing.insert(trace.clone());
+
+    for _ in 0..5 {
+        tracing.trace(&[group_id], &[], || "stored".to_string());
+    }
+    assert_eq!(tracing.stats(trace.id), (2, 3));
+
+    // Turning the trace off forgets its counts
+    tracing.remove(trace.id);
+    assert_eq!(tracing.stats(trace.id), (0, 0));
+}
+
+/// An expired trace logs nothing, even before the snapshot is refreshed
+#[test]
+fn test_expired_trace_logs_nothing() {
+    let tracing = VerboseTracing::default();
+    let client_id = Uuid::new_v4();
+    tracing.insert(trace_of(None, Some(client_id), -Duration::seconds(1)));
+
+    tracing.trace(&[], &[client_id], || {
+        panic!("built a line for an expired trace")
+    });
+    assert!(tracing.list().is_empty());
+}
+
+/// Refreshing picks up the unexpired traces stored through any instance
+#[tokio::test]
+async fn test_refresh_from_database() {
+    let db = MockDatabase::new();
+    let tracing = VerboseTracing::default();
+    let active = trace_of(Some(Uuid::new_v4()), None, Duration::hours(1));
+    db.create_verbose_trace(active.clone()).await.unwrap();
+    db.create_verbose_trace(trace_of(None, Some(Uuid::new_v4()), -Duration::hours(1)))
+        .await
+        .unwrap();
+
+    tracing.refresh(&db).await.unwrap();
+    assert_eq!(tracing.list(), vec![active.clone()]);
+
+    db.delete_verbose_trace(active.id).await.unwrap();
+    tracing.refresh(&db).await.unwrap();
+    assert!(tracing.list().is_empty());
+    assert!(db.delete_verbose_trace(active.id).await.is_err());
+}
+
+/// Messages are described by type, sequence, epoch and payload size, and long lists are cut
+#[test]
+fn test_describe_messages() {
+    let group_id = Uuid::new_v4();
+    let commit = MessageFixture::commit(group_id, 4)
+        .with_payload(vec![0; 512])
+        .build();
+    let description = describe(&commit);
+    assert!(description.starts_with(&format!("commit {}", commit.id)));
+    assert!(description.contains(&format!("in group {} #0 at epoch 4", group_id)));
+    assert!(description.ends_with(", 512 bytes"), "{}", description);
+
+    let messages: Vec<_> = (0..MAX_DESCRIBED_MESSAGES + 5)
+        .map(|_| MessageFixture::new(MessageType::Application).build())
+        .collect();
+    let description = describe_all(&messages);
+    assert_eq!(
+        description.matches("application").count(),
+        MAX_DESCRIBED_MESSAGES
+    );
+    assert!(description.ends_with("; and 5 more"), "{}", description);
+}